@@ -0,0 +1,68 @@
+//! Frame decoding throughput of serde_json versus simd-json on typical
+//! exchange payloads.
+//!
+//! Run with `cargo bench --features simd-json`.
+
+use chrono::Utc;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rust_market_data_stream::adapters::{
+    Adapter, BinanceAdapter, CoinbaseAdapter, JsonBackend, NativeAdapter,
+};
+use std::hint::black_box;
+
+const BINANCE_TRADE: &str = r#"{"stream":"btcusdt@trade","data":{"e":"trade","E":1700000000001,"s":"BTCUSDT","t":3290014519,"p":"37012.45000000","q":"0.00135000","b":22801283781,"a":22801283975,"T":1700000000000,"m":true,"M":true}}"#;
+const BINANCE_BOOK_TICKER: &str = r#"{"u":40090021712,"s":"BTCUSDT","b":"37012.44000000","B":"3.21340000","a":"37012.45000000","A":"0.87120000"}"#;
+const COINBASE_MATCH: &str = r#"{"type":"match","trade_id":573812039,"maker_order_id":"ac928c66-ca53-498f-9c13-a110027a60e8","taker_order_id":"132fb6ae-456b-4654-b4e0-d681ac05cea1","side":"sell","size":"0.00512034","price":"37010.12","product_id":"BTC-USD","sequence":68931415377,"time":"2024-01-01T00:00:00.123456Z"}"#;
+const COINBASE_TICKER: &str = r#"{"type":"ticker","sequence":68931415378,"product_id":"BTC-USD","price":"37010.12","open_24h":"36500.01","volume_24h":"15234.12345678","low_24h":"36200.00","high_24h":"37300.00","volume_30d":"412345.12345678","best_bid":"37010.11","best_bid_size":"0.51230000","best_ask":"37010.12","best_ask_size":"0.10000000","side":"buy","time":"2024-01-01T00:00:00.123456Z","trade_id":573812040,"last_size":"0.00100000"}"#;
+const NATIVE_QUOTE: &str = r#"{"type":"Quote","symbol":"BTCUSD","bid_price":37010.11,"bid_size":0.5123,"ask_price":37010.12,"ask_size":0.1,"timestamp":"2024-01-01T00:00:00.123456Z"}"#;
+
+fn bench_adapter(
+    c: &mut Criterion,
+    name: &str,
+    frame: &str,
+    make: impl Fn(JsonBackend) -> Box<dyn Adapter>,
+) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Bytes(frame.len() as u64));
+
+    for (label, backend) in [
+        ("serde_json", JsonBackend::SerdeJson),
+        ("simd_json", JsonBackend::SimdJson),
+    ] {
+        let mut adapter = make(backend);
+        let mut out = Vec::with_capacity(4);
+        let received = Utc::now();
+        group.bench_function(label, |b| {
+            b.iter_batched_ref(
+                || frame.as_bytes().to_vec(),
+                |buf| {
+                    adapter.decode(buf, received, &mut out).unwrap();
+                    black_box(out.drain(..).count())
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn parsing(c: &mut Criterion) {
+    bench_adapter(c, "binance_trade", BINANCE_TRADE, |backend| {
+        Box::new(BinanceAdapter::with_backend(&["btcusdt"], backend))
+    });
+    bench_adapter(c, "binance_book_ticker", BINANCE_BOOK_TICKER, |backend| {
+        Box::new(BinanceAdapter::with_backend(&["btcusdt"], backend))
+    });
+    bench_adapter(c, "coinbase_match", COINBASE_MATCH, |backend| {
+        Box::new(CoinbaseAdapter::with_backend(&["BTC-USD"], backend))
+    });
+    bench_adapter(c, "coinbase_ticker", COINBASE_TICKER, |backend| {
+        Box::new(CoinbaseAdapter::with_backend(&["BTC-USD"], backend))
+    });
+    bench_adapter(c, "native_quote", NATIVE_QUOTE, |backend| {
+        Box::new(NativeAdapter::with_backend(backend))
+    });
+}
+
+criterion_group!(benches, parsing);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+//! Rolling VWAP, volatility and min/max over trade windows of several
+//! sizes, and trade intake across many symbols.
+//!
+//! Run with `cargo bench --bench rolling`.
+
+use chrono::Utc;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_market_data_stream::rolling::{RollingStatsTracker, RollingWindow};
+use rust_market_data_stream::types::{Trade, TradeConditions, TradeSide};
+use rust_market_data_stream::Symbol;
+use std::hint::black_box;
+
+const SYMBOLS: usize = 500;
+
+fn price(i: usize) -> f64 {
+    100.0 + ((i * 7919) % 1000) as f64 / 100.0
+}
+
+fn window_stats(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rolling_stats");
+    for capacity in [64, 1024, 16_384] {
+        let mut window = RollingWindow::new(capacity);
+        for i in 0..capacity + 1 {
+            window.push(price(i), 1.0 + (i % 5) as f64);
+        }
+        group.throughput(Throughput::Elements(capacity as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(capacity), &window, |b, w| {
+            b.iter(|| black_box(w.stats()))
+        });
+    }
+    group.finish();
+}
+
+fn many_symbols(c: &mut Criterion) {
+    let symbols: Vec<Symbol> = (0..SYMBOLS).map(|i| format!("SYM{i}").into()).collect();
+    let trades: Vec<Trade> = (0..SYMBOLS * 10)
+        .map(|i| Trade {
+            symbol: symbols[i % SYMBOLS],
+            price: price(i),
+            quantity: 1.0,
+            side: TradeSide::Buy,
+            timestamp: Utc::now(),
+            trade_id: String::new(),
+            conditions: TradeConditions::empty(),
+            instrument_id: None,
+            contract: None,
+            received: None,
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("rolling_symbols");
+    group.throughput(Throughput::Elements(trades.len() as u64));
+    group.bench_function("on_trade_then_stats", |b| {
+        let mut tracker = RollingStatsTracker::new(256);
+        b.iter(|| {
+            for trade in &trades {
+                tracker.on_trade(trade);
+            }
+            black_box(tracker.all().len())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, window_stats, many_symbols);
+criterion_main!(benches);
@@ -1,39 +1,35 @@
 use rust_market_data_stream::*;
-use tokio;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize logger
-    env_logger::init();
-    
+    tracing_subscriber::fmt::init();
+
     println!("=== Market Data Stream Example ===\n");
-    
+
     // Create client
-    let client = MarketDataClient::new("wss://stream.binance.com:9443/ws/btcusdt@trade".to_string());
-    
-    println!("Connecting to Binance WebSocket...");
-    
+    let client = MarketDataClient::new("ws://localhost:8080".to_string(), 1000);
+    let mut receiver = client.subscribe();
+
+    println!("Connecting to market data feed...");
+
     // Connect
-    client.connect().await?;
-    
+    client.start().await?;
+
     println!("Connected! Streaming market data...\n");
-    
-    // Subscribe to trades
-    client.subscribe(vec!["btcusdt@trade".to_string()]).await?;
-    
+
     // Receive and process messages
+    let mut stats = MarketStats::new("BTCUSD".to_string());
     let mut count = 0;
-    while let Some(message) = client.receive().await {
+    while let Ok(message) = receiver.recv().await {
         match message {
             MarketDataMessage::Trade(trade) => {
                 println!(
                     "Trade: {} {} @ {} ({})",
-                    trade.symbol,
-                    trade.quantity,
-                    trade.price,
-                    trade.timestamp
+                    trade.symbol, trade.quantity, trade.price, trade.timestamp
                 );
-                
+                stats.update_with_trade(&trade);
+
                 count += 1;
                 if count >= 10 {
                     println!("\nReceived 10 trades, disconnecting...");
@@ -43,11 +39,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             MarketDataMessage::Quote(quote) => {
                 println!(
                     "Quote: {} - Bid: {} @ {} | Ask: {} @ {}",
-                    quote.symbol,
-                    quote.bid_quantity,
-                    quote.bid_price,
-                    quote.ask_quantity,
-                    quote.ask_price
+                    quote.symbol, quote.bid_size, quote.bid_price, quote.ask_size, quote.ask_price
                 );
             }
             MarketDataMessage::OrderBook(snapshot) => {
@@ -58,22 +50,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     snapshot.asks.len()
                 );
             }
+            // Heartbeats and message kinds added in later versions
+            _ => {}
         }
     }
-    
+
     // Disconnect
-    client.disconnect().await?;
+    client.stop().await;
     println!("Disconnected.");
-    
-    // Get statistics
-    let stats = client.get_statistics();
+
+    // Print statistics
     println!("\n=== Statistics ===");
-    println!("Messages received: {}", stats.messages_received);
-    println!("Bytes received: {}", stats.bytes_received);
+    println!("Trades: {}", stats.trade_count);
     println!("VWAP: {:.2}", stats.vwap);
-    println!("Volume: {:.2}", stats.volume);
+    println!("Volume: {:.2}", stats.total_volume);
     println!("High: {:.2}", stats.high);
     println!("Low: {:.2}", stats.low);
-    
+
     Ok(())
 }
@@ -0,0 +1,31 @@
+//! Feeds arbitrary frames through every adapter's decode path with both
+//! JSON backends. Decoding may fail but must never panic.
+
+#![no_main]
+
+use chrono::{DateTime, Utc};
+use libfuzzer_sys::fuzz_target;
+use rust_market_data_stream::adapters::{
+    Adapter, AlpacaAdapter, BinanceAdapter, BitstampAdapter, CoinbaseAdapter, GeminiAdapter,
+    IexAdapter, JsonBackend, NativeAdapter, OkxAdapter,
+};
+
+fuzz_target!(|data: &[u8]| {
+    for backend in [JsonBackend::SerdeJson, JsonBackend::SimdJson] {
+        let mut adapters: [Box<dyn Adapter>; 8] = [
+            Box::new(NativeAdapter::with_backend(backend)),
+            Box::new(BinanceAdapter::with_backend(&[], backend)),
+            Box::new(CoinbaseAdapter::with_backend(&[], backend)),
+            Box::new(OkxAdapter::with_backend(&[], backend)),
+            Box::new(BitstampAdapter::with_backend(&[], backend)),
+            Box::new(GeminiAdapter::with_backend(&[], backend)),
+            Box::new(AlpacaAdapter::with_backend("", "", &[], backend)),
+            Box::new(IexAdapter::with_backend(backend)),
+        ];
+        for adapter in adapters.iter_mut() {
+            let mut frame = data.to_vec();
+            let mut out = Vec::new();
+            let _ = adapter.decode(&mut frame, DateTime::<Utc>::UNIX_EPOCH, &mut out);
+        }
+    }
+});
@@ -0,0 +1,20 @@
+//! Reads arbitrary bytes as a DBN stream: the metadata header, then every
+//! record. Lengths and counts come from the input, so corrupt streams must
+//! produce errors, not panics or unbounded allocations.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_market_data_stream::DbnReader;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(reader) = DbnReader::new(data) else {
+        return;
+    };
+    let _ = reader.metadata();
+    for record in reader {
+        if record.is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,23 @@
+//! Reads arbitrary bytes as ITCH 5.0 frames, with and without book
+//! snapshots. Order references and locates come from the input, so
+//! executions and deletes of unknown orders must not panic.
+
+#![no_main]
+
+use chrono::{DateTime, Utc};
+use libfuzzer_sys::fuzz_target;
+use rust_market_data_stream::ItchReader;
+
+fuzz_target!(|data: &[u8]| {
+    for depth in [None, Some(5)] {
+        let mut reader = ItchReader::new(data, DateTime::<Utc>::UNIX_EPOCH);
+        if let Some(depth) = depth {
+            reader = reader.with_book_snapshots(depth);
+        }
+        for message in reader {
+            if message.is_err() {
+                break;
+            }
+        }
+    }
+});
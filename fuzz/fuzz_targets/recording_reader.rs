@@ -0,0 +1,23 @@
+//! Opens arbitrary bytes as a binary recording and reads every message.
+//! Corrupt files must produce errors, not panics or unbounded allocations.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_market_data_stream::recording::RecordingReader;
+use std::io::Write;
+
+fuzz_target!(|data: &[u8]| {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(data).unwrap();
+
+    let Ok(mut reader) = RecordingReader::open(file.path()) else {
+        return;
+    };
+    let _ = reader.time_span();
+    for message in reader.messages() {
+        if message.is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,65 @@
+//! Decodes arbitrary frames with an SBE schema shaped like CME MDP 3.0,
+//! both standalone and through the adapter with and without size
+//! prefixes. Header and group lengths come from the wire, so corrupt
+//! frames must produce errors, not panics.
+
+#![no_main]
+
+use chrono::{DateTime, Utc};
+use libfuzzer_sys::fuzz_target;
+use rust_market_data_stream::adapters::{Adapter, SbeAdapter};
+use rust_market_data_stream::SbeSchema;
+
+const SCHEMA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<sbe:messageSchema xmlns:sbe="http://fixprotocol.io/2016/sbe" package="fuzz" id="1" version="9" byteOrder="littleEndian">
+  <types>
+    <composite name="messageHeader">
+      <type name="blockLength" primitiveType="uint16"/>
+      <type name="templateId" primitiveType="uint16"/>
+      <type name="schemaId" primitiveType="uint16"/>
+      <type name="version" primitiveType="uint16"/>
+    </composite>
+    <composite name="groupSize">
+      <type name="blockLength" primitiveType="uint16"/>
+      <type name="numInGroup" primitiveType="uint8"/>
+    </composite>
+    <composite name="varData">
+      <type name="length" primitiveType="uint16"/>
+    </composite>
+    <composite name="PRICE9">
+      <type name="mantissa" primitiveType="int64"/>
+      <type name="exponent" primitiveType="int8" presence="constant">-9</type>
+    </composite>
+    <type name="Symbol" primitiveType="char" length="8"/>
+  </types>
+  <sbe:message name="TradeSummary" id="48" blockLength="16">
+    <field name="TransactTime" id="60" type="uint64"/>
+    <field name="Symbol" id="55" type="Symbol"/>
+    <group name="NoMDEntries" id="268" dimensionType="groupSize" blockLength="12">
+      <field name="MDEntryPx" id="270" type="PRICE9"/>
+      <field name="MDEntrySize" id="271" type="int32"/>
+    </group>
+  </sbe:message>
+  <sbe:message name="Heartbeat" id="12" blockLength="0"/>
+  <sbe:message name="News" id="50" blockLength="8">
+    <field name="SendingTime" id="52" type="uint64"/>
+    <data name="Headline" id="1" type="varData"/>
+  </sbe:message>
+</sbe:messageSchema>"#;
+
+fuzz_target!(|data: &[u8]| {
+    let schema = SbeSchema::parse(SCHEMA).unwrap();
+    let _ = schema.decode(data);
+
+    for size_prefix in [false, true] {
+        let mut adapter = SbeAdapter::new("fuzz", schema.clone())
+            .on_message("TradeSummary", |_, _, _| Ok(()))
+            .on_message("News", |_, _, _| Ok(()));
+        if size_prefix {
+            adapter = adapter.with_packet_header(12).with_size_prefix();
+        }
+        let mut frame = data.to_vec();
+        let mut out = Vec::new();
+        let _ = adapter.decode(&mut frame, DateTime::<Utc>::UNIX_EPOCH, &mut out);
+    }
+});
@@ -0,0 +1,385 @@
+//! Alpaca Market Data API v2 stock stream (`trades` and `quotes`).
+//!
+//! Frames are arrays of messages tagged by `T`, in JSON or, when negotiated
+//! with [`with_msgpack`](AlpacaAdapter::with_msgpack), MessagePack. The
+//! connection is authenticated with an `auth` action sent ahead of the
+//! subscription. Alpaca does not report the aggressor, so trades have
+//! [`TradeSide::Unknown`](crate::types::TradeSide::Unknown) sides for an
+//! [`AggressorInference`](crate::aggressor::AggressorInference) stage to
+//! fill in. Trade corrections (`c`) and cancels (`x`), sent
+//! with the trades subscription, become [`TradeCorrection`]s and
+//! [`TradeBust`]s.
+
+use super::json::{JsonBackend, JsonDecoder};
+use super::Adapter;
+use crate::client::{ClientError, Result};
+use crate::symbology::Symbol;
+use crate::types::{
+    MarketDataMessage, Quote, Trade, TradeBust, TradeConditions, TradeCorrection, TradeSide,
+    TradeTerms,
+};
+use chrono::{DateTime, Utc};
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use std::fmt;
+
+/// MessagePack extension type of timestamps
+const MSGPACK_TIMESTAMP: i8 = -1;
+
+#[derive(Deserialize)]
+struct Message<'a> {
+    #[serde(rename = "T", borrow)]
+    kind: &'a str,
+    #[serde(rename = "S", borrow)]
+    symbol: Option<&'a str>,
+    #[serde(rename = "i")]
+    trade_id: Option<u64>,
+    #[serde(rename = "p")]
+    price: Option<f64>,
+    #[serde(rename = "s")]
+    size: Option<f64>,
+    #[serde(rename = "c", borrow, default)]
+    conditions: Vec<&'a str>,
+    #[serde(rename = "t")]
+    time: Option<Timestamp>,
+    #[serde(rename = "bp")]
+    bid_price: Option<f64>,
+    #[serde(rename = "bs")]
+    bid_size: Option<f64>,
+    #[serde(rename = "ap")]
+    ask_price: Option<f64>,
+    #[serde(rename = "as")]
+    ask_size: Option<f64>,
+    /// Correction fields: original and corrected id, price, size and
+    /// conditions
+    #[serde(rename = "oi")]
+    original_id: Option<u64>,
+    #[serde(rename = "op")]
+    original_price: Option<f64>,
+    #[serde(rename = "os")]
+    original_size: Option<f64>,
+    #[serde(rename = "cp")]
+    corrected_price: Option<f64>,
+    #[serde(rename = "cs")]
+    corrected_size: Option<f64>,
+    #[serde(rename = "oc", borrow, default)]
+    original_conditions: Vec<&'a str>,
+    #[serde(rename = "cc", borrow, default)]
+    corrected_conditions: Vec<&'a str>,
+    code: Option<i64>,
+    msg: Option<String>,
+}
+
+/// RFC 3339 string in JSON, timestamp extension in MessagePack
+struct Timestamp(DateTime<Utc>);
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct TimestampVisitor;
+
+        impl<'de> Visitor<'de> for TimestampVisitor {
+            type Value = Timestamp;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an RFC 3339 string or MessagePack timestamp")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<Self::Value, E> {
+                DateTime::parse_from_rfc3339(v)
+                    .map(|ts| Timestamp(ts.with_timezone(&Utc)))
+                    .map_err(E::custom)
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> std::result::Result<Self::Value, D::Error> {
+                let (tag, data): (i8, &[u8]) = Deserialize::deserialize(deserializer)?;
+                if tag != MSGPACK_TIMESTAMP {
+                    return Err(de::Error::custom(format!("unexpected extension {}", tag)));
+                }
+                msgpack_timestamp(data)
+                    .map(Timestamp)
+                    .ok_or_else(|| de::Error::custom("invalid msgpack timestamp"))
+            }
+        }
+
+        deserializer.deserialize_any(TimestampVisitor)
+    }
+}
+
+/// Decode the 32, 64 and 96-bit MessagePack timestamp layouts
+fn msgpack_timestamp(data: &[u8]) -> Option<DateTime<Utc>> {
+    let (secs, nanos) = match data.len() {
+        4 => (u32::from_be_bytes(data.try_into().ok()?) as i64, 0),
+        8 => {
+            let value = u64::from_be_bytes(data.try_into().ok()?);
+            ((value & 0x3_ffff_ffff) as i64, (value >> 34) as u32)
+        }
+        12 => (
+            i64::from_be_bytes(data[4..].try_into().ok()?),
+            u32::from_be_bytes(data[..4].try_into().ok()?),
+        ),
+        _ => return None,
+    };
+    DateTime::from_timestamp(secs, nanos)
+}
+
+/// Map CTA/UTP sale condition codes
+fn trade_conditions(codes: &[&str]) -> TradeConditions {
+    let mut conditions = TradeConditions::empty();
+    for code in codes {
+        match *code {
+            // Opening, closing and reopening prints
+            "O" | "Q" | "M" | "5" | "6" => conditions.insert(TradeConditions::AUCTION),
+            // Average price and derivatively priced trades
+            "B" | "W" | "4" => conditions.insert(TradeConditions::OFF_BOOK),
+            _ => {}
+        }
+    }
+    conditions
+}
+
+/// Normalizes Alpaca stock trades and quotes
+pub struct AlpacaAdapter {
+    key: String,
+    secret: String,
+    symbols: Vec<String>,
+    msgpack: bool,
+    decoder: JsonDecoder,
+}
+
+impl AlpacaAdapter {
+    /// Adapter authenticating with an API key pair and subscribing to trades
+    /// and quotes for `symbols` such as `AAPL`
+    pub fn new(key: &str, secret: &str, symbols: &[&str]) -> Self {
+        Self::with_backend(key, secret, symbols, JsonBackend::default())
+    }
+
+    /// Credentials from `APCA_API_KEY_ID` and `APCA_API_SECRET_KEY`
+    pub fn from_env(symbols: &[&str]) -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        Self::new(
+            &var("APCA_API_KEY_ID"),
+            &var("APCA_API_SECRET_KEY"),
+            symbols,
+        )
+    }
+
+    pub fn with_backend(key: &str, secret: &str, symbols: &[&str], backend: JsonBackend) -> Self {
+        Self {
+            key: key.to_string(),
+            secret: secret.to_string(),
+            symbols: symbols.iter().map(|s| s.to_uppercase()).collect(),
+            msgpack: false,
+            decoder: JsonDecoder::new(backend),
+        }
+    }
+
+    /// Ask the server for MessagePack frames instead of JSON
+    pub fn with_msgpack(mut self) -> Self {
+        self.msgpack = true;
+        self
+    }
+}
+
+impl Adapter for AlpacaAdapter {
+    fn name(&self) -> &str {
+        "alpaca"
+    }
+
+    fn connect_headers(&self) -> Vec<(String, String)> {
+        if self.msgpack {
+            vec![(
+                "Content-Type".to_string(),
+                "application/msgpack".to_string(),
+            )]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn subscribe_frames(&self) -> Vec<String> {
+        vec![
+            serde_json::json!({ "action": "auth", "key": self.key, "secret": self.secret })
+                .to_string(),
+            serde_json::json!({
+                "action": "subscribe",
+                "trades": self.symbols,
+                "quotes": self.symbols
+            })
+            .to_string(),
+        ]
+    }
+
+    fn is_subscription_ack(&self, frame: &[u8]) -> bool {
+        frame.starts_with(br#"[{"T":"subscription""#)
+    }
+
+    fn decode(
+        &mut self,
+        frame: &mut [u8],
+        received: DateTime<Utc>,
+        out: &mut Vec<MarketDataMessage>,
+    ) -> Result<()> {
+        // JSON frames are always arrays; anything else is MessagePack
+        let messages: Vec<Message> = if frame.first() == Some(&b'[') {
+            self.decoder.decode(frame)?
+        } else {
+            rmp_serde::from_slice(frame).map_err(|e| ClientError::Parse(e.to_string()))?
+        };
+
+        for msg in messages {
+            let incomplete = || ClientError::Parse(format!("incomplete alpaca {}", msg.kind));
+            let timestamp = msg.time.as_ref().map_or(received, |time| time.0);
+
+            match msg.kind {
+                "t" => {
+                    let (Some(symbol), Some(price), Some(size)) = (msg.symbol, msg.price, msg.size)
+                    else {
+                        return Err(incomplete());
+                    };
+                    out.push(MarketDataMessage::Trade(Trade {
+                        symbol: Symbol::new(symbol)?,
+                        price,
+                        quantity: size,
+                        side: TradeSide::Unknown,
+                        timestamp,
+                        trade_id: msg.trade_id.unwrap_or_default().to_string(),
+                        conditions: trade_conditions(&msg.conditions),
+                        instrument_id: None,
+                        contract: None,
+                        received: None,
+                    }));
+                }
+                "q" => {
+                    let (Some(symbol), Some(bid), Some(bid_size), Some(ask), Some(ask_size)) = (
+                        msg.symbol,
+                        msg.bid_price,
+                        msg.bid_size,
+                        msg.ask_price,
+                        msg.ask_size,
+                    ) else {
+                        return Err(incomplete());
+                    };
+                    out.push(MarketDataMessage::Quote(Quote {
+                        symbol: Symbol::new(symbol)?,
+                        bid_price: bid,
+                        bid_size,
+                        ask_price: ask,
+                        ask_size,
+                        timestamp,
+                        instrument_id: None,
+                        contract: None,
+                        received: None,
+                    }));
+                }
+                "c" => {
+                    let (Some(symbol), Some(id), Some(price), Some(size)) = (
+                        msg.symbol,
+                        msg.original_id,
+                        msg.corrected_price,
+                        msg.corrected_size,
+                    ) else {
+                        return Err(incomplete());
+                    };
+                    let original = match (msg.original_price, msg.original_size) {
+                        (Some(price), Some(quantity)) => Some(TradeTerms {
+                            price,
+                            quantity,
+                            conditions: trade_conditions(&msg.original_conditions),
+                        }),
+                        _ => None,
+                    };
+                    out.push(MarketDataMessage::TradeCorrection(TradeCorrection {
+                        symbol: Symbol::new(symbol)?,
+                        trade_id: id.to_string(),
+                        timestamp,
+                        original,
+                        corrected: TradeTerms {
+                            price,
+                            quantity: size,
+                            conditions: trade_conditions(&msg.corrected_conditions),
+                        },
+                        instrument_id: None,
+                        contract: None,
+                        received: None,
+                    }));
+                }
+                // Cancels and errors both void the trade
+                "x" => {
+                    let (Some(symbol), Some(id)) = (msg.symbol, msg.trade_id) else {
+                        return Err(incomplete());
+                    };
+                    let original = match (msg.price, msg.size) {
+                        (Some(price), Some(quantity)) => Some(TradeTerms {
+                            price,
+                            quantity,
+                            conditions: trade_conditions(&msg.conditions),
+                        }),
+                        _ => None,
+                    };
+                    out.push(MarketDataMessage::TradeBust(TradeBust {
+                        symbol: Symbol::new(symbol)?,
+                        trade_id: id.to_string(),
+                        timestamp,
+                        original,
+                        instrument_id: None,
+                        contract: None,
+                        received: None,
+                    }));
+                }
+                "error" => {
+                    return Err(ClientError::WebSocket(format!(
+                        "alpaca error {}: {}",
+                        msg.code.unwrap_or_default(),
+                        msg.msg.as_deref().unwrap_or_default()
+                    )))
+                }
+                // `success`, `subscription`, bars and status updates
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_and_msgpack_frames() {
+        let mut adapter = AlpacaAdapter::new("key", "secret", &["aapl"]).with_msgpack();
+        let mut out = Vec::new();
+
+        let mut frame = br#"[{"T":"q","S":"AAPL","bx":"V","bp":172.5,"bs":1,"ax":"V","ap":172.6,"as":2,"t":"2024-03-01T14:30:00.123456789Z","c":["R"],"z":"C"}]"#.to_vec();
+        adapter.decode(&mut frame, Utc::now(), &mut out).unwrap();
+
+        // [{"T":"t","S":"AAPL","i":7,"p":172.51,"s":100,"t":<timestamp ext>}]
+        let mut frame = vec![0x91, 0x86];
+        for (key, value) in [("T", "t"), ("S", "AAPL")] {
+            frame.push(0xa0 | key.len() as u8);
+            frame.extend(key.as_bytes());
+            frame.push(0xa0 | value.len() as u8);
+            frame.extend(value.as_bytes());
+        }
+        frame.extend([0xa1, b'i', 0x07, 0xa1, b'p', 0xcb]);
+        frame.extend(172.51f64.to_be_bytes());
+        frame.extend([0xa1, b's', 0x64, 0xa1, b't', 0xd6, 0xff]);
+        frame.extend(1_709_303_400u32.to_be_bytes());
+        adapter.decode(&mut frame, Utc::now(), &mut out).unwrap();
+
+        let MarketDataMessage::Quote(quote) = &out[0] else {
+            panic!("expected quote");
+        };
+        assert_eq!(quote.timestamp.timestamp_subsec_nanos(), 123_456_789);
+        let MarketDataMessage::Trade(trade) = &out[1] else {
+            panic!("expected trade");
+        };
+        assert_eq!((trade.price, trade.quantity), (172.51, 100.0));
+        assert_eq!(trade.side, TradeSide::Unknown);
+        assert_eq!(trade.timestamp.timestamp(), 1_709_303_400);
+        assert_eq!(adapter.connect_headers()[0].1, "application/msgpack");
+    }
+}
@@ -0,0 +1,252 @@
+//! Binance spot WebSocket streams (`@trade` and `@bookTicker`).
+//!
+//! Trade times are in milliseconds unless the streams were opened with
+//! `timeUnit=MICROSECOND`, which [`BinanceAdapter::with_time_unit`] must
+//! then be told.
+
+use super::json::{decimal, JsonBackend, JsonDecoder, Scalar};
+use super::Adapter;
+use crate::client::{ClientError, Result};
+use crate::clock::TimestampPrecision;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Quote, Trade, TradeConditions, TradeSide};
+use chrono::{DateTime, Utc};
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+/// Fields of the raw and combined-stream event payloads we consume.
+///
+/// `D` is the type of the combined-stream `data` field; nesting stops after
+/// one level so hostile input cannot recurse.
+#[derive(Deserialize)]
+struct Event<'a, D> {
+    #[serde(rename = "e", borrow)]
+    event: Option<&'a str>,
+    #[serde(rename = "s", borrow)]
+    symbol: Option<&'a str>,
+    #[serde(rename = "t")]
+    trade_id: Option<u64>,
+    #[serde(rename = "p", borrow)]
+    price: Option<&'a str>,
+    #[serde(rename = "q", borrow)]
+    quantity: Option<&'a str>,
+    #[serde(rename = "T")]
+    trade_time: Option<i64>,
+    #[serde(rename = "m")]
+    buyer_is_maker: Option<bool>,
+    /// Order type of futures trades, e.g. `LIQUIDATION`
+    #[serde(rename = "X", borrow)]
+    order_type: Option<&'a str>,
+    /// Best bid in book tickers; buyer order id in older trade payloads
+    #[serde(rename = "b", borrow)]
+    bid_price: Option<Scalar<'a>>,
+    #[serde(rename = "B", borrow)]
+    bid_size: Option<&'a str>,
+    #[serde(rename = "a", borrow)]
+    ask_price: Option<Scalar<'a>>,
+    #[serde(rename = "A", borrow)]
+    ask_size: Option<&'a str>,
+    /// Present when connected to the combined `/stream` endpoint
+    data: Option<D>,
+}
+
+type Frame<'a> = Event<'a, Event<'a, IgnoredAny>>;
+
+/// Normalizes Binance trade and best bid/offer streams
+pub struct BinanceAdapter {
+    symbols: Vec<String>,
+    decoder: JsonDecoder,
+    time_unit: TimestampPrecision,
+}
+
+impl BinanceAdapter {
+    /// Adapter subscribing to trades and book tickers for `symbols`
+    pub fn new(symbols: &[&str]) -> Self {
+        Self::with_backend(symbols, JsonBackend::default())
+    }
+
+    pub fn with_backend(symbols: &[&str], backend: JsonBackend) -> Self {
+        Self {
+            symbols: symbols.iter().map(|s| s.to_uppercase()).collect(),
+            decoder: JsonDecoder::new(backend),
+            time_unit: TimestampPrecision::Millis,
+        }
+    }
+
+    /// Read trade times in `unit` instead of milliseconds
+    pub fn with_time_unit(mut self, unit: TimestampPrecision) -> Self {
+        self.time_unit = unit;
+        self
+    }
+}
+
+impl Adapter for BinanceAdapter {
+    fn name(&self) -> &str {
+        "binance"
+    }
+
+    fn subscribe_frames(&self) -> Vec<String> {
+        vec![request("SUBSCRIBE", &self.symbols)]
+    }
+
+    fn update_symbols(&mut self, add: &[String], remove: &[String]) -> Vec<String> {
+        let add: Vec<String> = add
+            .iter()
+            .map(|s| s.to_uppercase())
+            .filter(|s| !self.symbols.contains(s))
+            .collect();
+        let remove: Vec<String> = remove
+            .iter()
+            .map(|s| s.to_uppercase())
+            .filter(|s| self.symbols.contains(s))
+            .collect();
+        self.symbols.retain(|s| !remove.contains(s));
+        self.symbols.extend(add.iter().cloned());
+        let mut frames = Vec::new();
+        if !add.is_empty() {
+            frames.push(request("SUBSCRIBE", &add));
+        }
+        if !remove.is_empty() {
+            frames.push(request("UNSUBSCRIBE", &remove));
+        }
+        frames
+    }
+
+    fn is_subscription_ack(&self, frame: &[u8]) -> bool {
+        frame.starts_with(br#"{"result":null"#)
+    }
+
+    fn decode(
+        &mut self,
+        frame: &mut [u8],
+        received: DateTime<Utc>,
+        out: &mut Vec<MarketDataMessage>,
+    ) -> Result<()> {
+        let frame: Frame = self.decoder.decode(frame)?;
+        match &frame.data {
+            Some(data) => normalize(data, self.time_unit, received, out),
+            None => normalize(&frame, self.time_unit, received, out),
+        }
+    }
+}
+
+/// A `SUBSCRIBE` or `UNSUBSCRIBE` request for the trade and book ticker
+/// streams of `symbols`
+fn request(method: &str, symbols: &[String]) -> String {
+    let params: Vec<String> = symbols
+        .iter()
+        .flat_map(|symbol| {
+            let symbol = symbol.to_lowercase();
+            [
+                format!("{}@trade", symbol),
+                format!("{}@bookTicker", symbol),
+            ]
+        })
+        .collect();
+    serde_json::json!({ "method": method, "params": params, "id": 1 }).to_string()
+}
+
+fn normalize<D>(
+    event: &Event<'_, D>,
+    time_unit: TimestampPrecision,
+    received: DateTime<Utc>,
+    out: &mut Vec<MarketDataMessage>,
+) -> Result<()> {
+    // Subscription acks and other control replies carry no symbol
+    let Some(symbol) = event.symbol else {
+        return Ok(());
+    };
+
+    match event.event {
+        Some("trade") => {
+            let (Some(price), Some(quantity), Some(time)) =
+                (event.price, event.quantity, event.trade_time)
+            else {
+                return Err(ClientError::Parse("incomplete binance trade".to_string()));
+            };
+            out.push(MarketDataMessage::Trade(Trade {
+                symbol: Symbol::new(symbol)?,
+                price: decimal(price)?,
+                quantity: decimal(quantity)?,
+                // The buyer resting on the book means the seller aggressed
+                side: match event.buyer_is_maker {
+                    Some(true) => TradeSide::Sell,
+                    Some(false) => TradeSide::Buy,
+                    None => TradeSide::Unknown,
+                },
+                timestamp: time_unit.from_epoch(time).unwrap_or(received),
+                trade_id: event.trade_id.unwrap_or_default().to_string(),
+                conditions: match event.order_type {
+                    Some("LIQUIDATION" | "INSURANCE_FUND" | "ADL") => TradeConditions::LIQUIDATION,
+                    _ => TradeConditions::empty(),
+                },
+                instrument_id: None,
+                contract: None,
+                received: None,
+            }));
+        }
+        // Book ticker payloads have no event type or timestamp
+        None => {
+            let (Some(bid), Some(bid_size), Some(ask), Some(ask_size)) = (
+                event.bid_price.as_ref().and_then(Scalar::as_str),
+                event.bid_size,
+                event.ask_price.as_ref().and_then(Scalar::as_str),
+                event.ask_size,
+            ) else {
+                return Ok(());
+            };
+            out.push(MarketDataMessage::Quote(Quote {
+                symbol: Symbol::new(symbol)?,
+                bid_price: decimal(bid)?,
+                bid_size: decimal(bid_size)?,
+                ask_price: decimal(ask)?,
+                ask_size: decimal(ask_size)?,
+                timestamp: received,
+                instrument_id: None,
+                contract: None,
+                received: None,
+            }));
+        }
+        Some(_) => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trade_and_book_ticker() {
+        let mut adapter = BinanceAdapter::new(&["btcusdt"]);
+        let mut out = Vec::new();
+
+        let mut frame = br#"{"stream":"btcusdt@trade","data":{"e":"trade","E":1700000000001,"s":"BTCUSDT","t":42,"p":"37000.10","q":"0.5","b":88,"a":50,"T":1700000000000,"m":true}}"#.to_vec();
+        adapter.decode(&mut frame, Utc::now(), &mut out).unwrap();
+        let mut frame =
+            br#"{"u":400900217,"s":"BTCUSDT","b":"37000.00","B":"1.5","a":"37000.20","A":"2.0"}"#
+                .to_vec();
+        adapter.decode(&mut frame, Utc::now(), &mut out).unwrap();
+
+        let MarketDataMessage::Trade(trade) = &out[0] else {
+            panic!("expected trade");
+        };
+        assert_eq!((trade.price, trade.side), (37000.10, TradeSide::Sell));
+        assert_eq!(trade.trade_id, "42");
+        let MarketDataMessage::Quote(quote) = &out[1] else {
+            panic!("expected quote");
+        };
+        assert_eq!((quote.bid_price, quote.ask_size), (37000.0, 2.0));
+
+        let mut adapter =
+            BinanceAdapter::new(&["btcusdt"]).with_time_unit(TimestampPrecision::Micros);
+        let mut frame =
+            br#"{"e":"trade","s":"BTCUSDT","t":43,"p":"1","q":"1","T":1700000000000123,"m":false}"#
+                .to_vec();
+        adapter.decode(&mut frame, Utc::now(), &mut out).unwrap();
+        let MarketDataMessage::Trade(trade) = &out[2] else {
+            panic!("expected trade");
+        };
+        assert_eq!(trade.timestamp.timestamp_micros(), 1700000000000123);
+    }
+}
@@ -0,0 +1,242 @@
+//! Bitstamp WebSocket API v2 (`live_trades`, `order_book` and
+//! `diff_order_book` channels).
+//!
+//! Channels are named `<channel>_<pair>`, e.g. `live_trades_btcusd`; the pair
+//! is upper-cased into the normalized symbol. `order_book` pushes replace the
+//! local book, while `diff_order_book` changes are applied on top of it, so
+//! subscribe to both to get a full book from the start. Pairs limited to the
+//! top levels only take `order_book`, whose pushes already carry the best
+//! 100 levels.
+
+use super::json::{decimal, JsonBackend, JsonDecoder};
+use super::Adapter;
+use crate::book::{BookDepth, BookSide, OrderBook};
+use crate::client::{ClientError, Result};
+use crate::clock::TimestampPrecision;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, OrderBookSnapshot, PriceLevel, Trade, TradeConditions, TradeSide};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const CHANNELS: [&str; 3] = ["live_trades", "order_book", "diff_order_book"];
+
+#[derive(Deserialize)]
+struct Message<'a> {
+    #[serde(borrow)]
+    event: &'a str,
+    #[serde(borrow)]
+    channel: Option<&'a str>,
+    #[serde(borrow)]
+    data: Option<Data<'a>>,
+}
+
+#[derive(Deserialize)]
+struct Data<'a> {
+    id: Option<u64>,
+    #[serde(borrow)]
+    amount_str: Option<&'a str>,
+    #[serde(borrow)]
+    price_str: Option<&'a str>,
+    /// 0 for a buy, 1 for a sell, from the taker's point of view
+    #[serde(rename = "type")]
+    kind: Option<u8>,
+    #[serde(borrow)]
+    microtimestamp: Option<&'a str>,
+    #[serde(borrow, default)]
+    bids: Vec<(&'a str, &'a str)>,
+    #[serde(borrow, default)]
+    asks: Vec<(&'a str, &'a str)>,
+}
+
+/// Normalizes Bitstamp trades and order books
+pub struct BitstampAdapter {
+    pairs: Vec<String>,
+    decoder: JsonDecoder,
+    books: HashMap<String, OrderBook>,
+    depths: HashMap<String, BookDepth>,
+}
+
+impl BitstampAdapter {
+    /// Adapter subscribing to `pairs` such as `btcusd`
+    pub fn new(pairs: &[&str]) -> Self {
+        Self::with_backend(pairs, JsonBackend::default())
+    }
+
+    pub fn with_backend(pairs: &[&str], backend: JsonBackend) -> Self {
+        Self {
+            pairs: pairs.iter().map(|p| p.to_lowercase()).collect(),
+            decoder: JsonDecoder::new(backend),
+            books: HashMap::new(),
+            depths: HashMap::new(),
+        }
+    }
+
+    fn depth(&self, pair: &str) -> BookDepth {
+        self.depths.get(pair).copied().unwrap_or_default()
+    }
+}
+
+fn timestamp(micros: Option<&str>, received: DateTime<Utc>) -> DateTime<Utc> {
+    micros
+        .and_then(|micros| micros.parse().ok())
+        .and_then(|micros| TimestampPrecision::Micros.from_epoch(micros))
+        .unwrap_or(received)
+}
+
+fn levels<'a>(levels: &'a [(&'a str, &'a str)]) -> impl Iterator<Item = Result<PriceLevel>> + 'a {
+    levels.iter().map(|&(price, size)| {
+        Ok(PriceLevel {
+            price: decimal(price)?,
+            size: decimal(size)?,
+            num_orders: 0,
+        })
+    })
+}
+
+impl Adapter for BitstampAdapter {
+    fn name(&self) -> &str {
+        "bitstamp"
+    }
+
+    fn subscribe_frames(&self) -> Vec<String> {
+        self.pairs
+            .iter()
+            .flat_map(|pair| {
+                let channels = match self.depth(pair) {
+                    BookDepth::Full => &CHANNELS[..],
+                    _ => &CHANNELS[..2],
+                };
+                channels.iter().map(move |channel| {
+                    serde_json::json!({
+                        "event": "bts:subscribe",
+                        "data": { "channel": format!("{}_{}", channel, pair) }
+                    })
+                    .to_string()
+                })
+            })
+            .collect()
+    }
+
+    fn set_book_depth(&mut self, symbol: &str, depth: BookDepth) {
+        self.depths.insert(symbol.to_lowercase(), depth);
+    }
+
+    fn is_subscription_ack(&self, frame: &[u8]) -> bool {
+        frame.starts_with(br#"{"event":"bts:subscription_succeeded""#)
+    }
+
+    fn decode(
+        &mut self,
+        frame: &mut [u8],
+        received: DateTime<Utc>,
+        out: &mut Vec<MarketDataMessage>,
+    ) -> Result<()> {
+        let msg: Message = self.decoder.decode(frame)?;
+        if msg.event == "bts:heartbeat" {
+            out.push(MarketDataMessage::Heartbeat);
+            return Ok(());
+        }
+        let (Some(channel), Some(data)) = (msg.channel, &msg.data) else {
+            return Ok(());
+        };
+        let Some((kind, pair)) = CHANNELS
+            .iter()
+            .find_map(|kind| Some((*kind, channel.strip_prefix(kind)?.strip_prefix('_')?)))
+        else {
+            return Ok(());
+        };
+        let symbol = pair.to_uppercase();
+        let depth = self.depth(pair).levels();
+        let time = timestamp(data.microtimestamp, received);
+
+        match (msg.event, kind) {
+            ("trade", "live_trades") => {
+                let (Some(price), Some(amount)) = (data.price_str, data.amount_str) else {
+                    return Err(ClientError::Parse("incomplete bitstamp trade".to_string()));
+                };
+                out.push(MarketDataMessage::Trade(Trade {
+                    symbol: Symbol::new(&symbol)?,
+                    price: decimal(price)?,
+                    quantity: decimal(amount)?,
+                    side: match data.kind {
+                        Some(0) => TradeSide::Buy,
+                        Some(1) => TradeSide::Sell,
+                        _ => TradeSide::Unknown,
+                    },
+                    timestamp: time,
+                    trade_id: data.id.unwrap_or_default().to_string(),
+                    conditions: TradeConditions::empty(),
+                    instrument_id: None,
+                    contract: None,
+                    received: None,
+                }));
+            }
+            ("data", "order_book") => {
+                let snapshot = OrderBookSnapshot {
+                    symbol: Symbol::new(&symbol)?,
+                    bids: levels(&data.bids).collect::<Result<_>>()?,
+                    asks: levels(&data.asks).collect::<Result<_>>()?,
+                    timestamp: time,
+                    instrument_id: None,
+                    contract: None,
+                    received: None,
+                };
+                let book = self
+                    .books
+                    .entry(symbol)
+                    .or_insert_with_key(|symbol| OrderBook::new(symbol.clone()));
+                book.apply_snapshot(&snapshot);
+                out.push(MarketDataMessage::OrderBook(book.snapshot(depth)));
+            }
+            ("data", "diff_order_book") => {
+                let book = self
+                    .books
+                    .entry(symbol)
+                    .or_insert_with_key(|symbol| OrderBook::new(symbol.clone()));
+                for (side, changes) in [(BookSide::Bid, &data.bids), (BookSide::Ask, &data.asks)] {
+                    for level in levels(changes) {
+                        book.update_level(side, level?, time);
+                    }
+                }
+                out.push(MarketDataMessage::OrderBook(book.snapshot(depth)));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trades_and_book_diffs() {
+        let mut adapter = BitstampAdapter::new(&["btcusd"]);
+        let mut out = Vec::new();
+
+        let frames: [&[u8]; 3] = [
+            br#"{"data":{"id":312345678,"timestamp":"1700000000","amount":0.015,"amount_str":"0.01500000","price":37012,"price_str":"37012","type":1,"microtimestamp":"1700000000123456","buy_order_id":1,"sell_order_id":2},"channel":"live_trades_btcusd","event":"trade"}"#,
+            br#"{"data":{"timestamp":"1700000001","microtimestamp":"1700000001000000","bids":[["37010","0.5"],["37005","1.2"]],"asks":[["37015","0.8"]]},"channel":"order_book_btcusd","event":"data"}"#,
+            br#"{"data":{"timestamp":"1700000002","microtimestamp":"1700000002000000","bids":[["37010","0"]],"asks":[["37014","0.1"]]},"channel":"diff_order_book_btcusd","event":"data"}"#,
+        ];
+        for frame in frames {
+            adapter
+                .decode(&mut frame.to_vec(), Utc::now(), &mut out)
+                .unwrap();
+        }
+
+        let MarketDataMessage::Trade(trade) = &out[0] else {
+            panic!("expected trade");
+        };
+        assert_eq!(trade.symbol, "BTCUSD");
+        assert_eq!((trade.price, trade.side), (37012.0, TradeSide::Sell));
+        assert_eq!(trade.timestamp.timestamp_micros(), 1700000000123456);
+        let MarketDataMessage::OrderBook(book) = &out[2] else {
+            panic!("expected book");
+        };
+        assert_eq!(book.best_bid().unwrap().price, 37005.0);
+        assert_eq!(book.best_ask().unwrap().price, 37014.0);
+    }
+}
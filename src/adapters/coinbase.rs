@@ -0,0 +1,196 @@
+//! Coinbase Exchange WebSocket feed (`matches`, `ticker` and `heartbeat`).
+
+use super::json::{decimal, JsonBackend, JsonDecoder};
+use super::Adapter;
+use crate::client::{ClientError, Result};
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Quote, Trade, TradeConditions, TradeSide};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Message<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    #[serde(borrow)]
+    product_id: Option<&'a str>,
+    trade_id: Option<u64>,
+    #[serde(borrow)]
+    price: Option<&'a str>,
+    #[serde(borrow)]
+    size: Option<&'a str>,
+    #[serde(borrow)]
+    side: Option<&'a str>,
+    #[serde(borrow)]
+    time: Option<&'a str>,
+    #[serde(borrow)]
+    best_bid: Option<&'a str>,
+    #[serde(borrow)]
+    best_bid_size: Option<&'a str>,
+    #[serde(borrow)]
+    best_ask: Option<&'a str>,
+    #[serde(borrow)]
+    best_ask_size: Option<&'a str>,
+}
+
+/// Normalizes Coinbase match and ticker channels
+pub struct CoinbaseAdapter {
+    products: Vec<String>,
+    decoder: JsonDecoder,
+}
+
+impl CoinbaseAdapter {
+    /// Adapter subscribing to `products` such as `BTC-USD`
+    pub fn new(products: &[&str]) -> Self {
+        Self::with_backend(products, JsonBackend::default())
+    }
+
+    pub fn with_backend(products: &[&str], backend: JsonBackend) -> Self {
+        Self {
+            products: products.iter().map(|p| p.to_string()).collect(),
+            decoder: JsonDecoder::new(backend),
+        }
+    }
+}
+
+fn request(kind: &str, products: &[String]) -> String {
+    serde_json::json!({
+        "type": kind,
+        "product_ids": products,
+        "channels": ["matches", "ticker", "heartbeat"]
+    })
+    .to_string()
+}
+
+fn timestamp(time: Option<&str>, received: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    match time {
+        Some(time) => DateTime::parse_from_rfc3339(time)
+            .map(|ts| ts.with_timezone(&Utc))
+            .map_err(|e| ClientError::Parse(e.to_string())),
+        None => Ok(received),
+    }
+}
+
+impl Adapter for CoinbaseAdapter {
+    fn name(&self) -> &str {
+        "coinbase"
+    }
+
+    fn subscribe_frames(&self) -> Vec<String> {
+        vec![request("subscribe", &self.products)]
+    }
+
+    fn update_symbols(&mut self, add: &[String], remove: &[String]) -> Vec<String> {
+        let add: Vec<String> = add
+            .iter()
+            .filter(|p| !self.products.contains(p))
+            .cloned()
+            .collect();
+        let remove: Vec<String> = remove
+            .iter()
+            .filter(|p| self.products.contains(p))
+            .cloned()
+            .collect();
+        self.products.retain(|p| !remove.contains(p));
+        self.products.extend(add.iter().cloned());
+        let mut frames = Vec::new();
+        if !add.is_empty() {
+            frames.push(request("subscribe", &add));
+        }
+        if !remove.is_empty() {
+            frames.push(request("unsubscribe", &remove));
+        }
+        frames
+    }
+
+    fn is_subscription_ack(&self, frame: &[u8]) -> bool {
+        frame.starts_with(br#"{"type":"subscriptions""#)
+    }
+
+    fn decode(
+        &mut self,
+        frame: &mut [u8],
+        received: DateTime<Utc>,
+        out: &mut Vec<MarketDataMessage>,
+    ) -> Result<()> {
+        let msg: Message = self.decoder.decode(frame)?;
+        let incomplete = || ClientError::Parse(format!("incomplete coinbase {}", msg.kind));
+
+        match msg.kind {
+            "match" | "last_match" => {
+                let (Some(product), Some(price), Some(size)) =
+                    (msg.product_id, msg.price, msg.size)
+                else {
+                    return Err(incomplete());
+                };
+                out.push(MarketDataMessage::Trade(Trade {
+                    symbol: Symbol::new(product)?,
+                    price: decimal(price)?,
+                    quantity: decimal(size)?,
+                    // `side` is the maker's; the taker is on the other side
+                    side: match msg.side {
+                        Some("buy") => TradeSide::Sell,
+                        Some("sell") => TradeSide::Buy,
+                        _ => TradeSide::Unknown,
+                    },
+                    timestamp: timestamp(msg.time, received)?,
+                    trade_id: msg.trade_id.unwrap_or_default().to_string(),
+                    conditions: TradeConditions::empty(),
+                    instrument_id: None,
+                    contract: None,
+                    received: None,
+                }));
+            }
+            "ticker" => {
+                let (Some(product), Some(bid), Some(bid_size), Some(ask), Some(ask_size)) = (
+                    msg.product_id,
+                    msg.best_bid,
+                    msg.best_bid_size,
+                    msg.best_ask,
+                    msg.best_ask_size,
+                ) else {
+                    return Err(incomplete());
+                };
+                out.push(MarketDataMessage::Quote(Quote {
+                    symbol: Symbol::new(product)?,
+                    bid_price: decimal(bid)?,
+                    bid_size: decimal(bid_size)?,
+                    ask_price: decimal(ask)?,
+                    ask_size: decimal(ask_size)?,
+                    timestamp: timestamp(msg.time, received)?,
+                    instrument_id: None,
+                    contract: None,
+                    received: None,
+                }));
+            }
+            "heartbeat" => out.push(MarketDataMessage::Heartbeat),
+            "error" => {
+                return Err(ClientError::WebSocket(
+                    "coinbase rejected request".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_uses_taker_side() {
+        let mut adapter = CoinbaseAdapter::new(&["BTC-USD"]);
+        let mut out = Vec::new();
+        let mut frame = br#"{"type":"match","trade_id":10,"sequence":50,"time":"2024-01-01T00:00:00.123456Z","product_id":"BTC-USD","size":"0.01","price":"42000.5","side":"sell"}"#.to_vec();
+        adapter.decode(&mut frame, Utc::now(), &mut out).unwrap();
+
+        let MarketDataMessage::Trade(trade) = &out[0] else {
+            panic!("expected trade");
+        };
+        assert_eq!(trade.side, TradeSide::Buy);
+        assert_eq!(trade.price, 42000.5);
+        assert_eq!(trade.timestamp.timestamp_subsec_micros(), 123456);
+    }
+}
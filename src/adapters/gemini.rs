@@ -0,0 +1,195 @@
+//! Gemini market data API v2 (`l2` subscription).
+//!
+//! The first `l2_updates` message for a symbol carries the full book as
+//! changes together with recent `trades`; later ones are incremental. Trades
+//! in that first message predate the subscription and are not re-emitted.
+
+use super::json::{decimal, JsonBackend, JsonDecoder};
+use super::Adapter;
+use crate::book::{BookDepth, BookSide, OrderBook};
+use crate::client::{ClientError, Result};
+use crate::clock::TimestampPrecision;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, PriceLevel, Trade, TradeConditions, TradeSide};
+use chrono::{DateTime, Utc};
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Levels per side included in emitted book snapshots, unless set per symbol
+const SNAPSHOT_DEPTH: usize = 50;
+
+#[derive(Deserialize)]
+struct Message<'a> {
+    #[serde(rename = "type", borrow)]
+    kind: &'a str,
+    #[serde(borrow)]
+    symbol: Option<&'a str>,
+    /// `[side, price, quantity]`
+    #[serde(borrow, default)]
+    changes: Vec<(&'a str, &'a str, &'a str)>,
+    /// Recent trades, only sent with the initial book
+    trades: Option<IgnoredAny>,
+    event_id: Option<u64>,
+    timestamp: Option<i64>,
+    #[serde(borrow)]
+    price: Option<&'a str>,
+    #[serde(borrow)]
+    quantity: Option<&'a str>,
+    #[serde(borrow)]
+    side: Option<&'a str>,
+}
+
+/// Normalizes Gemini level 2 books and trades
+pub struct GeminiAdapter {
+    symbols: Vec<String>,
+    decoder: JsonDecoder,
+    books: HashMap<String, OrderBook>,
+    depths: HashMap<String, BookDepth>,
+}
+
+impl GeminiAdapter {
+    /// Adapter subscribing to `symbols` such as `BTCUSD`
+    pub fn new(symbols: &[&str]) -> Self {
+        Self::with_backend(symbols, JsonBackend::default())
+    }
+
+    pub fn with_backend(symbols: &[&str], backend: JsonBackend) -> Self {
+        Self {
+            symbols: symbols.iter().map(|s| s.to_uppercase()).collect(),
+            decoder: JsonDecoder::new(backend),
+            books: HashMap::new(),
+            depths: HashMap::new(),
+        }
+    }
+}
+
+impl Adapter for GeminiAdapter {
+    fn name(&self) -> &str {
+        "gemini"
+    }
+
+    fn subscribe_frames(&self) -> Vec<String> {
+        vec![serde_json::json!({
+            "type": "subscribe",
+            "subscriptions": [{ "name": "l2", "symbols": self.symbols }]
+        })
+        .to_string()]
+    }
+
+    fn set_book_depth(&mut self, symbol: &str, depth: BookDepth) {
+        self.depths.insert(symbol.to_uppercase(), depth);
+    }
+
+    fn decode(
+        &mut self,
+        frame: &mut [u8],
+        received: DateTime<Utc>,
+        out: &mut Vec<MarketDataMessage>,
+    ) -> Result<()> {
+        let msg: Message = self.decoder.decode(frame)?;
+        let incomplete = || ClientError::Parse(format!("incomplete gemini {}", msg.kind));
+
+        match msg.kind {
+            "trade" => {
+                let (Some(symbol), Some(price), Some(quantity)) =
+                    (msg.symbol, msg.price, msg.quantity)
+                else {
+                    return Err(incomplete());
+                };
+                out.push(MarketDataMessage::Trade(Trade {
+                    symbol: Symbol::new(symbol)?,
+                    price: decimal(price)?,
+                    quantity: decimal(quantity)?,
+                    // `side` is the taker's
+                    side: match msg.side {
+                        Some("buy") => TradeSide::Buy,
+                        Some("sell") => TradeSide::Sell,
+                        _ => TradeSide::Unknown,
+                    },
+                    timestamp: msg
+                        .timestamp
+                        .and_then(|ms| TimestampPrecision::Millis.from_epoch(ms))
+                        .unwrap_or(received),
+                    trade_id: msg.event_id.unwrap_or_default().to_string(),
+                    conditions: TradeConditions::empty(),
+                    instrument_id: None,
+                    contract: None,
+                    received: None,
+                }));
+            }
+            "l2_updates" => {
+                let Some(symbol) = msg.symbol else {
+                    return Err(incomplete());
+                };
+                if msg.trades.is_some() {
+                    self.books.remove(symbol);
+                }
+                let book = self
+                    .books
+                    .entry(symbol.to_string())
+                    .or_insert_with_key(|symbol| OrderBook::new(symbol.clone()));
+                for &(side, price, quantity) in &msg.changes {
+                    let side = match side {
+                        "buy" => BookSide::Bid,
+                        "sell" => BookSide::Ask,
+                        _ => return Err(incomplete()),
+                    };
+                    let level = PriceLevel {
+                        price: decimal(price)?,
+                        size: decimal(quantity)?,
+                        num_orders: 0,
+                    };
+                    book.update_level(side, level, received);
+                }
+                let depth = self
+                    .depths
+                    .get(symbol)
+                    .map_or(Some(SNAPSHOT_DEPTH), |depth| depth.levels());
+                out.push(MarketDataMessage::OrderBook(book.snapshot(depth)));
+            }
+            "heartbeat" => out.push(MarketDataMessage::Heartbeat),
+            "error" => {
+                return Err(ClientError::WebSocket(
+                    "gemini rejected request".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initial_book_updates_and_trades() {
+        let mut adapter = GeminiAdapter::new(&["btcusd"]);
+        let mut out = Vec::new();
+
+        let frames: [&[u8]; 3] = [
+            br#"{"type":"l2_updates","symbol":"BTCUSD","changes":[["buy","9122.04","0.5"],["sell","9122.07","0.98"],["sell","9123","2"]],"trades":[{"type":"trade","symbol":"BTCUSD","event_id":1,"timestamp":1560976400428,"price":"9122.04","quantity":"0.01","side":"sell"}]}"#,
+            br#"{"type":"trade","symbol":"BTCUSD","event_id":3575573053,"timestamp":1593803138224,"price":"9122.07","quantity":"0.03","side":"buy"}"#,
+            br#"{"type":"l2_updates","symbol":"BTCUSD","changes":[["sell","9122.07","0"]]}"#,
+        ];
+        for frame in frames {
+            adapter
+                .decode(&mut frame.to_vec(), Utc::now(), &mut out)
+                .unwrap();
+        }
+
+        assert_eq!(out.len(), 3);
+        let MarketDataMessage::Trade(trade) = &out[1] else {
+            panic!("expected trade");
+        };
+        assert_eq!((trade.price, trade.side), (9122.07, TradeSide::Buy));
+        assert_eq!(trade.trade_id, "3575573053");
+        let MarketDataMessage::OrderBook(book) = &out[2] else {
+            panic!("expected book");
+        };
+        assert_eq!(book.best_bid().unwrap().size, 0.5);
+        assert_eq!(book.best_ask().unwrap().price, 9123.0);
+    }
+}
@@ -0,0 +1,208 @@
+//! IEX Cloud streaming (server-sent events) for the `tops` and `last`
+//! channels.
+//!
+//! IEX streams over HTTP rather than WebSocket: the subscription and the API
+//! token are part of the URL built by [`IexAdapter::stream_url`], and the
+//! client needs the `sse` feature to connect to it. Each event is a JSON
+//! array of updates. Last sale reports carry no aggressor, so trades have
+//! [`TradeSide::Unknown`] sides for an
+//! [`AggressorInference`](crate::aggressor::AggressorInference) stage to
+//! fill in.
+
+use super::json::{JsonBackend, JsonDecoder};
+use super::Adapter;
+use crate::client::{ClientError, Result};
+use crate::clock::TimestampPrecision;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Quote, Trade, TradeConditions, TradeSide};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const BASE_URL: &str = "https://cloud-sse.iexapis.com/stable";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Update<'a> {
+    #[serde(borrow)]
+    symbol: Option<&'a str>,
+    bid_price: Option<f64>,
+    bid_size: Option<f64>,
+    ask_price: Option<f64>,
+    ask_size: Option<f64>,
+    last_updated: Option<i64>,
+    last_sale_price: Option<f64>,
+    last_sale_size: Option<f64>,
+    last_sale_time: Option<i64>,
+    /// `last` channel fields
+    price: Option<f64>,
+    size: Option<f64>,
+    time: Option<i64>,
+    seq: Option<u64>,
+}
+
+#[derive(Default)]
+struct SymbolState {
+    /// Time of the last sale already emitted; TOPS repeats it on every update
+    last_sale_time: Option<i64>,
+}
+
+/// Normalizes IEX Cloud TOPS quotes and last sale reports
+pub struct IexAdapter {
+    decoder: JsonDecoder,
+    symbols: HashMap<String, SymbolState>,
+}
+
+impl IexAdapter {
+    pub fn new() -> Self {
+        Self::with_backend(JsonBackend::default())
+    }
+
+    pub fn with_backend(backend: JsonBackend) -> Self {
+        Self {
+            decoder: JsonDecoder::new(backend),
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// Streaming URL for `channel` (`tops` or `last`), authenticated with a
+    /// publishable API `token`
+    pub fn stream_url(channel: &str, symbols: &[&str], token: &str) -> String {
+        format!(
+            "{}/{}?symbols={}&token={}",
+            BASE_URL,
+            channel,
+            symbols.join(",").to_uppercase(),
+            token
+        )
+    }
+}
+
+impl Default for IexAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn millis(time: Option<i64>, received: DateTime<Utc>) -> DateTime<Utc> {
+    time.and_then(|time| TimestampPrecision::Millis.from_epoch(time))
+        .unwrap_or(received)
+}
+
+impl Adapter for IexAdapter {
+    fn name(&self) -> &str {
+        "iex"
+    }
+
+    fn subscribe_frames(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn decode(
+        &mut self,
+        frame: &mut [u8],
+        received: DateTime<Utc>,
+        out: &mut Vec<MarketDataMessage>,
+    ) -> Result<()> {
+        let updates: Vec<Update> = self.decoder.decode(frame)?;
+
+        for update in updates {
+            let Some(symbol) = update.symbol else {
+                return Err(ClientError::Parse("iex update without symbol".to_string()));
+            };
+            if !self.symbols.contains_key(symbol) {
+                self.symbols
+                    .insert(symbol.to_string(), SymbolState::default());
+            }
+            let state = self.symbols.get_mut(symbol).unwrap();
+
+            if let (Some(bid), Some(bid_size), Some(ask), Some(ask_size)) = (
+                update.bid_price,
+                update.bid_size,
+                update.ask_price,
+                update.ask_size,
+            ) {
+                // Zero prices mean there is no quote on that side
+                if bid > 0.0 && ask > 0.0 {
+                    out.push(MarketDataMessage::Quote(Quote {
+                        symbol: Symbol::new(symbol)?,
+                        bid_price: bid,
+                        bid_size,
+                        ask_price: ask,
+                        ask_size,
+                        timestamp: millis(update.last_updated, received),
+                        instrument_id: None,
+                        contract: None,
+                        received: None,
+                    }));
+                }
+            }
+
+            let sale = match (update.price, update.size, update.time) {
+                (Some(price), Some(size), time) => Some((price, size, time)),
+                _ => match (update.last_sale_price, update.last_sale_size) {
+                    (Some(price), Some(size)) if size > 0.0 => {
+                        Some((price, size, update.last_sale_time))
+                    }
+                    _ => None,
+                },
+            };
+            if let Some((price, size, time)) = sale {
+                if time.is_some() && time == state.last_sale_time {
+                    continue;
+                }
+                state.last_sale_time = time;
+                out.push(MarketDataMessage::Trade(Trade {
+                    symbol: Symbol::new(symbol)?,
+                    price,
+                    quantity: size,
+                    side: TradeSide::Unknown,
+                    timestamp: millis(time, received),
+                    trade_id: update.seq.map(|seq| seq.to_string()).unwrap_or_default(),
+                    conditions: TradeConditions::empty(),
+                    instrument_id: None,
+                    contract: None,
+                    received: None,
+                }));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tops_and_last() {
+        let mut adapter = IexAdapter::new();
+        let mut out = Vec::new();
+
+        let frames: [&[u8]; 3] = [
+            br#"[{"symbol":"AAPL","bidSize":100,"bidPrice":172.5,"askSize":200,"askPrice":172.6,"volume":1000,"lastSalePrice":172.58,"lastSaleSize":10,"lastSaleTime":1709303400000,"lastUpdated":1709303400005}]"#,
+            br#"[{"symbol":"AAPL","bidSize":100,"bidPrice":172.5,"askSize":100,"askPrice":172.6,"volume":1000,"lastSalePrice":172.58,"lastSaleSize":10,"lastSaleTime":1709303400000,"lastUpdated":1709303400010}]"#,
+            br#"[{"symbol":"AAPL","price":172.51,"size":50,"time":1709303400020,"seq":42}]"#,
+        ];
+        for frame in frames {
+            adapter
+                .decode(&mut frame.to_vec(), Utc::now(), &mut out)
+                .unwrap();
+        }
+
+        assert_eq!(out.len(), 4);
+        let MarketDataMessage::Trade(trade) = &out[1] else {
+            panic!("expected trade");
+        };
+        assert_eq!((trade.price, trade.side), (172.58, TradeSide::Unknown));
+        let MarketDataMessage::Trade(trade) = &out[3] else {
+            panic!("expected trade");
+        };
+        assert_eq!(
+            (trade.side, trade.trade_id.as_str()),
+            (TradeSide::Unknown, "42")
+        );
+        assert!(IexAdapter::stream_url("tops", &["aapl"], "pk_test")
+            .ends_with("tops?symbols=AAPL&token=pk_test"));
+    }
+}
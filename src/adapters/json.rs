@@ -0,0 +1,142 @@
+//! JSON decoding backends shared by the adapters.
+
+use crate::client::{ClientError, Result};
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fmt;
+
+/// Parser used for exchange frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonBackend {
+    SerdeJson,
+    /// simd-json in borrowed mode, parsing the frame buffer in place
+    #[cfg(feature = "simd-json")]
+    SimdJson,
+}
+
+impl Default for JsonBackend {
+    fn default() -> Self {
+        #[cfg(feature = "simd-json")]
+        return JsonBackend::SimdJson;
+        #[cfg(not(feature = "simd-json"))]
+        return JsonBackend::SerdeJson;
+    }
+}
+
+/// Decoder holding reusable parser scratch space
+pub(crate) struct JsonDecoder {
+    backend: JsonBackend,
+    #[cfg(feature = "simd-json")]
+    buffers: simd_json::Buffers,
+}
+
+impl JsonDecoder {
+    pub fn new(backend: JsonBackend) -> Self {
+        Self {
+            backend,
+            #[cfg(feature = "simd-json")]
+            buffers: simd_json::Buffers::default(),
+        }
+    }
+
+    /// Deserialize a frame; the simd-json backend may overwrite `frame`
+    pub fn decode<'a, T: Deserialize<'a>>(&mut self, frame: &'a mut [u8]) -> Result<T> {
+        match self.backend {
+            JsonBackend::SerdeJson => {
+                serde_json::from_slice(frame).map_err(|e| ClientError::Parse(e.to_string()))
+            }
+            #[cfg(feature = "simd-json")]
+            JsonBackend::SimdJson => {
+                simd_json::serde::from_slice_with_buffers(frame, &mut self.buffers)
+                    .map_err(|e| ClientError::Parse(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Parse a decimal sent as a JSON string
+pub(crate) fn decimal(value: &str) -> Result<f64> {
+    value
+        .parse()
+        .map_err(|_| ClientError::Parse(format!("invalid decimal {:?}", value)))
+}
+
+/// A JSON string or number, for keys a venue reuses with both types.
+/// Only the string form is kept, borrowed from the frame unless it had to be
+/// unescaped.
+pub(crate) enum Scalar<'a> {
+    Str(Cow<'a, str>),
+    Num,
+}
+
+impl Scalar<'_> {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Scalar::Str(value) => Some(value),
+            Scalar::Num => None,
+        }
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Scalar<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct ScalarVisitor;
+
+        impl<'de> Visitor<'de> for ScalarVisitor {
+            type Value = Scalar<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or number")
+            }
+
+            fn visit_borrowed_str<E: de::Error>(
+                self,
+                v: &'de str,
+            ) -> std::result::Result<Self::Value, E> {
+                Ok(Scalar::Str(Cow::Borrowed(v)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<Self::Value, E> {
+                Ok(Scalar::Str(Cow::Owned(v.to_string())))
+            }
+
+            fn visit_string<E: de::Error>(self, v: String) -> std::result::Result<Self::Value, E> {
+                Ok(Scalar::Str(Cow::Owned(v)))
+            }
+
+            fn visit_u64<E: de::Error>(self, _: u64) -> std::result::Result<Self::Value, E> {
+                Ok(Scalar::Num)
+            }
+
+            fn visit_i64<E: de::Error>(self, _: i64) -> std::result::Result<Self::Value, E> {
+                Ok(Scalar::Num)
+            }
+
+            fn visit_f64<E: de::Error>(self, _: f64) -> std::result::Result<Self::Value, E> {
+                Ok(Scalar::Num)
+            }
+        }
+
+        deserializer.deserialize_any(ScalarVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalar_strings_borrowed_or_unescaped() {
+        let scalars: Vec<Scalar> = serde_json::from_str(r#"["100.5", "1\u0030", 7, 1.5]"#).unwrap();
+        assert!(matches!(scalars[0], Scalar::Str(Cow::Borrowed("100.5"))));
+        // Escapes can only be decoded into an owned copy
+        assert_eq!(scalars[1].as_str(), Some("10"));
+        assert_eq!(scalars[2].as_str(), None);
+        assert_eq!(scalars[3].as_str(), None);
+
+        // Deserializers without a buffer to borrow from hand out owned strings
+        let scalar = Scalar::deserialize(serde_json::json!("2.5")).unwrap();
+        assert_eq!(scalar.as_str(), Some("2.5"));
+    }
+}
@@ -0,0 +1,111 @@
+//! Exchange adapters normalizing venue-specific frames into
+//! [`MarketDataMessage`]s.
+//!
+//! An [`Adapter`] owns the subscription handshake and frame decoding for one
+//! venue protocol. JSON adapters parse with serde_json, or with simd-json when
+//! the `simd-json` feature is enabled.
+
+mod alpaca;
+mod binance;
+mod bitstamp;
+mod coinbase;
+mod gemini;
+mod iex;
+mod json;
+mod native;
+mod okx;
+mod sbe;
+
+pub use alpaca::AlpacaAdapter;
+pub use binance::BinanceAdapter;
+pub use bitstamp::BitstampAdapter;
+pub use coinbase::CoinbaseAdapter;
+pub use gemini::GeminiAdapter;
+pub use iex::IexAdapter;
+pub use json::JsonBackend;
+pub use native::NativeAdapter;
+pub use okx::OkxAdapter;
+pub use sbe::{SbeAdapter, SbeHandler};
+
+use crate::book::BookDepth;
+use crate::client::Result;
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Utc};
+
+/// Venue protocol handling for a client connection
+pub trait Adapter: Send {
+    /// Short venue name used in logs
+    fn name(&self) -> &str;
+
+    /// Frames to send after connecting (and on resubscribe)
+    fn subscribe_frames(&self) -> Vec<String>;
+
+    /// Add and drop symbols, returning the frames that apply the change to a
+    /// live connection. Later [`subscribe_frames`](Self::subscribe_frames)
+    /// include the change. Venues subscribed by channel rather than by
+    /// symbol send nothing.
+    fn update_symbols(&mut self, _add: &[String], _remove: &[String]) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Limit the book levels needed for `symbol`. Venues publishing several
+    /// book channels subscribe to the cheapest one covering `depth`, and
+    /// emitted snapshots are truncated to it; takes effect on the next
+    /// subscription.
+    fn set_book_depth(&mut self, _symbol: &str, _depth: BookDepth) {}
+
+    /// Extra HTTP headers for the connection request, e.g. to negotiate a
+    /// binary encoding
+    fn connect_headers(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Whether `frame` acknowledges a subscription request. Called on the
+    /// unmodified frame before it is decoded.
+    fn is_subscription_ack(&self, _frame: &[u8]) -> bool {
+        false
+    }
+
+    /// Decode one received frame, pushing zero or more messages to `out`.
+    ///
+    /// `received` is when the frame arrived and stands in for timestamps the
+    /// venue does not send. The frame buffer may be modified in place by the
+    /// parser.
+    fn decode(
+        &mut self,
+        frame: &mut [u8],
+        received: DateTime<Utc>,
+        out: &mut Vec<MarketDataMessage>,
+    ) -> Result<()>;
+}
+
+/// Build an adapter by venue name, e.g. `binance`, for CLI tools and fixtures
+pub fn by_name(name: &str, symbols: &[&str]) -> Option<Box<dyn Adapter>> {
+    match name {
+        "native" => Some(Box::new(NativeAdapter::new())),
+        "alpaca" => Some(Box::new(AlpacaAdapter::from_env(symbols))),
+        "binance" => Some(Box::new(BinanceAdapter::new(symbols))),
+        "bitstamp" => Some(Box::new(BitstampAdapter::new(symbols))),
+        "coinbase" => Some(Box::new(CoinbaseAdapter::new(symbols))),
+        "gemini" => Some(Box::new(GeminiAdapter::new(symbols))),
+        "iex" => Some(Box::new(IexAdapter::new())),
+        "okx" => Some(Box::new(OkxAdapter::new(symbols))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_by_name() {
+        for name in [
+            "native", "alpaca", "binance", "bitstamp", "coinbase", "gemini", "iex", "okx",
+        ] {
+            assert_eq!(by_name(name, &["BTCUSD"]).unwrap().name(), name);
+        }
+        assert!(by_name("kraken", &["BTCUSD"]).is_none());
+        assert!(by_name("Binance", &[]).is_none());
+    }
+}
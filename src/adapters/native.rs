@@ -0,0 +1,179 @@
+//! The crate's own tagged JSON message format.
+//!
+//! Frames whose `type` this build does not know, such as message types added
+//! by a newer sender, are skipped rather than rejected.
+
+use super::json::{JsonBackend, JsonDecoder};
+use super::Adapter;
+use crate::client::Result;
+use crate::types::{
+    MarketDataMessage, OrderBookSnapshot, Quote, Trade, TradeBust, TradeCorrection,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tracing::debug;
+
+/// [`MarketDataMessage`] as read off the wire, with unknown types caught
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Wire {
+    Trade(Trade),
+    Quote(Quote),
+    OrderBook(OrderBookSnapshot),
+    Heartbeat,
+    TradeCorrection(TradeCorrection),
+    TradeBust(TradeBust),
+    #[serde(other)]
+    Unknown,
+}
+
+impl Wire {
+    fn into_message(self) -> Option<MarketDataMessage> {
+        Some(match self {
+            Wire::Trade(trade) => MarketDataMessage::Trade(trade),
+            Wire::Quote(quote) => MarketDataMessage::Quote(quote),
+            Wire::OrderBook(book) => MarketDataMessage::OrderBook(book),
+            Wire::Heartbeat => MarketDataMessage::Heartbeat,
+            Wire::TradeCorrection(correction) => MarketDataMessage::TradeCorrection(correction),
+            Wire::TradeBust(bust) => MarketDataMessage::TradeBust(bust),
+            Wire::Unknown => return None,
+        })
+    }
+}
+
+/// Feeds that already speak [`MarketDataMessage`] JSON
+pub struct NativeAdapter {
+    decoder: JsonDecoder,
+}
+
+impl NativeAdapter {
+    pub fn new() -> Self {
+        Self::with_backend(JsonBackend::default())
+    }
+
+    pub fn with_backend(backend: JsonBackend) -> Self {
+        Self {
+            decoder: JsonDecoder::new(backend),
+        }
+    }
+}
+
+impl Default for NativeAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adapter for NativeAdapter {
+    fn name(&self) -> &str {
+        "native"
+    }
+
+    fn subscribe_frames(&self) -> Vec<String> {
+        vec![serde_json::json!({
+            "type": "subscribe",
+            "channels": ["trades", "quotes", "orderbook"]
+        })
+        .to_string()]
+    }
+
+    fn decode(
+        &mut self,
+        frame: &mut [u8],
+        _received: DateTime<Utc>,
+        out: &mut Vec<MarketDataMessage>,
+    ) -> Result<()> {
+        let wire: Wire = self.decoder.decode(frame)?;
+        match wire.into_message() {
+            Some(msg) => out.push(msg),
+            None => debug!("Skipping message of unknown type"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{PriceLevel, TradeConditions, TradeTerms};
+
+    #[test]
+    fn test_round_trips_every_message_kind() {
+        let terms = TradeTerms {
+            price: 100.5,
+            quantity: 2.0,
+            conditions: TradeConditions::empty(),
+        };
+        let messages = [
+            MarketDataMessage::Trade(Trade::test("BTCUSD", 100.5)),
+            MarketDataMessage::Quote(Quote::test("BTCUSD", 100.0, 101.0)),
+            MarketDataMessage::OrderBook(OrderBookSnapshot {
+                symbol: "ETHUSD".into(),
+                bids: vec![PriceLevel {
+                    price: 10.0,
+                    size: 2.0,
+                    num_orders: 1,
+                }],
+                ..Default::default()
+            }),
+            MarketDataMessage::Heartbeat,
+            MarketDataMessage::TradeCorrection(TradeCorrection {
+                symbol: "BTCUSD".into(),
+                trade_id: "1".to_string(),
+                timestamp: Utc::now(),
+                original: None,
+                corrected: terms,
+                instrument_id: None,
+                contract: None,
+                received: None,
+            }),
+            MarketDataMessage::TradeBust(TradeBust {
+                symbol: "BTCUSD".into(),
+                trade_id: "2".to_string(),
+                timestamp: Utc::now(),
+                original: Some(terms),
+                instrument_id: None,
+                contract: None,
+                received: None,
+            }),
+        ];
+        let mut adapter = NativeAdapter::with_backend(JsonBackend::SerdeJson);
+        let mut out = Vec::new();
+        for msg in &messages {
+            let mut frame = serde_json::to_vec(msg).unwrap();
+            adapter.decode(&mut frame, Utc::now(), &mut out).unwrap();
+        }
+        let json = |msgs: &[MarketDataMessage]| serde_json::to_value(msgs).unwrap();
+        assert_eq!(json(&out), json(&messages));
+    }
+
+    #[test]
+    fn test_rejects_foreign_frames() {
+        let mut adapter = NativeAdapter::new();
+        let mut out = Vec::new();
+        for frame in [
+            &b"not json"[..],
+            br#"{"type":"Trade","symbol":"BTCUSD"}"#,
+            br#"{"price":1.0}"#,
+        ] {
+            let mut frame = frame.to_vec();
+            assert!(adapter.decode(&mut frame, Utc::now(), &mut out).is_err());
+        }
+        assert!(out.is_empty());
+        assert_eq!(adapter.subscribe_frames().len(), 1);
+    }
+
+    #[test]
+    fn test_skips_unknown_message_types() {
+        let mut adapter = NativeAdapter::new();
+        let mut out = Vec::new();
+        for frame in [
+            &br#"{"type":"Imbalance","symbol":"BTCUSD","paired":10.0}"#[..],
+            br#"{"type":"Heartbeat"}"#,
+        ] {
+            let mut frame = frame.to_vec();
+            adapter.decode(&mut frame, Utc::now(), &mut out).unwrap();
+        }
+        assert!(matches!(out[..], [MarketDataMessage::Heartbeat]));
+    }
+}
@@ -0,0 +1,413 @@
+//! OKX v5 public WebSocket channels (`tickers`, `trades` and `books`).
+//!
+//! Pushes wrap an array of payloads in `data`, keyed by the subscription's
+//! `arg`. Order books arrive as a snapshot followed by incremental updates
+//! carrying a CRC32 checksum of the top 25 levels, which is verified against
+//! the locally maintained book. Symbols limited to the top 1 or 5 levels use
+//! the lighter `bbo-tbt` and `books5` snapshot channels instead. Only the
+//! public endpoint is handled; private
+//! channels need a login request and are not supported yet.
+
+use super::json::{decimal, JsonBackend, JsonDecoder};
+use super::Adapter;
+use crate::book::{BookDepth, Price};
+use crate::client::{ClientError, Result};
+use crate::clock::TimestampPrecision;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, OrderBookSnapshot, PriceLevel, Quote, Trade, TradeConditions, TradeSide};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Levels per side covered by the book checksum
+const CHECKSUM_DEPTH: usize = 25;
+
+#[derive(Deserialize)]
+struct Push<'a> {
+    #[serde(borrow)]
+    event: Option<&'a str>,
+    msg: Option<String>,
+    #[serde(borrow)]
+    arg: Option<Arg<'a>>,
+    /// `snapshot` or `update` for order books
+    #[serde(borrow)]
+    action: Option<&'a str>,
+    #[serde(borrow, default)]
+    data: Vec<Data<'a>>,
+}
+
+#[derive(Deserialize)]
+struct Arg<'a> {
+    #[serde(borrow)]
+    channel: &'a str,
+    #[serde(rename = "instId", borrow)]
+    inst_id: Option<&'a str>,
+}
+
+/// `[price, size, deprecated, order count]`
+type Level<'a> = (&'a str, &'a str, &'a str, &'a str);
+
+#[derive(Deserialize)]
+struct Data<'a> {
+    #[serde(rename = "instId", borrow)]
+    inst_id: Option<&'a str>,
+    #[serde(rename = "tradeId", borrow)]
+    trade_id: Option<&'a str>,
+    #[serde(borrow)]
+    px: Option<&'a str>,
+    #[serde(borrow)]
+    sz: Option<&'a str>,
+    #[serde(borrow)]
+    side: Option<&'a str>,
+    #[serde(borrow)]
+    ts: Option<&'a str>,
+    #[serde(rename = "bidPx", borrow)]
+    bid_px: Option<&'a str>,
+    #[serde(rename = "bidSz", borrow)]
+    bid_sz: Option<&'a str>,
+    #[serde(rename = "askPx", borrow)]
+    ask_px: Option<&'a str>,
+    #[serde(rename = "askSz", borrow)]
+    ask_sz: Option<&'a str>,
+    #[serde(borrow, default)]
+    bids: Vec<Level<'a>>,
+    #[serde(borrow, default)]
+    asks: Vec<Level<'a>>,
+    checksum: Option<i32>,
+    #[serde(rename = "seqId")]
+    seq_id: Option<i64>,
+    #[serde(rename = "prevSeqId")]
+    prev_seq_id: Option<i64>,
+}
+
+/// Level as sent by the venue; the checksum is over the original strings
+struct BookLevel {
+    price: String,
+    size: String,
+    orders: u32,
+}
+
+#[derive(Default)]
+struct Book {
+    bids: BTreeMap<Price, BookLevel>,
+    asks: BTreeMap<Price, BookLevel>,
+    seq_id: Option<i64>,
+}
+
+impl Book {
+    fn apply(&mut self, data: &Data) -> Result<()> {
+        for (levels, book) in [(&data.bids, &mut self.bids), (&data.asks, &mut self.asks)] {
+            for &(price, size, _, orders) in levels {
+                let key = Price(decimal(price)?);
+                if decimal(size)? == 0.0 {
+                    book.remove(&key);
+                } else {
+                    book.insert(
+                        key,
+                        BookLevel {
+                            price: price.to_string(),
+                            size: size.to_string(),
+                            orders: orders.parse().unwrap_or(0),
+                        },
+                    );
+                }
+            }
+        }
+        self.seq_id = data.seq_id;
+        Ok(())
+    }
+
+    /// CRC32 of `bid:size:ask:size:...` over the top levels, interleaved
+    fn checksum(&self) -> i32 {
+        let mut bids = self.bids.values().rev().take(CHECKSUM_DEPTH);
+        let mut asks = self.asks.values().take(CHECKSUM_DEPTH);
+        let mut text = String::new();
+        loop {
+            let (bid, ask) = (bids.next(), asks.next());
+            if bid.is_none() && ask.is_none() {
+                break;
+            }
+            for level in bid.into_iter().chain(ask) {
+                if !text.is_empty() {
+                    text.push(':');
+                }
+                text.push_str(&level.price);
+                text.push(':');
+                text.push_str(&level.size);
+            }
+        }
+        crc32fast::hash(text.as_bytes()) as i32
+    }
+
+    fn snapshot(
+        &self,
+        symbol: Symbol,
+        depth: BookDepth,
+        timestamp: DateTime<Utc>,
+    ) -> OrderBookSnapshot {
+        let depth = depth.levels().unwrap_or(usize::MAX);
+        let level = |(price, level): (&Price, &BookLevel)| PriceLevel {
+            price: price.0,
+            size: level.size.parse().unwrap_or(0.0),
+            num_orders: level.orders,
+        };
+        OrderBookSnapshot {
+            symbol,
+            bids: self.bids.iter().rev().take(depth).map(level).collect(),
+            asks: self.asks.iter().take(depth).map(level).collect(),
+            timestamp,
+            instrument_id: None,
+            contract: None,
+            received: None,
+        }
+    }
+}
+
+/// Normalizes OKX tickers, trades and checksummed order books
+pub struct OkxAdapter {
+    instruments: Vec<String>,
+    decoder: JsonDecoder,
+    books: HashMap<String, Book>,
+    depths: HashMap<String, BookDepth>,
+}
+
+impl OkxAdapter {
+    /// Adapter subscribing to instruments such as `BTC-USDT`
+    pub fn new(instruments: &[&str]) -> Self {
+        Self::with_backend(instruments, JsonBackend::default())
+    }
+
+    pub fn with_backend(instruments: &[&str], backend: JsonBackend) -> Self {
+        Self {
+            instruments: instruments.iter().map(|i| i.to_uppercase()).collect(),
+            decoder: JsonDecoder::new(backend),
+            books: HashMap::new(),
+            depths: HashMap::new(),
+        }
+    }
+
+    fn depth(&self, inst_id: &str) -> BookDepth {
+        self.depths.get(inst_id).copied().unwrap_or_default()
+    }
+}
+
+/// Cheapest public book channel covering `depth`
+fn book_channel(depth: BookDepth) -> &'static str {
+    match depth {
+        BookDepth::Top1 => "bbo-tbt",
+        BookDepth::Top5 => "books5",
+        BookDepth::Top20 | BookDepth::Full => "books",
+    }
+}
+
+fn timestamp(ts: Option<&str>, received: DateTime<Utc>) -> DateTime<Utc> {
+    ts.and_then(|ts| ts.parse().ok())
+        .and_then(|ts| TimestampPrecision::Millis.from_epoch(ts))
+        .unwrap_or(received)
+}
+
+impl Adapter for OkxAdapter {
+    fn name(&self) -> &str {
+        "okx"
+    }
+
+    fn subscribe_frames(&self) -> Vec<String> {
+        let args: Vec<serde_json::Value> = self
+            .instruments
+            .iter()
+            .flat_map(|inst_id| {
+                ["tickers", "trades", book_channel(self.depth(inst_id))]
+                    .map(|channel| serde_json::json!({ "channel": channel, "instId": inst_id }))
+            })
+            .collect();
+        vec![serde_json::json!({ "op": "subscribe", "args": args }).to_string()]
+    }
+
+    fn set_book_depth(&mut self, symbol: &str, depth: BookDepth) {
+        self.depths.insert(symbol.to_uppercase(), depth);
+    }
+
+    fn is_subscription_ack(&self, frame: &[u8]) -> bool {
+        frame.starts_with(br#"{"event":"subscribe""#)
+    }
+
+    fn decode(
+        &mut self,
+        frame: &mut [u8],
+        received: DateTime<Utc>,
+        out: &mut Vec<MarketDataMessage>,
+    ) -> Result<()> {
+        // Reply to the text keepalive `ping`
+        if frame == b"pong" {
+            out.push(MarketDataMessage::Heartbeat);
+            return Ok(());
+        }
+
+        let push: Push = self.decoder.decode(frame)?;
+        if push.event == Some("error") {
+            return Err(ClientError::WebSocket(format!(
+                "okx rejected request: {}",
+                push.msg.unwrap_or_default()
+            )));
+        }
+        // Subscription acks and other events carry no data
+        let Some(arg) = push.arg else {
+            return Ok(());
+        };
+
+        for data in &push.data {
+            let Some(inst_id) = data.inst_id.or(arg.inst_id) else {
+                continue;
+            };
+            let incomplete = || ClientError::Parse(format!("incomplete okx {}", arg.channel));
+
+            match arg.channel {
+                "trades" => {
+                    let (Some(price), Some(size)) = (data.px, data.sz) else {
+                        return Err(incomplete());
+                    };
+                    out.push(MarketDataMessage::Trade(Trade {
+                        symbol: Symbol::new(inst_id)?,
+                        price: decimal(price)?,
+                        quantity: decimal(size)?,
+                        // `side` is the taker's
+                        side: match data.side {
+                            Some("buy") => TradeSide::Buy,
+                            Some("sell") => TradeSide::Sell,
+                            _ => TradeSide::Unknown,
+                        },
+                        timestamp: timestamp(data.ts, received),
+                        trade_id: data.trade_id.unwrap_or_default().to_string(),
+                        conditions: TradeConditions::empty(),
+                        instrument_id: None,
+                        contract: None,
+                        received: None,
+                    }));
+                }
+                "tickers" => {
+                    let (Some(bid), Some(bid_size), Some(ask), Some(ask_size)) =
+                        (data.bid_px, data.bid_sz, data.ask_px, data.ask_sz)
+                    else {
+                        return Err(incomplete());
+                    };
+                    out.push(MarketDataMessage::Quote(Quote {
+                        symbol: Symbol::new(inst_id)?,
+                        bid_price: decimal(bid)?,
+                        bid_size: decimal(bid_size)?,
+                        ask_price: decimal(ask)?,
+                        ask_size: decimal(ask_size)?,
+                        timestamp: timestamp(data.ts, received),
+                        instrument_id: None,
+                        contract: None,
+                        received: None,
+                    }));
+                }
+                "books" | "books5" | "bbo-tbt" | "books50-l2-tbt" | "books-l2-tbt" => {
+                    let book = if push.action == Some("update") {
+                        let Some(book) = self.books.get_mut(inst_id) else {
+                            return Err(ClientError::Parse(format!(
+                                "okx book update for {} before snapshot",
+                                inst_id
+                            )));
+                        };
+                        if let (Some(prev), Some(seq)) = (data.prev_seq_id, book.seq_id) {
+                            if prev != seq {
+                                self.books.remove(inst_id);
+                                return Err(ClientError::Parse(format!(
+                                    "okx book sequence gap for {}",
+                                    inst_id
+                                )));
+                            }
+                        }
+                        book
+                    } else {
+                        self.books.insert(inst_id.to_string(), Book::default());
+                        self.books.get_mut(inst_id).unwrap()
+                    };
+                    book.apply(data)?;
+
+                    if let Some(expected) = data.checksum {
+                        if book.checksum() != expected {
+                            // Drop the book so later updates fail until a new
+                            // snapshot arrives (e.g. after resubscribing)
+                            self.books.remove(inst_id);
+                            return Err(ClientError::Parse(format!(
+                                "okx book checksum mismatch for {}",
+                                inst_id
+                            )));
+                        }
+                    }
+                    let depth = self.depths.get(inst_id).copied().unwrap_or_default();
+                    out.push(MarketDataMessage::OrderBook(book.snapshot(
+                        Symbol::new(inst_id)?,
+                        depth,
+                        timestamp(data.ts, received),
+                    )));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trades_tickers_and_books() {
+        let mut adapter = OkxAdapter::new(&["BTC-USDT"]);
+        let mut out = Vec::new();
+
+        let frames: [&[u8]; 4] = [
+            br#"{"arg":{"channel":"trades","instId":"BTC-USDT"},"data":[{"instId":"BTC-USDT","tradeId":"130639474","px":"42219.9","sz":"0.12060306","side":"sell","ts":"1630048897897"}]}"#,
+            br#"{"arg":{"channel":"tickers","instId":"BTC-USDT"},"data":[{"instType":"SPOT","instId":"BTC-USDT","last":"42220","askPx":"42220.1","askSz":"1.5","bidPx":"42219.9","bidSz":"0.3","ts":"1630048897900"}]}"#,
+            br#"{"arg":{"channel":"books","instId":"BTC-USDT"},"action":"snapshot","data":[{"asks":[["8476.98","415","0","13"],["8477","7","0","2"]],"bids":[["8476.97","256","0","12"],["8475.55","101","0","1"]],"ts":"1597026383085","checksum":2123921068,"prevSeqId":-1,"seqId":10}]}"#,
+            br#"{"arg":{"channel":"books","instId":"BTC-USDT"},"action":"update","data":[{"asks":[["8476.98","0","0","0"]],"bids":[],"ts":"1597026383086","checksum":1,"prevSeqId":10,"seqId":11}]}"#,
+        ];
+        for frame in &frames[..3] {
+            adapter
+                .decode(&mut frame.to_vec(), Utc::now(), &mut out)
+                .unwrap();
+        }
+        assert!(adapter
+            .decode(&mut frames[3].to_vec(), Utc::now(), &mut out)
+            .is_err());
+
+        let MarketDataMessage::Trade(trade) = &out[0] else {
+            panic!("expected trade");
+        };
+        assert_eq!((trade.price, trade.side), (42219.9, TradeSide::Sell));
+        let MarketDataMessage::Quote(quote) = &out[1] else {
+            panic!("expected quote");
+        };
+        assert_eq!((quote.bid_price, quote.ask_size), (42219.9, 1.5));
+        let MarketDataMessage::OrderBook(book) = &out[2] else {
+            panic!("expected book");
+        };
+        assert_eq!(book.best_ask().unwrap().num_orders, 13);
+        assert_eq!(book.bids.len(), 2);
+        assert!(adapter.books.is_empty());
+    }
+
+    #[test]
+    fn test_book_depth_tiers() {
+        let mut adapter = OkxAdapter::new(&["BTC-USDT", "ETH-USDT"]);
+        adapter.set_book_depth("btc-usdt", BookDepth::Top1);
+        let frames = adapter.subscribe_frames();
+        assert!(frames[0].contains(r#"{"channel":"bbo-tbt","instId":"BTC-USDT"}"#));
+        assert!(frames[0].contains(r#"{"channel":"books","instId":"ETH-USDT"}"#));
+
+        let mut out = Vec::new();
+        let frame = br#"{"arg":{"channel":"books","instId":"BTC-USDT"},"action":"snapshot","data":[{"asks":[["8476.98","415","0","13"],["8477","7","0","2"]],"bids":[["8476.97","256","0","12"],["8475.55","101","0","1"]],"ts":"1597026383085","checksum":2123921068}]}"#;
+        adapter
+            .decode(&mut frame.to_vec(), Utc::now(), &mut out)
+            .unwrap();
+        let MarketDataMessage::OrderBook(book) = &out[0] else {
+            panic!("expected book");
+        };
+        assert_eq!((book.bids.len(), book.asks.len()), (1, 1));
+        assert_eq!(book.best_bid().unwrap().price, 8476.97);
+    }
+}
@@ -0,0 +1,188 @@
+//! Generic adapter for SBE-encoded binary feeds.
+//!
+//! Messages are decoded with an [`SbeSchema`] and handed to per-template
+//! handlers that map the named fields to normalized messages. Feeds that
+//! batch several messages per frame, such as CME MDP 3.0 packets, are
+//! split with [`with_packet_header`](SbeAdapter::with_packet_header) and
+//! [`with_size_prefix`](SbeAdapter::with_size_prefix).
+
+use super::Adapter;
+use crate::client::{ClientError, Result};
+use crate::sbe::{SbeMessage, SbeSchema};
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Maps one decoded message to zero or more normalized messages
+pub type SbeHandler =
+    Box<dyn FnMut(&SbeMessage, DateTime<Utc>, &mut Vec<MarketDataMessage>) -> Result<()> + Send>;
+
+/// Normalizes SBE messages through user supplied per-template handlers
+pub struct SbeAdapter {
+    name: String,
+    schema: SbeSchema,
+    handlers: HashMap<u16, SbeHandler>,
+    subscribe_frames: Vec<String>,
+    packet_header_len: usize,
+    size_prefix: bool,
+}
+
+impl SbeAdapter {
+    pub fn new(name: &str, schema: SbeSchema) -> Self {
+        Self {
+            name: name.to_string(),
+            schema,
+            handlers: HashMap::new(),
+            subscribe_frames: Vec::new(),
+            packet_header_len: 0,
+            size_prefix: false,
+        }
+    }
+
+    /// Handle messages of the template called `message`; others are skipped
+    pub fn on_message(
+        mut self,
+        message: &str,
+        handler: impl FnMut(&SbeMessage, DateTime<Utc>, &mut Vec<MarketDataMessage>) -> Result<()>
+            + Send
+            + 'static,
+    ) -> Self {
+        match self.schema.template_id(message) {
+            Some(id) => {
+                self.handlers.insert(id, Box::new(handler));
+            }
+            None => warn!("SBE schema has no message named {}", message),
+        }
+        self
+    }
+
+    pub fn with_subscribe_frames(mut self, frames: Vec<String>) -> Self {
+        self.subscribe_frames = frames;
+        self
+    }
+
+    /// Skip a fixed-size packet header at the start of each frame, e.g. the
+    /// 12 byte sequence number and sending time of MDP 3.0
+    pub fn with_packet_header(mut self, len: usize) -> Self {
+        self.packet_header_len = len;
+        self
+    }
+
+    /// Messages are preceded by a little endian `u16` size that includes
+    /// the prefix itself, as in MDP 3.0
+    pub fn with_size_prefix(mut self) -> Self {
+        self.size_prefix = true;
+        self
+    }
+}
+
+impl Adapter for SbeAdapter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn subscribe_frames(&self) -> Vec<String> {
+        self.subscribe_frames.clone()
+    }
+
+    fn decode(
+        &mut self,
+        frame: &mut [u8],
+        received: DateTime<Utc>,
+        out: &mut Vec<MarketDataMessage>,
+    ) -> Result<()> {
+        let truncated = || ClientError::Parse(format!("truncated {} frame", self.name));
+        let mut rest = frame.get(self.packet_header_len..).ok_or_else(truncated)?;
+
+        while !rest.is_empty() {
+            let message = if self.size_prefix {
+                let size = rest
+                    .get(..2)
+                    .map(|size| u16::from_le_bytes([size[0], size[1]]) as usize)
+                    .filter(|&size| size >= 2 && size <= rest.len())
+                    .ok_or_else(truncated)?;
+                let (message, tail) = rest.split_at(size);
+                rest = tail;
+                &message[2..]
+            } else {
+                rest
+            };
+
+            // With size prefixes unhandled templates are skipped undecoded
+            let template_id = self.schema.peek_template(message)?;
+            let Some(handler) = self.handlers.get_mut(&template_id) else {
+                if self.size_prefix {
+                    continue;
+                }
+                let (_, len) = self.schema.decode(message)?;
+                rest = rest.get(len..).ok_or_else(truncated)?;
+                continue;
+            };
+            let (decoded, len) = self.schema.decode(message)?;
+            handler(&decoded, received, out)?;
+            if !self.size_prefix {
+                rest = rest.get(len..).ok_or_else(truncated)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sbe::tests::{trade_summary, SCHEMA};
+    use crate::types::{Trade, TradeSide};
+
+    #[test]
+    fn test_handlers_normalize_mdp_packets() {
+        let schema = SbeSchema::parse(SCHEMA).unwrap();
+        let mut adapter = SbeAdapter::new("cme", schema)
+            .with_packet_header(12)
+            .with_size_prefix()
+            .on_message("TradeSummary", |msg, received, out| {
+                let symbol = msg
+                    .body
+                    .get("Symbol")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                let timestamp = msg
+                    .body
+                    .get("TransactTime")
+                    .and_then(|v| v.as_i64())
+                    .map_or(received, DateTime::from_timestamp_nanos);
+                for entry in msg.body.group("NoMDEntries") {
+                    let field = |name| entry.get(name).and_then(|v| v.as_f64()).unwrap_or_default();
+                    out.push(MarketDataMessage::Trade(Trade {
+                        quantity: field("MDEntrySize"),
+                        side: match entry.get("AggressorSide").and_then(|v| v.as_str()) {
+                            Some("Buy") => TradeSide::Buy,
+                            Some("Sell") => TradeSide::Sell,
+                            _ => TradeSide::Unknown,
+                        },
+                        timestamp,
+                        trade_id: field("RptSeq").to_string(),
+                        ..Trade::test(symbol, field("MDEntryPx"))
+                    }));
+                }
+                Ok(())
+            });
+
+        let mut frame = vec![0u8; 12];
+        for entries in [&[(5125.25, 3, 1)][..], &[(5125.0, 1, 2), (5124.75, 2, 2)]] {
+            let message = trade_summary(1_709_301_600_000_000_000, entries);
+            frame.extend(((message.len() + 2) as u16).to_le_bytes());
+            frame.extend(message);
+        }
+        let mut out = Vec::new();
+        adapter.decode(&mut frame, Utc::now(), &mut out).unwrap();
+
+        assert_eq!(out.len(), 3);
+        let MarketDataMessage::Trade(trade) = &out[2] else {
+            panic!("expected trade");
+        };
+        assert_eq!((trade.symbol.as_str(), trade.price), ("ESH4", 5124.75));
+        assert_eq!((trade.quantity, trade.side), (2.0, TradeSide::Sell));
+    }
+}
@@ -0,0 +1,188 @@
+//! Aggressor inference for trades reported without a side.
+//!
+//! Venues that do not report the aggressor produce trades with
+//! [`TradeSide::Unknown`]. Counting those as buys or sells skews order flow
+//! imbalance, footprint delta and similar analytics, so they are left
+//! unknown unless an [`AggressorInference`] stage classifies them. It
+//! applies the quote rule (above the mid is a buy, below a sell), the tick
+//! rule (an uptick is a buy, a downtick a sell, a zero tick repeats the
+//! previous side) or both as Lee-Ready, and marks every side it fills in
+//! with [`TradeConditions::INFERRED_SIDE`]. Trades it cannot classify stay
+//! unknown.
+
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Quote, Trade, TradeConditions, TradeSide};
+use std::collections::HashMap;
+
+/// How an unreported side is inferred
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AggressorRule {
+    /// Where the trade printed against the prevailing mid
+    Quote,
+    /// Price change from the previous trade
+    Tick,
+    /// Quote rule, falling back to the tick rule at the mid or without a
+    /// quote
+    #[default]
+    LeeReady,
+}
+
+impl AggressorRule {
+    /// Side of a trade at `price` given the prevailing `mid` and the previous
+    /// trade's price and side
+    pub fn classify(
+        self,
+        price: f64,
+        mid: Option<f64>,
+        last: Option<(f64, TradeSide)>,
+    ) -> TradeSide {
+        let by_quote = match mid {
+            Some(mid) if price > mid => TradeSide::Buy,
+            Some(mid) if price < mid => TradeSide::Sell,
+            _ => TradeSide::Unknown,
+        };
+        let by_tick = || match last {
+            Some((previous, _)) if price > previous => TradeSide::Buy,
+            Some((previous, _)) if price < previous => TradeSide::Sell,
+            Some((_, side)) => side,
+            None => TradeSide::Unknown,
+        };
+        match self {
+            AggressorRule::Quote => by_quote,
+            AggressorRule::Tick => by_tick(),
+            AggressorRule::LeeReady if by_quote == TradeSide::Unknown => by_tick(),
+            AggressorRule::LeeReady => by_quote,
+        }
+    }
+}
+
+/// Pipeline stage filling in the side of trades the venue reported without
+/// one
+#[derive(Debug, Clone, Default)]
+pub struct AggressorInference {
+    rule: AggressorRule,
+    mids: HashMap<Symbol, f64>,
+    /// Last trade price and its known side, for the tick rule
+    last_trades: HashMap<Symbol, (f64, TradeSide)>,
+    inferred: u64,
+    unresolved: u64,
+}
+
+impl AggressorInference {
+    pub fn new(rule: AggressorRule) -> Self {
+        Self {
+            rule,
+            ..Self::default()
+        }
+    }
+
+    /// Trades whose side was filled in
+    pub fn inferred(&self) -> u64 {
+        self.inferred
+    }
+
+    /// Trades left unknown for lack of a quote or a previous trade
+    pub fn unresolved(&self) -> u64 {
+        self.unresolved
+    }
+
+    pub fn on_quote(&mut self, quote: &Quote) {
+        if quote.bid_price > 0.0 && quote.ask_price > 0.0 {
+            self.mids.insert(quote.symbol, quote.mid_price());
+        }
+    }
+
+    /// Infer the side of `trade` if it has none
+    pub fn on_trade(&mut self, trade: &mut Trade) {
+        let last = self.last_trades.get(&trade.symbol).copied();
+        if trade.side == TradeSide::Unknown {
+            let mid = self.mids.get(&trade.symbol).copied();
+            trade.side = self.rule.classify(trade.price, mid, last);
+            if trade.side == TradeSide::Unknown {
+                self.unresolved += 1;
+            } else {
+                trade.conditions.insert(TradeConditions::INFERRED_SIDE);
+                self.inferred += 1;
+            }
+        }
+        // An unknown zero tick keeps the previous side for the next one
+        let side = match (trade.side, last) {
+            (TradeSide::Unknown, Some((_, side))) => side,
+            (side, _) => side,
+        };
+        self.last_trades.insert(trade.symbol, (trade.price, side));
+    }
+}
+
+impl Stage for AggressorInference {
+    fn process(&mut self, mut msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        match &mut msg {
+            MarketDataMessage::Quote(quote) => self.on_quote(quote),
+            MarketDataMessage::Trade(trade) => self.on_trade(trade),
+            _ => {}
+        }
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(price: f64, side: TradeSide) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            quantity: 100.0,
+            side,
+            ..Trade::test("AAPL", price)
+        })
+    }
+
+    fn quote(bid: f64, ask: f64) -> MarketDataMessage {
+        MarketDataMessage::Quote(Quote {
+            bid_size: 100.0,
+            ask_size: 100.0,
+            ..Quote::test("AAPL", bid, ask)
+        })
+    }
+
+    #[test]
+    fn test_infers_and_flags_unknown_sides_only() {
+        let mut stage = AggressorInference::new(AggressorRule::LeeReady);
+        let messages = [
+            trade(100.0, TradeSide::Unknown),
+            quote(100.0, 100.2),
+            trade(100.2, TradeSide::Unknown),
+            trade(100.3, TradeSide::Sell),
+            // At the mid: a downtick, then a zero tick
+            trade(100.1, TradeSide::Unknown),
+            trade(100.1, TradeSide::Unknown),
+        ];
+        let mut out = Vec::new();
+        for msg in messages {
+            stage.process(msg, &mut out);
+        }
+
+        let trades: Vec<(TradeSide, bool)> = out
+            .iter()
+            .filter_map(|msg| match msg {
+                MarketDataMessage::Trade(trade) => Some((
+                    trade.side,
+                    trade.conditions.contains(TradeConditions::INFERRED_SIDE),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            trades,
+            [
+                (TradeSide::Unknown, false),
+                (TradeSide::Buy, true),
+                (TradeSide::Sell, false),
+                (TradeSide::Sell, true),
+                (TradeSide::Sell, true),
+            ]
+        );
+        assert_eq!((stage.inferred(), stage.unresolved()), (3, 1));
+    }
+}
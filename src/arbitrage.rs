@@ -0,0 +1,239 @@
+//! Cross-venue arbitrage spread monitoring.
+
+use crate::symbology::{Symbol, Venue};
+use crate::types::{MarketDataMessage, Quote};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Buy on one venue and sell on another for a net profit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArbOpportunity {
+    pub symbol: Symbol,
+    pub buy_venue: Venue,
+    pub buy_price: f64,
+    pub sell_venue: Venue,
+    pub sell_price: f64,
+    /// Quantity available on both sides
+    pub size: f64,
+    /// `sell_price - buy_price` before fees
+    pub gross_spread: f64,
+    /// Spread per unit after taker fees on both legs
+    pub net_spread: f64,
+    pub net_spread_bps: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Tracks quotes per venue and reports executable cross-venue spreads.
+///
+/// A venue's quote stops counting once it is more than the maximum quote
+/// age older than the symbol's newest quote, so a venue that went quiet or
+/// disconnected cannot keep reporting a spread that no longer exists.
+#[derive(Debug, Clone)]
+pub struct ArbMonitor {
+    threshold_bps: f64,
+    default_fee: f64,
+    fees: HashMap<Venue, f64>,
+    max_quote_age: Duration,
+    quotes: HashMap<Symbol, HashMap<Venue, Quote>>,
+}
+
+impl ArbMonitor {
+    /// Report opportunities whose net spread is at least `threshold_bps`
+    pub fn new(threshold_bps: f64) -> Self {
+        Self {
+            threshold_bps,
+            default_fee: 0.0,
+            fees: HashMap::new(),
+            max_quote_age: Duration::seconds(5),
+            quotes: HashMap::new(),
+        }
+    }
+
+    /// Ignore quotes older than `max_age` relative to the symbol's newest
+    /// quote; five seconds by default
+    pub fn with_max_quote_age(mut self, max_age: Duration) -> Self {
+        self.max_quote_age = max_age;
+        self
+    }
+
+    /// Taker fee rate (e.g. `0.001` for 10 bps) applied to venues without a
+    /// specific fee
+    pub fn with_default_fee(mut self, rate: f64) -> Self {
+        self.default_fee = rate;
+        self
+    }
+
+    /// Taker fee rate for a venue
+    pub fn with_fee(mut self, venue: &str, rate: f64) -> Self {
+        self.fees.insert(venue.into(), rate);
+        self
+    }
+
+    fn fee(&self, venue: &str) -> f64 {
+        self.fees.get(venue).copied().unwrap_or(self.default_fee)
+    }
+
+    /// Feed a message received from `venue`
+    pub fn on_message(&mut self, venue: &str, msg: &MarketDataMessage) -> Option<ArbOpportunity> {
+        match msg {
+            MarketDataMessage::Quote(quote) => self.on_quote(venue, quote),
+            MarketDataMessage::OrderBook(book) => {
+                let (bid, ask) = (book.best_bid()?, book.best_ask()?);
+                self.on_quote(
+                    venue,
+                    &Quote {
+                        symbol: book.symbol,
+                        bid_price: bid.price,
+                        bid_size: bid.size,
+                        ask_price: ask.price,
+                        ask_size: ask.size,
+                        timestamp: book.timestamp,
+                        instrument_id: None,
+                        contract: None,
+                        received: None,
+                    },
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// Update a venue's top of book and check for an opportunity
+    pub fn on_quote(&mut self, venue: &str, quote: &Quote) -> Option<ArbOpportunity> {
+        let venues = self.quotes.entry(quote.symbol).or_default();
+        venues.insert(venue.into(), quote.clone());
+        if let Some(newest) = venues.values().map(|quote| quote.timestamp).max() {
+            let cutoff = newest - self.max_quote_age;
+            venues.retain(|_, quote| quote.timestamp >= cutoff);
+        }
+
+        self.best_opportunity(&quote.symbol)
+            .filter(|opportunity| opportunity.net_spread_bps >= self.threshold_bps)
+    }
+
+    /// Drop quotes older than the maximum quote age at `now`, for venues
+    /// that stopped quoting while no other venue did either
+    pub fn expire(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - self.max_quote_age;
+        for venues in self.quotes.values_mut() {
+            venues.retain(|_, quote| quote.timestamp >= cutoff);
+        }
+        self.quotes.retain(|_, venues| !venues.is_empty());
+    }
+
+    /// Quotes within the maximum age of the symbol's newest quote
+    fn live_quotes(&self, symbol: &str) -> Vec<(&Venue, &Quote)> {
+        let Some(venues) = self.quotes.get(symbol) else {
+            return Vec::new();
+        };
+        let Some(newest) = venues.values().map(|quote| quote.timestamp).max() else {
+            return Vec::new();
+        };
+        let cutoff = newest - self.max_quote_age;
+        venues
+            .iter()
+            .filter(|(_, quote)| quote.timestamp >= cutoff)
+            .collect()
+    }
+
+    /// Highest bid across venues as `(venue, price)`
+    pub fn best_bid(&self, symbol: &str) -> Option<(&str, f64)> {
+        self.live_quotes(symbol)
+            .into_iter()
+            .map(|(venue, quote)| (venue.as_str(), quote.bid_price))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Lowest ask across venues as `(venue, price)`
+    pub fn best_ask(&self, symbol: &str) -> Option<(&str, f64)> {
+        self.live_quotes(symbol)
+            .into_iter()
+            .map(|(venue, quote)| (venue.as_str(), quote.ask_price))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Most profitable buy/sell venue pair after fees, regardless of threshold
+    pub fn best_opportunity(&self, symbol: &str) -> Option<ArbOpportunity> {
+        let venues = self.live_quotes(symbol);
+        let mut best: Option<ArbOpportunity> = None;
+
+        for &(buy_venue, buy) in &venues {
+            for &(sell_venue, sell) in &venues {
+                if buy_venue == sell_venue {
+                    continue;
+                }
+                let cost = buy.ask_price * (1.0 + self.fee(buy_venue));
+                let proceeds = sell.bid_price * (1.0 - self.fee(sell_venue));
+                let net_spread = proceeds - cost;
+                if best
+                    .as_ref()
+                    .is_some_and(|best| best.net_spread >= net_spread)
+                {
+                    continue;
+                }
+
+                best = Some(ArbOpportunity {
+                    symbol: symbol.into(),
+                    buy_venue: *buy_venue,
+                    buy_price: buy.ask_price,
+                    sell_venue: *sell_venue,
+                    sell_price: sell.bid_price,
+                    size: buy.ask_size.min(sell.bid_size),
+                    gross_spread: sell.bid_price - buy.ask_price,
+                    net_spread,
+                    net_spread_bps: net_spread / buy.ask_price * 10_000.0,
+                    timestamp: buy.timestamp.max(sell.timestamp),
+                });
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(bid: f64, ask: f64) -> Quote {
+        Quote {
+            ask_size: 2.0,
+            ..Quote::test("BTCUSD", bid, ask)
+        }
+    }
+
+    #[test]
+    fn test_opportunity_net_of_fees() {
+        let mut monitor = ArbMonitor::new(5.0).with_default_fee(0.001);
+
+        assert!(monitor.on_quote("coinbase", &quote(100.0, 100.1)).is_none());
+        // 30 bps gross less ~20 bps of fees
+        let opportunity = monitor.on_quote("kraken", &quote(100.4, 100.5)).unwrap();
+        assert_eq!(opportunity.buy_venue, "coinbase");
+        assert_eq!(opportunity.sell_venue, "kraken");
+        assert_eq!(opportunity.size, 1.0);
+        assert!((opportunity.net_spread_bps - 9.94).abs() < 0.01);
+
+        let monitor = monitor.with_fee("kraken", 0.002);
+        assert!(monitor.best_opportunity("BTCUSD").unwrap().net_spread_bps < 5.0);
+    }
+
+    #[test]
+    fn test_stale_venues_are_ignored() {
+        let t0 = Utc::now();
+        let at = |secs, bid, ask| Quote {
+            timestamp: t0 + Duration::seconds(secs),
+            ..quote(bid, ask)
+        };
+        let mut monitor = ArbMonitor::new(5.0).with_max_quote_age(Duration::seconds(2));
+
+        assert!(monitor.on_quote("coinbase", &at(0, 100.0, 100.1)).is_none());
+        assert!(monitor.on_quote("kraken", &at(1, 100.4, 100.5)).is_some());
+        // Coinbase's ask is 3s behind kraken's latest quote by now
+        assert!(monitor.on_quote("kraken", &at(3, 100.4, 100.5)).is_none());
+        assert_eq!(monitor.best_ask("BTCUSD"), Some(("kraken", 100.5)));
+
+        monitor.expire(t0 + Duration::seconds(10));
+        assert!(monitor.best_bid("BTCUSD").is_none());
+    }
+}
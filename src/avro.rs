@@ -0,0 +1,581 @@
+//! Avro encoding with Schema Registry support for Kafka pipelines.
+//!
+//! Normalized messages are written in Avro binary form behind the Confluent
+//! wire header (`[0][schema id: u32 BE]`), so the payloads can be handed to
+//! any Kafka producer and read by standard Avro consumers. Schemas are
+//! registered under a subject chosen by a [`SubjectNameStrategy`], after a
+//! backward-compatibility check against the latest registered version.
+//!
+//! [`MemoryRegistry`] checks compatibility locally; with the
+//! `schema-registry` feature, `ConfluentRegistry` talks to a Confluent
+//! Schema Registry over HTTP.
+
+use crate::client::{ClientError, Result};
+use crate::types::{MarketDataMessage, PriceLevel, TradeSide, TradeTerms};
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+const NAMESPACE: &str = "market_data";
+
+const TRADE_SCHEMA: &str = r#"{"type":"record","name":"Trade","namespace":"market_data","fields":[
+{"name":"symbol","type":"string"},
+{"name":"price","type":"double"},
+{"name":"quantity","type":"double"},
+{"name":"side","type":{"type":"enum","name":"TradeSide","symbols":["Buy","Sell","Unknown"],"default":"Unknown"}},
+{"name":"timestamp","type":{"type":"long","logicalType":"timestamp-micros"}},
+{"name":"trade_id","type":"string"},
+{"name":"conditions","type":"int","default":0},
+{"name":"instrument_id","type":["null","string"],"default":null}]}"#;
+
+const QUOTE_SCHEMA: &str = r#"{"type":"record","name":"Quote","namespace":"market_data","fields":[
+{"name":"symbol","type":"string"},
+{"name":"bid_price","type":"double"},
+{"name":"bid_size","type":"double"},
+{"name":"ask_price","type":"double"},
+{"name":"ask_size","type":"double"},
+{"name":"timestamp","type":{"type":"long","logicalType":"timestamp-micros"}},
+{"name":"instrument_id","type":["null","string"],"default":null}]}"#;
+
+const ORDER_BOOK_SCHEMA: &str = r#"{"type":"record","name":"OrderBook","namespace":"market_data","fields":[
+{"name":"symbol","type":"string"},
+{"name":"bids","type":{"type":"array","items":{"type":"record","name":"PriceLevel","fields":[
+{"name":"price","type":"double"},
+{"name":"size","type":"double"},
+{"name":"num_orders","type":"int"}]}}},
+{"name":"asks","type":{"type":"array","items":"PriceLevel"}},
+{"name":"timestamp","type":{"type":"long","logicalType":"timestamp-micros"}},
+{"name":"instrument_id","type":["null","string"],"default":null}]}"#;
+
+const TRADE_CORRECTION_SCHEMA: &str = r#"{"type":"record","name":"TradeCorrection","namespace":"market_data","fields":[
+{"name":"symbol","type":"string"},
+{"name":"trade_id","type":"string"},
+{"name":"timestamp","type":{"type":"long","logicalType":"timestamp-micros"}},
+{"name":"original","type":["null",{"type":"record","name":"TradeTerms","fields":[
+{"name":"price","type":"double"},
+{"name":"quantity","type":"double"}]}],"default":null},
+{"name":"corrected","type":"TradeTerms"},
+{"name":"instrument_id","type":["null","string"],"default":null}]}"#;
+
+const TRADE_BUST_SCHEMA: &str = r#"{"type":"record","name":"TradeBust","namespace":"market_data","fields":[
+{"name":"symbol","type":"string"},
+{"name":"trade_id","type":"string"},
+{"name":"timestamp","type":{"type":"long","logicalType":"timestamp-micros"}},
+{"name":"original","type":["null",{"type":"record","name":"TradeTerms","fields":[
+{"name":"price","type":"double"},
+{"name":"quantity","type":"double"}]}],"default":null},
+{"name":"instrument_id","type":["null","string"],"default":null}]}"#;
+
+/// Avro schema (JSON) and record name of a message; heartbeats have none
+pub fn schema_for(msg: &MarketDataMessage) -> Option<(&'static str, &'static str)> {
+    match msg {
+        MarketDataMessage::Trade(_) => Some(("Trade", TRADE_SCHEMA)),
+        MarketDataMessage::Quote(_) => Some(("Quote", QUOTE_SCHEMA)),
+        MarketDataMessage::OrderBook(_) => Some(("OrderBook", ORDER_BOOK_SCHEMA)),
+        MarketDataMessage::Heartbeat => None,
+        MarketDataMessage::TradeCorrection(_) => Some(("TradeCorrection", TRADE_CORRECTION_SCHEMA)),
+        MarketDataMessage::TradeBust(_) => Some(("TradeBust", TRADE_BUST_SCHEMA)),
+    }
+}
+
+/// How the registry subject of a topic's values is named.
+///
+/// The default, [`TopicRecordName`](Self::TopicRecordName), lets one topic
+/// carry trades, quotes and books; [`TopicName`](Self::TopicName) only suits
+/// topics that carry a single message type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SubjectNameStrategy {
+    /// `<topic>-value`, one schema per topic
+    TopicName,
+    /// `market_data.<Record>`, shared across topics
+    RecordName,
+    /// `<topic>-market_data.<Record>`, several record types per topic
+    #[default]
+    TopicRecordName,
+}
+
+impl SubjectNameStrategy {
+    pub fn subject(&self, topic: &str, record: &str) -> String {
+        match self {
+            SubjectNameStrategy::TopicName => format!("{}-value", topic),
+            SubjectNameStrategy::RecordName => format!("{}.{}", NAMESPACE, record),
+            SubjectNameStrategy::TopicRecordName => format!("{}-{}.{}", topic, NAMESPACE, record),
+        }
+    }
+}
+
+/// Avro binary body of a message, without the wire header
+pub fn encode(msg: &MarketDataMessage) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(64);
+    match msg {
+        MarketDataMessage::Trade(trade) => {
+            put_string(&mut out, &trade.symbol);
+            put_double(&mut out, trade.price);
+            put_double(&mut out, trade.quantity);
+            put_long(
+                &mut out,
+                match trade.side {
+                    TradeSide::Buy => 0,
+                    TradeSide::Sell => 1,
+                    TradeSide::Unknown => 2,
+                },
+            );
+            put_long(&mut out, trade.timestamp.timestamp_micros());
+            put_string(&mut out, &trade.trade_id);
+            put_long(&mut out, trade.conditions.bits() as i64);
+            put_optional(&mut out, trade.instrument_id.as_deref());
+        }
+        MarketDataMessage::Quote(quote) => {
+            put_string(&mut out, &quote.symbol);
+            for value in [
+                quote.bid_price,
+                quote.bid_size,
+                quote.ask_price,
+                quote.ask_size,
+            ] {
+                put_double(&mut out, value);
+            }
+            put_long(&mut out, quote.timestamp.timestamp_micros());
+            put_optional(&mut out, quote.instrument_id.as_deref());
+        }
+        MarketDataMessage::OrderBook(book) => {
+            put_string(&mut out, &book.symbol);
+            put_levels(&mut out, &book.bids);
+            put_levels(&mut out, &book.asks);
+            put_long(&mut out, book.timestamp.timestamp_micros());
+            put_optional(&mut out, book.instrument_id.as_deref());
+        }
+        MarketDataMessage::Heartbeat => return None,
+        MarketDataMessage::TradeCorrection(correction) => {
+            put_string(&mut out, &correction.symbol);
+            put_string(&mut out, &correction.trade_id);
+            put_long(&mut out, correction.timestamp.timestamp_micros());
+            put_terms(&mut out, correction.original);
+            put_double(&mut out, correction.corrected.price);
+            put_double(&mut out, correction.corrected.quantity);
+            put_optional(&mut out, correction.instrument_id.as_deref());
+        }
+        MarketDataMessage::TradeBust(bust) => {
+            put_string(&mut out, &bust.symbol);
+            put_string(&mut out, &bust.trade_id);
+            put_long(&mut out, bust.timestamp.timestamp_micros());
+            put_terms(&mut out, bust.original);
+            put_optional(&mut out, bust.instrument_id.as_deref());
+        }
+    }
+    Some(out)
+}
+
+/// Zigzag varint, used for Avro `int` and `long`
+fn put_long(out: &mut Vec<u8>, value: i64) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn put_double(out: &mut Vec<u8>, value: f64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_string(out: &mut Vec<u8>, value: &str) {
+    put_long(out, value.len() as i64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// `["null", "string"]` union
+fn put_optional(out: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        None => put_long(out, 0),
+        Some(value) => {
+            put_long(out, 1);
+            put_string(out, value);
+        }
+    }
+}
+
+fn put_terms(out: &mut Vec<u8>, terms: Option<TradeTerms>) {
+    match terms {
+        None => put_long(out, 0),
+        Some(terms) => {
+            put_long(out, 1);
+            put_double(out, terms.price);
+            put_double(out, terms.quantity);
+        }
+    }
+}
+
+fn put_levels(out: &mut Vec<u8>, levels: &[PriceLevel]) {
+    if !levels.is_empty() {
+        put_long(out, levels.len() as i64);
+        for level in levels {
+            put_double(out, level.price);
+            put_double(out, level.size);
+            put_long(out, level.num_orders as i64);
+        }
+    }
+    put_long(out, 0);
+}
+
+/// Reasons data written with `old` could not be read with `new`: added
+/// fields without defaults and fields whose type changed, other than enums
+/// gaining symbols
+pub fn compatibility_problems(old: &str, new: &str) -> Result<Vec<String>> {
+    let parse = |schema: &str| {
+        serde_json::from_str::<Value>(schema)
+            .map_err(|e| ClientError::Parse(format!("avro schema: {}", e)))
+    };
+    let (old, new) = (parse(old)?, parse(new)?);
+    let fields = |schema: &Value| -> HashMap<String, Value> {
+        schema["fields"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|field| Some((field["name"].as_str()?.to_string(), field.clone())))
+            .collect()
+    };
+    let old_fields = fields(&old);
+    let mut problems = Vec::new();
+    if old["name"] != new["name"] {
+        problems.push(format!(
+            "record renamed from {} to {}",
+            old["name"], new["name"]
+        ));
+    }
+    let mut new_fields: Vec<_> = fields(&new).into_iter().collect();
+    new_fields.sort_by(|a, b| a.0.cmp(&b.0));
+    for (name, field) in new_fields {
+        match old_fields.get(&name) {
+            Some(old_field) if !reads_as(&old_field["type"], &field["type"]) => {
+                problems.push(format!("field {} changed type", name))
+            }
+            Some(_) => {}
+            None if field.get("default").is_none() => {
+                problems.push(format!("field {} added without a default", name))
+            }
+            None => {}
+        }
+    }
+    Ok(problems)
+}
+
+/// Whether values of type `old` can be read as `new`
+fn reads_as(old: &Value, new: &Value) -> bool {
+    if old == new {
+        return true;
+    }
+    let symbols = |ty: &Value| ty["symbols"].as_array().cloned().unwrap_or_default();
+    old["type"] == "enum"
+        && new["type"] == "enum"
+        && old["name"] == new["name"]
+        && symbols(old).iter().all(|s| symbols(new).contains(s))
+}
+
+/// Stores schemas by subject and hands out their ids
+pub trait SchemaRegistry: Send + Sync {
+    /// Register `schema` under `subject`, returning its id; registering an
+    /// existing schema returns the existing id
+    fn register<'a>(&'a self, subject: &'a str, schema: &'a str) -> BoxFuture<'a, Result<u32>>;
+
+    /// Whether `schema` may be registered as the next version of `subject`
+    fn is_compatible<'a>(
+        &'a self,
+        subject: &'a str,
+        schema: &'a str,
+    ) -> BoxFuture<'a, Result<bool>>;
+}
+
+/// In-process registry enforcing backward compatibility, for tests and
+/// offline pipelines
+#[derive(Debug, Default)]
+pub struct MemoryRegistry {
+    /// Schema versions per subject, oldest first
+    subjects: Mutex<HashMap<String, Vec<(u32, String)>>>,
+    schemas: Mutex<Vec<String>>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Latest schema registered under `subject`
+    pub fn latest(&self, subject: &str) -> Option<(u32, String)> {
+        self.subjects.lock().unwrap().get(subject)?.last().cloned()
+    }
+}
+
+impl SchemaRegistry for MemoryRegistry {
+    fn register<'a>(&'a self, subject: &'a str, schema: &'a str) -> BoxFuture<'a, Result<u32>> {
+        Box::pin(async move {
+            if let Some((_, latest)) = self.latest(subject) {
+                let problems = compatibility_problems(&latest, schema)?;
+                if !problems.is_empty() {
+                    return Err(ClientError::Control(format!(
+                        "schema incompatible with {}: {}",
+                        subject,
+                        problems.join(", ")
+                    )));
+                }
+            }
+            let mut schemas = self.schemas.lock().unwrap();
+            let id = match schemas.iter().position(|s| s == schema) {
+                Some(i) => i as u32 + 1,
+                None => {
+                    schemas.push(schema.to_string());
+                    schemas.len() as u32
+                }
+            };
+            let mut subjects = self.subjects.lock().unwrap();
+            let versions = subjects.entry(subject.to_string()).or_default();
+            if !versions.iter().any(|(v, _)| *v == id) {
+                versions.push((id, schema.to_string()));
+            }
+            Ok(id)
+        })
+    }
+
+    fn is_compatible<'a>(
+        &'a self,
+        subject: &'a str,
+        schema: &'a str,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            match self.latest(subject) {
+                Some((_, latest)) => Ok(compatibility_problems(&latest, schema)?.is_empty()),
+                None => Ok(true),
+            }
+        })
+    }
+}
+
+/// Confluent Schema Registry REST client
+#[cfg(feature = "schema-registry")]
+#[derive(Debug, Clone)]
+pub struct ConfluentRegistry {
+    client: reqwest::Client,
+    url: String,
+    auth: Option<(String, String)>,
+}
+
+#[cfg(feature = "schema-registry")]
+impl ConfluentRegistry {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            auth: None,
+        }
+    }
+
+    pub fn with_basic_auth(mut self, user: &str, password: &str) -> Self {
+        self.auth = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    async fn post(&self, path: &str, schema: &str) -> Result<(reqwest::StatusCode, Value)> {
+        let connection = |e: reqwest::Error| ClientError::Connection(e.to_string());
+        let body = serde_json::json!({ "schema": schema }).to_string();
+        let mut request = self
+            .client
+            .post(format!("{}{}", self.url, path))
+            .header("content-type", "application/vnd.schemaregistry.v1+json")
+            .body(body);
+        if let Some((user, password)) = &self.auth {
+            request = request.basic_auth(user, Some(password));
+        }
+        let response = request.send().await.map_err(connection)?;
+        let status = response.status();
+        let bytes = response.bytes().await.map_err(connection)?;
+        let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        Ok((status, value))
+    }
+}
+
+#[cfg(feature = "schema-registry")]
+impl SchemaRegistry for ConfluentRegistry {
+    fn register<'a>(&'a self, subject: &'a str, schema: &'a str) -> BoxFuture<'a, Result<u32>> {
+        Box::pin(async move {
+            let (status, body) = self
+                .post(&format!("/subjects/{}/versions", subject), schema)
+                .await?;
+            match body["id"].as_u64() {
+                Some(id) if status.is_success() => Ok(id as u32),
+                _ => Err(ClientError::Control(format!(
+                    "registering {} failed ({}): {}",
+                    subject, status, body["message"]
+                ))),
+            }
+        })
+    }
+
+    fn is_compatible<'a>(
+        &'a self,
+        subject: &'a str,
+        schema: &'a str,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let path = format!("/compatibility/subjects/{}/versions/latest", subject);
+            let (status, body) = self.post(&path, schema).await?;
+            match status.as_u16() {
+                // No versions yet, so anything is compatible
+                404 => Ok(true),
+                _ if status.is_success() => Ok(body["is_compatible"].as_bool().unwrap_or(false)),
+                _ => Err(ClientError::Control(format!(
+                    "compatibility check for {} failed ({}): {}",
+                    subject, status, body["message"]
+                ))),
+            }
+        })
+    }
+}
+
+/// Encodes messages as Confluent-framed Avro, registering each record's
+/// schema on first use
+pub struct AvroSerializer<R: SchemaRegistry> {
+    registry: R,
+    strategy: SubjectNameStrategy,
+    /// Schema id by subject and record, as a subject may be shared by
+    /// records that each need their own id
+    ids: HashMap<(String, &'static str), u32>,
+}
+
+impl<R: SchemaRegistry> AvroSerializer<R> {
+    pub fn new(registry: R) -> Self {
+        Self {
+            registry,
+            strategy: SubjectNameStrategy::default(),
+            ids: HashMap::new(),
+        }
+    }
+
+    pub fn with_strategy(mut self, strategy: SubjectNameStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn registry(&self) -> &R {
+        &self.registry
+    }
+
+    /// Kafka record value for `msg` on `topic`; `None` for heartbeats.
+    /// Fails if the schema is incompatible with the subject's latest one.
+    pub async fn serialize(
+        &mut self,
+        topic: &str,
+        msg: &MarketDataMessage,
+    ) -> Result<Option<Vec<u8>>> {
+        let (Some((record, schema)), Some(body)) = (schema_for(msg), encode(msg)) else {
+            return Ok(None);
+        };
+        let subject = self.strategy.subject(topic, record);
+        let id = match self.ids.get(&(subject.clone(), record)) {
+            Some(id) => *id,
+            None => {
+                if !self.registry.is_compatible(&subject, schema).await? {
+                    return Err(ClientError::Control(format!(
+                        "{} schema is not compatible with subject {}",
+                        record, subject
+                    )));
+                }
+                let id = self.registry.register(&subject, schema).await?;
+                self.ids.insert((subject, record), id);
+                id
+            }
+        };
+        let mut out = Vec::with_capacity(5 + body.len());
+        out.push(0);
+        out.extend_from_slice(&id.to_be_bytes());
+        out.extend_from_slice(&body);
+        Ok(Some(out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Quote, Trade, TradeConditions};
+    use chrono::{TimeZone, Utc};
+
+    #[tokio::test]
+    async fn test_confluent_framing_and_evolution_checks() {
+        let trade = MarketDataMessage::Trade(Trade {
+            quantity: 2.0,
+            side: TradeSide::Sell,
+            timestamp: Utc.timestamp_opt(1, 0).unwrap(),
+            trade_id: "7".to_string(),
+            conditions: TradeConditions::BLOCK,
+            ..Trade::test("BTCUSD", 1.0)
+        });
+        let mut serializer = AvroSerializer::new(MemoryRegistry::new());
+        let bytes = serializer
+            .serialize("ticks", &trade)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut expected = vec![0, 0, 0, 0, 1, 12];
+        expected.extend_from_slice(b"BTCUSD");
+        expected.extend_from_slice(&1.0f64.to_le_bytes());
+        expected.extend_from_slice(&2.0f64.to_le_bytes());
+        // Sell, 1_000_000 micros, "7", conditions 1, null instrument
+        expected.extend_from_slice(&[2, 0x80, 0x89, 0x7a, 2, b'7', 2, 0]);
+        assert_eq!(bytes, expected);
+        assert!(serializer
+            .serialize("ticks", &MarketDataMessage::Heartbeat)
+            .await
+            .unwrap()
+            .is_none());
+
+        let subject = "ticks-market_data.Trade";
+        assert_eq!(serializer.registry().latest(subject).unwrap().0, 1);
+        let added = TRADE_SCHEMA.replace(
+            r#"{"name":"trade_id","type":"string"},"#,
+            r#"{"name":"trade_id","type":"string"},{"name":"venue","type":"string"},"#,
+        );
+        assert_eq!(
+            compatibility_problems(TRADE_SCHEMA, &added).unwrap(),
+            ["field venue added without a default"]
+        );
+        assert!(serializer
+            .registry()
+            .register(subject, &added)
+            .await
+            .is_err());
+        let defaulted = added.replace(
+            r#"{"name":"venue","type":"string"}"#,
+            r#"{"name":"venue","type":"string","default":""}"#,
+        );
+        assert_eq!(
+            serializer
+                .registry()
+                .register(subject, &defaulted)
+                .await
+                .unwrap(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mixed_record_types_on_one_topic() {
+        let trade = MarketDataMessage::Trade(Trade::test("BTCUSD", 1.0));
+        let quote = MarketDataMessage::Quote(Quote::test("BTCUSD", 1.0, 2.0));
+        let mut serializer = AvroSerializer::new(MemoryRegistry::new());
+        for msg in [&trade, &quote, &trade] {
+            assert!(serializer.serialize("ticks", msg).await.unwrap().is_some());
+        }
+        assert!(serializer
+            .registry()
+            .latest("ticks-market_data.Quote")
+            .is_some());
+
+        // One subject per topic rejects the second record type
+        let mut serializer = AvroSerializer::new(MemoryRegistry::new())
+            .with_strategy(SubjectNameStrategy::TopicName);
+        serializer.serialize("ticks", &trade).await.unwrap();
+        assert!(serializer.serialize("ticks", &quote).await.is_err());
+    }
+}
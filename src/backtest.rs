@@ -0,0 +1,534 @@
+//! Event-driven backtesting over replayed market data.
+//!
+//! A [`Backtest`] feeds recorded messages to a [`BacktestHandler`] while a
+//! [`VirtualClock`] follows message timestamps. Timers and bar closes fire
+//! when the clock passes their due time, before any message stamped at or
+//! after it, and events due at the same instant fire in scheduling order, so a
+//! given input always produces the same callback sequence. Handlers that
+//! need randomness draw it from [`BacktestContext::rng`], seeded through
+//! [`Backtest::with_seed`], so results are reproducible too; a seeded
+//! [`SyntheticFeed`](crate::generator::SyntheticFeed) makes the input
+//! reproducible as well.
+
+use crate::candles::CandleAggregator;
+use crate::client::{ClientError, Result};
+use crate::generator::SeededRng;
+use crate::recording::RecordingReader;
+use crate::types::{Candle, MarketDataMessage};
+use chrono::{DateTime, Duration, Utc};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::path::Path;
+
+/// Simulated time driven by replayed events
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VirtualClock {
+    now: DateTime<Utc>,
+}
+
+impl VirtualClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self { now: start }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    /// Move the clock forward; it never moves backwards
+    pub fn advance_to(&mut self, ts: DateTime<Utc>) {
+        if ts > self.now {
+            self.now = ts;
+        }
+    }
+}
+
+/// Identifier returned when scheduling a timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimerId(pub u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Timer {
+    due: DateTime<Utc>,
+    seq: u64,
+    id: TimerId,
+    every: Option<Duration>,
+}
+
+/// Scheduling and control surface passed to handler callbacks
+pub struct BacktestContext {
+    clock: VirtualClock,
+    timers: BinaryHeap<Reverse<Timer>>,
+    /// Timers still queued, so that cancelling a fired one records nothing
+    pending: HashSet<TimerId>,
+    /// Queued timers to skip, dropped once skipped
+    cancelled: HashSet<TimerId>,
+    next_id: u64,
+    next_seq: u64,
+    stopped: bool,
+    rng: SeededRng,
+}
+
+impl BacktestContext {
+    fn new(start: DateTime<Utc>, seed: u64) -> Self {
+        Self {
+            clock: VirtualClock::new(start),
+            timers: BinaryHeap::new(),
+            pending: HashSet::new(),
+            cancelled: HashSet::new(),
+            next_id: 0,
+            next_seq: 0,
+            stopped: false,
+            rng: SeededRng::new(seed),
+        }
+    }
+
+    /// Current simulated time
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// Fire [`BacktestHandler::on_interval`] once at `at`
+    pub fn schedule_at(&mut self, at: DateTime<Utc>) -> TimerId {
+        self.push_timer(at, None)
+    }
+
+    /// Fire [`BacktestHandler::on_interval`] every `every`, starting one
+    /// interval from now; `every` must be positive
+    pub fn schedule_interval(&mut self, every: Duration) -> Result<TimerId> {
+        if every <= Duration::zero() {
+            return Err(ClientError::Parse(format!(
+                "timer interval must be positive, got {}",
+                every
+            )));
+        }
+        let due = self.now() + every;
+        Ok(self.push_timer(due, Some(every)))
+    }
+
+    pub fn cancel(&mut self, id: TimerId) {
+        if self.pending.remove(&id) {
+            self.cancelled.insert(id);
+        }
+    }
+
+    /// End the backtest after the current callback
+    pub fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Random numbers seeded by [`Backtest::with_seed`]
+    pub fn rng(&mut self) -> &mut SeededRng {
+        &mut self.rng
+    }
+
+    fn push_timer(&mut self, due: DateTime<Utc>, every: Option<Duration>) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+        self.pending.insert(id);
+        self.requeue(Timer {
+            due,
+            seq: 0,
+            id,
+            every,
+        });
+        id
+    }
+
+    fn requeue(&mut self, mut timer: Timer) {
+        timer.seq = self.next_seq;
+        self.next_seq += 1;
+        self.timers.push(Reverse(timer));
+    }
+
+    fn pop_due(&mut self, until: DateTime<Utc>) -> Option<Timer> {
+        loop {
+            let Reverse(timer) = *self.timers.peek()?;
+            if timer.due > until {
+                return None;
+            }
+            self.timers.pop();
+            if !self.cancelled.remove(&timer.id) {
+                if timer.every.is_none() {
+                    self.pending.remove(&timer.id);
+                }
+                return Some(timer);
+            }
+        }
+    }
+}
+
+/// Callbacks driven by a [`Backtest`]
+pub trait BacktestHandler {
+    fn on_start(&mut self, _ctx: &mut BacktestContext) {}
+
+    fn on_message(&mut self, _ctx: &mut BacktestContext, _msg: &MarketDataMessage) {}
+
+    /// A bar closed (requires [`Backtest::with_bars`])
+    fn on_bar(&mut self, _ctx: &mut BacktestContext, _bar: &Candle) {}
+
+    /// A timer scheduled through the context fired
+    fn on_interval(&mut self, _ctx: &mut BacktestContext, _timer: TimerId) {}
+
+    fn on_stop(&mut self, _ctx: &mut BacktestContext) {}
+}
+
+/// Counts reported at the end of a run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BacktestReport {
+    pub messages: u64,
+    pub bars: u64,
+    pub timers_fired: u64,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// Deterministic event loop over replayed data
+#[derive(Debug, Default)]
+pub struct Backtest {
+    bars: Option<CandleAggregator>,
+    seed: u64,
+}
+
+impl Backtest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Aggregate trades into bars of `interval`, delivered via `on_bar`
+    pub fn with_bars(mut self, interval: Duration) -> Self {
+        self.bars = Some(CandleAggregator::new(interval));
+        self
+    }
+
+    /// Seed of [`BacktestContext::rng`] (default 0)
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Replay a recording file through `handler`, reading it as it goes.
+    ///
+    /// A corrupt record ends the run, calling [`BacktestHandler::on_stop`],
+    /// and is returned as the error.
+    pub fn run_recording<H: BacktestHandler>(
+        &mut self,
+        path: impl AsRef<Path>,
+        handler: &mut H,
+    ) -> Result<BacktestReport> {
+        let mut reader = RecordingReader::open(path)?;
+        let mut messages = reader.messages();
+        let Some(mut next) = messages.next().transpose()? else {
+            return Ok(BacktestReport::default());
+        };
+        let (mut ctx, mut report) = self.begin(next.0, handler);
+        loop {
+            let (ts, msg) = next;
+            if !self.step(&mut ctx, handler, ts, &msg, &mut report) {
+                break;
+            }
+            match messages.next().transpose() {
+                Ok(Some(event)) => next = event,
+                Ok(None) => break,
+                Err(e) => {
+                    self.finish(ctx, handler, report);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(self.finish(ctx, handler, report))
+    }
+
+    /// Replay timestamped messages through `handler`.
+    ///
+    /// Events must be in non-decreasing time order; the clock does not move
+    /// backwards for late events.
+    pub fn run<H, I>(&mut self, events: I, handler: &mut H) -> BacktestReport
+    where
+        H: BacktestHandler,
+        I: IntoIterator<Item = (DateTime<Utc>, MarketDataMessage)>,
+    {
+        let mut events = events.into_iter().peekable();
+        let Some((start, _)) = events.peek() else {
+            return BacktestReport::default();
+        };
+        let (mut ctx, mut report) = self.begin(*start, handler);
+        for (ts, msg) in events {
+            if !self.step(&mut ctx, handler, ts, &msg, &mut report) {
+                break;
+            }
+        }
+        self.finish(ctx, handler, report)
+    }
+
+    /// Start a run at `start`
+    pub(crate) fn begin<H: BacktestHandler>(
+        &mut self,
+        start: DateTime<Utc>,
+        handler: &mut H,
+    ) -> (BacktestContext, BacktestReport) {
+        let report = BacktestReport {
+            start: Some(start),
+            ..BacktestReport::default()
+        };
+        let mut ctx = BacktestContext::new(start, self.seed);
+        handler.on_start(&mut ctx);
+        (ctx, report)
+    }
+
+    /// Deliver one message stamped `ts` after whatever is due before it;
+    /// false once the handler stopped the run
+    pub(crate) fn step<H: BacktestHandler>(
+        &mut self,
+        ctx: &mut BacktestContext,
+        handler: &mut H,
+        ts: DateTime<Utc>,
+        msg: &MarketDataMessage,
+        report: &mut BacktestReport,
+    ) -> bool {
+        if ctx.stopped {
+            return false;
+        }
+        self.advance(ctx, handler, ts, report);
+        if ctx.stopped {
+            return false;
+        }
+
+        if let Some(bars) = self.bars.as_mut() {
+            match msg {
+                MarketDataMessage::Trade(trade) => {
+                    if let Some(bar) = bars.update(trade) {
+                        report.bars += 1;
+                        handler.on_bar(ctx, &bar);
+                    }
+                }
+                MarketDataMessage::TradeCorrection(correction) => {
+                    bars.correct(correction);
+                }
+                MarketDataMessage::TradeBust(bust) => {
+                    bars.bust(bust);
+                }
+                _ => {}
+            }
+        }
+        report.messages += 1;
+        handler.on_message(ctx, msg);
+        !ctx.stopped
+    }
+
+    /// End a run, returning its report
+    pub(crate) fn finish<H: BacktestHandler>(
+        &mut self,
+        mut ctx: BacktestContext,
+        handler: &mut H,
+        mut report: BacktestReport,
+    ) -> BacktestReport {
+        report.end = Some(ctx.now());
+        handler.on_stop(&mut ctx);
+        report
+    }
+
+    /// Earliest timer or bar close still to fire
+    pub(crate) fn next_due(&self, ctx: &BacktestContext) -> Option<DateTime<Utc>> {
+        let timer = ctx.timers.peek().map(|Reverse(timer)| timer.due);
+        let bar = self.bars.as_ref().and_then(|bars| bars.next_close());
+        timer.into_iter().chain(bar).min()
+    }
+
+    /// Fire timers and bar closes due up to `ts`, in time order
+    pub(crate) fn advance<H: BacktestHandler>(
+        &mut self,
+        ctx: &mut BacktestContext,
+        handler: &mut H,
+        ts: DateTime<Utc>,
+        report: &mut BacktestReport,
+    ) {
+        loop {
+            let next_timer = ctx
+                .timers
+                .peek()
+                .map(|Reverse(timer)| timer.due)
+                .filter(|due| *due <= ts);
+            let next_bar = self
+                .bars
+                .as_ref()
+                .and_then(|bars| bars.next_close())
+                .filter(|close| *close <= ts);
+
+            match (next_bar, next_timer) {
+                // Bars closing at the same instant as a timer are delivered first
+                (Some(close), due) if due.is_none_or(|due| close <= due) => {
+                    self.close_bars(ctx, handler, close, report)
+                }
+                (_, Some(due)) => {
+                    let Some(timer) = ctx.pop_due(due) else {
+                        continue;
+                    };
+                    ctx.clock.advance_to(timer.due);
+                    if let Some(every) = timer.every {
+                        ctx.requeue(Timer {
+                            due: timer.due + every,
+                            ..timer
+                        });
+                    }
+                    report.timers_fired += 1;
+                    handler.on_interval(ctx, timer.id);
+                }
+                _ => break,
+            }
+            if ctx.stopped {
+                return;
+            }
+        }
+        ctx.clock.advance_to(ts);
+    }
+
+    fn close_bars<H: BacktestHandler>(
+        &mut self,
+        ctx: &mut BacktestContext,
+        handler: &mut H,
+        close: DateTime<Utc>,
+        report: &mut BacktestReport,
+    ) {
+        ctx.clock.advance_to(close);
+        let closed = self
+            .bars
+            .as_mut()
+            .map(|bars| bars.flush_until(close))
+            .unwrap_or_default();
+        for bar in closed {
+            report.bars += 1;
+            handler.on_bar(ctx, &bar);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::RecordingWriter;
+    use crate::types::Trade;
+    use chrono::TimeZone;
+
+    #[derive(Default)]
+    struct Recorder {
+        log: Vec<String>,
+    }
+
+    impl BacktestHandler for Recorder {
+        fn on_start(&mut self, ctx: &mut BacktestContext) {
+            ctx.schedule_interval(Duration::seconds(30)).unwrap();
+        }
+
+        fn on_message(&mut self, ctx: &mut BacktestContext, _msg: &MarketDataMessage) {
+            self.log.push(format!("msg@{}", ctx.now().timestamp()));
+        }
+
+        fn on_bar(&mut self, _ctx: &mut BacktestContext, bar: &Candle) {
+            self.log.push(format!("bar@{}", bar.end.timestamp()));
+        }
+
+        fn on_interval(&mut self, ctx: &mut BacktestContext, _timer: TimerId) {
+            self.log.push(format!("timer@{}", ctx.now().timestamp()));
+        }
+    }
+
+    #[test]
+    fn test_deterministic_event_order() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut events: Vec<_> = [0, 45, 60, 130]
+            .iter()
+            .map(|secs| {
+                let ts = t0 + Duration::seconds(*secs);
+                let trade = Trade {
+                    timestamp: ts,
+                    trade_id: secs.to_string(),
+                    ..Trade::test("BTCUSD", 100.0)
+                };
+                (ts, MarketDataMessage::Trade(trade))
+            })
+            .collect();
+        // A late trade for the first bar, just flushed, must not open it
+        // again
+        let (ts, mut late) = events[2].clone();
+        if let MarketDataMessage::Trade(trade) = &mut late {
+            trade.timestamp = t0 + Duration::seconds(50);
+        }
+        events.insert(2, (ts, late));
+
+        let mut handler = Recorder::default();
+        let report = Backtest::new()
+            .with_bars(Duration::minutes(1))
+            .run(events, &mut handler);
+
+        let base = t0.timestamp();
+        let expected: Vec<String> = [
+            ("msg", 0),
+            ("timer", 30),
+            ("msg", 45),
+            ("bar", 60),
+            ("timer", 60),
+            ("msg", 60),
+            ("msg", 60),
+            ("timer", 90),
+            ("bar", 120),
+            ("timer", 120),
+            ("msg", 130),
+        ]
+        .iter()
+        .map(|(kind, secs)| format!("{}@{}", kind, base + secs))
+        .collect();
+        assert_eq!(handler.log, expected);
+        assert_eq!(report.messages, 5);
+        assert_eq!(report.bars, 2);
+
+        let mut ctx = BacktestContext::new(t0, 0);
+        assert!(ctx.schedule_interval(Duration::zero()).is_err());
+    }
+
+    #[test]
+    fn test_cancelled_timers_pruned() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut ctx = BacktestContext::new(t0, 0);
+        let fired = ctx.schedule_at(t0);
+        let skipped = ctx.schedule_at(t0 + Duration::seconds(1));
+        assert_eq!(ctx.pop_due(t0).map(|timer| timer.id), Some(fired));
+
+        // Cancelling a fired timer records nothing; a skipped one is
+        // forgotten once skipped
+        ctx.cancel(fired);
+        ctx.cancel(skipped);
+        assert_eq!(ctx.cancelled.len(), 1);
+        assert!(ctx.pop_due(t0 + Duration::seconds(1)).is_none());
+        assert!(ctx.cancelled.is_empty() && ctx.pending.is_empty());
+    }
+
+    #[test]
+    fn test_run_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trades.mdr");
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut writer = RecordingWriter::create(&path, 2).unwrap();
+        for secs in [0, 45, 60, 130] {
+            let ts = t0 + Duration::seconds(secs);
+            let trade = Trade {
+                timestamp: ts,
+                ..Trade::test("BTCUSD", 100.0)
+            };
+            writer
+                .write_at(ts, &MarketDataMessage::Trade(trade))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut handler = Recorder::default();
+        let report = Backtest::new().run_recording(&path, &mut handler).unwrap();
+        assert_eq!(report.messages, 4);
+        assert_eq!(report.timers_fired, 4);
+        assert_eq!(report.end, Some(t0 + Duration::seconds(130)));
+    }
+}
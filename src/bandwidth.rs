@@ -0,0 +1,187 @@
+//! Bytes-received accounting per connection, channel and symbol.
+
+use crate::types::MarketDataMessage;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Traffic attributed to one key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub bytes: u64,
+    pub messages: u64,
+}
+
+impl Usage {
+    fn add(&mut self, bytes: u64) {
+        self.bytes += bytes;
+        self.messages += 1;
+    }
+}
+
+/// Bandwidth used by one connection.
+///
+/// Frame bytes are split evenly between the messages decoded from the frame,
+/// the remainder going to the first, so per-channel and per-symbol totals add
+/// up to the connection total (minus frames that carried no market data).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BandwidthStats {
+    /// Raw frame bytes and frame count received
+    pub connection: Usage,
+    /// Keyed by `trade`, `quote`, `orderbook` or `heartbeat`
+    pub by_channel: HashMap<String, Usage>,
+    pub by_symbol: HashMap<String, Usage>,
+}
+
+impl BandwidthStats {
+    /// Record a received frame and the messages decoded from it
+    pub fn record(&mut self, frame_len: usize, messages: &[MarketDataMessage]) {
+        self.connection.add(frame_len as u64);
+        if messages.is_empty() {
+            return;
+        }
+
+        let count = messages.len() as u64;
+        let (share, remainder) = (frame_len as u64 / count, frame_len as u64 % count);
+        for (i, msg) in messages.iter().enumerate() {
+            let bytes = if i == 0 { share + remainder } else { share };
+            add(&mut self.by_channel, channel(msg), bytes);
+            if let Some(symbol) = msg.symbol() {
+                add(&mut self.by_symbol, &symbol, bytes);
+            }
+        }
+    }
+
+    /// Symbols using the most bytes, largest first
+    pub fn top_symbols(&self, n: usize) -> Vec<(&str, Usage)> {
+        let mut symbols: Vec<(&str, Usage)> = self
+            .by_symbol
+            .iter()
+            .map(|(symbol, usage)| (symbol.as_str(), *usage))
+            .collect();
+        symbols.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+        symbols.truncate(n);
+        symbols
+    }
+
+    /// Render as Prometheus text exposition, labelled with `connection`.
+    /// Channel and symbol breakdowns are separate metrics, so summing any
+    /// one of them does not count bytes twice.
+    pub fn to_prometheus(&self, connection: &str) -> String {
+        let connection = escape(connection);
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE mds_received_bytes_total counter");
+        let _ = writeln!(
+            out,
+            "mds_received_bytes_total{{connection=\"{}\"}} {}",
+            connection, self.connection.bytes
+        );
+        let mut channels: Vec<_> = self.by_channel.iter().collect();
+        channels.sort_by_key(|(channel, _)| channel.as_str());
+        let _ = writeln!(out, "# TYPE mds_channel_received_bytes_total counter");
+        for (channel, usage) in channels {
+            let _ = writeln!(
+                out,
+                "mds_channel_received_bytes_total{{connection=\"{}\",channel=\"{}\"}} {}",
+                connection,
+                escape(channel),
+                usage.bytes
+            );
+        }
+        let mut symbols: Vec<_> = self.by_symbol.iter().collect();
+        symbols.sort_by_key(|(symbol, _)| symbol.as_str());
+        let _ = writeln!(out, "# TYPE mds_symbol_received_bytes_total counter");
+        for (symbol, usage) in symbols {
+            let _ = writeln!(
+                out,
+                "mds_symbol_received_bytes_total{{connection=\"{}\",symbol=\"{}\"}} {}",
+                connection,
+                escape(symbol),
+                usage.bytes
+            );
+        }
+        out
+    }
+}
+
+/// Escape a Prometheus label value
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn add(map: &mut HashMap<String, Usage>, key: &str, bytes: u64) {
+    match map.get_mut(key) {
+        Some(usage) => usage.add(bytes),
+        None => map.entry(key.to_string()).or_default().add(bytes),
+    }
+}
+
+fn channel(msg: &MarketDataMessage) -> &'static str {
+    match msg {
+        MarketDataMessage::Trade(_)
+        | MarketDataMessage::TradeCorrection(_)
+        | MarketDataMessage::TradeBust(_) => "trade",
+        MarketDataMessage::Quote(_) => "quote",
+        MarketDataMessage::OrderBook(_) => "orderbook",
+        MarketDataMessage::Heartbeat => "heartbeat",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::OrderBookSnapshot;
+
+    fn book(symbol: &str) -> MarketDataMessage {
+        MarketDataMessage::OrderBook(OrderBookSnapshot {
+            symbol: symbol.into(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_accounts_by_channel_and_symbol() {
+        let mut stats = BandwidthStats::default();
+        stats.record(1000, &[book("BTCUSD")]);
+        stats.record(300, &[book("ETHUSD"), book("BTCUSD")]);
+        stats.record(20, &[MarketDataMessage::Heartbeat]);
+        stats.record(50, &[]);
+
+        assert_eq!(stats.connection.bytes, 1370);
+        assert_eq!(stats.connection.messages, 4);
+        assert_eq!(stats.by_channel["orderbook"].bytes, 1300);
+        assert_eq!(stats.by_channel["heartbeat"].messages, 1);
+
+        let top = stats.top_symbols(1);
+        assert_eq!(top.len(), 1);
+        assert_eq!((top[0].0, top[0].1.bytes), ("BTCUSD", 1150));
+        assert!(stats.to_prometheus("binance").contains(
+            "mds_symbol_received_bytes_total{connection=\"binance\",symbol=\"ETHUSD\"} 150"
+        ));
+    }
+
+    #[test]
+    fn test_shares_add_up_and_labels_are_escaped() {
+        let mut stats = BandwidthStats::default();
+        stats.record(100, &[book("A"), book("B"), book("C")]);
+        let by_symbol: u64 = stats.by_symbol.values().map(|usage| usage.bytes).sum();
+        assert_eq!(by_symbol, 100);
+        assert_eq!(stats.by_channel["orderbook"].bytes, 100);
+
+        let text = stats.to_prometheus("wss://feed/\"v2\"\\\n");
+        assert!(
+            text.contains(r#"mds_received_bytes_total{connection="wss://feed/\"v2\"\\\n"} 100"#)
+        );
+        assert!(text.contains("mds_channel_received_bytes_total{"));
+        // Each metric sums to the connection total on its own
+        assert_eq!(text.matches("mds_received_bytes_total{").count(), 1);
+    }
+}
@@ -0,0 +1,243 @@
+//! Learned message rate and volume baselines with anomaly flags.
+//!
+//! [`BaselineLearner`] counts messages and traded volume per symbol, and
+//! for the feed as a whole, over fixed intervals. Each closed interval is
+//! compared with what is normal for the same time of day: a running mean
+//! and variance per time-of-day bucket, learned from every earlier day.
+//! Intervals deviating by more than the z-score threshold are reported as
+//! [`Anomaly`]s, e.g. a symbol going quiet because the feed degraded or a
+//! volume spike on news. Symbols that go silent while the rest of the feed
+//! keeps flowing are caught too, since every symbol with a baseline is
+//! checked each interval.
+
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Duration, NaiveTime, Timelike, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Baseline key of the whole feed
+const FEED: &str = "";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyMetric {
+    /// Messages per second
+    MessageRate,
+    /// Traded quantity per second
+    Volume,
+}
+
+/// An interval whose rate was unusual for its time of day
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Anomaly {
+    /// `None` for the feed as a whole
+    pub symbol: Option<Symbol>,
+    pub metric: AnomalyMetric,
+    pub observed: f64,
+    /// Mean of the baseline
+    pub expected: f64,
+    /// Standard deviations from the mean, negative when below
+    pub z_score: f64,
+    /// Start of the interval
+    pub start: DateTime<Utc>,
+}
+
+/// Running mean and variance (Welford)
+#[derive(Debug, Clone, Copy, Default)]
+struct Moments {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Moments {
+    fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn std_dev(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        (self.m2 / (self.count - 1) as f64).sqrt()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    messages: u64,
+    volume: f64,
+}
+
+/// Learns normal rates per symbol and time of day and flags deviations
+#[derive(Debug, Clone)]
+pub struct BaselineLearner {
+    interval: Duration,
+    bucket: Duration,
+    threshold: f64,
+    min_samples: u64,
+    /// Start of the open interval
+    start: Option<DateTime<Utc>>,
+    counts: HashMap<String, Counts>,
+    /// Per symbol and time-of-day bucket: message rate and volume moments
+    baselines: HashMap<String, Vec<[Moments; 2]>>,
+}
+
+impl Default for BaselineLearner {
+    fn default() -> Self {
+        Self::new(Duration::minutes(1), Duration::minutes(30))
+    }
+}
+
+impl BaselineLearner {
+    /// Measure rates over `interval` and learn one baseline per `bucket`
+    /// of the day
+    pub fn new(interval: Duration, bucket: Duration) -> Self {
+        Self {
+            interval,
+            bucket,
+            threshold: 4.0,
+            min_samples: 5,
+            start: None,
+            counts: HashMap::new(),
+            baselines: HashMap::new(),
+        }
+    }
+
+    /// Flag intervals at least `z_score` standard deviations from the mean
+    /// (default 4)
+    pub fn with_threshold(mut self, z_score: f64) -> Self {
+        self.threshold = z_score;
+        self
+    }
+
+    /// Intervals learned for a bucket before it can flag anything
+    /// (default 5)
+    pub fn with_min_samples(mut self, samples: u64) -> Self {
+        self.min_samples = samples;
+        self
+    }
+
+    /// Count messages received at `at`, returning the anomalies of any
+    /// intervals closed by it
+    pub fn record(&mut self, msgs: &[MarketDataMessage], at: DateTime<Utc>) -> Vec<Anomaly> {
+        let mut anomalies = Vec::new();
+        let start = *self.start.get_or_insert_with(|| align(at, self.interval));
+        if at >= start + self.interval {
+            // Intervals without a single message on the feed are not
+            // learned: the feed or the process was down, which QoS gap
+            // detection reports
+            self.close(start, &mut anomalies);
+            self.start = Some(align(at, self.interval));
+        }
+
+        for msg in msgs {
+            let (symbol, volume) = match msg {
+                MarketDataMessage::Trade(trade) => (&trade.symbol, trade.quantity),
+                MarketDataMessage::Quote(quote) => (&quote.symbol, 0.0),
+                MarketDataMessage::OrderBook(book) => (&book.symbol, 0.0),
+                MarketDataMessage::TradeCorrection(correction) => (&correction.symbol, 0.0),
+                MarketDataMessage::TradeBust(bust) => (&bust.symbol, 0.0),
+                MarketDataMessage::Heartbeat => continue,
+            };
+            for key in [symbol.as_str(), FEED] {
+                let counts = self.counts.entry(key.to_string()).or_default();
+                counts.messages += 1;
+                counts.volume += volume;
+            }
+        }
+        anomalies
+    }
+
+    /// Score the interval starting at `start` against its bucket, then
+    /// learn from it
+    fn close(&mut self, start: DateTime<Utc>, anomalies: &mut Vec<Anomaly>) {
+        let seconds = self.interval.num_milliseconds() as f64 / 1000.0;
+        let bucket_seconds = self.bucket.num_seconds().clamp(1, 86_400);
+        let buckets = (86_400 + bucket_seconds - 1) / bucket_seconds;
+        let bucket = (start.num_seconds_from_midnight() as i64 / bucket_seconds) as usize;
+        for symbol in self.counts.keys() {
+            self.baselines
+                .entry(symbol.clone())
+                .or_insert_with(|| vec![Default::default(); buckets as usize]);
+        }
+
+        for (symbol, baseline) in self.baselines.iter_mut() {
+            let counts = self.counts.remove(symbol).unwrap_or_default();
+            let observed = [counts.messages as f64 / seconds, counts.volume / seconds];
+            let metrics = [AnomalyMetric::MessageRate, AnomalyMetric::Volume];
+            for ((moments, observed), metric) in
+                baseline[bucket].iter_mut().zip(observed).zip(metrics)
+            {
+                let std_dev = moments.std_dev();
+                if moments.count >= self.min_samples && std_dev > 0.0 {
+                    let z_score = (observed - moments.mean) / std_dev;
+                    if z_score.abs() >= self.threshold {
+                        anomalies.push(Anomaly {
+                            symbol: (symbol != FEED).then(|| symbol.as_str().into()),
+                            metric,
+                            observed,
+                            expected: moments.mean,
+                            z_score,
+                            start,
+                        });
+                    }
+                }
+                moments.push(observed);
+            }
+        }
+    }
+}
+
+/// Start of the interval containing `at`, counted from midnight
+fn align(at: DateTime<Utc>, interval: Duration) -> DateTime<Utc> {
+    let midnight = at.date_naive().and_time(NaiveTime::MIN).and_utc();
+    let step = interval.num_milliseconds().max(1);
+    let offset = (at - midnight).num_milliseconds() / step * step;
+    midnight + Duration::milliseconds(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Trade;
+    use chrono::TimeZone;
+
+    fn trade() -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade::test("BTCUSD", 100.0))
+    }
+
+    #[test]
+    fn test_flags_quiet_interval_against_same_time_of_day() {
+        let mut learner = BaselineLearner::new(Duration::minutes(1), Duration::minutes(1))
+            .with_min_samples(3)
+            .with_threshold(3.0);
+        let open = Utc.with_ymd_and_hms(2024, 1, 1, 14, 0, 0).unwrap();
+
+        // Five days of 10 to 12 trades in the 14:00 minute
+        for day in 0..5 {
+            let at = open + Duration::days(day);
+            let trades = vec![trade(); 10 + (day % 3) as usize];
+            assert!(learner.record(&trades, at).is_empty());
+            learner.record(&[], at + Duration::minutes(1));
+        }
+
+        // Only one trade on day six, then the minute closes
+        let at = open + Duration::days(5);
+        learner.record(&[trade()], at);
+        let anomalies = learner.record(&[], at + Duration::minutes(1));
+        let rate = anomalies
+            .iter()
+            .find(|a| {
+                a.symbol.as_deref() == Some("BTCUSD") && a.metric == AnomalyMetric::MessageRate
+            })
+            .unwrap();
+        assert!(rate.z_score < -3.0);
+        assert_eq!(rate.start, at);
+        assert!(anomalies.iter().any(|a| a.symbol.is_none()));
+    }
+}
@@ -0,0 +1,222 @@
+//! Execution benchmarks: interval VWAP and TWAP against one's own fills.
+//!
+//! [`BenchmarkTracker`] follows market trades over a fixed interval and
+//! computes each symbol's volume-weighted and time-weighted average price.
+//! Fills registered with [`record_fill`](BenchmarkTracker::record_fill) are
+//! compared with both, giving slippage in basis points where a positive
+//! number is a cost: paying above the benchmark on buys, or receiving below
+//! it on sells. Only regular trades count, since block, auction and off-book
+//! prints were not available to trade against.
+
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Trade, TradeSide};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Fills of one symbol and side against the interval benchmarks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkReport {
+    pub symbol: Symbol,
+    pub side: TradeSide,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Regular market volume traded in the interval so far
+    pub market_volume: f64,
+    pub vwap: f64,
+    /// Last trade price weighted by how long it stood
+    pub twap: f64,
+    pub fills: usize,
+    pub filled_quantity: f64,
+    pub average_price: f64,
+    /// Own fills as a fraction of market volume
+    pub participation: f64,
+    pub slippage_vwap_bps: f64,
+    pub slippage_twap_bps: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Market {
+    volume: f64,
+    notional: f64,
+    /// Last price and when it was set, or the interval start if earlier
+    last: Option<(DateTime<Utc>, f64)>,
+    /// Integral of price over time, in price-nanoseconds
+    weighted: f64,
+    elapsed: i64,
+}
+
+impl Market {
+    /// Advance the time-weighted sum to `until`
+    fn accrue(&mut self, until: DateTime<Utc>) {
+        if let Some((since, price)) = self.last {
+            let span = (until - since).num_nanoseconds().unwrap_or(0).max(0);
+            self.weighted += price * span as f64;
+            self.elapsed += span;
+            self.last = Some((until.max(since), price));
+        }
+    }
+
+    fn twap(&self, now: DateTime<Utc>) -> Option<f64> {
+        let mut market = self.clone();
+        market.accrue(now);
+        (market.elapsed > 0).then(|| market.weighted / market.elapsed as f64)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Fills {
+    count: usize,
+    quantity: f64,
+    notional: f64,
+}
+
+/// Interval VWAP and TWAP per symbol, with slippage of registered fills
+#[derive(Debug, Clone)]
+pub struct BenchmarkTracker {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    markets: HashMap<Symbol, Market>,
+    fills: HashMap<(Symbol, TradeSide), Fills>,
+}
+
+impl BenchmarkTracker {
+    /// Benchmarks over `start..end`
+    pub fn new(start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Self {
+            start,
+            end,
+            markets: HashMap::new(),
+            fills: HashMap::new(),
+        }
+    }
+
+    pub fn on_message(&mut self, msg: &MarketDataMessage) {
+        if let MarketDataMessage::Trade(trade) = msg {
+            self.on_trade(trade);
+        }
+    }
+
+    pub fn on_trade(&mut self, trade: &Trade) {
+        if !trade.conditions.is_regular() || trade.timestamp >= self.end {
+            return;
+        }
+        let market = self.markets.entry(trade.symbol).or_default();
+        // Earlier trades only set the price standing at the start
+        if trade.timestamp < self.start {
+            market.last = Some((self.start, trade.price));
+            return;
+        }
+        market.accrue(trade.timestamp);
+        market.last = Some((trade.timestamp, trade.price));
+        market.volume += trade.quantity;
+        market.notional += trade.price * trade.quantity;
+    }
+
+    /// Register one of our own executions
+    pub fn record_fill(&mut self, symbol: &str, side: TradeSide, price: f64, quantity: f64) {
+        let fills = self.fills.entry((symbol.into(), side)).or_default();
+        fills.count += 1;
+        fills.quantity += quantity;
+        fills.notional += price * quantity;
+    }
+
+    /// Interval VWAP of `symbol` so far
+    pub fn vwap(&self, symbol: &str) -> Option<f64> {
+        let market = self.markets.get(symbol)?;
+        (market.volume > 0.0).then(|| market.notional / market.volume)
+    }
+
+    /// Interval TWAP of `symbol` up to `now`, capped at the interval end
+    pub fn twap(&self, symbol: &str, now: DateTime<Utc>) -> Option<f64> {
+        self.markets.get(symbol)?.twap(now.min(self.end))
+    }
+
+    /// One report per symbol and side with fills and benchmarks, as of
+    /// `now`, ordered by symbol with buys first
+    pub fn reports(&self, now: DateTime<Utc>) -> Vec<BenchmarkReport> {
+        let mut reports: Vec<BenchmarkReport> = self
+            .fills
+            .iter()
+            .filter_map(|((symbol, side), fills)| {
+                let vwap = self.vwap(symbol)?;
+                let twap = self.twap(symbol, now)?;
+                let average_price = fills.notional / fills.quantity;
+                let sign = match side {
+                    TradeSide::Buy => 1.0,
+                    TradeSide::Sell => -1.0,
+                    TradeSide::Unknown => return None,
+                };
+                let slippage =
+                    |benchmark: f64| sign * (average_price - benchmark) / benchmark * 10_000.0;
+                let market_volume = self.markets[symbol].volume;
+                Some(BenchmarkReport {
+                    symbol: *symbol,
+                    side: *side,
+                    start: self.start,
+                    end: self.end,
+                    market_volume,
+                    vwap,
+                    twap,
+                    fills: fills.count,
+                    filled_quantity: fills.quantity,
+                    average_price,
+                    participation: fills.quantity / market_volume,
+                    slippage_vwap_bps: slippage(vwap),
+                    slippage_twap_bps: slippage(twap),
+                })
+            })
+            .collect();
+        reports.sort_by(|a, b| {
+            (&a.symbol, a.side == TradeSide::Sell).cmp(&(&b.symbol, b.side == TradeSide::Sell))
+        });
+        reports
+    }
+}
+
+impl Stage for BenchmarkTracker {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn trade(price: f64, quantity: f64, minute: u32) -> Trade {
+        Trade {
+            quantity,
+            timestamp: Utc.with_ymd_and_hms(2024, 3, 5, 14, minute, 0).unwrap(),
+            ..Trade::test("AAPL", price)
+        }
+    }
+
+    #[test]
+    fn test_slippage_against_vwap_and_twap() {
+        let t = |minute| Utc.with_ymd_and_hms(2024, 3, 5, 14, minute, 0).unwrap();
+        let mut tracker = BenchmarkTracker::new(t(30), t(40));
+        // Sets the price standing at the start, but is not in the VWAP
+        tracker.on_trade(&trade(99.0, 500.0, 29));
+        tracker.on_trade(&trade(100.0, 100.0, 32));
+        tracker.on_trade(&trade(102.0, 300.0, 36));
+        tracker.on_trade(&trade(150.0, 100.0, 41));
+        tracker.record_fill("AAPL", TradeSide::Buy, 101.0, 40.0);
+        tracker.record_fill("AAPL", TradeSide::Buy, 102.0, 60.0);
+
+        assert_eq!(tracker.vwap("AAPL"), Some(101.5));
+        // 99 for 2 minutes, 100 for 4 and 102 for 4
+        assert!((tracker.twap("AAPL", t(45)).unwrap() - 100.6).abs() < 1e-9);
+
+        let reports = tracker.reports(t(45));
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!((report.average_price, report.participation), (101.6, 0.25));
+        assert!((report.slippage_vwap_bps - 0.1 / 101.5 * 10_000.0).abs() < 1e-9);
+        assert!((report.slippage_twap_bps - 1.0 / 100.6 * 10_000.0).abs() < 1e-9);
+    }
+}
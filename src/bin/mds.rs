@@ -0,0 +1,227 @@
+//! Command line tools for working with market data captures.
+//!
+//! ```text
+//! mds compact <input> <output> [--keep-heartbeats] [--quote-interval-ms N] [--symbols A,B]
+//!             [--key-env VAR]
+//! mds capture <adapter> <url> <output> [--symbols A,B] [--frames N] [--seconds N]
+//! mds verify <recording> [--key-env VAR]
+//! mds emulate <venue> <recording> <addr> [--speed N]
+//! ```
+
+use chrono::Duration;
+use rust_market_data_stream::adapters;
+use rust_market_data_stream::emulator::{EmulatedVenue, ExchangeEmulator};
+use rust_market_data_stream::fixtures;
+use rust_market_data_stream::recording::{
+    compact, verify, CompactOptions, RecordingKey, RecordingReader,
+};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: mds compact <input> <output> [--keep-heartbeats] \
+                     [--quote-interval-ms N] [--symbols A,B] [--key-env VAR]\n       \
+                     mds capture <adapter> <url> <output> [--symbols A,B] [--frames N] \
+                     [--seconds N]\n       \
+                     mds verify <recording> [--key-env VAR]\n       \
+                     mds emulate <venue> <recording> <addr> [--speed N]";
+
+fn main() -> ExitCode {
+    tracing_subscriber::fmt::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("compact") => run_compact(&args[1..]),
+        Some("capture") => run_capture(&args[1..]),
+        Some("verify") => run_verify(&args[1..]),
+        Some("emulate") => run_emulate(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_compact(args: &[String]) -> Result<(), String> {
+    let [input, output, flags @ ..] = args else {
+        return Err(USAGE.to_string());
+    };
+
+    let mut options = CompactOptions::default();
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--keep-heartbeats" => options.drop_heartbeats = false,
+            "--quote-interval-ms" => {
+                let ms = flags
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or("--quote-interval-ms expects a number of milliseconds")?;
+                options.quote_interval = Some(Duration::milliseconds(ms));
+            }
+            "--symbols" => {
+                let symbols = flags
+                    .next()
+                    .ok_or("--symbols expects a comma separated list")?;
+                options.symbols = Some(symbols.split(',').map(Into::into).collect());
+            }
+            "--key-env" => {
+                let var = flags
+                    .next()
+                    .ok_or("--key-env expects an environment variable name")?;
+                options.key = Some(RecordingKey::from_env(var).map_err(|e| e.to_string())?);
+            }
+            other => return Err(format!("unknown flag: {}\n{}", other, USAGE)),
+        }
+    }
+
+    let report = compact(input, output, &options).map_err(|e| e.to_string())?;
+    println!(
+        "read {} messages, wrote {} (heartbeats dropped: {}, quotes conflated: {}, symbols filtered: {})",
+        report.read,
+        report.written,
+        report.heartbeats_dropped,
+        report.quotes_conflated,
+        report.symbols_filtered
+    );
+    Ok(())
+}
+
+fn run_capture(args: &[String]) -> Result<(), String> {
+    let [adapter, url, output, flags @ ..] = args else {
+        return Err(USAGE.to_string());
+    };
+
+    let mut symbols = String::new();
+    let mut max_frames = 100;
+    let mut seconds = 30;
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--symbols" => {
+                symbols = flags
+                    .next()
+                    .ok_or("--symbols expects a comma separated list")?
+                    .clone();
+            }
+            "--frames" => {
+                max_frames = flags
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or("--frames expects a number")?;
+            }
+            "--seconds" => {
+                seconds = flags
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or("--seconds expects a number")?;
+            }
+            other => return Err(format!("unknown flag: {}\n{}", other, USAGE)),
+        }
+    }
+
+    let symbols: Vec<&str> = symbols.split(',').filter(|s| !s.is_empty()).collect();
+    let adapter = adapters::by_name(adapter, &symbols)
+        .ok_or_else(|| format!("unknown adapter: {}", adapter))?;
+
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    let frames = runtime
+        .block_on(fixtures::capture(
+            url,
+            adapter.as_ref(),
+            max_frames,
+            std::time::Duration::from_secs(seconds),
+        ))
+        .map_err(|e| e.to_string())?;
+    fixtures::save(output, &frames).map_err(|e| e.to_string())?;
+    println!("captured {} frames to {}", frames.len(), output);
+    Ok(())
+}
+
+fn run_verify(args: &[String]) -> Result<(), String> {
+    let key = match args {
+        [_] => None,
+        [_, flag, var] if flag == "--key-env" => {
+            Some(RecordingKey::from_env(var).map_err(|e| e.to_string())?)
+        }
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let report = verify(&args[0], key.as_ref()).map_err(|e| e.to_string())?;
+    println!(
+        "{}: {} blocks, {} records decoded, footer {}, manifest {}",
+        args[0],
+        report.blocks,
+        report.records,
+        if report.finished {
+            "present"
+        } else {
+            "missing"
+        },
+        if report.manifest {
+            "checked"
+        } else {
+            "missing"
+        }
+    );
+    if report.is_ok() {
+        return Ok(());
+    }
+    for problem in &report.problems {
+        println!("  {}", problem);
+    }
+    Err(format!("{} problem(s) found", report.problems.len()))
+}
+
+fn run_emulate(args: &[String]) -> Result<(), String> {
+    let [venue, recording, addr, flags @ ..] = args else {
+        return Err(USAGE.to_string());
+    };
+    let venue = EmulatedVenue::by_name(venue).ok_or_else(|| format!("unknown venue: {}", venue))?;
+    let speed: f64 = match flags {
+        [] => 1.0,
+        [flag, value] if flag == "--speed" => value
+            .parse()
+            .map_err(|_| "--speed expects a number, 0 for as fast as possible")?,
+        _ => return Err(USAGE.to_string()),
+    };
+    let mut reader = RecordingReader::open(recording).map_err(|e| e.to_string())?;
+
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| e.to_string())?;
+        let (tx, rx) = tokio::sync::broadcast::channel(4096);
+        let emulator = ExchangeEmulator::new(venue, rx);
+        tokio::spawn(async move { emulator.serve(listener).await });
+
+        println!(
+            "emulating {} on {}, waiting for a client",
+            venue.name(),
+            addr
+        );
+        // The emulator holds one receiver, each connection another
+        while tx.receiver_count() < 2 {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+
+        let mut previous: Option<chrono::DateTime<chrono::Utc>> = None;
+        let mut sent = 0u64;
+        for entry in reader.messages() {
+            let (ts, msg) = entry.map_err(|e| e.to_string())?;
+            if let (Some(previous), true) = (previous, speed > 0.0) {
+                let gap = (ts - previous).to_std().unwrap_or_default();
+                tokio::time::sleep(gap.div_f64(speed)).await;
+            }
+            previous = Some(ts);
+            let _ = tx.send(msg);
+            sent += 1;
+        }
+        println!("replayed {} messages", sent);
+        Ok(())
+    })
+}
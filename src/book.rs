@@ -0,0 +1,334 @@
+//! Incrementally maintained order books: price levels, and individual
+//! orders aggregated into levels.
+
+use crate::client::{ClientError, Result};
+use crate::symbology::Symbol;
+use crate::types::{OrderBookSnapshot, PriceLevel};
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// Side of the book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookSide {
+    Bid,
+    Ask,
+}
+
+/// Levels per side a consumer needs from a symbol's book
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BookDepth {
+    /// Best bid and offer only
+    Top1,
+    Top5,
+    Top20,
+    /// Every level the venue publishes
+    #[default]
+    Full,
+}
+
+impl BookDepth {
+    /// Levels per side, `None` for the full book
+    pub fn levels(self) -> Option<usize> {
+        match self {
+            Self::Top1 => Some(1),
+            Self::Top5 => Some(5),
+            Self::Top20 => Some(20),
+            Self::Full => None,
+        }
+    }
+}
+
+impl FromStr for BookDepth {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "1" | "top1" => Ok(Self::Top1),
+            "5" | "top5" => Ok(Self::Top5),
+            "20" | "top20" => Ok(Self::Top20),
+            "full" => Ok(Self::Full),
+            _ => Err(ClientError::Parse(format!("unknown book depth: {}", s))),
+        }
+    }
+}
+
+/// Totally ordered price key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Price(pub(crate) f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Price-level order book for a single symbol
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    pub symbol: Symbol,
+    bids: BTreeMap<Price, PriceLevel>,
+    asks: BTreeMap<Price, PriceLevel>,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+impl OrderBook {
+    pub fn new(symbol: impl Into<Symbol>) -> Self {
+        Self {
+            symbol: symbol.into(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            timestamp: None,
+        }
+    }
+
+    /// Build a book from a full snapshot
+    pub fn from_snapshot(snapshot: &OrderBookSnapshot) -> Self {
+        let mut book = Self::new(snapshot.symbol);
+        book.apply_snapshot(snapshot);
+        book
+    }
+
+    /// Replace the whole book with a snapshot
+    pub fn apply_snapshot(&mut self, snapshot: &OrderBookSnapshot) {
+        self.bids = snapshot
+            .bids
+            .iter()
+            .filter(|level| level.size > 0.0)
+            .map(|level| (Price(level.price), level.clone()))
+            .collect();
+        self.asks = snapshot
+            .asks
+            .iter()
+            .filter(|level| level.size > 0.0)
+            .map(|level| (Price(level.price), level.clone()))
+            .collect();
+        self.timestamp = Some(snapshot.timestamp);
+    }
+
+    /// Set the size at a price level; a size of zero removes the level
+    pub fn update_level(&mut self, side: BookSide, level: PriceLevel, timestamp: DateTime<Utc>) {
+        let levels = match side {
+            BookSide::Bid => &mut self.bids,
+            BookSide::Ask => &mut self.asks,
+        };
+        if level.size > 0.0 {
+            levels.insert(Price(level.price), level);
+        } else {
+            levels.remove(&Price(level.price));
+        }
+        self.timestamp = Some(timestamp);
+    }
+
+    pub fn best_bid(&self) -> Option<&PriceLevel> {
+        self.bids.values().next_back()
+    }
+
+    pub fn best_ask(&self) -> Option<&PriceLevel> {
+        self.asks.values().next()
+    }
+
+    /// Bid levels from best to worst
+    pub fn bids(&self) -> impl Iterator<Item = &PriceLevel> {
+        self.bids.values().rev()
+    }
+
+    /// Ask levels from best to worst
+    pub fn asks(&self) -> impl Iterator<Item = &PriceLevel> {
+        self.asks.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    /// Materialize the top `depth` levels per side (all levels when `None`)
+    pub fn snapshot(&self, depth: Option<usize>) -> OrderBookSnapshot {
+        let mut snapshot = OrderBookSnapshot::default();
+        self.snapshot_into(depth, &mut snapshot);
+        snapshot
+    }
+
+    /// Like [`snapshot`](Self::snapshot), but overwrites an existing
+    /// snapshot to reuse its level buffers
+    pub fn snapshot_into(&self, depth: Option<usize>, snapshot: &mut OrderBookSnapshot) {
+        let depth = depth.unwrap_or(usize::MAX);
+        snapshot.symbol.clone_from(&self.symbol);
+        snapshot.bids.clear();
+        snapshot.bids.extend(self.bids().take(depth).cloned());
+        snapshot.asks.clear();
+        snapshot.asks.extend(self.asks().take(depth).cloned());
+        snapshot.timestamp = self.timestamp.unwrap_or_else(Utc::now);
+    }
+}
+
+/// A resting order in an [`L3Book`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct L3Order {
+    pub side: BookSide,
+    pub price: f64,
+    pub size: f64,
+}
+
+/// Order-by-order (level 3) book, aggregated into price levels as orders
+/// are added, reduced and removed
+#[derive(Debug, Clone)]
+pub struct L3Book {
+    orders: HashMap<u64, L3Order>,
+    levels: OrderBook,
+}
+
+impl L3Book {
+    pub fn new(symbol: impl Into<Symbol>) -> Self {
+        Self {
+            orders: HashMap::new(),
+            levels: OrderBook::new(symbol),
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.levels.symbol
+    }
+
+    /// Price-level view of the book
+    pub fn levels(&self) -> &OrderBook {
+        &self.levels
+    }
+
+    pub fn order(&self, id: u64) -> Option<&L3Order> {
+        self.orders.get(&id)
+    }
+
+    /// Number of resting orders
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    /// Add an order, replacing any resting order with the same id
+    pub fn add(&mut self, id: u64, order: L3Order, timestamp: DateTime<Utc>) {
+        if let Some(old) = self.orders.insert(id, order) {
+            self.apply(old, -old.size, -1, timestamp);
+        }
+        self.apply(order, order.size, 1, timestamp);
+    }
+
+    /// Reduce an order by `size` after a partial cancel or execution; it is
+    /// removed once nothing is left. Returns the order as it was before.
+    pub fn reduce(&mut self, id: u64, size: f64, timestamp: DateTime<Utc>) -> Option<L3Order> {
+        let order = self.orders.get_mut(&id)?;
+        let before = *order;
+        let size = size.min(order.size);
+        order.size -= size;
+        let removed = order.size <= 0.0;
+        if removed {
+            self.orders.remove(&id);
+        }
+        self.apply(before, -size, -(removed as i32), timestamp);
+        Some(before)
+    }
+
+    /// Remove an order entirely
+    pub fn remove(&mut self, id: u64, timestamp: DateTime<Utc>) -> Option<L3Order> {
+        let order = self.orders.remove(&id)?;
+        self.apply(order, -order.size, -1, timestamp);
+        Some(order)
+    }
+
+    pub fn clear(&mut self) {
+        self.orders.clear();
+        self.levels.bids.clear();
+        self.levels.asks.clear();
+    }
+
+    fn apply(&mut self, order: L3Order, size: f64, count: i32, timestamp: DateTime<Utc>) {
+        let levels = match order.side {
+            BookSide::Bid => &self.levels.bids,
+            BookSide::Ask => &self.levels.asks,
+        };
+        let (total, orders) = levels
+            .get(&Price(order.price))
+            .map_or((0.0, 0), |level| (level.size, level.num_orders));
+        let orders = (orders as i32 + count).max(0) as u32;
+        let level = PriceLevel {
+            price: order.price,
+            size: if orders == 0 {
+                0.0
+            } else {
+                (total + size).max(0.0)
+            },
+            num_orders: orders,
+        };
+        self.levels.update_level(order.side, level, timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: f64, size: f64) -> PriceLevel {
+        PriceLevel {
+            price,
+            size,
+            num_orders: 1,
+        }
+    }
+
+    #[test]
+    fn test_levels_are_sorted_and_removed() {
+        let now = Utc::now();
+        let mut book = OrderBook::new("BTCUSD".to_string());
+        book.update_level(BookSide::Bid, level(99.0, 1.0), now);
+        book.update_level(BookSide::Bid, level(100.0, 2.0), now);
+        book.update_level(BookSide::Ask, level(102.0, 1.0), now);
+        book.update_level(BookSide::Ask, level(101.0, 3.0), now);
+
+        let snapshot = book.snapshot(Some(1));
+        assert_eq!(snapshot.best_bid().unwrap().price, 100.0);
+        assert_eq!(snapshot.best_ask().unwrap().price, 101.0);
+        assert_eq!(snapshot.bids.len(), 1);
+
+        book.update_level(BookSide::Ask, level(101.0, 0.0), now);
+        assert_eq!(book.best_ask().unwrap().price, 102.0);
+    }
+
+    #[test]
+    fn test_l3_orders_aggregate_into_levels() {
+        let now = Utc::now();
+        let mut book = L3Book::new("AAPL".to_string());
+        let bid = |size| L3Order {
+            side: BookSide::Bid,
+            price: 179.6,
+            size,
+        };
+        book.add(1, bid(100.0), now);
+        book.add(2, bid(50.0), now);
+        book.reduce(1, 40.0, now);
+        assert_eq!(
+            book.levels().best_bid(),
+            Some(&PriceLevel {
+                price: 179.6,
+                size: 110.0,
+                num_orders: 2,
+            })
+        );
+
+        book.remove(2, now);
+        book.reduce(1, 60.0, now);
+        assert!(book.is_empty());
+        assert!(book.levels().is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+//! Circuit breaker for sustained parse failures.
+//!
+//! When a venue changes its schema every frame starts failing to decode.
+//! [`ParseBreaker`] measures the error rate over fixed windows, trips once
+//! it stays above a threshold and recovers when a later window is healthy
+//! again. It also rate-limits the per-frame parse warning so a storm of
+//! failures produces one log line per window instead of one per frame.
+
+use std::time::{Duration, Instant};
+
+/// Change of breaker state at the end of a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakerTransition {
+    /// The error rate reached the threshold
+    Degraded { error_rate: f64 },
+    /// A window with enough frames came in under the threshold
+    Recovered,
+}
+
+/// Tracks the parse error rate of one adapter
+#[derive(Debug, Clone)]
+pub struct ParseBreaker {
+    window: Duration,
+    max_error_rate: f64,
+    min_frames: u64,
+    passthrough: bool,
+    window_start: Option<Instant>,
+    frames: u64,
+    errors: u64,
+    warned: bool,
+    suppressed: u64,
+    degraded: bool,
+}
+
+impl Default for ParseBreaker {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), 0.5)
+    }
+}
+
+impl ParseBreaker {
+    /// Trip when at least `max_error_rate` (0..=1) of the frames in a
+    /// `window` fail to parse
+    pub fn new(window: Duration, max_error_rate: f64) -> Self {
+        Self {
+            window,
+            max_error_rate,
+            min_frames: 10,
+            passthrough: false,
+            window_start: None,
+            frames: 0,
+            errors: 0,
+            warned: false,
+            suppressed: 0,
+            degraded: false,
+        }
+    }
+
+    /// Ignore windows with fewer than `min_frames` frames (default 10)
+    pub fn with_min_frames(mut self, min_frames: u64) -> Self {
+        self.min_frames = min_frames;
+        self
+    }
+
+    /// Forward unparseable frames as raw text while degraded
+    pub fn with_raw_passthrough(mut self) -> Self {
+        self.passthrough = true;
+        self
+    }
+
+    /// Whether unparseable frames should currently be passed through raw
+    pub fn passthrough(&self) -> bool {
+        self.passthrough && self.degraded
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    /// Record the outcome of decoding one frame
+    pub fn record(&mut self, parsed: bool, now: Instant) -> Option<BreakerTransition> {
+        let transition = self.roll(now);
+        self.frames += 1;
+        if !parsed {
+            self.errors += 1;
+        }
+        transition
+    }
+
+    /// Whether a parse failure may be logged now. Returns the number of
+    /// failures suppressed since the last permitted warning.
+    pub fn should_warn(&mut self) -> Option<u64> {
+        if self.warned {
+            self.suppressed += 1;
+            return None;
+        }
+        self.warned = true;
+        Some(std::mem::take(&mut self.suppressed))
+    }
+
+    /// Close the current window if it has elapsed
+    fn roll(&mut self, now: Instant) -> Option<BreakerTransition> {
+        let start = *self.window_start.get_or_insert(now);
+        if now.duration_since(start) < self.window {
+            return None;
+        }
+
+        let mut transition = None;
+        if self.frames >= self.min_frames {
+            let error_rate = self.errors as f64 / self.frames as f64;
+            if error_rate >= self.max_error_rate && !self.degraded {
+                self.degraded = true;
+                transition = Some(BreakerTransition::Degraded { error_rate });
+            } else if error_rate < self.max_error_rate && self.degraded {
+                self.degraded = false;
+                transition = Some(BreakerTransition::Recovered);
+            }
+        }
+
+        self.window_start = Some(now);
+        self.frames = 0;
+        self.errors = 0;
+        self.warned = false;
+        transition
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trips_and_recovers() {
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+        let mut breaker = ParseBreaker::new(ms(100), 0.5)
+            .with_min_frames(4)
+            .with_raw_passthrough();
+
+        for i in 0..10 {
+            assert_eq!(breaker.record(false, t0 + ms(i)), None);
+        }
+        assert_eq!(breaker.should_warn(), Some(0));
+        assert_eq!(breaker.should_warn(), None);
+        assert_eq!(breaker.should_warn(), None);
+
+        assert_eq!(
+            breaker.record(true, t0 + ms(100)),
+            Some(BreakerTransition::Degraded { error_rate: 1.0 })
+        );
+        assert!(breaker.passthrough());
+        assert_eq!(breaker.should_warn(), Some(2));
+
+        for i in 1..10 {
+            assert_eq!(breaker.record(true, t0 + ms(100 + i)), None);
+        }
+        assert_eq!(
+            breaker.record(true, t0 + ms(200)),
+            Some(BreakerTransition::Recovered)
+        );
+        assert!(!breaker.is_degraded());
+    }
+}
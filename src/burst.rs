@@ -0,0 +1,190 @@
+//! Microburst detection and dispatch rate smoothing.
+//!
+//! A microburst is a short window (e.g. 10ms) in which more than a threshold
+//! number of messages arrive. [`BurstDetector`] tracks them on ingest and
+//! [`RateLimiter`] bounds how fast a smoothing queue releases messages
+//! downstream; both feed [`BurstStats`] so buffers can be sized from real
+//! traffic.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Burst and smoothing queue statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BurstStats {
+    /// Number of bursts detected
+    pub bursts: u64,
+    /// Most messages seen within a single detection window
+    pub peak_in_window: usize,
+    /// Most messages received during one burst
+    pub largest_burst: u64,
+    /// Longest time a burst lasted
+    pub longest_burst: Duration,
+    /// Deepest the smoothing queue has been
+    pub queue_peak: usize,
+    /// Messages dropped because the smoothing queue was full
+    pub queue_dropped: u64,
+}
+
+/// Flags windows where arrivals exceed a threshold
+#[derive(Debug, Clone)]
+pub struct BurstDetector {
+    window: Duration,
+    threshold: usize,
+    arrivals: VecDeque<Instant>,
+    /// First arrival, last arrival and message count of the burst in progress
+    current: Option<(Instant, Instant, u64)>,
+    stats: BurstStats,
+}
+
+impl BurstDetector {
+    /// Detect more than `threshold` messages within `window`
+    pub fn new(window: Duration, threshold: usize) -> Self {
+        Self {
+            window,
+            threshold,
+            arrivals: VecDeque::new(),
+            current: None,
+            stats: BurstStats::default(),
+        }
+    }
+
+    /// Record an arrival, returning true if it starts a new burst
+    pub fn record(&mut self, now: Instant) -> bool {
+        while self
+            .arrivals
+            .front()
+            .is_some_and(|first| now.duration_since(*first) > self.window)
+        {
+            self.arrivals.pop_front();
+        }
+        self.arrivals.push_back(now);
+
+        let count = self.arrivals.len();
+        self.stats.peak_in_window = self.stats.peak_in_window.max(count);
+
+        if count > self.threshold {
+            match &mut self.current {
+                Some((_, last, messages)) => {
+                    *last = now;
+                    *messages += 1;
+                }
+                None => {
+                    let start = self.arrivals[0];
+                    self.current = Some((start, now, count as u64));
+                    self.stats.bursts += 1;
+                    return true;
+                }
+            }
+        } else if let Some(burst) = self.current.take() {
+            self.stats = Self::with_burst(self.stats, burst);
+        }
+        false
+    }
+
+    fn with_burst(
+        mut stats: BurstStats,
+        (start, last, messages): (Instant, Instant, u64),
+    ) -> BurstStats {
+        stats.largest_burst = stats.largest_burst.max(messages);
+        stats.longest_burst = stats.longest_burst.max(last.duration_since(start));
+        stats
+    }
+
+    pub fn in_burst(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Record the smoothing queue depth after an enqueue
+    pub fn record_queue_depth(&mut self, depth: usize) {
+        self.stats.queue_peak = self.stats.queue_peak.max(depth);
+    }
+
+    pub fn record_queue_drop(&mut self) {
+        self.stats.queue_dropped += 1;
+    }
+
+    /// Statistics so far, including a burst still in progress
+    pub fn stats(&self) -> BurstStats {
+        match self.current {
+            Some(burst) => Self::with_burst(self.stats, burst),
+            None => self.stats,
+        }
+    }
+}
+
+/// Token bucket releasing at most `rate` messages per second, with at most
+/// a millisecond's worth of burst allowance
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, now: Instant) -> Self {
+        let capacity = (rate / 1000.0).max(1.0);
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            last: now,
+        }
+    }
+
+    /// Take a token, or return how long to wait until one is available
+    pub fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_bursts() {
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+        let mut detector = BurstDetector::new(ms(10), 3);
+
+        // Steady traffic: one message every 5ms never exceeds 3 per 10ms
+        for i in 0..10 {
+            assert!(!detector.record(t0 + ms(5 * i)));
+        }
+
+        let t1 = t0 + ms(100);
+        let started: Vec<bool> = (0..6).map(|i| detector.record(t1 + ms(i))).collect();
+        assert_eq!(started, vec![false, false, false, true, false, false]);
+        assert!(detector.in_burst());
+
+        assert!(!detector.record(t1 + ms(50)));
+        let stats = detector.stats();
+        assert_eq!(stats.bursts, 1);
+        assert_eq!(stats.peak_in_window, 6);
+        assert_eq!(stats.largest_burst, 6);
+        assert_eq!(stats.longest_burst, ms(5));
+    }
+
+    #[test]
+    fn test_rate_limiter_bounds_rate() {
+        let t0 = Instant::now();
+        let mut limiter = RateLimiter::new(1000.0, t0);
+
+        assert!(limiter.try_acquire(t0).is_ok());
+        let wait = limiter.try_acquire(t0).unwrap_err();
+        assert!(wait <= Duration::from_millis(1));
+        assert!(limiter.try_acquire(t0 + wait).is_ok());
+    }
+}
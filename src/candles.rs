@@ -0,0 +1,1031 @@
+//! OHLCV bar aggregation by time, activity thresholds and price movement.
+//!
+//! Time bars follow exchange or receive time, can wait out a grace period
+//! for late trades, and are re-emitted as revisions when trades arrive for
+//! bars already emitted.
+
+use crate::client::{ClientError, Result};
+use crate::clock::align;
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{
+    BarKind, Candle, ContractSpec, FootprintCandle, FootprintLevel, MarketDataMessage, Trade,
+    TradeBust, TradeCorrection, TradeSide, TradeTerms,
+};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 256;
+
+/// How trades are grouped into bars
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarSpec {
+    /// Fixed wall-clock intervals
+    Time(Duration),
+    /// A bar every `n` trades
+    Ticks(u64),
+    /// A bar once traded quantity reaches the threshold
+    Volume(f64),
+    /// A bar once traded notional (price times quantity times the contract
+    /// multiplier) reaches the threshold
+    Dollar(f64),
+}
+
+impl BarSpec {
+    pub fn kind(&self) -> BarKind {
+        match self {
+            BarSpec::Time(_) => BarKind::Time,
+            BarSpec::Ticks(_) => BarKind::Tick,
+            BarSpec::Volume(_) => BarKind::Volume,
+            BarSpec::Dollar(_) => BarKind::Dollar,
+        }
+    }
+}
+
+/// What a bust or correction did to the bars of a [`CandleAggregator`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarRevision {
+    /// The open bar holding the trade was adjusted and marked revised
+    Adjusted,
+    /// The open bar holding the trade was marked revised, but not
+    /// adjusted since the original terms are unknown
+    Flagged,
+    /// The trade is in a bar already emitted, which is now stale
+    Stale,
+}
+
+/// Which timestamp places trades in time bars
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BarClock {
+    /// The venue's trade time, so bars match the venue's own however late
+    /// trades are delivered
+    #[default]
+    Exchange,
+    /// When the trade was received, or its trade time if it was not stamped
+    Receive,
+}
+
+/// A bar being built, with the times of its earliest and latest trades so
+/// trades arriving out of order set the open and close right
+#[derive(Debug, Clone)]
+struct Bar {
+    candle: Candle,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+}
+
+impl Bar {
+    fn new(
+        trade: &Trade,
+        at: DateTime<Utc>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        kind: BarKind,
+    ) -> Self {
+        Self {
+            candle: open(trade, start, end, kind),
+            first: at,
+            last: at,
+        }
+    }
+
+    fn add(&mut self, trade: &Trade, at: DateTime<Utc>) {
+        let close = self.candle.close;
+        extend(&mut self.candle, trade);
+        if at < self.first {
+            self.candle.open = trade.price;
+            self.first = at;
+        }
+        if at >= self.last {
+            self.last = at;
+        } else {
+            self.candle.close = close;
+        }
+    }
+}
+
+/// Aggregates trades into candles per symbol, on fixed intervals by default
+/// or on tick, volume or dollar thresholds for configured symbols.
+///
+/// Threshold bars close on the trade that reaches the threshold, without
+/// splitting it, and span the times of their first and last trades.
+///
+/// Time bars are placed by exchange time unless another [`BarClock`] is
+/// chosen. A bar whose interval has passed can be held open for a grace
+/// period ([`with_grace`](Self::with_grace)) so trades delivered late still
+/// land in it; it closes once a trade or
+/// [`flush_until`](Self::flush_until) passes its end plus the grace. Trades
+/// for bars already emitted are dropped and counted in
+/// [`late_trades`](Self::late_trades), unless the bar is within the
+/// revision horizon ([`with_revisions`](Self::with_revisions)), in which
+/// case it is emitted again, marked revised.
+#[derive(Debug, Clone)]
+pub struct CandleAggregator {
+    interval: Duration,
+    specs: HashMap<Symbol, BarSpec>,
+    open: HashMap<Symbol, Bar>,
+    regular_only: bool,
+    clock: BarClock,
+    grace: Duration,
+    horizon: Duration,
+    /// Time bar before the open one, still taking late trades
+    closing: HashMap<Symbol, Bar>,
+    /// Time bars emitted within the revision horizon, oldest first
+    emitted: HashMap<Symbol, VecDeque<Candle>>,
+    /// Latest bar time of a trade per symbol
+    watermarks: HashMap<Symbol, DateTime<Utc>>,
+    /// End of the latest time bar emitted per symbol
+    emitted_until: HashMap<Symbol, DateTime<Utc>>,
+    late_trades: u64,
+}
+
+impl CandleAggregator {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            specs: HashMap::new(),
+            open: HashMap::new(),
+            regular_only: false,
+            clock: BarClock::Exchange,
+            grace: Duration::zero(),
+            horizon: Duration::zero(),
+            closing: HashMap::new(),
+            emitted: HashMap::new(),
+            watermarks: HashMap::new(),
+            emitted_until: HashMap::new(),
+            late_trades: 0,
+        }
+    }
+
+    /// Build bars for `symbol` by `spec` instead of the default interval
+    pub fn with_symbol_bars(mut self, symbol: &str, spec: BarSpec) -> Self {
+        self.specs.insert(symbol.into(), spec);
+        self
+    }
+
+    /// Leave trades with [`TradeConditions`](crate::types::TradeConditions)
+    /// set, such as block or auction prints, out of the bars
+    pub fn with_regular_only(mut self) -> Self {
+        self.regular_only = true;
+        self
+    }
+
+    /// Place trades in time bars by `clock` (default exchange time)
+    pub fn with_clock(mut self, clock: BarClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Keep time bars open for `grace` past their end for late trades
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Re-emit emitted time bars, marked revised, when trades for them
+    /// arrive up to `horizon` after their end
+    pub fn with_revisions(mut self, horizon: Duration) -> Self {
+        self.horizon = horizon;
+        self
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Bar type used for `symbol`
+    pub fn spec(&self, symbol: &str) -> BarSpec {
+        self.specs
+            .get(symbol)
+            .copied()
+            .unwrap_or(BarSpec::Time(self.interval))
+    }
+
+    /// Start of the default-interval bar containing `ts`
+    pub fn bar_start(&self, ts: DateTime<Utc>) -> DateTime<Utc> {
+        align(ts, self.interval)
+    }
+
+    /// Trades dropped because their bar was already emitted
+    pub fn late_trades(&self) -> u64 {
+        self.late_trades
+    }
+
+    /// Add a trade, returning the bar it completes: for time bars a
+    /// previous bar of its symbol once the trade moves past its end and
+    /// grace, or an emitted bar the trade revised; for threshold bars the
+    /// bar including the trade
+    pub fn update(&mut self, trade: &Trade) -> Option<Candle> {
+        if self.regular_only && !trade.conditions.is_regular() {
+            return None;
+        }
+        match self.spec(&trade.symbol) {
+            BarSpec::Time(interval) => self.update_timed(trade, interval),
+            spec => self.update_threshold(trade, spec),
+        }
+    }
+
+    fn update_timed(&mut self, trade: &Trade, interval: Duration) -> Option<Candle> {
+        let symbol = trade.symbol;
+        let at = match self.clock {
+            BarClock::Exchange => trade.timestamp,
+            BarClock::Receive => trade.received.unwrap_or(trade.timestamp),
+        };
+        let start = align(at, interval);
+        let watermark = *self
+            .watermarks
+            .entry(symbol)
+            .and_modify(|watermark| *watermark = (*watermark).max(at))
+            .or_insert(at);
+
+        let mut closed = None;
+        match (self.open.get_mut(&symbol), self.closing.get_mut(&symbol)) {
+            (Some(bar), _) if bar.candle.start == start => bar.add(trade, at),
+            (_, Some(bar)) if bar.candle.start == start => bar.add(trade, at),
+            (Some(bar), _) if bar.candle.start > start => return self.revise_late(trade, start),
+            // Flushed bars must not be opened again
+            _ if self
+                .emitted_until
+                .get(&symbol)
+                .is_some_and(|end| start < *end) =>
+            {
+                return self.revise_late(trade, start)
+            }
+            _ => {
+                let bar = Bar::new(trade, at, start, start + interval, BarKind::Time);
+                if let Some(previous) = self.open.insert(symbol, bar) {
+                    closed = self.closing.insert(symbol, previous);
+                }
+            }
+        }
+        let expired = self
+            .closing
+            .get(&symbol)
+            .is_some_and(|bar| bar.candle.end + self.grace <= watermark);
+        if closed.is_none() && expired {
+            closed = self.closing.remove(&symbol);
+        }
+        closed.map(|bar| self.emit(bar.candle))
+    }
+
+    /// Add a trade for a bar already emitted to it, if still remembered
+    fn revise_late(&mut self, trade: &Trade, start: DateTime<Utc>) -> Option<Candle> {
+        let emitted = self
+            .emitted
+            .get_mut(&trade.symbol)
+            .and_then(|bars| bars.iter_mut().find(|candle| candle.start == start));
+        let Some(candle) = emitted else {
+            self.late_trades += 1;
+            return None;
+        };
+        // The trade's place in the bar is unknown, so open and close stay
+        let close = candle.close;
+        extend(candle, trade);
+        candle.close = close;
+        candle.revised = true;
+        Some(candle.clone())
+    }
+
+    /// Remember an emitted time bar for revisions
+    fn emit(&mut self, candle: Candle) -> Candle {
+        let until = self
+            .emitted_until
+            .entry(candle.symbol)
+            .or_insert(candle.end);
+        *until = (*until).max(candle.end);
+        if self.horizon > Duration::zero() {
+            let watermark = self.watermarks.get(&candle.symbol).copied();
+            let bars = self.emitted.entry(candle.symbol).or_default();
+            bars.push_back(candle.clone());
+            if let Some(watermark) = watermark {
+                while bars
+                    .front()
+                    .is_some_and(|bar| bar.end + self.horizon < watermark)
+                {
+                    bars.pop_front();
+                }
+            }
+        }
+        candle
+    }
+
+    fn update_threshold(&mut self, trade: &Trade, spec: BarSpec) -> Option<Candle> {
+        let at = trade.timestamp;
+        let bar = match self.open.get_mut(&trade.symbol) {
+            Some(bar) => {
+                bar.add(trade, at);
+                bar.candle.end = bar.candle.end.max(at);
+                bar
+            }
+            None => self
+                .open
+                .entry(trade.symbol)
+                .or_insert_with(|| Bar::new(trade, at, at, at, spec.kind())),
+        };
+        let candle = &bar.candle;
+        let done = match spec {
+            BarSpec::Ticks(n) => candle.trade_count >= n,
+            BarSpec::Volume(threshold) => candle.volume >= threshold,
+            BarSpec::Dollar(threshold) => candle.notional >= threshold,
+            BarSpec::Time(_) => false,
+        };
+        if !done {
+            return None;
+        }
+        self.open.remove(&trade.symbol).map(|bar| bar.candle)
+    }
+
+    /// Close and return every time bar whose interval and grace ended at
+    /// or before `now`
+    pub fn flush_until(&mut self, now: DateTime<Utc>) -> Vec<Candle> {
+        let due = |bars: &HashMap<Symbol, Bar>| -> Vec<Symbol> {
+            bars.iter()
+                .filter(|(symbol, bar)| self.is_timed(symbol) && bar.candle.end + self.grace <= now)
+                .map(|(symbol, _)| *symbol)
+                .collect()
+        };
+        let (closing, open) = (due(&self.closing), due(&self.open));
+
+        let bars: Vec<Bar> = closing
+            .iter()
+            .filter_map(|symbol| self.closing.remove(symbol))
+            .chain(open.iter().filter_map(|symbol| self.open.remove(symbol)))
+            .collect();
+        let mut candles: Vec<Candle> = bars.into_iter().map(|bar| self.emit(bar.candle)).collect();
+        candles.sort_by(|a, b| a.end.cmp(&b.end).then_with(|| a.symbol.cmp(&b.symbol)));
+        candles
+    }
+
+    /// Earliest time at which a time bar currently being built closes
+    pub fn next_close(&self) -> Option<DateTime<Utc>> {
+        self.open
+            .iter()
+            .chain(&self.closing)
+            .filter(|(symbol, _)| self.is_timed(symbol))
+            .map(|(_, bar)| bar.candle.end + self.grace)
+            .min()
+    }
+
+    /// The bar currently being built for `symbol`
+    pub fn current(&self, symbol: &str) -> Option<&Candle> {
+        self.open.get(symbol).map(|bar| &bar.candle)
+    }
+
+    /// Take a busted trade out of the open bar holding it
+    pub fn bust(&mut self, bust: &TradeBust) -> BarRevision {
+        self.revise(
+            &bust.symbol,
+            bust.timestamp,
+            bust.original,
+            None,
+            bust.contract,
+        )
+    }
+
+    /// Replace a trade's terms in the open bar holding it
+    pub fn correct(&mut self, correction: &TradeCorrection) -> BarRevision {
+        self.revise(
+            &correction.symbol,
+            correction.timestamp,
+            correction.original,
+            Some(correction.corrected),
+            correction.contract,
+        )
+    }
+
+    fn revise(
+        &mut self,
+        symbol: &str,
+        at: DateTime<Utc>,
+        original: Option<TradeTerms>,
+        corrected: Option<TradeTerms>,
+        contract: Option<ContractSpec>,
+    ) -> BarRevision {
+        let timed = self.is_timed(symbol);
+        let holds = |bar: &&mut Bar| at >= bar.candle.start && !(timed && at >= bar.candle.end);
+        let bar = match self.open.get_mut(symbol).filter(holds) {
+            Some(bar) => bar,
+            None => match self.closing.get_mut(symbol).filter(holds) {
+                Some(bar) => bar,
+                None => return BarRevision::Stale,
+            },
+        };
+        let candle = &mut bar.candle;
+        candle.revised = true;
+        let Some(original) = original else {
+            return BarRevision::Flagged;
+        };
+        // The high and low may still reflect the original price
+        let multiplier = contract.map_or(1.0, |contract| contract.multiplier);
+        candle.volume -= original.quantity;
+        candle.notional -= original.price * original.quantity * multiplier;
+        candle.trade_count = candle.trade_count.saturating_sub(1);
+        if let Some(corrected) = corrected {
+            candle.volume += corrected.quantity;
+            candle.notional += corrected.price * corrected.quantity * multiplier;
+            candle.trade_count += 1;
+            candle.high = candle.high.max(corrected.price);
+            candle.low = candle.low.min(corrected.price);
+        }
+        BarRevision::Adjusted
+    }
+
+    fn is_timed(&self, symbol: &str) -> bool {
+        matches!(self.spec(symbol), BarSpec::Time(_))
+    }
+}
+
+fn open(trade: &Trade, start: DateTime<Utc>, end: DateTime<Utc>, bar_kind: BarKind) -> Candle {
+    Candle {
+        symbol: trade.symbol,
+        start,
+        end,
+        open: trade.price,
+        high: trade.price,
+        low: trade.price,
+        close: trade.price,
+        volume: trade.quantity,
+        notional: trade.notional(),
+        trade_count: 1,
+        bar_kind,
+        revised: false,
+    }
+}
+
+fn extend(candle: &mut Candle, trade: &Trade) {
+    candle.high = candle.high.max(trade.price);
+    candle.low = candle.low.min(trade.price);
+    candle.close = trade.price;
+    candle.volume += trade.quantity;
+    candle.notional += trade.notional();
+    candle.trade_count += 1;
+}
+
+/// Most bricks one trade completes; a larger gap skips the earlier ones
+const MAX_BRICKS_PER_TRADE: usize = 1000;
+
+/// Per-symbol state of a [`RenkoBuilder`]
+#[derive(Debug, Clone)]
+struct Bricks {
+    /// Bounds of the last completed brick
+    bottom: f64,
+    top: f64,
+    /// Trades since the last brick
+    pending: Candle,
+}
+
+/// Builds Renko bars: a brick of `brick_size` each time price closes a full
+/// brick beyond the previous one, so a reversal needs two bricks of travel.
+///
+/// Bricks span the trades since the previous brick; when one trade
+/// completes several bricks, its volume goes to the first. A gap of more
+/// than 1000 bricks emits only the last 1000.
+#[derive(Debug, Clone)]
+pub struct RenkoBuilder {
+    brick_size: f64,
+    bricks: HashMap<Symbol, Bricks>,
+}
+
+impl RenkoBuilder {
+    /// Build bricks of `brick_size`, which must be positive and finite
+    pub fn new(brick_size: f64) -> Result<Self> {
+        if !(brick_size > 0.0 && brick_size.is_finite()) {
+            return Err(ClientError::Parse(format!(
+                "renko brick size must be positive, got {}",
+                brick_size
+            )));
+        }
+        Ok(Self {
+            brick_size,
+            bricks: HashMap::new(),
+        })
+    }
+
+    /// Add a trade, returning the bricks it completes
+    pub fn update(&mut self, trade: &Trade) -> Vec<Candle> {
+        let Some(state) = self.bricks.get_mut(&trade.symbol) else {
+            // The first trade anchors the grid
+            let pending = open(trade, trade.timestamp, trade.timestamp, BarKind::Renko);
+            let state = Bricks {
+                bottom: trade.price,
+                top: trade.price,
+                pending,
+            };
+            self.bricks.insert(trade.symbol, state);
+            return Vec::new();
+        };
+        extend(&mut state.pending, trade);
+        state.pending.end = trade.timestamp;
+
+        let mut bricks = Vec::new();
+        // Tolerate float noise in decimal prices
+        let eps = self.brick_size * 1e-9;
+        let (gap, direction) = if trade.price > state.top {
+            (trade.price - state.top, 1.0)
+        } else {
+            (state.bottom - trade.price, -1.0)
+        };
+        let skipped = ((gap + eps) / self.brick_size).floor() - MAX_BRICKS_PER_TRADE as f64;
+        if skipped > 0.0 {
+            let shift = skipped * self.brick_size * direction;
+            state.top += shift;
+            state.bottom += shift;
+        }
+        loop {
+            let (open, close) = if trade.price >= state.top + self.brick_size - eps {
+                (state.top, state.top + self.brick_size)
+            } else if trade.price <= state.bottom - self.brick_size + eps {
+                (state.bottom, state.bottom - self.brick_size)
+            } else {
+                break;
+            };
+            let mut brick = state.pending.clone();
+            brick.open = open;
+            brick.close = close;
+            brick.high = open.max(close);
+            brick.low = open.min(close);
+            (state.bottom, state.top) = (brick.low, brick.high);
+            state.pending.volume = 0.0;
+            state.pending.notional = 0.0;
+            state.pending.trade_count = 0;
+            state.pending.start = trade.timestamp;
+            bricks.push(brick);
+        }
+        bricks
+    }
+}
+
+/// Builds range bars, each closing once its high-low range would exceed
+/// `range`; the trade that breaks out opens the next bar
+#[derive(Debug, Clone)]
+pub struct RangeBarBuilder {
+    range: f64,
+    open: HashMap<Symbol, Candle>,
+}
+
+impl RangeBarBuilder {
+    pub fn new(range: f64) -> Self {
+        Self {
+            range,
+            open: HashMap::new(),
+        }
+    }
+
+    /// Add a trade, returning the bar it closes
+    pub fn update(&mut self, trade: &Trade) -> Option<Candle> {
+        if let Some(candle) = self.open.get_mut(&trade.symbol) {
+            let high = candle.high.max(trade.price);
+            let low = candle.low.min(trade.price);
+            if high - low <= self.range * (1.0 + 1e-9) {
+                extend(candle, trade);
+                candle.end = trade.timestamp;
+                return None;
+            }
+        }
+        let candle = open(trade, trade.timestamp, trade.timestamp, BarKind::Range);
+        self.open.insert(trade.symbol, candle)
+    }
+
+    /// The bar currently being built for `symbol`
+    pub fn current(&self, symbol: &str) -> Option<&Candle> {
+        self.open.get(symbol)
+    }
+}
+
+/// Aggregates trades into footprint bars: the same candles as
+/// [`CandleAggregator`] plus buy and sell aggressor volume per price, with
+/// prices snapped to multiples of `price_step`.
+///
+/// Every closed bar is also published to receivers from
+/// [`subscribe`](Self::subscribe); as a pipeline [`Stage`] it passes
+/// messages through unchanged and closes time bars by their timestamps.
+#[derive(Debug, Clone)]
+pub struct FootprintAggregator {
+    bars: CandleAggregator,
+    price_step: f64,
+    regular_only: bool,
+    /// Buy and sell volume by price step for each open bar
+    levels: HashMap<Symbol, BTreeMap<i64, (f64, f64)>>,
+    cumulative_delta: HashMap<Symbol, f64>,
+    tx: broadcast::Sender<FootprintCandle>,
+}
+
+impl FootprintAggregator {
+    pub fn new(interval: Duration, price_step: f64) -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            bars: CandleAggregator::new(interval),
+            price_step,
+            regular_only: false,
+            levels: HashMap::new(),
+            cumulative_delta: HashMap::new(),
+            tx,
+        }
+    }
+
+    /// See [`CandleAggregator::with_symbol_bars`]
+    pub fn with_symbol_bars(mut self, symbol: &str, spec: BarSpec) -> Self {
+        self.bars = self.bars.with_symbol_bars(symbol, spec);
+        self
+    }
+
+    /// See [`CandleAggregator::with_regular_only`]
+    pub fn with_regular_only(mut self) -> Self {
+        self.bars = self.bars.with_regular_only();
+        self.regular_only = true;
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<FootprintCandle> {
+        self.tx.subscribe()
+    }
+
+    /// Feed a message, returning the bars it closes: trades as in
+    /// [`update`](Self::update), anything else timestamped as in
+    /// [`flush_until`](Self::flush_until)
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<FootprintCandle> {
+        match msg {
+            MarketDataMessage::Trade(trade) => self.update(trade).into_iter().collect(),
+            _ => msg
+                .timestamp()
+                .map(|ts| self.flush_until(ts))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Add a trade, returning the bar it completes as in
+    /// [`CandleAggregator::update`]
+    pub fn update(&mut self, trade: &Trade) -> Option<FootprintCandle> {
+        if self.regular_only && !trade.conditions.is_regular() {
+            return None;
+        }
+        // Threshold bars close including the trade, time bars before it
+        if !matches!(self.bars.spec(&trade.symbol), BarSpec::Time(_)) {
+            self.record(trade);
+            return self.bars.update(trade).map(|candle| self.close(candle));
+        }
+        let closed = self.bars.update(trade).map(|candle| self.close(candle));
+        self.record(trade);
+        closed
+    }
+
+    fn record(&mut self, trade: &Trade) {
+        let step = (trade.price / self.price_step).round() as i64;
+        let volume = self
+            .levels
+            .entry(trade.symbol)
+            .or_default()
+            .entry(step)
+            .or_default();
+        match trade.side {
+            TradeSide::Buy => volume.0 += trade.quantity,
+            TradeSide::Sell => volume.1 += trade.quantity,
+            TradeSide::Unknown => {}
+        }
+    }
+
+    /// Close and return every bar whose interval ended at or before `now`
+    pub fn flush_until(&mut self, now: DateTime<Utc>) -> Vec<FootprintCandle> {
+        self.bars
+            .flush_until(now)
+            .into_iter()
+            .map(|candle| self.close(candle))
+            .collect()
+    }
+
+    /// The bar currently being built for `symbol`
+    pub fn current(&self, symbol: &str) -> Option<FootprintCandle> {
+        let candle = self.bars.current(symbol)?.clone();
+        let levels = self.levels.get(symbol).cloned().unwrap_or_default();
+        let cumulative = self.cumulative_delta.get(symbol).copied().unwrap_or(0.0);
+        Some(Self::build(self.price_step, candle, levels, cumulative))
+    }
+
+    fn close(&mut self, candle: Candle) -> FootprintCandle {
+        let levels = self.levels.remove(&candle.symbol).unwrap_or_default();
+        let cumulative = self.cumulative_delta.entry(candle.symbol).or_default();
+        let footprint = Self::build(self.price_step, candle, levels, *cumulative);
+        *cumulative = footprint.cumulative_delta;
+        let _ = self.tx.send(footprint.clone());
+        footprint
+    }
+
+    fn build(
+        price_step: f64,
+        candle: Candle,
+        levels: BTreeMap<i64, (f64, f64)>,
+        cumulative: f64,
+    ) -> FootprintCandle {
+        let levels: Vec<FootprintLevel> = levels
+            .into_iter()
+            .map(|(step, (buy_volume, sell_volume))| FootprintLevel {
+                price: step as f64 * price_step,
+                buy_volume,
+                sell_volume,
+            })
+            .collect();
+        let delta = levels
+            .iter()
+            .map(|level| level.buy_volume - level.sell_volume)
+            .sum::<f64>();
+        FootprintCandle {
+            candle,
+            levels,
+            delta,
+            cumulative_delta: cumulative + delta,
+        }
+    }
+}
+
+impl Stage for FootprintAggregator {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Quote, TradeConditions, TradeSide};
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_bars_roll_over() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut aggregator = CandleAggregator::new(Duration::minutes(1));
+        let trade = |secs: i64, price: f64| Trade {
+            quantity: 2.0,
+            timestamp: t0 + Duration::seconds(secs),
+            trade_id: secs.to_string(),
+            ..Trade::test("BTCUSD", price)
+        };
+
+        assert!(aggregator.update(&trade(5, 100.0)).is_none());
+        assert!(aggregator.update(&trade(30, 105.0)).is_none());
+        assert!(aggregator.update(&trade(59, 95.0)).is_none());
+
+        let bar = aggregator.update(&trade(61, 101.0)).unwrap();
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.close),
+            (100.0, 105.0, 95.0, 95.0)
+        );
+        assert_eq!((bar.volume, bar.notional), (6.0, 600.0));
+        assert_eq!(bar.end, t0 + Duration::minutes(1));
+
+        let flushed = aggregator.flush_until(t0 + Duration::minutes(2));
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].open, 101.0);
+    }
+
+    #[test]
+    fn test_late_trades_join_or_revise_bars() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut aggregator = CandleAggregator::new(Duration::minutes(1))
+            .with_grace(Duration::seconds(10))
+            .with_revisions(Duration::minutes(5));
+        let trade = |secs: i64, price: f64| Trade {
+            timestamp: t0 + Duration::seconds(secs),
+            trade_id: secs.to_string(),
+            received: Some(t0 + Duration::seconds(secs + 3)),
+            ..Trade::test("BTCUSD", price)
+        };
+
+        for (secs, price) in [(5, 100.0), (59, 95.0), (61, 101.0), (30, 110.0)] {
+            assert!(aggregator.update(&trade(secs, price)).is_none());
+        }
+        let bar = aggregator.update(&trade(72, 102.0)).unwrap();
+        assert_eq!(
+            (bar.open, bar.high, bar.close, bar.trade_count),
+            (100.0, 110.0, 95.0, 3)
+        );
+        assert!(!bar.revised);
+
+        let revised = aggregator.update(&trade(40, 90.0)).unwrap();
+        assert_eq!(
+            (revised.start, revised.low, revised.close),
+            (t0, 90.0, 95.0)
+        );
+        assert!(revised.revised);
+
+        assert_eq!(aggregator.update(&trade(400, 103.0)).unwrap().open, 101.0);
+        assert!(aggregator.update(&trade(20, 99.0)).is_none());
+        assert_eq!(aggregator.late_trades(), 1);
+
+        // A trade for a bar flushed on the clock revises it too
+        let mut flushed =
+            CandleAggregator::new(Duration::minutes(1)).with_revisions(Duration::minutes(5));
+        flushed.update(&trade(10, 100.0));
+        assert_eq!(flushed.flush_until(t0 + Duration::seconds(61)).len(), 1);
+        let revised = flushed.update(&trade(30, 105.0)).unwrap();
+        assert_eq!((revised.high, revised.revised), (105.0, true));
+        assert!(flushed.current("BTCUSD").is_none());
+
+        let mut by_receipt =
+            CandleAggregator::new(Duration::minutes(1)).with_clock(BarClock::Receive);
+        by_receipt.update(&trade(58, 100.0));
+        assert_eq!(
+            by_receipt.current("BTCUSD").unwrap().start,
+            t0 + Duration::minutes(1)
+        );
+    }
+
+    #[test]
+    fn test_busts_and_corrections_revise_open_bar() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut aggregator = CandleAggregator::new(Duration::minutes(1));
+        let trade = |secs: i64, price: f64| Trade {
+            quantity: 10.0,
+            timestamp: t0 + Duration::seconds(secs),
+            trade_id: secs.to_string(),
+            ..Trade::test("AAPL", price)
+        };
+        let terms = |price| {
+            Some(TradeTerms {
+                price,
+                quantity: 10.0,
+                conditions: TradeConditions::empty(),
+            })
+        };
+        let bust = |secs: i64, original| TradeBust {
+            symbol: "AAPL".into(),
+            trade_id: secs.to_string(),
+            timestamp: t0 + Duration::seconds(secs),
+            original,
+            instrument_id: None,
+            contract: None,
+            received: None,
+        };
+        for (secs, price) in [(5, 100.0), (10, 101.0), (65, 102.0), (70, 103.0)] {
+            aggregator.update(&trade(secs, price));
+        }
+
+        assert_eq!(aggregator.bust(&bust(10, terms(101.0))), BarRevision::Stale);
+        assert_eq!(aggregator.bust(&bust(70, None)), BarRevision::Flagged);
+        let correction = TradeCorrection {
+            symbol: "AAPL".into(),
+            trade_id: "65".to_string(),
+            timestamp: t0 + Duration::seconds(65),
+            original: terms(102.0),
+            corrected: TradeTerms {
+                price: 104.0,
+                quantity: 5.0,
+                conditions: TradeConditions::empty(),
+            },
+            instrument_id: None,
+            contract: None,
+            received: None,
+        };
+        assert_eq!(aggregator.correct(&correction), BarRevision::Adjusted);
+
+        let bar = aggregator.current("AAPL").unwrap();
+        assert!(bar.revised);
+        assert_eq!((bar.volume, bar.trade_count, bar.high), (15.0, 2, 104.0));
+        assert_eq!(bar.notional, 520.0 + 1030.0);
+    }
+
+    #[test]
+    fn test_threshold_bars_per_symbol() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut aggregator = CandleAggregator::new(Duration::minutes(1))
+            .with_symbol_bars("BTCUSD", BarSpec::Ticks(3))
+            .with_symbol_bars("ETHUSD", BarSpec::Dollar(10_000.0));
+        let trade = |symbol: &str, secs: i64, price: f64| Trade {
+            quantity: 2.0,
+            timestamp: t0 + Duration::seconds(secs),
+            trade_id: secs.to_string(),
+            ..Trade::test(symbol, price)
+        };
+
+        assert!(aggregator.update(&trade("BTCUSD", 5, 100.0)).is_none());
+        assert!(aggregator.update(&trade("BTCUSD", 90, 102.0)).is_none());
+        let bar = aggregator.update(&trade("BTCUSD", 200, 101.0)).unwrap();
+        assert_eq!((bar.open, bar.high, bar.close), (100.0, 102.0, 101.0));
+        assert_eq!(
+            (bar.start, bar.end),
+            (t0 + Duration::seconds(5), t0 + Duration::seconds(200))
+        );
+
+        assert!(aggregator.update(&trade("ETHUSD", 1, 2400.0)).is_none());
+        let bar = aggregator.update(&trade("ETHUSD", 2, 2600.0)).unwrap();
+        assert_eq!(bar.trade_count, 2);
+        assert!(aggregator.current("ETHUSD").is_none());
+        assert!(aggregator.flush_until(t0 + Duration::hours(1)).is_empty());
+    }
+
+    #[test]
+    fn test_regular_only_skips_conditioned_trades() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 14, 30, 0).unwrap();
+        let mut aggregator = CandleAggregator::new(Duration::minutes(1)).with_regular_only();
+        let mut trade = Trade {
+            quantity: 5000.0,
+            timestamp: t0,
+            trade_id: "1".to_string(),
+            conditions: TradeConditions::AUCTION,
+            ..Trade::test("AAPL", 180.0)
+        };
+        aggregator.update(&trade);
+        assert!(aggregator.current("AAPL").is_none());
+
+        trade.price = 180.5;
+        trade.quantity = 100.0;
+        trade.conditions = TradeConditions::empty();
+        aggregator.update(&trade);
+        let bar = aggregator.current("AAPL").unwrap();
+        assert_eq!((bar.open, bar.volume), (180.5, 100.0));
+    }
+
+    #[test]
+    fn test_renko_and_range_bars() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let trade = |secs: i64, price: f64| Trade {
+            timestamp: t0 + Duration::seconds(secs),
+            trade_id: secs.to_string(),
+            ..Trade::test("BTCUSD", price)
+        };
+        let prices = [100.0, 104.0, 121.0, 112.0, 95.0];
+
+        assert!(RenkoBuilder::new(0.0).is_err());
+        assert!(RenkoBuilder::new(f64::NAN).is_err());
+        let mut renko = RenkoBuilder::new(10.0).unwrap();
+        let bricks: Vec<(f64, f64)> = prices
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &price)| renko.update(&trade(i as i64, price)))
+            .map(|brick| (brick.open, brick.close))
+            .collect();
+        // Up to 120, then the reversal needs to reach 100
+        assert_eq!(bricks, [(100.0, 110.0), (110.0, 120.0), (110.0, 100.0)]);
+        // A huge gap completes a bounded number of bricks, ending at the price
+        let bricks = renko.update(&trade(5, 1e12));
+        assert_eq!(bricks.len(), MAX_BRICKS_PER_TRADE);
+        assert!(1e12 - bricks.last().unwrap().close < 10.0);
+
+        let mut range = RangeBarBuilder::new(10.0);
+        let bars: Vec<Candle> = prices
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &price)| range.update(&trade(i as i64, price)))
+            .collect();
+        assert_eq!(bars.len(), 2);
+        assert_eq!(
+            (bars[0].low, bars[0].high, bars[0].trade_count),
+            (100.0, 104.0, 2)
+        );
+        assert_eq!((bars[1].open, bars[1].close), (121.0, 112.0));
+        assert_eq!(bars[1].bar_kind, BarKind::Range);
+    }
+
+    #[test]
+    fn test_footprint_levels_and_cumulative_delta() {
+        let t0 = Utc.with_ymd_and_hms(2024, 3, 1, 14, 30, 0).unwrap();
+        let mut footprint = FootprintAggregator::new(Duration::minutes(1), 0.25);
+        let mut rx = footprint.subscribe();
+        let trade = |secs: i64, price: f64, quantity: f64, side: TradeSide| Trade {
+            quantity,
+            side,
+            timestamp: t0 + Duration::seconds(secs),
+            trade_id: secs.to_string(),
+            ..Trade::test("ESH4", price)
+        };
+
+        footprint.update(&trade(1, 5125.25, 3.0, TradeSide::Buy));
+        footprint.update(&trade(2, 5125.25, 1.0, TradeSide::Sell));
+        footprint.update(&trade(3, 5125.0, 5.0, TradeSide::Sell));
+        let bar = footprint
+            .update(&trade(61, 5125.5, 2.0, TradeSide::Buy))
+            .unwrap();
+
+        assert_eq!(
+            bar.levels,
+            [
+                FootprintLevel {
+                    price: 5125.0,
+                    buy_volume: 0.0,
+                    sell_volume: 5.0
+                },
+                FootprintLevel {
+                    price: 5125.25,
+                    buy_volume: 3.0,
+                    sell_volume: 1.0
+                },
+            ]
+        );
+        assert_eq!((bar.delta, bar.cumulative_delta), (-3.0, -3.0));
+        assert_eq!(bar.candle.volume, 9.0);
+
+        assert_eq!(rx.try_recv().unwrap().delta, -3.0);
+
+        // Any later message closes the time bar as a stage
+        let mut out = Vec::new();
+        let quote = Quote {
+            timestamp: t0 + Duration::minutes(2),
+            ..Quote::test("ESH4", 5125.25, 5125.5)
+        };
+        footprint.process(MarketDataMessage::Quote(quote), &mut out);
+        let bar = rx.try_recv().unwrap();
+        assert_eq!((bar.delta, bar.cumulative_delta), (2.0, -1.0));
+        assert_eq!(out.len(), 1);
+    }
+}
@@ -0,0 +1,238 @@
+//! Replay-to-live switchover for graph sources.
+//!
+//! A [`CatchUpSource`] first yields history, such as a recording or a
+//! backfill, then continues with a live source. The live source should be
+//! subscribed before the replay starts so nothing is missed in between;
+//! its messages wait in its channel until the history is exhausted. Live
+//! messages the history already covered are dropped, symbol by symbol:
+//! those older than the symbol's last replayed timestamp by more than the
+//! overlap window, and within the window those matching a replayed message
+//! by trade id, or by type and timestamp otherwise. Downstream nodes see one continuous stream and
+//! need no changes to go from warm-up to live.
+
+use crate::graph::Source;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, MessageKind};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+
+/// Identity of a message for deduplicating the replay/live overlap
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum MessageKey {
+    Trade {
+        symbol: Symbol,
+        trade_id: String,
+    },
+    Other {
+        kind: MessageKind,
+        symbol: Symbol,
+        timestamp: DateTime<Utc>,
+    },
+}
+
+impl MessageKey {
+    fn of(msg: &MarketDataMessage) -> Option<Self> {
+        match msg {
+            MarketDataMessage::Trade(trade) if !trade.trade_id.is_empty() => {
+                Some(MessageKey::Trade {
+                    symbol: trade.symbol,
+                    trade_id: trade.trade_id.clone(),
+                })
+            }
+            _ => Some(MessageKey::Other {
+                kind: msg.kind(),
+                symbol: msg.symbol()?,
+                timestamp: msg.timestamp()?,
+            }),
+        }
+    }
+}
+
+/// Where the history of one symbol ends
+#[derive(Debug)]
+struct Seam {
+    /// Latest timestamp replayed
+    watermark: DateTime<Utc>,
+    /// Replayed messages within the overlap of the watermark
+    recent: Vec<(DateTime<Utc>, MessageKey)>,
+    seen: HashSet<MessageKey>,
+}
+
+/// History followed by live data, without duplicates at the seam
+pub struct CatchUpSource<H, L> {
+    history: Option<H>,
+    live: L,
+    overlap: Duration,
+    seams: HashMap<Symbol, Seam>,
+    replayed: u64,
+    duplicates: u64,
+}
+
+impl<H: Source, L: Source> CatchUpSource<H, L> {
+    pub fn new(history: H, live: L) -> Self {
+        Self {
+            history: Some(history),
+            live,
+            overlap: Duration::seconds(5),
+            seams: HashMap::new(),
+            replayed: 0,
+            duplicates: 0,
+        }
+    }
+
+    /// How far before the end of the history live messages are checked
+    /// against it (default 5s)
+    pub fn with_overlap(mut self, overlap: Duration) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Whether the history is exhausted and messages now come from live
+    pub fn is_live(&self) -> bool {
+        self.history.is_none()
+    }
+
+    /// Live messages dropped as already replayed
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    fn record_replayed(&mut self, msg: &MarketDataMessage) {
+        self.replayed += 1;
+        let (Some(symbol), Some(timestamp)) = (msg.symbol(), msg.timestamp()) else {
+            return;
+        };
+        let seam = self.seams.entry(symbol).or_insert_with(|| Seam {
+            watermark: timestamp,
+            recent: Vec::new(),
+            seen: HashSet::new(),
+        });
+        seam.watermark = seam.watermark.max(timestamp);
+        if let Some(key) = MessageKey::of(msg) {
+            seam.recent.push((timestamp, key));
+        }
+        // Trim now and then rather than per message
+        let since = seam.watermark - self.overlap;
+        if seam.recent.len() > 1024 && seam.recent[0].0 < since {
+            seam.recent.retain(|(at, _)| *at >= since);
+        }
+    }
+
+    fn go_live(&mut self) {
+        self.history = None;
+        for seam in self.seams.values_mut() {
+            let since = seam.watermark - self.overlap;
+            seam.seen = seam
+                .recent
+                .drain(..)
+                .filter(|(at, _)| *at >= since)
+                .map(|(_, key)| key)
+                .collect();
+        }
+        info!(
+            event = "catch_up",
+            replayed = self.replayed,
+            "Caught up after {} replayed messages, switching to live",
+            self.replayed
+        );
+    }
+
+    /// Whether a live message was already delivered from history
+    fn is_duplicate(&mut self, msg: &MarketDataMessage) -> bool {
+        let (Some(symbol), Some(timestamp)) = (msg.symbol(), msg.timestamp()) else {
+            return false;
+        };
+        let Some(seam) = self.seams.get(&symbol) else {
+            return false;
+        };
+        if timestamp > seam.watermark {
+            // Past the seam, nothing further of the symbol can repeat the
+            // history
+            self.seams.remove(&symbol);
+            return false;
+        }
+        timestamp < seam.watermark - self.overlap
+            || MessageKey::of(msg).is_some_and(|key| seam.seen.contains(&key))
+    }
+}
+
+impl<H: Source, L: Source> Source for CatchUpSource<H, L> {
+    async fn next_message(&mut self) -> Option<MarketDataMessage> {
+        if let Some(history) = self.history.as_mut() {
+            match history.next_message().await {
+                Some(msg) => {
+                    self.record_replayed(&msg);
+                    return Some(msg);
+                }
+                None => self.go_live(),
+            }
+        }
+        loop {
+            let msg = self.live.next_message().await?;
+            if !self.is_duplicate(&msg) {
+                return Some(msg);
+            }
+            self.duplicates += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::IterSource;
+    use crate::types::Trade;
+    use chrono::TimeZone;
+
+    fn trade(id: &str, second: u32) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, second).unwrap(),
+            trade_id: id.to_string(),
+            ..Trade::test("BTCUSD", 100.0)
+        })
+    }
+
+    fn id(msg: &MarketDataMessage) -> &str {
+        match msg {
+            MarketDataMessage::Trade(trade) => &trade.trade_id,
+            _ => "",
+        }
+    }
+
+    #[tokio::test]
+    async fn test_switches_to_live_without_duplicates() {
+        let eth = |id: &str, second| {
+            let mut msg = trade(id, second);
+            if let MarketDataMessage::Trade(trade) = &mut msg {
+                trade.symbol = "ETHUSD".into();
+            }
+            msg
+        };
+        let history = IterSource(
+            vec![trade("1", 0), eth("e1", 2), trade("2", 10), trade("3", 11)].into_iter(),
+        );
+        // Live starts before the end of the history and repeats part of it;
+        // ETHUSD's history ends earlier, so its live trade is new
+        let live = IterSource(
+            vec![
+                trade("0", 1),
+                eth("e2", 3),
+                trade("2", 10),
+                trade("3", 11),
+                trade("4", 11),
+                trade("5", 12),
+            ]
+            .into_iter(),
+        );
+        let mut source = CatchUpSource::new(history, live).with_overlap(Duration::seconds(5));
+
+        let mut ids = Vec::new();
+        while let Some(msg) = source.next_message().await {
+            ids.push(id(&msg).to_string());
+        }
+        assert_eq!(ids, ["1", "e1", "2", "3", "e2", "4", "5"]);
+        assert!(source.is_live());
+        assert_eq!(source.duplicates(), 3);
+    }
+}
@@ -0,0 +1,238 @@
+//! Fault injection for testing gap and recovery handling.
+//!
+//! A [`ChaosLayer`] delays, drops, duplicates and reorders messages at
+//! seeded random rates, and can force disconnects. As a pipeline
+//! [`Stage`] it disturbs a stream in process, the latency blocking the
+//! stage like a slow consumer would. Given to an
+//! [`ExchangeEmulator`](crate::emulator::ExchangeEmulator) with
+//! [`with_chaos`](crate::emulator::ExchangeEmulator::with_chaos), it
+//! disturbs every connection's feed, and disconnects close the socket
+//! without a close frame, so unmodified clients see what a flaky venue
+//! would do. The same seed always injects the same faults.
+//!
+//! Only built with the `chaos` feature.
+
+use crate::generator::SeededRng;
+use crate::pipeline::Stage;
+use crate::types::MarketDataMessage;
+use std::time::Duration;
+
+/// Faults injected so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChaosStats {
+    pub delayed: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+    pub disconnects: u64,
+}
+
+/// Seeded latency, drop, duplication, reordering and disconnect injection
+#[derive(Debug, Clone)]
+pub struct ChaosLayer {
+    rng: SeededRng,
+    latency: Option<(Duration, Duration)>,
+    latency_rate: f64,
+    drop_rate: f64,
+    duplicate_rate: f64,
+    reorder_rate: f64,
+    disconnect_rate: f64,
+    /// Message held back to be released after the next one
+    held: Option<MarketDataMessage>,
+    stats: ChaosStats,
+}
+
+impl ChaosLayer {
+    /// A layer injecting nothing until rates are set
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: SeededRng::new(seed),
+            latency: None,
+            latency_rate: 0.0,
+            drop_rate: 0.0,
+            duplicate_rate: 0.0,
+            reorder_rate: 0.0,
+            disconnect_rate: 0.0,
+            held: None,
+            stats: ChaosStats::default(),
+        }
+    }
+
+    /// Delay a `rate` fraction of messages by between `min` and `max`
+    pub fn with_latency(mut self, rate: f64, min: Duration, max: Duration) -> Self {
+        self.latency_rate = rate;
+        self.latency = Some((min, max.max(min)));
+        self
+    }
+
+    /// Drop a `rate` fraction of messages
+    pub fn with_drops(mut self, rate: f64) -> Self {
+        self.drop_rate = rate;
+        self
+    }
+
+    /// Deliver a `rate` fraction of messages twice
+    pub fn with_duplicates(mut self, rate: f64) -> Self {
+        self.duplicate_rate = rate;
+        self
+    }
+
+    /// Swap a `rate` fraction of messages with the one after them
+    pub fn with_reordering(mut self, rate: f64) -> Self {
+        self.reorder_rate = rate;
+        self
+    }
+
+    /// Force a disconnect before a `rate` fraction of messages
+    pub fn with_disconnects(mut self, rate: f64) -> Self {
+        self.disconnect_rate = rate;
+        self
+    }
+
+    /// An independent copy for another connection, with its own faults
+    pub fn fork(&self, stream: u64) -> Self {
+        let mut fork = self.clone();
+        fork.rng = SeededRng::new(self.rng.clone().next_u64() ^ stream);
+        fork.held = None;
+        fork.stats = ChaosStats::default();
+        fork
+    }
+
+    pub fn stats(&self) -> ChaosStats {
+        self.stats
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        rate > 0.0 && self.rng.next_f64() < rate
+    }
+
+    /// Latency to add before the next message, if any
+    pub fn delay(&mut self) -> Option<Duration> {
+        let (min, max) = self.latency?;
+        if !self.chance(self.latency_rate) {
+            return None;
+        }
+        self.stats.delayed += 1;
+        Some(min + (max - min).mul_f64(self.rng.next_f64()))
+    }
+
+    /// Whether to disconnect before the next message
+    pub fn disconnect(&mut self) -> bool {
+        let disconnect = self.chance(self.disconnect_rate);
+        if disconnect {
+            self.stats.disconnects += 1;
+            // Nothing held survives the connection
+            self.held = None;
+        }
+        disconnect
+    }
+
+    /// Push what is delivered in place of `msg`: nothing, it, it twice,
+    /// or a held back message after it
+    pub fn inject(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        if self.chance(self.drop_rate) {
+            self.stats.dropped += 1;
+            return;
+        }
+        if self.held.is_none() && self.chance(self.reorder_rate) {
+            self.stats.reordered += 1;
+            self.held = Some(msg);
+            return;
+        }
+        if self.chance(self.duplicate_rate) {
+            self.stats.duplicated += 1;
+            out.push(msg.clone());
+        }
+        out.push(msg);
+        out.extend(self.held.take());
+    }
+
+    /// Release a held back message at the end of the stream
+    pub fn flush(&mut self, out: &mut Vec<MarketDataMessage>) {
+        out.extend(self.held.take());
+    }
+}
+
+impl Stage for ChaosLayer {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        if let Some(delay) = self.delay() {
+            std::thread::sleep(delay);
+        }
+        self.inject(msg, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{EmulatedVenue, ExchangeEmulator};
+    use crate::types::Trade;
+
+    use futures_util::StreamExt;
+    use tokio::net::TcpListener;
+    use tokio::sync::broadcast;
+    use tokio_tungstenite::connect_async;
+
+    fn trade(id: u64) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            trade_id: id.to_string(),
+            ..Trade::test("BTCUSDT", 100.0)
+        })
+    }
+
+    fn ids(msgs: &[MarketDataMessage]) -> Vec<u64> {
+        msgs.iter()
+            .map(|msg| match msg {
+                MarketDataMessage::Trade(trade) => trade.trade_id.parse().unwrap(),
+                _ => panic!("expected trade"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_faults_are_seeded() {
+        let run = || {
+            let mut chaos = ChaosLayer::new(7)
+                .with_drops(0.1)
+                .with_duplicates(0.1)
+                .with_reordering(0.1);
+            let mut out = Vec::new();
+            for id in 0..1000 {
+                chaos.process(trade(id), &mut out);
+            }
+            chaos.flush(&mut out);
+            (ids(&out), chaos.stats())
+        };
+        let (ids, stats) = run();
+        assert_eq!(run(), (ids.clone(), stats));
+        assert!(stats.dropped > 50 && stats.duplicated > 50 && stats.reordered > 50);
+        assert_eq!(ids.len() as u64, 1000 - stats.dropped + stats.duplicated);
+        assert!(ids.windows(2).any(|w| w[0] > w[1]));
+        assert!(ids.windows(2).any(|w| w[0] == w[1]));
+    }
+
+    #[tokio::test]
+    async fn test_emulator_forces_disconnects() {
+        let (tx, rx) = broadcast::channel(64);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let emulator = ExchangeEmulator::new(EmulatedVenue::Binance, rx)
+            .with_chaos(ChaosLayer::new(1).with_disconnects(0.2));
+        tokio::spawn(async move { emulator.serve(listener).await });
+
+        let url = format!("ws://{}/ws/btcusdt@trade", addr);
+        let (mut ws, _) = connect_async(url.as_str()).await.unwrap();
+        // Wait for the connection to subscribe to the source
+        while tx.receiver_count() < 2 {
+            tokio::task::yield_now().await;
+        }
+        for id in 0..50 {
+            tx.send(trade(id)).unwrap();
+        }
+        let mut received = 0;
+        while let Some(Ok(_)) = ws.next().await {
+            received += 1;
+        }
+        assert!(received < 50);
+    }
+}
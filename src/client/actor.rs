@@ -0,0 +1,212 @@
+//! Supervision of the client's internal actors.
+//!
+//! A running client is a chain of tasks, each owning its state and reading a
+//! typed mailbox: the connection actor reads the socket and feeds frames to
+//! the parser actor, which decodes them and runs the pipeline; the router
+//! actor publishes the results to broadcast subscribers and hands them to
+//! sink actors such as batch delivery. Sink mailboxes are bounded and
+//! written without waiting, so a stalled sink loses its own messages rather
+//! than holding up ingestion. Actors that panic are restarted under a
+//! [`RestartPolicy`], reading on from the same mailbox; those that cannot be
+//! restarted, or keep failing, are reported and leave the client
+//! [`Health::Degraded`] instead of quietly ending the stream.
+
+use super::ClientEvent;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// What to do when an actor panics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Leave it stopped
+    Never,
+    /// Restart it, giving up after `max_restarts` within `window`
+    OnPanic { max_restarts: u32, window: Duration },
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::OnPanic {
+            max_restarts: 5,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Receiving end of a mailbox, kept across restarts of its actor
+pub(crate) type Mailbox<R> = Arc<Mutex<R>>;
+
+pub(crate) fn mailbox<R>(rx: R) -> Mailbox<R> {
+    Arc::new(Mutex::new(rx))
+}
+
+/// Whether every internal actor is running
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Health {
+    #[default]
+    Healthy,
+    /// These actors panicked and were not restarted; the stream may be
+    /// incomplete or have stopped
+    Degraded { failed: Vec<String> },
+}
+
+/// Root of the supervision tree: restarts actors under the restart policy
+/// and escalates the ones it gives up on
+#[derive(Clone)]
+pub(crate) struct Supervisor {
+    policy: RestartPolicy,
+    events: broadcast::Sender<ClientEvent>,
+    failed: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl Supervisor {
+    pub fn new(
+        policy: RestartPolicy,
+        events: broadcast::Sender<ClientEvent>,
+        failed: Arc<std::sync::Mutex<Vec<String>>>,
+    ) -> Self {
+        Self {
+            policy,
+            events,
+            failed,
+        }
+    }
+
+    /// Run the actor built by `start` until it returns, restarting it after
+    /// panics as the policy allows
+    pub fn spawn<F, Fut>(&self, actor: &'static str, mut start: F) -> JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let supervisor = self.clone();
+        tokio::spawn(async move {
+            let mut restarts: VecDeque<Instant> = VecDeque::new();
+            loop {
+                let Err(e) = tokio::spawn(start()).await else {
+                    break;
+                };
+                if !e.is_panic() {
+                    break;
+                }
+                let RestartPolicy::OnPanic {
+                    max_restarts,
+                    window,
+                } = supervisor.policy
+                else {
+                    error!(event = "actor", actor, "{} actor panicked", actor);
+                    supervisor.fail(actor);
+                    break;
+                };
+                let now = Instant::now();
+                while restarts
+                    .front()
+                    .is_some_and(|at| now.saturating_duration_since(*at) > window)
+                {
+                    restarts.pop_front();
+                }
+                if restarts.len() >= max_restarts as usize {
+                    error!(
+                        event = "actor",
+                        actor,
+                        "{} actor panicked {} times within {:?}, giving up",
+                        actor,
+                        restarts.len() + 1,
+                        window
+                    );
+                    supervisor.fail(actor);
+                    break;
+                }
+                restarts.push_back(now);
+                warn!(
+                    event = "actor",
+                    actor, "{} actor panicked, restarting", actor
+                );
+                let _ = supervisor.events.send(ClientEvent::ActorRestarted {
+                    actor: actor.to_string(),
+                    restarts: restarts.len() as u32,
+                });
+            }
+        })
+    }
+
+    /// Wait for an actor that cannot be restarted; true if it panicked
+    pub async fn watch(&self, actor: &'static str, task: JoinHandle<()>) -> bool {
+        match task.await {
+            Err(e) if e.is_panic() => {
+                error!(event = "actor", actor, "{} actor panicked", actor);
+                self.fail(actor);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Escalate a failed actor and mark the client degraded
+    pub fn fail(&self, actor: &str) {
+        let failed = {
+            let mut failed = self.failed.lock().unwrap();
+            failed.push(actor.to_string());
+            failed.clone()
+        };
+        let _ = self.events.send(ClientEvent::ActorFailed {
+            actor: actor.to_string(),
+        });
+        let _ = self.events.send(ClientEvent::Degraded { failed });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_restarts_until_limit() {
+        let (events, mut rx) = broadcast::channel(16);
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&runs);
+        let policy = RestartPolicy::OnPanic {
+            max_restarts: 2,
+            window: Duration::from_secs(60),
+        };
+        let failed = Arc::default();
+        let supervisor = Supervisor::new(policy, events, Arc::clone(&failed));
+        supervisor
+            .spawn("test", move || {
+                let counter = Arc::clone(&counter);
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    panic!("boom");
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        let restarted = |restarts| ClientEvent::ActorRestarted {
+            actor: "test".to_string(),
+            restarts,
+        };
+        assert_eq!(rx.try_recv().unwrap(), restarted(1));
+        assert_eq!(rx.try_recv().unwrap(), restarted(2));
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            ClientEvent::ActorFailed {
+                actor: "test".to_string()
+            }
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            ClientEvent::Degraded {
+                failed: vec!["test".to_string()]
+            }
+        );
+        assert_eq!(*failed.lock().unwrap(), ["test"]);
+    }
+}
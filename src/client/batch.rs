@@ -0,0 +1,214 @@
+//! Batched delivery to high-throughput consumers.
+
+use super::actor::Mailbox;
+use crate::memory::{Pool, PoolStats};
+use crate::types::MarketDataMessage;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::Instant;
+use tracing::warn;
+
+/// Batches that may be queued per consumer before new ones are dropped
+pub(crate) const BATCH_CHANNEL_CAPACITY: usize = 64;
+
+/// Idle batch buffers kept for reuse across all consumers
+const BATCH_POOL_SIZE: usize = 256;
+
+/// Messages the batch sink actor may have queued before the router drops
+/// them
+pub(crate) const BATCH_MAILBOX_CAPACITY: usize = 4096;
+
+struct BatchSink {
+    tx: mpsc::Sender<Vec<MarketDataMessage>>,
+    max_batch: usize,
+    max_delay: Duration,
+    buf: Vec<MarketDataMessage>,
+    deadline: Option<Instant>,
+    dropped: u64,
+}
+
+impl BatchSink {
+    /// Hand the buffered batch to the consumer; false once it has gone away
+    fn flush(&mut self, pool: &Pool<Vec<MarketDataMessage>>) -> bool {
+        self.deadline = None;
+        if self.buf.is_empty() {
+            return true;
+        }
+        let mut next = pool.get();
+        next.reserve(self.max_batch);
+        let batch = std::mem::replace(&mut self.buf, next);
+        match self.tx.try_send(batch) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(batch)) => {
+                self.dropped += batch.len() as u64;
+                warn!(
+                    "Batch consumer lagging, dropped {} messages ({} total)",
+                    batch.len(),
+                    self.dropped
+                );
+                pool.put(batch);
+                true
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+/// Registered batch consumers, fed by the message processing task
+pub(crate) struct BatchSinks {
+    sinks: Vec<BatchSink>,
+    pool: Arc<Pool<Vec<MarketDataMessage>>>,
+    /// Wakes the batch sink actor when a batch gets a deadline
+    deadline_set: Arc<Notify>,
+}
+
+impl Default for BatchSinks {
+    fn default() -> Self {
+        Self {
+            sinks: Vec::new(),
+            pool: Arc::new(Pool::new(BATCH_POOL_SIZE)),
+            deadline_set: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl BatchSinks {
+    pub fn add(
+        &mut self,
+        max_batch: usize,
+        max_delay: Duration,
+    ) -> mpsc::Receiver<Vec<MarketDataMessage>> {
+        let (tx, rx) = mpsc::channel(BATCH_CHANNEL_CAPACITY);
+        let max_batch = max_batch.max(1);
+        let mut buf = self.pool.get();
+        buf.reserve(max_batch);
+        self.sinks.push(BatchSink {
+            tx,
+            max_batch,
+            max_delay,
+            buf,
+            deadline: None,
+            dropped: 0,
+        });
+        rx
+    }
+
+    /// Buffer a message for every consumer, sending batches that are full
+    pub fn push(&mut self, msg: &MarketDataMessage) {
+        let now = Instant::now();
+        self.sinks.retain_mut(|sink| {
+            sink.buf.push(msg.clone());
+            if sink.deadline.is_none() {
+                sink.deadline = Some(now + sink.max_delay);
+                self.deadline_set.notify_one();
+            }
+            sink.buf.len() < sink.max_batch || sink.flush(&self.pool)
+        });
+    }
+
+    /// Send batches whose delay has expired
+    pub fn flush_expired(&mut self) {
+        let now = Instant::now();
+        self.sinks.retain_mut(|sink| {
+            sink.deadline.is_none_or(|deadline| deadline > now) || sink.flush(&self.pool)
+        });
+    }
+
+    /// Send every non-empty batch regardless of size or delay
+    pub fn flush_all(&mut self) {
+        self.sinks.retain_mut(|sink| sink.flush(&self.pool));
+    }
+
+    /// Return a delivered batch so its buffer can be reused
+    pub fn recycle(&self, batch: Vec<MarketDataMessage>) {
+        self.pool.put(batch);
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    /// Earliest time a partial batch must be sent
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.sinks.iter().filter_map(|sink| sink.deadline).min()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+}
+
+/// Batch sink actor: buffer routed messages and send batches when full or
+/// due, flushing everything once the router hangs up
+pub(crate) async fn run_batches(
+    sinks: Arc<Mutex<BatchSinks>>,
+    mailbox: Mailbox<mpsc::Receiver<MarketDataMessage>>,
+) {
+    let mut rx = mailbox.lock().await;
+    let deadline_set = Arc::clone(&sinks.lock().unwrap().deadline_set);
+    loop {
+        // Batches may also be pushed outside the actor, e.g. by a journal
+        // recovery, so a new deadline wakes the loop to recompute it
+        let deadline = sinks.lock().unwrap().next_deadline();
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => sinks.lock().unwrap().push(&msg),
+                None => break,
+            },
+            _ = deadline_set.notified() => {}
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)),
+                if deadline.is_some() =>
+            {
+                sinks.lock().unwrap().flush_expired();
+            }
+        }
+    }
+    sinks.lock().unwrap().flush_all();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::actor;
+
+    #[tokio::test]
+    async fn test_batches_by_size_and_delay() {
+        let mut sinks = BatchSinks::default();
+        let mut rx = sinks.add(2, Duration::from_millis(10));
+
+        for _ in 0..3 {
+            sinks.push(&MarketDataMessage::Heartbeat);
+        }
+        assert_eq!(rx.recv().await.unwrap().len(), 2);
+        assert!(rx.try_recv().is_err());
+
+        tokio::time::sleep_until(sinks.next_deadline().unwrap()).await;
+        sinks.flush_expired();
+        assert_eq!(rx.recv().await.unwrap().len(), 1);
+        assert!(sinks.next_deadline().is_none());
+
+        drop(rx);
+        sinks.push(&MarketDataMessage::Heartbeat);
+        sinks.flush_all();
+        // The closed consumer was removed and no longer buffers
+        sinks.push(&MarketDataMessage::Heartbeat);
+        assert!(sinks.next_deadline().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_actor_flushes_batches_pushed_outside_it() {
+        let sinks = Arc::new(Mutex::new(BatchSinks::default()));
+        let mut rx = sinks.lock().unwrap().add(10, Duration::from_millis(10));
+        let (tx, mailbox) = mpsc::channel(8);
+        let task = tokio::spawn(run_batches(Arc::clone(&sinks), actor::mailbox(mailbox)));
+        // Let the actor start waiting without a deadline
+        tokio::task::yield_now().await;
+
+        sinks.lock().unwrap().push(&MarketDataMessage::Heartbeat);
+        let batch = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await;
+        assert_eq!(batch.unwrap().unwrap().len(), 1);
+        drop(tx);
+        task.await.unwrap();
+    }
+}
@@ -1,12 +1,48 @@
+mod actor;
+mod batch;
+mod processor;
+#[cfg(feature = "sse")]
+mod sse;
+
+pub use self::actor::{Health, RestartPolicy};
+pub use self::processor::{ProcessingMode, WaitStrategy};
+
+use crate::adapters::{Adapter, NativeAdapter};
+use crate::bandwidth::BandwidthStats;
+use crate::book::BookDepth;
+use crate::baseline::{Anomaly, BaselineLearner};
+use crate::breaker::ParseBreaker;
+use crate::hydrate::Hydrate;
+use crate::overload::OverloadController;
+use crate::burst::{BurstDetector, BurstStats};
+use crate::clock::{ClockSource, SystemClock};
+use crate::control::{self, ControlCommand, ControlHandle};
+use self::actor::Supervisor;
+use self::batch::BatchSinks;
+use self::processor::{Dispatcher, FrameProcessor, FrameSink, Router, SharedState, Smoother};
+use crate::journal::Journal;
+use crate::lvc::{SyncHandle, SyncSnapshot};
+use crate::memory::PoolStats;
+use crate::pipeline::{Pipeline, Stage};
+use crate::qos::{QosReport, QosTracker};
+use crate::symbology::Venue;
 use crate::types::MarketDataMessage;
+use crate::validation::{ValidationFailure, Validator};
 use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
-use tokio::sync::broadcast;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio::sync::{broadcast, mpsc, watch, Mutex};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::connect_async;
 use tracing::{debug, error, info, warn};
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum ClientError {
     #[error("WebSocket error: {0}")]
     WebSocket(String),
@@ -16,26 +52,414 @@ pub enum ClientError {
     
     #[error("Parse error: {0}")]
     Parse(String),
+
+    #[error("Control error: {0}")]
+    Control(String),
+
+    #[error("I/O error: {0}")]
+    Io(String),
 }
 
 pub type Result<T> = std::result::Result<T, ClientError>;
 
+/// Notable changes in client state, delivered on
+/// [`events`](MarketDataClient::events)
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// Opening the WebSocket connection
+    Connecting,
+    /// Connected and subscription frames sent
+    Connected,
+    /// The venue confirmed a subscription request
+    SubscriptionAck,
+    /// The connection was lost or closed by the server
+    Disconnected { reason: String },
+    /// About to retry the connection, counting from 1
+    Reconnecting { attempt: u32 },
+    /// The processing task has exited
+    Stopped,
+    /// The adapter's parse error rate tripped the parse breaker
+    ParserDegraded { adapter: String, error_rate: f64 },
+    /// The adapter is parsing frames again
+    ParserRecovered { adapter: String },
+    /// An internal actor panicked and was restarted, the `restarts`th time
+    /// within its restart window
+    ActorRestarted { actor: String, restarts: u32 },
+    /// An internal actor panicked and will not be restarted
+    ActorFailed { actor: String },
+    /// The client is running without the actors listed, e.g. delivering
+    /// nothing after its router failed
+    Degraded { failed: Vec<String> },
+    /// The `sink` actor had no room for messages and dropped them,
+    /// `dropped` in total; sent as the count reaches each power of two
+    SinkLagging { sink: String, dropped: u64 },
+    /// A frame took `latency_ms` to dispatch, over the overload budget;
+    /// quotes are conflated and books truncated until it clears
+    OverloadActive { latency_ms: f64 },
+    /// Processing is back within budget at full fidelity
+    OverloadCleared,
+    /// A message rate or volume unusual for the time of day
+    Anomaly { venue: Venue, anomaly: Anomaly },
+    /// A message broke an invariant checked by the client's validator
+    ValidationFailed {
+        venue: Venue,
+        failure: ValidationFailure,
+    },
+}
+
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// Upper bound on the exponential reconnect backoff
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Outgoing half of a feed connection
+type WsWrite = std::pin::Pin<Box<dyn futures_util::Sink<Message, Error = WsError> + Send>>;
+/// Incoming half of a feed connection; SSE events arrive as text messages
+type WsRead = std::pin::Pin<
+    Box<dyn futures_util::Stream<Item = std::result::Result<Message, WsError>> + Send>,
+>;
+
 /// WebSocket client for market data streaming
 pub struct MarketDataClient {
     url: String,
     broadcast_tx: broadcast::Sender<MarketDataMessage>,
+    raw_tx: broadcast::Sender<String>,
+    events_tx: broadcast::Sender<ClientEvent>,
     running: Arc<tokio::sync::Mutex<bool>>,
+    /// Set by [`stop`](Self::stop) to wake the connection actor at once
+    shutdown: watch::Sender<bool>,
+    control_tx: mpsc::Sender<ControlCommand>,
+    control_rx: Arc<Mutex<Option<mpsc::Receiver<ControlCommand>>>>,
+    journal: Option<Arc<std::sync::Mutex<Journal>>>,
+    adapter: Arc<std::sync::Mutex<Box<dyn Adapter>>>,
+    /// Book levels kept per symbol, in the order they were set
+    book_depths: Vec<(String, BookDepth)>,
+    pipeline: Arc<std::sync::Mutex<Pipeline>>,
+    batches: Arc<std::sync::Mutex<BatchSinks>>,
+    mode: ProcessingMode,
+    bandwidth: Arc<std::sync::Mutex<BandwidthStats>>,
+    bursts: Arc<std::sync::Mutex<BurstDetector>>,
+    burst_detection: bool,
+    /// Dispatch rate limit (messages per second) and queue capacity
+    smoothing: Option<(f64, usize)>,
+    breaker: Arc<std::sync::Mutex<ParseBreaker>>,
+    overload: Option<Arc<std::sync::Mutex<OverloadController>>>,
+    baseline: Option<Arc<std::sync::Mutex<BaselineLearner>>>,
+    validator: Option<Arc<std::sync::Mutex<Validator>>>,
+    /// Maximum reconnect attempts and initial backoff
+    reconnect: Option<(u32, Duration)>,
+    qos: Arc<std::sync::Mutex<QosTracker>>,
+    clock: Arc<dyn ClockSource>,
+    sync: Option<SyncHandle>,
+    restart_policy: RestartPolicy,
+    /// Actors the supervisor gave up on since the last start
+    failed: Arc<std::sync::Mutex<Vec<String>>>,
 }
 
 impl MarketDataClient {
     pub fn new(url: String, buffer_size: usize) -> Self {
         let (broadcast_tx, _) = broadcast::channel(buffer_size);
+        let (control_tx, control_rx) = mpsc::channel(32);
+        let (raw_tx, _) = broadcast::channel(buffer_size);
+        let (events_tx, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
         
         Self {
             url,
             broadcast_tx,
+            raw_tx,
+            events_tx,
             running: Arc::new(tokio::sync::Mutex::new(false)),
+            shutdown: watch::channel(false).0,
+            control_tx,
+            control_rx: Arc::new(Mutex::new(Some(control_rx))),
+            journal: None,
+            adapter: Arc::new(std::sync::Mutex::new(Box::new(NativeAdapter::new()))),
+            book_depths: Vec::new(),
+            pipeline: Arc::new(std::sync::Mutex::new(Pipeline::new())),
+            batches: Arc::new(std::sync::Mutex::new(BatchSinks::default())),
+            mode: ProcessingMode::default(),
+            bandwidth: Arc::default(),
+            bursts: Arc::new(std::sync::Mutex::new(BurstDetector::new(
+                Duration::from_millis(10),
+                usize::MAX,
+            ))),
+            burst_detection: false,
+            smoothing: None,
+            breaker: Arc::default(),
+            overload: None,
+            baseline: None,
+            validator: None,
+            reconnect: None,
+            qos: Arc::default(),
+            clock: Arc::new(SystemClock),
+            sync: None,
+            restart_policy: RestartPolicy::default(),
+            failed: Arc::default(),
+        }
+    }
+
+    /// Speak a venue-specific protocol instead of the native message format
+    pub fn with_adapter(self, adapter: impl Adapter + 'static) -> Self {
+        let mut adapter: Box<dyn Adapter> = Box::new(adapter);
+        for (symbol, depth) in &self.book_depths {
+            adapter.set_book_depth(symbol, *depth);
+        }
+        *self.adapter.lock().unwrap() = adapter;
+        self
+    }
+
+    /// Only keep the top levels of `symbol`'s book. Every source's book
+    /// snapshots are truncated to `depth`; adapters with several book
+    /// channels also subscribe to the cheapest one covering it.
+    pub fn with_book_depth(mut self, symbol: &str, depth: BookDepth) -> Self {
+        self.adapter.lock().unwrap().set_book_depth(symbol, depth);
+        self.book_depths.push((symbol.to_string(), depth));
+        self
+    }
+
+    /// Take receive timestamps from `clock` instead of the system clock,
+    /// e.g. a PTP-synchronized NIC clock
+    pub fn with_clock(mut self, clock: impl ClockSource + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Append a processing stage run on every message before it is broadcast
+    pub fn with_stage(self, stage: impl Stage + 'static) -> Self {
+        self.pipeline.lock().unwrap().push(stage);
+        self
+    }
+
+    /// Choose where frames are parsed and dispatched, e.g. a dedicated
+    /// pinned thread for latency-sensitive consumers
+    pub fn with_processing_mode(mut self, mode: ProcessingMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Restart the parser, router and sink actors after a panic under
+    /// `policy`; the dedicated processing thread is never restarted
+    pub fn with_restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// Count microbursts of more than `threshold` frames within `window`
+    pub fn with_burst_detection(mut self, window: Duration, threshold: usize) -> Self {
+        *self.bursts.lock().unwrap() = BurstDetector::new(window, threshold);
+        self.burst_detection = true;
+        self
+    }
+
+    /// Queue processed messages and release them to subscribers at no more
+    /// than `max_per_second`. Messages arriving while `capacity` are queued
+    /// are dropped and counted in [`burst_stats`](Self::burst_stats).
+    pub fn with_smoothing(mut self, max_per_second: f64, capacity: usize) -> Self {
+        self.smoothing = Some((max_per_second, capacity.max(1)));
+        self
+    }
+
+    /// Replace the default parse breaker, which trips when half the frames
+    /// in a second fail to parse
+    pub fn with_parse_breaker(self, breaker: ParseBreaker) -> Self {
+        *self.breaker.lock().unwrap() = breaker;
+        self
+    }
+
+    /// Shed quote and book detail whenever processing falls behind the
+    /// controller's latency budget
+    pub fn with_overload_control(mut self, controller: OverloadController) -> Self {
+        self.overload = Some(Arc::new(std::sync::Mutex::new(controller)));
+        self
+    }
+
+    /// Learn normal message rates and volumes per symbol and time of day,
+    /// reporting deviations as [`ClientEvent::Anomaly`]
+    pub fn with_anomaly_detection(mut self, learner: BaselineLearner) -> Self {
+        self.baseline = Some(Arc::new(std::sync::Mutex::new(learner)));
+        self
+    }
+
+    /// Check every decoded message against `validator`'s invariants,
+    /// reporting failures as [`ClientEvent::ValidationFailed`]
+    pub fn with_validation(mut self, validator: Validator) -> Self {
+        self.validator = Some(Arc::new(std::sync::Mutex::new(validator)));
+        self
+    }
+
+    /// Reconnect after the connection drops, up to `max_attempts` times in a
+    /// row, doubling the delay from `backoff` after each failed attempt
+    pub fn with_reconnect(mut self, max_attempts: u32, backoff: Duration) -> Self {
+        self.reconnect = Some((max_attempts, backoff));
+        self
+    }
+
+    /// Count a feed gap in the QoS stats when no frame arrives for
+    /// `threshold` while connected (default 5 seconds)
+    pub fn with_gap_threshold(self, threshold: Duration) -> Self {
+        *self.qos.lock().unwrap() = QosTracker::new(threshold);
+        self
+    }
+
+    /// Keep a last value cache of every broadcast message so late joiners
+    /// can start from current state with
+    /// [`subscribe_synced`](Self::subscribe_synced)
+    pub fn with_late_join_sync(mut self) -> Self {
+        self.sync = Some(SyncHandle::new(self.broadcast_tx.clone()));
+        self
+    }
+
+    /// Microburst and smoothing queue statistics
+    pub fn burst_stats(&self) -> BurstStats {
+        self.bursts.lock().unwrap().stats()
+    }
+
+    /// Bytes received on this connection, per channel and per symbol
+    pub fn bandwidth_stats(&self) -> BandwidthStats {
+        self.bandwidth.lock().unwrap().clone()
+    }
+
+    /// Bandwidth counters in Prometheus text format, labelled with the URL
+    pub fn bandwidth_metrics(&self) -> String {
+        self.bandwidth.lock().unwrap().to_prometheus(&self.url)
+    }
+
+    /// Uptime, gaps, rates, latency and parse errors of the current QoS
+    /// window, labelled with the URL
+    pub fn qos_stats(&self) -> QosReport {
+        self.qos
+            .lock()
+            .unwrap()
+            .report(&self.url, std::time::Instant::now())
+    }
+
+    /// Degraded while an internal actor is down for good
+    pub fn health(&self) -> Health {
+        let failed = self.failed.lock().unwrap();
+        if failed.is_empty() {
+            Health::Healthy
+        } else {
+            Health::Degraded {
+                failed: failed.clone(),
+            }
+        }
+    }
+
+    pub(crate) fn failed_actors(&self) -> Arc<std::sync::Mutex<Vec<String>>> {
+        Arc::clone(&self.failed)
+    }
+
+    pub(crate) fn qos_tracker(&self) -> Arc<std::sync::Mutex<QosTracker>> {
+        Arc::clone(&self.qos)
+    }
+
+    pub(crate) fn bandwidth_tracker(&self) -> Arc<std::sync::Mutex<BandwidthStats>> {
+        Arc::clone(&self.bandwidth)
+    }
+
+    pub(crate) fn burst_detector(&self) -> Arc<std::sync::Mutex<BurstDetector>> {
+        Arc::clone(&self.bursts)
+    }
+
+    /// Journal every received frame before it is processed; the journal is
+    /// truncated as frames are delivered to subscribers
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(Arc::new(std::sync::Mutex::new(journal)));
+        self
+    }
+
+    /// Book levels kept per upper-cased symbol, the last setting winning
+    fn depth_limits(&self) -> HashMap<String, BookDepth> {
+        self.book_depths
+            .iter()
+            .map(|(symbol, depth)| (symbol.to_ascii_uppercase(), *depth))
+            .collect()
+    }
+
+    /// Replay all frames from the journal to current subscribers.
+    ///
+    /// Call this before [`start`](Self::start) after a restart so that frames
+    /// received before a crash are processed. Frames whose messages all
+    /// reached a subscriber are cut from the journal, up to the first one
+    /// that did not, so the next restart only replays what was missed.
+    /// Returns the number of messages broadcast.
+    pub async fn recover(&self) -> Result<usize> {
+        let Some(journal) = &self.journal else {
+            return Ok(0);
+        };
+        let path = journal.lock().unwrap().path().to_path_buf();
+
+        let depths = self.depth_limits();
+        let mut replayed = 0;
+        let mut undelivered = 0;
+        // End of the frames before the first one with an undelivered message
+        let mut delivered_through = 0;
+        let mut decoded = Vec::new();
+        let mut out = Vec::new();
+        let mut frames = Journal::recover(&path)?;
+        while let Some(frame) = frames.next() {
+            let mut frame = frame?;
+            let received = self.clock.now();
+            if let Err(e) = self
+                .adapter
+                .lock()
+                .unwrap()
+                .decode(&mut frame, received, &mut decoded) {
+                warn!("Skipping unparseable journal frame: {}", e);
+                if undelivered == 0 {
+                    delivered_through = frames.offset();
+                }
+                continue;
+            }
+            for mut msg in decoded.drain(..) {
+                msg.set_received(received);
+                if let MarketDataMessage::OrderBook(book) = &mut msg {
+                    processor::limit_depth(&depths, book);
+                }
+                self.pipeline.lock().unwrap().process(msg, &mut out);
+            }
+            for msg in out.drain(..) {
+                self.batches.lock().unwrap().push(&msg);
+                let sent = match &self.sync {
+                    Some(sync) => sync.publish(msg),
+                    None => self.broadcast_tx.send(msg).map_err(Box::new),
+                };
+                match sent {
+                    Ok(_) => replayed += 1,
+                    Err(_) => undelivered += 1,
+                }
+            }
+            if undelivered == 0 {
+                delivered_through = frames.offset();
+            }
+        }
+
+        self.batches.lock().unwrap().flush_all();
+        if undelivered > 0 {
+            warn!(
+                "{} recovered messages had no subscriber, keeping their frames in {}",
+                undelivered,
+                path.display()
+            );
         }
+        // Frames up to the first undelivered message are not replayed again
+        blocking(journal, move |journal| journal.discard_before(delivered_through)).await?;
+        info!("Recovered {} messages from {}", replayed, path.display());
+        Ok(replayed)
+    }
+
+    /// Warm up the pipeline's stages on historical messages, discarding
+    /// their output, so indicators are current from the first live
+    /// message. Returns the number of messages replayed.
+    pub fn hydrate(&self, history: impl IntoIterator<Item = MarketDataMessage>) -> usize {
+        let replayed = self.pipeline.lock().unwrap().hydrate(history);
+        info!("Hydrated pipeline with {} historical messages", replayed);
+        replayed
+    }
+
+    /// Handle for issuing runtime admin commands to this client
+    pub fn control(&self) -> ControlHandle {
+        ControlHandle::new(self.control_tx.clone())
     }
 
     /// Subscribe to market data stream
@@ -43,6 +467,57 @@ impl MarketDataClient {
         self.broadcast_tx.subscribe()
     }
 
+    /// Current last trade, quote, book and stats per symbol, and a receiver
+    /// starting right after them. The snapshot is empty unless
+    /// [`with_late_join_sync`](Self::with_late_join_sync) was set.
+    pub fn subscribe_synced(&self) -> (SyncSnapshot, broadcast::Receiver<MarketDataMessage>) {
+        match &self.sync {
+            Some(sync) => sync.subscribe(),
+            None => (SyncSnapshot::default(), self.subscribe()),
+        }
+    }
+
+    /// Cache and channel for serving late joiners elsewhere, such as a
+    /// [`FanOutServer`](crate::FanOutServer)
+    pub fn sync_handle(&self) -> Option<SyncHandle> {
+        self.sync.clone()
+    }
+
+    /// Subscribe to connection lifecycle and parser health events
+    pub fn events(&self) -> broadcast::Receiver<ClientEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Subscribe to frames the adapter could not parse, forwarded verbatim
+    /// while a parse breaker with raw passthrough is open
+    pub fn subscribe_raw(&self) -> broadcast::Receiver<String> {
+        self.raw_tx.subscribe()
+    }
+
+    /// Subscribe to the stream in chunks of up to `max_batch` messages.
+    ///
+    /// A partial batch is delivered once its first message is `max_delay`
+    /// old. Batches are dropped with a warning if the consumer falls more than
+    /// a few dozen batches behind.
+    pub fn subscribe_batched(
+        &self,
+        max_batch: usize,
+        max_delay: Duration,
+    ) -> mpsc::Receiver<Vec<MarketDataMessage>> {
+        self.batches.lock().unwrap().add(max_batch, max_delay)
+    }
+
+    /// Hand a batch back once processed so its buffer is reused for later
+    /// batches instead of being reallocated
+    pub fn recycle_batch(&self, batch: Vec<MarketDataMessage>) {
+        self.batches.lock().unwrap().recycle(batch);
+    }
+
+    /// Reuse counters for batch buffers
+    pub fn batch_pool_stats(&self) -> PoolStats {
+        self.batches.lock().unwrap().pool_stats()
+    }
+
     /// Start streaming market data
     pub async fn start(&self) -> Result<()> {
         let mut running = self.running.lock().await;
@@ -50,72 +525,297 @@ impl MarketDataClient {
             warn!("Client already running");
             return Ok(());
         }
+        // Taken before connecting, so a client whose previous run has not
+        // handed it back yet fails without opening a socket
+        let control_slot = Arc::clone(&self.control_rx);
+        let Some(mut control_rx) = control_slot.lock().await.take() else {
+            return Err(ClientError::Control("control receiver in use".to_string()));
+        };
         *running = true;
         drop(running);
+        self.shutdown.send_replace(false);
+        let mut shutdown = self.shutdown.subscribe();
 
-        info!("Connecting to {}", self.url);
+        let subscribe_frames = self.adapter.lock().unwrap().subscribe_frames();
+        let headers = self.adapter.lock().unwrap().connect_headers();
+        let mut qos_events = Some(self.events_tx.subscribe());
+        let (mut write, mut read) = match connect(&self.url, &headers, &subscribe_frames, &self.events_tx).await {
+            Ok(halves) => halves,
+            Err(e) => {
+                *control_slot.lock().await = Some(control_rx);
+                *self.running.lock().await = false;
+                return Err(e);
+            }
+        };
 
-        let (ws_stream, _) = connect_async(&self.url)
-            .await
-            .map_err(|e| ClientError::Connection(e.to_string()))?;
+        self.failed.lock().unwrap().clear();
+        let supervisor = Supervisor::new(
+            self.restart_policy,
+            self.events_tx.clone(),
+            Arc::clone(&self.failed),
+        );
 
-        info!("Connected successfully");
+        let qos = Arc::clone(&self.qos);
+        let events = self.events_tx.clone();
+        supervisor.spawn("qos", move || {
+            // A restarted tracker misses the events sent while it was down
+            let rx = qos_events.take().unwrap_or_else(|| events.subscribe());
+            qos.clear_poison();
+            run_qos(rx, Arc::clone(&qos))
+        });
 
-        let (mut write, mut read) = ws_stream.split();
-        let broadcast_tx = self.broadcast_tx.clone();
         let running = Arc::clone(&self.running);
+        let journal = self.journal.clone();
+        let state = SharedState::default();
 
-        // Send subscription message
-        let subscribe_msg = serde_json::json!({
-            "type": "subscribe",
-            "channels": ["trades", "quotes", "orderbook"]
+        let (batch_tx, batch_rx) = mpsc::channel(batch::BATCH_MAILBOX_CAPACITY);
+        let batch_mailbox = actor::mailbox(batch_rx);
+        let batch_sinks = Arc::clone(&self.batches);
+        supervisor.spawn("batches", move || {
+            batch_sinks.clear_poison();
+            batch::run_batches(Arc::clone(&batch_sinks), Arc::clone(&batch_mailbox))
         });
-        
-        write
-            .send(Message::Text(subscribe_msg.to_string()))
-            .await
-            .map_err(|e| ClientError::WebSocket(e.to_string()))?;
 
-        // Spawn message processing task
-        tokio::spawn(async move {
-            while *running.lock().await {
-                match read.next().await {
-                    Some(Ok(Message::Text(text))) => {
-                        debug!("Received message: {}", text);
-                        
-                        match serde_json::from_str::<MarketDataMessage>(&text) {
-                            Ok(msg) => {
-                                if let Err(e) = broadcast_tx.send(msg) {
-                                    error!("Failed to broadcast message: {}", e);
+        let (router_tx, router_rx) = mpsc::channel(processor::ROUTER_MAILBOX_CAPACITY);
+        let router_mailbox = actor::mailbox(router_rx);
+        let router = Router {
+            broadcast_tx: self.broadcast_tx.clone(),
+            raw_tx: self.raw_tx.clone(),
+            sync: self.sync.clone(),
+            batch_sinks: Arc::clone(&self.batches),
+            batches: batch_tx,
+            dropped: 0,
+            events: self.events_tx.clone(),
+            delivered: Arc::clone(&state.delivered),
+        };
+        supervisor.spawn("router", move || {
+            processor::run_router(router.clone(), Arc::clone(&router_mailbox))
+        });
+        let dispatcher = Dispatcher { tx: router_tx };
+        let smoother = self.smoothing.map(|(rate, capacity)| {
+            let (tx, rx) = mpsc::channel(capacity);
+            let mailbox = actor::mailbox(rx);
+            let dispatcher = dispatcher.clone();
+            supervisor.spawn("smoother", move || {
+                processor::run_smoother(Arc::clone(&mailbox), rate, dispatcher.clone())
+            });
+            Smoother {
+                tx,
+                stats: Arc::clone(&self.bursts),
+                events: self.events_tx.clone(),
+            }
+        });
+        let processor = FrameProcessor {
+            dispatcher,
+            adapter: Arc::clone(&self.adapter),
+            book_depths: Arc::new(self.depth_limits()),
+            pipeline: Arc::clone(&self.pipeline),
+            state: state.clone(),
+            bandwidth: Arc::clone(&self.bandwidth),
+            bursts: self.burst_detection.then(|| Arc::clone(&self.bursts)),
+            smoother,
+            breaker: Arc::clone(&self.breaker),
+            overload: self.overload.clone(),
+            baseline: self.baseline.clone(),
+            validator: self.validator.clone(),
+            qos: Arc::clone(&self.qos),
+            clock: Arc::clone(&self.clock),
+            events: self.events_tx.clone(),
+            decoded: Vec::new(),
+            out: Vec::new(),
+            routed: Vec::new(),
+        };
+
+        let mut sink = match FrameSink::new(processor, self.mode, &supervisor) {
+            Ok(sink) => sink,
+            Err(e) => {
+                *control_slot.lock().await = Some(control_rx);
+                *running.lock().await = false;
+                return Err(ClientError::Io(e.to_string()));
+            }
+        };
+
+        let url = self.url.clone();
+        let adapter = Arc::clone(&self.adapter);
+        let clock = Arc::clone(&self.clock);
+        let events = self.events_tx.clone();
+        let reconnect = self.reconnect;
+
+        let watched_running = Arc::clone(&running);
+        let watched_events = events.clone();
+
+        // Spawn the connection actor
+        let connection = tokio::spawn(async move {
+            // Frames handed to the parser, to tell when it has caught up
+            let mut submitted = 0u64;
+            // Under an interval fsync policy, sync frames a quiet feed left
+            // unsynced once the interval is up
+            let sync_interval = journal
+                .as_ref()
+                .and_then(|journal| journal.lock().unwrap().sync_interval());
+            let mut sync_ticker = tokio::time::interval(
+                sync_interval
+                    .unwrap_or(Duration::from_secs(1))
+                    .max(Duration::from_millis(1)),
+            );
+            loop {
+                let disconnected = loop {
+                    if !*running.lock().await {
+                        break None;
+                    }
+
+                    tokio::select! {
+                        _ = stopped(&mut shutdown) => break None,
+                        frame = read.next() => match frame {
+                            Some(Ok(message @ (Message::Text(_) | Message::Binary(_)))) => {
+                                // Stamp before journaling or parsing, so latency splits
+                                // into network and processing time
+                                let received = clock.now();
+                                let frame = message.into_data();
+                                debug!("Received message: {}", String::from_utf8_lossy(&frame));
+
+                                if let Some(journal) = &journal {
+                                    let caught_up = state.delivered.load(Ordering::Acquire) == submitted;
+                                    if let Err(e) = journal_frame(journal, &frame, caught_up).await {
+                                        error!("Failed to journal frame: {}", e);
+                                    }
+                                }
+                            
+                                if !sink.submit(frame, received).await {
+                                    error!("Parser has stopped, shutting down");
+                                    break None;
                                 }
+                                submitted += 1;
+                            }
+                            Some(Ok(Message::Ping(_data))) => {
+                                debug!("Received ping, sending pong");
+                                // Pong is handled automatically by tokio-tungstenite
+                            }
+                            Some(Ok(Message::Close(_))) => {
+                                info!(event = "connection", venue = %url, "Connection closed by server");
+                                break Some("closed by server".to_string());
                             }
-                            Err(e) => {
-                                warn!("Failed to parse message: {} - {}", e, text);
+                            Some(Err(e)) => {
+                                error!(event = "connection", venue = %url, "WebSocket error: {}", e);
+                                break Some(e.to_string());
+                            }
+                            None => {
+                                info!(event = "connection", venue = %url, "Stream ended");
+                                break Some("stream ended".to_string());
+                            }
+                            _ => {}
+                        },
+                        _ = sync_ticker.tick(), if sync_interval.is_some() => {
+                            if let Some(journal) = &journal {
+                                let due = journal.lock().unwrap().sync_due();
+                                if due {
+                                    if let Err(e) = blocking(journal, Journal::sync).await {
+                                        error!("Failed to sync journal: {}", e);
+                                    }
+                                }
                             }
                         }
+                        Some(command) = control_rx.recv() => match command {
+                            ControlCommand::PauseSink => {
+                                info!("Pausing message delivery");
+                                state.paused.store(true, Ordering::Relaxed);
+                            }
+                            ControlCommand::ResumeSink => {
+                                info!("Resuming message delivery");
+                                state.paused.store(false, Ordering::Relaxed);
+                            }
+                            ControlCommand::Resubscribe => {
+                                info!("Resubscribing");
+                                let subscribe_frames = adapter.lock().unwrap().subscribe_frames();
+                                for frame in &subscribe_frames {
+                                    if let Err(e) = write.send(Message::Text(frame.clone())).await {
+                                        error!("Failed to resubscribe: {}", e);
+                                    }
+                                }
+                            }
+                            ControlCommand::UpdateSymbols { add, remove } => {
+                                info!("Updating symbols: +{:?} -{:?}", add, remove);
+                                let frames = adapter.lock().unwrap().update_symbols(&add, &remove);
+                                for frame in frames {
+                                    if let Err(e) = write.send(Message::Text(frame)).await {
+                                        error!("Failed to update symbols: {}", e);
+                                    }
+                                }
+                            }
+                            ControlCommand::FlushRecorder => match &journal {
+                                Some(journal) => {
+                                    if let Err(e) = blocking(journal, Journal::sync).await {
+                                        error!("Failed to flush journal: {}", e);
+                                    }
+                                }
+                                None => debug!("No recorder attached, nothing to flush"),
+                            },
+                            ControlCommand::SetLogLevel(level) => control::apply_log_level(level),
+                            ControlCommand::SnapshotBook { symbol, reply } => {
+                                let _ = reply.send(state.books.lock().unwrap().get(symbol.as_str()).cloned());
+                            }
+                        },
                     }
-                    Some(Ok(Message::Ping(_data))) => {
-                        debug!("Received ping, sending pong");
-                        // Pong is handled automatically by tokio-tungstenite
-                    }
-                    Some(Ok(Message::Close(_))) => {
-                        info!("Connection closed by server");
+                };
+
+                let Some(reason) = disconnected else {
+                    break;
+                };
+                let _ = events.send(ClientEvent::Disconnected { reason });
+                let Some((max_attempts, backoff)) = reconnect else {
+                    break;
+                };
+                let mut reconnected = None;
+                for attempt in 1..=max_attempts {
+                    if !*running.lock().await {
                         break;
                     }
-                    Some(Err(e)) => {
-                        error!("WebSocket error: {}", e);
-                        break;
+                    let _ = events.send(ClientEvent::Reconnecting { attempt });
+                    let delay = backoff
+                        .saturating_mul(1 << (attempt - 1).min(16))
+                        .min(MAX_RECONNECT_DELAY);
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = stopped(&mut shutdown) => break,
                     }
-                    None => {
-                        info!("Stream ended");
-                        break;
+                    let subscribe_frames = adapter.lock().unwrap().subscribe_frames();
+                    match connect(&url, &headers, &subscribe_frames, &events).await {
+                        Ok(halves) => {
+                            reconnected = Some(halves);
+                            break;
+                        }
+                        Err(e) => warn!(
+                            event = "connection",
+                            venue = %url,
+                            attempt,
+                            "Reconnect attempt {} failed: {}",
+                            attempt,
+                            e
+                        ),
                     }
-                    _ => {}
+                }
+                match reconnected {
+                    Some(halves) => (write, read) = halves,
+                    None => break,
                 }
             }
             
+            // Hanging up on the parser stops the actors down the chain,
+            // flushing batches on the way
+            drop(sink);
+            *running.lock().await = false;
+            *control_slot.lock().await = Some(control_rx);
+            let _ = events.send(ClientEvent::Stopped);
             info!("Message processing task stopped");
         });
+        // The connection actor owns the socket and cannot be restarted; if it
+        // panics, stop rather than leave subscribers waiting on a dead stream
+        tokio::spawn(async move {
+            if supervisor.watch("connection", connection).await {
+                *watched_running.lock().await = false;
+                let _ = watched_events.send(ClientEvent::Stopped);
+            }
+        });
 
         Ok(())
     }
@@ -125,6 +825,7 @@ impl MarketDataClient {
         info!("Stopping client");
         let mut running = self.running.lock().await;
         *running = false;
+        self.shutdown.send_replace(true);
     }
 
     /// Check if client is running
@@ -133,9 +834,118 @@ impl MarketDataClient {
     }
 }
 
+/// Resolve once [`MarketDataClient::stop`] has been called. A dropped
+/// client never stops its stream this way.
+async fn stopped(shutdown: &mut watch::Receiver<bool>) {
+    if shutdown.wait_for(|stop| *stop).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Journal `frame` ahead of parsing. Once every frame in a journal past its
+/// checkpoint size has been delivered (`caught_up`) the journal is
+/// truncated first; fsyncs run on the blocking pool.
+async fn journal_frame(
+    journal: &Arc<std::sync::Mutex<Journal>>,
+    frame: &[u8],
+    caught_up: bool,
+) -> Result<()> {
+    if caught_up && journal.lock().unwrap().checkpoint_due() {
+        blocking(journal, Journal::reset).await?;
+    }
+    let sync = journal.lock().unwrap().write(frame)?;
+    if sync {
+        blocking(journal, Journal::sync).await?;
+    }
+    Ok(())
+}
+
+/// Run a journal operation that may fsync off the async threads
+async fn blocking(
+    journal: &Arc<std::sync::Mutex<Journal>>,
+    op: impl FnOnce(&mut Journal) -> Result<()> + Send + 'static,
+) -> Result<()> {
+    let journal = Arc::clone(journal);
+    tokio::task::spawn_blocking(move || op(&mut journal.lock().unwrap()))
+        .await
+        .map_err(|e| ClientError::Io(e.to_string()))?
+}
+
+/// Feed client events to the QoS tracker until the client stops
+async fn run_qos(
+    mut events: broadcast::Receiver<ClientEvent>,
+    qos: Arc<std::sync::Mutex<QosTracker>>,
+) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                qos.lock().unwrap().record_event(&event, std::time::Instant::now());
+                if event == ClientEvent::Stopped {
+                    break;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Open the feed and send the subscription frames. `http(s)` URLs are
+/// read as server-sent events when the `sse` feature is enabled.
+async fn connect(
+    url: &str,
+    headers: &[(String, String)],
+    subscribe_frames: &[String],
+    events: &broadcast::Sender<ClientEvent>,
+) -> Result<(WsWrite, WsRead)> {
+    info!(event = "connection", venue = url, "Connecting to {}", url);
+    let _ = events.send(ClientEvent::Connecting);
+
+    let (mut write, read) = if url.starts_with("http") {
+        #[cfg(feature = "sse")]
+        {
+            sse::connect(url, headers).await?
+        }
+        #[cfg(not(feature = "sse"))]
+        return Err(ClientError::Connection(
+            "http(s) feeds require the `sse` feature".to_string(),
+        ));
+    } else {
+        let mut request = url
+            .into_client_request()
+            .map_err(|e| ClientError::Connection(e.to_string()))?;
+        for (name, value) in headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| ClientError::Connection(e.to_string()))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| ClientError::Connection(e.to_string()))?;
+            request.headers_mut().insert(name, value);
+        }
+        let (ws_stream, _) = connect_async(request)
+            .await
+            .map_err(|e| ClientError::Connection(e.to_string()))?;
+        let (write, read) = ws_stream.split();
+        (Box::pin(write) as WsWrite, Box::pin(read) as WsRead)
+    };
+
+    // Send subscription messages
+    for frame in subscribe_frames {
+        write
+            .send(Message::Text(frame.clone()))
+            .await
+            .map_err(|e| ClientError::WebSocket(e.to_string()))?;
+    }
+
+    info!(event = "connection", venue = url, "Connected successfully");
+    let _ = events.send(ClientEvent::Connected);
+    Ok((write, read))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::journal::FsyncPolicy;
+    use crate::types::{OrderBookSnapshot, PriceLevel};
 
     #[tokio::test]
     async fn test_client_creation() {
@@ -143,6 +953,277 @@ mod tests {
         assert!(!client.is_running().await);
     }
 
+    #[tokio::test]
+    async fn test_start_without_control_receiver_stays_stopped() {
+        let client = MarketDataClient::new("ws://127.0.0.1:1".to_string(), 1000);
+        let control_rx = client.control_rx.lock().await.take();
+        assert!(matches!(client.start().await, Err(ClientError::Control(_))));
+        assert!(!client.is_running().await);
+
+        // A failed connect hands the receiver back for the next start
+        *client.control_rx.lock().await = control_rx;
+        assert!(matches!(client.start().await, Err(ClientError::Connection(_))));
+        assert!(client.control_rx.lock().await.is_some());
+        assert!(!client.is_running().await);
+    }
+
+    #[tokio::test]
+    async fn test_recover_from_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+        let mut journal = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        journal.append(br#"{"type":"Heartbeat"}"#).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        let client = MarketDataClient::new("ws://localhost:8080".to_string(), 1000)
+            .with_journal(journal);
+        let mut receiver = client.subscribe();
+
+        assert_eq!(client.recover().await.unwrap(), 1);
+        assert!(matches!(receiver.recv().await.unwrap(), MarketDataMessage::Heartbeat));
+
+        // Recovered frames are not replayed again on the next restart
+        assert_eq!(client.recover().await.unwrap(), 0);
+        assert!(Journal::recover(&path).unwrap().next().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_recover_keeps_undelivered_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+        let mut journal = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        journal.append(br#"{"type":"Heartbeat"}"#).unwrap();
+
+        let client =
+            MarketDataClient::new("ws://localhost:8080".to_string(), 1000).with_journal(journal);
+        assert_eq!(client.recover().await.unwrap(), 0);
+        assert_eq!(Journal::recover(&path).unwrap().count(), 1);
+
+        let mut receiver = client.subscribe();
+        assert_eq!(client.recover().await.unwrap(), 1);
+        assert!(matches!(
+            receiver.recv().await.unwrap(),
+            MarketDataMessage::Heartbeat
+        ));
+    }
+
+    /// Holds the only subscriber and lets go of it at the second message
+    struct Unsubscribe(Option<broadcast::Receiver<MarketDataMessage>>, usize);
+
+    impl Stage for Unsubscribe {
+        fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+            self.1 += 1;
+            if self.1 == 2 {
+                self.0 = None;
+            }
+            out.push(msg);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recover_cuts_delivered_frames_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+        let mut journal = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        for _ in 0..3 {
+            journal.append(br#"{"type":"Heartbeat"}"#).unwrap();
+        }
+
+        let client =
+            MarketDataClient::new("ws://localhost:8080".to_string(), 1000).with_journal(journal);
+        client
+            .pipeline
+            .lock()
+            .unwrap()
+            .push(Unsubscribe(Some(client.subscribe()), 0));
+        assert_eq!(client.recover().await.unwrap(), 1);
+        assert_eq!(Journal::recover(&path).unwrap().count(), 2);
+
+        let _receiver = client.subscribe();
+        assert_eq!(client.recover().await.unwrap(), 2);
+        assert_eq!(Journal::recover(&path).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_recover_delivers_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+        let mut journal = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        for _ in 0..5 {
+            journal.append(br#"{"type":"Heartbeat"}"#).unwrap();
+        }
+
+        let client = MarketDataClient::new("ws://localhost:8080".to_string(), 1000)
+            .with_journal(journal);
+        let mut batches = client.subscribe_batched(2, Duration::from_secs(1));
+
+        client.recover().await.unwrap();
+        let sizes: Vec<usize> = std::iter::from_fn(|| batches.try_recv().ok())
+            .map(|batch| batch.len())
+            .collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_book_depth_in_any_builder_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+        let level = |price| PriceLevel {
+            price,
+            size: 1.0,
+            num_orders: 1,
+        };
+        let book = MarketDataMessage::OrderBook(OrderBookSnapshot {
+            symbol: "BTCUSD".into(),
+            bids: vec![level(99.0), level(98.0)],
+            asks: vec![level(101.0), level(102.0)],
+            timestamp: chrono::Utc::now(),
+            instrument_id: None,
+            contract: None,
+            received: None,
+        });
+        let mut journal = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        journal
+            .append(serde_json::to_string(&book).unwrap().as_bytes())
+            .unwrap();
+
+        // Set before the adapter, the depth still picks OKX's BBO channel
+        let client = MarketDataClient::new("ws://localhost:8080".to_string(), 1000)
+            .with_book_depth("btc-usdt", BookDepth::Top1)
+            .with_adapter(crate::adapters::OkxAdapter::new(&["BTC-USDT"]));
+        let frames = client.adapter.lock().unwrap().subscribe_frames();
+        assert!(frames[0].contains(r#"{"channel":"bbo-tbt","instId":"BTC-USDT"}"#));
+
+        // Sources without book channels are truncated by the client
+        let client = MarketDataClient::new("ws://localhost:8080".to_string(), 1000)
+            .with_book_depth("btcusd", BookDepth::Top1)
+            .with_journal(journal);
+        let mut receiver = client.subscribe();
+        client.recover().await.unwrap();
+        let MarketDataMessage::OrderBook(book) = receiver.recv().await.unwrap() else {
+            panic!("expected book");
+        };
+        assert_eq!((book.bids.len(), book.asks.len()), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_events() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await;
+            ws.close(None).await.unwrap();
+        });
+
+        let client = MarketDataClient::new(url, 16);
+        let mut events = client.events();
+        client.start().await.unwrap();
+
+        let mut seen = Vec::new();
+        while let Ok(event) = events.recv().await {
+            seen.push(event);
+            if seen.last() == Some(&ClientEvent::Stopped) {
+                break;
+            }
+        }
+        assert_eq!(seen[..2], [ClientEvent::Connecting, ClientEvent::Connected]);
+        assert!(matches!(seen[2], ClientEvent::Disconnected { .. }));
+        assert!(!client.is_running().await);
+    }
+
+    #[tokio::test]
+    async fn test_stop_on_quiet_feed() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                // Accept and then say nothing
+                sockets.push(tokio_tungstenite::accept_async(stream).await.unwrap());
+            }
+        });
+
+        let client = MarketDataClient::new(url, 16);
+        let mut events = client.events();
+        for _ in 0..2 {
+            client.start().await.unwrap();
+            client.stop().await;
+            let stopped = async {
+                while events.recv().await.unwrap() != ClientEvent::Stopped {}
+            };
+            tokio::time::timeout(Duration::from_secs(5), stopped)
+                .await
+                .unwrap();
+        }
+        assert!(!client.is_running().await);
+    }
+
+    #[tokio::test]
+    async fn test_journal_truncated_once_delivered() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (next_tx, mut next_rx) = mpsc::channel::<()>(1);
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await;
+            for _ in 0..3 {
+                let heartbeat = Message::Text(r#"{"type":"Heartbeat"}"#.to_string());
+                ws.send(heartbeat).await.unwrap();
+                next_rx.recv().await;
+            }
+        });
+
+        let journal = Journal::open(&path, FsyncPolicy::Always)
+            .unwrap()
+            .with_checkpoint_size(1);
+        let client = MarketDataClient::new(url, 16).with_journal(journal);
+        let mut receiver = client.subscribe();
+        client.start().await.unwrap();
+        for _ in 0..3 {
+            receiver.recv().await.unwrap();
+            // Let the frame be delivered before the next one arrives
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            next_tx.send(()).await.unwrap();
+        }
+        client.stop().await;
+
+        // Each frame found the earlier ones delivered and truncated them
+        assert_eq!(Journal::recover(&path).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_journal_synced_on_a_quiet_feed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await;
+            let heartbeat = Message::Text(r#"{"type":"Heartbeat"}"#.to_string());
+            ws.send(heartbeat).await.unwrap();
+            // Then nothing, so only the timer can sync the frame
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let interval = Duration::from_millis(300);
+        let journal = Journal::open(&path, FsyncPolicy::Interval(interval)).unwrap();
+        let client = MarketDataClient::new(url, 16).with_journal(journal);
+        let mut receiver = client.subscribe();
+        client.start().await.unwrap();
+        receiver.recv().await.unwrap();
+        tokio::time::sleep(interval * 3).await;
+        assert!(!client.journal.as_ref().unwrap().lock().unwrap().sync_due());
+        client.stop().await;
+    }
+
     #[tokio::test]
     async fn test_subscription() {
         let client = MarketDataClient::new("ws://localhost:8080".to_string(), 1000);
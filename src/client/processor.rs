@@ -0,0 +1,857 @@
+//! Parse/normalize path for received frames, run by the parser actor on the
+//! Tokio pool or on a dedicated OS thread, and the router actor delivering
+//! its output.
+
+use super::actor::{self, Mailbox, Supervisor};
+use super::batch::BatchSinks;
+use super::ClientEvent;
+use crate::adapters::Adapter;
+use crate::bandwidth::BandwidthStats;
+use crate::baseline::BaselineLearner;
+use crate::book::BookDepth;
+use crate::breaker::{BreakerTransition, ParseBreaker};
+use crate::burst::{BurstDetector, RateLimiter};
+use crate::clock::ClockSource;
+use crate::lvc::SyncHandle;
+use crate::overload::{OverloadController, OverloadTransition};
+use crate::pipeline::Pipeline;
+use crate::qos::QosTracker;
+use crate::symbology::{Symbol, Venue};
+use crate::types::{MarketDataMessage, OrderBookSnapshot};
+use crate::validation::Validator;
+use chrono::{DateTime, Utc};
+use crossbeam_channel::{Receiver, Sender, TryRecvError, TrySendError};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, error, info, warn};
+
+/// Where received frames are parsed and dispatched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProcessingMode {
+    /// In a supervised parser task on the shared Tokio runtime
+    #[default]
+    Tokio,
+    /// On a dedicated OS thread fed by the connection task, which stops
+    /// reading the socket while the thread's queue is full
+    Dedicated {
+        /// Pin the thread to this core id
+        core: Option<usize>,
+        wait: WaitStrategy,
+    },
+}
+
+/// How the dedicated processing thread waits for the next frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WaitStrategy {
+    /// Spin on the queue without ever sleeping; burns a full core
+    BusyPoll,
+    /// Spin briefly, then yield the CPU between polls
+    SpinYield,
+    /// Block on the queue until a frame arrives
+    #[default]
+    Block,
+}
+
+/// Polls spent spinning before `SpinYield` starts yielding
+const SPIN_LIMIT: u32 = 1_000;
+
+/// Frames the parser actor or the processing thread may have queued before
+/// the connection actor waits
+const PARSER_MAILBOX_CAPACITY: usize = 1024;
+
+/// Messages the router actor may have queued before the parser waits
+pub(crate) const ROUTER_MAILBOX_CAPACITY: usize = 4096;
+
+/// A raw frame and when it was read from the socket
+pub(crate) type Frame = (Vec<u8>, DateTime<Utc>);
+
+/// State shared between the connection task and the frame processor
+#[derive(Clone, Default)]
+pub(crate) struct SharedState {
+    pub books: Arc<Mutex<HashMap<Symbol, OrderBookSnapshot>>>,
+    pub paused: Arc<AtomicBool>,
+    /// Frames the parser has taken on, also ones it panicked on
+    pub handled: Arc<AtomicU64>,
+    /// Frames whose messages all reached subscribers, for journal
+    /// checkpoints
+    pub delivered: Arc<AtomicU64>,
+}
+
+/// Mailbox messages of the router actor
+#[derive(Debug, Clone)]
+pub(crate) enum Routed {
+    Message(MarketDataMessage),
+    /// Unparseable frame forwarded while the parse breaker is open
+    Raw(String),
+    /// Every message of the first `n` frames has been routed. Counts only
+    /// grow, so a marker lost to a full queue is covered by the next one.
+    Handled(u64),
+}
+
+/// Hands processed messages to the router actor. Its mailbox is bounded:
+/// while it is full the sender waits, which backs up through the parser's
+/// mailbox to the socket instead of queueing without limit.
+#[derive(Clone)]
+pub(crate) struct Dispatcher {
+    pub tx: mpsc::Sender<Routed>,
+}
+
+impl Dispatcher {
+    pub async fn send(&self, routed: Routed) {
+        if self.tx.send(routed).await.is_err() {
+            error!("Router has exited, dropping message");
+        }
+    }
+
+    /// [`send`](Self::send) for the processing thread, outside the runtime
+    pub fn blocking_send(&self, routed: Routed) {
+        if self.tx.blocking_send(routed).is_err() {
+            error!("Router has exited, dropping message");
+        }
+    }
+}
+
+/// Final delivery to broadcast subscribers and sink actors
+#[derive(Clone)]
+pub(crate) struct Router {
+    pub broadcast_tx: broadcast::Sender<MarketDataMessage>,
+    pub raw_tx: broadcast::Sender<String>,
+    /// Last value cache updated with each broadcast, when enabled
+    pub sync: Option<SyncHandle>,
+    /// Registered batch consumers, to skip the batch sink when there are none
+    pub batch_sinks: Arc<Mutex<BatchSinks>>,
+    /// Mailbox of the batch sink actor
+    pub batches: mpsc::Sender<MarketDataMessage>,
+    /// Messages the batch sink actor had no room for
+    pub dropped: u64,
+    /// Lifecycle events, to report a lagging batch sink
+    pub events: broadcast::Sender<ClientEvent>,
+    /// Frames delivered, from [`SharedState::delivered`]
+    pub delivered: Arc<AtomicU64>,
+}
+
+impl Router {
+    pub fn deliver(&mut self, msg: MarketDataMessage) {
+        if !self.batch_sinks.lock().unwrap().is_empty() {
+            if let Err(mpsc::error::TrySendError::Full(_)) = self.batches.try_send(msg.clone()) {
+                self.dropped += 1;
+                if self.dropped.is_power_of_two() {
+                    warn!("Batch sink lagging, dropped {} messages", self.dropped);
+                    let _ = self.events.send(ClientEvent::SinkLagging {
+                        sink: "batches".to_string(),
+                        dropped: self.dropped,
+                    });
+                }
+            }
+        }
+        let sent = match &self.sync {
+            Some(sync) => sync.publish(msg),
+            None => self.broadcast_tx.send(msg).map_err(Box::new),
+        };
+        if let Err(e) = sent {
+            error!("Failed to broadcast message: {}", e);
+        }
+    }
+
+    pub fn deliver_raw(&self, frame: String) {
+        // No raw subscribers is the common case and not an error
+        let _ = self.raw_tx.send(frame);
+    }
+}
+
+/// Router actor: deliver everything in the mailbox until every dispatcher
+/// is gone
+pub(crate) async fn run_router(
+    mut router: Router,
+    mailbox: Mailbox<mpsc::Receiver<Routed>>,
+) {
+    let mut rx = mailbox.lock().await;
+    while let Some(routed) = rx.recv().await {
+        match routed {
+            Routed::Message(msg) => router.deliver(msg),
+            Routed::Raw(frame) => router.deliver_raw(frame),
+            Routed::Handled(frames) => {
+                router.delivered.fetch_max(frames, Ordering::Release);
+            }
+        }
+    }
+    debug!("Router stopped");
+}
+
+/// Bounded queue in front of a rate-limited dispatcher task
+#[derive(Clone)]
+pub(crate) struct Smoother {
+    pub tx: mpsc::Sender<Routed>,
+    pub stats: Arc<Mutex<BurstDetector>>,
+    pub events: broadcast::Sender<ClientEvent>,
+}
+
+impl Smoother {
+    fn enqueue(&self, msg: MarketDataMessage) {
+        match self.tx.try_send(Routed::Message(msg)) {
+            Ok(()) => {
+                let depth = self.tx.max_capacity() - self.tx.capacity();
+                self.stats.lock().unwrap().record_queue_depth(depth);
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                let mut stats = self.stats.lock().unwrap();
+                stats.record_queue_drop();
+                let dropped = stats.stats().queue_dropped;
+                if dropped.is_power_of_two() {
+                    let _ = self.events.send(ClientEvent::SinkLagging {
+                        sink: "smoother".to_string(),
+                        dropped,
+                    });
+                }
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                error!("Smoothing dispatcher has exited, dropping message");
+            }
+        }
+    }
+
+    /// Queue a frame count behind the messages of those frames
+    fn enqueue_handled(&self, frames: u64) {
+        let _ = self.tx.try_send(Routed::Handled(frames));
+    }
+}
+
+/// Release queued messages no faster than `rate` per second
+pub(crate) async fn run_smoother(
+    mailbox: Mailbox<mpsc::Receiver<Routed>>,
+    rate: f64,
+    dispatcher: Dispatcher,
+) {
+    let mut rx = mailbox.lock().await;
+    let mut limiter = RateLimiter::new(rate, Instant::now());
+    while let Some(routed) = rx.recv().await {
+        if let Routed::Message(_) = routed {
+            while let Err(wait) = limiter.try_acquire(Instant::now()) {
+                tokio::time::sleep(wait).await;
+            }
+        }
+        dispatcher.send(routed).await;
+    }
+    debug!("Smoothing dispatcher stopped");
+}
+
+/// Decodes frames, runs the pipeline and delivers the results
+#[derive(Clone)]
+pub(crate) struct FrameProcessor {
+    pub dispatcher: Dispatcher,
+    pub adapter: Arc<Mutex<Box<dyn Adapter>>>,
+    /// Book levels kept per upper-cased symbol
+    pub book_depths: Arc<HashMap<String, BookDepth>>,
+    pub pipeline: Arc<Mutex<Pipeline>>,
+    pub state: SharedState,
+    pub bandwidth: Arc<Mutex<BandwidthStats>>,
+    /// Ingest burst detection, when enabled
+    pub bursts: Option<Arc<Mutex<BurstDetector>>>,
+    pub smoother: Option<Smoother>,
+    pub breaker: Arc<Mutex<ParseBreaker>>,
+    /// Load shedding, when enabled
+    pub overload: Option<Arc<Mutex<OverloadController>>>,
+    /// Rate and volume anomaly detection, when enabled
+    pub baseline: Option<Arc<Mutex<BaselineLearner>>>,
+    /// Invariant checks, when enabled
+    pub validator: Option<Arc<Mutex<Validator>>>,
+    pub qos: Arc<Mutex<QosTracker>>,
+    pub clock: Arc<dyn ClockSource>,
+    pub events: broadcast::Sender<ClientEvent>,
+    pub decoded: Vec<MarketDataMessage>,
+    pub out: Vec<MarketDataMessage>,
+    /// Output of the last frame for the router, handed over by
+    /// [`flush`](Self::flush)
+    pub routed: Vec<Routed>,
+}
+
+impl FrameProcessor {
+    /// Decode and dispatch a frame read from the socket at `received`
+    pub fn handle_frame(&mut self, mut frame: Vec<u8>, received: DateTime<Utc>) {
+        // Counted up front, so a frame that panics the parser does not hold
+        // back journal checkpoints
+        let frames = self.state.handled.fetch_add(1, Ordering::AcqRel) + 1;
+        if let Some(bursts) = &self.bursts {
+            if bursts.lock().unwrap().record(Instant::now()) {
+                debug!("Microburst detected");
+            }
+        }
+
+        // Decoding may rewrite the buffer in place, so keep a copy for raw
+        // passthrough while the breaker is open
+        let raw = self
+            .breaker
+            .lock()
+            .unwrap()
+            .passthrough()
+            .then(|| String::from_utf8_lossy(&frame).into_owned());
+        let frame_len = frame.len();
+        let mut adapter = self.adapter.lock().unwrap();
+        if adapter.is_subscription_ack(&frame) {
+            let _ = self.events.send(ClientEvent::SubscriptionAck);
+        }
+        let decoded = adapter.decode(&mut frame, received, &mut self.decoded);
+        drop(adapter);
+        for msg in &mut self.decoded {
+            msg.set_received(received);
+            if let MarketDataMessage::OrderBook(book) = msg {
+                limit_depth(&self.book_depths, book);
+            }
+        }
+        let mut qos = self.qos.lock().unwrap();
+        qos.record_frame(decoded.is_ok(), Instant::now());
+        for msg in &self.decoded {
+            qos.record_message(msg, received);
+        }
+        drop(qos);
+        if let Some(baseline) = &self.baseline {
+            let anomalies = baseline.lock().unwrap().record(&self.decoded, received);
+            if !anomalies.is_empty() {
+                let venue = Venue::from(self.adapter.lock().unwrap().name());
+                for anomaly in anomalies {
+                    warn!(
+                        event = "anomaly",
+                        venue = %venue,
+                        symbol = anomaly.symbol.as_deref().unwrap_or("*"),
+                        z_score = anomaly.z_score,
+                        "{:?} of {:.2}/s against {:.2}/s usual",
+                        anomaly.metric,
+                        anomaly.observed,
+                        anomaly.expected
+                    );
+                    let _ = self.events.send(ClientEvent::Anomaly { venue, anomaly });
+                }
+            }
+        }
+        self.bandwidth
+            .lock()
+            .unwrap()
+            .record(frame_len, &self.decoded);
+
+        let mut breaker = self.breaker.lock().unwrap();
+        let transition = breaker.record(decoded.is_ok(), Instant::now());
+        if let Err(e) = &decoded {
+            match breaker.should_warn() {
+                Some(0) => warn!(event = "parse_error", "Failed to parse message: {}", e),
+                Some(suppressed) => warn!(
+                    event = "parse_error",
+                    suppressed,
+                    "Failed to parse message: {} ({} similar errors suppressed)",
+                    e,
+                    suppressed
+                ),
+                None => {}
+            }
+        }
+        drop(breaker);
+        if let Some(transition) = transition {
+            self.on_breaker_transition(transition);
+        }
+
+        if decoded.is_err() {
+            self.decoded.clear();
+            if let Some(raw) = raw {
+                if !self.state.paused.load(Ordering::Relaxed) {
+                    self.routed.push(Routed::Raw(raw));
+                }
+            }
+            self.mark_handled(frames);
+            return;
+        }
+
+        if let Some(validator) = &self.validator {
+            let failures = validator.lock().unwrap().validate(&mut self.decoded);
+            if !failures.is_empty() {
+                let venue = Venue::from(self.adapter.lock().unwrap().name());
+                for failure in failures {
+                    debug!(
+                        event = "validation",
+                        venue = %venue,
+                        symbol = %failure.symbol,
+                        rejected = failure.rejected,
+                        "Invalid message: {:?}",
+                        failure.violations
+                    );
+                    let _ = self
+                        .events
+                        .send(ClientEvent::ValidationFailed { venue, failure });
+                }
+            }
+        }
+
+        let mut pipeline = self.pipeline.lock().unwrap();
+        for msg in self.decoded.drain(..) {
+            pipeline.process(msg, &mut self.out);
+        }
+        drop(pipeline);
+
+        if let Some(overload) = &self.overload {
+            overload.lock().unwrap().shed(&mut self.out, Instant::now());
+        }
+        let dispatching = !self.out.is_empty();
+        for msg in self.out.drain(..) {
+            if let MarketDataMessage::OrderBook(book) = &msg {
+                self.state
+                    .books
+                    .lock()
+                    .unwrap()
+                    .insert(book.symbol, book.clone());
+            }
+            if self.state.paused.load(Ordering::Relaxed) {
+                continue;
+            }
+            match &self.smoother {
+                Some(smoother) => smoother.enqueue(msg),
+                None => self.routed.push(Routed::Message(msg)),
+            }
+        }
+        self.mark_handled(frames);
+        if dispatching {
+            let dispatched = self.clock.now();
+            self.qos
+                .lock()
+                .unwrap()
+                .record_processing(received, dispatched);
+            if let Some(overload) = &self.overload {
+                let latency = (dispatched - received).to_std().unwrap_or_default();
+                let transition = overload.lock().unwrap().record(latency, Instant::now());
+                if let Some(transition) = transition {
+                    self.on_overload_transition(transition);
+                }
+            }
+        }
+    }
+
+    /// Tell the router once the messages of the first `frames` frames are
+    /// ahead of it, behind any still queued for smoothing
+    fn mark_handled(&mut self, frames: u64) {
+        match &self.smoother {
+            Some(smoother) => smoother.enqueue_handled(frames),
+            None => self.routed.push(Routed::Handled(frames)),
+        }
+    }
+
+    /// Hand the last frame's output to the router, waiting while its
+    /// mailbox is full
+    pub async fn flush(&mut self) {
+        for routed in self.routed.drain(..) {
+            self.dispatcher.send(routed).await;
+        }
+    }
+
+    /// [`flush`](Self::flush) for the processing thread
+    fn flush_blocking(&mut self) {
+        for routed in self.routed.drain(..) {
+            self.dispatcher.blocking_send(routed);
+        }
+    }
+
+    /// Release locks poisoned by a panic in an earlier run of the parser
+    fn clear_poison(&self) {
+        self.adapter.clear_poison();
+        self.pipeline.clear_poison();
+        self.state.books.clear_poison();
+        self.bandwidth.clear_poison();
+        self.breaker.clear_poison();
+        self.qos.clear_poison();
+        if let Some(overload) = &self.overload {
+            overload.clear_poison();
+        }
+        if let Some(baseline) = &self.baseline {
+            baseline.clear_poison();
+        }
+        if let Some(validator) = &self.validator {
+            validator.clear_poison();
+        }
+        if let Some(bursts) = &self.bursts {
+            bursts.clear_poison();
+        }
+    }
+
+    fn on_breaker_transition(&self, transition: BreakerTransition) {
+        let adapter = self.adapter.lock().unwrap().name().to_string();
+        let event = match transition {
+            BreakerTransition::Degraded { error_rate } => {
+                warn!(
+                    "{} parser degraded: {:.0}% of frames failing",
+                    adapter,
+                    error_rate * 100.0
+                );
+                ClientEvent::ParserDegraded {
+                    adapter,
+                    error_rate,
+                }
+            }
+            BreakerTransition::Recovered => {
+                info!("{} parser recovered", adapter);
+                ClientEvent::ParserRecovered { adapter }
+            }
+        };
+        let _ = self.events.send(event);
+    }
+
+    fn on_overload_transition(&self, transition: OverloadTransition) {
+        let event = match transition {
+            OverloadTransition::Active { latency } => {
+                let latency_ms = latency.as_secs_f64() * 1000.0;
+                warn!(
+                    event = "overload",
+                    latency_ms, "Processing over budget, shedding load"
+                );
+                ClientEvent::OverloadActive { latency_ms }
+            }
+            OverloadTransition::Cleared => {
+                info!(event = "overload", "Processing back within budget");
+                ClientEvent::OverloadCleared
+            }
+        };
+        let _ = self.events.send(event);
+    }
+}
+
+/// Truncate `book` to the depth set for its symbol
+pub(crate) fn limit_depth(depths: &HashMap<String, BookDepth>, book: &mut OrderBookSnapshot) {
+    if depths.is_empty() {
+        return;
+    }
+    let levels = depths
+        .get(&book.symbol.as_str().to_ascii_uppercase())
+        .and_then(|depth| depth.levels());
+    if let Some(levels) = levels {
+        book.bids.truncate(levels);
+        book.asks.truncate(levels);
+    }
+}
+
+/// Mailbox of the parser actor, written by the connection actor
+pub(crate) enum FrameSink {
+    Actor(mpsc::Sender<Frame>),
+    /// The processing thread's queue and how often it had no room
+    Dedicated(Sender<Frame>, u64),
+}
+
+impl FrameSink {
+    /// Start the parser for `mode`: a task restarted by `supervisor`, or a
+    /// processing thread, which is only reported if it panics
+    pub fn new(
+        processor: FrameProcessor,
+        mode: ProcessingMode,
+        supervisor: &Supervisor,
+    ) -> std::io::Result<Self> {
+        match mode {
+            ProcessingMode::Tokio => {
+                let (tx, rx) = mpsc::channel(PARSER_MAILBOX_CAPACITY);
+                let mailbox = actor::mailbox(rx);
+                supervisor.spawn("parser", move || {
+                    let processor = processor.clone();
+                    processor.clear_poison();
+                    run_parser(processor, Arc::clone(&mailbox))
+                });
+                Ok(FrameSink::Actor(tx))
+            }
+            ProcessingMode::Dedicated { core, wait } => {
+                let (tx, rx) = crossbeam_channel::bounded(PARSER_MAILBOX_CAPACITY);
+                let thread = thread::Builder::new()
+                    .name("mds-processor".to_string())
+                    .spawn(move || run_dedicated(processor, rx, core, wait))?;
+                let supervisor = supervisor.clone();
+                tokio::task::spawn_blocking(move || {
+                    if thread.join().is_err() {
+                        supervisor.fail("parser");
+                    }
+                });
+                Ok(FrameSink::Dedicated(tx, 0))
+            }
+        }
+    }
+
+    /// Queue a frame for parsing, waiting for room while the parser is
+    /// behind; false once the parser has stopped for good
+    pub async fn submit(&mut self, frame: Vec<u8>, received: DateTime<Utc>) -> bool {
+        match self {
+            FrameSink::Actor(tx) => tx.send((frame, received)).await.is_ok(),
+            FrameSink::Dedicated(tx, stalls) => match tx.try_send((frame, received)) {
+                Ok(()) => true,
+                Err(TrySendError::Full(frame)) => {
+                    *stalls += 1;
+                    if stalls.is_power_of_two() {
+                        warn!(
+                            "Processing thread lagging, waited for room {} times",
+                            stalls
+                        );
+                    }
+                    // Block a pool thread rather than the runtime
+                    let tx = tx.clone();
+                    tokio::task::spawn_blocking(move || tx.send(frame).is_ok())
+                        .await
+                        .unwrap_or(false)
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            },
+        }
+    }
+}
+
+/// Parser actor: handle frames until the connection actor hangs up
+async fn run_parser(mut processor: FrameProcessor, mailbox: Mailbox<mpsc::Receiver<Frame>>) {
+    let mut rx = mailbox.lock().await;
+    while let Some((frame, received)) = rx.recv().await {
+        processor.handle_frame(frame, received);
+        processor.flush().await;
+    }
+    debug!("Parser stopped");
+}
+
+fn run_dedicated(
+    mut processor: FrameProcessor,
+    rx: Receiver<Frame>,
+    core: Option<usize>,
+    wait: WaitStrategy,
+) {
+    if let Some(id) = core {
+        if core_affinity::set_for_current(core_affinity::CoreId { id }) {
+            info!("Processing thread pinned to core {}", id);
+        } else {
+            warn!("Failed to pin processing thread to core {}", id);
+        }
+    }
+
+    let mut idle_polls = 0u32;
+    loop {
+        let (frame, received) = match wait {
+            WaitStrategy::Block => match rx.recv() {
+                Ok(frame) => frame,
+                Err(_) => break,
+            },
+            WaitStrategy::BusyPoll | WaitStrategy::SpinYield => match rx.try_recv() {
+                Ok(frame) => {
+                    idle_polls = 0;
+                    frame
+                }
+                Err(TryRecvError::Empty) => {
+                    idle_polls = idle_polls.saturating_add(1);
+                    if wait == WaitStrategy::SpinYield && idle_polls > SPIN_LIMIT {
+                        thread::yield_now();
+                    } else {
+                        std::hint::spin_loop();
+                    }
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => break,
+            },
+        };
+        processor.handle_frame(frame, received);
+        processor.flush_blocking();
+    }
+
+    info!("Processing thread stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::NativeAdapter;
+    use crate::client::RestartPolicy;
+    use crate::clock::SystemClock;
+    use crate::pipeline::Stage;
+    use std::time::Duration;
+
+    const TRADE: &[u8] = br#"{"type":"Trade","symbol":"BTCUSD","price":1.0,"quantity":1.0,"side":"Buy","timestamp":"2023-11-14T22:13:19Z","trade_id":"1"}"#;
+
+    fn processor() -> (FrameProcessor, mpsc::Receiver<Routed>) {
+        let (tx, rx) = mpsc::channel(ROUTER_MAILBOX_CAPACITY);
+        let processor = FrameProcessor {
+            dispatcher: Dispatcher { tx },
+            adapter: Arc::new(Mutex::new(Box::new(NativeAdapter::new()))),
+            book_depths: Arc::default(),
+            pipeline: Arc::default(),
+            state: SharedState::default(),
+            bandwidth: Arc::default(),
+            bursts: None,
+            smoother: None,
+            breaker: Arc::default(),
+            overload: None,
+            baseline: None,
+            validator: None,
+            qos: Arc::default(),
+            clock: Arc::new(SystemClock),
+            events: broadcast::channel(16).0,
+            decoded: Vec::new(),
+            out: Vec::new(),
+            routed: Vec::new(),
+        };
+        (processor, rx)
+    }
+
+    #[tokio::test]
+    async fn test_dedicated_thread_delivers_messages() {
+        let (processor, mut rx) = processor();
+        let mode = ProcessingMode::Dedicated {
+            core: None,
+            wait: WaitStrategy::SpinYield,
+        };
+
+        let supervisor = Supervisor::new(
+            RestartPolicy::Never,
+            processor.events.clone(),
+            Arc::default(),
+        );
+        let mut sink = FrameSink::new(processor, mode, &supervisor).unwrap();
+        assert!(
+            sink.submit(br#"{"type":"Heartbeat"}"#.to_vec(), Utc::now())
+                .await
+        );
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            Routed::Message(MarketDataMessage::Heartbeat)
+        ));
+
+        // A full queue holds the sender back until there is room, rather
+        // than dropping frames
+        let (tx, queued) = crossbeam_channel::bounded(1);
+        let mut sink = FrameSink::Dedicated(tx, 0);
+        assert!(sink.submit(b"1".to_vec(), Utc::now()).await);
+        let reader = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(20));
+            queued.iter().map(|(frame, _)| frame).collect::<Vec<_>>()
+        });
+        assert!(sink.submit(b"2".to_vec(), Utc::now()).await);
+        assert!(matches!(sink, FrameSink::Dedicated(_, 1)));
+        drop(sink);
+        assert_eq!(reader.join().unwrap(), [b"1".to_vec(), b"2".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_full_router_mailbox_holds_the_parser_back() {
+        let (mut processor, _) = processor();
+        let (tx, mut rx) = mpsc::channel(2);
+        processor.dispatcher = Dispatcher { tx };
+
+        processor.handle_frame(TRADE.to_vec(), Utc::now());
+        processor.flush().await;
+        // The next trade does not fit until the router catches up, and the
+        // parser waits for it rather than dropping or queueing it elsewhere
+        processor.handle_frame(TRADE.to_vec(), Utc::now());
+        let parser = tokio::spawn(async move { processor.flush().await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!parser.is_finished());
+
+        let mut routed = Vec::new();
+        for _ in 0..4 {
+            routed.push(rx.recv().await.unwrap());
+        }
+        parser.await.unwrap();
+        assert!(matches!(
+            routed[..],
+            [
+                Routed::Message(_),
+                Routed::Handled(1),
+                Routed::Message(_),
+                Routed::Handled(2)
+            ]
+        ));
+    }
+
+    struct PanicOnce(bool);
+
+    impl Stage for PanicOnce {
+        fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+            if !std::mem::replace(&mut self.0, true) {
+                panic!("stage failure");
+            }
+            out.push(msg);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parser_actor_restarts_after_panic() {
+        let (processor, mut rx) = processor();
+        processor.pipeline.lock().unwrap().push(PanicOnce(false));
+        let mut events = processor.events.subscribe();
+
+        let supervisor = Supervisor::new(
+            RestartPolicy::default(),
+            processor.events.clone(),
+            Arc::default(),
+        );
+        let mut sink = FrameSink::new(processor, ProcessingMode::Tokio, &supervisor).unwrap();
+        // The first trade is lost with the panicking run, the next one is
+        // parsed by the restarted actor despite the poisoned pipeline lock
+        assert!(sink.submit(TRADE.to_vec(), Utc::now()).await);
+        assert!(sink.submit(TRADE.to_vec(), Utc::now()).await);
+        assert_eq!(
+            events.recv().await.unwrap(),
+            ClientEvent::ActorRestarted {
+                actor: "parser".to_string(),
+                restarts: 1,
+            }
+        );
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            Routed::Message(MarketDataMessage::Trade(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_storm_degrades_to_passthrough() {
+        let (mut processor, mut rx) = processor();
+        *processor.breaker.lock().unwrap() = ParseBreaker::new(Duration::ZERO, 0.5)
+            .with_min_frames(1)
+            .with_raw_passthrough();
+        let mut events = processor.events.subscribe();
+
+        for _ in 0..3 {
+            processor.handle_frame(b"not json".to_vec(), Utc::now());
+            processor.flush_blocking();
+        }
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ClientEvent::ParserDegraded {
+                adapter: "native".to_string(),
+                error_rate: 1.0,
+            }
+        );
+        let routed: Vec<Routed> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert!(matches!(routed.last(), Some(Routed::Handled(3))));
+        let raw: Vec<&Routed> = routed
+            .iter()
+            .filter(|routed| !matches!(routed, Routed::Handled(_)))
+            .collect();
+        assert!(matches!(raw[..], [Routed::Raw(frame)] if frame == "not json"));
+
+        processor.handle_frame(br#"{"type":"Heartbeat"}"#.to_vec(), Utc::now());
+        processor.handle_frame(br#"{"type":"Heartbeat"}"#.to_vec(), Utc::now());
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            ClientEvent::ParserRecovered { .. }
+        ));
+    }
+
+    #[test]
+    fn test_receive_stamp_splits_latency() {
+        let (mut processor, mut rx) = processor();
+        let at = DateTime::from_timestamp(1_700_000_000, 123_456_789).unwrap();
+        // Dispatch is read from the clock 3ms after the socket read
+        processor.clock = Arc::new(move || at + chrono::Duration::milliseconds(3));
+
+        processor.handle_frame(TRADE.to_vec(), at);
+        processor.flush_blocking();
+        let Routed::Message(msg) = rx.try_recv().unwrap() else {
+            panic!("expected a message");
+        };
+        assert_eq!(msg.received(), Some(at));
+        // The frame is marked handled only behind its messages
+        assert!(matches!(rx.try_recv().unwrap(), Routed::Handled(1)));
+        let report = processor
+            .qos
+            .lock()
+            .unwrap()
+            .report("native", Instant::now());
+        assert_eq!(report.latency_ms.max, 1123.456);
+        assert_eq!(report.processing_ms.max, 3.0);
+    }
+}
@@ -0,0 +1,98 @@
+//! Server-sent events transport for HTTP streaming feeds such as IEX Cloud.
+//!
+//! Each event's `data` is handed to the adapter as one text frame, so SSE
+//! feeds share the WebSocket processing path.
+
+use super::{ClientError, Result, WsRead, WsWrite};
+use futures_util::{stream, SinkExt, StreamExt};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+/// Incremental `text/event-stream` parser
+#[derive(Default)]
+struct EventParser {
+    buf: Vec<u8>,
+    data: String,
+}
+
+impl EventParser {
+    /// Feed a chunk of the body, pushing the data of each completed event
+    fn push(&mut self, chunk: &[u8], out: &mut VecDeque<String>) {
+        self.buf.extend_from_slice(chunk);
+        let mut start = 0;
+        while let Some(end) = self.buf[start..].iter().position(|&b| b == b'\n') {
+            let line = &self.buf[start..start + end];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            start += end + 1;
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    out.push_back(std::mem::take(&mut self.data));
+                }
+            } else if let Some(value) = line.strip_prefix(b"data:") {
+                let value = value.strip_prefix(b" ").unwrap_or(value);
+                if !self.data.is_empty() {
+                    self.data.push('\n');
+                }
+                self.data.push_str(&String::from_utf8_lossy(value));
+            }
+            // Comments (keepalives), `event`, `id` and `retry` are ignored
+        }
+        self.buf.drain(..start);
+    }
+}
+
+/// Open an event stream at `url`. Nothing can be sent upstream, so writes
+/// (subscription frames) are discarded.
+pub(super) async fn connect(url: &str, headers: &[(String, String)]) -> Result<(WsWrite, WsRead)> {
+    let mut request = reqwest::Client::new()
+        .get(url)
+        .header("Accept", "text/event-stream");
+    for (name, value) in headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    let response = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| ClientError::Connection(e.to_string()))?;
+
+    let body = Box::pin(response.bytes_stream());
+    let read = stream::unfold(
+        (body, EventParser::default(), VecDeque::new()),
+        |(mut body, mut parser, mut ready)| async move {
+            loop {
+                if let Some(data) = ready.pop_front() {
+                    return Some((Ok(Message::Text(data)), (body, parser, ready)));
+                }
+                match body.next().await {
+                    Some(Ok(chunk)) => parser.push(&chunk, &mut ready),
+                    Some(Err(e)) => {
+                        let e = WsError::Io(std::io::Error::other(e));
+                        return Some((Err(e), (body, parser, ready)));
+                    }
+                    None => return None,
+                }
+            }
+        },
+    );
+    let write = futures_util::sink::drain().sink_map_err(|never: Infallible| match never {});
+    Ok((Box::pin(write), Box::pin(read)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_split_events() {
+        let mut parser = EventParser::default();
+        let mut out = VecDeque::new();
+
+        parser.push(b": keepalive\n\ndata: [{\"a\"", &mut out);
+        assert!(out.is_empty());
+        parser.push(b":1}]\r\n\r\nevent: x\ndata: one\ndata: two\n\n", &mut out);
+        assert_eq!(out, ["[{\"a\":1}]", "one\ntwo"]);
+    }
+}
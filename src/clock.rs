@@ -0,0 +1,188 @@
+//! Receive timestamp sources and timestamp precision.
+//!
+//! The client stamps every normalized message with the time its frame was
+//! received, read from a [`ClockSource`]. The default is the system clock;
+//! hosts with PTP-disciplined NICs or other precise time sources can supply
+//! their own, e.g. a closure reading the NIC's hardware clock.
+//!
+//! Timestamps are kept to the nanosecond throughout: in messages, their
+//! serialized form and recordings. [`TimestampPrecision`] reads the epoch
+//! integers venues send in seconds, milliseconds, microseconds or
+//! nanoseconds, and truncates timestamps for consumers that want less.
+
+use crate::client::{ClientError, Result};
+use chrono::{DateTime, Duration, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Source of receive timestamps
+pub trait ClockSource: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The operating system's realtime clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+impl<F> ClockSource for F
+where
+    F: Fn() -> DateTime<Utc> + Send + Sync,
+{
+    fn now(&self) -> DateTime<Utc> {
+        self()
+    }
+}
+
+/// Unit of an epoch timestamp, or the precision timestamps are kept to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampPrecision {
+    Seconds,
+    Millis,
+    Micros,
+    #[default]
+    Nanos,
+}
+
+impl TimestampPrecision {
+    /// Nanoseconds in one unit
+    fn nanos(self) -> i64 {
+        match self {
+            TimestampPrecision::Seconds => 1_000_000_000,
+            TimestampPrecision::Millis => 1_000_000,
+            TimestampPrecision::Micros => 1_000,
+            TimestampPrecision::Nanos => 1,
+        }
+    }
+
+    /// The unit of a current epoch timestamp judged by its magnitude, for
+    /// venues that mix units; dates before 2001 are misjudged
+    pub fn detect(value: i64) -> Self {
+        match value.unsigned_abs() {
+            0..=999_999_999_999 => TimestampPrecision::Seconds,
+            1_000_000_000_000..=999_999_999_999_999 => TimestampPrecision::Millis,
+            1_000_000_000_000_000..=999_999_999_999_999_999 => TimestampPrecision::Micros,
+            _ => TimestampPrecision::Nanos,
+        }
+    }
+
+    /// The time `value` units after the epoch, if representable
+    pub fn from_epoch(self, value: i64) -> Option<DateTime<Utc>> {
+        let unit = self.nanos();
+        let secs = value.div_euclid(1_000_000_000 / unit);
+        let nanos = value.rem_euclid(1_000_000_000 / unit) * unit;
+        DateTime::from_timestamp(secs, nanos as u32)
+    }
+
+    /// Whole units since the epoch, saturating at the bounds of `i64` for
+    /// times too far out to count in this unit
+    pub fn to_epoch(self, ts: DateTime<Utc>) -> i64 {
+        let unit = i128::from(self.nanos());
+        let units = i128::from(ts.timestamp()) * (1_000_000_000 / unit)
+            + i128::from(ts.timestamp_subsec_nanos()) / unit;
+        units.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64
+    }
+
+    /// `ts` with digits finer than this precision dropped
+    pub fn truncate(self, ts: DateTime<Utc>) -> DateTime<Utc> {
+        let unit = self.nanos() as u32;
+        let nanos = ts.timestamp_subsec_nanos();
+        ts.with_nanosecond(nanos - nanos % unit).unwrap_or(ts)
+    }
+}
+
+/// Start of the `interval` wide bucket holding `ts`, counting buckets from
+/// the epoch; intervals under a nanosecond are taken as one
+pub(crate) fn align(ts: DateTime<Utc>, interval: Duration) -> DateTime<Utc> {
+    let width = interval.num_nanoseconds().unwrap_or(i64::MAX).max(1);
+    let nanos = ts.timestamp_nanos_opt().unwrap_or(i64::MAX);
+    DateTime::from_timestamp_nanos(nanos - nanos.rem_euclid(width))
+}
+
+impl fmt::Display for TimestampPrecision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TimestampPrecision::Seconds => "seconds",
+            TimestampPrecision::Millis => "millis",
+            TimestampPrecision::Micros => "micros",
+            TimestampPrecision::Nanos => "nanos",
+        })
+    }
+}
+
+impl FromStr for TimestampPrecision {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "s" | "seconds" => Ok(TimestampPrecision::Seconds),
+            "ms" | "millis" => Ok(TimestampPrecision::Millis),
+            "us" | "micros" => Ok(TimestampPrecision::Micros),
+            "ns" | "nanos" => Ok(TimestampPrecision::Nanos),
+            other => Err(ClientError::Parse(format!(
+                "unknown timestamp precision: {}",
+                other
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoch_units_round_trip() {
+        let ns = 1_700_000_000_123_456_789;
+        let ts = TimestampPrecision::Nanos.from_epoch(ns).unwrap();
+        for (precision, value) in [
+            (TimestampPrecision::Seconds, 1_700_000_000),
+            (TimestampPrecision::Millis, 1_700_000_000_123),
+            (TimestampPrecision::Micros, 1_700_000_000_123_456),
+            (TimestampPrecision::Nanos, ns),
+        ] {
+            assert_eq!(TimestampPrecision::detect(value), precision);
+            assert_eq!(precision.to_epoch(ts), value);
+            assert_eq!(precision.from_epoch(value), Some(precision.truncate(ts)));
+        }
+        assert_eq!(
+            TimestampPrecision::Millis
+                .from_epoch(-1)
+                .unwrap()
+                .timestamp_nanos_opt(),
+            Some(-1_000_000)
+        );
+
+        // Nanoseconds run out around 2262; coarser units still count
+        for (ts, nanos) in [
+            (DateTime::<Utc>::MAX_UTC, i64::MAX),
+            (DateTime::<Utc>::MIN_UTC, i64::MIN),
+        ] {
+            assert_eq!(TimestampPrecision::Nanos.to_epoch(ts), nanos);
+            assert_eq!(TimestampPrecision::Seconds.to_epoch(ts), ts.timestamp());
+        }
+    }
+
+    #[test]
+    fn test_align_to_interval() {
+        let ts = DateTime::from_timestamp_nanos(1_700_000_061_500_000_000);
+        assert_eq!(
+            align(ts, Duration::minutes(1)),
+            DateTime::from_timestamp(1_700_000_040, 0).unwrap()
+        );
+        // Before the epoch, buckets still start at or before `ts`
+        let early = DateTime::from_timestamp_nanos(-1);
+        assert_eq!(
+            align(early, Duration::seconds(1)),
+            DateTime::from_timestamp(-1, 0).unwrap()
+        );
+        assert_eq!(align(ts, Duration::zero()), ts);
+    }
+}
@@ -0,0 +1,247 @@
+//! Alternative wire and capture serializations.
+//!
+//! [`WireFormat`] picks JSON, MessagePack or CBOR for anything serializable.
+//! Framed output is newline-delimited for JSON and `[len: u32 BE][payload]`
+//! for the binary formats; [`FrameWriter`] and [`FrameReader`] write and
+//! read such streams, e.g. compact capture files.
+
+use crate::client::{ClientError, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, Read, Write};
+use std::str::FromStr;
+
+/// Largest frame a [`FrameReader`] accepts unless configured otherwise
+const DEFAULT_MAX_FRAME: usize = 16 * 1024 * 1024;
+
+/// Serialization of messages on the wire or on disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    #[default]
+    Json,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    Cbor,
+}
+
+impl WireFormat {
+    pub fn is_binary(&self) -> bool {
+        *self != WireFormat::Json
+    }
+
+    pub fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        let parse = |e: String| ClientError::Parse(format!("{} encode: {}", self, e));
+        match self {
+            WireFormat::Json => serde_json::to_vec(value).map_err(|e| parse(e.to_string())),
+            WireFormat::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(|e| parse(e.to_string()))
+            }
+            WireFormat::Cbor => {
+                let mut out = Vec::new();
+                ciborium::into_writer(value, &mut out).map_err(|e| parse(e.to_string()))?;
+                Ok(out)
+            }
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        let parse = |e: String| ClientError::Parse(format!("{} decode: {}", self, e));
+        match self {
+            WireFormat::Json => serde_json::from_slice(bytes).map_err(|e| parse(e.to_string())),
+            WireFormat::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(|e| parse(e.to_string()))
+            }
+            WireFormat::Cbor => ciborium::from_reader(bytes).map_err(|e| parse(e.to_string())),
+        }
+    }
+
+    /// `value` framed for a stream
+    pub fn encode_frame<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        let payload = self.encode(value)?;
+        if !self.is_binary() {
+            let mut line = payload;
+            line.push(b'\n');
+            return Ok(line);
+        }
+        let len = u32::try_from(payload.len())
+            .map_err(|_| ClientError::Parse("frame larger than 4 GiB".to_string()))?;
+        let mut frame = Vec::with_capacity(4 + payload.len());
+        frame.extend_from_slice(&len.to_be_bytes());
+        frame.extend_from_slice(&payload);
+        Ok(frame)
+    }
+}
+
+impl fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            WireFormat::Json => "json",
+            WireFormat::MessagePack => "msgpack",
+            WireFormat::Cbor => "cbor",
+        })
+    }
+}
+
+impl FromStr for WireFormat {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(WireFormat::Json),
+            "msgpack" | "messagepack" => Ok(WireFormat::MessagePack),
+            "cbor" => Ok(WireFormat::Cbor),
+            other => Err(ClientError::Parse(format!(
+                "unknown wire format: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Writes framed values in one format
+pub struct FrameWriter<W: Write> {
+    inner: W,
+    format: WireFormat,
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(inner: W, format: WireFormat) -> Self {
+        Self { inner, format }
+    }
+
+    pub fn write<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let frame = self.format.encode_frame(value)?;
+        self.inner
+            .write_all(&frame)
+            .map_err(|e| ClientError::Io(e.to_string()))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads values framed by a [`FrameWriter`] or a server in `format`
+pub struct FrameReader<R: BufRead> {
+    inner: R,
+    format: WireFormat,
+    max_frame: usize,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> FrameReader<R> {
+    pub fn new(inner: R, format: WireFormat) -> Self {
+        Self {
+            inner,
+            format,
+            max_frame: DEFAULT_MAX_FRAME,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Fail on frames or lines longer than `bytes` (default 16 MiB)
+    pub fn with_max_frame(mut self, bytes: usize) -> Self {
+        self.max_frame = bytes;
+        self
+    }
+
+    /// Next value, or `None` at the end of the stream
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<Option<T>> {
+        let io = |e: std::io::Error| ClientError::Io(e.to_string());
+        let max_frame = self.max_frame;
+        let too_long = || ClientError::Parse(format!("frame longer than {} bytes", max_frame));
+        self.buf.clear();
+        if !self.format.is_binary() {
+            let limit = self.max_frame as u64 + 1;
+            let read = (&mut self.inner)
+                .take(limit)
+                .read_until(b'\n', &mut self.buf)
+                .map_err(io)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            if read > self.max_frame {
+                return Err(too_long());
+            }
+            return self.format.decode(&self.buf).map(Some);
+        }
+        let mut len = [0u8; 4];
+        match self.inner.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(io(e)),
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len > self.max_frame {
+            return Err(too_long());
+        }
+        self.buf.resize(len, 0);
+        self.inner.read_exact(&mut self.buf).map_err(io)?;
+        self.format.decode(&self.buf).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        MarketDataMessage, OrderBookSnapshot, PriceLevel, Trade, TradeConditions, TradeSide,
+    };
+    use chrono::Utc;
+
+    #[test]
+    fn test_round_trip_matches_json() {
+        let messages = vec![
+            MarketDataMessage::Trade(Trade {
+                quantity: 0.25,
+                side: TradeSide::Sell,
+                trade_id: "42".to_string(),
+                conditions: TradeConditions::BLOCK,
+                instrument_id: Some("BBG000".into()),
+                ..Trade::test("BTCUSD", 50000.5)
+            }),
+            MarketDataMessage::OrderBook(OrderBookSnapshot {
+                symbol: "ETHUSD".into(),
+                bids: vec![PriceLevel {
+                    price: 3000.0,
+                    size: 2.0,
+                    num_orders: 3,
+                }],
+                asks: Vec::new(),
+                timestamp: Utc::now(),
+                instrument_id: None,
+                contract: None,
+                received: None,
+            }),
+            MarketDataMessage::Heartbeat,
+        ];
+        let json: Vec<serde_json::Value> = messages
+            .iter()
+            .map(|msg| serde_json::to_value(msg).unwrap())
+            .collect();
+
+        for format in [WireFormat::Json, WireFormat::MessagePack, WireFormat::Cbor] {
+            let mut writer = FrameWriter::new(Vec::new(), format);
+            for msg in &messages {
+                writer.write(msg).unwrap();
+            }
+            let bytes = writer.into_inner();
+            let mut reader = FrameReader::new(bytes.as_slice(), format);
+            let mut decoded = Vec::new();
+            while let Some(msg) = reader.read::<MarketDataMessage>().unwrap() {
+                decoded.push(serde_json::to_value(&msg).unwrap());
+            }
+            assert_eq!(decoded, json, "{}", format);
+            assert_eq!(format.to_string().parse::<WireFormat>().unwrap(), format);
+
+            let mut reader = FrameReader::new(bytes.as_slice(), format).with_max_frame(8);
+            assert!(reader.read::<MarketDataMessage>().is_err());
+        }
+        // A corrupt length is refused before anything is allocated
+        let huge = [0xffu8, 0xff, 0xff, 0xff];
+        let mut reader = FrameReader::new(huge.as_slice(), WireFormat::Cbor);
+        assert!(reader.read::<MarketDataMessage>().is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//! Struct-of-arrays batches for analytics consumers.
+//!
+//! [`TradeBatch`] and [`QuoteBatch`] hold one column per field, so
+//! downstream math runs over contiguous `f64` slices the compiler can
+//! vectorize instead of walking enum messages. Timestamps are nanoseconds
+//! since the Unix epoch. With the `flight` feature, batches move into Arrow
+//! record batches without copying the numeric columns.
+
+#[cfg(feature = "flight")]
+use crate::client::{ClientError, Result};
+use crate::types::{MarketDataMessage, Quote, Trade, TradeSide};
+#[cfg(feature = "flight")]
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, RecordBatch, StringArray, TimestampNanosecondArray,
+};
+#[cfg(feature = "flight")]
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+#[cfg(feature = "flight")]
+use std::sync::Arc;
+
+/// Trades as columns, one row per trade
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TradeBatch {
+    pub symbols: Vec<String>,
+    pub timestamps: Vec<i64>,
+    pub prices: Vec<f64>,
+    pub sizes: Vec<f64>,
+    pub sides: Vec<TradeSide>,
+}
+
+impl TradeBatch {
+    pub fn with_capacity(rows: usize) -> Self {
+        Self {
+            symbols: Vec::with_capacity(rows),
+            timestamps: Vec::with_capacity(rows),
+            prices: Vec::with_capacity(rows),
+            sizes: Vec::with_capacity(rows),
+            sides: Vec::with_capacity(rows),
+        }
+    }
+
+    /// The trades among `messages`; other messages are skipped
+    pub fn from_messages(messages: &[MarketDataMessage]) -> Self {
+        messages
+            .iter()
+            .filter_map(|msg| match msg {
+                MarketDataMessage::Trade(trade) => Some(trade),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn push(&mut self, trade: &Trade) {
+        self.symbols.push(trade.symbol.to_string());
+        self.timestamps
+            .push(trade.timestamp.timestamp_nanos_opt().unwrap_or_default());
+        self.prices.push(trade.price);
+        self.sizes.push(trade.quantity);
+        self.sides.push(trade.side);
+    }
+
+    pub fn len(&self) -> usize {
+        self.prices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prices.is_empty()
+    }
+
+    /// Volume-weighted average price of the whole batch
+    pub fn vwap(&self) -> Option<f64> {
+        let volume: f64 = self.sizes.iter().sum();
+        let notional: f64 = self
+            .prices
+            .iter()
+            .zip(&self.sizes)
+            .map(|(p, q)| p * q)
+            .sum();
+        (volume > 0.0).then(|| notional / volume)
+    }
+
+    /// Columns `symbol`, `timestamp`, `price`, `size` and `is_buy`
+    #[cfg(feature = "flight")]
+    pub fn into_record_batch(self) -> Result<RecordBatch> {
+        let schema = Schema::new(vec![
+            Field::new("symbol", DataType::Utf8, false),
+            timestamp_field(),
+            Field::new("price", DataType::Float64, false),
+            Field::new("size", DataType::Float64, false),
+            // Null when the venue did not report the aggressor
+            Field::new("is_buy", DataType::Boolean, true),
+        ]);
+        let is_buy: BooleanArray = self
+            .sides
+            .iter()
+            .map(|side| match side {
+                TradeSide::Buy => Some(true),
+                TradeSide::Sell => Some(false),
+                _ => None,
+            })
+            .collect();
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(self.symbols)),
+            timestamp_column(self.timestamps),
+            Arc::new(Float64Array::from(self.prices)),
+            Arc::new(Float64Array::from(self.sizes)),
+            Arc::new(is_buy),
+        ];
+        RecordBatch::try_new(Arc::new(schema), columns)
+            .map_err(|e| ClientError::Parse(e.to_string()))
+    }
+}
+
+impl<'a> FromIterator<&'a Trade> for TradeBatch {
+    fn from_iter<I: IntoIterator<Item = &'a Trade>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut batch = Self::with_capacity(iter.size_hint().0);
+        iter.for_each(|trade| batch.push(trade));
+        batch
+    }
+}
+
+/// Top-of-book quotes as columns, one row per quote
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuoteBatch {
+    pub symbols: Vec<String>,
+    pub timestamps: Vec<i64>,
+    pub bid_prices: Vec<f64>,
+    pub bid_sizes: Vec<f64>,
+    pub ask_prices: Vec<f64>,
+    pub ask_sizes: Vec<f64>,
+}
+
+impl QuoteBatch {
+    pub fn with_capacity(rows: usize) -> Self {
+        Self {
+            symbols: Vec::with_capacity(rows),
+            timestamps: Vec::with_capacity(rows),
+            bid_prices: Vec::with_capacity(rows),
+            bid_sizes: Vec::with_capacity(rows),
+            ask_prices: Vec::with_capacity(rows),
+            ask_sizes: Vec::with_capacity(rows),
+        }
+    }
+
+    /// The quotes among `messages`; other messages are skipped
+    pub fn from_messages(messages: &[MarketDataMessage]) -> Self {
+        messages
+            .iter()
+            .filter_map(|msg| match msg {
+                MarketDataMessage::Quote(quote) => Some(quote),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn push(&mut self, quote: &Quote) {
+        self.symbols.push(quote.symbol.to_string());
+        self.timestamps
+            .push(quote.timestamp.timestamp_nanos_opt().unwrap_or_default());
+        self.bid_prices.push(quote.bid_price);
+        self.bid_sizes.push(quote.bid_size);
+        self.ask_prices.push(quote.ask_price);
+        self.ask_sizes.push(quote.ask_size);
+    }
+
+    pub fn len(&self) -> usize {
+        self.bid_prices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bid_prices.is_empty()
+    }
+
+    /// Mid price of every row
+    pub fn mids(&self) -> Vec<f64> {
+        self.bid_prices
+            .iter()
+            .zip(&self.ask_prices)
+            .map(|(bid, ask)| (bid + ask) / 2.0)
+            .collect()
+    }
+
+    /// Columns `symbol`, `timestamp`, `bid_price`, `bid_size`, `ask_price`
+    /// and `ask_size`
+    #[cfg(feature = "flight")]
+    pub fn into_record_batch(self) -> Result<RecordBatch> {
+        let float = |name| Field::new(name, DataType::Float64, false);
+        let schema = Schema::new(vec![
+            Field::new("symbol", DataType::Utf8, false),
+            timestamp_field(),
+            float("bid_price"),
+            float("bid_size"),
+            float("ask_price"),
+            float("ask_size"),
+        ]);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(self.symbols)),
+            timestamp_column(self.timestamps),
+            Arc::new(Float64Array::from(self.bid_prices)),
+            Arc::new(Float64Array::from(self.bid_sizes)),
+            Arc::new(Float64Array::from(self.ask_prices)),
+            Arc::new(Float64Array::from(self.ask_sizes)),
+        ];
+        RecordBatch::try_new(Arc::new(schema), columns)
+            .map_err(|e| ClientError::Parse(e.to_string()))
+    }
+}
+
+impl<'a> FromIterator<&'a Quote> for QuoteBatch {
+    fn from_iter<I: IntoIterator<Item = &'a Quote>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut batch = Self::with_capacity(iter.size_hint().0);
+        iter.for_each(|quote| batch.push(quote));
+        batch
+    }
+}
+
+#[cfg(feature = "flight")]
+fn timestamp_field() -> Field {
+    Field::new(
+        "timestamp",
+        DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+        false,
+    )
+}
+
+#[cfg(feature = "flight")]
+fn timestamp_column(nanos: Vec<i64>) -> ArrayRef {
+    Arc::new(TimestampNanosecondArray::from(nanos).with_timezone("UTC"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::DateTime;
+
+    #[test]
+    fn test_batches_from_messages() {
+        let at = DateTime::from_timestamp(1_700_000_000, 5).unwrap();
+        let trade = |price, quantity| {
+            MarketDataMessage::Trade(Trade {
+                quantity,
+                timestamp: at,
+                ..Trade::test("BTCUSD", price)
+            })
+        };
+        let quote = MarketDataMessage::Quote(Quote {
+            ask_size: 2.0,
+            timestamp: at,
+            ..Quote::test("BTCUSD", 99.0, 101.0)
+        });
+        let messages = vec![trade(100.0, 1.0), quote, trade(104.0, 3.0)];
+
+        let trades = TradeBatch::from_messages(&messages);
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades.prices, vec![100.0, 104.0]);
+        assert_eq!(trades.timestamps, vec![1_700_000_000_000_000_005; 2]);
+        assert_eq!(trades.vwap(), Some(103.0));
+
+        let quotes = QuoteBatch::from_messages(&messages);
+        assert_eq!((quotes.len(), quotes.mids()), (1, vec![100.0]));
+        assert!(QuoteBatch::from_messages(&[]).is_empty());
+    }
+}
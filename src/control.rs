@@ -0,0 +1,342 @@
+//! Runtime control plane for long-running streamers.
+//!
+//! A [`ControlHandle`] delivers [`ControlCommand`]s to a running
+//! [`MarketDataClient`](crate::MarketDataClient) over an mpsc channel. On unix
+//! targets the same commands can be issued through a local socket with
+//! [`serve_unix`].
+
+use crate::client::{ClientError, Result};
+use crate::logging::LogConfig;
+use crate::types::OrderBookSnapshot;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc::error::SendTimeoutError;
+use tokio::sync::{mpsc, oneshot};
+use tracing::level_filters::LevelFilter;
+use tracing::{info, warn};
+use tracing_subscriber::{reload, Registry};
+
+/// Administrative commands accepted by a running client
+#[derive(Debug)]
+pub enum ControlCommand {
+    /// Stop forwarding messages to subscribers
+    PauseSink,
+    /// Resume forwarding messages to subscribers
+    ResumeSink,
+    /// Re-send the subscription message on the current connection
+    Resubscribe,
+    /// Subscribe to and drop symbols on the current connection, for venues
+    /// that subscribe by symbol
+    UpdateSymbols {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    /// Flush any buffered recorder output to disk
+    FlushRecorder,
+    /// Change the global log level (requires [`init_tracing`])
+    SetLogLevel(LevelFilter),
+    /// Return the latest order book snapshot seen for a symbol
+    SnapshotBook {
+        symbol: String,
+        reply: oneshot::Sender<Option<OrderBookSnapshot>>,
+    },
+}
+
+/// How long a command waits for room in the queue, or for its reply
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Cloneable handle used to send commands to a running client
+#[derive(Debug, Clone)]
+pub struct ControlHandle {
+    tx: mpsc::Sender<ControlCommand>,
+}
+
+impl ControlHandle {
+    pub(crate) fn new(tx: mpsc::Sender<ControlCommand>) -> Self {
+        Self { tx }
+    }
+
+    /// Send a raw command; fails if the client is gone, or not running
+    /// and its queue is full
+    pub async fn send(&self, command: ControlCommand) -> Result<()> {
+        self.tx
+            .send_timeout(command, COMMAND_TIMEOUT)
+            .await
+            .map_err(|e| match e {
+                SendTimeoutError::Closed(_) => {
+                    ClientError::Control("control channel closed".to_string())
+                }
+                SendTimeoutError::Timeout(_) => {
+                    ClientError::Control("client is not taking commands".to_string())
+                }
+            })
+    }
+
+    pub async fn pause_sink(&self) -> Result<()> {
+        self.send(ControlCommand::PauseSink).await
+    }
+
+    pub async fn resume_sink(&self) -> Result<()> {
+        self.send(ControlCommand::ResumeSink).await
+    }
+
+    pub async fn resubscribe(&self) -> Result<()> {
+        self.send(ControlCommand::Resubscribe).await
+    }
+
+    pub async fn subscribe_symbols(&self, symbols: &[&str]) -> Result<()> {
+        self.send(ControlCommand::UpdateSymbols {
+            add: symbols.iter().map(|s| s.to_string()).collect(),
+            remove: Vec::new(),
+        })
+        .await
+    }
+
+    pub async fn unsubscribe_symbols(&self, symbols: &[&str]) -> Result<()> {
+        self.send(ControlCommand::UpdateSymbols {
+            add: Vec::new(),
+            remove: symbols.iter().map(|s| s.to_string()).collect(),
+        })
+        .await
+    }
+
+    pub async fn flush_recorder(&self) -> Result<()> {
+        self.send(ControlCommand::FlushRecorder).await
+    }
+
+    pub async fn set_log_level(&self, level: LevelFilter) -> Result<()> {
+        self.send(ControlCommand::SetLogLevel(level)).await
+    }
+
+    /// Request the latest book snapshot for `symbol`
+    pub async fn snapshot_book(&self, symbol: &str) -> Result<Option<OrderBookSnapshot>> {
+        let (reply, rx) = oneshot::channel();
+        self.send(ControlCommand::SnapshotBook {
+            symbol: symbol.to_string(),
+            reply,
+        })
+        .await?;
+        tokio::time::timeout(COMMAND_TIMEOUT, rx)
+            .await
+            .map_err(|_| ClientError::Control("client is not running".to_string()))?
+            .map_err(|_| ClientError::Control("client dropped snapshot request".to_string()))
+    }
+}
+
+/// Textual form of a command, as used by the unix socket protocol
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlRequest {
+    PauseSink,
+    ResumeSink,
+    Resubscribe,
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+    FlushRecorder,
+    SetLogLevel(LevelFilter),
+    SnapshotBook(String),
+}
+
+impl FromStr for ControlRequest {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split_whitespace();
+        let command = parts.next().unwrap_or_default();
+        let arg = parts.next();
+
+        match (command, arg) {
+            ("pause", None) => Ok(Self::PauseSink),
+            ("resume", None) => Ok(Self::ResumeSink),
+            ("resubscribe", None) => Ok(Self::Resubscribe),
+            ("subscribe", Some(symbols)) => Ok(Self::Subscribe(split_symbols(symbols))),
+            ("unsubscribe", Some(symbols)) => Ok(Self::Unsubscribe(split_symbols(symbols))),
+            ("flush", None) => Ok(Self::FlushRecorder),
+            ("log-level", Some(level)) => LevelFilter::from_str(level)
+                .map(Self::SetLogLevel)
+                .map_err(|e| ClientError::Control(e.to_string())),
+            ("snapshot", Some(symbol)) => Ok(Self::SnapshotBook(symbol.to_string())),
+            _ => Err(ClientError::Control(format!(
+                "unknown command: {}",
+                s.trim()
+            ))),
+        }
+    }
+}
+
+fn split_symbols(list: &str) -> Vec<String> {
+    list.split(',')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+impl ControlHandle {
+    /// Execute a parsed request and render the textual reply
+    pub async fn execute(&self, request: ControlRequest) -> Result<String> {
+        match request {
+            ControlRequest::PauseSink => self.pause_sink().await?,
+            ControlRequest::ResumeSink => self.resume_sink().await?,
+            ControlRequest::Resubscribe => self.resubscribe().await?,
+            ControlRequest::Subscribe(add) => {
+                self.send(ControlCommand::UpdateSymbols {
+                    add,
+                    remove: Vec::new(),
+                })
+                .await?
+            }
+            ControlRequest::Unsubscribe(remove) => {
+                self.send(ControlCommand::UpdateSymbols {
+                    add: Vec::new(),
+                    remove,
+                })
+                .await?
+            }
+            ControlRequest::FlushRecorder => self.flush_recorder().await?,
+            ControlRequest::SetLogLevel(level) => self.set_log_level(level).await?,
+            ControlRequest::SnapshotBook(symbol) => {
+                let snapshot = self.snapshot_book(&symbol).await?;
+                return serde_json::to_string(&snapshot)
+                    .map_err(|e| ClientError::Parse(e.to_string()));
+            }
+        }
+        Ok("ok".to_string())
+    }
+}
+
+pub(crate) static LOG_LEVEL_HANDLE: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+/// Install a global tracing subscriber whose level can be changed at runtime
+/// through [`ControlCommand::SetLogLevel`]; see [`LogConfig`] for JSON output
+pub fn init_tracing(level: LevelFilter) -> Result<()> {
+    LogConfig::new(level).init()
+}
+
+pub(crate) fn apply_log_level(level: LevelFilter) {
+    match LOG_LEVEL_HANDLE.get() {
+        Some(handle) => match handle.reload(level) {
+            Ok(()) => info!("Log level set to {}", level),
+            Err(e) => warn!("Failed to change log level: {}", e),
+        },
+        None => {
+            warn!("Log level change ignored, tracing not initialised via control::init_tracing or LogConfig")
+        }
+    }
+}
+
+/// Serve control commands on a unix socket, one command per line.
+///
+/// A socket left at `path` by an earlier run is replaced; any other file
+/// there is an error rather than being deleted.
+#[cfg(unix)]
+pub async fn serve_unix(path: impl AsRef<std::path::Path>, handle: ControlHandle) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+
+    let path = path.as_ref();
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            std::fs::remove_file(path).map_err(|e| ClientError::Control(e.to_string()))?
+        }
+        Ok(_) => {
+            return Err(ClientError::Control(format!(
+                "{} exists and is not a socket",
+                path.display()
+            )))
+        }
+        Err(_) => {}
+    }
+    let listener = UnixListener::bind(path).map_err(|e| ClientError::Control(e.to_string()))?;
+    info!("Control socket listening on {}", path.display());
+
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| ClientError::Control(e.to_string()))?;
+        let handle = handle.clone();
+
+        tokio::spawn(async move {
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply = match line.parse::<ControlRequest>() {
+                    Ok(request) => handle.execute(request).await,
+                    Err(e) => Err(e),
+                };
+                let reply = match reply {
+                    Ok(text) => text,
+                    Err(e) => format!("error: {}", e),
+                };
+                if write
+                    .write_all(format!("{}\n", reply).as_bytes())
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_requests() {
+        assert_eq!(
+            "pause".parse::<ControlRequest>().unwrap(),
+            ControlRequest::PauseSink
+        );
+        assert_eq!(
+            "log-level debug".parse::<ControlRequest>().unwrap(),
+            ControlRequest::SetLogLevel(LevelFilter::DEBUG)
+        );
+        assert_eq!(
+            "snapshot BTCUSD".parse::<ControlRequest>().unwrap(),
+            ControlRequest::SnapshotBook("BTCUSD".to_string())
+        );
+        assert_eq!(
+            "subscribe BTCUSD,ETHUSD".parse::<ControlRequest>().unwrap(),
+            ControlRequest::Subscribe(vec!["BTCUSD".to_string(), "ETHUSD".to_string()])
+        );
+        assert!("explode".parse::<ControlRequest>().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_commands_to_stopped_client_fail() {
+        let (tx, rx) = mpsc::channel(1);
+        let control = ControlHandle::new(tx);
+        // Queued, but nobody is reading
+        assert!(control.snapshot_book("BTCUSD").await.is_err());
+        assert!(control.pause_sink().await.is_err());
+        drop(rx);
+        assert!(control.resume_sink().await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_serve_unix_keeps_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        std::fs::write(&path, b"not a socket").unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+        assert!(serve_unix(&path, ControlHandle::new(tx)).await.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"not a socket");
+
+        // A stale socket is replaced
+        std::fs::remove_file(&path).unwrap();
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let (tx, _rx) = mpsc::channel(1);
+        let server = tokio::spawn(serve_unix(path.clone(), ControlHandle::new(tx)));
+        while tokio::net::UnixStream::connect(&path).await.is_err() {
+            assert!(!server.is_finished());
+            tokio::task::yield_now().await;
+        }
+        server.abort();
+    }
+}
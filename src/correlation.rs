@@ -0,0 +1,504 @@
+//! Rolling cross-symbol return statistics.
+//!
+//! Prices (last trade or quote mid) are sampled on a fixed event-time grid,
+//! turned into log returns and kept in a rolling window whose means and
+//! co-moments are updated incrementally, so each sample costs O(n²) in the
+//! number of symbols regardless of the window length. The co-moments are
+//! recomputed from the window once per window length, so rounding drift from
+//! removing old returns cannot build up over a long-running session.
+
+use crate::clock::align;
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Pairwise return correlations at one sample time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorrelationMatrix {
+    pub timestamp: DateTime<Utc>,
+    pub symbols: Vec<Symbol>,
+    /// Row-major, `NaN` where a symbol's returns have no variance
+    pub values: Vec<Vec<f64>>,
+    /// Returns in the window
+    pub samples: usize,
+}
+
+/// One symbol's performance against the benchmark over the window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelativeStrength {
+    pub symbol: Symbol,
+    /// Sensitivity of the symbol's returns to the benchmark's, `NaN` while
+    /// the benchmark has no variance
+    pub beta: f64,
+    pub correlation: f64,
+    /// Log return over the window
+    pub total_return: f64,
+    /// Log return in excess of the benchmark's
+    pub relative_return: f64,
+}
+
+/// Rolling beta and relative strength of each symbol at one sample time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BetaReport {
+    pub timestamp: DateTime<Utc>,
+    pub benchmark: Symbol,
+    /// Log return of the benchmark over the window
+    pub benchmark_return: f64,
+    pub symbols: Vec<RelativeStrength>,
+    pub samples: usize,
+}
+
+impl CorrelationMatrix {
+    pub fn get(&self, a: &str, b: &str) -> Option<f64> {
+        let i = self.symbols.iter().position(|s| s == a)?;
+        let j = self.symbols.iter().position(|s| s == b)?;
+        Some(self.values[i][j])
+    }
+}
+
+/// Synchronously sampled log returns of a fixed symbol set with running
+/// means and co-moments over a rolling window
+#[derive(Debug, Clone)]
+pub(crate) struct ReturnWindow {
+    symbols: Vec<Symbol>,
+    index: HashMap<Symbol, usize>,
+    interval: Duration,
+    capacity: usize,
+    prices: Vec<Option<f64>>,
+    sampled: Vec<Option<f64>>,
+    next_tick: Option<DateTime<Utc>>,
+    returns: VecDeque<Vec<f64>>,
+    means: Vec<f64>,
+    /// Row-major sums of products of deviations from the means
+    comoments: Vec<f64>,
+    /// Samples pushed since the co-moments were last recomputed
+    since_recompute: usize,
+}
+
+impl ReturnWindow {
+    pub(crate) fn new(symbols: &[&str], interval: Duration, window: Duration) -> Self {
+        let n = symbols.len();
+        let width = interval.num_nanoseconds().unwrap_or(i64::MAX).max(1);
+        let capacity = (window.num_nanoseconds().unwrap_or(i64::MAX) / width).max(2) as usize;
+        let symbols: Vec<Symbol> = symbols.iter().map(|&s| Symbol::from(s)).collect();
+        Self {
+            index: symbols.iter().enumerate().map(|(i, &s)| (s, i)).collect(),
+            symbols,
+            interval,
+            capacity,
+            prices: vec![None; n],
+            sampled: vec![None; n],
+            next_tick: None,
+            returns: VecDeque::with_capacity(capacity),
+            means: vec![0.0; n],
+            comoments: vec![0.0; n * n],
+            since_recompute: 0,
+        }
+    }
+
+    pub(crate) fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.returns.len()
+    }
+
+    /// Take the message's price after sampling any tick due before it;
+    /// returns the tick time if a sample was taken
+    pub(crate) fn on_message(&mut self, msg: &MarketDataMessage) -> Option<DateTime<Utc>> {
+        let ts = msg.timestamp()?;
+        let sampled = self.poll(ts);
+        let (symbol, price) = match msg {
+            MarketDataMessage::Trade(trade) => (&trade.symbol, trade.price),
+            MarketDataMessage::Quote(quote) => (&quote.symbol, quote.mid_price()),
+            MarketDataMessage::OrderBook(book) => (&book.symbol, book.mid_price()?),
+            _ => return sampled,
+        };
+        if let Some(&i) = self.index.get(symbol) {
+            if price > 0.0 {
+                self.prices[i] = Some(price);
+            }
+        }
+        sampled
+    }
+
+    fn poll(&mut self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let tick = align(now, self.interval);
+        let Some(next) = self.next_tick else {
+            self.next_tick = Some(tick + self.interval);
+            return None;
+        };
+        if now < next {
+            return None;
+        }
+        self.next_tick = Some(tick + self.interval);
+
+        // A sample needs a price for every symbol now and at the last tick
+        let returns: Option<Vec<f64>> = self
+            .prices
+            .iter()
+            .zip(&self.sampled)
+            .map(|(price, prev)| Some((price.as_ref()? / prev.as_ref()?).ln()))
+            .collect();
+        self.sampled.clone_from(&self.prices);
+        self.push(returns?);
+        Some(tick)
+    }
+
+    fn push(&mut self, returns: Vec<f64>) {
+        if self.returns.len() == self.capacity {
+            if let Some(old) = self.returns.pop_front() {
+                self.remove(&old);
+            }
+        }
+        self.add(&returns);
+        self.returns.push_back(returns);
+        self.since_recompute += 1;
+        if self.since_recompute >= self.capacity {
+            self.recompute();
+        }
+    }
+
+    /// Welford update for a return vector joining the window
+    fn add(&mut self, returns: &[f64]) {
+        let count = (self.returns.len() + 1) as f64;
+        let deltas = deviations(returns, &self.means);
+        for (mean, delta) in self.means.iter_mut().zip(&deltas) {
+            *mean += delta / count;
+        }
+        let after = deviations(returns, &self.means);
+        accumulate(&mut self.comoments, &deltas, &after);
+    }
+
+    /// Inverse of [`add`](Self::add) for a return vector leaving the window
+    fn remove(&mut self, returns: &[f64]) {
+        let count = self.returns.len() as f64;
+        if count == 0.0 {
+            self.means.fill(0.0);
+            self.comoments.fill(0.0);
+            return;
+        }
+        let remaining: Vec<f64> = returns
+            .iter()
+            .zip(&self.means)
+            .map(|(x, m)| m - (x - m) / count)
+            .collect();
+        // Negated, to take back what adding the vector contributed
+        let before: Vec<f64> = remaining.iter().zip(returns).map(|(m, x)| m - x).collect();
+        let after = deviations(returns, &self.means);
+        accumulate(&mut self.comoments, &before, &after);
+        self.means = remaining;
+    }
+
+    /// Two-pass means and co-moments of the whole window
+    fn recompute(&mut self) {
+        let count = self.returns.len().max(1) as f64;
+        self.since_recompute = 0;
+        self.means.fill(0.0);
+        self.comoments.fill(0.0);
+        for returns in &self.returns {
+            for (mean, x) in self.means.iter_mut().zip(returns) {
+                *mean += x / count;
+            }
+        }
+        for returns in &self.returns {
+            let deviations = deviations(returns, &self.means);
+            accumulate(&mut self.comoments, &deviations, &deviations);
+        }
+    }
+
+    /// Summed log return of symbol `i` over the window
+    pub(crate) fn total_return(&self, i: usize) -> f64 {
+        self.means[i] * self.returns.len() as f64
+    }
+
+    /// Population covariance of the returns of symbols `i` and `j`
+    pub(crate) fn covariance(&self, i: usize, j: usize) -> f64 {
+        let cross = self.comoments[i * self.symbols.len() + j] / self.returns.len() as f64;
+        if i == j {
+            // Rounding must not turn a constant series' variance negative
+            cross.max(0.0)
+        } else {
+            cross
+        }
+    }
+
+    pub(crate) fn correlation(&self, i: usize, j: usize) -> f64 {
+        let denominator = (self.covariance(i, i) * self.covariance(j, j)).sqrt();
+        if denominator > 0.0 {
+            (self.covariance(i, j) / denominator).clamp(-1.0, 1.0)
+        } else {
+            f64::NAN
+        }
+    }
+}
+
+fn deviations(returns: &[f64], means: &[f64]) -> Vec<f64> {
+    returns.iter().zip(means).map(|(x, m)| x - m).collect()
+}
+
+/// Add the outer product of `a` and `b` to the row-major `comoments`
+fn accumulate(comoments: &mut [f64], a: &[f64], b: &[f64]) {
+    for (row, a) in comoments.chunks_mut(b.len().max(1)).zip(a) {
+        for (comoment, b) in row.iter_mut().zip(b) {
+            *comoment += a * b;
+        }
+    }
+}
+
+/// Rolling pairwise correlations of sampled returns across a symbol set.
+///
+/// A [`CorrelationMatrix`] goes to receivers from
+/// [`subscribe`](Self::subscribe) every `publish_every` samples.
+pub struct CorrelationTracker {
+    returns: ReturnWindow,
+    publish_every: usize,
+    since_publish: usize,
+    tx: broadcast::Sender<CorrelationMatrix>,
+}
+
+impl CorrelationTracker {
+    /// Correlations of returns sampled every `interval` over `window`
+    pub fn new(symbols: &[&str], interval: Duration, window: Duration) -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            returns: ReturnWindow::new(symbols, interval, window),
+            publish_every: 1,
+            since_publish: 0,
+            tx,
+        }
+    }
+
+    /// Publish a matrix every `samples` samples instead of every one
+    pub fn with_publish_every(mut self, samples: usize) -> Self {
+        self.publish_every = samples.max(1);
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<CorrelationMatrix> {
+        self.tx.subscribe()
+    }
+
+    /// Feed a message, returning the matrix if one was published
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Option<CorrelationMatrix> {
+        let tick = self.returns.on_message(msg)?;
+        self.since_publish += 1;
+        if self.since_publish < self.publish_every || self.returns.len() < 2 {
+            return None;
+        }
+        self.since_publish = 0;
+        let matrix = self.matrix(tick);
+        let _ = self.tx.send(matrix.clone());
+        Some(matrix)
+    }
+
+    /// Correlations over the current window
+    pub fn matrix(&self, timestamp: DateTime<Utc>) -> CorrelationMatrix {
+        let n = self.returns.symbols().len();
+        CorrelationMatrix {
+            timestamp,
+            symbols: self.returns.symbols().to_vec(),
+            values: (0..n)
+                .map(|i| (0..n).map(|j| self.returns.correlation(i, j)).collect())
+                .collect(),
+            samples: self.returns.len(),
+        }
+    }
+}
+
+impl Stage for CorrelationTracker {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+/// Rolling beta and relative performance of symbols against a benchmark
+/// such as `BTCUSD`, sampled and published like [`CorrelationTracker`]
+pub struct BetaTracker {
+    returns: ReturnWindow,
+    publish_every: usize,
+    since_publish: usize,
+    tx: broadcast::Sender<BetaReport>,
+}
+
+impl BetaTracker {
+    pub fn new(benchmark: &str, symbols: &[&str], interval: Duration, window: Duration) -> Self {
+        let all: Vec<&str> = std::iter::once(benchmark)
+            .chain(symbols.iter().copied().filter(|&s| s != benchmark))
+            .collect();
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            returns: ReturnWindow::new(&all, interval, window),
+            publish_every: 1,
+            since_publish: 0,
+            tx,
+        }
+    }
+
+    /// Publish a report every `samples` samples instead of every one
+    pub fn with_publish_every(mut self, samples: usize) -> Self {
+        self.publish_every = samples.max(1);
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<BetaReport> {
+        self.tx.subscribe()
+    }
+
+    /// Feed a message, returning the report if one was published
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Option<BetaReport> {
+        let tick = self.returns.on_message(msg)?;
+        self.since_publish += 1;
+        if self.since_publish < self.publish_every || self.returns.len() < 2 {
+            return None;
+        }
+        self.since_publish = 0;
+        let report = self.report(tick);
+        let _ = self.tx.send(report.clone());
+        Some(report)
+    }
+
+    /// Beta and relative strength over the current window
+    pub fn report(&self, timestamp: DateTime<Utc>) -> BetaReport {
+        let returns = &self.returns;
+        let variance = returns.covariance(0, 0);
+        let benchmark_return = returns.total_return(0);
+        BetaReport {
+            timestamp,
+            benchmark: returns.symbols()[0],
+            benchmark_return,
+            symbols: (1..returns.symbols().len())
+                .map(|i| RelativeStrength {
+                    symbol: returns.symbols()[i],
+                    beta: if variance > 0.0 {
+                        returns.covariance(i, 0) / variance
+                    } else {
+                        f64::NAN
+                    },
+                    correlation: returns.correlation(i, 0),
+                    total_return: returns.total_return(i),
+                    relative_return: returns.total_return(i) - benchmark_return,
+                })
+                .collect(),
+            samples: returns.len(),
+        }
+    }
+}
+
+impl Stage for BetaTracker {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Trade;
+    use chrono::TimeZone;
+
+    fn trade(symbol: &str, ts: DateTime<Utc>, price: f64) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            timestamp: ts,
+            trade_id: "1".to_string(),
+            ..Trade::test(symbol, price)
+        })
+    }
+
+    #[test]
+    fn test_rolling_correlations() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut tracker = CorrelationTracker::new(
+            &["BTCUSD", "ETHUSD", "SOLUSD"],
+            Duration::seconds(1),
+            Duration::seconds(10),
+        );
+        let mut rx = tracker.subscribe();
+
+        // ETH moves with BTC at twice the size, SOL against it
+        let mut prices = [40_000.0, 2_000.0, 100.0];
+        let mut published = None;
+        for (step, pct) in [1.0, -2.0, 0.5, 3.0, -1.0, 2.0].into_iter().enumerate() {
+            let ts = t0 + Duration::milliseconds(500 + 1000 * step as i64);
+            for (price, scale) in prices.iter_mut().zip([1.0, 2.0, -1.0]) {
+                *price *= 1.0 + scale * pct / 100.0;
+            }
+            for msg in [
+                trade("BTCUSD", ts, prices[0]),
+                trade("ETHUSD", ts, prices[1]),
+                trade("SOLUSD", ts, prices[2]),
+            ] {
+                published = tracker.on_message(&msg).or(published);
+            }
+        }
+
+        let matrix = published.unwrap();
+        assert_eq!(rx.try_recv().unwrap().timestamp, t0 + Duration::seconds(3));
+        assert_eq!(matrix.samples, 4);
+        assert_eq!(matrix.get("BTCUSD", "BTCUSD"), Some(1.0));
+        assert!(matrix.get("BTCUSD", "ETHUSD").unwrap() > 0.99);
+        assert!(matrix.get("BTCUSD", "SOLUSD").unwrap() < -0.99);
+    }
+
+    #[test]
+    fn test_moments_stay_accurate_over_a_long_session() {
+        let mut window = ReturnWindow::new(&["A", "B"], Duration::seconds(1), Duration::seconds(7));
+        // A large common offset with tiny variation is where raw sums of
+        // products lose every significant digit
+        for k in 0..10_000 {
+            let x = (k as f64 * 0.7).sin() * 1e-4;
+            window.push(vec![1e3 + x, 1e3 - 2.0 * x]);
+            if k % 100 == 3 {
+                let mut exact = window.clone();
+                exact.recompute();
+                for (i, j) in [(0, 0), (0, 1), (1, 1)] {
+                    let (running, exact) = (window.covariance(i, j), exact.covariance(i, j));
+                    assert!(
+                        (running - exact).abs() <= 1e-6 * exact.abs(),
+                        "{running} vs {exact}"
+                    );
+                }
+                assert!((window.correlation(0, 1) + 1.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_beta_against_benchmark() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut tracker = BetaTracker::new(
+            "BTCUSD",
+            &["BTCUSD", "ETHUSD"],
+            Duration::minutes(1),
+            Duration::minutes(30),
+        );
+
+        // ETH returns are exactly 1.5 times BTC's in log terms
+        let mut report = None;
+        let mut btc: f64 = 40_000.0;
+        for (step, r) in [0.01, -0.02, 0.015, 0.005, -0.01].into_iter().enumerate() {
+            let ts = t0 + Duration::seconds(30 + 60 * step as i64);
+            btc *= f64::exp(r);
+            let eth = 2_000.0 * (btc / 40_000.0).powf(1.5);
+            report = tracker.on_message(&trade("BTCUSD", ts, btc)).or(report);
+            tracker.on_message(&trade("ETHUSD", ts, eth));
+        }
+
+        let report = report.unwrap();
+        assert_eq!(report.benchmark, "BTCUSD");
+        assert_eq!(report.samples, 3);
+        let eth = &report.symbols[0];
+        assert_eq!(eth.symbol, "ETHUSD");
+        assert!((eth.beta - 1.5).abs() < 1e-9);
+        assert!((eth.relative_return - 0.5 * report.benchmark_return).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,427 @@
+//! Locked and crossed market detection.
+//!
+//! A market is locked when the best bid equals the best ask and crossed when
+//! it is above it. On a single venue either usually means a feed problem
+//! (missed deletes, stale levels); on the consolidated book across venues it
+//! is an arbitrage window. [`CrossedMarketDetector`] checks both and reports
+//! when each condition starts and how long it lasted once it clears.
+
+use crate::symbology::{Symbol, Venue};
+use crate::types::{MarketDataMessage, Quote};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Abnormal top of book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BookCondition {
+    /// Best bid equals best ask
+    Locked,
+    /// Best bid above best ask
+    Crossed,
+}
+
+impl BookCondition {
+    fn of(bid: f64, ask: f64) -> Option<Self> {
+        if bid > ask {
+            Some(Self::Crossed)
+        } else if bid == ask {
+            Some(Self::Locked)
+        } else {
+            None
+        }
+    }
+}
+
+/// Start or end of a locked or crossed condition
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum CrossedMarketEvent {
+    Started {
+        symbol: Symbol,
+        /// Venue whose own book is affected, `None` for the consolidated book
+        venue: Option<Venue>,
+        condition: BookCondition,
+        bid: f64,
+        ask: f64,
+        timestamp: DateTime<Utc>,
+    },
+    Ended {
+        symbol: Symbol,
+        venue: Option<Venue>,
+        condition: BookCondition,
+        /// How long the condition lasted
+        duration_ms: i64,
+        timestamp: DateTime<Utc>,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Active {
+    condition: BookCondition,
+    since: DateTime<Utc>,
+}
+
+/// Tracks top of book per venue and flags locked and crossed markets on each
+/// venue and across them.
+///
+/// A venue's quote is dropped once it is more than the maximum quote age
+/// older than the symbol's newest quote, or when its book goes one-sided,
+/// ending any condition it was part of rather than comparing against a
+/// price the venue no longer shows.
+#[derive(Debug, Clone)]
+pub struct CrossedMarketDetector {
+    max_quote_age: Duration,
+    quotes: HashMap<Symbol, HashMap<Venue, Quote>>,
+    /// Ongoing conditions keyed by `(symbol, venue)`
+    active: HashMap<(Symbol, Option<Venue>), Active>,
+}
+
+impl Default for CrossedMarketDetector {
+    fn default() -> Self {
+        Self {
+            max_quote_age: Duration::seconds(5),
+            quotes: HashMap::new(),
+            active: HashMap::new(),
+        }
+    }
+}
+
+impl CrossedMarketDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop venue quotes older than `max_age` relative to the symbol's
+    /// newest quote; five seconds by default
+    pub fn with_max_quote_age(mut self, max_age: Duration) -> Self {
+        self.max_quote_age = max_age;
+        self
+    }
+
+    /// Feed a message received from `venue`
+    pub fn on_message(&mut self, venue: &str, msg: &MarketDataMessage) -> Vec<CrossedMarketEvent> {
+        match msg {
+            MarketDataMessage::Quote(quote) => self.on_quote(venue, quote),
+            MarketDataMessage::OrderBook(book) => {
+                let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask()) else {
+                    // A one-sided book has no top of book to compare
+                    let mut events = Vec::new();
+                    self.withdraw(book.symbol, venue, book.timestamp, &mut events);
+                    self.consolidate(book.symbol, book.timestamp, &mut events);
+                    return events;
+                };
+                self.on_quote(
+                    venue,
+                    &Quote {
+                        symbol: book.symbol,
+                        bid_price: bid.price,
+                        bid_size: bid.size,
+                        ask_price: ask.price,
+                        ask_size: ask.size,
+                        timestamp: book.timestamp,
+                        instrument_id: None,
+                        contract: None,
+                        received: None,
+                    },
+                )
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Update a venue's top of book and check it and the consolidated book
+    pub fn on_quote(&mut self, venue: &str, quote: &Quote) -> Vec<CrossedMarketEvent> {
+        let mut events = Vec::new();
+        let (symbol, at) = (quote.symbol, quote.timestamp);
+        self.transition(
+            &symbol,
+            Some(venue),
+            Some((quote.bid_price, quote.ask_price)),
+            at,
+            &mut events,
+        );
+
+        let venues = self.quotes.entry(symbol).or_default();
+        venues.insert(venue.into(), quote.clone());
+        let newest = venues.values().map(|quote| quote.timestamp).max();
+        if let Some(newest) = newest {
+            self.drop_older(symbol, newest - self.max_quote_age, at, &mut events);
+        }
+        self.consolidate(symbol, at, &mut events);
+        events
+    }
+
+    /// Drop quotes older than the maximum quote age at `now`, for venues
+    /// that stopped quoting while no other venue did either
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Vec<CrossedMarketEvent> {
+        let mut events = Vec::new();
+        let symbols: Vec<Symbol> = self.quotes.keys().copied().collect();
+        for symbol in symbols {
+            self.drop_older(symbol, now - self.max_quote_age, now, &mut events);
+            self.consolidate(symbol, now, &mut events);
+        }
+        events
+    }
+
+    /// Remove quotes of `symbol` from before `cutoff`
+    fn drop_older(
+        &mut self,
+        symbol: Symbol,
+        cutoff: DateTime<Utc>,
+        at: DateTime<Utc>,
+        events: &mut Vec<CrossedMarketEvent>,
+    ) {
+        let stale: Vec<Venue> = self.quotes.get(&symbol).map_or_else(Vec::new, |venues| {
+            venues
+                .iter()
+                .filter(|(_, quote)| quote.timestamp < cutoff)
+                .map(|(venue, _)| *venue)
+                .collect()
+        });
+        for venue in stale {
+            self.withdraw(symbol, &venue, at, events);
+        }
+    }
+
+    /// Remove a venue's quote, ending the condition of its own book
+    fn withdraw(
+        &mut self,
+        symbol: Symbol,
+        venue: &str,
+        at: DateTime<Utc>,
+        events: &mut Vec<CrossedMarketEvent>,
+    ) {
+        self.transition(&symbol, Some(venue), None, at, events);
+        if let Some(venues) = self.quotes.get_mut(&symbol) {
+            venues.remove(venue);
+            if venues.is_empty() {
+                self.quotes.remove(&symbol);
+            }
+        }
+    }
+
+    /// Check the consolidated book of `symbol` across the venues quoting it
+    fn consolidate(
+        &mut self,
+        symbol: Symbol,
+        at: DateTime<Utc>,
+        events: &mut Vec<CrossedMarketEvent>,
+    ) {
+        // A single venue's book is already checked on its own
+        let consolidated = self
+            .quotes
+            .get(&symbol)
+            .filter(|venues| venues.len() > 1)
+            .map(|venues| {
+                let bid = venues
+                    .values()
+                    .map(|q| q.bid_price)
+                    .fold(f64::MIN, f64::max);
+                let ask = venues
+                    .values()
+                    .map(|q| q.ask_price)
+                    .fold(f64::MAX, f64::min);
+                (bid, ask)
+            });
+        self.transition(&symbol, None, consolidated, at, events);
+    }
+
+    /// Conditions in progress as `(symbol, venue, condition, duration so
+    /// far)`, `venue` being `None` for the consolidated book
+    pub fn active(&self, now: DateTime<Utc>) -> Vec<(&str, Option<&str>, BookCondition, Duration)> {
+        self.active
+            .iter()
+            .map(|((symbol, venue), active)| {
+                (
+                    symbol.as_str(),
+                    venue.as_deref(),
+                    active.condition,
+                    now - active.since,
+                )
+            })
+            .collect()
+    }
+
+    fn transition(
+        &mut self,
+        symbol: &str,
+        venue: Option<&str>,
+        top: Option<(f64, f64)>,
+        at: DateTime<Utc>,
+        events: &mut Vec<CrossedMarketEvent>,
+    ) {
+        let key = (Symbol::from(symbol), venue.map(Venue::from));
+        let condition = top.and_then(|(bid, ask)| BookCondition::of(bid, ask));
+        let previous = self.active.get(&key).copied();
+        if previous.map(|p| p.condition) == condition {
+            return;
+        }
+
+        if let Some(previous) = previous {
+            self.active.remove(&key);
+            events.push(CrossedMarketEvent::Ended {
+                symbol: key.0,
+                venue: key.1,
+                condition: previous.condition,
+                duration_ms: (at - previous.since).num_milliseconds(),
+                timestamp: at,
+            });
+        }
+        if let (Some(condition), Some((bid, ask))) = (condition, top) {
+            events.push(CrossedMarketEvent::Started {
+                symbol: key.0,
+                venue: key.1,
+                condition,
+                bid,
+                ask,
+                timestamp: at,
+            });
+            self.active.insert(
+                key,
+                Active {
+                    condition,
+                    since: at,
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{OrderBookSnapshot, PriceLevel};
+    use chrono::TimeZone;
+
+    fn quote(bid: f64, ask: f64, second: u32) -> Quote {
+        Quote {
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, second).unwrap(),
+            ..Quote::test("BTCUSD", bid, ask)
+        }
+    }
+
+    #[test]
+    fn test_venue_and_consolidated_conditions() {
+        let mut detector = CrossedMarketDetector::new();
+        assert!(detector
+            .on_quote("coinbase", &quote(100.0, 101.0, 0))
+            .is_empty());
+
+        // Kraken's bid meets Coinbase's ask: the consolidated book locks
+        let events = detector.on_quote("kraken", &quote(101.0, 102.0, 1));
+        assert!(matches!(
+            &events[..],
+            [CrossedMarketEvent::Started {
+                venue: None,
+                condition: BookCondition::Locked,
+                ..
+            }]
+        ));
+
+        // Then Kraken's own book crosses, which also crosses the consolidated one
+        let events = detector.on_quote("kraken", &quote(103.0, 102.5, 4));
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            &events[0],
+            CrossedMarketEvent::Started { venue: Some(v), condition: BookCondition::Crossed, .. } if v == "kraken"
+        ));
+        assert!(matches!(
+            &events[1],
+            CrossedMarketEvent::Ended {
+                venue: None,
+                condition: BookCondition::Locked,
+                duration_ms: 3000,
+                ..
+            }
+        ));
+        assert_eq!(detector.active(quote(0.0, 0.0, 5).timestamp).len(), 2);
+
+        let events = detector.on_quote("kraken", &quote(100.5, 102.0, 6));
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| matches!(
+            e,
+            CrossedMarketEvent::Ended {
+                duration_ms: 2000,
+                ..
+            }
+        )));
+        assert!(detector.active(quote(0.0, 0.0, 6).timestamp).is_empty());
+    }
+
+    #[test]
+    fn test_one_sided_book_clears_its_conditions() {
+        let mut detector = CrossedMarketDetector::new();
+        let book = |bids: &[f64], asks: &[f64], second| {
+            let level = |&price| PriceLevel {
+                price,
+                size: 1.0,
+                num_orders: 1,
+            };
+            MarketDataMessage::OrderBook(OrderBookSnapshot {
+                symbol: Symbol::from("BTCUSD"),
+                bids: bids.iter().map(level).collect(),
+                asks: asks.iter().map(level).collect(),
+                timestamp: quote(0.0, 0.0, second).timestamp,
+                ..Default::default()
+            })
+        };
+        detector.on_quote("coinbase", &quote(100.0, 101.0, 0));
+        let events = detector.on_message("kraken", &book(&[102.0], &[101.5], 1));
+        assert_eq!(events.len(), 2);
+
+        // Kraken's asks empty out: its crossed book and the crossed
+        // consolidated book both end instead of lingering on the old ask
+        let events = detector.on_message("kraken", &book(&[102.0], &[], 3));
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| matches!(
+            e,
+            CrossedMarketEvent::Ended {
+                condition: BookCondition::Crossed,
+                duration_ms: 2000,
+                ..
+            }
+        )));
+        assert!(detector.active(quote(0.0, 0.0, 3).timestamp).is_empty());
+        assert!(detector
+            .on_quote("coinbase", &quote(100.0, 101.0, 4))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_stale_venue_quotes_are_dropped() {
+        let mut detector = CrossedMarketDetector::new().with_max_quote_age(Duration::seconds(2));
+        detector.on_quote("kraken", &quote(101.5, 102.0, 0));
+        let events = detector.on_quote("coinbase", &quote(100.0, 101.0, 1));
+        assert!(matches!(
+            &events[..],
+            [CrossedMarketEvent::Started {
+                venue: None,
+                condition: BookCondition::Crossed,
+                ..
+            }]
+        ));
+
+        // Kraken went quiet: its bid no longer crosses Coinbase's ask
+        let events = detector.on_quote("coinbase", &quote(100.0, 101.0, 3));
+        assert!(matches!(
+            &events[..],
+            [CrossedMarketEvent::Ended {
+                venue: None,
+                duration_ms: 2000,
+                ..
+            }]
+        ));
+        assert!(detector
+            .on_quote("kraken", &quote(100.5, 102.0, 4))
+            .is_empty());
+
+        // Nobody quotes any more, a locked venue book ends once it is stale
+        detector.on_quote("kraken", &quote(101.0, 101.0, 5));
+        assert_eq!(detector.active(quote(0.0, 0.0, 5).timestamp).len(), 2);
+        let events = detector.expire(quote(0.0, 0.0, 8).timestamp);
+        assert_eq!(events.len(), 2);
+        assert!(detector.active(quote(0.0, 0.0, 8).timestamp).is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+//! Databento live subscription gateway.
+//!
+//! The gateway speaks a line-based `key=value|key=value` control protocol
+//! before switching to a DBN stream: it sends a challenge (`cram`), the
+//! client answers with `sha256("{cram}|{api_key}")` and the key's bucket
+//! (its last five characters), subscribes and starts the session.
+
+use super::{invalid, DbnDecoder, DbnMetadata, DbnRecord, HEADER_LEN};
+use crate::client::{ClientError, Result};
+use crate::recording::io_error;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::info;
+
+const GATEWAY_PORT: u16 = 13000;
+const BUCKET_ID_LEN: usize = 5;
+
+/// Fields of a control line such as `success=1|session_id=5`
+fn fields(line: &str) -> HashMap<&str, &str> {
+    line.trim_end()
+        .split('|')
+        .filter_map(|field| field.split_once('='))
+        .collect()
+}
+
+fn auth_response(cram: &str, key: &str) -> String {
+    let digest = Sha256::digest(format!("{}|{}", cram, key).as_bytes());
+    let mut response = String::with_capacity(64 + 1 + BUCKET_ID_LEN);
+    for byte in digest {
+        let _ = write!(response, "{:02x}", byte);
+    }
+    let bucket = key
+        .get(key.len().saturating_sub(BUCKET_ID_LEN)..)
+        .unwrap_or_default();
+    format!("{}-{}", response, bucket)
+}
+
+/// Authenticated live session streaming normalized DBN records
+pub struct DatabentoLive {
+    stream: BufReader<TcpStream>,
+    decoder: DbnDecoder,
+    session_id: String,
+    record: Vec<u8>,
+    pending: VecDeque<DbnRecord>,
+}
+
+impl DatabentoLive {
+    /// Subscribe to `schema` (e.g. `trades`, `mbp-1`) for raw `symbols` on
+    /// `dataset` (e.g. `XNAS.ITCH`) through its regional gateway
+    pub async fn connect(key: &str, dataset: &str, schema: &str, symbols: &[&str]) -> Result<Self> {
+        let host = format!(
+            "{}.lsg.databento.com:{}",
+            dataset.to_lowercase().replace('.', "-"),
+            GATEWAY_PORT
+        );
+        Self::connect_to(&host, key, dataset, schema, symbols).await
+    }
+
+    /// Like [`connect`](Self::connect) against a specific gateway address
+    pub async fn connect_to(
+        addr: &str,
+        key: &str,
+        dataset: &str,
+        schema: &str,
+        symbols: &[&str],
+    ) -> Result<Self> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .map_err(|e| ClientError::Connection(e.to_string()))?;
+        let mut stream = BufReader::new(stream);
+        let mut line = String::new();
+
+        // Greeting (`lsg_version=...`), then the challenge
+        let cram = loop {
+            line.clear();
+            if stream.read_line(&mut line).await.map_err(io_error)? == 0 {
+                return Err(ClientError::Connection(
+                    "gateway closed during greeting".to_string(),
+                ));
+            }
+            if let Some(cram) = fields(&line).get("cram") {
+                break cram.to_string();
+            }
+        };
+
+        let auth = format!(
+            "auth={}|dataset={}|encoding=dbn|ts_out=0\n",
+            auth_response(&cram, key),
+            dataset
+        );
+        stream.write_all(auth.as_bytes()).await.map_err(io_error)?;
+        line.clear();
+        stream.read_line(&mut line).await.map_err(io_error)?;
+        let reply = fields(&line);
+        if reply.get("success") != Some(&"1") {
+            return Err(ClientError::Connection(format!(
+                "databento authentication failed: {}",
+                reply.get("error").unwrap_or(&"no reply")
+            )));
+        }
+        let session_id = reply.get("session_id").unwrap_or(&"").to_string();
+
+        let subscribe = format!(
+            "schema={}|stype_in=raw_symbol|symbols={}\nstart_session\n",
+            schema,
+            symbols.join(",")
+        );
+        stream
+            .write_all(subscribe.as_bytes())
+            .await
+            .map_err(io_error)?;
+
+        let mut prefix = [0u8; 8];
+        stream.read_exact(&mut prefix).await.map_err(io_error)?;
+        let (version, len) = DbnMetadata::prefix(&prefix)?;
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await.map_err(io_error)?;
+        let metadata = DbnMetadata::decode(version, &body)?;
+        info!("Databento session {} started on {}", session_id, dataset);
+
+        Ok(Self {
+            stream,
+            decoder: DbnDecoder::new(metadata),
+            session_id,
+            record: Vec::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub fn metadata(&self) -> &DbnMetadata {
+        self.decoder.metadata()
+    }
+
+    /// Next normalized record, or `None` once the gateway closes the session
+    pub async fn next(&mut self) -> Result<Option<DbnRecord>> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Ok(Some(record));
+            }
+            let mut len = [0u8; 1];
+            if self.stream.read(&mut len).await.map_err(io_error)? == 0 {
+                return Ok(None);
+            }
+            self.record.clear();
+            self.record.resize(len[0] as usize * 4, 0);
+            if self.record.len() < HEADER_LEN {
+                return Err(invalid("bad record length"));
+            }
+            self.record[0] = len[0];
+            self.stream
+                .read_exact(&mut self.record[1..])
+                .await
+                .map_err(io_error)?;
+            self.decoder.decode(&self.record, &mut self.pending)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{metadata, trade};
+    use super::*;
+    use crate::types::MarketDataMessage;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_authenticates_and_streams_records() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let gateway = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            socket
+                .write_all(b"lsg_version=0.1.0\ncram=challenge\n")
+                .await
+                .unwrap();
+            let mut line = String::new();
+            socket.read_line(&mut line).await.unwrap();
+            let expected = auth_response("challenge", "db-key-abcde");
+            assert!(line.starts_with(&format!("auth={}|dataset=XNAS.ITCH", expected)));
+            assert!(expected.ends_with("-abcde"));
+            socket
+                .write_all(b"success=1|session_id=42\n")
+                .await
+                .unwrap();
+
+            line.clear();
+            socket.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "schema=trades|stype_in=raw_symbol|symbols=AAPL\n");
+            line.clear();
+            socket.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "start_session\n");
+
+            let mut stream = metadata();
+            stream.extend(trade(1_709_301_600_000_000_000, 179.65, 100, b'B'));
+            socket.write_all(&stream).await.unwrap();
+        });
+
+        let mut live =
+            DatabentoLive::connect_to(&addr, "db-key-abcde", "XNAS.ITCH", "trades", &["AAPL"])
+                .await
+                .unwrap();
+        assert_eq!(live.session_id(), "42");
+        let Some(DbnRecord::Message(MarketDataMessage::Trade(trade))) = live.next().await.unwrap()
+        else {
+            panic!("expected trade");
+        };
+        assert_eq!((trade.symbol.as_str(), trade.quantity), ("AAPL", 100.0));
+        assert!(live.next().await.unwrap().is_none());
+        gateway.await.unwrap();
+    }
+}
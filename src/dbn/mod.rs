@@ -0,0 +1,656 @@
+//! Databento Binary Encoding (DBN) ingestion.
+//!
+//! A DBN stream is a metadata header followed by fixed-layout records, each
+//! starting with a `[length/4: u8][rtype: u8][publisher: u16][instrument: u32][ts_event: u64]`
+//! header. [`DbnReader`] reads historical files (plain or zstd-compressed)
+//! and [`DatabentoLive`] the live gateway; both normalize records through a
+//! [`DbnDecoder`]:
+//!
+//! | schema        | output                                   |
+//! |---------------|------------------------------------------|
+//! | `trades`      | [`Trade`]                                |
+//! | `mbp-1`       | [`Quote`], plus a [`Trade`] on trade actions |
+//! | `mbp-10`      | [`OrderBookSnapshot`]                    |
+//! | `mbo`         | [`Trade`]s and snapshots of a book rebuilt from orders |
+//! | `ohlcv-*`     | [`Candle`]                               |
+//!
+//! Versions 1 to 3 are supported. Prices are fixed point with nine decimals
+//! and timestamps are nanoseconds since the Unix epoch; records carry the
+//! exchange `ts_event`.
+
+mod live;
+
+pub use live::DatabentoLive;
+
+use crate::book::{BookSide, L3Book, L3Order};
+use crate::client::{ClientError, Result};
+use crate::recording::{from_nanos, io_error};
+use crate::symbology::Symbol;
+use crate::types::{
+    BarKind, Candle, MarketDataMessage, OrderBookSnapshot, PriceLevel, Quote, Trade,
+    TradeConditions, TradeSide,
+};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+const MAGIC: &[u8; 3] = b"DBN";
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// Fixed part of the metadata, identical in size across versions
+const METADATA_FIXED_LEN: usize = 100;
+const V1_SYMBOL_CSTR_LEN: usize = 22;
+/// Largest metadata section accepted
+const MAX_METADATA_LEN: usize = 64 << 20;
+
+const PRICE_SCALE: f64 = 1e9;
+const UNDEF_PRICE: i64 = i64::MAX;
+/// Last record of an event; books are published once it is applied
+const F_LAST: u8 = 0x80;
+/// Levels per side included in snapshots of books rebuilt from orders
+const SNAPSHOT_DEPTH: usize = 50;
+
+const RTYPE_MBP_0: u8 = 0x00;
+const RTYPE_MBP_1: u8 = 0x01;
+const RTYPE_MBP_10: u8 = 0x0a;
+const RTYPE_ERROR: u8 = 0x15;
+const RTYPE_SYMBOL_MAPPING: u8 = 0x16;
+const RTYPE_SYSTEM: u8 = 0x17;
+const RTYPE_OHLCV_1S: u8 = 0x20;
+const RTYPE_OHLCV_1M: u8 = 0x21;
+const RTYPE_OHLCV_1H: u8 = 0x22;
+const RTYPE_OHLCV_EOD: u8 = 0x24;
+const RTYPE_MBO: u8 = 0xa0;
+
+const HEADER_LEN: usize = 16;
+const MBP_LEN: usize = 48;
+const LEVEL_LEN: usize = 32;
+const MBO_LEN: usize = 56;
+const OHLCV_LEN: usize = 56;
+
+pub(crate) fn invalid(reason: impl Into<String>) -> ClientError {
+    ClientError::Parse(format!("invalid dbn: {}", reason.into()))
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(buf[at..at + 2].try_into().unwrap())
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+fn i64_at(buf: &[u8], at: usize) -> i64 {
+    i64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+/// NUL-padded string
+fn cstr(buf: &[u8]) -> String {
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).into_owned()
+}
+
+fn price(raw: i64) -> Option<f64> {
+    (raw != UNDEF_PRICE).then(|| raw as f64 / PRICE_SCALE)
+}
+
+/// Bounds-checked reader over the metadata section
+struct Cursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or_else(|| invalid("truncated metadata"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        self.take(4).map(|bytes| u32_at(bytes, 0))
+    }
+}
+
+/// Dataset and symbology from a DBN metadata header
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbnMetadata {
+    pub version: u8,
+    pub dataset: String,
+    /// Raw symbol per instrument id, from the header's symbol mappings
+    pub symbols: HashMap<u32, String>,
+    symbol_cstr_len: usize,
+}
+
+impl DbnMetadata {
+    /// Read the `DBN` prefix and metadata from the start of a stream
+    pub fn read(reader: &mut impl Read) -> Result<Self> {
+        let mut prefix = [0u8; 8];
+        reader.read_exact(&mut prefix).map_err(io_error)?;
+        let (version, len) = Self::prefix(&prefix)?;
+        let mut body = vec![0u8; len];
+        reader.read_exact(&mut body).map_err(io_error)?;
+        Self::decode(version, &body)
+    }
+
+    /// Version and metadata length from the 8 byte prefix
+    pub(crate) fn prefix(prefix: &[u8; 8]) -> Result<(u8, usize)> {
+        if &prefix[..3] != MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        let version = prefix[3];
+        if !(1..=3).contains(&version) {
+            return Err(invalid(format!("unsupported version {}", version)));
+        }
+        let len = u32_at(prefix, 4) as usize;
+        if len > MAX_METADATA_LEN {
+            return Err(invalid(format!("metadata of {} bytes", len)));
+        }
+        Ok((version, len))
+    }
+
+    pub(crate) fn decode(version: u8, body: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor { buf: body, pos: 0 };
+        let fixed = cursor.take(METADATA_FIXED_LEN)?;
+        let dataset = cstr(&fixed[..16]);
+        let symbol_cstr_len = if version == 1 {
+            V1_SYMBOL_CSTR_LEN
+        } else {
+            // After dataset, schema, start, end, limit and the stype/ts_out bytes
+            u16_at(fixed, 45) as usize
+        };
+
+        let schema_definition_len = cursor.u32()? as usize;
+        cursor.take(schema_definition_len)?;
+        // `symbols`, `partial` and `not_found` lists
+        for _ in 0..3 {
+            let count = cursor.u32()? as usize;
+            cursor.take(count.saturating_mul(symbol_cstr_len))?;
+        }
+
+        let mut symbols = HashMap::new();
+        for _ in 0..cursor.u32()? {
+            let raw_symbol = cstr(cursor.take(symbol_cstr_len)?);
+            for _ in 0..cursor.u32()? {
+                // Start and end dates, then the mapped symbol
+                cursor.take(8)?;
+                let mapped = cstr(cursor.take(symbol_cstr_len)?);
+                if let Ok(instrument_id) = mapped.parse() {
+                    symbols.insert(instrument_id, raw_symbol.clone());
+                }
+            }
+        }
+
+        Ok(Self {
+            version,
+            dataset,
+            symbols,
+            symbol_cstr_len,
+        })
+    }
+}
+
+/// A normalized DBN record
+#[derive(Debug, Clone)]
+pub enum DbnRecord {
+    Message(MarketDataMessage),
+    Candle(Candle),
+}
+
+/// Book rebuilt from `mbo` order events, and whether it changed since it
+/// was last published
+struct MboBook {
+    book: L3Book,
+    dirty: bool,
+}
+
+/// Converts DBN records into normalized messages and candles
+pub struct DbnDecoder {
+    metadata: DbnMetadata,
+    books: HashMap<u32, MboBook>,
+}
+
+impl DbnDecoder {
+    pub fn new(metadata: DbnMetadata) -> Self {
+        Self {
+            metadata,
+            books: HashMap::new(),
+        }
+    }
+
+    pub fn metadata(&self) -> &DbnMetadata {
+        &self.metadata
+    }
+
+    /// Interned symbol of `instrument_id`, checked since files may come
+    /// from anywhere
+    fn symbol(&self, instrument_id: u32) -> Result<Symbol> {
+        match self.metadata.symbols.get(&instrument_id) {
+            Some(symbol) => Symbol::new(symbol),
+            None => Symbol::new(&instrument_id.to_string()),
+        }
+    }
+
+    /// Decode one complete record, header included. Unsupported record
+    /// types are skipped; gateway error records become errors.
+    pub fn decode(&mut self, record: &[u8], out: &mut VecDeque<DbnRecord>) -> Result<()> {
+        if record.len() < HEADER_LEN || record.len() != record[0] as usize * 4 {
+            return Err(invalid("bad record length"));
+        }
+        let rtype = record[1];
+        let instrument_id = u32_at(record, 4);
+        let ts_event = u64_at(record, 8);
+        let timestamp = from_nanos(ts_event.min(i64::MAX as u64) as i64);
+        let min_len = match rtype {
+            RTYPE_MBP_0 => MBP_LEN,
+            RTYPE_MBP_1 => MBP_LEN + LEVEL_LEN,
+            RTYPE_MBP_10 => MBP_LEN + 10 * LEVEL_LEN,
+            RTYPE_MBO => MBO_LEN,
+            RTYPE_OHLCV_1S..=RTYPE_OHLCV_EOD => OHLCV_LEN,
+            RTYPE_SYMBOL_MAPPING if self.metadata.symbol_cstr_len == V1_SYMBOL_CSTR_LEN => 60,
+            RTYPE_SYMBOL_MAPPING => 160,
+            _ => HEADER_LEN,
+        };
+        if record.len() < min_len {
+            return Err(invalid(format!("short record of rtype {:#04x}", rtype)));
+        }
+
+        match rtype {
+            RTYPE_MBP_0 | RTYPE_MBP_1 | RTYPE_MBP_10 => {
+                let symbol = self.symbol(instrument_id)?;
+                let action = record[28];
+                if rtype != RTYPE_MBP_10 && action == b'T' {
+                    if let Some(price) = price(i64_at(record, 16)) {
+                        out.push_back(DbnRecord::Message(MarketDataMessage::Trade(Trade {
+                            symbol,
+                            price,
+                            quantity: u32_at(record, 24) as f64,
+                            // `side` is the aggressor's
+                            side: match record[29] {
+                                b'A' => TradeSide::Sell,
+                                b'B' => TradeSide::Buy,
+                                _ => TradeSide::Unknown,
+                            },
+                            timestamp,
+                            trade_id: u32_at(record, 44).to_string(),
+                            conditions: TradeConditions::empty(),
+                            instrument_id: None,
+                            contract: None,
+                            received: None,
+                        })));
+                    }
+                }
+                match rtype {
+                    RTYPE_MBP_1 => {
+                        let level = &record[MBP_LEN..];
+                        if let (Some(bid), Some(ask)) =
+                            (price(i64_at(level, 0)), price(i64_at(level, 8)))
+                        {
+                            out.push_back(DbnRecord::Message(MarketDataMessage::Quote(Quote {
+                                symbol,
+                                bid_price: bid,
+                                bid_size: u32_at(level, 16) as f64,
+                                ask_price: ask,
+                                ask_size: u32_at(level, 20) as f64,
+                                timestamp,
+                                instrument_id: None,
+                                contract: None,
+                                received: None,
+                            })));
+                        }
+                    }
+                    RTYPE_MBP_10 => {
+                        let mut snapshot = OrderBookSnapshot {
+                            symbol,
+                            timestamp,
+                            ..Default::default()
+                        };
+                        for level in record[MBP_LEN..MBP_LEN + 10 * LEVEL_LEN].chunks(LEVEL_LEN) {
+                            if let Some(bid) = price(i64_at(level, 0)) {
+                                snapshot.bids.push(PriceLevel {
+                                    price: bid,
+                                    size: u32_at(level, 16) as f64,
+                                    num_orders: u32_at(level, 24),
+                                });
+                            }
+                            if let Some(ask) = price(i64_at(level, 8)) {
+                                snapshot.asks.push(PriceLevel {
+                                    price: ask,
+                                    size: u32_at(level, 20) as f64,
+                                    num_orders: u32_at(level, 28),
+                                });
+                            }
+                        }
+                        out.push_back(DbnRecord::Message(MarketDataMessage::OrderBook(snapshot)));
+                    }
+                    _ => {}
+                }
+            }
+            RTYPE_MBO => self.decode_mbo(record, instrument_id, timestamp, out)?,
+            RTYPE_OHLCV_1S..=RTYPE_OHLCV_EOD => {
+                let interval = match rtype {
+                    RTYPE_OHLCV_1S => Duration::seconds(1),
+                    RTYPE_OHLCV_1M => Duration::minutes(1),
+                    RTYPE_OHLCV_1H => Duration::hours(1),
+                    // Daily and end-of-day bars
+                    _ => Duration::days(1),
+                };
+                let bar_price = |at| i64_at(record, at) as f64 / PRICE_SCALE;
+                out.push_back(DbnRecord::Candle(Candle {
+                    symbol: self.symbol(instrument_id)?,
+                    start: timestamp,
+                    end: timestamp + interval,
+                    open: bar_price(16),
+                    high: bar_price(24),
+                    low: bar_price(32),
+                    close: bar_price(40),
+                    volume: u64_at(record, 48) as f64,
+                    notional: 0.0,
+                    trade_count: 0,
+                    bar_kind: BarKind::Time,
+                    revised: false,
+                }));
+            }
+            RTYPE_SYMBOL_MAPPING => {
+                let (in_symbol, out_symbol) = if self.metadata.symbol_cstr_len == V1_SYMBOL_CSTR_LEN
+                {
+                    (&record[16..38], &record[38..60])
+                } else {
+                    (&record[17..88], &record[89..160])
+                };
+                let symbol = match cstr(out_symbol) {
+                    symbol if symbol.is_empty() => cstr(in_symbol),
+                    symbol => symbol,
+                };
+                self.metadata.symbols.insert(instrument_id, symbol);
+            }
+            RTYPE_SYSTEM if record[HEADER_LEN..].starts_with(b"Heartbeat") => {
+                out.push_back(DbnRecord::Message(MarketDataMessage::Heartbeat));
+            }
+            RTYPE_ERROR => {
+                return Err(ClientError::Connection(format!(
+                    "databento error: {}",
+                    cstr(&record[HEADER_LEN..])
+                )))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn decode_mbo(
+        &mut self,
+        record: &[u8],
+        instrument_id: u32,
+        timestamp: DateTime<Utc>,
+        out: &mut VecDeque<DbnRecord>,
+    ) -> Result<()> {
+        let order_id = u64_at(record, 16);
+        let raw_price = i64_at(record, 24);
+        let size = u32_at(record, 32);
+        let flags = record[36];
+        let action = record[38];
+        let side = record[39];
+
+        if !self.books.contains_key(&instrument_id) {
+            let book = MboBook {
+                book: L3Book::new(self.symbol(instrument_id)?),
+                dirty: false,
+            };
+            self.books.insert(instrument_id, book);
+        }
+        let book = self.books.get_mut(&instrument_id).unwrap();
+        let book_side = match side {
+            b'B' => Some(BookSide::Bid),
+            b'A' => Some(BookSide::Ask),
+            _ => None,
+        };
+
+        match (action, book_side) {
+            (b'A' | b'M', Some(side)) if raw_price != UNDEF_PRICE => {
+                let order = L3Order {
+                    side,
+                    price: raw_price as f64 / PRICE_SCALE,
+                    size: size as f64,
+                };
+                book.book.add(order_id, order, timestamp);
+                book.dirty = true;
+            }
+            (b'C', _) => {
+                book.dirty |= book.book.reduce(order_id, size as f64, timestamp).is_some();
+            }
+            (b'R', _) => {
+                book.book.clear();
+                book.dirty = true;
+            }
+            (b'T', _) => {
+                if let Some(price) = price(raw_price) {
+                    out.push_back(DbnRecord::Message(MarketDataMessage::Trade(Trade {
+                        symbol: book.book.symbol().into(),
+                        price,
+                        quantity: size as f64,
+                        side: match side {
+                            b'A' => TradeSide::Sell,
+                            b'B' => TradeSide::Buy,
+                            _ => TradeSide::Unknown,
+                        },
+                        timestamp,
+                        trade_id: u32_at(record, 52).to_string(),
+                        conditions: TradeConditions::empty(),
+                        instrument_id: None,
+                        contract: None,
+                        received: None,
+                    })));
+                }
+            }
+            // Fills report the passive side of a trade already seen as `T`
+            _ => {}
+        }
+
+        if flags & F_LAST != 0 && book.dirty {
+            book.dirty = false;
+            let mut snapshot = book.book.levels().snapshot(Some(SNAPSHOT_DEPTH));
+            snapshot.timestamp = timestamp;
+            out.push_back(DbnRecord::Message(MarketDataMessage::OrderBook(snapshot)));
+        }
+        Ok(())
+    }
+}
+
+/// Reads a DBN file, yielding normalized records
+pub struct DbnReader<R> {
+    reader: R,
+    decoder: DbnDecoder,
+    record: Vec<u8>,
+    pending: VecDeque<DbnRecord>,
+}
+
+impl DbnReader<Box<dyn Read + Send>> {
+    /// Open a `.dbn` or zstd-compressed `.dbn.zst` file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path).map_err(io_error)?);
+        let compressed = file.fill_buf().map_err(io_error)?.starts_with(&ZSTD_MAGIC);
+        let reader: Box<dyn Read + Send> = if compressed {
+            Box::new(zstd::Decoder::with_buffer(file).map_err(io_error)?)
+        } else {
+            Box::new(file)
+        };
+        Self::new(reader)
+    }
+}
+
+impl<R: Read> DbnReader<R> {
+    /// Read the metadata header from an uncompressed DBN stream
+    pub fn new(mut reader: R) -> Result<Self> {
+        let metadata = DbnMetadata::read(&mut reader)?;
+        Ok(Self {
+            reader,
+            decoder: DbnDecoder::new(metadata),
+            record: Vec::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    pub fn metadata(&self) -> &DbnMetadata {
+        self.decoder.metadata()
+    }
+
+    /// Only market data messages, e.g. to feed a
+    /// [`Backtest`](crate::backtest::Backtest) or pipeline alongside other sources
+    pub fn messages(self) -> impl Iterator<Item = Result<MarketDataMessage>> {
+        self.filter_map(|record| match record {
+            Ok(DbnRecord::Message(msg)) => Some(Ok(msg)),
+            Ok(DbnRecord::Candle(_)) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    /// Read the next record; `false` at a clean end of stream
+    fn read_record(&mut self) -> Result<bool> {
+        let mut len = [0u8; 1];
+        match self.reader.read(&mut len) {
+            Ok(0) => return Ok(false),
+            Ok(_) => {}
+            Err(e) => return Err(io_error(e)),
+        }
+        self.record.clear();
+        self.record.resize(len[0] as usize * 4, 0);
+        if self.record.len() < HEADER_LEN {
+            return Err(invalid("bad record length"));
+        }
+        self.record[0] = len[0];
+        self.reader
+            .read_exact(&mut self.record[1..])
+            .map_err(io_error)?;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for DbnReader<R> {
+    type Item = Result<DbnRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Some(Ok(record));
+            }
+            match self.read_record() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+            if let Err(e) = self.decoder.decode(&self.record, &mut self.pending) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const INSTRUMENT_ID: u32 = 15144;
+
+    /// Version 2 metadata mapping `AAPL` to [`INSTRUMENT_ID`]
+    pub(crate) fn metadata() -> Vec<u8> {
+        let symbol = |s: &str| {
+            let mut buf = s.as_bytes().to_vec();
+            buf.resize(71, 0);
+            buf
+        };
+        let mut body = vec![0u8; METADATA_FIXED_LEN];
+        body[..9].copy_from_slice(b"XNAS.ITCH");
+        body[45..47].copy_from_slice(&71u16.to_le_bytes());
+        body.extend(0u32.to_le_bytes());
+        for _ in 0..3 {
+            body.extend(0u32.to_le_bytes());
+        }
+        body.extend(1u32.to_le_bytes());
+        body.extend(symbol("AAPL"));
+        body.extend(1u32.to_le_bytes());
+        body.extend(20240301u32.to_le_bytes());
+        body.extend(20240302u32.to_le_bytes());
+        body.extend(symbol(&INSTRUMENT_ID.to_string()));
+
+        let mut buf = b"DBN\x02".to_vec();
+        buf.extend((body.len() as u32).to_le_bytes());
+        buf.extend(body);
+        buf
+    }
+
+    pub(crate) fn record(rtype: u8, ts: u64, body: &[u8]) -> Vec<u8> {
+        let mut buf = vec![((HEADER_LEN + body.len()) / 4) as u8, rtype, 1, 0];
+        buf.extend(INSTRUMENT_ID.to_le_bytes());
+        buf.extend(ts.to_le_bytes());
+        buf.extend(body);
+        buf
+    }
+
+    pub(crate) fn trade(ts: u64, price: f64, size: u32, side: u8) -> Vec<u8> {
+        let mut body = ((price * PRICE_SCALE).round() as i64)
+            .to_le_bytes()
+            .to_vec();
+        body.extend(size.to_le_bytes());
+        body.extend([b'T', side, F_LAST, 0]);
+        body.extend(ts.to_le_bytes());
+        body.extend(0i32.to_le_bytes());
+        body.extend(7u32.to_le_bytes());
+        record(RTYPE_MBP_0, ts, &body)
+    }
+
+    fn mbo(order_id: u64, price: f64, size: u32, action: u8, side: u8, flags: u8) -> Vec<u8> {
+        let mut body = order_id.to_le_bytes().to_vec();
+        body.extend(((price * PRICE_SCALE).round() as i64).to_le_bytes());
+        body.extend(size.to_le_bytes());
+        body.extend([flags, 0, action, side]);
+        body.extend(0u64.to_le_bytes());
+        body.extend(0i32.to_le_bytes());
+        body.extend(0u32.to_le_bytes());
+        record(RTYPE_MBO, 1_709_301_600_000_000_000, &body)
+    }
+
+    #[test]
+    fn test_reads_trades_books_and_bars() {
+        let mut buf = metadata();
+        buf.extend(trade(1_709_301_600_000_000_000, 179.65, 100, b'A'));
+        buf.extend(mbo(1, 179.6, 100, b'A', b'B', 0));
+        buf.extend(mbo(2, 179.6, 50, b'A', b'B', F_LAST));
+        buf.extend(mbo(1, 179.6, 40, b'C', b'B', F_LAST));
+        let mut ohlcv = Vec::new();
+        for price in [179.5, 179.8, 179.4, 179.7] {
+            ohlcv.extend(((price * PRICE_SCALE).round() as i64).to_le_bytes());
+        }
+        ohlcv.extend(12_000u64.to_le_bytes());
+        buf.extend(record(RTYPE_OHLCV_1M, 1_709_301_600_000_000_000, &ohlcv));
+
+        let reader = DbnReader::new(buf.as_slice()).unwrap();
+        assert_eq!(reader.metadata().dataset, "XNAS.ITCH");
+        let records: Vec<DbnRecord> = reader.map(|record| record.unwrap()).collect();
+        assert_eq!(records.len(), 4);
+
+        let DbnRecord::Message(MarketDataMessage::Trade(trade)) = &records[0] else {
+            panic!("expected trade");
+        };
+        assert_eq!(trade.symbol, "AAPL");
+        assert_eq!((trade.price, trade.side), (179.65, TradeSide::Sell));
+        let DbnRecord::Message(MarketDataMessage::OrderBook(book)) = &records[2] else {
+            panic!("expected book");
+        };
+        assert_eq!(book.bids[0].size, 110.0);
+        assert_eq!(book.bids[0].num_orders, 2);
+        let DbnRecord::Candle(candle) = &records[3] else {
+            panic!("expected candle");
+        };
+        assert_eq!((candle.high, candle.volume), (179.8, 12_000.0));
+        assert_eq!(candle.end - candle.start, Duration::minutes(1));
+    }
+}
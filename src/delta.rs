@@ -0,0 +1,312 @@
+//! Book delta encoding for rebroadcast to downstream clients.
+//!
+//! [`BookDeltaEncoder`] sends each client only the levels that changed since
+//! the last book state it acknowledged, with a full keyframe whenever there
+//! is no acknowledged state and every `keyframe_every` updates. A removed
+//! level is sent with size 0. [`BookDeltaDecoder`] rebuilds full books on
+//! the client side.
+//!
+//! The encoder keeps unacknowledged states as the levels that changed since
+//! the state sent before them, so a client lagging on acks costs little more
+//! than the acknowledged book.
+
+use crate::book::{BookSide, OrderBook};
+use crate::client::{ClientError, Result};
+use crate::symbology::Symbol;
+use crate::types::{OrderBookSnapshot, PriceLevel};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+/// Sent states kept per symbol awaiting acknowledgement
+const MAX_UNACKED: usize = 64;
+
+/// Changed levels of one book, or the whole book when `base` is `None`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub struct BookDelta {
+    pub symbol: Symbol,
+    /// Acknowledge this to make the resulting state the next base
+    pub seq: u64,
+    /// State the levels apply to; `None` for a keyframe
+    pub base: Option<u64>,
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl BookDelta {
+    pub fn is_keyframe(&self) -> bool {
+        self.base.is_none()
+    }
+}
+
+/// Both sides of a book state, or of the changes to one
+#[derive(Debug, Clone, Default)]
+struct Levels {
+    bids: Vec<PriceLevel>,
+    asks: Vec<PriceLevel>,
+}
+
+impl Levels {
+    fn diff(&self, new: &Levels) -> Levels {
+        Levels {
+            bids: diff(&self.bids, &new.bids),
+            asks: diff(&self.asks, &new.asks),
+        }
+    }
+
+    fn apply(&mut self, changes: &Levels) {
+        apply(&mut self.bids, &changes.bids);
+        apply(&mut self.asks, &changes.asks);
+    }
+}
+
+#[derive(Debug, Default)]
+struct EncoderState {
+    seq: u64,
+    since_keyframe: u64,
+    acked: Option<(u64, Levels)>,
+    /// State just before the first of `sent`
+    anchor: Levels,
+    /// Latest state sent
+    last: Levels,
+    /// Changes of each sent state from the one sent before it
+    sent: VecDeque<(u64, Levels)>,
+}
+
+/// Per-client delta encoder
+#[derive(Debug)]
+pub struct BookDeltaEncoder {
+    keyframe_every: u64,
+    books: HashMap<Symbol, EncoderState>,
+}
+
+impl BookDeltaEncoder {
+    /// Force a keyframe every `keyframe_every` updates of a symbol
+    pub fn new(keyframe_every: u64) -> Self {
+        Self {
+            keyframe_every: keyframe_every.max(1),
+            books: HashMap::new(),
+        }
+    }
+
+    pub fn encode(&mut self, book: &OrderBookSnapshot) -> BookDelta {
+        let state = self.books.entry(book.symbol).or_default();
+        state.seq += 1;
+        state.since_keyframe += 1;
+        let base = match &state.acked {
+            Some(_) if state.since_keyframe >= self.keyframe_every => None,
+            acked => acked.as_ref(),
+        };
+        let delta = match base {
+            Some((base_seq, base)) => BookDelta {
+                symbol: book.symbol,
+                seq: state.seq,
+                base: Some(*base_seq),
+                bids: diff(&base.bids, &book.bids),
+                asks: diff(&base.asks, &book.asks),
+                timestamp: book.timestamp,
+            },
+            None => {
+                state.since_keyframe = 0;
+                BookDelta {
+                    symbol: book.symbol,
+                    seq: state.seq,
+                    base: None,
+                    bids: book.bids.clone(),
+                    asks: book.asks.clone(),
+                    timestamp: book.timestamp,
+                }
+            }
+        };
+        let current = Levels {
+            bids: book.bids.clone(),
+            asks: book.asks.clone(),
+        };
+        if state.sent.len() == MAX_UNACKED {
+            if let Some((_, oldest)) = state.sent.pop_front() {
+                state.anchor.apply(&oldest);
+            }
+        }
+        state.sent.push_back((state.seq, state.last.diff(&current)));
+        state.last = current;
+        delta
+    }
+
+    /// Make the state sent as `seq` the base of later deltas. Returns
+    /// whether that state was still known.
+    pub fn ack(&mut self, symbol: &str, seq: u64) -> bool {
+        let Some(state) = self.books.get_mut(symbol) else {
+            return false;
+        };
+        let Some(i) = state.sent.iter().position(|(s, _)| *s == seq) else {
+            return false;
+        };
+        for (_, changes) in state.sent.drain(..=i) {
+            state.anchor.apply(&changes);
+        }
+        state.acked = Some((seq, state.anchor.clone()));
+        true
+    }
+}
+
+/// Levels of `new` that differ from `old`, plus removed levels with size 0
+pub(crate) fn diff(old: &[PriceLevel], new: &[PriceLevel]) -> Vec<PriceLevel> {
+    let old: HashMap<u64, &PriceLevel> = old.iter().map(|l| (l.price.to_bits(), l)).collect();
+    let mut changed: Vec<PriceLevel> = new
+        .iter()
+        .filter(|level| old.get(&level.price.to_bits()) != Some(level))
+        .cloned()
+        .collect();
+    let kept: HashSet<u64> = new.iter().map(|l| l.price.to_bits()).collect();
+    changed.extend(
+        old.values()
+            .filter(|level| !kept.contains(&level.price.to_bits()))
+            .map(|level| PriceLevel {
+                price: level.price,
+                size: 0.0,
+                num_orders: 0,
+            }),
+    );
+    changed
+}
+
+/// Apply levels from [`diff`] to `levels`, removing those with size 0
+fn apply(levels: &mut Vec<PriceLevel>, changes: &[PriceLevel]) {
+    let changed: HashSet<u64> = changes.iter().map(|l| l.price.to_bits()).collect();
+    levels.retain(|level| !changed.contains(&level.price.to_bits()));
+    levels.extend(changes.iter().filter(|level| level.size > 0.0).cloned());
+}
+
+/// Client-side reconstruction of books from [`BookDelta`]s
+#[derive(Debug, Default)]
+pub struct BookDeltaDecoder {
+    /// Recent states per symbol, keyed by sequence
+    books: HashMap<Symbol, BTreeMap<u64, OrderBook>>,
+}
+
+impl BookDeltaDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a delta and return the full book. Acknowledge `delta.seq` to
+    /// the sender afterwards; states before the delta's base are dropped.
+    pub fn apply(&mut self, delta: &BookDelta) -> Result<OrderBookSnapshot> {
+        let states = self.books.entry(delta.symbol).or_default();
+        let mut book = match delta.base {
+            None => OrderBook::new(delta.symbol),
+            Some(base) => states.get(&base).cloned().ok_or_else(|| {
+                ClientError::Parse(format!(
+                    "book delta {} for {} needs unknown base {}",
+                    delta.seq, delta.symbol, base
+                ))
+            })?,
+        };
+        for (side, levels) in [(BookSide::Bid, &delta.bids), (BookSide::Ask, &delta.asks)] {
+            for level in levels {
+                book.update_level(side, level.clone(), delta.timestamp);
+            }
+        }
+        let snapshot = book.snapshot(None);
+        if let Some(base) = delta.base {
+            states.retain(|seq, _| *seq >= base);
+        }
+        states.insert(delta.seq, book);
+        while states.len() > MAX_UNACKED {
+            states.pop_first();
+        }
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(bids: &[(f64, f64)], asks: &[(f64, f64)]) -> OrderBookSnapshot {
+        let levels = |levels: &[(f64, f64)]| {
+            levels
+                .iter()
+                .map(|&(price, size)| PriceLevel {
+                    price,
+                    size,
+                    num_orders: 1,
+                })
+                .collect()
+        };
+        OrderBookSnapshot {
+            symbol: "BTCUSD".into(),
+            bids: levels(bids),
+            asks: levels(asks),
+            timestamp: Utc::now(),
+            instrument_id: None,
+            contract: None,
+            received: None,
+        }
+    }
+
+    #[test]
+    fn test_deltas_against_acked_state() {
+        let mut encoder = BookDeltaEncoder::new(3);
+        let mut decoder = BookDeltaDecoder::new();
+        let first = book(&[(100.0, 1.0), (99.0, 2.0)], &[(101.0, 1.0), (102.0, 5.0)]);
+        let second = book(&[(100.0, 3.0), (99.0, 2.0)], &[(102.0, 5.0)]);
+        let third = book(&[(99.0, 2.0)], &[(102.0, 4.0)]);
+
+        let keyframe = encoder.encode(&first);
+        assert!(keyframe.is_keyframe());
+        assert_eq!(decoder.apply(&keyframe).unwrap().bids, first.bids);
+        assert!(encoder.ack("BTCUSD", keyframe.seq));
+
+        let delta = encoder.encode(&second);
+        assert_eq!(delta.base, Some(keyframe.seq));
+        assert_eq!(delta.bids, book(&[(100.0, 3.0)], &[]).bids);
+        assert_eq!((delta.asks[0].price, delta.asks[0].size), (101.0, 0.0));
+        assert_eq!(decoder.apply(&delta).unwrap().asks, second.asks);
+
+        // Without a newer ack, deltas stay relative to the acked keyframe
+        let delta = encoder.encode(&third);
+        assert_eq!(delta.base, Some(keyframe.seq));
+        let decoded = decoder.apply(&delta).unwrap();
+        assert_eq!((&decoded.bids, &decoded.asks), (&third.bids, &third.asks));
+        assert!(encoder.encode(&third).is_keyframe());
+
+        // A delta on an unknown base is rejected rather than misapplied
+        assert!(BookDeltaDecoder::new().apply(&delta).is_err());
+    }
+
+    #[test]
+    fn test_ack_after_unacked_states_overflow() {
+        let mut encoder = BookDeltaEncoder::new(1000);
+        let asks = |k: usize| {
+            if k < 50 {
+                vec![(101.0, 1.0)]
+            } else {
+                vec![(101.0, 1.0), (102.0, k as f64)]
+            }
+        };
+        let seqs: Vec<u64> = (0..MAX_UNACKED + 10)
+            .map(|k| {
+                encoder
+                    .encode(&book(&[(100.0, k as f64 + 1.0)], &asks(k)))
+                    .seq
+            })
+            .collect();
+
+        // The oldest states are gone, later ones rebuild from their changes
+        assert!(!encoder.ack("BTCUSD", seqs[5]));
+        assert!(encoder.ack("BTCUSD", seqs[40]));
+        let delta = encoder.encode(&book(&[(100.0, 41.0)], &asks(60)));
+        assert_eq!(delta.base, Some(seqs[40]));
+        assert!(delta.bids.is_empty());
+        assert_eq!(delta.asks, book(&[], &[(102.0, 60.0)]).asks);
+
+        assert!(encoder.ack("BTCUSD", seqs[70]));
+        let delta = encoder.encode(&book(&[(100.0, 71.0)], &asks(0)));
+        assert_eq!(delta.base, Some(seqs[70]));
+        assert!(delta.bids.is_empty());
+        assert_eq!((delta.asks[0].price, delta.asks[0].size), (102.0, 0.0));
+    }
+}
@@ -0,0 +1,438 @@
+//! Exchange emulation over WebSocket.
+//!
+//! An [`ExchangeEmulator`] serves a normalized stream, e.g. replayed from a
+//! recording or produced by a generator, in the wire format of a real venue,
+//! so third-party tools and unmodified exchange clients can be pointed at
+//! controlled data. [`EmulatedVenue`] picks the venue.
+//!
+//! [`EmulatedVenue::Binance`] speaks the Binance spot stream API: raw
+//! streams at `/ws/<stream>`, combined streams at
+//! `/stream?streams=<a>/<b>` wrapped as `{"stream":..,"data":..}`, and
+//! `SUBSCRIBE`, `UNSUBSCRIBE` and `LIST_SUBSCRIPTIONS` requests on either.
+//! The `<symbol>@trade`, `<symbol>@bookTicker` and
+//! `<symbol>@depth5|10|20` streams are served, the symbol being the
+//! lowercase message symbol.
+//!
+//! ```rust,no_run
+//! # use rust_market_data_stream::emulator::{EmulatedVenue, ExchangeEmulator};
+//! # async fn run() -> rust_market_data_stream::client::Result<()> {
+//! let (tx, rx) = tokio::sync::broadcast::channel(1024);
+//! let emulator = ExchangeEmulator::new(EmulatedVenue::Binance, rx);
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:9443")
+//!     .await
+//!     .map_err(|e| rust_market_data_stream::ClientError::Io(e.to_string()))?;
+//! tokio::spawn(async move { emulator.serve(listener).await });
+//! // Publish replayed or synthetic messages with `tx.send(msg)`
+//! # drop(tx);
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "chaos")]
+use crate::chaos::ChaosLayer;
+use crate::client::{ClientError, Result};
+use crate::types::{MarketDataMessage, OrderBookSnapshot, PriceLevel, Quote, Trade, TradeSide};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::handshake::server::{
+    Callback, ErrorResponse, Request, Response,
+};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+
+/// Venue whose wire format is emulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EmulatedVenue {
+    /// Binance spot WebSocket streams
+    Binance,
+}
+
+impl EmulatedVenue {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmulatedVenue::Binance => "binance",
+        }
+    }
+
+    /// Look a venue up by [`name`](Self::name)
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "binance" => Some(EmulatedVenue::Binance),
+            _ => None,
+        }
+    }
+}
+
+/// Streams of one connection and how they are framed
+#[derive(Debug, Default)]
+struct Session {
+    streams: BTreeSet<String>,
+    /// Wrap payloads with their stream name
+    combined: bool,
+    /// Update id of book tickers and depth snapshots
+    update_id: u64,
+}
+
+impl Session {
+    /// Streams requested in the connection path
+    fn from_path(venue: EmulatedVenue, path: &str) -> Self {
+        match venue {
+            EmulatedVenue::Binance => {
+                let (path, query) = path.split_once('?').unwrap_or((path, ""));
+                let mut session = Session::default();
+                if let Some(stream) = path.strip_prefix("/ws/") {
+                    session.streams.insert(stream.to_string());
+                } else if path.starts_with("/stream") {
+                    session.combined = true;
+                    let streams = query
+                        .split('&')
+                        .find_map(|param| param.strip_prefix("streams="))
+                        .unwrap_or_default();
+                    session.streams.extend(
+                        streams
+                            .split('/')
+                            .filter(|s| !s.is_empty())
+                            .map(str::to_string),
+                    );
+                }
+                session
+            }
+        }
+    }
+
+    /// Reply to a client request
+    fn request(&mut self, venue: EmulatedVenue, text: &str) -> String {
+        match venue {
+            EmulatedVenue::Binance => {
+                let Ok(request) = serde_json::from_str::<Value>(text) else {
+                    return binance_error(2, "Invalid JSON", Value::Null);
+                };
+                let id = request.get("id").cloned().unwrap_or(Value::Null);
+                let params = request.get("params").and_then(Value::as_array);
+                let streams = params
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string);
+                match request.get("method").and_then(Value::as_str) {
+                    Some("SUBSCRIBE") => self.streams.extend(streams),
+                    Some("UNSUBSCRIBE") => {
+                        for stream in streams {
+                            self.streams.remove(&stream);
+                        }
+                    }
+                    Some("LIST_SUBSCRIPTIONS") => {
+                        return json!({ "result": self.streams, "id": id }).to_string()
+                    }
+                    _ => return binance_error(1, "Invalid request", id),
+                }
+                // Field order as Binance sends it, which clients match on
+                format!(r#"{{"result":null,"id":{}}}"#, id)
+            }
+        }
+    }
+
+    /// Frames carrying `msg` on the subscribed streams
+    fn encode(&mut self, venue: EmulatedVenue, msg: &MarketDataMessage) -> Vec<String> {
+        match venue {
+            EmulatedVenue::Binance => {
+                let Some(symbol) = msg.symbol() else {
+                    return Vec::new();
+                };
+                let symbol = symbol.to_lowercase();
+                let mut frames = Vec::new();
+                for stream in &self.streams {
+                    let Some((name, channel)) = stream.split_once('@') else {
+                        continue;
+                    };
+                    if name != symbol {
+                        continue;
+                    }
+                    let payload = match (channel, msg) {
+                        ("trade", MarketDataMessage::Trade(trade)) => binance_trade(trade),
+                        ("bookTicker", MarketDataMessage::Quote(quote)) => {
+                            self.update_id += 1;
+                            binance_book_ticker(quote, self.update_id)
+                        }
+                        ("depth5" | "depth10" | "depth20", MarketDataMessage::OrderBook(book)) => {
+                            self.update_id += 1;
+                            let depth = channel[5..].parse().unwrap_or(20);
+                            binance_depth(book, depth, self.update_id)
+                        }
+                        _ => continue,
+                    };
+                    frames.push(if self.combined {
+                        json!({ "stream": stream, "data": payload }).to_string()
+                    } else {
+                        payload.to_string()
+                    });
+                }
+                frames
+            }
+        }
+    }
+}
+
+fn binance_error(code: i64, msg: &str, id: Value) -> String {
+    json!({ "error": { "code": code, "msg": msg }, "id": id }).to_string()
+}
+
+fn binance_trade(trade: &Trade) -> Value {
+    let time = trade.timestamp.timestamp_millis();
+    json!({
+        "e": "trade",
+        "E": time,
+        "s": trade.symbol.as_str(),
+        "t": trade.trade_id.parse::<u64>().unwrap_or_default(),
+        "p": trade.price.to_string(),
+        "q": trade.quantity.to_string(),
+        "T": time,
+        // The buyer resting on the book means the seller aggressed
+        "m": trade.side == TradeSide::Sell,
+        "M": true,
+    })
+}
+
+fn binance_book_ticker(quote: &Quote, update_id: u64) -> Value {
+    json!({
+        "u": update_id,
+        "s": quote.symbol.as_str(),
+        "b": quote.bid_price.to_string(),
+        "B": quote.bid_size.to_string(),
+        "a": quote.ask_price.to_string(),
+        "A": quote.ask_size.to_string(),
+    })
+}
+
+fn binance_depth(book: &OrderBookSnapshot, depth: usize, update_id: u64) -> Value {
+    let levels = |levels: &[PriceLevel]| -> Vec<[String; 2]> {
+        levels
+            .iter()
+            .take(depth)
+            .map(|level| [level.price.to_string(), level.size.to_string()])
+            .collect()
+    };
+    json!({
+        "lastUpdateId": update_id,
+        "bids": levels(&book.bids),
+        "asks": levels(&book.asks),
+    })
+}
+
+/// Serves one source stream in a venue's wire format
+pub struct ExchangeEmulator {
+    venue: EmulatedVenue,
+    source: broadcast::Receiver<MarketDataMessage>,
+    #[cfg(feature = "chaos")]
+    chaos: Option<ChaosLayer>,
+}
+
+impl ExchangeEmulator {
+    /// Emulate `venue`, sending the messages of `source` to every
+    /// connection subscribed to them
+    pub fn new(venue: EmulatedVenue, source: broadcast::Receiver<MarketDataMessage>) -> Self {
+        Self {
+            venue,
+            source,
+            #[cfg(feature = "chaos")]
+            chaos: None,
+        }
+    }
+
+    /// Inject `chaos`'s faults into every connection's feed, each
+    /// connection drawing its own
+    #[cfg(feature = "chaos")]
+    pub fn with_chaos(mut self, chaos: ChaosLayer) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Accept connections until the listener fails
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        if let Ok(addr) = listener.local_addr() {
+            info!("Emulating {} on {}", self.venue.name(), addr);
+        }
+        let mut accepted = 0u64;
+        loop {
+            let (stream, peer) = listener
+                .accept()
+                .await
+                .map_err(|e| ClientError::Connection(e.to_string()))?;
+            let connection = Connection {
+                venue: self.venue,
+                rx: self.source.resubscribe(),
+                #[cfg(feature = "chaos")]
+                chaos: self.chaos.as_ref().map(|chaos| chaos.fork(accepted)),
+            };
+            accepted += 1;
+            tokio::spawn(async move {
+                if let Err(e) = connection.run(stream).await {
+                    debug!(
+                        "Emulator client {} ({}) disconnected: {}",
+                        accepted, peer, e
+                    );
+                }
+            });
+        }
+    }
+}
+
+/// Handshake callback keeping the requested path, which names the streams
+struct RequestPath<'a>(&'a mut String);
+
+impl Callback for RequestPath<'_> {
+    fn on_request(
+        self,
+        request: &Request,
+        response: Response,
+    ) -> std::result::Result<Response, ErrorResponse> {
+        *self.0 = request.uri().to_string();
+        Ok(response)
+    }
+}
+
+/// One client's feed
+struct Connection {
+    venue: EmulatedVenue,
+    rx: broadcast::Receiver<MarketDataMessage>,
+    #[cfg(feature = "chaos")]
+    chaos: Option<ChaosLayer>,
+}
+
+impl Connection {
+    async fn run(mut self, stream: TcpStream) -> Result<()> {
+        let venue = self.venue;
+        let ws_error =
+            |e: tokio_tungstenite::tungstenite::Error| ClientError::WebSocket(e.to_string());
+        let mut path = String::new();
+        let ws = tokio_tungstenite::accept_hdr_async(stream, RequestPath(&mut path))
+            .await
+            .map_err(ws_error)?;
+        let mut session = Session::from_path(venue, &path);
+        let (mut write, mut read) = ws.split();
+
+        loop {
+            tokio::select! {
+                frame = read.next() => match frame {
+                    Some(Ok(Message::Text(text))) => {
+                        let reply = session.request(venue, &text);
+                        write.send(Message::Text(reply)).await.map_err(ws_error)?;
+                    }
+                    Some(Ok(Message::Ping(payload))) => {
+                        write.send(Message::Pong(payload)).await.map_err(ws_error)?;
+                    }
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(ws_error(e)),
+                },
+                msg = self.rx.recv() => match msg {
+                    Ok(msg) => {
+                        #[cfg(feature = "chaos")]
+                        let msgs = match self.chaos.as_mut() {
+                            Some(chaos) => {
+                                if chaos.disconnect() {
+                                    // Dropping the socket without a close frame
+                                    return Ok(());
+                                }
+                                if let Some(delay) = chaos.delay() {
+                                    tokio::time::sleep(delay).await;
+                                }
+                                let mut msgs = Vec::new();
+                                chaos.inject(msg, &mut msgs);
+                                msgs
+                            }
+                            None => vec![msg],
+                        };
+                        #[cfg(not(feature = "chaos"))]
+                        let msgs = [msg];
+                        for msg in &msgs {
+                            for frame in session.encode(venue, msg) {
+                                write.send(Message::Text(frame)).await.map_err(ws_error)?;
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Emulator client lagged, {} messages dropped", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        let _ = write.send(Message::Close(None)).await;
+                        return Ok(());
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::{Adapter, BinanceAdapter};
+
+    use chrono::{TimeZone, Utc};
+    use tokio_tungstenite::connect_async;
+
+    #[tokio::test]
+    async fn test_binance_client_reads_emulated_streams() {
+        let (tx, rx) = broadcast::channel(16);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let emulator = ExchangeEmulator::new(EmulatedVenue::Binance, rx);
+        tokio::spawn(async move { emulator.serve(listener).await });
+
+        let url = format!("ws://{}/stream?streams=btcusdt@trade", addr);
+        let (mut ws, _) = connect_async(url.as_str()).await.unwrap();
+        let adapter = BinanceAdapter::new(&["btcusdt"]);
+        // Add the book ticker the way a Binance client would
+        ws.send(Message::Text(adapter.subscribe_frames().remove(0)))
+            .await
+            .unwrap();
+        let Some(Ok(Message::Text(ack))) = ws.next().await else {
+            panic!("expected subscription ack");
+        };
+        assert!(adapter.is_subscription_ack(ack.as_bytes()));
+
+        let timestamp = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        tx.send(MarketDataMessage::Trade(Trade {
+            quantity: 0.5,
+            side: TradeSide::Sell,
+            timestamp,
+            trade_id: "42".to_string(),
+            ..Trade::test("BTCUSDT", 37000.1)
+        }))
+        .unwrap();
+        tx.send(MarketDataMessage::Quote(Quote {
+            bid_size: 1.5,
+            ask_size: 2.0,
+            timestamp,
+            ..Quote::test("BTCUSDT", 37000.0, 37000.2)
+        }))
+        .unwrap();
+
+        let mut adapter = adapter;
+        let mut out = Vec::new();
+        while out.len() < 2 {
+            let Some(Ok(Message::Text(frame))) = ws.next().await else {
+                panic!("stream ended");
+            };
+            let mut frame = frame.into_bytes();
+            adapter.decode(&mut frame, Utc::now(), &mut out).unwrap();
+        }
+        let MarketDataMessage::Trade(trade) = &out[0] else {
+            panic!("expected trade");
+        };
+        assert_eq!(
+            (trade.price, trade.side, trade.timestamp),
+            (37000.1, TradeSide::Sell, timestamp)
+        );
+        assert_eq!(trade.trade_id, "42");
+        let MarketDataMessage::Quote(quote) = &out[1] else {
+            panic!("expected quote");
+        };
+        assert_eq!((quote.bid_price, quote.ask_size), (37000.0, 2.0));
+    }
+}
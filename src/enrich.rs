@@ -0,0 +1,236 @@
+//! Trades and quotes annotated with market context.
+//!
+//! [`Enricher`] keeps, per symbol, the prevailing quote, the session VWAP
+//! and the sizes of recent trades, and attaches them to each trade and
+//! quote as an [`EnrichedTrade`] or [`EnrichedQuote`], for consumers that
+//! want features rather than raw ticks. Context reflects the market before
+//! the message: a trade is compared with the VWAP and sizes of the trades
+//! before it. Sessions reset the VWAP at a daily close, midnight UTC by
+//! default.
+
+use crate::eod::next_close;
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Quote, Trade};
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A trade with the market around it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrichedTrade {
+    pub trade: Trade,
+    /// Mid of the prevailing quote
+    pub mid: Option<f64>,
+    /// Prevailing quoted spread in basis points of the mid
+    pub spread_bps: Option<f64>,
+    /// Trade price above (positive) or below the session VWAP, in basis
+    /// points of the VWAP
+    pub vwap_distance_bps: Option<f64>,
+    /// Fraction of recent trades no larger than this one, from 0 to 1
+    pub volume_percentile: Option<f64>,
+}
+
+/// A quote with the market around it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrichedQuote {
+    pub quote: Quote,
+    pub mid: f64,
+    pub spread_bps: Option<f64>,
+    /// Mid above (positive) or below the session VWAP, in basis points of
+    /// the VWAP
+    pub vwap_distance_bps: Option<f64>,
+}
+
+/// An enriched message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Enriched {
+    Trade(EnrichedTrade),
+    Quote(EnrichedQuote),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Context {
+    bid: Option<f64>,
+    ask: Option<f64>,
+    /// Close of the session `volume` and `notional` belong to
+    session_end: Option<DateTime<Utc>>,
+    volume: f64,
+    notional: f64,
+    /// Quantities of the last trades, oldest first
+    sizes: VecDeque<f64>,
+}
+
+impl Context {
+    fn mid(&self) -> Option<f64> {
+        Some((self.bid? + self.ask?) / 2.0)
+    }
+
+    fn spread_bps(&self) -> Option<f64> {
+        let mid = self.mid().filter(|mid| *mid > 0.0)?;
+        Some((self.ask? - self.bid?) / mid * 10_000.0)
+    }
+
+    fn vwap_distance_bps(&self, price: f64) -> Option<f64> {
+        let vwap = (self.volume > 0.0).then(|| self.notional / self.volume)?;
+        (vwap > 0.0).then(|| (price - vwap) / vwap * 10_000.0)
+    }
+}
+
+/// Attaches prevailing quote, session VWAP and relative size context to
+/// trades and quotes.
+///
+/// The stream itself is left as is; each [`Enriched`] copy goes to
+/// receivers from [`subscribe`](Self::subscribe).
+pub struct Enricher {
+    window: usize,
+    session_close: NaiveTime,
+    symbols: HashMap<Symbol, Context>,
+    tx: broadcast::Sender<Enriched>,
+}
+
+impl Enricher {
+    /// Rank trade sizes among the last `window` trades of their symbol
+    pub fn new(window: usize) -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            window: window.max(1),
+            session_close: NaiveTime::MIN,
+            symbols: HashMap::new(),
+            tx,
+        }
+    }
+
+    /// Reset session VWAPs at `close` UTC instead of midnight
+    pub fn with_session_close(mut self, close: NaiveTime) -> Self {
+        self.session_close = close;
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Enriched> {
+        self.tx.subscribe()
+    }
+
+    /// Feed a message, returning it enriched if it is a trade or quote.
+    /// Books update the prevailing quote without being enriched.
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Option<Enriched> {
+        let enriched = match msg {
+            MarketDataMessage::Trade(trade) => Enriched::Trade(self.on_trade(trade)),
+            MarketDataMessage::Quote(quote) => Enriched::Quote(self.on_quote(quote)),
+            MarketDataMessage::OrderBook(book) => {
+                let context = self.symbols.entry(book.symbol).or_default();
+                context.bid = book.best_bid().map(|level| level.price);
+                context.ask = book.best_ask().map(|level| level.price);
+                return None;
+            }
+            _ => return None,
+        };
+        let _ = self.tx.send(enriched.clone());
+        Some(enriched)
+    }
+
+    pub fn on_trade(&mut self, trade: &Trade) -> EnrichedTrade {
+        let context = self.symbols.entry(trade.symbol).or_default();
+        if context.session_end.is_none_or(|end| trade.timestamp >= end) {
+            context.session_end = Some(next_close(trade.timestamp, self.session_close));
+            context.volume = 0.0;
+            context.notional = 0.0;
+        }
+
+        let volume_percentile = (!context.sizes.is_empty()).then(|| {
+            let smaller = context
+                .sizes
+                .iter()
+                .filter(|size| **size <= trade.quantity)
+                .count();
+            smaller as f64 / context.sizes.len() as f64
+        });
+        let enriched = EnrichedTrade {
+            trade: trade.clone(),
+            mid: context.mid(),
+            spread_bps: context.spread_bps(),
+            vwap_distance_bps: context.vwap_distance_bps(trade.price),
+            volume_percentile,
+        };
+
+        context.volume += trade.quantity;
+        context.notional += trade.price * trade.quantity;
+        context.sizes.push_back(trade.quantity);
+        if context.sizes.len() > self.window {
+            context.sizes.pop_front();
+        }
+        enriched
+    }
+
+    pub fn on_quote(&mut self, quote: &Quote) -> EnrichedQuote {
+        let context = self.symbols.entry(quote.symbol).or_default();
+        context.bid = Some(quote.bid_price);
+        context.ask = Some(quote.ask_price);
+        let mid = quote.mid_price();
+        EnrichedQuote {
+            quote: quote.clone(),
+            mid,
+            spread_bps: context.spread_bps(),
+            vwap_distance_bps: context.vwap_distance_bps(mid),
+        }
+    }
+}
+
+impl Stage for Enricher {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_trades_carry_quote_vwap_and_size_context() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 23, 0, 0).unwrap();
+        let trade = |mins: i64, price: f64, quantity: f64| Trade {
+            quantity,
+            timestamp: t0 + Duration::minutes(mins),
+            trade_id: mins.to_string(),
+            ..Trade::test("BTCUSD", price)
+        };
+        let mut enricher = Enricher::new(3);
+        let mut rx = enricher.subscribe();
+
+        let first = enricher.on_trade(&trade(0, 100.0, 1.0));
+        assert_eq!((first.mid, first.vwap_distance_bps), (None, None));
+        assert_eq!(first.volume_percentile, None);
+
+        let quote = Quote {
+            timestamp: t0 + Duration::minutes(1),
+            ..Quote::test("BTCUSD", 99.0, 101.0)
+        };
+        let mut out = Vec::new();
+        enricher.process(MarketDataMessage::Quote(quote), &mut out);
+        assert_eq!(out.len(), 1);
+        let Ok(Enriched::Quote(quote)) = rx.try_recv() else {
+            panic!("expected enriched quote");
+        };
+        assert_eq!((quote.mid, quote.spread_bps), (100.0, Some(200.0)));
+
+        enricher.on_trade(&trade(2, 104.0, 3.0));
+        let enriched = enricher.on_trade(&trade(3, 102.0, 2.0));
+        assert_eq!(enriched.mid, Some(100.0));
+        // Session VWAP 103 before this trade
+        let distance = enriched.vwap_distance_bps.unwrap();
+        assert!((distance - (102.0 - 103.0) / 103.0 * 10_000.0).abs() < 1e-9);
+        assert_eq!(enriched.volume_percentile, Some(0.5));
+
+        // The next session starts a new VWAP
+        let next = enricher.on_trade(&trade(61, 90.0, 1.0));
+        assert_eq!(next.vwap_distance_bps, None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Per-consumer symbol and channel permissioning.
+//!
+//! [`Entitlements`] grant each named consumer (an in-process subscriber or
+//! a [`FanOutServer`](crate::FanOutServer) tenant) a set of symbol patterns
+//! and channels. Consumers without a grant receive nothing. Denied requests
+//! are logged as warnings on the `audit` tracing target.
+//!
+//! Config is a JSON object keyed by consumer:
+//!
+//! ```json
+//! {
+//!   "research": { "symbols": ["BTC*", "ETHUSD"], "channels": ["trade", "quote"] },
+//!   "risk": { "symbols": ["*"] }
+//! }
+//! ```
+
+use crate::client::{ClientError, Result};
+use crate::pipeline::Stage;
+use crate::types::MarketDataMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Channel name of a message: `trade`, `quote`, `book` or `heartbeat`.
+/// Trade corrections and busts go with the trades they amend.
+pub fn channel(msg: &MarketDataMessage) -> &'static str {
+    match msg {
+        MarketDataMessage::Trade(_)
+        | MarketDataMessage::TradeCorrection(_)
+        | MarketDataMessage::TradeBust(_) => "trade",
+        MarketDataMessage::Quote(_) => "quote",
+        MarketDataMessage::OrderBook(_) => "book",
+        MarketDataMessage::Heartbeat => "heartbeat",
+    }
+}
+
+/// What one consumer may receive
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Grant {
+    /// Exact symbols, prefixes ending in `*`, or `*` for every symbol
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    /// Allowed channels, every channel when empty
+    #[serde(default)]
+    pub channels: Vec<String>,
+}
+
+impl Grant {
+    pub fn new(symbols: &[&str]) -> Self {
+        Self {
+            symbols: symbols.iter().map(|s| s.to_string()).collect(),
+            channels: Vec::new(),
+        }
+    }
+
+    pub fn with_channels(mut self, channels: &[&str]) -> Self {
+        self.channels = channels.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    pub fn allows_symbol(&self, symbol: &str) -> bool {
+        self.symbols
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => symbol.starts_with(prefix),
+                None => pattern == symbol,
+            })
+    }
+
+    pub fn allows_channel(&self, channel: &str) -> bool {
+        self.channels.is_empty() || self.channels.iter().any(|c| c == channel)
+    }
+}
+
+/// Grants keyed by consumer name
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Entitlements {
+    grants: HashMap<String, Grant>,
+}
+
+impl Entitlements {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_grant(mut self, consumer: &str, grant: Grant) -> Self {
+        self.grants.insert(consumer.to_string(), grant);
+        self
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| ClientError::Parse(e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| ClientError::Io(format!("{}: {}", path.display(), e)))?;
+        Self::from_json(&json)
+    }
+
+    pub fn grant(&self, consumer: &str) -> Option<&Grant> {
+        self.grants.get(consumer)
+    }
+
+    /// Whether `consumer` may receive `msg`; heartbeats only need the channel
+    pub fn allows(&self, consumer: &str, msg: &MarketDataMessage) -> bool {
+        let Some(grant) = self.grants.get(consumer) else {
+            return false;
+        };
+        let Some(symbol) = msg.symbol() else {
+            return grant.allows_channel("heartbeat");
+        };
+        grant.allows_channel(channel(msg)) && grant.allows_symbol(&symbol)
+    }
+
+    /// Check a request for `symbol`, optionally on one channel, logging it
+    /// to the audit target when denied
+    pub fn authorize(&self, consumer: &str, symbol: &str, channel: Option<&str>) -> Result<()> {
+        let allowed = self.grants.get(consumer).is_some_and(|grant| {
+            grant.allows_symbol(symbol) && channel.is_none_or(|c| grant.allows_channel(c))
+        });
+        if allowed {
+            return Ok(());
+        }
+        let request = match channel {
+            Some(channel) => format!("{} {}", channel, symbol),
+            None => symbol.to_string(),
+        };
+        warn!(target: "audit", consumer, request = %request, "Entitlement denied");
+        Err(ClientError::Control(format!(
+            "{} not entitled to {}",
+            consumer, request
+        )))
+    }
+}
+
+/// Pipeline stage passing on only what one consumer is entitled to
+pub struct EntitlementFilter {
+    entitlements: Arc<Entitlements>,
+    consumer: String,
+}
+
+impl EntitlementFilter {
+    pub fn new(entitlements: Arc<Entitlements>, consumer: &str) -> Self {
+        Self {
+            entitlements,
+            consumer: consumer.to_string(),
+        }
+    }
+}
+
+impl Stage for EntitlementFilter {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        if self.entitlements.allows(&self.consumer, &msg) {
+            out.push(msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Quote, Trade};
+
+    #[test]
+    fn test_grants_from_config() {
+        let entitlements = Entitlements::from_json(
+            r#"{ "research": { "symbols": ["BTC*", "ETHUSD"], "channels": ["trade"] } }"#,
+        )
+        .unwrap();
+        let trade = MarketDataMessage::Trade(Trade {
+            trade_id: "1".to_string(),
+            ..Trade::test("BTCUSDT", 100.0)
+        });
+        let quote = MarketDataMessage::Quote(Quote::test("ETHUSD", 99.0, 101.0));
+
+        let mut stage = EntitlementFilter::new(Arc::new(entitlements.clone()), "research");
+        let mut out = Vec::new();
+        stage.process(trade.clone(), &mut out);
+        stage.process(quote, &mut out);
+        stage.process(MarketDataMessage::Heartbeat, &mut out);
+        assert_eq!(out.len(), 1);
+        assert!(matches!(&out[0], MarketDataMessage::Trade(t) if t.symbol == "BTCUSDT"));
+        assert!(!entitlements.allows("ops", &trade));
+
+        assert!(entitlements.authorize("research", "ETHUSD", None).is_ok());
+        assert!(entitlements
+            .authorize("research", "ETHUSD", Some("quote"))
+            .is_err());
+        assert!(entitlements.authorize("research", "SOLUSD", None).is_err());
+    }
+}
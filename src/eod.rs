@@ -0,0 +1,367 @@
+//! End-of-day summaries built from the live stream.
+//!
+//! [`EodSummarizer`] accumulates per-symbol daily statistics and, once the
+//! session closes, hands one [`DailySummary`] per traded symbol to its
+//! [`EodSink`]. Sessions close on event time when a trade from the next
+//! session arrives, or on wall-clock time via [`EodSummarizer::poll`] so a
+//! quiet overnight feed still produces the summary at the close.
+
+use crate::client::{ClientError, Result};
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Trade};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// One symbol's trading over one session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailySummary {
+    pub symbol: Symbol,
+    /// Trading date, the date on which the session closes
+    pub date: NaiveDate,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub vwap: f64,
+    pub trade_count: u64,
+    /// Time of the first trade at the high
+    pub high_time: DateTime<Utc>,
+    /// Time of the first trade at the low
+    pub low_time: DateTime<Utc>,
+}
+
+impl DailySummary {
+    fn open(trade: &Trade, date: NaiveDate) -> Self {
+        Self {
+            symbol: trade.symbol,
+            date,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: 0.0,
+            vwap: 0.0,
+            trade_count: 0,
+            high_time: trade.timestamp,
+            low_time: trade.timestamp,
+        }
+    }
+
+    fn update(&mut self, trade: &Trade) {
+        if trade.price > self.high {
+            self.high = trade.price;
+            self.high_time = trade.timestamp;
+        }
+        if trade.price < self.low {
+            self.low = trade.price;
+            self.low_time = trade.timestamp;
+        }
+        self.close = trade.price;
+        let volume = self.volume + trade.quantity;
+        if volume > 0.0 {
+            self.vwap = (self.vwap * self.volume + trade.price * trade.quantity) / volume;
+        }
+        self.volume = volume;
+        self.trade_count += 1;
+    }
+}
+
+/// Destination for the summaries of a closed session
+pub trait EodSink: Send {
+    fn write(&mut self, summaries: &[DailySummary]) -> Result<()>;
+}
+
+impl<F: FnMut(&[DailySummary]) -> Result<()> + Send> EodSink for F {
+    fn write(&mut self, summaries: &[DailySummary]) -> Result<()> {
+        self(summaries)
+    }
+}
+
+/// Appends summaries, or [`MetricsSnapshot`](crate::MetricsSnapshot)s, to
+/// a file as JSON lines
+#[derive(Debug, Clone)]
+pub struct JsonLinesSink {
+    path: PathBuf,
+}
+
+impl JsonLinesSink {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub(crate) fn append<T: Serialize>(&self, items: &[T]) -> Result<()> {
+        let io = |e: std::io::Error| ClientError::Io(format!("{}: {}", self.path.display(), e));
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(io)?;
+        for item in items {
+            let json =
+                serde_json::to_string(item).map_err(|e| ClientError::Parse(e.to_string()))?;
+            writeln!(file, "{}", json).map_err(io)?;
+        }
+        Ok(())
+    }
+}
+
+impl EodSink for JsonLinesSink {
+    fn write(&mut self, summaries: &[DailySummary]) -> Result<()> {
+        self.append(summaries)
+    }
+}
+
+/// Builds [`DailySummary`]s per symbol and persists them at the session
+/// close (UTC, midnight by default)
+pub struct EodSummarizer {
+    close: NaiveTime,
+    sink: Option<Box<dyn EodSink>>,
+    /// Close of the session being accumulated
+    session_end: Option<DateTime<Utc>>,
+    days: BTreeMap<Symbol, DailySummary>,
+    /// Summaries the sink failed to write, retried with the next close
+    unsaved: Vec<DailySummary>,
+    tx: broadcast::Sender<DailySummary>,
+}
+
+impl EodSummarizer {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(256);
+        Self {
+            close: NaiveTime::MIN,
+            sink: None,
+            session_end: None,
+            days: BTreeMap::new(),
+            unsaved: Vec::new(),
+            tx,
+        }
+    }
+
+    /// Close sessions at `close` UTC, e.g. 21:00 for US equities in winter
+    pub fn with_session_close(mut self, close: NaiveTime) -> Self {
+        self.close = close;
+        self
+    }
+
+    pub fn with_sink(mut self, sink: impl EodSink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Summaries of every closed session
+    pub fn subscribe(&self) -> broadcast::Receiver<DailySummary> {
+        self.tx.subscribe()
+    }
+
+    /// Close of the session being accumulated, once it has a trade
+    pub fn session_end(&self) -> Option<DateTime<Utc>> {
+        self.session_end
+    }
+
+    /// Feed a message; a trade after the close first closes the session.
+    /// The trade is counted even if persisting the closed session fails.
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Result<Vec<DailySummary>> {
+        let MarketDataMessage::Trade(trade) = msg else {
+            return Ok(Vec::new());
+        };
+        let closed = self.poll(trade.timestamp);
+        let end = *self
+            .session_end
+            .get_or_insert_with(|| next_close(trade.timestamp, self.close));
+        if trade.timestamp < end - Duration::days(1) {
+            debug!("Ignoring {} trade from a closed session", trade.symbol);
+            return closed;
+        }
+        let date = trading_date(end);
+        self.days
+            .entry(trade.symbol)
+            .or_insert_with(|| DailySummary::open(trade, date))
+            .update(trade);
+        closed
+    }
+
+    /// Close the session if `now` is at or past its close
+    pub fn poll(&mut self, now: DateTime<Utc>) -> Result<Vec<DailySummary>> {
+        match self.session_end {
+            Some(end) if now >= end => self.close_session(),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Summarize and persist the current session now, e.g. on shutdown.
+    /// Summaries the sink fails on are still published, and kept to be
+    /// written again ahead of the next session's.
+    pub fn close_session(&mut self) -> Result<Vec<DailySummary>> {
+        self.session_end = None;
+        let summaries: Vec<DailySummary> = std::mem::take(&mut self.days).into_values().collect();
+        for summary in &summaries {
+            let _ = self.tx.send(summary.clone());
+        }
+        self.unsaved.extend(summaries.iter().cloned());
+        if let (Some(sink), false) = (&mut self.sink, self.unsaved.is_empty()) {
+            sink.write(&self.unsaved)?;
+        }
+        self.unsaved.clear();
+        Ok(summaries)
+    }
+
+    /// Summarize `rx` on a background task, closing sessions on the wall
+    /// clock as well as on event time
+    pub fn spawn(mut self, mut rx: broadcast::Receiver<MarketDataMessage>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let until_close = self.session_end.map(|end| {
+                    (end - Utc::now())
+                        .to_std()
+                        .unwrap_or(std::time::Duration::ZERO)
+                });
+                let outcome = tokio::select! {
+                    msg = rx.recv() => match msg {
+                        Ok(msg) => self.on_message(&msg),
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            warn!("EOD summarizer lagged, skipped {} messages", n);
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = tokio::time::sleep(until_close.unwrap_or_default()), if until_close.is_some() => {
+                        self.poll(Utc::now())
+                    }
+                };
+                if let Err(e) = outcome {
+                    warn!("Persisting end-of-day summaries failed: {}", e);
+                }
+            }
+            if let Err(e) = self.close_session() {
+                warn!("Persisting end-of-day summaries failed: {}", e);
+            }
+        })
+    }
+}
+
+impl Default for EodSummarizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// First session close strictly after `t`
+pub(crate) fn next_close(t: DateTime<Utc>, close: NaiveTime) -> DateTime<Utc> {
+    let today = t.date_naive().and_time(close).and_utc();
+    if today > t {
+        today
+    } else {
+        today + Duration::days(1)
+    }
+}
+
+/// Sessions are dated by the day they close on; a midnight close ends the
+/// previous day
+fn trading_date(end: DateTime<Utc>) -> NaiveDate {
+    (end - Duration::nanoseconds(1)).date_naive()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use std::sync::{Arc, Mutex};
+
+    fn trade(
+        symbol: &str,
+        price: f64,
+        quantity: f64,
+        timestamp: DateTime<Utc>,
+    ) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            quantity,
+            timestamp,
+            ..Trade::test(symbol, price)
+        })
+    }
+
+    #[test]
+    fn test_session_close_persists_summaries() {
+        let persisted = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let persisted = Arc::clone(&persisted);
+            move |summaries: &[DailySummary]| {
+                persisted.lock().unwrap().extend_from_slice(summaries);
+                Ok(())
+            }
+        };
+        let mut eod = EodSummarizer::new()
+            .with_session_close(NaiveTime::from_hms_opt(21, 0, 0).unwrap())
+            .with_sink(sink);
+        let t = |h, m| Utc.with_ymd_and_hms(2024, 3, 5, h, m, 0).unwrap();
+
+        for (price, quantity, at) in [
+            (100.0, 1.0, t(14, 30)),
+            (104.0, 1.0, t(15, 0)),
+            (98.0, 2.0, t(16, 0)),
+            (101.0, 1.0, t(20, 59)),
+        ] {
+            assert!(eod
+                .on_message(&trade("AAPL", price, quantity, at))
+                .unwrap()
+                .is_empty());
+        }
+        eod.on_message(&trade("MSFT", 400.0, 1.0, t(18, 0)))
+            .unwrap();
+        assert!(eod.poll(t(20, 59)).unwrap().is_empty());
+
+        // The first trade of the next session closes this one
+        let closed = eod
+            .on_message(&trade("AAPL", 102.0, 1.0, t(21, 5)))
+            .unwrap();
+        assert_eq!(closed.len(), 2);
+        let aapl = &closed[0];
+        assert_eq!(aapl.date, NaiveDate::from_ymd_opt(2024, 3, 5).unwrap());
+        assert_eq!(
+            (aapl.open, aapl.high, aapl.low, aapl.close),
+            (100.0, 104.0, 98.0, 101.0)
+        );
+        assert_eq!((aapl.volume, aapl.trade_count), (5.0, 4));
+        assert!((aapl.vwap - 100.2).abs() < 1e-9);
+        assert_eq!((aapl.high_time, aapl.low_time), (t(15, 0), t(16, 0)));
+        assert_eq!(*persisted.lock().unwrap(), closed);
+
+        // The after-hours trade is dated by the session it closes with
+        let next = eod.close_session().unwrap();
+        assert_eq!(next[0].date, NaiveDate::from_ymd_opt(2024, 3, 6).unwrap());
+
+        // A failed write keeps the session for the next one
+        let mut fail = true;
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let written = Arc::clone(&written);
+            move |summaries: &[DailySummary]| {
+                if std::mem::take(&mut fail) {
+                    return Err(ClientError::Io("disk full".to_string()));
+                }
+                written.lock().unwrap().extend_from_slice(summaries);
+                Ok(())
+            }
+        };
+        let mut eod = EodSummarizer::new().with_sink(sink);
+        eod.on_message(&trade("AAPL", 100.0, 1.0, t(12, 0)))
+            .unwrap();
+        assert!(eod
+            .on_message(&trade("AAPL", 101.0, 1.0, t(12, 0) + Duration::days(1)))
+            .is_err());
+        assert_eq!(eod.close_session().unwrap()[0].close, 101.0);
+        let dates: Vec<_> = written.lock().unwrap().iter().map(|s| s.date).collect();
+        assert_eq!(dates.len(), 2);
+        assert!(dates[0] < dates[1]);
+    }
+}
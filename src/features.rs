@@ -0,0 +1,425 @@
+//! Fixed-schema feature vectors for online models.
+//!
+//! [`FeatureExtractor`] samples every symbol on an event-time grid and
+//! emits one [`FeatureVector`] per symbol and sample: quoted spread, top of
+//! book imbalance, log returns over several horizons, volatility of the
+//! sampled returns and trade intensity. Columns keep their order for the
+//! extractor's lifetime, and values that cannot be computed yet are `NaN`
+//! rather than missing, so vectors can be stacked into matrices.
+//! [`FeatureBatch`] collects them for `.npy` files or, with the `flight`
+//! feature, Arrow record batches.
+
+use crate::client::{ClientError, Result};
+use crate::clock::align;
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+#[cfg(feature = "flight")]
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampNanosecondArray};
+#[cfg(feature = "flight")]
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+#[cfg(feature = "flight")]
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// One symbol's features at one sample time, in
+/// [`FeatureExtractor::columns`] order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureVector {
+    pub symbol: Symbol,
+    pub timestamp: DateTime<Utc>,
+    pub values: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SymbolState {
+    /// Best bid and ask as `(price, size)`
+    bid: Option<(f64, f64)>,
+    ask: Option<(f64, f64)>,
+    last_trade: Option<f64>,
+    /// Prices at past samples, oldest first, back to the longest horizon
+    samples: VecDeque<(DateTime<Utc>, f64)>,
+    /// Log returns between consecutive samples
+    returns: VecDeque<f64>,
+    /// Trades since the last sample
+    trades: u64,
+}
+
+impl SymbolState {
+    /// Quote mid, or the last trade without a two-sided quote
+    fn price(&self) -> Option<f64> {
+        match (self.bid, self.ask) {
+            (Some((bid, _)), Some((ask, _))) => Some((bid + ask) / 2.0),
+            _ => self.last_trade,
+        }
+        .filter(|price| *price > 0.0)
+    }
+
+    /// Price at the latest sample no later than `at`
+    fn price_at(&self, at: DateTime<Utc>) -> Option<f64> {
+        self.samples
+            .iter()
+            .rev()
+            .find(|(ts, _)| *ts <= at)
+            .map(|(_, price)| *price)
+    }
+}
+
+/// Samples spread, imbalance, multi-horizon returns, volatility and trade
+/// intensity per symbol every `interval` of event time.
+///
+/// Vectors go to receivers from [`subscribe`](Self::subscribe) as they are
+/// sampled.
+pub struct FeatureExtractor {
+    interval: Duration,
+    horizons: Vec<Duration>,
+    volatility_window: usize,
+    next_tick: Option<DateTime<Utc>>,
+    symbols: BTreeMap<Symbol, SymbolState>,
+    tx: broadcast::Sender<FeatureVector>,
+}
+
+impl FeatureExtractor {
+    /// Sample every `interval`, with returns over 1, 5 and 15 intervals
+    /// and volatility over the last 20 samples
+    pub fn new(interval: Duration) -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            interval,
+            horizons: vec![interval, interval * 5, interval * 15],
+            volatility_window: 20,
+            next_tick: None,
+            symbols: BTreeMap::new(),
+            tx,
+        }
+    }
+
+    /// Log returns over `horizons` instead of the defaults
+    pub fn with_horizons(mut self, horizons: &[Duration]) -> Self {
+        self.horizons = horizons.to_vec();
+        self
+    }
+
+    /// Volatility of the returns of the last `samples` samples
+    pub fn with_volatility_window(mut self, samples: usize) -> Self {
+        self.volatility_window = samples.max(2);
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<FeatureVector> {
+        self.tx.subscribe()
+    }
+
+    /// Names of the values of every vector, e.g. `return_60s`
+    pub fn columns(&self) -> Vec<String> {
+        let mut columns = vec!["spread_bps".to_string(), "imbalance".to_string()];
+        columns.extend(
+            self.horizons
+                .iter()
+                .map(|horizon| match horizon.num_milliseconds() {
+                    ms if ms % 1000 == 0 => format!("return_{}s", ms / 1000),
+                    ms => format!("return_{}ms", ms),
+                }),
+        );
+        columns.push("volatility".to_string());
+        columns.push("trade_intensity".to_string());
+        columns
+    }
+
+    /// Feed a message, returning the vectors of any sample due before it
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<FeatureVector> {
+        let Some(ts) = msg.timestamp() else {
+            return Vec::new();
+        };
+        let vectors = self.poll(ts);
+        match msg {
+            MarketDataMessage::Trade(trade) => {
+                let state = self.symbols.entry(trade.symbol).or_default();
+                state.last_trade = Some(trade.price);
+                state.trades += 1;
+            }
+            MarketDataMessage::Quote(quote) => {
+                let state = self.symbols.entry(quote.symbol).or_default();
+                state.bid = Some((quote.bid_price, quote.bid_size));
+                state.ask = Some((quote.ask_price, quote.ask_size));
+            }
+            MarketDataMessage::OrderBook(book) => {
+                let state = self.symbols.entry(book.symbol).or_default();
+                state.bid = book.best_bid().map(|level| (level.price, level.size));
+                state.ask = book.best_ask().map(|level| (level.price, level.size));
+            }
+            _ => {}
+        }
+        vectors
+    }
+
+    /// Take the samples due at or before `now`, one per interval that
+    /// passed since the last, e.g. on a timer while the feed is quiet
+    pub fn poll(&mut self, now: DateTime<Utc>) -> Vec<FeatureVector> {
+        let tick = align(now, self.interval);
+        let Some(next) = self.next_tick else {
+            self.next_tick = Some(tick + self.interval);
+            return Vec::new();
+        };
+        if now < next {
+            return Vec::new();
+        }
+        self.next_tick = Some(tick + self.interval);
+
+        let symbols: Vec<Symbol> = self.symbols.keys().copied().collect();
+        let mut vectors = Vec::new();
+        let mut due = next;
+        while due <= tick {
+            vectors.extend(
+                symbols
+                    .iter()
+                    .filter_map(|symbol| self.sample(*symbol, due)),
+            );
+            due += self.interval;
+        }
+        for vector in &vectors {
+            let _ = self.tx.send(vector.clone());
+        }
+        vectors
+    }
+
+    fn sample(&mut self, symbol: Symbol, tick: DateTime<Utc>) -> Option<FeatureVector> {
+        let longest = self.horizons.iter().copied().max().unwrap_or(self.interval);
+        let seconds = self.interval.num_milliseconds() as f64 / 1000.0;
+        let state = self.symbols.get_mut(&symbol)?;
+        let trades = std::mem::take(&mut state.trades);
+        let price = state.price()?;
+
+        if let Some(&(_, last)) = state.samples.back() {
+            state.returns.push_back((price / last).ln());
+            if state.returns.len() > self.volatility_window {
+                state.returns.pop_front();
+            }
+        }
+        state.samples.push_back((tick, price));
+        // Keep one sample at or before the longest horizon
+        while state
+            .samples
+            .get(1)
+            .is_some_and(|(ts, _)| *ts <= tick - longest)
+        {
+            state.samples.pop_front();
+        }
+
+        let (spread_bps, imbalance) = match (state.bid, state.ask) {
+            (Some((bid, bid_size)), Some((ask, ask_size))) => (
+                (ask - bid) / ((bid + ask) / 2.0) * 10_000.0,
+                (bid_size - ask_size) / (bid_size + ask_size),
+            ),
+            _ => (f64::NAN, f64::NAN),
+        };
+        let mut values = vec![spread_bps, imbalance];
+        values.extend(self.horizons.iter().map(|horizon| {
+            state
+                .price_at(tick - *horizon)
+                .map_or(f64::NAN, |then| (price / then).ln())
+        }));
+        values.push(std_dev(&state.returns));
+        values.push(trades as f64 / seconds);
+        Some(FeatureVector {
+            symbol,
+            timestamp: tick,
+            values,
+        })
+    }
+}
+
+impl Stage for FeatureExtractor {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+/// Sample standard deviation, `NaN` below two values
+fn std_dev(values: &VecDeque<f64>) -> f64 {
+    let n = values.len() as f64;
+    if values.len() < 2 {
+        return f64::NAN;
+    }
+    let mean = values.iter().sum::<f64>() / n;
+    let squares: f64 = values.iter().map(|v| (v - mean) * (v - mean)).sum();
+    (squares / (n - 1.0)).sqrt()
+}
+
+/// Feature vectors as a row-major matrix with symbol and time per row
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FeatureBatch {
+    pub columns: Vec<String>,
+    pub symbols: Vec<String>,
+    /// Nanoseconds since the Unix epoch
+    pub timestamps: Vec<i64>,
+    /// `len() * columns.len()` values, one row per vector
+    pub values: Vec<f64>,
+}
+
+impl FeatureBatch {
+    /// An empty batch for vectors with `columns`, e.g.
+    /// [`FeatureExtractor::columns`]
+    pub fn new(columns: Vec<String>) -> Self {
+        Self {
+            columns,
+            ..Self::default()
+        }
+    }
+
+    pub fn push(&mut self, vector: &FeatureVector) -> Result<()> {
+        if vector.values.len() != self.columns.len() {
+            return Err(ClientError::Parse(format!(
+                "feature vector has {} values for {} columns",
+                vector.values.len(),
+                self.columns.len()
+            )));
+        }
+        self.symbols.push(vector.symbol.to_string());
+        self.timestamps
+            .push(vector.timestamp.timestamp_nanos_opt().unwrap_or_default());
+        self.values.extend_from_slice(&vector.values);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// The values as a little-endian `float64` NumPy array of shape
+    /// `(rows, columns)`, readable with `numpy.load`
+    pub fn write_npy<W: Write>(&self, mut out: W) -> Result<()> {
+        let mut header = format!(
+            "{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}",
+            self.len(),
+            self.columns.len()
+        );
+        // Magic, version and length take 10 bytes; the data starts aligned
+        // to 64 bytes after a newline-terminated header
+        let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+        header.extend(std::iter::repeat_n(' ', padding));
+        header.push('\n');
+
+        let mut bytes = Vec::with_capacity(10 + header.len() + self.values.len() * 8);
+        bytes.extend_from_slice(b"\x93NUMPY\x01\x00");
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        for value in &self.values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        out.write_all(&bytes)
+            .map_err(|e| ClientError::Io(e.to_string()))
+    }
+
+    /// Columns `symbol`, `timestamp` and one `Float64` column per feature
+    #[cfg(feature = "flight")]
+    pub fn into_record_batch(self) -> Result<RecordBatch> {
+        let width = self.columns.len();
+        let mut fields = vec![
+            Field::new("symbol", DataType::Utf8, false),
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+                false,
+            ),
+        ];
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(self.symbols)),
+            Arc::new(TimestampNanosecondArray::from(self.timestamps).with_timezone("UTC")),
+        ];
+        for (i, name) in self.columns.iter().enumerate() {
+            fields.push(Field::new(name, DataType::Float64, false));
+            let column: Float64Array = self.values.iter().skip(i).step_by(width).copied().collect();
+            columns.push(Arc::new(column));
+        }
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+            .map_err(|e| ClientError::Parse(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Quote, Trade};
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_vectors_sample_on_the_grid() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let quote = |secs: i64, bid: f64, bid_size: f64| {
+            MarketDataMessage::Quote(Quote {
+                bid_size,
+                timestamp: t0 + Duration::seconds(secs),
+                ..Quote::test("BTCUSD", bid, bid + 2.0)
+            })
+        };
+        let trade = MarketDataMessage::Trade(Trade {
+            timestamp: t0 + Duration::seconds(12),
+            ..Trade::test("BTCUSD", 100.0)
+        });
+        let mut extractor = FeatureExtractor::new(Duration::seconds(10))
+            .with_horizons(&[Duration::seconds(10), Duration::milliseconds(20_500)]);
+        assert_eq!(
+            extractor.columns(),
+            vec![
+                "spread_bps",
+                "imbalance",
+                "return_10s",
+                "return_20500ms",
+                "volatility",
+                "trade_intensity"
+            ]
+        );
+
+        let mut vectors = Vec::new();
+        for msg in [
+            quote(1, 99.0, 3.0),
+            trade,
+            quote(15, 109.0, 1.0),
+            quote(25, 99.0, 1.0),
+        ] {
+            vectors.extend(extractor.on_message(&msg));
+        }
+        // Samples at 10s and 20s; the first message only starts the grid
+        assert_eq!(vectors.len(), 2);
+        let first = &vectors[0].values;
+        assert_eq!((first[0], first[1]), (200.0, 0.5));
+        assert!(first[2].is_nan() && first[4].is_nan());
+        assert_eq!(first[5], 0.0);
+
+        let second = &vectors[1].values;
+        assert_eq!(vectors[1].timestamp, t0 + Duration::seconds(20));
+        assert!((second[2] - (110.0f64 / 100.0).ln()).abs() < 1e-12);
+        assert!(second[3].is_nan());
+        assert_eq!((second[1], second[5]), (0.0, 0.1));
+
+        let mut batch = FeatureBatch::new(extractor.columns());
+        for vector in &vectors {
+            batch.push(vector).unwrap();
+        }
+        let mut npy = Vec::new();
+        batch.write_npy(&mut npy).unwrap();
+        assert_eq!(&npy[..6], b"\x93NUMPY");
+        let data = npy.len() - 2 * 6 * 8;
+        assert_eq!(data % 64, 0);
+        assert_eq!(&npy[data..data + 8], &200.0f64.to_le_bytes());
+
+        // A quiet spell still yields a sample per interval
+        let vectors = extractor.poll(t0 + Duration::seconds(55));
+        let times: Vec<_> = vectors.iter().map(|v| v.timestamp).collect();
+        assert_eq!(times, [30, 40, 50].map(|secs| t0 + Duration::seconds(secs)));
+        assert_eq!(vectors[1].values[2], 0.0);
+        assert!(vectors.iter().all(|v| v.values[5] == 0.0));
+    }
+}
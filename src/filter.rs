@@ -0,0 +1,464 @@
+//! Message filter expressions.
+//!
+//! A small language for selecting messages without custom code, e.g.
+//! `symbol == 'BTCUSD' && type == 'trade' && price > 50000`. Expressions
+//! combine comparisons with `&&`, `||`, `!` and parentheses; fields and
+//! literal types are checked when the expression is compiled.
+//!
+//! | Field            | Type   | Messages                                  |
+//! |------------------|--------|-------------------------------------------|
+//! | `type`           | string | `trade`, `quote`, `book`, `heartbeat`, `correction`, `bust` |
+//! | `symbol`         | string | all but heartbeats                        |
+//! | `instrument_id`  | string | when tagged                               |
+//! | `side`           | string | trades: `buy`, `sell`                     |
+//! | `price`          | number | trade and corrected price, quote and book mid |
+//! | `quantity`       | number | trades and corrections                    |
+//! | `bid`, `ask`     | number | quotes and books                          |
+//! | `bid_size`, `ask_size` | number | quotes and books                    |
+//! | `spread`         | number | quotes and books                          |
+//!
+//! A comparison on a field the message does not have is false.
+
+use crate::client::{ClientError, Result};
+use crate::pipeline::Stage;
+use crate::types::MarketDataMessage;
+use std::fmt;
+use std::str::FromStr;
+
+/// Longest accepted expression, in bytes
+const MAX_SOURCE_LEN: usize = 4096;
+
+/// Deepest nesting of `!` and parentheses
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Type,
+    Symbol,
+    InstrumentId,
+    Side,
+    Price,
+    Quantity,
+    Bid,
+    Ask,
+    BidSize,
+    AskSize,
+    Spread,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "type" => Field::Type,
+            "symbol" => Field::Symbol,
+            "instrument_id" => Field::InstrumentId,
+            "side" => Field::Side,
+            "price" => Field::Price,
+            "quantity" => Field::Quantity,
+            "bid" => Field::Bid,
+            "ask" => Field::Ask,
+            "bid_size" => Field::BidSize,
+            "ask_size" => Field::AskSize,
+            "spread" => Field::Spread,
+            _ => return None,
+        })
+    }
+
+    fn is_text(self) -> bool {
+        matches!(
+            self,
+            Field::Type | Field::Symbol | Field::InstrumentId | Field::Side
+        )
+    }
+
+    fn text(self, msg: &MarketDataMessage) -> Option<&str> {
+        match (self, msg) {
+            (Field::Type, MarketDataMessage::Trade(_)) => Some("trade"),
+            (Field::Type, MarketDataMessage::Quote(_)) => Some("quote"),
+            (Field::Type, MarketDataMessage::OrderBook(_)) => Some("book"),
+            (Field::Type, MarketDataMessage::Heartbeat) => Some("heartbeat"),
+            (Field::Type, MarketDataMessage::TradeCorrection(_)) => Some("correction"),
+            (Field::Type, MarketDataMessage::TradeBust(_)) => Some("bust"),
+            (Field::Symbol, MarketDataMessage::Trade(trade)) => Some(&trade.symbol),
+            (Field::Symbol, MarketDataMessage::Quote(quote)) => Some(&quote.symbol),
+            (Field::Symbol, MarketDataMessage::OrderBook(book)) => Some(&book.symbol),
+            (Field::Symbol, MarketDataMessage::TradeCorrection(correction)) => {
+                Some(&correction.symbol)
+            }
+            (Field::Symbol, MarketDataMessage::TradeBust(bust)) => Some(&bust.symbol),
+            (Field::InstrumentId, MarketDataMessage::Trade(trade)) => {
+                trade.instrument_id.as_deref()
+            }
+            (Field::InstrumentId, MarketDataMessage::Quote(quote)) => {
+                quote.instrument_id.as_deref()
+            }
+            (Field::InstrumentId, MarketDataMessage::OrderBook(book)) => {
+                book.instrument_id.as_deref()
+            }
+            (Field::InstrumentId, MarketDataMessage::TradeCorrection(correction)) => {
+                correction.instrument_id.as_deref()
+            }
+            (Field::InstrumentId, MarketDataMessage::TradeBust(bust)) => {
+                bust.instrument_id.as_deref()
+            }
+            (Field::Side, MarketDataMessage::Trade(trade)) => Some(trade.side.as_str()),
+            _ => None,
+        }
+    }
+
+    fn number(self, msg: &MarketDataMessage) -> Option<f64> {
+        match msg {
+            MarketDataMessage::Trade(trade) => match self {
+                Field::Price => Some(trade.price),
+                Field::Quantity => Some(trade.quantity),
+                _ => None,
+            },
+            MarketDataMessage::Quote(quote) => match self {
+                Field::Price => Some(quote.mid_price()),
+                Field::Bid => Some(quote.bid_price),
+                Field::Ask => Some(quote.ask_price),
+                Field::BidSize => Some(quote.bid_size),
+                Field::AskSize => Some(quote.ask_size),
+                Field::Spread => Some(quote.spread()),
+                _ => None,
+            },
+            MarketDataMessage::OrderBook(book) => match self {
+                Field::Price => book.mid_price(),
+                Field::Bid => book.best_bid().map(|level| level.price),
+                Field::Ask => book.best_ask().map(|level| level.price),
+                Field::BidSize => book.best_bid().map(|level| level.size),
+                Field::AskSize => book.best_ask().map(|level| level.size),
+                Field::Spread => book.spread(),
+                _ => None,
+            },
+            MarketDataMessage::TradeCorrection(correction) => match self {
+                Field::Price => Some(correction.corrected.price),
+                Field::Quantity => Some(correction.corrected.quantity),
+                _ => None,
+            },
+            MarketDataMessage::Heartbeat | MarketDataMessage::TradeBust(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn apply<T: PartialOrd + ?Sized>(self, a: &T, b: &T) -> bool {
+        match self {
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Text(Field, Op, String),
+    Number(Field, Op, f64),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, msg: &MarketDataMessage) -> bool {
+        match self {
+            Expr::Text(field, op, value) => field
+                .text(msg)
+                .is_some_and(|text| op.apply(text, value.as_str())),
+            Expr::Number(field, op, value) => {
+                field.number(msg).is_some_and(|n| op.apply(&n, value))
+            }
+            Expr::Not(expr) => !expr.eval(msg),
+            Expr::And(a, b) => a.eval(msg) && b.eval(msg),
+            Expr::Or(a, b) => a.eval(msg) || b.eval(msg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Text(String),
+    Number(f64),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            ('\'' | '"', _) => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == c)
+                    .ok_or_else(|| invalid("unterminated string"))?;
+                let text = chars[i + 1..i + 1 + end].iter().collect();
+                (Token::Text(text), end + 2)
+            }
+            (c, _) if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let len = chars[i..]
+                    .iter()
+                    .enumerate()
+                    .take_while(|&(k, &ch)| {
+                        ch.is_ascii_digit()
+                            || ch == '.'
+                            || ch == 'e'
+                            || ch == 'E'
+                            || (k == 0 && ch == '-')
+                            || ((ch == '-' || ch == '+') && matches!(chars[i + k - 1], 'e' | 'E'))
+                    })
+                    .count();
+                let text: String = chars[i..i + len].iter().collect();
+                let number = text
+                    .parse()
+                    .map_err(|_| invalid(&format!("bad number {}", text)))?;
+                (Token::Number(number), len)
+            }
+            (c, _) if c.is_ascii_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|ch| ch.is_ascii_alphanumeric() || **ch == '_')
+                    .count();
+                (Token::Ident(chars[i..i + len].iter().collect()), len)
+            }
+            (c, _) => return Err(invalid(&format!("unexpected '{}'", c))),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the token list
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    /// Run `f` one nesting level deeper, bounding the recursion
+    fn nested(&mut self, f: impl FnOnce(&mut Self) -> Result<Expr>) -> Result<Expr> {
+        if self.depth == MAX_DEPTH {
+            return Err(invalid(&format!("nested deeper than {}", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let expr = f(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Not) => self.nested(|parser| Ok(Expr::Not(Box::new(parser.unary()?)))),
+            Some(Token::Open) => {
+                let expr = self.nested(Self::or)?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err(invalid("expected ')'")),
+                }
+            }
+            Some(Token::Ident(name)) => {
+                let field = Field::parse(&name)
+                    .ok_or_else(|| invalid(&format!("unknown field {}", name)))?;
+                let Some(Token::Op(op)) = self.next() else {
+                    return Err(invalid(&format!("expected comparison after {}", name)));
+                };
+                match (self.next(), field.is_text()) {
+                    (Some(Token::Text(value)), true) => Ok(Expr::Text(field, op, value)),
+                    (Some(Token::Number(value)), false) => Ok(Expr::Number(field, op, value)),
+                    (Some(Token::Text(_)), false) => {
+                        Err(invalid(&format!("{} compares to numbers", name)))
+                    }
+                    (Some(Token::Number(_)), true) => {
+                        Err(invalid(&format!("{} compares to strings", name)))
+                    }
+                    _ => Err(invalid(&format!("expected value after {}", name))),
+                }
+            }
+            _ => Err(invalid("expected comparison")),
+        }
+    }
+}
+
+fn invalid(what: &str) -> ClientError {
+    ClientError::Parse(format!("filter: {}", what))
+}
+
+/// A compiled filter expression.
+///
+/// As a pipeline [`Stage`] it drops messages that do not match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl Filter {
+    /// Compile `source`; expressions over 4 KiB or nested more than 32
+    /// deep are rejected
+    pub fn parse(source: &str) -> Result<Self> {
+        if source.len() > MAX_SOURCE_LEN {
+            return Err(invalid(&format!("longer than {} bytes", MAX_SOURCE_LEN)));
+        }
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(invalid("unexpected trailing input"));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    pub fn matches(&self, msg: &MarketDataMessage) -> bool {
+        self.expr.eval(msg)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Stage for Filter {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        if self.matches(&msg) {
+            out.push(msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Quote, Trade, TradeSide};
+
+    fn trade(symbol: &str, price: f64) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            quantity: 0.5,
+            side: TradeSide::Sell,
+            trade_id: "1".to_string(),
+            ..Trade::test(symbol, price)
+        })
+    }
+
+    #[test]
+    fn test_filter_expressions() {
+        let filter: Filter = "symbol == 'BTCUSD' && type == \"trade\" && price > 5e4"
+            .parse()
+            .unwrap();
+        assert!(filter.matches(&trade("BTCUSD", 50_001.0)));
+        assert!(!filter.matches(&trade("BTCUSD", 49_999.0)));
+        assert!(!filter.matches(&trade("ETHUSD", 60_000.0)));
+
+        let quote = MarketDataMessage::Quote(Quote {
+            bid_size: 3.0,
+            ..Quote::test("ETHUSD", 2_000.0, 2_000.5)
+        });
+        let filter =
+            Filter::parse("!(type == 'trade') && (spread <= 0.5 || side == 'buy')").unwrap();
+        assert!(filter.matches(&quote));
+        assert!(!filter.matches(&trade("ETHUSD", 2_000.0)));
+        // Trades have no bid, so the comparison is false either way
+        assert!(!Filter::parse("bid < -1")
+            .unwrap()
+            .matches(&trade("BTCUSD", 1.0)));
+
+        for bad in [
+            "price > 'high'",
+            "symbol == 1",
+            "volume > 1",
+            "price >",
+            "(price > 1",
+        ] {
+            assert!(Filter::parse(bad).is_err(), "{}", bad);
+        }
+
+        // Bounded before the recursion can exhaust the stack
+        assert!(Filter::parse(&("!".repeat(30) + "price > 1")).is_ok());
+        assert!(Filter::parse(&("!".repeat(1_000) + "price > 1")).is_err());
+        assert!(Filter::parse(&("(".repeat(40) + "price > 1" + &")".repeat(40))).is_err());
+        assert!(Filter::parse(&"!".repeat(1_000_000)).is_err());
+    }
+}
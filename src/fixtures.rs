@@ -0,0 +1,177 @@
+//! Captured feed samples for deterministic adapter tests.
+//!
+//! A fixture is a JSON Lines file of raw frames with their receive time, as
+//! produced by [`capture`] (or `mds capture`). [`replay`] runs them through an
+//! adapter offline; [`check_golden`] compares the normalized output with a
+//! checked-in golden file, one message per line.
+
+use crate::adapters::Adapter;
+use crate::client::{ClientError, Result};
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+/// One captured frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FixtureFrame {
+    pub received: DateTime<Utc>,
+    pub frame: String,
+}
+
+fn io_error(path: &Path, e: impl std::fmt::Display) -> ClientError {
+    ClientError::Io(format!("{}: {}", path.display(), e))
+}
+
+/// Read a fixture file
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<FixtureFrame>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| ClientError::Parse(e.to_string())))
+        .collect()
+}
+
+/// Write a fixture file, one frame per line
+pub fn save(path: impl AsRef<Path>, frames: &[FixtureFrame]) -> Result<()> {
+    let path = path.as_ref();
+    let mut text = String::new();
+    for frame in frames {
+        text.push_str(&serde_json::to_string(frame).map_err(|e| io_error(path, e))?);
+        text.push('\n');
+    }
+    fs::write(path, text).map_err(|e| io_error(path, e))
+}
+
+/// Decode captured frames with `adapter`, as the client would have
+pub fn replay(adapter: &mut dyn Adapter, frames: &[FixtureFrame]) -> Result<Vec<MarketDataMessage>> {
+    let mut out = Vec::new();
+    for frame in frames {
+        let mut bytes = frame.frame.clone().into_bytes();
+        adapter.decode(&mut bytes, frame.received, &mut out)?;
+    }
+    Ok(out)
+}
+
+/// Compare `messages` with the golden file at `path`.
+///
+/// When `update` is set the file is rewritten instead. Returns a description
+/// of the first difference, or of the golden file missing.
+pub fn check_golden(
+    path: impl AsRef<Path>,
+    messages: &[MarketDataMessage],
+    update: bool,
+) -> Result<std::result::Result<(), String>> {
+    let path = path.as_ref();
+    let actual: Vec<String> = messages
+        .iter()
+        .map(|msg| serde_json::to_string(msg).map_err(|e| ClientError::Parse(e.to_string())))
+        .collect::<Result<_>>()?;
+
+    if !update && !path.exists() {
+        return Ok(Err(format!(
+            "golden file {} does not exist",
+            path.display()
+        )));
+    }
+    if update {
+        let mut text = actual.join("\n");
+        text.push('\n');
+        fs::write(path, text).map_err(|e| io_error(path, e))?;
+        return Ok(Ok(()));
+    }
+
+    let expected = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+    let expected: Vec<&str> = expected.lines().filter(|l| !l.is_empty()).collect();
+    for (i, (expected, actual)) in expected.iter().zip(&actual).enumerate() {
+        let same = serde_json::from_str::<serde_json::Value>(expected).ok()
+            == serde_json::from_str::<serde_json::Value>(actual).ok();
+        if !same {
+            return Ok(Err(format!(
+                "message {} differs\n  expected: {}\n    actual: {}",
+                i, expected, actual
+            )));
+        }
+    }
+    if expected.len() != actual.len() {
+        return Ok(Err(format!(
+            "expected {} messages, got {}",
+            expected.len(),
+            actual.len()
+        )));
+    }
+    Ok(Ok(()))
+}
+
+/// Connect to `url`, subscribe with `adapter` and record up to `max_frames`
+/// text frames, stopping early after `timeout`
+pub async fn capture(
+    url: &str,
+    adapter: &dyn Adapter,
+    max_frames: usize,
+    timeout: Duration,
+) -> Result<Vec<FixtureFrame>> {
+    let (ws_stream, _) = connect_async(url)
+        .await
+        .map_err(|e| ClientError::Connection(e.to_string()))?;
+    let (mut write, mut read) = ws_stream.split();
+
+    for frame in adapter.subscribe_frames() {
+        write
+            .send(Message::Text(frame))
+            .await
+            .map_err(|e| ClientError::WebSocket(e.to_string()))?;
+    }
+
+    let mut frames = Vec::new();
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(deadline);
+    while frames.len() < max_frames {
+        tokio::select! {
+            _ = &mut deadline => break,
+            frame = read.next() => match frame {
+                Some(Ok(Message::Text(text))) => frames.push(FixtureFrame {
+                    received: Utc::now(),
+                    frame: text,
+                }),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(ClientError::WebSocket(e.to_string())),
+                None => break,
+            },
+        }
+    }
+
+    let _ = write.close().await;
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::NativeAdapter;
+
+    #[test]
+    fn test_golden_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = dir.path().join("native.jsonl");
+        let golden = dir.path().join("native.golden.jsonl");
+        let frames = vec![FixtureFrame {
+            received: Utc::now(),
+            frame: r#"{"type":"Heartbeat"}"#.to_string(),
+        }];
+        save(&fixture, &frames).unwrap();
+
+        let messages = replay(&mut NativeAdapter::new(), &load(&fixture).unwrap()).unwrap();
+        // A missing golden file fails rather than being written
+        assert!(check_golden(&golden, &messages, false).unwrap().is_err());
+        assert!(!golden.exists());
+        assert!(check_golden(&golden, &messages, true).unwrap().is_ok());
+        assert!(check_golden(&golden, &messages, false).unwrap().is_ok());
+        assert!(check_golden(&golden, &[], false).unwrap().is_err());
+    }
+}
@@ -0,0 +1,860 @@
+//! Arrow Flight endpoint for research consumers.
+//!
+//! [`FlightServer`] speaks the Arrow Flight gRPC protocol, so pyarrow, the R
+//! arrow package and other Flight clients pull columnar record batches with
+//! no custom parsing. A ticket is a JSON [`FlightQuery`]. Without a
+//! `recording` it streams live data, one batch per `batch_size` rows or per
+//! flush interval; with one it replays that recording from the history
+//! directory, optionally limited to a time range:
+//!
+//! ```text
+//! {"kind": "trades", "symbols": ["BTCUSD"]}
+//! {"kind": "books", "recording": "2024-03-05.mds", "from": "2024-03-05T14:30:00Z"}
+//! ```
+//!
+//! `ListFlights` lists every recording in the history directory,
+//! `GetFlightInfo` and `GetSchema` describe a query given as the command of
+//! a descriptor, and `DoGet` serves it. Other Flight calls are unimplemented.
+
+use crate::client::{ClientError, Result};
+use crate::recording::RecordingReader;
+use crate::types::{MarketDataMessage, OrderBookSnapshot, PriceLevel, Quote, Trade};
+use arrow_array::{
+    ArrayRef, Float64Array, RecordBatch, StringArray, TimestampNanosecondArray, UInt32Array,
+    UInt8Array,
+};
+use arrow_ipc::writer::{DictionaryTracker, IpcDataGenerator, IpcWriteOptions};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::ops::Bound;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService, ServerStreamingService, UnaryService};
+use tonic::Status;
+use tracing::{info, warn};
+
+const SERVICE: &str = "arrow.flight.protocol.FlightService";
+/// `FlightDescriptor.type` of an opaque command
+const DESCRIPTOR_CMD: i32 = 2;
+
+/// Row layout of the batches served for a query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchKind {
+    Trades,
+    Quotes,
+    /// One row per book level
+    Books,
+}
+
+impl BatchKind {
+    pub fn schema(&self) -> SchemaRef {
+        let utf8 = |name| Field::new(name, DataType::Utf8, false);
+        let float = |name| Field::new(name, DataType::Float64, false);
+        let timestamp = Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+            false,
+        );
+        let instrument_id = Field::new("instrument_id", DataType::Utf8, true);
+        let fields = match self {
+            BatchKind::Trades => vec![
+                utf8("symbol"),
+                timestamp,
+                float("price"),
+                float("quantity"),
+                utf8("side"),
+                utf8("trade_id"),
+                Field::new("conditions", DataType::UInt8, false),
+                instrument_id,
+            ],
+            BatchKind::Quotes => vec![
+                utf8("symbol"),
+                timestamp,
+                float("bid_price"),
+                float("bid_size"),
+                float("ask_price"),
+                float("ask_size"),
+                instrument_id,
+            ],
+            BatchKind::Books => vec![
+                utf8("symbol"),
+                timestamp,
+                utf8("side"),
+                Field::new("level", DataType::UInt32, false),
+                float("price"),
+                float("size"),
+                Field::new("num_orders", DataType::UInt32, false),
+                instrument_id,
+            ],
+        };
+        Arc::new(Schema::new(fields))
+    }
+
+    /// The `messages` of this kind as one batch; others are skipped
+    pub fn record_batch(&self, messages: &[MarketDataMessage]) -> Result<RecordBatch> {
+        let columns = match self {
+            BatchKind::Trades => {
+                let trades: Vec<&Trade> = messages
+                    .iter()
+                    .filter_map(|msg| match msg {
+                        MarketDataMessage::Trade(trade) => Some(trade),
+                        _ => None,
+                    })
+                    .collect();
+                vec![
+                    strings(trades.iter().map(|t| t.symbol.as_str())),
+                    timestamps(trades.iter().map(|t| t.timestamp)),
+                    floats(trades.iter().map(|t| t.price)),
+                    floats(trades.iter().map(|t| t.quantity)),
+                    strings(trades.iter().map(|t| t.side.as_str())),
+                    strings(trades.iter().map(|t| t.trade_id.as_str())),
+                    Arc::new(UInt8Array::from_iter_values(
+                        trades.iter().map(|t| t.conditions.bits()),
+                    )) as ArrayRef,
+                    optional_strings(trades.iter().map(|t| t.instrument_id.as_deref())),
+                ]
+            }
+            BatchKind::Quotes => {
+                let quotes: Vec<&Quote> = messages
+                    .iter()
+                    .filter_map(|msg| match msg {
+                        MarketDataMessage::Quote(quote) => Some(quote),
+                        _ => None,
+                    })
+                    .collect();
+                vec![
+                    strings(quotes.iter().map(|q| q.symbol.as_str())),
+                    timestamps(quotes.iter().map(|q| q.timestamp)),
+                    floats(quotes.iter().map(|q| q.bid_price)),
+                    floats(quotes.iter().map(|q| q.bid_size)),
+                    floats(quotes.iter().map(|q| q.ask_price)),
+                    floats(quotes.iter().map(|q| q.ask_size)),
+                    optional_strings(quotes.iter().map(|q| q.instrument_id.as_deref())),
+                ]
+            }
+            BatchKind::Books => {
+                let mut rows: Vec<(&OrderBookSnapshot, &str, u32, &PriceLevel)> = Vec::new();
+                for msg in messages {
+                    let MarketDataMessage::OrderBook(book) = msg else {
+                        continue;
+                    };
+                    for (side, levels) in [("bid", &book.bids), ("ask", &book.asks)] {
+                        for (i, level) in levels.iter().enumerate() {
+                            rows.push((book, side, i as u32, level));
+                        }
+                    }
+                }
+                vec![
+                    strings(rows.iter().map(|r| r.0.symbol.as_str())),
+                    timestamps(rows.iter().map(|r| r.0.timestamp)),
+                    strings(rows.iter().map(|r| r.1)),
+                    Arc::new(UInt32Array::from_iter_values(rows.iter().map(|r| r.2))) as ArrayRef,
+                    floats(rows.iter().map(|r| r.3.price)),
+                    floats(rows.iter().map(|r| r.3.size)),
+                    Arc::new(UInt32Array::from_iter_values(
+                        rows.iter().map(|r| r.3.num_orders),
+                    )),
+                    optional_strings(rows.iter().map(|r| r.0.instrument_id.as_deref())),
+                ]
+            }
+        };
+        RecordBatch::try_new(self.schema(), columns).map_err(|e| ClientError::Parse(e.to_string()))
+    }
+
+    fn matches(&self, msg: &MarketDataMessage) -> bool {
+        matches!(
+            (self, msg),
+            (BatchKind::Trades, MarketDataMessage::Trade(_))
+                | (BatchKind::Quotes, MarketDataMessage::Quote(_))
+                | (BatchKind::Books, MarketDataMessage::OrderBook(_))
+        )
+    }
+}
+
+fn strings<'a>(values: impl Iterator<Item = &'a str>) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(values))
+}
+
+fn optional_strings<'a>(values: impl Iterator<Item = Option<&'a str>>) -> ArrayRef {
+    Arc::new(values.collect::<StringArray>())
+}
+
+fn floats(values: impl Iterator<Item = f64>) -> ArrayRef {
+    Arc::new(Float64Array::from_iter_values(values))
+}
+
+fn timestamps(values: impl Iterator<Item = DateTime<Utc>>) -> ArrayRef {
+    let nanos = values.map(|t| t.timestamp_nanos_opt().unwrap_or_default());
+    Arc::new(TimestampNanosecondArray::from_iter_values(nanos).with_timezone("UTC"))
+}
+
+/// What a Flight ticket asks for, as JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlightQuery {
+    pub kind: BatchKind,
+    /// Every symbol when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<String>,
+    /// Recording file in the history directory; the live stream when `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recording: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl FlightQuery {
+    pub fn live(kind: BatchKind) -> Self {
+        Self {
+            kind,
+            symbols: Vec::new(),
+            recording: None,
+            from: None,
+            to: None,
+        }
+    }
+
+    pub fn history(kind: BatchKind, recording: &str) -> Self {
+        Self {
+            recording: Some(recording.to_string()),
+            ..Self::live(kind)
+        }
+    }
+
+    pub fn with_symbols(mut self, symbols: &[&str]) -> Self {
+        self.symbols = symbols.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Only messages recorded within `from..=to`; ignored for live data
+    pub fn with_range(mut self, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        self.from = Some(from);
+        self.to = Some(to);
+        self
+    }
+
+    pub fn to_ticket(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    pub fn from_ticket(ticket: &[u8]) -> Result<Self> {
+        serde_json::from_slice(ticket)
+            .map_err(|e| ClientError::Parse(format!("invalid flight query: {}", e)))
+    }
+
+    fn matches(&self, msg: &MarketDataMessage) -> bool {
+        self.kind.matches(msg)
+            && (self.symbols.is_empty()
+                || msg
+                    .symbol()
+                    .is_some_and(|s| self.symbols.iter().any(|x| *x == s)))
+    }
+}
+
+/// Arrow Flight server over the live stream and a directory of recordings
+pub struct FlightServer {
+    source: broadcast::Receiver<MarketDataMessage>,
+    history: Option<PathBuf>,
+    batch_size: usize,
+    flush_interval: Duration,
+}
+
+impl FlightServer {
+    pub fn new(source: broadcast::Receiver<MarketDataMessage>) -> Self {
+        Self {
+            source,
+            history: None,
+            batch_size: 1024,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Serve recordings from `dir`
+    pub fn with_history_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.history = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Rows per record batch
+    pub fn with_batch_size(mut self, rows: usize) -> Self {
+        self.batch_size = rows.max(1);
+        self
+    }
+
+    /// Send a partial live batch after this long
+    pub fn with_flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Accept Flight clients until the listener fails
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        if let Ok(addr) = listener.local_addr() {
+            info!("Arrow Flight server listening on {}", addr);
+        }
+        let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None)
+            .map_err(|e| ClientError::Connection(e.to_string()))?;
+        let service = FlightService(Arc::new(FlightServer {
+            source: self.source.resubscribe(),
+            history: self.history.clone(),
+            batch_size: self.batch_size,
+            flush_interval: self.flush_interval,
+        }));
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(incoming)
+            .await
+            .map_err(|e| ClientError::Connection(e.to_string()))
+    }
+
+    fn recording_path(&self, name: &str) -> Result<PathBuf> {
+        let Some(dir) = &self.history else {
+            return Err(ClientError::Control("no history directory".to_string()));
+        };
+        let mut components = Path::new(name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => Ok(dir.join(name)),
+            _ => Err(ClientError::Parse(format!(
+                "invalid recording name: {}",
+                name
+            ))),
+        }
+    }
+
+    fn do_get(&self, ticket: Ticket) -> Result<FlightStream<FlightData>> {
+        let query = FlightQuery::from_ticket(&ticket.ticket)?;
+        let (tx, rx) = mpsc::channel(4);
+        match &query.recording {
+            Some(name) => {
+                let path = self.recording_path(name)?;
+                let batch_size = self.batch_size;
+                tokio::task::spawn_blocking(move || replay(path, query, batch_size, tx));
+            }
+            None => {
+                let source = self.source.resubscribe();
+                let (batch_size, interval) = (self.batch_size, self.flush_interval);
+                tokio::spawn(stream_live(source, query, batch_size, interval, tx));
+            }
+        }
+        Ok(FlightStream(rx))
+    }
+
+    fn flight_info(&self, descriptor: FlightDescriptor) -> Result<FlightInfo> {
+        if descriptor.r#type != DESCRIPTOR_CMD {
+            return Err(ClientError::Parse(
+                "expected a command descriptor".to_string(),
+            ));
+        }
+        let query = FlightQuery::from_ticket(&descriptor.cmd)?;
+        if let Some(name) = &query.recording {
+            let path = self.recording_path(name)?;
+            if !path.is_file() {
+                return Err(ClientError::Io(format!("no recording {}", name)));
+            }
+        }
+        Ok(FlightInfo {
+            schema: schema_bytes(&query.kind.schema()),
+            endpoint: vec![FlightEndpoint {
+                ticket: Some(Ticket {
+                    ticket: descriptor.cmd.clone(),
+                }),
+            }],
+            flight_descriptor: Some(descriptor),
+            total_records: -1,
+            total_bytes: -1,
+            ordered: true,
+        })
+    }
+
+    fn schema(&self, descriptor: FlightDescriptor) -> Result<SchemaResult> {
+        let info = self.flight_info(descriptor)?;
+        Ok(SchemaResult {
+            schema: info.schema,
+        })
+    }
+
+    /// Every kind of every recording in the history directory
+    fn list_flights(&self, _: Criteria) -> Result<FlightStream<FlightInfo>> {
+        let mut names = Vec::new();
+        if let Some(dir) = &self.history {
+            let entries = std::fs::read_dir(dir).map_err(|e| ClientError::Io(e.to_string()))?;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "mds") {
+                    names.extend(path.file_name().map(|n| n.to_string_lossy().into_owned()));
+                }
+            }
+        }
+        names.sort();
+        let (tx, rx) = mpsc::channel(names.len() * 3 + 1);
+        for name in names {
+            for kind in [BatchKind::Trades, BatchKind::Quotes, BatchKind::Books] {
+                let info = self.flight_info(FlightDescriptor {
+                    r#type: DESCRIPTOR_CMD,
+                    cmd: FlightQuery::history(kind, &name).to_ticket(),
+                });
+                let _ = tx.try_send(info.map_err(status));
+            }
+        }
+        Ok(FlightStream(rx))
+    }
+}
+
+/// Live batches of `query` until the client goes away or the source closes
+async fn stream_live(
+    mut source: broadcast::Receiver<MarketDataMessage>,
+    query: FlightQuery,
+    batch_size: usize,
+    interval: Duration,
+    tx: mpsc::Sender<std::result::Result<FlightData, Status>>,
+) {
+    let mut encoder = BatchEncoder::new();
+    if tx
+        .send(Ok(encoder.schema(&query.kind.schema())))
+        .await
+        .is_err()
+    {
+        return;
+    }
+    let mut pending = Vec::with_capacity(batch_size);
+    let mut flush = tokio::time::interval(interval);
+    loop {
+        let closed = tokio::select! {
+            msg = source.recv() => match msg {
+                Ok(msg) => {
+                    if query.matches(&msg) {
+                        pending.push(msg);
+                    }
+                    if pending.len() < batch_size {
+                        continue;
+                    }
+                    false
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("Flight stream lagged, skipped {} messages", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => true,
+            },
+            _ = flush.tick() => false,
+            _ = tx.closed() => return,
+        };
+        if !pending.is_empty() {
+            let batch = encoder.batch(query.kind, &pending).map_err(status);
+            pending.clear();
+            if tx.send(batch).await.is_err() {
+                return;
+            }
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+/// Batches of `query` from one recording, on a blocking thread
+fn replay(
+    path: PathBuf,
+    query: FlightQuery,
+    batch_size: usize,
+    tx: mpsc::Sender<std::result::Result<FlightData, Status>>,
+) {
+    let mut reader = match RecordingReader::open(&path) {
+        Ok(reader) => reader,
+        Err(e) => {
+            let _ = tx.blocking_send(Err(status(e)));
+            return;
+        }
+    };
+    let mut encoder = BatchEncoder::new();
+    if tx
+        .blocking_send(Ok(encoder.schema(&query.kind.schema())))
+        .is_err()
+    {
+        return;
+    }
+    let bound = |t: Option<DateTime<Utc>>| t.map_or(Bound::Unbounded, Bound::Included);
+    let mut pending = Vec::with_capacity(batch_size);
+    for item in reader.range((bound(query.from), bound(query.to))) {
+        match item {
+            Ok((_, msg)) if query.matches(&msg) => pending.push(msg),
+            Ok(_) => continue,
+            Err(e) => {
+                let _ = tx.blocking_send(Err(Status::data_loss(e.to_string())));
+                return;
+            }
+        }
+        if pending.len() == batch_size {
+            let batch = encoder.batch(query.kind, &pending).map_err(status);
+            pending.clear();
+            if tx.blocking_send(batch).is_err() {
+                return;
+            }
+        }
+    }
+    if !pending.is_empty() {
+        let _ = tx.blocking_send(encoder.batch(query.kind, &pending).map_err(status));
+    }
+}
+
+/// gRPC status of a failed call
+fn status(e: ClientError) -> Status {
+    match e {
+        ClientError::Parse(e) => Status::invalid_argument(e),
+        ClientError::Io(e) => Status::not_found(e),
+        ClientError::Control(e) => Status::failed_precondition(e),
+        e => Status::internal(e.to_string()),
+    }
+}
+
+/// Response stream of a streaming Flight call
+struct FlightStream<T>(mpsc::Receiver<std::result::Result<T, Status>>);
+
+impl<T> futures_util::Stream for FlightStream<T> {
+    type Item = std::result::Result<T, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+/// Arrow IPC encoding of one stream's schema and batches
+struct BatchEncoder {
+    generator: IpcDataGenerator,
+    dictionaries: DictionaryTracker,
+    options: IpcWriteOptions,
+}
+
+impl BatchEncoder {
+    fn new() -> Self {
+        Self {
+            generator: IpcDataGenerator::default(),
+            dictionaries: DictionaryTracker::new(false),
+            options: IpcWriteOptions::default(),
+        }
+    }
+
+    fn schema(&mut self, schema: &Schema) -> FlightData {
+        let encoded = self.generator.schema_to_bytes_with_dictionary_tracker(
+            schema,
+            &mut self.dictionaries,
+            &self.options,
+        );
+        FlightData {
+            data_header: encoded.ipc_message,
+            ..FlightData::default()
+        }
+    }
+
+    fn batch(&mut self, kind: BatchKind, messages: &[MarketDataMessage]) -> Result<FlightData> {
+        let batch = kind.record_batch(messages)?;
+        let (_, encoded) = self
+            .generator
+            .encoded_batch(&batch, &mut self.dictionaries, &self.options)
+            .map_err(|e| ClientError::Parse(e.to_string()))?;
+        Ok(FlightData {
+            data_header: encoded.ipc_message,
+            data_body: encoded.arrow_data,
+            ..FlightData::default()
+        })
+    }
+}
+
+/// A schema as an encapsulated IPC message, as `FlightInfo` carries it
+fn schema_bytes(schema: &Schema) -> Vec<u8> {
+    let mut encoder = BatchEncoder::new();
+    let encoded = encoder.generator.schema_to_bytes_with_dictionary_tracker(
+        schema,
+        &mut encoder.dictionaries,
+        &encoder.options,
+    );
+    let mut out = Vec::new();
+    let _ = arrow_ipc::writer::write_message(&mut out, encoded, &encoder.options);
+    out
+}
+
+// The subset of the Flight protocol (Flight.proto) the server speaks.
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Ticket {
+    #[prost(bytes = "vec", tag = "1")]
+    ticket: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct FlightDescriptor {
+    #[prost(int32, tag = "1")]
+    r#type: i32,
+    #[prost(bytes = "vec", tag = "2")]
+    cmd: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct FlightData {
+    #[prost(message, optional, tag = "1")]
+    flight_descriptor: Option<FlightDescriptor>,
+    #[prost(bytes = "vec", tag = "2")]
+    data_header: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    app_metadata: Vec<u8>,
+    #[prost(bytes = "vec", tag = "1000")]
+    data_body: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct FlightEndpoint {
+    #[prost(message, optional, tag = "1")]
+    ticket: Option<Ticket>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct FlightInfo {
+    #[prost(bytes = "vec", tag = "1")]
+    schema: Vec<u8>,
+    #[prost(message, optional, tag = "2")]
+    flight_descriptor: Option<FlightDescriptor>,
+    #[prost(message, repeated, tag = "3")]
+    endpoint: Vec<FlightEndpoint>,
+    #[prost(int64, tag = "4")]
+    total_records: i64,
+    #[prost(int64, tag = "5")]
+    total_bytes: i64,
+    #[prost(bool, tag = "6")]
+    ordered: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SchemaResult {
+    #[prost(bytes = "vec", tag = "1")]
+    schema: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Criteria {
+    #[prost(bytes = "vec", tag = "1")]
+    expression: Vec<u8>,
+}
+
+/// The gRPC service, routing Flight calls to a [`FlightServer`]
+#[derive(Clone)]
+struct FlightService(Arc<FlightServer>);
+
+impl NamedService for FlightService {
+    const NAME: &'static str = SERVICE;
+}
+
+impl<B> Service<http::Request<B>> for FlightService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<std::result::Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let server = self.0.clone();
+        let method = req.uri().path().rsplit('/').next().unwrap_or_default();
+        match method {
+            "DoGet" => Box::pin(async move {
+                let call = Call::new(server, FlightServer::do_get);
+                Ok(Grpc::new(ProstCodec::default())
+                    .server_streaming(call, req)
+                    .await)
+            }),
+            "GetFlightInfo" => Box::pin(async move {
+                let call = Call::new(server, FlightServer::flight_info);
+                Ok(Grpc::new(ProstCodec::default()).unary(call, req).await)
+            }),
+            "GetSchema" => Box::pin(async move {
+                let call = Call::new(server, FlightServer::schema);
+                Ok(Grpc::new(ProstCodec::default()).unary(call, req).await)
+            }),
+            "ListFlights" => Box::pin(async move {
+                let call = Call::new(server, FlightServer::list_flights);
+                Ok(Grpc::new(ProstCodec::default())
+                    .server_streaming(call, req)
+                    .await)
+            }),
+            other => {
+                let status = Status::unimplemented(format!("{} is not supported", other));
+                Box::pin(async move { Ok(status.into_http()) })
+            }
+        }
+    }
+}
+
+/// One Flight method bound to the server
+struct Call<Req, Out> {
+    server: Arc<FlightServer>,
+    handler: fn(&FlightServer, Req) -> Result<Out>,
+}
+
+impl<Req, Out> Call<Req, Out> {
+    fn new(server: Arc<FlightServer>, handler: fn(&FlightServer, Req) -> Result<Out>) -> Self {
+        Self { server, handler }
+    }
+
+    fn ready(&self, request: tonic::Request<Req>) -> Ready<tonic::Response<Out>> {
+        std::future::ready(
+            (self.handler)(&self.server, request.into_inner())
+                .map(tonic::Response::new)
+                .map_err(status),
+        )
+    }
+}
+
+type Ready<T> = std::future::Ready<std::result::Result<T, Status>>;
+
+impl<Req, Resp> UnaryService<Req> for Call<Req, Resp> {
+    type Response = Resp;
+    type Future = Ready<tonic::Response<Resp>>;
+
+    fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+        self.ready(request)
+    }
+}
+
+impl<Req, Resp> ServerStreamingService<Req> for Call<Req, FlightStream<Resp>> {
+    type Response = Resp;
+    type ResponseStream = FlightStream<Resp>;
+    type Future = Ready<tonic::Response<FlightStream<Resp>>>;
+
+    fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+        self.ready(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::RecordingWriter;
+
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Float64Type;
+    use arrow_ipc::reader::StreamReader;
+    use arrow_ipc::writer::{write_message, EncodedData};
+    use chrono::TimeZone;
+    use tonic::transport::Channel;
+
+    fn trade(symbol: &str, price: f64, timestamp: DateTime<Utc>) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            timestamp,
+            trade_id: price.to_string(),
+            ..Trade::test(symbol, price)
+        })
+    }
+
+    async fn do_get(
+        channel: &Channel,
+        query: &FlightQuery,
+    ) -> std::result::Result<tonic::Streaming<FlightData>, Status> {
+        let mut grpc = tonic::client::Grpc::new(channel.clone());
+        grpc.ready().await.unwrap();
+        let ticket = Ticket {
+            ticket: query.to_ticket(),
+        };
+        let path =
+            http::uri::PathAndQuery::from_static("/arrow.flight.protocol.FlightService/DoGet");
+        let response = grpc
+            .server_streaming(
+                tonic::Request::new(ticket),
+                path,
+                tonic::codec::ProstCodec::default(),
+            )
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Reassemble Flight messages into an IPC stream, as Flight clients do
+    fn decode(data: &[FlightData]) -> Vec<RecordBatch> {
+        let options = IpcWriteOptions::default();
+        let mut ipc = Vec::new();
+        for d in data {
+            let encoded = EncodedData {
+                ipc_message: d.data_header.clone(),
+                arrow_data: d.data_body.clone(),
+            };
+            write_message(&mut ipc, encoded, &options).unwrap();
+        }
+        StreamReader::try_new(ipc.as_slice(), None)
+            .unwrap()
+            .map(|batch| batch.unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_do_get_history_and_live() {
+        let dir = tempfile::tempdir().unwrap();
+        let t = |s| Utc.with_ymd_and_hms(2024, 3, 5, 14, 30, s).unwrap();
+        let mut writer = RecordingWriter::create(dir.path().join("day.mds"), 16).unwrap();
+        for (i, symbol) in ["BTCUSD", "ETHUSD", "BTCUSD", "BTCUSD", "BTCUSD"]
+            .iter()
+            .enumerate()
+        {
+            writer
+                .write(&trade(symbol, 100.0 + i as f64, t(i as u32)))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let (tx, rx) = broadcast::channel(16);
+        let server = FlightServer::new(rx)
+            .with_history_dir(dir.path())
+            .with_batch_size(2);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server.serve(listener).await });
+        let channel = Channel::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+
+        let query = FlightQuery::history(BatchKind::Trades, "day.mds")
+            .with_symbols(&["BTCUSD"])
+            .with_range(t(0), t(3));
+        let mut stream = do_get(&channel, &query).await.unwrap();
+        let mut data = Vec::new();
+        while let Some(msg) = stream.message().await.unwrap() {
+            data.push(msg);
+        }
+        let batches = decode(&data);
+        assert_eq!(
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            [2, 1]
+        );
+        assert_eq!(batches[0].schema(), BatchKind::Trades.schema());
+        let prices = batches[1].column(2).as_primitive::<Float64Type>();
+        assert_eq!(prices.value(0), 103.0);
+
+        // Live batches start with the schema, then fill to the batch size
+        let mut stream = do_get(&channel, &FlightQuery::live(BatchKind::Trades))
+            .await
+            .unwrap();
+        let schema = stream.message().await.unwrap().unwrap();
+        tx.send(trade("BTCUSD", 1.0, t(10))).unwrap();
+        tx.send(trade("ETHUSD", 2.0, t(11))).unwrap();
+        let batch = stream.message().await.unwrap().unwrap();
+        let live = decode(&[schema, batch]);
+        assert_eq!(live[0].num_rows(), 2);
+
+        let escape = FlightQuery::history(BatchKind::Trades, "../day.mds");
+        let err = do_get(&channel, &escape).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,165 @@
+//! Currency conversion of prices into a reference currency.
+
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Converts instrument prices into a reference currency using FX or
+/// stablecoin pair quotes seen on the stream.
+///
+/// As a pipeline stage it passes every message through and, for configured
+/// instruments, also emits a converted copy whose symbol is suffixed with the
+/// reference currency (e.g. `BTCUSDT.EUR`). Sizes are left unchanged.
+#[derive(Debug, Clone)]
+pub struct FxConverter {
+    reference: String,
+    pairs: HashMap<Symbol, (String, String)>,
+    instruments: HashMap<Symbol, String>,
+    mids: HashMap<Symbol, f64>,
+}
+
+impl FxConverter {
+    pub fn new(reference: &str) -> Self {
+        Self {
+            reference: reference.to_string(),
+            pairs: HashMap::new(),
+            instruments: HashMap::new(),
+            mids: HashMap::new(),
+        }
+    }
+
+    /// Use quotes/trades on `symbol` (one `base` costs price `quote`) as a rate source
+    pub fn with_pair(mut self, symbol: &str, base: &str, quote: &str) -> Self {
+        self.pairs
+            .insert(symbol.into(), (base.to_string(), quote.to_string()));
+        self
+    }
+
+    /// Convert prices of `symbol`, which is quoted in `currency`
+    pub fn with_instrument(mut self, symbol: &str, currency: &str) -> Self {
+        self.instruments.insert(symbol.into(), currency.to_string());
+        self
+    }
+
+    /// Symbols of the configured rate pairs, for subscription
+    pub fn pair_symbols(&self) -> impl Iterator<Item = &str> {
+        self.pairs.keys().map(|symbol| symbol.as_str())
+    }
+
+    /// Value of one unit of `currency` in the reference currency
+    pub fn rate(&self, currency: &str) -> Option<f64> {
+        if currency == self.reference {
+            return Some(1.0);
+        }
+
+        // Breadth-first walk over known pairs starting from the reference
+        let mut rates: HashMap<&str, f64> = HashMap::from([(self.reference.as_str(), 1.0)]);
+        let mut queue = VecDeque::from([self.reference.as_str()]);
+        let mut visited = HashSet::new();
+
+        while let Some(ccy) = queue.pop_front() {
+            if !visited.insert(ccy) {
+                continue;
+            }
+            let value = rates[ccy];
+            for (symbol, (base, quote)) in &self.pairs {
+                let Some(mid) = self.mids.get(symbol).filter(|mid| **mid > 0.0) else {
+                    continue;
+                };
+                let next = if quote == ccy {
+                    (base.as_str(), value * mid)
+                } else if base == ccy {
+                    (quote.as_str(), value / mid)
+                } else {
+                    continue;
+                };
+                if next.0 == currency {
+                    return Some(next.1);
+                }
+                rates.entry(next.0).or_insert(next.1);
+                queue.push_back(next.0);
+            }
+        }
+        None
+    }
+
+    /// Convert a price quoted in `currency` into the reference currency
+    pub fn convert(&self, price: f64, currency: &str) -> Option<f64> {
+        self.rate(currency).map(|rate| price * rate)
+    }
+
+    /// Update rates from a message, returning its converted copy if any
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Option<MarketDataMessage> {
+        match msg {
+            MarketDataMessage::Quote(quote) if self.pairs.contains_key(&quote.symbol) => {
+                self.mids.insert(quote.symbol, quote.mid_price());
+            }
+            MarketDataMessage::Trade(trade) if self.pairs.contains_key(&trade.symbol) => {
+                self.mids.insert(trade.symbol, trade.price);
+            }
+            _ => {}
+        }
+
+        match msg {
+            MarketDataMessage::Trade(trade) => {
+                let rate = self.rate(self.instruments.get(&trade.symbol)?)?;
+                let mut converted = trade.clone();
+                converted.symbol = format!("{}.{}", trade.symbol, self.reference).into();
+                converted.price *= rate;
+                Some(MarketDataMessage::Trade(converted))
+            }
+            MarketDataMessage::Quote(quote) => {
+                let rate = self.rate(self.instruments.get(&quote.symbol)?)?;
+                let mut converted = quote.clone();
+                converted.symbol = format!("{}.{}", quote.symbol, self.reference).into();
+                converted.bid_price *= rate;
+                converted.ask_price *= rate;
+                Some(MarketDataMessage::Quote(converted))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Stage for FxConverter {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        let converted = self.on_message(&msg);
+        out.push(msg);
+        out.extend(converted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Quote;
+
+    fn quote(symbol: &str, bid: f64, ask: f64) -> MarketDataMessage {
+        MarketDataMessage::Quote(Quote::test(symbol, bid, ask))
+    }
+
+    #[test]
+    fn test_cross_rate_conversion() {
+        let mut fx = FxConverter::new("EUR")
+            .with_pair("EURUSD", "EUR", "USD")
+            .with_pair("USDTUSD", "USDT", "USD")
+            .with_instrument("BTCUSDT", "USDT");
+
+        assert!(fx.on_message(&quote("BTCUSDT", 50000.0, 50000.0)).is_none());
+        fx.on_message(&quote("EURUSD", 1.25, 1.25));
+        fx.on_message(&quote("USDTUSD", 1.0, 1.0));
+
+        // 1 USDT = 1 USD = 0.8 EUR
+        assert!((fx.rate("USDT").unwrap() - 0.8).abs() < 1e-12);
+
+        let mut out = Vec::new();
+        fx.process(quote("BTCUSDT", 50000.0, 50010.0), &mut out);
+        assert_eq!(out.len(), 2);
+        let MarketDataMessage::Quote(converted) = &out[1] else {
+            panic!("expected quote");
+        };
+        assert_eq!(converted.symbol, "BTCUSDT.EUR");
+        assert!((converted.bid_price - 40000.0).abs() < 1e-6);
+    }
+}
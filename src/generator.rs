@@ -0,0 +1,227 @@
+//! Seeded synthetic market data.
+//!
+//! A [`SyntheticFeed`] generates trades and quotes for a set of symbols as
+//! a random walk, entirely from a seed and a start time: emission times are
+//! virtual, advancing by random gaps from the start rather than following
+//! the wall clock or task scheduling. The same seed always yields the same
+//! stream, so tests and [`Backtest`](crate::backtest::Backtest) runs over it
+//! reproduce bit for bit. Only basic arithmetic is used, so the output is
+//! the same on every platform too.
+//!
+//! The feed is an iterator of `(timestamp, message)` pairs, ready for
+//! [`Backtest::run`](crate::backtest::Backtest::run), or for a
+//! [`StreamGraph`](crate::graph::StreamGraph) through an
+//! [`IterSource`](crate::graph::IterSource).
+
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Quote, Trade, TradeConditions, TradeSide};
+use chrono::{DateTime, Duration, Utc};
+
+/// Small deterministic random number generator (SplitMix64)
+#[derive(Debug, Clone)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Approximately standard normal (sum of twelve uniforms)
+    pub fn next_normal(&mut self) -> f64 {
+        (0..12).map(|_| self.next_f64()).sum::<f64>() - 6.0
+    }
+
+    /// Uniform in `0..n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SymbolWalk {
+    symbol: Symbol,
+    price: f64,
+}
+
+/// Seeded random-walk trades and quotes
+#[derive(Debug, Clone)]
+pub struct SyntheticFeed {
+    rng: SeededRng,
+    now: DateTime<Utc>,
+    symbols: Vec<SymbolWalk>,
+    rate: f64,
+    volatility: f64,
+    quote_ratio: f64,
+    tick: f64,
+    max_quantity: f64,
+    next_trade_id: u64,
+}
+
+impl SyntheticFeed {
+    /// A feed starting at `start`; add symbols with
+    /// [`with_symbol`](Self::with_symbol)
+    pub fn new(seed: u64, start: DateTime<Utc>) -> Self {
+        Self {
+            rng: SeededRng::new(seed),
+            now: start,
+            symbols: Vec::new(),
+            rate: 10.0,
+            volatility: 0.0005,
+            quote_ratio: 0.5,
+            tick: 0.01,
+            max_quantity: 1.0,
+            next_trade_id: 1,
+        }
+    }
+
+    pub fn with_symbol(mut self, symbol: &str, price: f64) -> Self {
+        self.symbols.push(SymbolWalk {
+            symbol: symbol.into(),
+            price,
+        });
+        self
+    }
+
+    /// Average messages per second across all symbols (default 10)
+    pub fn with_rate(mut self, per_second: f64) -> Self {
+        self.rate = per_second.max(f64::MIN_POSITIVE);
+        self
+    }
+
+    /// Standard deviation of the relative price move per message (default
+    /// 0.0005)
+    pub fn with_volatility(mut self, volatility: f64) -> Self {
+        self.volatility = volatility;
+        self
+    }
+
+    /// Share of messages that are quotes rather than trades (default 0.5)
+    pub fn with_quote_ratio(mut self, ratio: f64) -> Self {
+        self.quote_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Price increment prices are rounded to (default 0.01)
+    pub fn with_tick(mut self, tick: f64) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    /// Largest trade or quote size (default 1)
+    pub fn with_max_quantity(mut self, quantity: f64) -> Self {
+        self.max_quantity = quantity;
+        self
+    }
+
+    fn round(&self, price: f64) -> f64 {
+        if self.tick > 0.0 {
+            (price / self.tick).round() * self.tick
+        } else {
+            price
+        }
+    }
+
+    fn quantity(&mut self) -> f64 {
+        let quantity = self.max_quantity * (1.0 - self.rng.next_f64());
+        (quantity * 1e8).round() / 1e8
+    }
+}
+
+impl Iterator for SyntheticFeed {
+    type Item = (DateTime<Utc>, MarketDataMessage);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.symbols.is_empty() {
+            return None;
+        }
+        // Gaps uniform in [0, 2 / rate), averaging the configured rate
+        let gap = self.rng.next_f64() * 2e9 / self.rate;
+        self.now += Duration::nanoseconds(gap as i64);
+        let index = self.rng.below(self.symbols.len());
+        let step = 1.0 + self.volatility * self.rng.next_normal();
+        let previous = self.symbols[index].price;
+        let price = self.round(previous * step).max(self.tick);
+        self.symbols[index].price = price;
+        let symbol = self.symbols[index].symbol;
+
+        let msg = if self.rng.next_f64() < self.quote_ratio {
+            let half_spread = self.tick.max(price * 1e-4);
+            let bid_size = self.quantity();
+            let ask_size = self.quantity();
+            MarketDataMessage::Quote(Quote {
+                symbol,
+                bid_price: self.round(price - half_spread),
+                bid_size,
+                ask_price: self.round(price + half_spread),
+                ask_size,
+                timestamp: self.now,
+                instrument_id: None,
+                contract: None,
+                received: None,
+            })
+        } else {
+            let trade_id = self.next_trade_id;
+            self.next_trade_id += 1;
+            MarketDataMessage::Trade(Trade {
+                symbol,
+                price,
+                quantity: self.quantity(),
+                side: if price < previous {
+                    TradeSide::Sell
+                } else {
+                    TradeSide::Buy
+                },
+                timestamp: self.now,
+                trade_id: trade_id.to_string(),
+                conditions: TradeConditions::empty(),
+                instrument_id: None,
+                contract: None,
+                received: None,
+            })
+        };
+        Some((self.now, msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn lines(seed: u64) -> Vec<String> {
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 9, 30, 0).unwrap();
+        SyntheticFeed::new(seed, start)
+            .with_symbol("AAPL", 190.0)
+            .with_symbol("MSFT", 370.0)
+            .with_rate(100.0)
+            .take(500)
+            .map(|(at, msg)| format!("{} {}", at, serde_json::to_string(&msg).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_same_seed_same_stream() {
+        let stream = lines(7);
+        assert_eq!(stream, lines(7));
+        assert_ne!(stream, lines(8));
+        assert!(stream.iter().any(|line| line.contains("\"MSFT\"")));
+        // About 5s of data at 100 messages per second
+        let last = stream.last().unwrap();
+        assert!(last.starts_with("2024-01-02 09:30:0"), "{}", last);
+    }
+}
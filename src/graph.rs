@@ -0,0 +1,450 @@
+//! Declarative processing graphs of sources, transforms and sinks.
+//!
+//! A [`StreamGraph`] names its nodes and lists each node's inputs. A node
+//! with several consumers sends each of them a copy of every message
+//! (fan-out), and a node with several inputs reads them merged in arrival
+//! order (fan-in). [`spawn`](StreamGraph::spawn) checks that the graph is
+//! well formed before starting one task per node:
+//!
+//! ```rust,no_run
+//! # use rust_market_data_stream::{MarketDataClient, StreamGraph, Filter};
+//! # async fn run(client: MarketDataClient) -> rust_market_data_stream::client::Result<()> {
+//! let graph = StreamGraph::new()
+//!     .with_source("feed", client.subscribe())
+//!     .with_transform("btc", Filter::parse("symbol == 'BTCUSD'")?, &["feed"])
+//!     .with_sink("print", |msg| println!("{:?}", msg), &["btc"])
+//!     .spawn()?;
+//! graph.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Sinks can be rate-capped per symbol with
+//! [`with_throttles`](StreamGraph::with_throttles).
+//!
+//! Shutdown stops the sources first; every other node finishes once all of
+//! its inputs have hung up, so messages already emitted still reach the
+//! sinks.
+
+use crate::client::{ClientError, Result};
+use crate::pipeline::Stage;
+use crate::throttle::{SinkThrottles, ThrottledSink};
+use crate::types::MarketDataMessage;
+use futures_util::future::BoxFuture;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, warn};
+
+/// Messages a node may have queued before its inputs wait
+const NODE_CHANNEL_CAPACITY: usize = 1024;
+
+/// Where a graph's messages come from
+pub trait Source: Send + 'static {
+    /// The next message, or `None` once the source is exhausted
+    fn next_message(&mut self) -> impl Future<Output = Option<MarketDataMessage>> + Send;
+}
+
+impl Source for broadcast::Receiver<MarketDataMessage> {
+    async fn next_message(&mut self) -> Option<MarketDataMessage> {
+        loop {
+            match self.recv().await {
+                Ok(msg) => return Some(msg),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("Graph source lagged, skipped {} messages", n)
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Source for mpsc::Receiver<MarketDataMessage> {
+    async fn next_message(&mut self) -> Option<MarketDataMessage> {
+        self.recv().await
+    }
+}
+
+/// A finite source over an iterator, such as a recording
+pub struct IterSource<I>(pub I);
+
+impl<I> Source for IterSource<I>
+where
+    I: Iterator<Item = MarketDataMessage> + Send + 'static,
+{
+    async fn next_message(&mut self) -> Option<MarketDataMessage> {
+        self.0.next()
+    }
+}
+
+/// Where a graph's messages end up
+pub trait MessageSink: Send + 'static {
+    fn consume(&mut self, msg: MarketDataMessage);
+
+    /// Called once after the last message
+    fn close(&mut self) {}
+}
+
+impl<F> MessageSink for F
+where
+    F: FnMut(MarketDataMessage) + Send + 'static,
+{
+    fn consume(&mut self, msg: MarketDataMessage) {
+        self(msg)
+    }
+}
+
+type SourceTask = Box<dyn FnOnce(watch::Receiver<bool>, Outputs) -> BoxFuture<'static, ()> + Send>;
+
+enum NodeKind {
+    Source(SourceTask),
+    Transform(Box<dyn Stage>),
+    Sink(Box<dyn MessageSink>),
+}
+
+struct Node {
+    name: String,
+    inputs: Vec<String>,
+    kind: NodeKind,
+}
+
+/// Senders to every consumer of a node
+struct Outputs(Vec<mpsc::Sender<MarketDataMessage>>);
+
+impl Outputs {
+    /// Send `msg` to every consumer still running; false once none are
+    async fn emit(&self, msg: MarketDataMessage) -> bool {
+        let Some((last, rest)) = self.0.split_last() else {
+            return false;
+        };
+        let mut delivered = false;
+        for tx in rest {
+            delivered |= tx.send(msg.clone()).await.is_ok();
+        }
+        delivered | last.send(msg).await.is_ok()
+    }
+}
+
+/// Builder for a graph of named nodes
+#[derive(Default)]
+pub struct StreamGraph {
+    nodes: Vec<Node>,
+    throttles: SinkThrottles,
+}
+
+impl StreamGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_source(self, name: &str, mut source: impl Source) -> Self {
+        let task: SourceTask = Box::new(move |mut shutdown, outputs| {
+            Box::pin(async move {
+                loop {
+                    let msg = tokio::select! {
+                        msg = source.next_message() => msg,
+                        _ = shutdown.changed() => None,
+                    };
+                    let Some(msg) = msg else {
+                        break;
+                    };
+                    if !outputs.emit(msg).await {
+                        break;
+                    }
+                }
+            })
+        });
+        self.push(name, &[], NodeKind::Source(task))
+    }
+
+    /// Run `stage` on the merged messages of `inputs`
+    pub fn with_transform(self, name: &str, stage: impl Stage + 'static, inputs: &[&str]) -> Self {
+        self.push(name, inputs, NodeKind::Transform(Box::new(stage)))
+    }
+
+    /// Deliver the merged messages of `inputs` to `sink`
+    pub fn with_sink(self, name: &str, sink: impl MessageSink, inputs: &[&str]) -> Self {
+        self.push(name, inputs, NodeKind::Sink(Box::new(sink)))
+    }
+
+    /// Cap the sinks named in `throttles`
+    pub fn with_throttles(mut self, throttles: SinkThrottles) -> Self {
+        self.throttles = throttles;
+        self
+    }
+
+    fn push(mut self, name: &str, inputs: &[&str], kind: NodeKind) -> Self {
+        self.nodes.push(Node {
+            name: name.to_string(),
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+            kind,
+        });
+        self
+    }
+
+    /// Check names, inputs and acyclicity, returning the node indices in
+    /// topological order
+    pub fn validate(&self) -> Result<Vec<usize>> {
+        let mut index = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if index.insert(node.name.as_str(), i).is_some() {
+                return Err(invalid(format!("duplicate node {}", node.name)));
+            }
+        }
+        if !self
+            .nodes
+            .iter()
+            .any(|node| matches!(node.kind, NodeKind::Source(_)))
+        {
+            return Err(invalid("no sources".to_string()));
+        }
+
+        let mut consumers = vec![Vec::new(); self.nodes.len()];
+        let mut pending = vec![0; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !matches!(node.kind, NodeKind::Source(_)) && node.inputs.is_empty() {
+                return Err(invalid(format!("{} has no inputs", node.name)));
+            }
+            let mut seen = HashSet::new();
+            for input in &node.inputs {
+                let &from = index.get(input.as_str()).ok_or_else(|| {
+                    invalid(format!("{} reads unknown node {}", node.name, input))
+                })?;
+                if matches!(self.nodes[from].kind, NodeKind::Sink(_)) {
+                    return Err(invalid(format!("{} reads sink {}", node.name, input)));
+                }
+                if seen.insert(from) {
+                    consumers[from].push(i);
+                    pending[i] += 1;
+                }
+            }
+        }
+        if let Some(node) = self
+            .nodes
+            .iter()
+            .zip(&consumers)
+            .find(|(node, consumers)| {
+                !matches!(node.kind, NodeKind::Sink(_)) && consumers.is_empty()
+            })
+            .map(|(node, _)| node)
+        {
+            return Err(invalid(format!("output of {} is unused", node.name)));
+        }
+        if let Some(sink) = self.throttles.sinks().find(|sink| {
+            index
+                .get(sink)
+                .is_none_or(|&i| !matches!(self.nodes[i].kind, NodeKind::Sink(_)))
+        }) {
+            return Err(invalid(format!("throttle for unknown sink {}", sink)));
+        }
+
+        let mut ready: VecDeque<usize> =
+            (0..self.nodes.len()).filter(|&i| pending[i] == 0).collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(i) = ready.pop_front() {
+            order.push(i);
+            for &next in &consumers[i] {
+                pending[next] -= 1;
+                if pending[next] == 0 {
+                    ready.push_back(next);
+                }
+            }
+        }
+        if order.len() < self.nodes.len() {
+            // Unordered nodes merely downstream of a cycle feed nothing that
+            // is still unordered; drop them to name only the cycle
+            let mut cycle: Vec<usize> = (0..self.nodes.len()).filter(|&i| pending[i] > 0).collect();
+            while let Some(at) = cycle
+                .iter()
+                .position(|&i| !consumers[i].iter().any(|next| cycle.contains(next)))
+            {
+                cycle.remove(at);
+            }
+            let cycle: Vec<&str> = cycle.iter().map(|&i| self.nodes[i].name.as_str()).collect();
+            return Err(invalid(format!("cycle through {}", cycle.join(", "))));
+        }
+        Ok(order)
+    }
+
+    /// Validate the graph and start one task per node
+    pub fn spawn(self) -> Result<GraphHandle> {
+        let order = self.validate()?;
+        let index: HashMap<String, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.name.clone(), i))
+            .collect();
+
+        let mut senders = Vec::with_capacity(self.nodes.len());
+        let mut receivers = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let (tx, rx) = match node.kind {
+                NodeKind::Source(_) => (None, None),
+                _ => {
+                    let (tx, rx) = mpsc::channel(NODE_CHANNEL_CAPACITY);
+                    (Some(tx), Some(rx))
+                }
+            };
+            senders.push(tx);
+            receivers.push(rx);
+        }
+        let mut outputs: Vec<Vec<mpsc::Sender<MarketDataMessage>>> =
+            vec![Vec::new(); self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            let inputs: HashSet<usize> = node.inputs.iter().map(|input| index[input]).collect();
+            for from in inputs {
+                outputs[from].push(senders[i].clone().unwrap());
+            }
+        }
+        drop(senders);
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let mut nodes: Vec<Option<Node>> = self.nodes.into_iter().map(Some).collect();
+        let mut tasks = Vec::with_capacity(order.len());
+        for i in order {
+            let node = nodes[i].take().unwrap();
+            let outputs = Outputs(std::mem::take(&mut outputs[i]));
+            let rx = receivers[i].take();
+            let task = match node.kind {
+                NodeKind::Source(run) => tokio::spawn(run(shutdown_rx.clone(), outputs)),
+                NodeKind::Transform(stage) => {
+                    tokio::spawn(run_transform(stage, rx.unwrap(), outputs))
+                }
+                NodeKind::Sink(sink) => match self.throttles.throttle(&node.name) {
+                    Some(throttle) => {
+                        tokio::spawn(ThrottledSink { sink, throttle }.run(rx.unwrap()))
+                    }
+                    None => tokio::spawn(run_sink(sink, rx.unwrap())),
+                },
+            };
+            tasks.push((node.name, task));
+        }
+        Ok(GraphHandle { shutdown_tx, tasks })
+    }
+}
+
+async fn run_transform(
+    mut stage: Box<dyn Stage>,
+    mut rx: mpsc::Receiver<MarketDataMessage>,
+    outputs: Outputs,
+) {
+    let mut out = Vec::new();
+    while let Some(msg) = rx.recv().await {
+        stage.process(msg, &mut out);
+        for msg in out.drain(..) {
+            if !outputs.emit(msg).await {
+                return;
+            }
+        }
+    }
+}
+
+async fn run_sink(mut sink: Box<dyn MessageSink>, mut rx: mpsc::Receiver<MarketDataMessage>) {
+    while let Some(msg) = rx.recv().await {
+        sink.consume(msg);
+    }
+    sink.close();
+}
+
+/// Running graph, with its node tasks in topological order
+pub struct GraphHandle {
+    shutdown_tx: watch::Sender<bool>,
+    tasks: Vec<(String, JoinHandle<()>)>,
+}
+
+impl GraphHandle {
+    /// Stop the sources and wait for every node to drain
+    pub async fn shutdown(self) {
+        let _ = self.shutdown_tx.send(true);
+        self.join().await;
+    }
+
+    /// Wait until every source is exhausted and every node has drained
+    pub async fn join(self) {
+        for (name, task) in self.tasks {
+            match task.await {
+                Ok(()) => debug!("Graph node {} finished", name),
+                Err(e) => error!("Graph node {} failed: {}", name, e),
+            }
+        }
+    }
+}
+
+fn invalid(what: String) -> ClientError {
+    ClientError::Control(format!("stream graph: {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Passthrough;
+
+    impl Stage for Passthrough {
+        fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+            out.push(msg);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fan_in_and_fan_out() {
+        let heartbeats = |n| IterSource(std::iter::repeat_n(MarketDataMessage::Heartbeat, n));
+        let counter = |count: &Arc<Mutex<usize>>| {
+            let count = Arc::clone(count);
+            move |_| *count.lock().unwrap() += 1
+        };
+        let (left, right) = (Arc::default(), Arc::default());
+
+        StreamGraph::new()
+            .with_sink("left", counter(&left), &["merge"])
+            .with_source("a", heartbeats(3))
+            .with_source("b", heartbeats(4))
+            .with_transform("merge", Passthrough, &["a", "b"])
+            .with_sink("right", counter(&right), &["merge", "a"])
+            .spawn()
+            .unwrap()
+            .join()
+            .await;
+
+        assert_eq!(*left.lock().unwrap(), 7);
+        // Both the merged stream and source `a` directly
+        assert_eq!(*right.lock().unwrap(), 10);
+    }
+
+    #[test]
+    fn test_invalid_graphs_rejected() {
+        let error = |graph: StreamGraph| match graph.validate() {
+            Err(ClientError::Control(e)) => e,
+            other => panic!("expected an error, got {:?}", other),
+        };
+        let source = || IterSource(std::iter::empty());
+
+        let graph = StreamGraph::new()
+            .with_source("feed", source())
+            .with_transform("a", Passthrough, &["feed", "b"])
+            .with_transform("b", Passthrough, &["a"])
+            .with_sink("out", |_| {}, &["b"]);
+        assert_eq!(error(graph), "stream graph: cycle through a, b");
+
+        let graph =
+            StreamGraph::new()
+                .with_source("feed", source())
+                .with_sink("out", |_| {}, &["fed"]);
+        assert_eq!(error(graph), "stream graph: out reads unknown node fed");
+
+        let graph = StreamGraph::new()
+            .with_source("feed", source())
+            .with_source("spare", source())
+            .with_sink("out", |_| {}, &["feed"]);
+        assert_eq!(error(graph), "stream graph: output of spare is unused");
+
+        let graph = StreamGraph::new()
+            .with_source("feed", source())
+            .with_sink("out", |_| {}, &["feed"])
+            .with_throttles(SinkThrottles::new().with_rate("feed", "quote", 10.0));
+        assert_eq!(error(graph), "stream graph: throttle for unknown sink feed");
+    }
+}
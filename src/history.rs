@@ -0,0 +1,314 @@
+//! Bounded in-memory history per symbol.
+//!
+//! A [`HistoryBuffer`] keeps the latest messages of each symbol, bounded by
+//! age, count or both, and answers range queries by time or by the sequence
+//! number assigned on [`push`](HistoryBuffer::push). It backs gap-fill
+//! replays in the [fan-out server](crate::server), recent history for late
+//! joiners through [`SyncHandle::with_history`](crate::SyncHandle::with_history),
+//! and quick looks at what just happened with [`last`](HistoryBuffer::last).
+//!
+//! Ages are measured against the newest timestamp pushed rather than the
+//! wall clock, so replayed data is bounded the same way as live data.
+//! Timestamps more than a few seconds ahead of the wall clock count as
+//! now, so one mis-stamped message cannot age out everything else.
+//! Heartbeats are numbered but not kept.
+
+use crate::client::{ClientError, Result};
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeBounds;
+
+/// Pushes between sweeps of symbols that have gone quiet
+const SWEEP_EVERY: u64 = 256;
+
+/// Messages kept per symbol unless configured otherwise
+const DEFAULT_MAX_MESSAGES: usize = 100_000;
+
+/// How far ahead of the wall clock a timestamp is believed
+const MAX_CLOCK_SKEW: Duration = Duration::seconds(5);
+
+#[derive(Debug, Clone)]
+struct Entry {
+    seq: u64,
+    at: DateTime<Utc>,
+    msg: MarketDataMessage,
+}
+
+/// Latest messages per symbol with time and sequence range queries
+#[derive(Debug, Clone)]
+pub struct HistoryBuffer {
+    max_age: Option<Duration>,
+    max_messages: Option<usize>,
+    next_seq: u64,
+    /// Highest sequence number evicted; everything after it is retained
+    evicted: u64,
+    /// Numbers given to messages lost before they were pushed
+    lost: Vec<(u64, u64)>,
+    newest: Option<DateTime<Utc>>,
+    symbols: HashMap<Symbol, VecDeque<Entry>>,
+}
+
+impl Default for HistoryBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HistoryBuffer {
+    /// Keep the last minute of every symbol, up to 100,000 messages each
+    pub fn new() -> Self {
+        Self {
+            max_age: Some(Duration::minutes(1)),
+            max_messages: Some(DEFAULT_MAX_MESSAGES),
+            next_seq: 1,
+            evicted: 0,
+            lost: Vec::new(),
+            newest: None,
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// Keep messages up to `max_age` older than the newest, or without an
+    /// age limit when `None`
+    pub fn with_max_age(mut self, max_age: Option<Duration>) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Keep at most `max_messages` per symbol
+    pub fn with_max_messages(mut self, max_messages: usize) -> Self {
+        self.max_messages = Some(max_messages.max(1));
+        self
+    }
+
+    /// Add a message, returning its sequence number
+    pub fn push(&mut self, msg: MarketDataMessage) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let Some(symbol) = msg.symbol() else {
+            return seq;
+        };
+        let now = Utc::now();
+        let at = msg
+            .timestamp()
+            .or(self.newest)
+            .unwrap_or(now)
+            .min(now + MAX_CLOCK_SKEW);
+        self.newest = Some(self.newest.map_or(at, |newest| newest.max(at)));
+
+        let entries = self.symbols.entry(symbol).or_default();
+        entries.push_back(Entry { seq, at, msg });
+        if let Some(max) = self.max_messages {
+            while entries.len() > max {
+                let evicted = entries.pop_front().map_or(0, |entry| entry.seq);
+                self.evicted = self.evicted.max(evicted);
+            }
+        }
+        if seq.is_multiple_of(SWEEP_EVERY) {
+            self.expire();
+        } else {
+            self.expire_symbol(&symbol);
+        }
+        seq
+    }
+
+    /// Number `count` messages that were lost, so the gap shows in the
+    /// sequence and replays across it fail
+    pub fn skip(&mut self, count: u64) {
+        if count == 0 {
+            return;
+        }
+        let evicted = self.evicted;
+        self.lost.retain(|(_, last)| *last > evicted);
+        self.lost.push((self.next_seq, self.next_seq + count - 1));
+        self.next_seq += count;
+    }
+
+    /// Messages of `symbol`, or of every symbol in sequence order when
+    /// `None`, timestamped within `range`
+    pub fn query(
+        &self,
+        symbol: Option<&str>,
+        range: impl RangeBounds<DateTime<Utc>>,
+    ) -> Vec<MarketDataMessage> {
+        self.collect(symbol, |entry| range.contains(&entry.at))
+            .into_iter()
+            .map(|(_, msg)| msg)
+            .collect()
+    }
+
+    /// Messages of every symbol from the last `window` before the newest
+    pub fn last(&self, window: Duration) -> Vec<MarketDataMessage> {
+        match self.newest {
+            Some(newest) => self.query(None, newest - window..),
+            None => Vec::new(),
+        }
+    }
+
+    /// Messages numbered `from..=to`, unless some at or after `from` were
+    /// already evicted or some in the range were lost
+    pub fn sequence_range(&self, from: u64, to: u64) -> Result<Vec<(u64, MarketDataMessage)>> {
+        // Quiet symbols are only swept now and then, so count what has
+        // expired since as evicted too
+        let expired = self
+            .symbols
+            .values()
+            .flatten()
+            .filter(|entry| self.expired(entry))
+            .map(|entry| entry.seq)
+            .max();
+        let evicted = self.evicted.max(expired.unwrap_or(0));
+        if from <= evicted {
+            return Err(ClientError::Control(format!(
+                "messages before {} are no longer retained",
+                evicted + 1
+            )));
+        }
+        if let Some((first, last)) = self
+            .lost
+            .iter()
+            .find(|(first, last)| *first <= to && from <= *last)
+        {
+            return Err(ClientError::Control(format!(
+                "messages {} to {} were lost",
+                first, last
+            )));
+        }
+        Ok(self.collect(None, |entry| (from..=to).contains(&entry.seq)))
+    }
+
+    /// Sequence number the next message will get
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Messages currently retained
+    pub fn len(&self) -> usize {
+        self.symbols.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn collect(
+        &self,
+        symbol: Option<&str>,
+        keep: impl Fn(&Entry) -> bool,
+    ) -> Vec<(u64, MarketDataMessage)> {
+        let mut out: Vec<_> = self
+            .symbols
+            .iter()
+            .filter(|(name, _)| symbol.is_none_or(|symbol| symbol == name.as_str()))
+            .flat_map(|(_, entries)| entries.iter())
+            .filter(|entry| !self.expired(entry) && keep(entry))
+            .map(|entry| (entry.seq, entry.msg.clone()))
+            .collect();
+        out.sort_unstable_by_key(|(seq, _)| *seq);
+        out
+    }
+
+    fn expired(&self, entry: &Entry) -> bool {
+        match (self.max_age, self.newest) {
+            (Some(max_age), Some(newest)) => entry.at < newest - max_age,
+            _ => false,
+        }
+    }
+
+    /// Drop expired messages of every symbol, and symbols left empty
+    fn expire(&mut self) {
+        let symbols: Vec<_> = self.symbols.keys().cloned().collect();
+        for symbol in symbols {
+            self.expire_symbol(&symbol);
+        }
+        self.symbols.retain(|_, entries| !entries.is_empty());
+    }
+
+    fn expire_symbol(&mut self, symbol: &str) {
+        let (Some(max_age), Some(newest)) = (self.max_age, self.newest) else {
+            return;
+        };
+        let Some(entries) = self.symbols.get_mut(symbol) else {
+            return;
+        };
+        while entries
+            .front()
+            .is_some_and(|entry| entry.at < newest - max_age)
+        {
+            let evicted = entries.pop_front().map_or(0, |entry| entry.seq);
+            self.evicted = self.evicted.max(evicted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Trade;
+    use chrono::TimeZone;
+
+    fn trade(symbol: &str, second: i64) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 2, 3, 0, 0).unwrap()
+                + Duration::seconds(second),
+            trade_id: second.to_string(),
+            ..Trade::test(symbol, 100.0)
+        })
+    }
+
+    fn ids(msgs: &[MarketDataMessage]) -> Vec<&str> {
+        msgs.iter()
+            .map(|msg| match msg {
+                MarketDataMessage::Trade(trade) => trade.trade_id.as_str(),
+                _ => "",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bounds_by_age_and_count() {
+        let mut history = HistoryBuffer::new()
+            .with_max_age(Some(Duration::seconds(60)))
+            .with_max_messages(3);
+        for second in [0, 10, 20, 30, 40] {
+            history.push(trade("BTCUSD", second));
+        }
+        history.push(trade("ETHUSD", 50));
+        history.push(MarketDataMessage::Heartbeat);
+        history.push(trade("ETHUSD", 90));
+
+        // BTCUSD lost 0 and 10 to the count limit, and 20 aged out against
+        // the newest message at 90
+        assert_eq!(ids(&history.query(Some("BTCUSD"), ..)), ["30", "40"]);
+        assert_eq!(ids(&history.last(Duration::seconds(45))), ["50", "90"]);
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 3, 0, 35).unwrap();
+        assert_eq!(ids(&history.query(None, start..)), ["40", "50", "90"]);
+
+        assert!(history.sequence_range(3, 8).is_err());
+        let replayed = history.sequence_range(4, 8).unwrap();
+        let seqs: Vec<_> = replayed.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(seqs, [4, 5, 6, 8]);
+
+        history.skip(3);
+        assert_eq!(history.push(trade("ETHUSD", 91)), 12);
+        assert!(history.sequence_range(8, 12).is_err());
+        assert_eq!(history.sequence_range(12, 12).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_future_timestamps_do_not_expire_history() {
+        let stamped = |at: DateTime<Utc>| {
+            let mut msg = trade("BTCUSD", 0);
+            if let MarketDataMessage::Trade(trade) = &mut msg {
+                trade.timestamp = at;
+            }
+            msg
+        };
+        let mut history = HistoryBuffer::new();
+        history.push(stamped(Utc::now()));
+        history.push(stamped(Utc::now() + Duration::days(365)));
+        assert_eq!(history.len(), 2);
+    }
+}
@@ -0,0 +1,209 @@
+//! Iceberg (hidden liquidity) inference.
+//!
+//! An iceberg order shows only part of its size; each time the visible part
+//! trades away the venue replenishes it at the same price. [`IcebergDetector`]
+//! watches trades against the last book seen and flags a level once its
+//! visible size has been refilled after executions a number of times,
+//! estimating the hidden size from how much traded there beyond what was
+//! ever displayed.
+
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, OrderBookSnapshot, PriceLevel, Trade, TradeSide};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A price level that keeps refilling after trading
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IcebergSuspected {
+    pub symbol: Symbol,
+    /// Side of the hidden order: `Buy` for a bid
+    pub side: TradeSide,
+    pub price: f64,
+    /// Largest size shown at the level
+    pub display_size: f64,
+    /// Times the level was refilled after executions
+    pub refills: u32,
+    /// Volume traded at the level while it was tracked
+    pub executed: f64,
+    /// Volume traded beyond the largest displayed size
+    pub estimated_hidden: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Executions against one price level since the book last showed it
+#[derive(Debug, Clone)]
+struct Level {
+    visible: f64,
+    display_size: f64,
+    /// Traded since the last book update
+    pending: f64,
+    executed: f64,
+    refills: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SymbolState {
+    book: Option<OrderBookSnapshot>,
+    levels: HashMap<(TradeSide, u64), Level>,
+}
+
+/// Flags probable iceberg orders from trades and book updates
+#[derive(Debug, Clone)]
+pub struct IcebergDetector {
+    min_refills: u32,
+    symbols: HashMap<Symbol, SymbolState>,
+}
+
+impl IcebergDetector {
+    /// Report levels refilled at least `min_refills` times
+    pub fn new(min_refills: u32) -> Self {
+        Self {
+            min_refills: min_refills.max(1),
+            symbols: HashMap::new(),
+        }
+    }
+
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<IcebergSuspected> {
+        match msg {
+            MarketDataMessage::Trade(trade) => {
+                self.on_trade(trade);
+                Vec::new()
+            }
+            MarketDataMessage::OrderBook(book) => self.on_book(book),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Attribute a trade to the resting level it executed against
+    pub fn on_trade(&mut self, trade: &Trade) {
+        let Some(state) = self.symbols.get_mut(&trade.symbol) else {
+            return;
+        };
+        // A buyer lifts the offer, so the resting order is on the other side
+        let side = trade.side.opposite();
+        let Some(shown) = state
+            .book
+            .as_ref()
+            .and_then(|book| size_at(book, side, trade.price))
+        else {
+            return;
+        };
+        let level = state
+            .levels
+            .entry((side, trade.price.to_bits()))
+            .or_insert(Level {
+                visible: shown,
+                display_size: shown,
+                pending: 0.0,
+                executed: 0.0,
+                refills: 0,
+            });
+        level.pending += trade.quantity;
+        level.executed += trade.quantity;
+    }
+
+    /// Compare the new book with the executions since the last one
+    pub fn on_book(&mut self, book: &OrderBookSnapshot) -> Vec<IcebergSuspected> {
+        let state = self.symbols.entry(book.symbol).or_default();
+        let mut events = Vec::new();
+        state.levels.retain(|&(side, bits), level| {
+            let price = f64::from_bits(bits);
+            // Traded through or pulled: not an iceberg at this price
+            let Some(size) = size_at(book, side, price) else {
+                return false;
+            };
+            if level.pending > 0.0 {
+                if size > level.visible - level.pending {
+                    level.refills += 1;
+                    if level.refills >= self.min_refills {
+                        events.push(IcebergSuspected {
+                            symbol: book.symbol,
+                            side,
+                            price,
+                            display_size: level.display_size,
+                            refills: level.refills,
+                            executed: level.executed,
+                            estimated_hidden: (level.executed - level.display_size).max(0.0),
+                            timestamp: book.timestamp,
+                        });
+                    }
+                }
+                level.pending = 0.0;
+            }
+            level.visible = size;
+            level.display_size = level.display_size.max(size);
+            true
+        });
+        state.book = Some(book.clone());
+        events
+    }
+}
+
+fn size_at(book: &OrderBookSnapshot, side: TradeSide, price: f64) -> Option<f64> {
+    let levels: &[PriceLevel] = match side {
+        TradeSide::Buy => &book.bids,
+        TradeSide::Sell => &book.asks,
+        TradeSide::Unknown => return None,
+    };
+    levels
+        .iter()
+        .find(|level| level.price == price && level.size > 0.0)
+        .map(|level| level.size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(ask_size: f64) -> OrderBookSnapshot {
+        let level = |price, size| PriceLevel {
+            price,
+            size,
+            num_orders: 1,
+        };
+        OrderBookSnapshot {
+            symbol: "BTCUSD".into(),
+            bids: vec![level(99.0, 4.0)],
+            asks: vec![level(100.0, ask_size), level(101.0, 3.0)],
+            timestamp: Utc::now(),
+            instrument_id: None,
+            contract: None,
+            received: None,
+        }
+    }
+
+    fn buy(quantity: f64) -> Trade {
+        Trade {
+            quantity,
+            ..Trade::test("BTCUSD", 100.0)
+        }
+    }
+
+    #[test]
+    fn test_refilled_offer_flagged() {
+        let mut detector = IcebergDetector::new(2);
+        assert!(detector.on_book(&book(1.0)).is_empty());
+
+        // The 1.0 shown at 100 trades away and comes back, twice
+        detector.on_trade(&buy(1.0));
+        assert!(detector.on_book(&book(1.0)).is_empty());
+        detector.on_trade(&buy(0.6));
+        detector.on_trade(&buy(0.4));
+        let events = detector.on_book(&book(1.0));
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(
+            (event.side, event.price, event.refills),
+            (TradeSide::Sell, 100.0, 2)
+        );
+        assert_eq!((event.executed, event.estimated_hidden), (2.0, 1.0));
+
+        // A partial fill that is not replenished is not a refill
+        detector.on_trade(&buy(0.5));
+        assert!(detector.on_book(&book(0.5)).is_empty());
+        // Once the level is gone tracking stops
+        assert!(detector.on_book(&book(0.0)).is_empty());
+        assert!(detector.symbols["BTCUSD"].levels.is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+//! ONNX model inference on feature vectors.
+//!
+//! An [`InferenceStage`] runs a [`FeatureExtractor`] and feeds each
+//! [`FeatureVector`] it emits to an [`OnnxModel`], publishing the model's
+//! outputs as [`Prediction`]s. As a pipeline stage it runs on the
+//! processing task next to the data, so signals do not wait on a hop to a
+//! separate model server. Models take one `float32` input of shape
+//! `[1, columns]`, in [`FeatureExtractor::columns`] order, and their first
+//! output is flattened into the prediction. Models are run with tract, in
+//! pure Rust.
+//!
+//! Only built with the `onnx` feature.
+
+use crate::client::{ClientError, Result};
+use crate::features::{FeatureExtractor, FeatureVector};
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::sync::broadcast;
+use tracing::warn;
+use tract_onnx::prelude::*;
+
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A model's output for one feature vector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Prediction {
+    pub symbol: Symbol,
+    /// Sample time of the feature vector
+    pub timestamp: DateTime<Utc>,
+    pub model: String,
+    pub outputs: Vec<f32>,
+}
+
+fn onnx_error(e: TractError) -> ClientError {
+    ClientError::Parse(format!("onnx: {:#}", e))
+}
+
+/// An optimized ONNX model taking one feature vector
+pub struct OnnxModel {
+    name: String,
+    inputs: usize,
+    plan: TypedRunnableModel<TypedModel>,
+}
+
+impl OnnxModel {
+    /// Load the model at `path`, taking vectors of `inputs` features
+    pub fn load(path: impl AsRef<Path>, inputs: usize) -> Result<Self> {
+        let path = path.as_ref();
+        let model = tract_onnx::onnx()
+            .model_for_path(path)
+            .map_err(onnx_error)?;
+        let name = path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        Self::optimize(name, model, inputs)
+    }
+
+    /// A model from the bytes of an `.onnx` file
+    pub fn from_bytes(name: &str, bytes: &[u8], inputs: usize) -> Result<Self> {
+        let model = tract_onnx::onnx()
+            .model_for_read(&mut &*bytes)
+            .map_err(onnx_error)?;
+        Self::optimize(name.to_string(), model, inputs)
+    }
+
+    fn optimize(name: String, model: InferenceModel, inputs: usize) -> Result<Self> {
+        let plan = model
+            .with_input_fact(0, f32::fact([1, inputs]).into())
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .map_err(onnx_error)?;
+        Ok(Self { name, inputs, plan })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of features the model takes
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// The model's first output for `features`
+    pub fn predict(&self, features: &[f64]) -> Result<Vec<f32>> {
+        if features.len() != self.inputs {
+            return Err(ClientError::Parse(format!(
+                "model {} takes {} features, got {}",
+                self.name,
+                self.inputs,
+                features.len()
+            )));
+        }
+        let values: Vec<f32> = features.iter().map(|value| *value as f32).collect();
+        let input = Tensor::from_shape(&[1, self.inputs], &values).map_err(onnx_error)?;
+        let outputs = self.plan.run(tvec!(input.into())).map_err(onnx_error)?;
+        let output = outputs
+            .first()
+            .ok_or_else(|| ClientError::Parse(format!("model {} has no output", self.name)))?;
+        let view = output.to_array_view::<f32>().map_err(onnx_error)?;
+        Ok(view.iter().copied().collect())
+    }
+}
+
+/// Extracts features from the stream and runs a model on each vector.
+///
+/// Messages pass through unchanged; predictions go to receivers from
+/// [`subscribe`](Self::subscribe). Vectors with `NaN` features, such as
+/// returns over horizons not yet seen, are skipped unless a fill value is
+/// set.
+pub struct InferenceStage {
+    extractor: FeatureExtractor,
+    model: OnnxModel,
+    fill: Option<f64>,
+    skipped: u64,
+    tx: broadcast::Sender<Prediction>,
+}
+
+impl InferenceStage {
+    pub fn new(extractor: FeatureExtractor, model: OnnxModel) -> Result<Self> {
+        let columns = extractor.columns().len();
+        if columns != model.inputs() {
+            return Err(ClientError::Parse(format!(
+                "model {} takes {} features, extractor has {}",
+                model.name(),
+                model.inputs(),
+                columns
+            )));
+        }
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Ok(Self {
+            extractor,
+            model,
+            fill: None,
+            skipped: 0,
+            tx,
+        })
+    }
+
+    /// Replace `NaN` features with `value` instead of skipping the vector
+    pub fn with_nan_fill(mut self, value: f64) -> Self {
+        self.fill = Some(value);
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Prediction> {
+        self.tx.subscribe()
+    }
+
+    /// Vectors not run for `NaN` features
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Feed a message, returning the predictions of any sample due before it
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<Prediction> {
+        self.extractor
+            .on_message(msg)
+            .into_iter()
+            .filter_map(|vector| self.predict(vector))
+            .collect()
+    }
+
+    fn predict(&mut self, mut vector: FeatureVector) -> Option<Prediction> {
+        for value in vector.values.iter_mut().filter(|value| value.is_nan()) {
+            match self.fill {
+                Some(fill) => *value = fill,
+                None => {
+                    self.skipped += 1;
+                    return None;
+                }
+            }
+        }
+        let outputs = match self.model.predict(&vector.values) {
+            Ok(outputs) => outputs,
+            Err(e) => {
+                warn!("Inference on {} failed: {}", vector.symbol, e);
+                return None;
+            }
+        };
+        let prediction = Prediction {
+            symbol: vector.symbol,
+            timestamp: vector.timestamp,
+            model: self.model.name().to_string(),
+            outputs,
+        };
+        let _ = self.tx.send(prediction.clone());
+        Some(prediction)
+    }
+}
+
+impl Stage for InferenceStage {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Quote;
+    use chrono::{Duration, TimeZone};
+    use tract_onnx::pb;
+
+    /// A model summing its inputs with `weights`
+    fn linear(weights: &[f32]) -> OnnxModel {
+        let float = pb::tensor_proto::DataType::Float as i32;
+        let value = |name: &str| pb::ValueInfoProto {
+            name: name.to_string(),
+            r#type: Some(pb::TypeProto {
+                value: Some(pb::type_proto::Value::TensorType(pb::type_proto::Tensor {
+                    elem_type: float,
+                    shape: None,
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let proto = pb::ModelProto {
+            ir_version: 8,
+            opset_import: vec![pb::OperatorSetIdProto {
+                domain: String::new(),
+                version: 13,
+            }],
+            graph: Some(pb::GraphProto {
+                node: vec![pb::NodeProto {
+                    input: vec!["features".to_string(), "weights".to_string()],
+                    output: vec!["score".to_string()],
+                    op_type: "MatMul".to_string(),
+                    ..Default::default()
+                }],
+                initializer: vec![pb::TensorProto {
+                    name: "weights".to_string(),
+                    dims: vec![weights.len() as i64, 1],
+                    data_type: float,
+                    float_data: weights.to_vec(),
+                    ..Default::default()
+                }],
+                input: vec![value("features")],
+                output: vec![value("score")],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let model = tract_onnx::onnx().model_for_proto_model(&proto).unwrap();
+        OnnxModel::optimize("linear".to_string(), model, weights.len()).unwrap()
+    }
+
+    #[test]
+    fn test_predictions_from_features() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let extractor = FeatureExtractor::new(Duration::seconds(1)).with_horizons(&[]);
+        // spread_bps, imbalance, volatility, trade_intensity
+        let model = linear(&[1.0, 10.0, 0.0, 0.0]);
+        assert!(
+            InferenceStage::new(FeatureExtractor::new(Duration::seconds(1)), linear(&[1.0]))
+                .is_err()
+        );
+        let mut stage = InferenceStage::new(extractor, model).unwrap();
+        let mut rx = stage.subscribe();
+
+        let quote = |millis: i64| {
+            MarketDataMessage::Quote(Quote {
+                bid_size: 3.0,
+                timestamp: t0 + Duration::milliseconds(millis),
+                ..Quote::test("BTCUSD", 99.0, 101.0)
+            })
+        };
+        let mut out = Vec::new();
+        for millis in [100, 1_100, 2_100] {
+            stage.process(quote(millis), &mut out);
+        }
+        assert_eq!(out.len(), 3);
+        // Volatility needs two returns, so three samples
+        assert_eq!(stage.skipped(), 2);
+
+        let predictions = stage.on_message(&quote(3_100));
+        assert_eq!(predictions.len(), 1);
+        assert!((predictions[0].outputs[0] - 205.0).abs() < 1e-3);
+        assert_eq!(rx.try_recv().unwrap().model, "linear");
+    }
+}
@@ -0,0 +1,439 @@
+//! Cross-venue instrument identifiers.
+//!
+//! An [`InstrumentRegistry`] maps venue listings (`venue`, `symbol`) and
+//! external identifiers (ISIN, FIGI, CUSIP) to one canonical instrument id,
+//! so data for the same instrument can be joined across sources. Mappings
+//! load from CSV, or from the OpenFIGI API with the `openfigi` feature;
+//! [`InstrumentTagger`] stamps the id onto normalized messages. Futures and
+//! perpetual swaps also carry their [`ContractSpec`], which the tagger
+//! copies onto messages so notionals account for the contract multiplier.
+
+use crate::client::{ClientError, Result};
+use crate::pipeline::Stage;
+use crate::types::{ContractSpec, MarketDataMessage};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+/// External identifier schemes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum IdScheme {
+    Isin,
+    Figi,
+    Cusip,
+}
+
+/// Canonical instrument and its external identifiers
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Instrument {
+    pub id: String,
+    pub isin: Option<String>,
+    pub figi: Option<String>,
+    pub cusip: Option<String>,
+    /// Contract terms, for derivatives
+    pub contract: Option<ContractSpec>,
+}
+
+impl Instrument {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_isin(mut self, isin: &str) -> Self {
+        self.isin = Some(isin.to_string());
+        self
+    }
+
+    pub fn with_figi(mut self, figi: &str) -> Self {
+        self.figi = Some(figi.to_string());
+        self
+    }
+
+    pub fn with_cusip(mut self, cusip: &str) -> Self {
+        self.cusip = Some(cusip.to_string());
+        self
+    }
+
+    pub fn with_contract(mut self, contract: ContractSpec) -> Self {
+        self.contract = Some(contract);
+        self
+    }
+
+    fn identifiers(&self) -> impl Iterator<Item = (IdScheme, &str)> {
+        [
+            (IdScheme::Isin, &self.isin),
+            (IdScheme::Figi, &self.figi),
+            (IdScheme::Cusip, &self.cusip),
+        ]
+        .into_iter()
+        .filter_map(|(scheme, value)| Some((scheme, value.as_deref()?)))
+    }
+}
+
+/// One row of a mapping file
+#[derive(Debug, Deserialize)]
+struct CsvRow {
+    instrument_id: String,
+    venue: String,
+    symbol: String,
+    #[serde(default)]
+    isin: Option<String>,
+    #[serde(default)]
+    figi: Option<String>,
+    #[serde(default)]
+    cusip: Option<String>,
+    #[serde(default)]
+    multiplier: Option<f64>,
+    #[serde(default)]
+    expiry: Option<String>,
+    #[serde(default)]
+    settlement_currency: Option<String>,
+    #[serde(default)]
+    perpetual: Option<bool>,
+}
+
+impl CsvRow {
+    /// Contract terms, if any contract column is filled in
+    fn contract(&self) -> Result<Option<ContractSpec>> {
+        let expiry = match self.expiry.as_deref().filter(|e| !e.is_empty()) {
+            Some(expiry) => Some(parse_expiry(expiry)?),
+            None => None,
+        };
+        let currency = self
+            .settlement_currency
+            .as_deref()
+            .filter(|c| !c.is_empty());
+        let perpetual = self.perpetual.unwrap_or(false);
+        if self.multiplier.is_none() && expiry.is_none() && currency.is_none() && !perpetual {
+            return Ok(None);
+        }
+        Ok(Some(ContractSpec {
+            multiplier: self.multiplier.unwrap_or(1.0),
+            expiry,
+            settlement_currency: currency.map(Into::into),
+            perpetual,
+        }))
+    }
+}
+
+/// An RFC 3339 time or a date, expiring at midnight UTC
+fn parse_expiry(value: &str) -> Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|at| at.with_timezone(&Utc))
+        .or_else(|_| {
+            chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc())
+        })
+        .map_err(|e| ClientError::Parse(format!("expiry {:?}: {}", value, e)))
+}
+
+/// Maps venue symbols and external identifiers to canonical instruments
+#[derive(Debug, Default)]
+pub struct InstrumentRegistry {
+    instruments: HashMap<String, Instrument>,
+    listings: HashMap<(String, String), String>,
+    identifiers: HashMap<(IdScheme, String), String>,
+}
+
+impl InstrumentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an instrument, merging identifiers into an existing entry
+    /// with the same id
+    pub fn add(&mut self, instrument: Instrument) {
+        for (scheme, value) in instrument.identifiers() {
+            self.identifiers
+                .insert((scheme, value.to_string()), instrument.id.clone());
+        }
+        match self.instruments.get_mut(&instrument.id) {
+            Some(existing) => {
+                existing.isin = instrument.isin.or(existing.isin.take());
+                existing.figi = instrument.figi.or(existing.figi.take());
+                existing.cusip = instrument.cusip.or(existing.cusip.take());
+                existing.contract = instrument.contract.or(existing.contract.take());
+            }
+            None => {
+                self.instruments.insert(instrument.id.clone(), instrument);
+            }
+        }
+    }
+
+    /// Map `symbol` on `venue` to instrument `id`
+    pub fn add_listing(&mut self, venue: &str, symbol: &str, id: &str) {
+        self.listings
+            .insert((venue.to_string(), symbol.to_string()), id.to_string());
+    }
+
+    /// Load rows of `instrument_id,venue,symbol,isin,figi,cusip` with a
+    /// header line; identifier columns may be empty or omitted. Derivatives
+    /// add `multiplier`, `expiry`, `settlement_currency` and `perpetual`.
+    pub fn read_csv(&mut self, reader: impl Read) -> Result<usize> {
+        let mut rows = 0;
+        for row in csv::Reader::from_reader(reader).deserialize() {
+            let row: CsvRow = row.map_err(|e| ClientError::Parse(e.to_string()))?;
+            let non_empty = |value: Option<String>| value.filter(|v| !v.is_empty());
+            let contract = row.contract()?;
+            self.add(Instrument {
+                id: row.instrument_id.clone(),
+                isin: non_empty(row.isin),
+                figi: non_empty(row.figi),
+                cusip: non_empty(row.cusip),
+                contract,
+            });
+            self.add_listing(&row.venue, &row.symbol, &row.instrument_id);
+            rows += 1;
+        }
+        Ok(rows)
+    }
+
+    /// [`read_csv`](Self::read_csv) from a file
+    pub fn load_csv(&mut self, path: impl AsRef<Path>) -> Result<usize> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|e| ClientError::Io(format!("{}: {}", path.display(), e)))?;
+        self.read_csv(file)
+    }
+
+    /// Canonical id of `symbol` as listed on `venue`
+    pub fn resolve(&self, venue: &str, symbol: &str) -> Option<&str> {
+        self.listings
+            .get(&(venue.to_string(), symbol.to_string()))
+            .map(String::as_str)
+    }
+
+    /// Instrument carrying the external identifier `value`
+    pub fn lookup(&self, scheme: IdScheme, value: &str) -> Option<&Instrument> {
+        self.identifiers
+            .get(&(scheme, value.to_string()))
+            .and_then(|id| self.instruments.get(id))
+    }
+
+    pub fn instrument(&self, id: &str) -> Option<&Instrument> {
+        self.instruments.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.instruments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instruments.is_empty()
+    }
+
+    /// Contract terms of `symbol` as listed on `venue`
+    pub fn contract(&self, venue: &str, symbol: &str) -> Option<ContractSpec> {
+        self.instrument(self.resolve(venue, symbol)?)?.contract
+    }
+
+    /// Set the canonical id and contract terms on a message from `venue`;
+    /// returns whether the symbol was known
+    pub fn enrich(&self, venue: &str, msg: &mut MarketDataMessage) -> bool {
+        let (symbol, instrument_id, contract) = match msg {
+            MarketDataMessage::Trade(t) => (&t.symbol, &mut t.instrument_id, &mut t.contract),
+            MarketDataMessage::Quote(q) => (&q.symbol, &mut q.instrument_id, &mut q.contract),
+            MarketDataMessage::OrderBook(b) => (&b.symbol, &mut b.instrument_id, &mut b.contract),
+            MarketDataMessage::TradeCorrection(c) => {
+                (&c.symbol, &mut c.instrument_id, &mut c.contract)
+            }
+            MarketDataMessage::TradeBust(b) => (&b.symbol, &mut b.instrument_id, &mut b.contract),
+            MarketDataMessage::Heartbeat => return false,
+        };
+        match self.resolve(venue, symbol) {
+            Some(id) => {
+                *instrument_id = Some(id.into());
+                *contract = self.instrument(id).and_then(|i| i.contract);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Map `tickers` listed on the OpenFIGI exchange code `exch_code` (e.g.
+    /// `US`) with the OpenFIGI API, registering each as a listing on
+    /// `venue` keyed by its composite FIGI; returns how many were mapped
+    #[cfg(feature = "openfigi")]
+    pub async fn load_openfigi(
+        &mut self,
+        api_key: Option<&str>,
+        venue: &str,
+        exch_code: &str,
+        tickers: &[&str],
+    ) -> Result<usize> {
+        const URL: &str = "https://api.openfigi.com/v3/mapping";
+
+        #[derive(Deserialize)]
+        struct Job {
+            #[serde(default)]
+            data: Vec<Figi>,
+        }
+        #[derive(Deserialize)]
+        struct Figi {
+            figi: String,
+            #[serde(rename = "compositeFIGI")]
+            composite_figi: Option<String>,
+        }
+
+        let jobs: Vec<_> = tickers
+            .iter()
+            .map(|ticker| {
+                serde_json::json!({ "idType": "TICKER", "idValue": ticker, "exchCode": exch_code })
+            })
+            .collect();
+        let mut request = reqwest::Client::new()
+            .post(URL)
+            .header("Content-Type", "application/json")
+            .body(serde_json::Value::Array(jobs).to_string());
+        if let Some(key) = api_key {
+            request = request.header("X-OPENFIGI-APIKEY", key);
+        }
+        let body = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ClientError::Connection(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| ClientError::Connection(e.to_string()))?;
+        let results: Vec<Job> =
+            serde_json::from_str(&body).map_err(|e| ClientError::Parse(e.to_string()))?;
+
+        let mut mapped = 0;
+        for (ticker, job) in tickers.iter().zip(results) {
+            let Some(figi) = job.data.into_iter().next() else {
+                continue;
+            };
+            let id = figi.composite_figi.unwrap_or_else(|| figi.figi.clone());
+            self.add(Instrument::new(&id).with_figi(&figi.figi));
+            self.add_listing(venue, ticker, &id);
+            mapped += 1;
+        }
+        Ok(mapped)
+    }
+}
+
+/// Pipeline stage that sets `instrument_id` on messages from one venue,
+/// leaving ids already set upstream untouched
+pub struct InstrumentTagger {
+    registry: Arc<InstrumentRegistry>,
+    venue: String,
+}
+
+impl InstrumentTagger {
+    pub fn new(registry: Arc<InstrumentRegistry>, venue: &str) -> Self {
+        Self {
+            registry,
+            venue: venue.to_string(),
+        }
+    }
+}
+
+impl Stage for InstrumentTagger {
+    fn process(&mut self, mut msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        let tagged = match &msg {
+            MarketDataMessage::Trade(trade) => trade.instrument_id.is_some(),
+            MarketDataMessage::Quote(quote) => quote.instrument_id.is_some(),
+            MarketDataMessage::OrderBook(book) => book.instrument_id.is_some(),
+            MarketDataMessage::TradeCorrection(correction) => correction.instrument_id.is_some(),
+            MarketDataMessage::TradeBust(bust) => bust.instrument_id.is_some(),
+            MarketDataMessage::Heartbeat => true,
+        };
+        if !tagged {
+            self.registry.enrich(&self.venue, &mut msg);
+        }
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Trade;
+
+    const CSV: &str = "instrument_id,venue,symbol,isin,figi,cusip\n\
+        AAPL.US,alpaca,AAPL,US0378331005,BBG000B9XRY4,037833100\n\
+        AAPL.US,iex,aapl,,,\n";
+
+    fn trade(symbol: &str) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            quantity: 10.0,
+            trade_id: "1".to_string(),
+            ..Trade::test(symbol, 189.5)
+        })
+    }
+
+    #[test]
+    fn test_csv_maps_listings_and_identifiers() {
+        let mut registry = InstrumentRegistry::new();
+        assert_eq!(registry.read_csv(CSV.as_bytes()).unwrap(), 2);
+
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.resolve("iex", "aapl"), Some("AAPL.US"));
+        assert_eq!(registry.resolve("iex", "AAPL"), None);
+        let aapl = registry.lookup(IdScheme::Cusip, "037833100").unwrap();
+        assert_eq!(aapl.isin.as_deref(), Some("US0378331005"));
+        assert_eq!(
+            registry
+                .lookup(IdScheme::Figi, "BBG000B9XRY4")
+                .map(|i| i.id.as_str()),
+            Some("AAPL.US")
+        );
+    }
+
+    #[test]
+    fn test_csv_contract_columns() {
+        let csv = "instrument_id,venue,symbol,multiplier,expiry,settlement_currency,perpetual\n\
+            ES.H24,cme,ESH4,50,2024-03-15,USD,\n\
+            BTC.PERP,okx,BTC-USD-SWAP,0.01,,USD,true\n\
+            AAPL.US,iex,aapl,,,,\n";
+        let mut registry = InstrumentRegistry::new();
+        registry.read_csv(csv.as_bytes()).unwrap();
+
+        let es = registry.contract("cme", "ESH4").unwrap();
+        assert_eq!(es.multiplier, 50.0);
+        assert_eq!(es.notional(5000.0, 2.0), 500_000.0);
+        assert_eq!(es.expiry.unwrap().to_rfc3339(), "2024-03-15T00:00:00+00:00");
+        let perp = registry.contract("okx", "BTC-USD-SWAP").unwrap();
+        assert!(perp.perpetual && perp.expiry.is_none());
+        assert_eq!(perp.settlement_currency.as_deref(), Some("USD"));
+        assert_eq!(registry.contract("iex", "aapl"), None);
+
+        let mut tagger = InstrumentTagger::new(Arc::new(registry), "cme");
+        let mut out = Vec::new();
+        tagger.process(trade("ESH4"), &mut out);
+        match &out[0] {
+            MarketDataMessage::Trade(trade) => assert_eq!(trade.notional(), 189.5 * 10.0 * 50.0),
+            _ => panic!("expected trade"),
+        }
+    }
+
+    #[test]
+    fn test_tagger_joins_venues_on_instrument_id() {
+        let mut registry = InstrumentRegistry::new();
+        registry.read_csv(CSV.as_bytes()).unwrap();
+        let registry = Arc::new(registry);
+        let mut alpaca = InstrumentTagger::new(registry.clone(), "alpaca");
+        let mut iex = InstrumentTagger::new(registry, "iex");
+
+        let mut out = Vec::new();
+        alpaca.process(trade("AAPL"), &mut out);
+        iex.process(trade("aapl"), &mut out);
+        iex.process(trade("MSFT"), &mut out);
+
+        let ids: Vec<_> = out
+            .iter()
+            .map(|msg| match msg {
+                MarketDataMessage::Trade(trade) => trade.instrument_id.as_deref(),
+                _ => panic!("expected trade"),
+            })
+            .collect();
+        assert_eq!(ids, [Some("AAPL.US"), Some("AAPL.US"), None]);
+    }
+}
@@ -0,0 +1,434 @@
+//! NASDAQ TotalView-ITCH 5.0 file parsing.
+//!
+//! ITCH files are a sequence of `[length: u16][message]` frames with big
+//! endian fields. An [`ItchReader`] maintains an [`L3Book`] per symbol from
+//! the order messages and yields trades from executions and non-displayed
+//! matches, busts from broken trades, and optionally a book snapshot after
+//! every book change, so a day of ITCH replays through
+//! [`Backtest`](crate::backtest::Backtest) like any other recording.
+//!
+//! Message timestamps are nanoseconds since midnight Eastern time of the
+//! trading day, so readers are given that midnight as a UTC instant.
+
+use crate::book::{BookSide, L3Book, L3Order};
+use crate::client::{ClientError, Result};
+use crate::recording::io_error;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Trade, TradeBust, TradeConditions, TradeSide};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const PRICE_SCALE: f64 = 1e4;
+
+fn invalid(reason: impl Into<String>) -> ClientError {
+    ClientError::Parse(format!("invalid itch: {}", reason.into()))
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes(buf[at..at + 2].try_into().unwrap())
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    u64::from_be_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+fn price_at(buf: &[u8], at: usize) -> f64 {
+    u32_at(buf, at) as f64 / PRICE_SCALE
+}
+
+/// Space-padded stock symbol
+fn stock_at(buf: &[u8], at: usize) -> String {
+    String::from_utf8_lossy(&buf[at..at + 8])
+        .trim_end()
+        .to_string()
+}
+
+/// Length of each message type handled, header included
+fn message_len(kind: u8) -> Option<usize> {
+    Some(match kind {
+        b'R' => 39,
+        b'A' => 36,
+        b'F' => 40,
+        b'E' => 31,
+        b'C' => 36,
+        b'X' => 23,
+        b'D' => 19,
+        b'U' => 35,
+        b'P' => 44,
+        b'Q' => 40,
+        b'B' => 19,
+        _ => return None,
+    })
+}
+
+/// Side taking liquidity when a resting order on `side` is executed
+fn aggressor(side: BookSide) -> TradeSide {
+    match side {
+        BookSide::Bid => TradeSide::Sell,
+        BookSide::Ask => TradeSide::Buy,
+    }
+}
+
+/// Replays an ITCH 5.0 file as trades and, optionally, book snapshots
+pub struct ItchReader<R> {
+    reader: R,
+    midnight: DateTime<Utc>,
+    filter: Option<HashSet<String>>,
+    snapshot_depth: Option<usize>,
+    /// Symbol per stock locate code
+    symbols: HashMap<u16, String>,
+    books: HashMap<u16, L3Book>,
+    message: Vec<u8>,
+    pending: VecDeque<MarketDataMessage>,
+}
+
+impl ItchReader<Box<dyn Read + Send>> {
+    /// Open an ITCH file, plain or gzip-compressed as distributed by NASDAQ
+    pub fn open(path: impl AsRef<Path>, midnight: DateTime<Utc>) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path).map_err(io_error)?);
+        let compressed = file.fill_buf().map_err(io_error)?.starts_with(&GZIP_MAGIC);
+        let reader: Box<dyn Read + Send> = if compressed {
+            Box::new(BufReader::new(flate2::bufread::GzDecoder::new(file)))
+        } else {
+            Box::new(file)
+        };
+        Ok(Self::new(reader, midnight))
+    }
+}
+
+impl<R: Read> ItchReader<R> {
+    /// Reader over uncompressed ITCH frames for the trading day starting at
+    /// `midnight` (Eastern), e.g. `2024-03-01T05:00:00Z`
+    pub fn new(reader: R, midnight: DateTime<Utc>) -> Self {
+        Self {
+            reader,
+            midnight,
+            filter: None,
+            snapshot_depth: None,
+            symbols: HashMap::new(),
+            books: HashMap::new(),
+            message: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Only track books and trades for `symbols`
+    pub fn with_symbols(mut self, symbols: &[&str]) -> Self {
+        self.filter = Some(symbols.iter().map(|s| s.to_uppercase()).collect());
+        self
+    }
+
+    /// Emit a snapshot of the top `depth` levels after every book change
+    pub fn with_book_snapshots(mut self, depth: usize) -> Self {
+        self.snapshot_depth = Some(depth);
+        self
+    }
+
+    /// Current book of `symbol`
+    pub fn book(&self, symbol: &str) -> Option<&L3Book> {
+        self.books.values().find(|book| book.symbol() == symbol)
+    }
+
+    /// Read the next message; `false` at a clean end of file
+    fn read_message(&mut self) -> Result<bool> {
+        let mut len = [0u8; 2];
+        match self.reader.read(&mut len[..1]) {
+            Ok(0) => return Ok(false),
+            Ok(_) => {}
+            Err(e) => return Err(io_error(e)),
+        }
+        self.reader.read_exact(&mut len[1..]).map_err(io_error)?;
+        self.message.clear();
+        self.message.resize(u16::from_be_bytes(len) as usize, 0);
+        self.reader
+            .read_exact(&mut self.message)
+            .map_err(io_error)?;
+        Ok(true)
+    }
+
+    /// Interns only the symbols of tracked books, checked since files may
+    /// come from anywhere
+    fn track(&mut self, locate: u16, symbol: String) -> Result<()> {
+        let tracked = self
+            .filter
+            .as_ref()
+            .is_none_or(|filter| filter.contains(&symbol));
+        if tracked && !self.books.contains_key(&locate) {
+            self.books.insert(locate, L3Book::new(Symbol::new(&symbol)?));
+        }
+        self.symbols.insert(locate, symbol);
+        Ok(())
+    }
+
+    fn handle(&mut self) -> Result<()> {
+        let msg = &self.message;
+        let Some(&kind) = msg.first() else {
+            return Err(invalid("empty message"));
+        };
+        let Some(len) = message_len(kind) else {
+            // System events, trading actions, NOII and other administrative messages
+            return Ok(());
+        };
+        if msg.len() < len {
+            return Err(invalid(format!("short '{}' message", kind as char)));
+        }
+        let locate = u16_at(msg, 1);
+        let nanos = (u16_at(msg, 5) as i64) << 32 | u32_at(msg, 7) as i64;
+        let timestamp = self.midnight + Duration::nanoseconds(nanos);
+
+        if kind == b'R' || kind == b'A' || kind == b'F' {
+            let offset = if kind == b'R' { 11 } else { 24 };
+            if !self.symbols.contains_key(&locate) {
+                let symbol = stock_at(msg, offset);
+                self.track(locate, symbol)?;
+            }
+        }
+        let msg = &self.message;
+        let Some(book) = self.books.get_mut(&locate) else {
+            return Ok(());
+        };
+        let print = |symbol: &str,
+                     price: f64,
+                     quantity: f64,
+                     side: TradeSide,
+                     match_number: u64,
+                     conditions: TradeConditions| {
+            MarketDataMessage::Trade(Trade {
+                symbol: symbol.into(),
+                price,
+                quantity,
+                side,
+                timestamp,
+                trade_id: match_number.to_string(),
+                conditions,
+                instrument_id: None,
+                contract: None,
+                received: None,
+            })
+        };
+
+        let changed = match kind {
+            b'A' | b'F' => {
+                let side = if msg[19] == b'B' {
+                    BookSide::Bid
+                } else {
+                    BookSide::Ask
+                };
+                let order = L3Order {
+                    side,
+                    price: price_at(msg, 32),
+                    size: u32_at(msg, 20) as f64,
+                };
+                book.add(u64_at(msg, 11), order, timestamp);
+                true
+            }
+            b'E' | b'C' => {
+                let shares = u32_at(msg, 19) as f64;
+                let Some(order) = book.reduce(u64_at(msg, 11), shares, timestamp) else {
+                    return Ok(());
+                };
+                // Executions with price are only printed when flagged printable
+                let price = match kind {
+                    b'E' => Some(order.price),
+                    _ => (msg[31] == b'Y').then(|| price_at(msg, 32)),
+                };
+                if let Some(price) = price {
+                    let trade = print(
+                        book.symbol(),
+                        price,
+                        shares,
+                        aggressor(order.side),
+                        u64_at(msg, 23),
+                        TradeConditions::empty(),
+                    );
+                    self.pending.push_back(trade);
+                }
+                true
+            }
+            b'X' => book
+                .reduce(u64_at(msg, 11), u32_at(msg, 19) as f64, timestamp)
+                .is_some(),
+            b'D' => book.remove(u64_at(msg, 11), timestamp).is_some(),
+            b'U' => match book.remove(u64_at(msg, 11), timestamp) {
+                Some(order) => {
+                    let replacement = L3Order {
+                        side: order.side,
+                        price: price_at(msg, 31),
+                        size: u32_at(msg, 27) as f64,
+                    };
+                    book.add(u64_at(msg, 19), replacement, timestamp);
+                    true
+                }
+                None => false,
+            },
+            b'P' => {
+                // Matches against non-displayed orders, which never enter the book
+                let side = if msg[19] == b'B' {
+                    BookSide::Bid
+                } else {
+                    BookSide::Ask
+                };
+                let trade = print(
+                    book.symbol(),
+                    price_at(msg, 32),
+                    u32_at(msg, 20) as f64,
+                    aggressor(side),
+                    u64_at(msg, 36),
+                    TradeConditions::empty(),
+                );
+                self.pending.push_back(trade);
+                false
+            }
+            b'Q' => {
+                let shares = u64_at(msg, 11);
+                if shares > 0 {
+                    // Crosses have no aggressor
+                    let trade = print(
+                        book.symbol(),
+                        price_at(msg, 27),
+                        shares as f64,
+                        TradeSide::Unknown,
+                        u64_at(msg, 31),
+                        TradeConditions::AUCTION,
+                    );
+                    self.pending.push_back(trade);
+                }
+                false
+            }
+            b'B' => {
+                // Only the match number is repeated
+                let bust = TradeBust {
+                    symbol: book.symbol().into(),
+                    trade_id: u64_at(msg, 11).to_string(),
+                    timestamp,
+                    original: None,
+                    instrument_id: None,
+                    contract: None,
+                    received: None,
+                };
+                self.pending.push_back(MarketDataMessage::TradeBust(bust));
+                false
+            }
+            _ => false,
+        };
+
+        if let (true, Some(depth)) = (changed, self.snapshot_depth) {
+            let snapshot = book.levels().snapshot(Some(depth));
+            self.pending
+                .push_back(MarketDataMessage::OrderBook(snapshot));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for ItchReader<R> {
+    type Item = Result<MarketDataMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(msg) = self.pending.pop_front() {
+                return Some(Ok(msg));
+            }
+            match self.read_message() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+            if let Err(e) = self.handle() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(kind: u8, nanos: u64, body: &[u8]) -> Vec<u8> {
+        let mut msg = vec![kind];
+        msg.extend(1u16.to_be_bytes());
+        msg.extend(0u16.to_be_bytes());
+        msg.extend(&nanos.to_be_bytes()[2..]);
+        msg.extend(body);
+        let mut frame = (msg.len() as u16).to_be_bytes().to_vec();
+        frame.extend(msg);
+        frame
+    }
+
+    fn add(order_ref: u64, side: u8, shares: u32, price: u32) -> Vec<u8> {
+        let mut body = order_ref.to_be_bytes().to_vec();
+        body.push(side);
+        body.extend(shares.to_be_bytes());
+        body.extend(b"AAPL    ");
+        body.extend(price.to_be_bytes());
+        message(b'A', 34_200_000_000_000, &body)
+    }
+
+    #[test]
+    fn test_replays_orders_into_book_and_trades() {
+        let mut file = Vec::new();
+        let mut directory = b"AAPL    ".to_vec();
+        directory.resize(28, 0);
+        file.extend(message(b'R', 0, &directory));
+        file.extend(message(b'S', 0, b"O"));
+        file.extend(add(1, b'B', 100, 1_796_000));
+        file.extend(add(2, b'S', 200, 1_797_000));
+        // Order 2 executed for 50 shares, match 77
+        let mut body = 2u64.to_be_bytes().to_vec();
+        body.extend(50u32.to_be_bytes());
+        body.extend(77u64.to_be_bytes());
+        file.extend(message(b'E', 34_200_000_000_500, &body));
+        // Order 1 replaced by order 3: 80 shares at 179.62
+        let mut body = 1u64.to_be_bytes().to_vec();
+        body.extend(3u64.to_be_bytes());
+        body.extend(80u32.to_be_bytes());
+        body.extend(1_796_200u32.to_be_bytes());
+        file.extend(message(b'U', 34_200_000_001_000, &body));
+        // Match 77 broken
+        file.extend(message(b'B', 34_200_000_002_000, &77u64.to_be_bytes()));
+
+        let midnight = "2024-03-01T05:00:00Z".parse().unwrap();
+        let mut reader = ItchReader::new(file.as_slice(), midnight).with_symbols(&["AAPL"]);
+        let trades: Vec<MarketDataMessage> = reader.by_ref().map(|msg| msg.unwrap()).collect();
+
+        assert_eq!(trades.len(), 2);
+        let MarketDataMessage::TradeBust(bust) = &trades[1] else {
+            panic!("expected bust");
+        };
+        assert_eq!(
+            (bust.symbol.as_str(), bust.trade_id.as_str()),
+            ("AAPL", "77")
+        );
+        let MarketDataMessage::Trade(trade) = &trades[0] else {
+            panic!("expected trade");
+        };
+        assert_eq!((trade.price, trade.quantity), (179.7, 50.0));
+        assert_eq!(
+            (trade.side, trade.trade_id.as_str()),
+            (TradeSide::Buy, "77")
+        );
+        assert_eq!(
+            trade.timestamp.to_rfc3339(),
+            "2024-03-01T14:30:00.000000500+00:00"
+        );
+
+        let book = reader.book("AAPL").unwrap().levels();
+        assert_eq!(
+            (
+                book.best_bid().unwrap().price,
+                book.best_bid().unwrap().size
+            ),
+            (179.62, 80.0)
+        );
+        assert_eq!(book.best_ask().unwrap().size, 150.0);
+    }
+}
@@ -0,0 +1,389 @@
+//! Write-ahead journal of raw frames.
+//!
+//! Every text frame received by the client is appended to the journal before it
+//! is parsed, so a crashed process can replay exactly what it had received.
+//! Records are stored as `[len: u32][crc32: u32][payload]` (little endian); a
+//! torn record at the tail of the file is discarded on recovery.
+//!
+//! Each record is handed to the operating system as it is appended, so it
+//! survives a crash of the process; the [`FsyncPolicy`] decides how many
+//! survive a crash of the machine.
+//!
+//! A running client truncates the journal whenever it holds at least the
+//! checkpoint size and every frame in it has been delivered, so the file
+//! stays bounded and a restart only replays frames received since then.
+
+use crate::client::{ClientError, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+const HEADER_LEN: usize = 8;
+
+/// Journal size from which processed frames are truncated away
+const DEFAULT_CHECKPOINT_SIZE: u64 = 1 << 20;
+
+/// When journal writes are forced to stable storage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsyncPolicy {
+    /// fsync after every frame
+    Always,
+    /// fsync after every `n` frames
+    EveryN(u64),
+    /// fsync once the given interval has elapsed since the last sync; a
+    /// client also syncs on a timer, so a quiet feed is not left unsynced
+    Interval(Duration),
+    /// Leave syncing to the operating system
+    Never,
+}
+
+/// Append-only journal of raw frames
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    policy: FsyncPolicy,
+    unsynced: u64,
+    last_sync: Instant,
+    /// Bytes of intact records in the file
+    size: u64,
+    checkpoint_size: u64,
+}
+
+impl Journal {
+    /// Open (or create) a journal, discarding any torn record at its tail
+    pub fn open(path: impl AsRef<Path>, policy: FsyncPolicy) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let valid_len = if path.exists() {
+            let mut frames = Self::recover(&path)?;
+            for frame in &mut frames {
+                frame?;
+            }
+            frames.offset
+        } else {
+            0
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(io_error)?;
+        if file.metadata().map_err(io_error)?.len() > valid_len {
+            warn!("Truncating torn journal tail in {}", path.display());
+            file.set_len(valid_len).map_err(io_error)?;
+        }
+
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            policy,
+            unsynced: 0,
+            last_sync: Instant::now(),
+            size: valid_len,
+            checkpoint_size: DEFAULT_CHECKPOINT_SIZE,
+        })
+    }
+
+    /// Let a client truncate the journal once it holds `bytes` of processed
+    /// frames, instead of 1 MiB
+    pub fn with_checkpoint_size(mut self, bytes: u64) -> Self {
+        self.checkpoint_size = bytes;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Bytes of records in the journal
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether the journal has grown to its checkpoint size
+    pub(crate) fn checkpoint_due(&self) -> bool {
+        self.size >= self.checkpoint_size
+    }
+
+    /// Append a frame, syncing according to the configured policy
+    pub fn append(&mut self, frame: &[u8]) -> Result<()> {
+        if self.write(frame)? {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Append a frame and write it out to the operating system without
+    /// syncing; returns whether the policy asks for a sync now
+    pub(crate) fn write(&mut self, frame: &[u8]) -> Result<bool> {
+        let len = u32::try_from(frame.len())
+            .map_err(|_| ClientError::Io("frame too large for journal".to_string()))?;
+
+        self.writer
+            .write_all(&len.to_le_bytes())
+            .map_err(io_error)?;
+        self.writer
+            .write_all(&crc32fast::hash(frame).to_le_bytes())
+            .map_err(io_error)?;
+        self.writer.write_all(frame).map_err(io_error)?;
+        self.writer.flush().map_err(io_error)?;
+        self.unsynced += 1;
+        self.size += (HEADER_LEN + frame.len()) as u64;
+
+        Ok(match self.policy {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::EveryN(n) => self.unsynced >= n,
+            FsyncPolicy::Interval(interval) => self.last_sync.elapsed() >= interval,
+            FsyncPolicy::Never => false,
+        })
+    }
+
+    /// How often a timer should check for frames left unsynced, under
+    /// [`FsyncPolicy::Interval`]
+    pub(crate) fn sync_interval(&self) -> Option<Duration> {
+        match self.policy {
+            FsyncPolicy::Interval(interval) => Some(interval),
+            _ => None,
+        }
+    }
+
+    /// Whether frames have waited at least the sync interval for an fsync
+    pub(crate) fn sync_due(&self) -> bool {
+        self.unsynced > 0
+            && self
+                .sync_interval()
+                .is_some_and(|interval| self.last_sync.elapsed() >= interval)
+    }
+
+    /// Flush buffered records and fsync the file
+    pub fn sync(&mut self) -> Result<()> {
+        self.writer.flush().map_err(io_error)?;
+        self.writer.get_ref().sync_data().map_err(io_error)?;
+        self.unsynced = 0;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Discard all journaled frames, e.g. once they have been durably processed
+    pub fn reset(&mut self) -> Result<()> {
+        self.writer.flush().map_err(io_error)?;
+        self.writer.get_ref().set_len(0).map_err(io_error)?;
+        self.size = 0;
+        self.sync()
+    }
+
+    /// Discard the records in the first `offset` bytes, as reported by
+    /// [`JournalFrames::offset`], keeping the frames after them. The kept
+    /// frames are written to a new file that replaces the journal, so a
+    /// crash leaves either the old or the new journal in place.
+    pub fn discard_before(&mut self, offset: u64) -> Result<()> {
+        if offset == 0 {
+            return Ok(());
+        }
+        if offset >= self.size {
+            return self.reset();
+        }
+        self.writer.flush().map_err(io_error)?;
+        let mut kept = Vec::new();
+        let mut file = File::open(&self.path).map_err(io_error)?;
+        file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+        file.take(self.size - offset)
+            .read_to_end(&mut kept)
+            .map_err(io_error)?;
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut replacement = File::create(&tmp).map_err(io_error)?;
+        replacement.write_all(&kept).map_err(io_error)?;
+        replacement.sync_data().map_err(io_error)?;
+        std::fs::rename(&tmp, &self.path).map_err(io_error)?;
+
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(io_error)?;
+        self.writer = BufWriter::new(file);
+        self.size = kept.len() as u64;
+        self.unsynced = 0;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Read the intact frames of a journal file, one at a time
+    pub fn recover(path: impl AsRef<Path>) -> Result<JournalFrames> {
+        let file = File::open(path).map_err(io_error)?;
+        let remaining = file.metadata().map_err(io_error)?.len();
+        Ok(JournalFrames {
+            reader: BufReader::new(file),
+            remaining,
+            offset: 0,
+        })
+    }
+}
+
+/// Frames of a journal file, from [`Journal::recover`]. Iteration stops at
+/// a torn or corrupt record.
+#[derive(Debug)]
+pub struct JournalFrames {
+    reader: BufReader<File>,
+    /// Bytes after `offset`, so a corrupt length cannot over-allocate
+    remaining: u64,
+    /// End of the last intact record
+    offset: u64,
+}
+
+impl JournalFrames {
+    /// End of the last frame read, in bytes from the start of the file
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl Iterator for JournalFrames {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining < HEADER_LEN as u64 {
+            return None;
+        }
+        let mut header = [0u8; HEADER_LEN];
+        if let Err(e) = self.reader.read_exact(&mut header) {
+            self.remaining = 0;
+            return Some(Err(io_error(e)));
+        }
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as u64;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+        if self.remaining - (HEADER_LEN as u64) < len {
+            self.remaining = 0;
+            return None;
+        }
+        let mut payload = vec![0u8; len as usize];
+        if let Err(e) = self.reader.read_exact(&mut payload) {
+            self.remaining = 0;
+            return Some(Err(io_error(e)));
+        }
+        if crc32fast::hash(&payload) != crc {
+            warn!(
+                "Journal checksum mismatch at offset {}, stopping recovery",
+                self.offset
+            );
+            self.remaining = 0;
+            return None;
+        }
+        self.remaining -= HEADER_LEN as u64 + len;
+        self.offset += HEADER_LEN as u64 + len;
+        Some(Ok(payload))
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+fn io_error(e: std::io::Error) -> ClientError {
+    ClientError::Io(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_recover() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+
+        let mut journal = Journal::open(&path, FsyncPolicy::EveryN(2)).unwrap();
+        journal.append(b"{\"type\":\"Heartbeat\"}").unwrap();
+        journal.append(b"second").unwrap();
+        drop(journal);
+
+        let frames: Vec<_> = Journal::recover(&path)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            frames,
+            vec![b"{\"type\":\"Heartbeat\"}".to_vec(), b"second".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_torn_tail_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+
+        let mut journal = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        journal.append(b"complete").unwrap();
+        drop(journal);
+
+        // Simulate a crash midway through writing a record
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[42, 0, 0, 0, 1, 2]).unwrap();
+        drop(file);
+
+        let mut journal = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(journal.size(), 16);
+        journal.append(b"after restart").unwrap();
+        drop(journal);
+
+        let frames: Vec<_> = Journal::recover(&path)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            frames,
+            vec![b"complete".to_vec(), b"after restart".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_frames_reach_the_file_before_a_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+
+        let mut journal = Journal::open(&path, FsyncPolicy::Interval(Duration::ZERO)).unwrap();
+        assert!(!journal.sync_due());
+        journal.write(b"unsynced").unwrap();
+        assert!(journal.sync_due());
+
+        // A crashed process never runs the journal's destructor
+        std::mem::forget(journal);
+        let frames: Vec<_> = Journal::recover(&path)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(frames, vec![b"unsynced".to_vec()]);
+    }
+
+    #[test]
+    fn test_discard_before_keeps_later_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.wal");
+
+        let mut journal = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        for frame in [&b"first"[..], b"second", b"third"] {
+            journal.append(frame).unwrap();
+        }
+        let mut frames = Journal::recover(&path).unwrap();
+        frames.next().unwrap().unwrap();
+        journal.discard_before(frames.offset()).unwrap();
+        assert_eq!(journal.size(), 2 * HEADER_LEN as u64 + 11);
+        journal.append(b"fourth").unwrap();
+        drop(journal);
+
+        let frames: Vec<_> = Journal::recover(&path)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            frames,
+            vec![b"second".to_vec(), b"third".to_vec(), b"fourth".to_vec()]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Large trade ("whale") alerts.
+//!
+//! [`LargeTradeDetector`] flags single trades whose notional reaches a
+//! per-symbol threshold and, optionally, runs of same-side trades within a
+//! short window that add up to it, as when a large order is sliced or sweeps
+//! several levels. Thresholds are either fixed notionals or a percentile of
+//! the symbol's recent trade sizes. Notionals of tagged derivatives
+//! include the contract multiplier.
+
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Trade, TradeSide};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+/// Notional a trade or cluster must reach to be reported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    /// Fixed notional in the quote currency
+    Notional(f64),
+    /// The `quantile` (e.g. `0.999`) of the last `sample` trade notionals;
+    /// nothing is reported until that many trades were seen. A `sample` of
+    /// zero counts as one.
+    Percentile { quantile: f64, sample: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LargeTradeKind {
+    Single,
+    /// Same-side trades within the cluster window
+    Cluster,
+}
+
+/// A trade or cluster of trades above the threshold
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LargeTrade {
+    pub symbol: Symbol,
+    pub kind: LargeTradeKind,
+    pub side: TradeSide,
+    pub notional: f64,
+    pub quantity: f64,
+    pub vwap: f64,
+    pub trades: usize,
+    /// Threshold in effect when the alert fired
+    pub threshold: f64,
+    /// Time of the first trade
+    pub start: DateTime<Utc>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SymbolState {
+    /// Recent notionals for percentile thresholds
+    history: VecDeque<f64>,
+    /// Trades of the open cluster as `(time, price, quantity)`
+    cluster: VecDeque<(DateTime<Utc>, f64, f64)>,
+    cluster_side: Option<TradeSide>,
+}
+
+/// Flags trades and trade clusters with a large notional
+#[derive(Debug, Clone)]
+pub struct LargeTradeDetector {
+    default: Threshold,
+    thresholds: HashMap<Symbol, Threshold>,
+    cluster_window: Option<Duration>,
+    symbols: HashMap<Symbol, SymbolState>,
+    /// Reused copy of a history to select a percentile from
+    scratch: Vec<f64>,
+}
+
+impl LargeTradeDetector {
+    /// Apply `threshold` to symbols without their own
+    pub fn new(threshold: Threshold) -> Self {
+        Self {
+            default: threshold,
+            thresholds: HashMap::new(),
+            cluster_window: None,
+            symbols: HashMap::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn with_threshold(mut self, symbol: &str, threshold: Threshold) -> Self {
+        self.thresholds.insert(symbol.into(), threshold);
+        self
+    }
+
+    /// Also report same-side trades within `window` whose combined notional
+    /// reaches the threshold
+    pub fn with_cluster_window(mut self, window: Duration) -> Self {
+        self.cluster_window = Some(window);
+        self
+    }
+
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Option<LargeTrade> {
+        match msg {
+            MarketDataMessage::Trade(trade) => self.on_trade(trade),
+            _ => None,
+        }
+    }
+
+    pub fn on_trade(&mut self, trade: &Trade) -> Option<LargeTrade> {
+        let rule = self
+            .thresholds
+            .get(&trade.symbol)
+            .copied()
+            .unwrap_or(self.default);
+        let state = self.symbols.entry(trade.symbol).or_default();
+        let notional = trade.notional();
+        let threshold = match rule {
+            Threshold::Notional(notional) => Some(notional),
+            Threshold::Percentile { quantile, sample } => {
+                let sample = sample.max(1);
+                let threshold = (state.history.len() >= sample).then(|| {
+                    let scratch = &mut self.scratch;
+                    scratch.clear();
+                    scratch.extend(&state.history);
+                    let rank = ((scratch.len() - 1) as f64 * quantile.clamp(0.0, 1.0)).round();
+                    *scratch
+                        .select_nth_unstable_by(rank as usize, f64::total_cmp)
+                        .1
+                });
+                state.history.push_back(notional);
+                if state.history.len() > sample {
+                    state.history.pop_front();
+                }
+                threshold
+            }
+        };
+
+        let Some(window) = self.cluster_window else {
+            let threshold = threshold.filter(|threshold| notional >= *threshold)?;
+            let fill = (trade.timestamp, trade.price, trade.quantity);
+            return Some(alert(trade, LargeTradeKind::Single, threshold, &[fill]));
+        };
+        if state.cluster_side != Some(trade.side) {
+            state.cluster.clear();
+            state.cluster_side = Some(trade.side);
+        }
+        while state
+            .cluster
+            .front()
+            .is_some_and(|(at, _, _)| trade.timestamp - *at > window)
+        {
+            state.cluster.pop_front();
+        }
+        state
+            .cluster
+            .push_back((trade.timestamp, trade.price, trade.quantity));
+
+        let threshold = threshold?;
+        let kind = if notional >= threshold {
+            LargeTradeKind::Single
+        } else if state.cluster.iter().map(|(_, p, q)| p * q).sum::<f64>() * multiplier(trade)
+            >= threshold
+        {
+            LargeTradeKind::Cluster
+        } else {
+            return None;
+        };
+        let fills: Vec<_> = match kind {
+            LargeTradeKind::Single => vec![*state.cluster.back().unwrap()],
+            LargeTradeKind::Cluster => state.cluster.iter().copied().collect(),
+        };
+        // Reported trades do not count towards the next cluster
+        state.cluster.clear();
+        Some(alert(trade, kind, threshold, &fills))
+    }
+}
+
+fn multiplier(trade: &Trade) -> f64 {
+    trade.contract.map_or(1.0, |contract| contract.multiplier)
+}
+
+fn alert(
+    trade: &Trade,
+    kind: LargeTradeKind,
+    threshold: f64,
+    fills: &[(DateTime<Utc>, f64, f64)],
+) -> LargeTrade {
+    let value: f64 = fills.iter().map(|(_, p, q)| p * q).sum();
+    let quantity: f64 = fills.iter().map(|(_, _, q)| q).sum();
+    LargeTrade {
+        symbol: trade.symbol,
+        kind,
+        side: trade.side,
+        notional: value * multiplier(trade),
+        quantity,
+        vwap: value / quantity,
+        trades: fills.len(),
+        threshold,
+        start: fills[0].0,
+        timestamp: trade.timestamp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn trade(price: f64, quantity: f64, side: TradeSide, ms: i64) -> Trade {
+        Trade {
+            quantity,
+            side,
+            timestamp: Utc.timestamp_millis_opt(1_700_000_000_000 + ms).unwrap(),
+            ..Trade::test("BTCUSD", price)
+        }
+    }
+
+    #[test]
+    fn test_single_and_clustered_trades() {
+        let mut detector = LargeTradeDetector::new(Threshold::Notional(1_000_000.0))
+            .with_cluster_window(Duration::milliseconds(500));
+        let alert = detector
+            .on_trade(&trade(50_000.0, 25.0, TradeSide::Buy, 0))
+            .unwrap();
+        assert_eq!(
+            (alert.kind, alert.notional),
+            (LargeTradeKind::Single, 1_250_000.0)
+        );
+
+        // A buy breaks the first run of sells
+        assert!(detector
+            .on_trade(&trade(50_000.0, 10.0, TradeSide::Sell, 100))
+            .is_none());
+        assert!(detector
+            .on_trade(&trade(50_000.0, 1.0, TradeSide::Buy, 150))
+            .is_none());
+        assert!(detector
+            .on_trade(&trade(50_000.0, 10.0, TradeSide::Sell, 200))
+            .is_none());
+        let alert = detector
+            .on_trade(&trade(40_000.0, 15.0, TradeSide::Sell, 600))
+            .unwrap();
+        assert_eq!(alert.kind, LargeTradeKind::Cluster);
+        assert_eq!(
+            (alert.trades, alert.notional, alert.quantity),
+            (2, 1_100_000.0, 25.0)
+        );
+        assert_eq!(alert.vwap, 44_000.0);
+        // Reported trades start no new cluster, and old ones fall out
+        assert!(detector
+            .on_trade(&trade(50_000.0, 19.0, TradeSide::Sell, 700))
+            .is_none());
+        assert!(detector
+            .on_trade(&trade(50_000.0, 2.0, TradeSide::Sell, 1300))
+            .is_none());
+    }
+
+    #[test]
+    fn test_percentile_threshold_warms_up() {
+        let mut detector = LargeTradeDetector::new(Threshold::Notional(f64::MAX)).with_threshold(
+            "BTCUSD",
+            Threshold::Percentile {
+                quantile: 0.9,
+                sample: 10,
+            },
+        );
+        for i in 0..10 {
+            assert!(detector
+                .on_trade(&trade(100.0, 1.0 + i as f64, TradeSide::Buy, i))
+                .is_none());
+        }
+        // The 90th percentile of 100..=1000 is 900
+        assert!(detector
+            .on_trade(&trade(100.0, 8.5, TradeSide::Buy, 10))
+            .is_none());
+        let alert = detector
+            .on_trade(&trade(100.0, 12.0, TradeSide::Buy, 11))
+            .unwrap();
+        assert_eq!(alert.threshold, 900.0);
+    }
+
+    #[test]
+    fn test_percentile_of_zero_sample_uses_the_last_trade() {
+        let mut detector = LargeTradeDetector::new(Threshold::Percentile {
+            quantile: 0.5,
+            sample: 0,
+        });
+        assert!(detector
+            .on_trade(&trade(100.0, 2.0, TradeSide::Buy, 0))
+            .is_none());
+        let alert = detector
+            .on_trade(&trade(100.0, 3.0, TradeSide::Buy, 1))
+            .unwrap();
+        assert_eq!(alert.threshold, 200.0);
+    }
+}
@@ -6,8 +6,77 @@
 //!
 //! - **WebSocket Client**: Async WebSocket client for real-time market data feeds
 //! - **Multiple Data Types**: Support for trades, quotes, and order book snapshots
-//! - **Market Statistics**: Real-time calculation of VWAP, high/low, volume
+//! - **Trade Corrections and Busts**: Venue amendments of earlier trades, applied retroactively to running stats, open bars and the tick store, with bars already emitted reported stale
+//! - **Typed Symbology**: Interned, copyable `Symbol`, `Venue`, `InstrumentId` and `Currency` names that serialize as plain strings
+//! - **Market Statistics**: Real-time calculation of VWAP, high/low, quantity and notional volume, optionally excluding block, auction and other conditioned trades, in a sharded single-writer engine with lock-free reads and a top-by-notional scanner
+//! - **Bar Aggregation**: Time, tick, volume and dollar OHLCV bars per symbol, Renko and range bars, footprint bars with per-price buy/sell volume and cumulative delta, exchange- or receive-time alignment, and grace periods and revisions for late trades
+//! - **Avro Serialization**: Confluent-framed Avro records for Kafka producers with Schema Registry subject naming and backward-compatibility checks
+//! - **Columnar Batches**: Struct-of-arrays trade and quote batches for vectorized analytics, convertible to Arrow record batches
+//! - **Arrow Flight**: Optional Flight endpoint streaming live record batches and serving time-range queries over recordings to Python and R clients
+//! - **Wire Formats**: JSON, MessagePack and CBOR framing for the fan-out server and capture files
 //! - **Broadcast Channels**: Efficient message distribution to multiple consumers
+//! - **Watchlists**: Named symbol sets editable at runtime that subscribe and unsubscribe through the client and stream per-watchlist quote and stats updates
+//! - **Receive Timestamps**: Exchange and receive time on every message to the nanosecond, with a pluggable clock source for PTP or hardware time, per-venue epoch units and optional truncation of served timestamps
+//! - **Late-Joiner Sync**: Last value cache snapshots (last trade, BBO, book and stats) followed by the live stream at a consistent sequence boundary
+//! - **End-of-Day Summaries**: Daily per-symbol OHLC, volume, VWAP, trade counts and high/low times persisted at the session close
+//! - **Write-Ahead Journal**: Crash-safe journaling of raw frames with replay on restart
+//! - **Binary Recordings**: Compressed, time-indexed capture format with fast range seeks, pluggable storage backends, per-block checksums with manifest verification, retention policies, object storage upload, an embedded SQL tick store, order book delta journaling with exact replay and optional AES-256-GCM encryption at rest
+//! - **Order Book Engine**: Incremental level 2 and order-by-order level 3 books with time-travel reconstruction from recordings, and per-symbol depth tiers that pick the cheapest venue channel
+//! - **Backtesting**: Deterministic event loop with a virtual clock, timers, bar callbacks and seeded randomness
+//! - **Strategy Runner**: A `Strategy` trait with start, trade, quote, book, bar, timer and stop hooks, driven identically from live sources on the wall clock or replayed ones on event time
+//! - **Portfolio Tracking**: Registered positions marked to market from the stream, with unrealized PnL, exposure and per-position price alerts
+//! - **Risk Limits**: Adverse-move, volatility circuit-breaker and stale-price limits checked against the stream, raising typed breaches and halting symbols for execution systems
+//! - **Synthetic Feeds**: Seeded random-walk trades and quotes on virtual time, reproducible bit for bit
+//! - **Fill Simulation**: Paper trading against the live or replayed book with latency and queue models
+//! - **Synthetic Instruments**: Spread, ratio and weighted streams derived from several symbols, and index baskets tolerant of stale constituents
+//! - **Quote Analytics**: Time-weighted quoted spread, time at the minimum tick and top-of-book depth over rolling windows, plus a top-of-book change-only stream
+//! - **Execution Quality**: Trades matched to the prevailing quote for effective spread, price improvement and aggressor inference, live or in replay
+//! - **Aggressor Inference**: Trades without a reported side stay `Unknown` unless a quote, tick or Lee-Ready rule stage fills them in, flagged as inferred
+//! - **Execution Benchmarks**: Interval VWAP and TWAP with slippage and participation of registered fills
+//! - **Rolling Statistics**: VWAP, volatility and min/max over the last N trades per symbol with vectorized kernels over ring buffers
+//! - **Tick Enrichment**: Trades and quotes annotated with the prevailing mid and spread in basis points, distance from the session VWAP and rolling trade size percentile
+//! - **Feature Vectors**: Fixed-schema per-symbol spread, imbalance, multi-horizon return, volatility and trade intensity vectors on an event-time cadence, written as NumPy `.npy` matrices or Arrow record batches
+//! - **Model Inference**: ONNX models run on the feature vector stream inside the pipeline, publishing predictions (`onnx` feature)
+//! - **Sampled Series**: Evenly spaced mid-price series with forward-fill, staleness flags and gap interpolation
+//! - **Cross-Symbol Correlation**: Rolling pairwise return correlation matrices, and beta and relative strength against a benchmark, published on analytics channels
+//! - **Arbitrage Monitoring**: Cross-venue best bid/ask and fee-adjusted spread alerts
+//! - **Message Validation**: Per-venue invariant checks on prices, sizes, crossed quotes, timestamps and symbols, rejecting or marking failures
+//! - **Crossed Markets**: Locked and crossed book detection per venue and across venues, with durations
+//! - **Iceberg Detection**: Flags price levels repeatedly refilled after executions, with estimated hidden size
+//! - **Large Trade Alerts**: Single and clustered trades above fixed or percentile-based notional thresholds
+//! - **Momentum Signals**: Short-horizon price velocity and acceleration with signed momentum changes
+//! - **Book Snapshots**: Periodic full-depth snapshots materialized from incremental books
+//! - **Processing Pipeline**: Pluggable stages such as FX conversion into a reference currency and filter expressions like `symbol == 'BTCUSD' && price > 50000`
+//! - **Supervised Actors**: Connection, parser, router and sink tasks with typed mailboxes and restart policies, so a stalled or panicking sink cannot hold up ingestion; actors that keep failing are reported and leave the client in a degraded health state
+//! - **Stream Graphs**: Declarative source, transform and sink graphs with fan-out and fan-in, validated before their tasks start and shut down in order
+//! - **History Buffer**: Last minutes or messages per symbol in memory, queryable by time or sequence range for gap-fill, late joiners and debugging
+//! - **Warm-Up Hydration**: Historical trades, bars or recordings replayed through stats and indicator stages before going live
+//! - **Replay-to-Live Switchover**: Catch-up sources replaying history before continuing live, deduplicating the overlap
+//! - **Dedicated Processing**: Optional pinned OS thread with busy-poll or blocking wait strategies
+//! - **Memory Reuse**: Buffer pools for hot-path batches and optional allocation accounting
+//! - **Exchange Adapters**: Binance, Coinbase, OKX, Bitstamp and Gemini crypto feeds plus Alpaca and IEX Cloud equities (SSE via the `sse` feature), with optional simd-json parsing
+//! - **Databento DBN**: Historical DBN files and the live gateway normalized into trades, quotes, books and bars
+//! - **ITCH 5.0 Replay**: NASDAQ TotalView-ITCH files replayed through level 3 books
+//! - **SBE Decoding**: Runtime XML schemas decode binary feeds such as CME MDP 3.0 for per-template adapter handlers
+//! - **Instrument Identifiers**: ISIN, FIGI and CUSIP mappings from CSV or OpenFIGI tag messages with a canonical instrument id and, for futures and perpetuals, contract multiplier, expiry and settlement currency
+//! - **Microburst Detection**: Burst statistics and an optional rate-bounded smoothing queue
+//! - **Bandwidth Accounting**: Bytes received per connection, channel and symbol
+//! - **Feed QoS Reports**: Per-venue uptime, reconnects, gaps, message rates, latency percentiles and parse-error rates for SLA tracking
+//! - **Metrics History**: Periodic snapshots of each client's rates, latencies, gaps and health persisted to a sink for long-term feed-quality trends
+//! - **Feed Fixtures**: Captured adapter samples replayed by offline golden tests
+//! - **Parse Circuit Breaker**: Degraded-parser events, raw passthrough and throttled parse warnings
+//! - **Anomaly Baselines**: Learned per-symbol message rate and volume norms by time of day, with deviations reported as client events
+//! - **Overload Shedding**: Quote conflation and book depth limits while ingest-to-dispatch latency is over budget
+//! - **Lifecycle Events**: Typed connect, subscription ack, disconnect and reconnect events
+//! - **Entitlements**: Per-consumer symbol and channel permissioning from config, with audit logging of denied requests
+//! - **Sink Throttles**: Centrally configured per-sink, per-symbol rate caps on each channel, dropping excess trades and conflating quotes and books
+//! - **Fan-Out Server**: Multi-tenant TCP re-publishing with API keys, symbol entitlements, per-connection filters, rate limits, conflation and acknowledged book delta compression, sequence-range gap-fill replays, and usage accounting
+//! - **Exchange Emulation**: WebSocket server speaking a venue's own wire format, such as Binance streams, from replayed or synthetic data for testing unmodified exchange clients
+//! - **Chaos Testing**: Seeded latency, drop, duplication, reordering and forced disconnect injection into pipelines or the exchange emulator (`chaos` feature)
+//! - **Control Plane**: Runtime admin commands over a channel or unix socket
+//! - **Structured Logging**: JSON log lines with event categories and venue, symbol, sequence and latency fields
+//! - **Health Probes**: `/healthz` and `/readyz` HTTP endpoints reflecting connection state, staleness and actor health
+//! - **Symbol Sharding**: Static or lease-based splitting of a symbol universe across streamer processes, with takeover of a failed peer's shard
 //! - **Error Handling**: Comprehensive error types and recovery mechanisms
 //!
 //! ## Example
@@ -40,13 +109,168 @@
 //! }
 //! ```
 
+pub mod adapters;
+pub mod aggressor;
+pub mod arbitrage;
+pub mod avro;
+pub mod backtest;
+pub mod bandwidth;
+pub mod baseline;
+pub mod benchmark;
+pub mod book;
+pub mod breaker;
+pub mod burst;
+pub mod candles;
+pub mod catchup;
+#[cfg(feature = "chaos")]
+pub mod chaos;
 pub mod client;
+pub mod clock;
+pub mod codec;
+pub mod columnar;
+pub mod control;
+pub mod correlation;
+pub mod crossed;
+pub mod dbn;
+pub mod delta;
+pub mod emulator;
+pub mod enrich;
+pub mod entitlements;
+pub mod eod;
+pub mod features;
+pub mod filter;
+pub mod fixtures;
+#[cfg(feature = "flight")]
+pub mod flight;
+pub mod fx;
+pub mod generator;
+pub mod graph;
+pub mod history;
+pub mod hydrate;
+pub mod iceberg;
+#[cfg(feature = "onnx")]
+pub mod inference;
+pub mod instruments;
+pub mod itch;
+pub mod journal;
+pub mod large_trade;
+pub mod logging;
+pub mod lvc;
+pub mod memory;
+pub mod metrics;
+pub mod momentum;
+pub mod overload;
+pub mod pipeline;
+pub mod positions;
+pub mod probes;
+pub mod qos;
+pub mod quotes;
+pub mod recording;
+pub mod risk;
+pub mod rolling;
+pub mod sampling;
+pub mod sbe;
+pub mod server;
+pub mod shard;
+pub mod simulator;
+pub mod snapshot;
+pub mod stats;
+pub mod strategy;
+pub mod symbology;
+pub mod synthetic;
+pub mod throttle;
 pub mod types;
+pub mod validation;
+pub mod watchlist;
 
-pub use client::{ClientError, MarketDataClient};
+pub use adapters::{
+    Adapter, AlpacaAdapter, BinanceAdapter, BitstampAdapter, CoinbaseAdapter, GeminiAdapter,
+    IexAdapter, JsonBackend, NativeAdapter, OkxAdapter, SbeAdapter,
+};
+pub use aggressor::{AggressorInference, AggressorRule};
+pub use arbitrage::{ArbMonitor, ArbOpportunity};
+pub use avro::{AvroSerializer, MemoryRegistry, SchemaRegistry, SubjectNameStrategy};
+pub use backtest::{Backtest, BacktestContext, BacktestHandler, VirtualClock};
+pub use bandwidth::{BandwidthStats, Usage};
+pub use baseline::{Anomaly, AnomalyMetric, BaselineLearner};
+pub use benchmark::{BenchmarkReport, BenchmarkTracker};
+pub use book::{BookDepth, BookSide, L3Book, L3Order, OrderBook};
+pub use breaker::ParseBreaker;
+pub use burst::{BurstDetector, BurstStats};
+pub use candles::{
+    BarClock, BarRevision, BarSpec, CandleAggregator, FootprintAggregator, RangeBarBuilder,
+    RenkoBuilder,
+};
+pub use catchup::CatchUpSource;
+#[cfg(feature = "chaos")]
+pub use chaos::{ChaosLayer, ChaosStats};
+pub use client::{
+    ClientError, ClientEvent, Health, MarketDataClient, ProcessingMode, RestartPolicy,
+    WaitStrategy,
+};
+pub use clock::{ClockSource, SystemClock, TimestampPrecision};
+pub use codec::{FrameReader, FrameWriter, WireFormat};
+pub use columnar::{QuoteBatch, TradeBatch};
+pub use control::{ControlCommand, ControlHandle};
+pub use correlation::{BetaReport, BetaTracker, CorrelationMatrix, CorrelationTracker, RelativeStrength};
+pub use crossed::{BookCondition, CrossedMarketDetector, CrossedMarketEvent};
+pub use delta::{BookDelta, BookDeltaDecoder, BookDeltaEncoder};
+pub use dbn::{DatabentoLive, DbnReader, DbnRecord};
+pub use emulator::{EmulatedVenue, ExchangeEmulator};
+pub use enrich::{Enriched, EnrichedQuote, EnrichedTrade, Enricher};
+pub use entitlements::{EntitlementFilter, Entitlements, Grant};
+pub use eod::{DailySummary, EodSink, EodSummarizer, JsonLinesSink};
+pub use features::{FeatureBatch, FeatureExtractor, FeatureVector};
+pub use filter::Filter;
+#[cfg(feature = "flight")]
+pub use flight::{BatchKind, FlightQuery, FlightServer};
+pub use fx::FxConverter;
+pub use generator::{SeededRng, SyntheticFeed};
+pub use graph::{GraphHandle, IterSource, MessageSink, Source, StreamGraph};
+pub use history::HistoryBuffer;
+pub use hydrate::{candle_trades, Hydrate};
+pub use iceberg::{IcebergDetector, IcebergSuspected};
+#[cfg(feature = "onnx")]
+pub use inference::{InferenceStage, OnnxModel, Prediction};
+pub use instruments::{IdScheme, Instrument, InstrumentRegistry, InstrumentTagger};
+pub use itch::ItchReader;
+pub use journal::{FsyncPolicy, Journal, JournalFrames};
+pub use large_trade::{LargeTrade, LargeTradeDetector, LargeTradeKind, Threshold};
+pub use logging::{LogConfig, LogFormat};
+pub use lvc::{LastValueCache, SymbolState, SyncHandle, SyncSnapshot};
+pub use memory::{AllocationStats, CountingAllocator, Pool, PoolStats};
+pub use metrics::{load_history, MetricsRecorder, MetricsSink, MetricsSnapshot};
+pub use momentum::{Momentum, MomentumSignal, MomentumTracker};
+pub use overload::OverloadController;
+pub use pipeline::{Pipeline, Stage};
+pub use positions::{PortfolioEvent, PositionTracker, PriceAlert};
+pub use probes::HealthProbes;
+pub use qos::{LatencyPercentiles, QosReport, QosReporter, QosTracker};
+pub use quotes::{BboChangeFilter, MatchedTrade, QuoteAnalytics, QuoteMetrics, TradeQuoteMatcher};
+pub use recording::{
+    BookReconstructor, FileStorage, MemoryStorage, RecordingKey, RecordingReader, RecordingWriter,
+    StorageBackend,
+};
+pub use risk::{BreachKind, RiskBreach, RiskLimit, RiskMonitor};
+pub use rolling::{RollingStats, RollingStatsTracker, RollingWindow};
+pub use sampling::{MidSampler, Sample};
+pub use sbe::SbeSchema;
+pub use server::{FanOutServer, Sequenced, Tenant, TenantUsage};
+pub use shard::{FileLeaseStore, LeaseStore, ShardCoordinator};
+pub use simulator::{Fill, FillSimulator, OrderType, QueueModel};
+pub use snapshot::SnapshotScheduler;
+pub use stats::{StatsEngine, StatsReader};
+pub use strategy::{Strategy, StrategyRunner};
+pub use symbology::{Currency, InstrumentId, Symbol, Venue};
+pub use synthetic::{BasketCalculator, BasketConfig, SyntheticEngine, SyntheticInstrument};
+pub use throttle::{SinkThrottles, Throttle};
 pub use types::{
-    MarketDataMessage, MarketStats, OrderBookSnapshot, PriceLevel, Quote, Trade, TradeSide,
+    BarKind, Candle, ContractSpec, FootprintCandle, FootprintLevel, MarketDataMessage, MarketStats,
+    MessageKind, OrderBookSnapshot, PriceLevel, Quote, Trade, TradeBust, TradeConditions,
+    TradeCorrection, TradeSide, TradeTerms,
 };
+pub use validation::{ValidationFailure, ValidationMode, Validator, Violation};
+pub use watchlist::{WatchlistRow, WatchlistUpdate, Watchlists};
 
 #[cfg(test)]
 mod tests {
@@ -55,12 +279,9 @@ mod tests {
     #[test]
     fn test_quote_calculations() {
         let quote = Quote {
-            symbol: "BTCUSD".to_string(),
-            bid_price: 50000.0,
             bid_size: 1.5,
-            ask_price: 50100.0,
             ask_size: 2.0,
-            timestamp: chrono::Utc::now(),
+            ..Quote::test("BTCUSD", 50000.0, 50100.0)
         };
 
         assert_eq!(quote.spread(), 100.0);
@@ -72,12 +293,8 @@ mod tests {
         let mut stats = MarketStats::new("BTCUSD".to_string());
         
         let trade1 = Trade {
-            symbol: "BTCUSD".to_string(),
-            price: 50000.0,
-            quantity: 1.0,
-            side: TradeSide::Buy,
-            timestamp: chrono::Utc::now(),
             trade_id: "1".to_string(),
+            ..Trade::test("BTCUSD", 50000.0)
         };
         
         stats.update_with_trade(&trade1);
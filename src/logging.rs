@@ -0,0 +1,232 @@
+//! Log output configuration, including structured JSON logs.
+//!
+//! [`LogConfig`] installs the global tracing subscriber. In
+//! [`LogFormat::Json`] every event is written as one JSON object per line
+//! with its level, target, message and fields, ready for ELK or Datadog
+//! ingestion. Log sites tag themselves with an `event` category and, where
+//! known, `venue`, `symbol`, `seq` and `latency_ms` fields; events without
+//! a category are filed under the module that logged them, e.g. `client`.
+//!
+//! ```text
+//! {"timestamp":"2024-01-02T03:04:05.000006Z","level":"WARN","target":"rust_market_data_stream::client::processor","event":"overload","latency_ms":12.5,"message":"Processing over budget, shedding load"}
+//! ```
+
+use crate::client::{ClientError, Result};
+use crate::control::LOG_LEVEL_HANDLE;
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::io::Write;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::reload;
+
+/// How log lines are rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Global tracing subscriber settings
+pub struct LogConfig {
+    level: LevelFilter,
+    format: LogFormat,
+    writer: BoxMakeWriter,
+}
+
+impl LogConfig {
+    pub fn new(level: LevelFilter) -> Self {
+        Self {
+            level,
+            format: LogFormat::Text,
+            writer: BoxMakeWriter::new(std::io::stdout),
+        }
+    }
+
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn json(self) -> Self {
+        self.with_format(LogFormat::Json)
+    }
+
+    /// Write logs somewhere other than stdout
+    pub fn with_writer<W>(mut self, writer: W) -> Self
+    where
+        W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+    {
+        self.writer = BoxMakeWriter::new(writer);
+        self
+    }
+
+    /// Install as the global subscriber; the level can then be changed
+    /// through [`ControlCommand::SetLogLevel`](crate::control::ControlCommand::SetLogLevel)
+    pub fn init(self) -> Result<()> {
+        use tracing_subscriber::prelude::*;
+
+        let (filter, handle) = reload::Layer::new(self.level);
+        let (text, json) = match self.format {
+            LogFormat::Text => (
+                Some(tracing_subscriber::fmt::layer().with_writer(self.writer)),
+                None,
+            ),
+            LogFormat::Json => (None, Some(JsonLayer::new(self.writer))),
+        };
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(text)
+            .with(json)
+            .try_init()
+            .map_err(|e| ClientError::Control(e.to_string()))?;
+
+        let _ = LOG_LEVEL_HANDLE.set(handle);
+        Ok(())
+    }
+}
+
+/// Layer writing each event as a line of JSON
+pub struct JsonLayer<W> {
+    writer: W,
+}
+
+impl<W> JsonLayer<W>
+where
+    W: for<'a> MakeWriter<'a> + 'static,
+{
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<S, W> Layer<S> for JsonLayer<W>
+where
+    S: Subscriber,
+    W: for<'a> MakeWriter<'a> + 'static,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut fields = JsonFields(Map::new());
+        event.record(&mut fields);
+        let mut fields = fields.0;
+
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_string(),
+            Utc::now()
+                .to_rfc3339_opts(SecondsFormat::Micros, true)
+                .into(),
+        );
+        line.insert("level".to_string(), metadata.level().as_str().into());
+        line.insert("target".to_string(), metadata.target().into());
+        let category = fields
+            .remove("event")
+            .unwrap_or_else(|| category(metadata.target()).into());
+        line.insert("event".to_string(), category);
+        line.extend(fields);
+
+        let mut bytes = Value::Object(line).to_string().into_bytes();
+        bytes.push(b'\n');
+        let _ = self.writer.make_writer_for(metadata).write_all(&bytes);
+    }
+}
+
+/// Default category of an event: the top module of this crate that logged
+/// it, or the whole target elsewhere
+fn category(target: &str) -> &str {
+    match target.strip_prefix("rust_market_data_stream::") {
+        Some(path) => path.split("::").next().unwrap_or(path),
+        None => target,
+    }
+}
+
+struct JsonFields(Map<String, Value>);
+
+impl Visit for JsonFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::prelude::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_lines_carry_fields_and_category() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber =
+            tracing_subscriber::registry().with(JsonLayer::new(move || writer.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(
+                target: "rust_market_data_stream::client::processor",
+                venue = "binance",
+                symbol = "BTCUSD",
+                seq = 42u64,
+                latency_ms = 1.5,
+                "Sequence gap"
+            );
+            tracing::info!(event = "connection", "Connected successfully");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["event"], "client");
+        assert_eq!(lines[0]["level"], "WARN");
+        assert_eq!(lines[0]["venue"], "binance");
+        assert_eq!(lines[0]["symbol"], "BTCUSD");
+        assert_eq!(lines[0]["seq"], 42);
+        assert_eq!(lines[0]["latency_ms"], 1.5);
+        assert_eq!(lines[0]["message"], "Sequence gap");
+        assert_eq!(lines[1]["event"], "connection");
+    }
+}
@@ -0,0 +1,237 @@
+//! Last value cache and snapshot-then-live sync for late joiners.
+//!
+//! A subscriber that joins mid-session gets a [`SyncSnapshot`] of the
+//! current state of every symbol followed by the live stream. The cache is
+//! updated and the message broadcast under one lock, so the snapshot and the
+//! receiver share a sequence boundary: every message is either reflected in
+//! the snapshot or delivered on the receiver, never both or neither. With a
+//! [`HistoryBuffer`] attached, late joiners can also get the recent messages
+//! themselves on the same boundary.
+
+use crate::history::HistoryBuffer;
+use crate::types::{MarketDataMessage, MarketStats, OrderBookSnapshot, Quote, Trade};
+use chrono::Duration;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Latest known state of one symbol
+#[derive(Debug, Clone)]
+pub struct SymbolState {
+    pub last_trade: Option<Trade>,
+    /// Latest quote
+    pub bbo: Option<Quote>,
+    pub book: Option<OrderBookSnapshot>,
+    pub stats: MarketStats,
+}
+
+impl SymbolState {
+    fn new(symbol: &str) -> Self {
+        Self {
+            last_trade: None,
+            bbo: None,
+            book: None,
+            stats: MarketStats::new(symbol.to_string()),
+        }
+    }
+}
+
+/// State of every symbol as of message `seq`
+#[derive(Debug, Clone, Default)]
+pub struct SyncSnapshot {
+    /// Messages published before the snapshot was taken; the paired
+    /// receiver starts with message `seq + 1`
+    pub seq: u64,
+    pub symbols: BTreeMap<String, SymbolState>,
+}
+
+impl SyncSnapshot {
+    /// The cached book, quote and last trade of each symbol, in that order,
+    /// to prime a consumer that only understands messages
+    pub fn messages(&self) -> Vec<MarketDataMessage> {
+        let mut out = Vec::new();
+        for state in self.symbols.values() {
+            out.extend(state.book.clone().map(MarketDataMessage::OrderBook));
+            out.extend(state.bbo.clone().map(MarketDataMessage::Quote));
+            out.extend(state.last_trade.clone().map(MarketDataMessage::Trade));
+        }
+        out
+    }
+}
+
+/// Latest trade, quote, book and running stats per symbol
+#[derive(Debug, Clone, Default)]
+pub struct LastValueCache {
+    seq: u64,
+    symbols: BTreeMap<String, SymbolState>,
+}
+
+impl LastValueCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, msg: &MarketDataMessage) {
+        self.seq += 1;
+        match msg {
+            MarketDataMessage::Trade(trade) => {
+                let state = self.state_mut(&trade.symbol);
+                state.stats.update_with_trade(trade);
+                state.last_trade = Some(trade.clone());
+            }
+            MarketDataMessage::Quote(quote) => {
+                self.state_mut(&quote.symbol).bbo = Some(quote.clone());
+            }
+            MarketDataMessage::OrderBook(book) => {
+                self.state_mut(&book.symbol).book = Some(book.clone());
+            }
+            MarketDataMessage::TradeCorrection(correction) => {
+                self.state_mut(&correction.symbol).stats.update(msg);
+            }
+            MarketDataMessage::TradeBust(bust) => self.state_mut(&bust.symbol).stats.update(msg),
+            MarketDataMessage::Heartbeat => {}
+        }
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&SymbolState> {
+        self.symbols.get(symbol)
+    }
+
+    /// Messages seen so far
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn snapshot(&self) -> SyncSnapshot {
+        SyncSnapshot {
+            seq: self.seq,
+            symbols: self.symbols.clone(),
+        }
+    }
+
+    fn state_mut(&mut self, symbol: &str) -> &mut SymbolState {
+        self.symbols
+            .entry(symbol.to_string())
+            .or_insert_with(|| SymbolState::new(symbol))
+    }
+}
+
+impl From<SyncSnapshot> for LastValueCache {
+    /// A cache that carries on from `snapshot`
+    fn from(snapshot: SyncSnapshot) -> Self {
+        Self {
+            seq: snapshot.seq,
+            symbols: snapshot.symbols,
+        }
+    }
+}
+
+/// A broadcast channel paired with the cache of what it has carried
+#[derive(Clone)]
+pub struct SyncHandle {
+    cache: Arc<Mutex<LastValueCache>>,
+    /// Only locked while holding `cache`
+    history: Option<Arc<Mutex<HistoryBuffer>>>,
+    tx: broadcast::Sender<MarketDataMessage>,
+}
+
+impl SyncHandle {
+    pub fn new(tx: broadcast::Sender<MarketDataMessage>) -> Self {
+        Self {
+            cache: Arc::default(),
+            history: None,
+            tx,
+        }
+    }
+
+    /// Also keep recent messages in `history` for
+    /// [`subscribe_with_history`](Self::subscribe_with_history)
+    pub fn with_history(mut self, history: HistoryBuffer) -> Self {
+        self.history = Some(Arc::new(Mutex::new(history)));
+        self
+    }
+
+    /// Cache and broadcast `msg` atomically with respect to
+    /// [`subscribe`](Self::subscribe)
+    pub fn publish(
+        &self,
+        msg: MarketDataMessage,
+    ) -> Result<usize, Box<broadcast::error::SendError<MarketDataMessage>>> {
+        let mut cache = self.cache.lock().unwrap();
+        cache.update(&msg);
+        if let Some(history) = &self.history {
+            history.lock().unwrap().push(msg.clone());
+        }
+        self.tx.send(msg).map_err(Box::new)
+    }
+
+    /// Current state and a receiver for everything published after it
+    pub fn subscribe(&self) -> (SyncSnapshot, broadcast::Receiver<MarketDataMessage>) {
+        let cache = self.cache.lock().unwrap();
+        (cache.snapshot(), self.tx.subscribe())
+    }
+
+    /// Current state, the retained messages from the last `window`, and a
+    /// receiver for everything published after them. The history is empty
+    /// without [`with_history`](Self::with_history).
+    pub fn subscribe_with_history(
+        &self,
+        window: Duration,
+    ) -> (
+        SyncSnapshot,
+        Vec<MarketDataMessage>,
+        broadcast::Receiver<MarketDataMessage>,
+    ) {
+        let cache = self.cache.lock().unwrap();
+        let recent = self
+            .history
+            .as_ref()
+            .map(|history| history.lock().unwrap().last(window))
+            .unwrap_or_default();
+        (cache.snapshot(), recent, self.tx.subscribe())
+    }
+
+    /// Current state of one symbol
+    pub fn get(&self, symbol: &str) -> Option<SymbolState> {
+        self.cache.lock().unwrap().get(symbol).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(symbol: &str, price: f64) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade::test(symbol, price))
+    }
+
+    #[test]
+    fn test_snapshot_then_live_boundary() {
+        let (tx, _) = broadcast::channel(16);
+        let sync = SyncHandle::new(tx);
+        sync.publish(trade("BTCUSD", 100.0)).ok();
+        sync.publish(trade("BTCUSD", 101.0)).ok();
+        sync.publish(MarketDataMessage::Quote(Quote::test(
+            "BTCUSD", 100.5, 101.5,
+        )))
+        .ok();
+
+        let (snapshot, mut rx) = sync.subscribe();
+        sync.publish(trade("ETHUSD", 10.0)).unwrap();
+
+        assert_eq!(snapshot.seq, 3);
+        let btc = &snapshot.symbols["BTCUSD"];
+        assert_eq!(btc.stats.trade_count, 2);
+        assert_eq!(btc.last_trade.as_ref().unwrap().price, 101.0);
+        assert!(matches!(
+            snapshot.messages().as_slice(),
+            [MarketDataMessage::Quote(_), MarketDataMessage::Trade(_)]
+        ));
+        assert!(!snapshot.symbols.contains_key("ETHUSD"));
+        match rx.try_recv().unwrap() {
+            MarketDataMessage::Trade(trade) => assert_eq!(trade.symbol, "ETHUSD"),
+            other => panic!("expected the live trade, got {:?}", other),
+        }
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Object reuse for hot-path buffers and allocation accounting.
+//!
+//! [`Pool`] keeps cleared buffers around so steady-state processing stops
+//! hitting the allocator. [`CountingAllocator`] wraps the system allocator to
+//! report allocation counts and live bytes; install it in the binary with
+//! `#[global_allocator]` to populate [`allocation_stats`].
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A value that can be cleared for reuse while keeping its capacity
+pub trait Recycle {
+    fn recycle(&mut self);
+}
+
+impl<T> Recycle for Vec<T> {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+impl Recycle for String {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+/// Counters for a [`Pool`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Objects handed out from the pool
+    pub reused: u64,
+    /// Objects that had to be freshly allocated
+    pub allocated: u64,
+    /// Objects returned and kept
+    pub returned: u64,
+    /// Objects returned while the pool was full, and freed
+    pub discarded: u64,
+}
+
+/// Thread-safe free list of reusable objects
+#[derive(Debug)]
+pub struct Pool<T> {
+    free: Mutex<Vec<T>>,
+    max_retained: usize,
+    reused: AtomicU64,
+    allocated: AtomicU64,
+    returned: AtomicU64,
+    discarded: AtomicU64,
+}
+
+impl<T: Default + Recycle> Pool<T> {
+    /// Pool keeping at most `max_retained` idle objects
+    pub fn new(max_retained: usize) -> Self {
+        Self {
+            free: Mutex::new(Vec::new()),
+            max_retained,
+            reused: AtomicU64::new(0),
+            allocated: AtomicU64::new(0),
+            returned: AtomicU64::new(0),
+            discarded: AtomicU64::new(0),
+        }
+    }
+
+    /// Take an empty object, reusing a returned one when available
+    pub fn get(&self) -> T {
+        match self.free.lock().unwrap().pop() {
+            Some(value) => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                value
+            }
+            None => {
+                self.allocated.fetch_add(1, Ordering::Relaxed);
+                T::default()
+            }
+        }
+    }
+
+    /// Give an object back for reuse
+    pub fn put(&self, mut value: T) {
+        let mut free = self.free.lock().unwrap();
+        if free.len() >= self.max_retained {
+            self.discarded.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        value.recycle();
+        free.push(value);
+        self.returned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of idle objects currently held
+    pub fn idle(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            reused: self.reused.load(Ordering::Relaxed),
+            allocated: self.allocated.load(Ordering::Relaxed),
+            returned: self.returned.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static DEALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static BYTES_ALLOCATED: AtomicU64 = AtomicU64::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Global allocator wrapper counting allocations made through it.
+///
+/// ```
+/// use rust_market_data_stream::memory::{allocation_stats, CountingAllocator};
+///
+/// #[global_allocator]
+/// static ALLOC: CountingAllocator = CountingAllocator;
+///
+/// fn main() {
+///     let before = allocation_stats();
+///     let buf = vec![0u8; 1024];
+///     assert!(allocation_stats().since(&before).allocations >= 1);
+///     drop(buf);
+/// }
+/// ```
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record_dealloc(layout.size());
+            record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+fn record_alloc(size: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    BYTES_ALLOCATED.fetch_add(size as u64, Ordering::Relaxed);
+    LIVE_BYTES.fetch_add(size, Ordering::Relaxed);
+}
+
+fn record_dealloc(size: usize) {
+    DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    LIVE_BYTES.fetch_sub(size, Ordering::Relaxed);
+}
+
+/// Process-wide allocation counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+    pub allocations: u64,
+    pub deallocations: u64,
+    pub bytes_allocated: u64,
+    pub live_bytes: usize,
+}
+
+impl AllocationStats {
+    /// Counters accumulated since an earlier reading
+    pub fn since(&self, earlier: &AllocationStats) -> AllocationStats {
+        AllocationStats {
+            allocations: self.allocations - earlier.allocations,
+            deallocations: self.deallocations - earlier.deallocations,
+            bytes_allocated: self.bytes_allocated - earlier.bytes_allocated,
+            live_bytes: self.live_bytes,
+        }
+    }
+}
+
+/// Current counters; all zero unless [`CountingAllocator`] is installed
+pub fn allocation_stats() -> AllocationStats {
+    AllocationStats {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        deallocations: DEALLOCATIONS.load(Ordering::Relaxed),
+        bytes_allocated: BYTES_ALLOCATED.load(Ordering::Relaxed),
+        live_bytes: LIVE_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_reuses_capacity() {
+        let pool: Pool<Vec<u64>> = Pool::new(1);
+
+        let mut buf = pool.get();
+        buf.extend(0..100);
+        let capacity = buf.capacity();
+        pool.put(buf);
+        pool.put(Vec::new());
+
+        let buf = pool.get();
+        assert!(buf.is_empty());
+        assert_eq!(buf.capacity(), capacity);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                reused: 1,
+                allocated: 1,
+                returned: 1,
+                discarded: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Persistent snapshots of the client's own operational metrics.
+//!
+//! Prometheus-style counters only show the present. A [`MetricsRecorder`]
+//! samples the QoS, bandwidth, burst and health state of several clients
+//! every period and writes one [`MetricsSnapshot`] per client to a
+//! [`MetricsSink`], such as a [`JsonLinesSink`], building a history of
+//! feed quality that can be analyzed over weeks. Counters in a snapshot
+//! cover the interval since the previous one, so rates compare across
+//! snapshots; latency percentiles and uptime are those of the client's
+//! current QoS window. [`load_history`] reads a JSON lines history back.
+
+use crate::bandwidth::BandwidthStats;
+use crate::burst::BurstDetector;
+use crate::client::{ClientError, MarketDataClient, Result};
+use crate::eod::JsonLinesSink;
+use crate::qos::{LatencyPercentiles, QosTracker};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// Operational metrics of one client over one interval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub client: String,
+    pub timestamp: DateTime<Utc>,
+    /// Seconds since the previous snapshot of the client
+    pub interval: f64,
+    /// No internal actor is down
+    pub healthy: bool,
+    /// Fraction of the current QoS window spent connected
+    pub uptime: f64,
+    pub reconnects: u64,
+    pub gaps: u64,
+    pub frames: u64,
+    pub messages: u64,
+    /// Messages per second over the interval
+    pub message_rate: f64,
+    pub bytes: u64,
+    /// Bytes per second over the interval
+    pub byte_rate: f64,
+    pub parse_errors: u64,
+    pub latency_ms: LatencyPercentiles,
+    pub processing_ms: LatencyPercentiles,
+    pub bursts: u64,
+    /// Messages dropped by a full smoothing queue
+    pub queue_dropped: u64,
+}
+
+/// Destination for metrics snapshots
+pub trait MetricsSink: Send {
+    fn write(&mut self, snapshots: &[MetricsSnapshot]) -> Result<()>;
+}
+
+impl<F: FnMut(&[MetricsSnapshot]) -> Result<()> + Send> MetricsSink for F {
+    fn write(&mut self, snapshots: &[MetricsSnapshot]) -> Result<()> {
+        self(snapshots)
+    }
+}
+
+impl MetricsSink for JsonLinesSink {
+    fn write(&mut self, snapshots: &[MetricsSnapshot]) -> Result<()> {
+        self.append(snapshots)
+    }
+}
+
+/// Read the snapshots a [`JsonLinesSink`] wrote to `path`, oldest first
+pub fn load_history(path: impl AsRef<Path>) -> Result<Vec<MetricsSnapshot>> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| ClientError::Io(format!("{}: {}", path.display(), e)))?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| ClientError::Parse(e.to_string())))
+        .collect()
+}
+
+/// Cumulative counters of the previous snapshot
+#[derive(Debug, Clone, Copy, Default)]
+struct Counters {
+    reconnects: u64,
+    gaps: u64,
+    frames: u64,
+    messages: u64,
+    bytes: u64,
+    parse_errors: u64,
+    bursts: u64,
+    queue_dropped: u64,
+}
+
+/// Handles to one client's metrics
+struct Source {
+    name: String,
+    qos: Arc<Mutex<QosTracker>>,
+    bandwidth: Arc<Mutex<BandwidthStats>>,
+    bursts: Arc<Mutex<BurstDetector>>,
+    failed: Arc<Mutex<Vec<String>>>,
+    last: (Instant, Counters),
+}
+
+impl Source {
+    /// The snapshot since `last`, and the counters it ends at
+    fn snapshot(
+        &self,
+        now: Instant,
+        timestamp: DateTime<Utc>,
+    ) -> (MetricsSnapshot, (Instant, Counters)) {
+        let qos = self.qos.lock().unwrap().report(&self.name, now);
+        let bytes = self.bandwidth.lock().unwrap().connection.bytes;
+        let bursts = self.bursts.lock().unwrap().stats();
+        let counters = Counters {
+            reconnects: qos.reconnects,
+            gaps: qos.gaps,
+            frames: qos.frames,
+            messages: qos.messages,
+            bytes,
+            parse_errors: qos.parse_errors,
+            bursts: bursts.bursts,
+            queue_dropped: bursts.queue_dropped,
+        };
+        let (since, last) = self.last;
+        // QoS counters restart when a reporter rolls the window
+        let delta = |current: u64, previous: u64| current.checked_sub(previous).unwrap_or(current);
+        let interval = now.saturating_duration_since(since).as_secs_f64();
+        let rate = |n: u64| {
+            if interval > 0.0 {
+                n as f64 / interval
+            } else {
+                0.0
+            }
+        };
+        let messages = delta(counters.messages, last.messages);
+        let bytes = delta(counters.bytes, last.bytes);
+        let snapshot = MetricsSnapshot {
+            client: self.name.clone(),
+            timestamp,
+            interval,
+            healthy: self.failed.lock().unwrap().is_empty(),
+            uptime: qos.uptime,
+            reconnects: delta(counters.reconnects, last.reconnects),
+            gaps: delta(counters.gaps, last.gaps),
+            frames: delta(counters.frames, last.frames),
+            messages,
+            message_rate: rate(messages),
+            bytes,
+            byte_rate: rate(bytes),
+            parse_errors: delta(counters.parse_errors, last.parse_errors),
+            latency_ms: qos.latency_ms,
+            processing_ms: qos.processing_ms,
+            bursts: delta(counters.bursts, last.bursts),
+            queue_dropped: delta(counters.queue_dropped, last.queue_dropped),
+        };
+        (snapshot, (now, counters))
+    }
+}
+
+/// Periodic [`MetricsSnapshot`]s of several clients
+pub struct MetricsRecorder {
+    sources: Vec<Source>,
+    sink: Option<Box<dyn MetricsSink>>,
+    tx: broadcast::Sender<MetricsSnapshot>,
+}
+
+impl Default for MetricsRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricsRecorder {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(64);
+        Self {
+            sources: Vec::new(),
+            sink: None,
+            tx,
+        }
+    }
+
+    /// Record the metrics of `client` under `name`
+    pub fn with_client(mut self, name: &str, client: &MarketDataClient) -> Self {
+        let mut source = Source {
+            name: name.to_string(),
+            qos: client.qos_tracker(),
+            bandwidth: client.bandwidth_tracker(),
+            bursts: client.burst_detector(),
+            failed: client.failed_actors(),
+            last: (Instant::now(), Counters::default()),
+        };
+        // Start from the current counters so the first interval is not
+        // everything since the client started
+        source.last = source.snapshot(Instant::now(), Utc::now()).1;
+        self.sources.push(source);
+        self
+    }
+
+    /// Persist every snapshot to `sink`
+    pub fn with_sink(mut self, sink: impl MetricsSink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<MetricsSnapshot> {
+        self.tx.subscribe()
+    }
+
+    /// Snapshot every client, then persist and publish the snapshots. If
+    /// the sink fails, the next snapshots cover this interval too.
+    pub fn record(&mut self) -> Result<Vec<MetricsSnapshot>> {
+        let (now, timestamp) = (Instant::now(), Utc::now());
+        let (snapshots, ends): (Vec<MetricsSnapshot>, Vec<_>) = self
+            .sources
+            .iter()
+            .map(|source| source.snapshot(now, timestamp))
+            .unzip();
+        if let Some(sink) = &mut self.sink {
+            sink.write(&snapshots)?;
+        }
+        for (source, end) in self.sources.iter_mut().zip(ends) {
+            source.last = end;
+        }
+        for snapshot in &snapshots {
+            let _ = self.tx.send(snapshot.clone());
+        }
+        Ok(snapshots)
+    }
+
+    /// Record every `period`, which must not be zero, on a background task
+    pub fn spawn(mut self, period: Duration) -> Result<JoinHandle<()>> {
+        if period.is_zero() {
+            return Err(ClientError::Parse(
+                "metrics period must not be zero".to_string(),
+            ));
+        }
+        Ok(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            // The first tick completes immediately
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.record() {
+                    warn!("Writing metrics snapshots failed: {}", e);
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshots_persist_interval_counters() {
+        let client = MarketDataClient::new("wss://example.com".to_string(), 16);
+        let path = std::env::temp_dir().join(format!("mds-metrics-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let qos = client.qos_tracker();
+        qos.lock().unwrap().record_frame(true, Instant::now());
+        let mut recorder = MetricsRecorder::new()
+            .with_client("binance", &client)
+            .with_sink(JsonLinesSink::new(&path));
+        let mut rx = recorder.subscribe();
+
+        for parsed in [true, true, false] {
+            qos.lock().unwrap().record_frame(parsed, Instant::now());
+        }
+        client.bandwidth_tracker().lock().unwrap().record(512, &[]);
+        let first = recorder.record().unwrap();
+        // The frame before the recorder started is not counted
+        assert_eq!((first[0].frames, first[0].parse_errors), (3, 1));
+        assert_eq!((first[0].bytes, first[0].healthy), (512, true));
+
+        qos.lock().unwrap().record_frame(true, Instant::now());
+        recorder.record().unwrap();
+        assert_eq!(rx.try_recv().unwrap(), first[0]);
+
+        let history = load_history(&path).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!((history[1].frames, history[1].bytes), (1, 0));
+        std::fs::remove_file(&path).unwrap();
+
+        // A failed write leaves its interval to the next snapshot
+        let mut fail = true;
+        let mut flaky = MetricsRecorder::new()
+            .with_client("binance", &client)
+            .with_sink(move |_: &[MetricsSnapshot]| {
+                if std::mem::take(&mut fail) {
+                    return Err(ClientError::Io("disk full".to_string()));
+                }
+                Ok(())
+            });
+        qos.lock().unwrap().record_frame(true, Instant::now());
+        assert!(flaky.record().is_err());
+        qos.lock().unwrap().record_frame(true, Instant::now());
+        assert_eq!(flaky.record().unwrap()[0].frames, 2);
+        assert!(flaky.spawn(Duration::ZERO).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Short-horizon price velocity and momentum signals.
+//!
+//! [`MomentumTracker`] follows each symbol's last trade price and measures
+//! how many ticks it moved over the last horizon (velocity) and how that
+//! compares with the horizon before (acceleration). A symbol's momentum is up
+//! or down while its velocity is at least the threshold, and a
+//! [`MomentumSignal`] is published whenever that state changes, e.g. to
+//! raise an alert or pause execution while the price runs.
+
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Trade};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Momentum {
+    Up,
+    Down,
+    #[default]
+    Flat,
+}
+
+/// A symbol's momentum changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MomentumSignal {
+    pub symbol: Symbol,
+    pub momentum: Momentum,
+    /// Signed move over the horizon, in ticks
+    pub ticks: f64,
+    /// Ticks per second over the horizon
+    pub velocity: f64,
+    /// Change in velocity from the previous horizon, in ticks per second
+    /// squared
+    pub acceleration: f64,
+    pub price: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SymbolState {
+    /// `(time, price)`, reaching back just past two horizons
+    prices: VecDeque<(DateTime<Utc>, f64)>,
+    momentum: Momentum,
+}
+
+impl SymbolState {
+    /// Last price at or before `at`
+    fn price_at(&self, at: DateTime<Utc>) -> Option<f64> {
+        self.prices
+            .iter()
+            .rev()
+            .find(|(ts, _)| *ts <= at)
+            .map(|(_, price)| *price)
+    }
+}
+
+/// Tracks price velocity per symbol and publishes momentum changes to
+/// receivers from [`subscribe`](Self::subscribe)
+pub struct MomentumTracker {
+    horizon: Duration,
+    threshold: f64,
+    tick_size: f64,
+    tick_sizes: HashMap<Symbol, f64>,
+    symbols: HashMap<Symbol, SymbolState>,
+    tx: broadcast::Sender<MomentumSignal>,
+}
+
+impl MomentumTracker {
+    /// Momentum over `horizon` once the price moves at least `threshold`
+    /// ticks per second, with a default tick of `tick_size`
+    pub fn new(horizon: Duration, threshold: f64, tick_size: f64) -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            horizon,
+            threshold,
+            tick_size,
+            tick_sizes: HashMap::new(),
+            symbols: HashMap::new(),
+            tx,
+        }
+    }
+
+    /// Minimum tick of `symbol` when it differs from the default
+    pub fn with_tick_size(mut self, symbol: &str, tick_size: f64) -> Self {
+        self.tick_sizes.insert(symbol.into(), tick_size);
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<MomentumSignal> {
+        self.tx.subscribe()
+    }
+
+    /// Current momentum of `symbol`
+    pub fn momentum(&self, symbol: &str) -> Momentum {
+        self.symbols
+            .get(symbol)
+            .map(|state| state.momentum)
+            .unwrap_or_default()
+    }
+
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Option<MomentumSignal> {
+        match msg {
+            MarketDataMessage::Trade(trade) => self.on_trade(trade),
+            _ => None,
+        }
+    }
+
+    /// Take a trade's price, returning the signal if momentum changed
+    pub fn on_trade(&mut self, trade: &Trade) -> Option<MomentumSignal> {
+        let tick_size = self
+            .tick_sizes
+            .get(&trade.symbol)
+            .copied()
+            .unwrap_or(self.tick_size);
+        let state = self.symbols.entry(trade.symbol).or_default();
+        let now = trade.timestamp;
+        state.prices.push_back((now, trade.price));
+        // Keep the price prevailing two horizons ago
+        let oldest = now - self.horizon * 2;
+        while state.prices.len() > 1 && state.prices[1].0 <= oldest {
+            state.prices.pop_front();
+        }
+
+        let seconds = self.horizon.num_microseconds()? as f64 / 1e6;
+        let then = state.price_at(now - self.horizon)?;
+        let ticks = (trade.price - then) / tick_size;
+        let velocity = ticks / seconds;
+        let acceleration = state
+            .price_at(oldest)
+            .map(|before| (velocity - (then - before) / tick_size / seconds) / seconds)
+            .unwrap_or(0.0);
+
+        let momentum = if velocity >= self.threshold {
+            Momentum::Up
+        } else if velocity <= -self.threshold {
+            Momentum::Down
+        } else {
+            Momentum::Flat
+        };
+        if momentum == state.momentum {
+            return None;
+        }
+        state.momentum = momentum;
+        let signal = MomentumSignal {
+            symbol: trade.symbol,
+            momentum,
+            ticks,
+            velocity,
+            acceleration,
+            price: trade.price,
+            timestamp: now,
+        };
+        let _ = self.tx.send(signal.clone());
+        Some(signal)
+    }
+}
+
+impl Stage for MomentumTracker {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn trade(price: f64, ms: i64) -> Trade {
+        Trade {
+            timestamp: Utc.timestamp_millis_opt(1_700_000_000_000 + ms).unwrap(),
+            ..Trade::test("BTCUSD", price)
+        }
+    }
+
+    #[test]
+    fn test_momentum_changes() {
+        // 5 ticks of 0.5 per second over 1s horizons
+        let mut tracker = MomentumTracker::new(Duration::seconds(1), 5.0, 0.5);
+        let mut rx = tracker.subscribe();
+        assert!(tracker.on_trade(&trade(100.0, 0)).is_none());
+        assert!(tracker.on_trade(&trade(100.5, 1000)).is_none());
+
+        // 6 ticks in the next second after 1 in the one before
+        let signal = tracker.on_trade(&trade(103.5, 2000)).unwrap();
+        assert_eq!(signal.momentum, Momentum::Up);
+        assert_eq!(
+            (signal.ticks, signal.velocity, signal.acceleration),
+            (6.0, 6.0, 5.0)
+        );
+        assert_eq!(rx.try_recv().unwrap(), signal);
+
+        assert!(tracker.on_trade(&trade(103.0, 2200)).is_none());
+        let signal = tracker.on_trade(&trade(100.0, 2500)).unwrap();
+        assert_eq!((signal.momentum, signal.ticks), (Momentum::Flat, -1.0));
+        let signal = tracker.on_trade(&trade(97.0, 3500)).unwrap();
+        assert_eq!((signal.momentum, signal.velocity), (Momentum::Down, -6.0));
+        assert_eq!(tracker.momentum("BTCUSD"), Momentum::Down);
+    }
+}
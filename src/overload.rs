@@ -0,0 +1,256 @@
+//! Load shedding when processing falls behind the feed.
+//!
+//! [`OverloadController`] watches the time from reading a frame off the
+//! socket to dispatching its messages. Once a frame takes longer than the
+//! budget it sheds the least valuable fidelity: quotes are conflated to the
+//! latest per symbol at a fixed interval and order books are cut to their
+//! top levels. Trades are never shed. Full fidelity returns after a whole
+//! window in which every frame made the budget, so shedding does not flap
+//! on and off with each frame.
+
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Quote};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Change of overload state
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverloadTransition {
+    /// A frame took `latency` from receipt to dispatch, over the budget
+    Active { latency: Duration },
+    /// Every frame of the last window made the budget
+    Cleared,
+}
+
+/// Sheds quote and book detail while ingest-to-dispatch latency is over
+/// budget
+#[derive(Debug, Clone)]
+pub struct OverloadController {
+    budget: Duration,
+    window: Duration,
+    max_depth: usize,
+    conflation: Duration,
+    active: bool,
+    window_start: Option<Instant>,
+    over_budget: bool,
+    /// Latest quote per symbol held back by conflation
+    pending: HashMap<Symbol, Quote>,
+    last_quote: HashMap<Symbol, Instant>,
+}
+
+impl OverloadController {
+    /// Shed load once a frame takes longer than `budget` to dispatch
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            window: Duration::from_secs(1),
+            max_depth: 10,
+            conflation: Duration::from_millis(100),
+            active: false,
+            window_start: None,
+            over_budget: false,
+            pending: HashMap::new(),
+            last_quote: HashMap::new(),
+        }
+    }
+
+    /// Restore full fidelity after `window` within budget (default 1s)
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Book levels per side kept while overloaded (default 10)
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Release at most one quote per symbol per `interval` while overloaded
+    /// (default 100ms)
+    pub fn with_conflation(mut self, interval: Duration) -> Self {
+        self.conflation = interval;
+        self
+    }
+
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Record how long a frame took from receipt to dispatch
+    pub fn record(&mut self, latency: Duration, now: Instant) -> Option<OverloadTransition> {
+        if latency > self.budget {
+            self.over_budget = true;
+            if !self.active {
+                self.active = true;
+                self.window_start = Some(now);
+                self.over_budget = false;
+                return Some(OverloadTransition::Active { latency });
+            }
+        }
+
+        let start = *self.window_start.get_or_insert(now);
+        if now.saturating_duration_since(start) < self.window {
+            return None;
+        }
+        let transition = (self.active && !self.over_budget).then(|| {
+            self.active = false;
+            OverloadTransition::Cleared
+        });
+        self.window_start = Some(now);
+        self.over_budget = false;
+        transition
+    }
+
+    /// Shed detail from the messages of one frame. Quotes held back while
+    /// overloaded are released once due, or all at once ahead of the frame
+    /// after clearing.
+    pub fn shed(&mut self, msgs: &mut Vec<MarketDataMessage>, now: Instant) {
+        if !self.active {
+            // Held quotes are older than the frame's own, so they go first
+            // and only for symbols the frame does not quote again
+            for msg in msgs.iter() {
+                if let MarketDataMessage::Quote(quote) = msg {
+                    self.pending.remove(&quote.symbol);
+                }
+            }
+            if !self.pending.is_empty() {
+                let held: Vec<MarketDataMessage> = self
+                    .pending
+                    .drain()
+                    .map(|(_, q)| MarketDataMessage::Quote(q))
+                    .collect();
+                msgs.splice(0..0, held);
+            }
+            return;
+        }
+        msgs.retain_mut(|msg| match msg {
+            MarketDataMessage::Quote(quote) => {
+                self.pending.insert(quote.symbol, quote.clone());
+                false
+            }
+            MarketDataMessage::OrderBook(book) => {
+                book.bids.truncate(self.max_depth);
+                book.asks.truncate(self.max_depth);
+                true
+            }
+            _ => true,
+        });
+
+        let conflation = self.conflation;
+        let last_quote = &mut self.last_quote;
+        let due: Vec<Symbol> = self
+            .pending
+            .keys()
+            .filter(|symbol| {
+                last_quote
+                    .get(*symbol)
+                    .is_none_or(|at| now.saturating_duration_since(*at) >= conflation)
+            })
+            .cloned()
+            .collect();
+        for symbol in due {
+            let quote = self.pending.remove(&symbol).unwrap();
+            last_quote.insert(symbol, now);
+            msgs.push(MarketDataMessage::Quote(quote));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{OrderBookSnapshot, PriceLevel};
+    use chrono::Utc;
+
+    fn quote(bid: f64) -> MarketDataMessage {
+        MarketDataMessage::Quote(Quote::test("BTCUSD", bid, bid + 1.0))
+    }
+
+    #[test]
+    fn test_sheds_while_over_budget() {
+        let mut overload = OverloadController::new(Duration::from_millis(5))
+            .with_max_depth(1)
+            .with_window(Duration::from_secs(1));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(overload.record(Duration::from_millis(1), at(0)), None);
+        assert_eq!(
+            overload.record(Duration::from_millis(8), at(10)),
+            Some(OverloadTransition::Active {
+                latency: Duration::from_millis(8)
+            })
+        );
+
+        let level = |price| PriceLevel {
+            price,
+            size: 1.0,
+            num_orders: 1,
+        };
+        let mut msgs = vec![
+            quote(100.0),
+            quote(101.0),
+            MarketDataMessage::OrderBook(OrderBookSnapshot {
+                symbol: "BTCUSD".into(),
+                bids: vec![level(100.0), level(99.0)],
+                asks: vec![level(101.0), level(102.0)],
+                timestamp: Utc::now(),
+                instrument_id: None,
+                contract: None,
+                received: None,
+            }),
+        ];
+        overload.shed(&mut msgs, at(10));
+        assert!(matches!(&msgs[0], MarketDataMessage::OrderBook(b) if b.bids.len() == 1));
+        assert!(matches!(&msgs[1], MarketDataMessage::Quote(q) if q.bid_price == 101.0));
+
+        // Within the conflation interval the quote is held back
+        let mut msgs = vec![quote(102.0)];
+        overload.shed(&mut msgs, at(50));
+        assert!(msgs.is_empty());
+
+        // A full window within budget restores fidelity and the held quote
+        assert_eq!(overload.record(Duration::from_millis(1), at(500)), None);
+        assert_eq!(
+            overload.record(Duration::from_millis(1), at(1010)),
+            Some(OverloadTransition::Cleared)
+        );
+        let mut msgs = Vec::new();
+        overload.shed(&mut msgs, at(1010));
+        assert!(matches!(&msgs[..], [MarketDataMessage::Quote(q)] if q.bid_price == 102.0));
+    }
+
+    #[test]
+    fn test_held_quotes_do_not_overwrite_fresh_ones() {
+        let mut overload = OverloadController::new(Duration::from_millis(5));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        overload.record(Duration::from_millis(8), at(0));
+
+        let eth = || MarketDataMessage::Quote(Quote::test("ETHUSD", 10.0, 11.0));
+        let mut msgs = vec![quote(100.0), eth()];
+        overload.shed(&mut msgs, at(0));
+        let mut msgs = vec![quote(101.0), eth()];
+        overload.shed(&mut msgs, at(50));
+        assert!(msgs.is_empty());
+
+        assert_eq!(
+            overload.record(Duration::from_millis(1), at(1000)),
+            Some(OverloadTransition::Cleared)
+        );
+        // The frame that clears overload quotes BTCUSD afresh: the held
+        // BTCUSD quote is dropped, the held ETHUSD one goes ahead of it
+        let mut msgs = vec![quote(102.0)];
+        overload.shed(&mut msgs, at(1000));
+        assert!(matches!(
+            &msgs[..],
+            [MarketDataMessage::Quote(eth), MarketDataMessage::Quote(btc)]
+                if eth.symbol == "ETHUSD" && btc.bid_price == 102.0
+        ));
+    }
+}
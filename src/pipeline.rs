@@ -0,0 +1,86 @@
+//! Processing stages applied to messages before they are broadcast.
+//!
+//! A [`Stage`] may pass a message through, drop it, rewrite it or emit extra
+//! derived messages. Stages run in the order they were added to a
+//! [`Pipeline`].
+//!
+//! Analytics like [`CorrelationTracker`](crate::correlation::CorrelationTracker)
+//! or [`RiskMonitor`](crate::risk::RiskMonitor) are stages too, but only
+//! observe: they forward every message unchanged and publish their results
+//! on a broadcast channel of their own.
+
+use crate::types::MarketDataMessage;
+
+/// A single message transformation step
+pub trait Stage: Send {
+    /// Process one message, pushing zero or more messages to `out`
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>);
+}
+
+/// Ordered chain of stages
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+    scratch: Vec<MarketDataMessage>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, stage: impl Stage + 'static) {
+        self.stages.push(Box::new(stage));
+    }
+
+    pub fn with_stage(mut self, stage: impl Stage + 'static) -> Self {
+        self.push(stage);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Run `msg` through every stage, appending the results to `out`
+    pub fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        let start = out.len();
+        out.push(msg);
+
+        for stage in self.stages.iter_mut() {
+            self.scratch.extend(out.drain(start..));
+            for msg in self.scratch.drain(..) {
+                stage.process(msg, out);
+            }
+        }
+    }
+}
+
+impl Stage for Pipeline {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        Pipeline::process(self, msg, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct DropHeartbeats;
+
+    impl Stage for DropHeartbeats {
+        fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+            if !matches!(msg, MarketDataMessage::Heartbeat) {
+                out.push(msg);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stage_can_drop_messages() {
+        let mut pipeline = Pipeline::new().with_stage(DropHeartbeats);
+        let mut out = Vec::new();
+        pipeline.process(MarketDataMessage::Heartbeat, &mut out);
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,364 @@
+//! Mark-to-market of registered positions.
+//!
+//! A [`PositionTracker`] holds a signed quantity and average entry price
+//! per symbol and revalues them from the stream: at the quote or book mid,
+//! or the last trade for symbols without a recent two-sided market. Each
+//! revaluation is published as a [`PositionMark`] with unrealized PnL and
+//! exposure, and price alerts set on a position fire once when the mark
+//! crosses their level, re-arming when it crosses back. Values of tagged
+//! derivatives apply the contract multiplier.
+
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Side of a level a price alert watches for
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceAlert {
+    /// The mark reached the level from below
+    Above(f64),
+    /// The mark reached the level from above
+    Below(f64),
+}
+
+impl PriceAlert {
+    fn crossed(&self, mark: f64) -> bool {
+        match *self {
+            PriceAlert::Above(level) => mark >= level,
+            PriceAlert::Below(level) => mark <= level,
+        }
+    }
+}
+
+/// A position revalued at the current mark
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionMark {
+    pub symbol: Symbol,
+    /// Signed quantity, negative when short
+    pub quantity: f64,
+    pub average_price: f64,
+    pub mark: f64,
+    pub unrealized_pnl: f64,
+    /// Signed market value of the position
+    pub exposure: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A position's mark crossed one of its alert levels
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionAlert {
+    pub symbol: Symbol,
+    pub alert: PriceAlert,
+    pub mark: f64,
+    pub unrealized_pnl: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Published by a [`PositionTracker`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum PortfolioEvent {
+    Mark(PositionMark),
+    Alert(PositionAlert),
+}
+
+/// Totals over the marked positions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PortfolioSummary {
+    pub positions: usize,
+    pub unrealized_pnl: f64,
+    /// Sum of absolute exposures
+    pub gross_exposure: f64,
+    /// Longs minus shorts
+    pub net_exposure: f64,
+}
+
+#[derive(Debug, Clone)]
+struct Position {
+    quantity: f64,
+    average_price: f64,
+    multiplier: f64,
+    /// Alerts with whether each is currently crossed
+    alerts: Vec<(PriceAlert, bool)>,
+    mark: Option<PositionMark>,
+}
+
+impl Position {
+    fn revalue(&mut self, symbol: Symbol, mark: f64, timestamp: DateTime<Utc>) -> &PositionMark {
+        let exposure = self.quantity * mark * self.multiplier;
+        self.mark.insert(PositionMark {
+            symbol,
+            quantity: self.quantity,
+            average_price: self.average_price,
+            mark,
+            unrealized_pnl: exposure - self.quantity * self.average_price * self.multiplier,
+            exposure,
+            timestamp,
+        })
+    }
+}
+
+/// Marks registered positions to market and publishes
+/// [`PortfolioEvent`]s.
+///
+/// Positions are marked at the mid of two-sided markets, or else at the last
+/// trade, and each mark goes to receivers from [`subscribe`](Self::subscribe).
+/// A mid older than the maximum quote age at a trade's time no longer
+/// holds the trade back, so a quote feed that went quiet does not leave a
+/// position at a stale mark.
+pub struct PositionTracker {
+    positions: HashMap<Symbol, Position>,
+    /// Time of the last two-sided market of positions' symbols, marked at
+    /// the mid rather than trades while recent
+    quoted: HashMap<Symbol, DateTime<Utc>>,
+    max_quote_age: Duration,
+    tx: broadcast::Sender<PortfolioEvent>,
+}
+
+impl Default for PositionTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PositionTracker {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            positions: HashMap::new(),
+            quoted: HashMap::new(),
+            max_quote_age: Duration::seconds(5),
+            tx,
+        }
+    }
+
+    /// Mark at trades once the last two-sided market is older than
+    /// `max_age` at the trade's time; five seconds by default
+    pub fn with_max_quote_age(mut self, max_age: Duration) -> Self {
+        self.max_quote_age = max_age;
+        self
+    }
+
+    pub fn with_position(mut self, symbol: &str, quantity: f64, average_price: f64) -> Self {
+        self.set_position(symbol, quantity, average_price);
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PortfolioEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Register or replace the position in `symbol`, keeping its alerts;
+    /// `quantity` is negative for shorts
+    pub fn set_position(&mut self, symbol: &str, quantity: f64, average_price: f64) {
+        let position = self
+            .positions
+            .entry(symbol.into())
+            .or_insert_with(|| Position {
+                quantity,
+                average_price,
+                multiplier: 1.0,
+                alerts: Vec::new(),
+                mark: None,
+            });
+        position.quantity = quantity;
+        position.average_price = average_price;
+        if let Some(last) = position.mark.clone() {
+            position.revalue(last.symbol, last.mark, last.timestamp);
+        }
+    }
+
+    pub fn remove_position(&mut self, symbol: &str) {
+        self.positions.remove(symbol);
+        self.quoted.remove(symbol);
+    }
+
+    /// Alert when the mark of `symbol`'s position crosses a level; ignored
+    /// without a registered position
+    pub fn add_alert(&mut self, symbol: &str, alert: PriceAlert) {
+        if let Some(position) = self.positions.get_mut(symbol) {
+            let crossed = position
+                .mark
+                .as_ref()
+                .is_some_and(|mark| alert.crossed(mark.mark));
+            position.alerts.push((alert, crossed));
+        }
+    }
+
+    /// The position in `symbol` at its last mark
+    pub fn position(&self, symbol: &str) -> Option<&PositionMark> {
+        self.positions.get(symbol)?.mark.as_ref()
+    }
+
+    /// Totals over the positions marked so far
+    pub fn summary(&self) -> PortfolioSummary {
+        self.positions
+            .values()
+            .filter_map(|position| position.mark.as_ref())
+            .fold(PortfolioSummary::default(), |mut summary, mark| {
+                summary.positions += 1;
+                summary.unrealized_pnl += mark.unrealized_pnl;
+                summary.gross_exposure += mark.exposure.abs();
+                summary.net_exposure += mark.exposure;
+                summary
+            })
+    }
+
+    /// Feed a message, returning the events of the position it revalues
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<PortfolioEvent> {
+        let Some(symbol) = msg
+            .symbol()
+            .filter(|symbol| self.positions.contains_key(symbol))
+        else {
+            return Vec::new();
+        };
+        let mark = match msg {
+            MarketDataMessage::Quote(quote) => {
+                let two_sided = quote.bid_price > 0.0 && quote.ask_price > 0.0;
+                self.market(
+                    symbol,
+                    two_sided.then(|| quote.mid_price()),
+                    quote.timestamp,
+                )
+            }
+            MarketDataMessage::OrderBook(book) => {
+                self.market(symbol, book.mid_price(), book.timestamp)
+            }
+            MarketDataMessage::Trade(trade) => {
+                let quoted = self.quoted.get(&symbol).is_some_and(|at| {
+                    trade.timestamp.signed_duration_since(*at) <= self.max_quote_age
+                });
+                (!quoted).then_some(trade.price)
+            }
+            _ => None,
+        };
+        let Some(mark) = mark else {
+            return Vec::new();
+        };
+        let position = self.positions.get_mut(&symbol).unwrap();
+        if let Some(contract) = msg.contract() {
+            position.multiplier = contract.multiplier;
+        }
+        let timestamp = msg.timestamp().unwrap_or_else(Utc::now);
+        let marked = position.revalue(symbol, mark, timestamp).clone();
+
+        let mut events = Vec::new();
+        for (alert, crossed) in &mut position.alerts {
+            let now = alert.crossed(mark);
+            if now && !*crossed {
+                events.push(PortfolioEvent::Alert(PositionAlert {
+                    symbol,
+                    alert: *alert,
+                    mark,
+                    unrealized_pnl: marked.unrealized_pnl,
+                    timestamp,
+                }));
+            }
+            *crossed = now;
+        }
+        events.insert(0, PortfolioEvent::Mark(marked));
+        for event in &events {
+            let _ = self.tx.send(event.clone());
+        }
+        events
+    }
+
+    /// Note whether `symbol` has a two-sided market as of `at`, passing on
+    /// its mid
+    fn market(&mut self, symbol: Symbol, mid: Option<f64>, at: DateTime<Utc>) -> Option<f64> {
+        match mid {
+            Some(_) => self.quoted.insert(symbol, at),
+            None => self.quoted.remove(&symbol),
+        };
+        mid
+    }
+}
+
+impl Stage for PositionTracker {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ContractSpec, Quote, Trade};
+
+    #[test]
+    fn test_positions_marked_with_alerts() {
+        let trade = |symbol: &str, price: f64| {
+            MarketDataMessage::Trade(Trade {
+                contract: (symbol == "ESZ4").then(|| ContractSpec::perpetual(50.0)),
+                ..Trade::test(symbol, price)
+            })
+        };
+        let mut tracker = PositionTracker::new()
+            .with_position("BTCUSD", 2.0, 100.0)
+            .with_position("ESZ4", -1.0, 5000.0);
+        tracker.add_alert("BTCUSD", PriceAlert::Above(110.0));
+        let mut rx = tracker.subscribe();
+
+        assert_eq!(tracker.on_message(&trade("BTCUSD", 105.0)).len(), 1);
+        let events = tracker.on_message(&trade("BTCUSD", 111.0));
+        assert!(matches!(&events[1], PortfolioEvent::Alert(alert) if alert.mark == 111.0));
+        // Still above the level, so no repeat
+        assert_eq!(tracker.on_message(&trade("BTCUSD", 112.0)).len(), 1);
+        assert!(matches!(rx.try_recv(), Ok(PortfolioEvent::Mark(_))));
+
+        // Quoted symbols are marked at the mid, not trades
+        let quote = MarketDataMessage::Quote(Quote::test("BTCUSD", 107.0, 109.0));
+        tracker.on_message(&quote);
+        assert!(tracker.on_message(&trade("BTCUSD", 120.0)).is_empty());
+        assert_eq!(tracker.position("BTCUSD").unwrap().unrealized_pnl, 16.0);
+
+        tracker.on_message(&trade("ESZ4", 4990.0));
+        let es = tracker.position("ESZ4").unwrap();
+        assert_eq!((es.unrealized_pnl, es.exposure), (500.0, -249_500.0));
+
+        let summary = tracker.summary();
+        assert_eq!(summary.positions, 2);
+        assert_eq!(summary.unrealized_pnl, 516.0);
+        assert_eq!(summary.gross_exposure, 216.0 + 249_500.0);
+    }
+
+    #[test]
+    fn test_trades_mark_once_quotes_go_quiet() {
+        let t0 = Utc::now();
+        let trade = |price, secs| {
+            MarketDataMessage::Trade(Trade {
+                timestamp: t0 + Duration::seconds(secs),
+                ..Trade::test("BTCUSD", price)
+            })
+        };
+        let mut tracker = PositionTracker::new()
+            .with_position("BTCUSD", 1.0, 100.0)
+            .with_max_quote_age(Duration::seconds(2));
+        tracker.on_message(&MarketDataMessage::Quote(Quote {
+            timestamp: t0,
+            ..Quote::test("BTCUSD", 107.0, 109.0)
+        }));
+        assert!(tracker.on_message(&trade(120.0, 1)).is_empty());
+        assert_eq!(tracker.on_message(&trade(121.0, 3)).len(), 1);
+        assert_eq!(tracker.position("BTCUSD").unwrap().mark, 121.0);
+
+        // A one-sided quote leaves trades to mark at once
+        tracker.on_message(&MarketDataMessage::Quote(Quote {
+            timestamp: t0 + Duration::seconds(4),
+            ..Quote::test("BTCUSD", 0.0, 109.0)
+        }));
+        assert_eq!(tracker.on_message(&trade(122.0, 4)).len(), 1);
+
+        tracker.on_message(&MarketDataMessage::Quote(Quote::test("ETHUSD", 9.0, 11.0)));
+        tracker.remove_position("BTCUSD");
+        assert!(tracker.quoted.is_empty());
+    }
+}
@@ -0,0 +1,356 @@
+//! Liveness and readiness probes over HTTP.
+//!
+//! [`HealthProbes`] follows a client's lifecycle events and messages and
+//! answers `GET /healthz` and `GET /readyz` for orchestrators such as
+//! Kubernetes. The streamer is live while its client has not stopped and
+//! no internal actor has failed for good; restarting the process is the
+//! only remedy for either. It is ready once connected and a first message
+//! has arrived since the last subscription, and stays ready while messages
+//! keep arriving within the staleness limit, its sink actors (router,
+//! batches, smoother) are running and none dropped messages within that
+//! limit.
+//!
+//! Responses are `200` or `503` with a JSON body listing the reasons for
+//! failing, e.g. `{"ok":false,"reasons":["stale for 12.0s"]}`.
+
+use crate::client::{ClientError, ClientEvent, MarketDataClient, Result};
+use crate::types::MarketDataMessage;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tracing::{debug, info};
+
+/// Outcome of one probe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProbeStatus {
+    pub ok: bool,
+    pub reasons: Vec<String>,
+}
+
+impl ProbeStatus {
+    fn from_reasons(reasons: Vec<String>) -> Self {
+        Self {
+            ok: reasons.is_empty(),
+            reasons,
+        }
+    }
+}
+
+/// What the probes know of the client, updated from its events and messages
+#[derive(Debug, Clone, Default)]
+pub struct ProbeState {
+    connected: bool,
+    stopped: bool,
+    /// Whether a message has arrived since the last connect
+    primed: bool,
+    last_message: Option<Instant>,
+    /// Sink actor that last dropped messages, and when
+    sink_lag: Option<(String, Instant)>,
+}
+
+/// How long a probe connection may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Actors delivering messages to subscribers and batch consumers
+const SINK_ACTORS: [&str; 3] = ["router", "batches", "smoother"];
+
+impl ProbeState {
+    pub fn on_event(&mut self, event: &ClientEvent) {
+        match event {
+            ClientEvent::Connecting => self.stopped = false,
+            ClientEvent::Connected => {
+                self.connected = true;
+                self.primed = false;
+            }
+            ClientEvent::Disconnected { .. } => self.connected = false,
+            ClientEvent::Stopped => {
+                self.connected = false;
+                self.stopped = true;
+            }
+            ClientEvent::SinkLagging { sink, .. } => {
+                self.sink_lag = Some((sink.clone(), Instant::now()))
+            }
+            _ => {}
+        }
+    }
+
+    pub fn on_message(&mut self, now: Instant) {
+        self.primed = true;
+        self.last_message = Some(now);
+    }
+
+    pub fn liveness(&self, failed: &[String]) -> ProbeStatus {
+        let mut reasons = Vec::new();
+        if self.stopped {
+            reasons.push("client stopped".to_string());
+        }
+        for actor in failed {
+            reasons.push(format!("{} actor failed", actor));
+        }
+        ProbeStatus::from_reasons(reasons)
+    }
+
+    /// Ready to serve data, given the actors that failed for good
+    pub fn readiness(
+        &self,
+        now: Instant,
+        max_staleness: Duration,
+        failed: &[String],
+    ) -> ProbeStatus {
+        let mut reasons = Vec::new();
+        if !self.connected {
+            reasons.push("not connected".to_string());
+        } else if !self.primed {
+            reasons.push("no message since subscribing".to_string());
+        } else if let Some(last) = self.last_message {
+            let silent = now.saturating_duration_since(last);
+            if silent > max_staleness {
+                reasons.push(format!("stale for {:.1}s", silent.as_secs_f64()));
+            }
+        }
+        for actor in failed {
+            if SINK_ACTORS.contains(&actor.as_str()) {
+                reasons.push(format!("{} sink failed", actor));
+            }
+        }
+        if let Some((sink, at)) = &self.sink_lag {
+            if now.saturating_duration_since(*at) <= max_staleness {
+                reasons.push(format!("{} sink dropping messages", sink));
+            }
+        }
+        ProbeStatus::from_reasons(reasons)
+    }
+}
+
+/// `/healthz` and `/readyz` endpoints for one client
+pub struct HealthProbes {
+    probes: Probes,
+    events: broadcast::Receiver<ClientEvent>,
+    messages: broadcast::Receiver<MarketDataMessage>,
+}
+
+impl HealthProbes {
+    /// Probe `client`; create before starting it so the first connection
+    /// is seen
+    pub fn new(client: &MarketDataClient) -> Self {
+        Self {
+            probes: Probes {
+                state: Arc::default(),
+                failed: client.failed_actors(),
+                max_staleness: Duration::from_secs(30),
+                request_timeout: REQUEST_TIMEOUT,
+            },
+            events: client.events(),
+            messages: client.subscribe(),
+        }
+    }
+
+    /// Report not ready after `max_staleness` without a message (default
+    /// 30s)
+    pub fn with_max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.probes.max_staleness = max_staleness;
+        self
+    }
+
+    /// Track the client and answer probes on `listener` until it fails
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        if let Ok(addr) = listener.local_addr() {
+            info!("Health probes listening on {}", addr);
+        }
+        let HealthProbes {
+            probes,
+            mut events,
+            mut messages,
+        } = self;
+        let state = Arc::clone(&probes.state);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) => state.lock().unwrap().on_event(&event),
+                        Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    // Lagging still means messages are flowing
+                    msg = messages.recv() => match msg {
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                            state.lock().unwrap().on_message(Instant::now())
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                }
+            }
+        });
+
+        let probes = Arc::new(probes);
+        loop {
+            let (stream, peer) = listener
+                .accept()
+                .await
+                .map_err(|e| ClientError::Connection(e.to_string()))?;
+            let probes = Arc::clone(&probes);
+            tokio::spawn(async move {
+                if let Err(e) = probes.respond(stream).await {
+                    debug!("Health probe from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+struct Probes {
+    state: Arc<Mutex<ProbeState>>,
+    failed: Arc<Mutex<Vec<String>>>,
+    max_staleness: Duration,
+    request_timeout: Duration,
+}
+
+impl Probes {
+    fn liveness(&self) -> ProbeStatus {
+        let failed = self.failed.lock().unwrap().clone();
+        self.state.lock().unwrap().liveness(&failed)
+    }
+
+    fn readiness(&self) -> ProbeStatus {
+        let failed = self.failed.lock().unwrap().clone();
+        self.state
+            .lock()
+            .unwrap()
+            .readiness(Instant::now(), self.max_staleness, &failed)
+    }
+
+    async fn respond(&self, mut stream: TcpStream) -> Result<()> {
+        let io = |e: std::io::Error| ClientError::Io(e.to_string());
+        let mut request = [0u8; 1024];
+        // A peer that connects and never sends must not hold the task
+        let len = tokio::time::timeout(self.request_timeout, stream.read(&mut request))
+            .await
+            .map_err(|_| ClientError::Io("timed out reading probe request".to_string()))?
+            .map_err(io)?;
+        let request = String::from_utf8_lossy(&request[..len]);
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+        let status = match path {
+            "/healthz" => Some(self.liveness()),
+            "/readyz" => Some(self.readiness()),
+            _ => None,
+        };
+        let (code, body) = match status {
+            Some(status) => (
+                if status.ok {
+                    "200 OK"
+                } else {
+                    "503 Service Unavailable"
+                },
+                serde_json::to_string(&status).map_err(|e| ClientError::Parse(e.to_string()))?,
+            ),
+            None => ("404 Not Found", String::new()),
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            code,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await.map_err(io)?;
+        stream.shutdown().await.map_err(io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ready_after_first_message_until_stale() {
+        let mut state = ProbeState::default();
+        let start = Instant::now();
+        let staleness = Duration::from_secs(5);
+        assert!(!state.readiness(start, staleness, &[]).ok);
+
+        state.on_event(&ClientEvent::Connected);
+        assert_eq!(
+            state.readiness(start, staleness, &[]).reasons,
+            ["no message since subscribing"]
+        );
+        state.on_message(start);
+        assert!(
+            state
+                .readiness(start + Duration::from_secs(1), staleness, &[])
+                .ok
+        );
+        assert!(
+            !state
+                .readiness(start + Duration::from_secs(6), staleness, &[])
+                .ok
+        );
+
+        // A reconnect waits for fresh data again
+        state.on_event(&ClientEvent::Disconnected {
+            reason: "closed".to_string(),
+        });
+        state.on_event(&ClientEvent::Connected);
+        assert!(!state.readiness(start, staleness, &[]).ok);
+
+        assert!(state.liveness(&[]).ok);
+        assert!(!state.liveness(&["router".to_string()]).ok);
+        state.on_event(&ClientEvent::Stopped);
+        assert_eq!(state.liveness(&[]).reasons, ["client stopped"]);
+    }
+
+    #[test]
+    fn test_sink_health_affects_readiness() {
+        let mut state = ProbeState::default();
+        let staleness = Duration::from_secs(5);
+        state.on_event(&ClientEvent::Connected);
+        state.on_message(Instant::now());
+        assert!(state.readiness(Instant::now(), staleness, &[]).ok);
+        assert_eq!(
+            state
+                .readiness(Instant::now(), staleness, &["router".to_string()])
+                .reasons,
+            ["router sink failed"]
+        );
+        // Other actors only affect liveness
+        assert!(
+            state
+                .readiness(Instant::now(), staleness, &["qos".to_string()])
+                .ok
+        );
+
+        state.on_event(&ClientEvent::SinkLagging {
+            sink: "batches".to_string(),
+            dropped: 1,
+        });
+        let now = Instant::now();
+        assert_eq!(
+            state.readiness(now, staleness, &[]).reasons,
+            ["batches sink dropping messages"]
+        );
+        state.on_message(now + Duration::from_secs(6));
+        assert!(
+            state
+                .readiness(now + Duration::from_secs(6), staleness, &[])
+                .ok
+        );
+    }
+
+    #[tokio::test]
+    async fn test_silent_peer_times_out() {
+        let probes = Probes {
+            state: Arc::default(),
+            failed: Arc::default(),
+            max_staleness: Duration::from_secs(30),
+            request_timeout: Duration::from_millis(50),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _peer = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let response = tokio::time::timeout(Duration::from_secs(5), probes.respond(stream));
+        assert!(matches!(response.await, Ok(Err(ClientError::Io(_)))));
+    }
+}
@@ -0,0 +1,356 @@
+//! Feed quality-of-service reporting for SLA tracking.
+//!
+//! Every [`MarketDataClient`] keeps a [`QosTracker`] fed by its connection
+//! events and received frames, readable at any time through
+//! [`MarketDataClient::qos_stats`]. A [`QosReporter`] closes a window on
+//! the trackers of several venues every period, publishing one
+//! [`QosReport`] per venue and optionally appending them as JSON lines.
+
+use crate::client::{ClientError, ClientEvent, MarketDataClient, Result};
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// Latencies kept per window for percentiles; older samples are dropped
+const MAX_LATENCY_SAMPLES: usize = 100_000;
+
+/// Latency percentiles in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyPercentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// Feed quality of one venue over one window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QosReport {
+    pub venue: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Fraction of the window spent connected
+    pub uptime: f64,
+    /// Successful connections after the first
+    pub reconnects: u64,
+    /// Silences longer than the gap threshold while connected
+    pub gaps: u64,
+    pub frames: u64,
+    pub messages: u64,
+    /// Messages per second
+    pub message_rate: f64,
+    /// Network latency, from exchange timestamp to frame receipt
+    pub latency_ms: LatencyPercentiles,
+    /// Processing latency, from frame receipt to dispatch
+    #[serde(default)]
+    pub processing_ms: LatencyPercentiles,
+    pub parse_errors: u64,
+    /// Fraction of frames that failed to parse
+    pub parse_error_rate: f64,
+}
+
+impl QosReport {
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|e| ClientError::Parse(e.to_string()))
+    }
+}
+
+/// Accumulates the quality metrics of one connection for the current window
+#[derive(Debug, Clone)]
+pub struct QosTracker {
+    gap_threshold: Duration,
+    window_start: (Instant, DateTime<Utc>),
+    connected_since: Option<Instant>,
+    connected_for: Duration,
+    ever_connected: bool,
+    last_frame: Option<Instant>,
+    reconnects: u64,
+    gaps: u64,
+    frames: u64,
+    messages: u64,
+    parse_errors: u64,
+    latencies: VecDeque<f64>,
+    processing: VecDeque<f64>,
+}
+
+impl Default for QosTracker {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(5))
+    }
+}
+
+impl QosTracker {
+    /// Count a gap whenever no frame arrives for `gap_threshold` while
+    /// connected
+    pub fn new(gap_threshold: Duration) -> Self {
+        Self {
+            gap_threshold,
+            window_start: (Instant::now(), Utc::now()),
+            connected_since: None,
+            connected_for: Duration::ZERO,
+            ever_connected: false,
+            last_frame: None,
+            reconnects: 0,
+            gaps: 0,
+            frames: 0,
+            messages: 0,
+            parse_errors: 0,
+            latencies: VecDeque::new(),
+            processing: VecDeque::new(),
+        }
+    }
+
+    /// Track connects and disconnects for uptime and reconnect counts
+    pub fn record_event(&mut self, event: &ClientEvent, now: Instant) {
+        match event {
+            ClientEvent::Connected => {
+                if self.ever_connected {
+                    self.reconnects += 1;
+                }
+                self.ever_connected = true;
+                self.connected_since.get_or_insert(now);
+                self.last_frame = Some(now);
+            }
+            ClientEvent::Disconnected { .. } | ClientEvent::Stopped => {
+                if let Some(since) = self.connected_since.take() {
+                    self.connected_for += now.saturating_duration_since(since);
+                }
+                self.last_frame = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Record a received frame and whether it parsed
+    pub fn record_frame(&mut self, parsed: bool, now: Instant) {
+        self.frames += 1;
+        if !parsed {
+            self.parse_errors += 1;
+        }
+        if let Some(last) = self.last_frame.replace(now) {
+            if self.connected_since.is_some()
+                && now.saturating_duration_since(last) > self.gap_threshold
+            {
+                self.gaps += 1;
+            }
+        }
+    }
+
+    /// Record a decoded message received at `received`
+    pub fn record_message(&mut self, msg: &MarketDataMessage, received: DateTime<Utc>) {
+        self.messages += 1;
+        // Corrections and busts carry the time of the trade they amend
+        let timestamp = match msg {
+            MarketDataMessage::Trade(trade) => trade.timestamp,
+            MarketDataMessage::Quote(quote) => quote.timestamp,
+            MarketDataMessage::OrderBook(book) => book.timestamp,
+            _ => return,
+        };
+        push_sample(&mut self.latencies, received - timestamp);
+    }
+
+    /// Record a frame received at `received` whose messages were dispatched
+    /// at `dispatched`
+    pub fn record_processing(&mut self, received: DateTime<Utc>, dispatched: DateTime<Utc>) {
+        push_sample(&mut self.processing, dispatched - received);
+    }
+
+    /// Metrics of the window so far
+    pub fn report(&self, venue: &str, now: Instant) -> QosReport {
+        let (start, start_time) = self.window_start;
+        let elapsed = now.saturating_duration_since(start);
+        let connected = self.connected_for
+            + self
+                .connected_since
+                .map_or(Duration::ZERO, |since| now.saturating_duration_since(since));
+        let seconds = elapsed.as_secs_f64();
+        let ratio = |n: u64, total: f64| if total > 0.0 { n as f64 / total } else { 0.0 };
+        QosReport {
+            venue: venue.to_string(),
+            start: start_time,
+            end: start_time + chrono::Duration::from_std(elapsed).unwrap_or_default(),
+            uptime: if seconds > 0.0 {
+                (connected.as_secs_f64() / seconds).min(1.0)
+            } else {
+                0.0
+            },
+            reconnects: self.reconnects,
+            gaps: self.gaps,
+            frames: self.frames,
+            messages: self.messages,
+            message_rate: ratio(self.messages, seconds),
+            latency_ms: percentiles(&self.latencies),
+            processing_ms: percentiles(&self.processing),
+            parse_errors: self.parse_errors,
+            parse_error_rate: ratio(self.parse_errors, self.frames as f64),
+        }
+    }
+
+    /// Report the window and start the next one at `now`
+    pub fn roll(&mut self, venue: &str, now: Instant) -> QosReport {
+        let report = self.report(venue, now);
+        *self = Self {
+            window_start: (now, report.end),
+            connected_since: self.connected_since.map(|_| now),
+            ever_connected: self.ever_connected,
+            last_frame: self.last_frame,
+            ..Self::new(self.gap_threshold)
+        };
+        report
+    }
+}
+
+fn push_sample(samples: &mut VecDeque<f64>, latency: chrono::Duration) {
+    let micros = latency.num_microseconds().unwrap_or(i64::MAX);
+    if samples.len() == MAX_LATENCY_SAMPLES {
+        samples.pop_front();
+    }
+    samples.push_back(micros.max(0) as f64 / 1000.0);
+}
+
+fn percentiles(samples: &VecDeque<f64>) -> LatencyPercentiles {
+    let mut sorted: Vec<f64> = samples.iter().copied().collect();
+    if sorted.is_empty() {
+        return LatencyPercentiles::default();
+    }
+    sorted.sort_by(f64::total_cmp);
+    let at = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize];
+    LatencyPercentiles {
+        p50: at(0.5),
+        p90: at(0.9),
+        p99: at(0.99),
+        max: sorted[sorted.len() - 1],
+    }
+}
+
+/// Periodic per-venue [`QosReport`]s from several clients
+pub struct QosReporter {
+    venues: Vec<(String, Arc<Mutex<QosTracker>>)>,
+    output: Option<PathBuf>,
+    tx: broadcast::Sender<QosReport>,
+}
+
+impl Default for QosReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QosReporter {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(64);
+        Self {
+            venues: Vec::new(),
+            output: None,
+            tx,
+        }
+    }
+
+    /// Report on `client` under `venue`
+    pub fn with_client(mut self, venue: &str, client: &MarketDataClient) -> Self {
+        self.venues.push((venue.to_string(), client.qos_tracker()));
+        self
+    }
+
+    /// Append every report to `path` as a JSON line
+    pub fn with_output(mut self, path: impl AsRef<Path>) -> Self {
+        self.output = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<QosReport> {
+        self.tx.subscribe()
+    }
+
+    /// Close the current window of every venue and publish its report
+    pub fn report(&self) -> Result<Vec<QosReport>> {
+        let now = Instant::now();
+        let reports: Vec<QosReport> = self
+            .venues
+            .iter()
+            .map(|(venue, tracker)| tracker.lock().unwrap().roll(venue, now))
+            .collect();
+        if let Some(path) = &self.output {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| ClientError::Io(format!("{}: {}", path.display(), e)))?;
+            for report in &reports {
+                writeln!(file, "{}", report.to_json()?)
+                    .map_err(|e| ClientError::Io(e.to_string()))?;
+            }
+        }
+        for report in &reports {
+            let _ = self.tx.send(report.clone());
+        }
+        Ok(reports)
+    }
+
+    /// Report every `period` on a background task
+    pub fn spawn(self, period: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            // The first tick completes immediately
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.report() {
+                    warn!("Writing QoS reports failed: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Trade;
+
+    #[test]
+    fn test_window_metrics() {
+        let mut tracker = QosTracker::new(Duration::from_secs(5));
+        let t0 = tracker.window_start.0;
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+        let received = Utc::now();
+
+        tracker.record_event(&ClientEvent::Connected, at(0));
+        for (i, secs) in [1, 2, 10, 11].into_iter().enumerate() {
+            tracker.record_frame(i != 3, at(secs));
+            let trade = Trade {
+                timestamp: received - chrono::Duration::milliseconds(10 * (i as i64 + 1)),
+                trade_id: i.to_string(),
+                ..Trade::test("BTCUSD", 100.0)
+            };
+            tracker.record_message(&MarketDataMessage::Trade(trade), received);
+        }
+        let reason = "closed by server".to_string();
+        tracker.record_event(&ClientEvent::Disconnected { reason }, at(12));
+        tracker.record_event(&ClientEvent::Connected, at(15));
+
+        let report = tracker.roll("coinbase", at(20));
+        assert_eq!((report.reconnects, report.gaps), (1, 1));
+        assert_eq!(
+            (report.frames, report.messages, report.parse_errors),
+            (4, 4, 1)
+        );
+        assert!((report.uptime - 0.85).abs() < 1e-9);
+        assert!((report.message_rate - 0.2).abs() < 1e-9);
+        assert_eq!(report.parse_error_rate, 0.25);
+        assert_eq!((report.latency_ms.p50, report.latency_ms.max), (30.0, 40.0));
+
+        // The next window starts connected with fresh counters
+        let report = tracker.report("coinbase", at(30));
+        assert_eq!((report.frames, report.reconnects), (0, 0));
+        assert_eq!(report.uptime, 1.0);
+    }
+}
@@ -0,0 +1,547 @@
+//! Quoted spread, top-of-book depth and trade execution analytics.
+
+use crate::aggressor::AggressorRule;
+use crate::clock::align;
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Quote, Trade, TradeSide};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Time-weighted quote statistics for one symbol over a window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuoteMetrics {
+    pub symbol: Symbol,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Time-weighted average quoted spread
+    pub avg_spread: f64,
+    /// Time-weighted average spread relative to the mid, in basis points
+    pub avg_spread_bps: f64,
+    /// Fraction of the quoted time the spread was at the minimum tick
+    pub at_min_tick: f64,
+    /// Time-weighted average size at the best bid
+    pub avg_bid_size: f64,
+    /// Time-weighted average size at the best ask
+    pub avg_ask_size: f64,
+    /// Quotes received during the window
+    pub quote_count: u64,
+}
+
+/// A quote and the time it became the prevailing one
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: DateTime<Utc>,
+    spread: f64,
+    spread_bps: f64,
+    bid_size: f64,
+    ask_size: f64,
+    at_min_tick: bool,
+}
+
+/// Tracks time-weighted spread, time at the minimum tick and quoted depth
+/// over a rolling window, for execution-quality monitoring.
+///
+/// Like [`SnapshotScheduler`](crate::snapshot::SnapshotScheduler), metrics are
+/// emitted on ticks aligned to multiples of the interval and driven by event
+/// time; each covers the window ending at the tick. One-sided quotes are
+/// ignored. Emitted metrics are also published to receivers from
+/// [`subscribe`](Self::subscribe); as a pipeline [`Stage`] it passes
+/// messages through unchanged.
+#[derive(Debug, Clone)]
+pub struct QuoteAnalytics {
+    window: Duration,
+    interval: Duration,
+    tick_size: f64,
+    tick_sizes: HashMap<Symbol, f64>,
+    quotes: BTreeMap<Symbol, VecDeque<Segment>>,
+    next_tick: Option<DateTime<Utc>>,
+    tx: broadcast::Sender<QuoteMetrics>,
+}
+
+impl QuoteAnalytics {
+    /// Metrics over `window`, emitted once per window, counting spreads of
+    /// `tick_size` or less as at the minimum tick
+    pub fn new(window: Duration, tick_size: f64) -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            window,
+            interval: window,
+            tick_size,
+            tick_sizes: HashMap::new(),
+            quotes: BTreeMap::new(),
+            next_tick: None,
+            tx,
+        }
+    }
+
+    /// Emit more often than the window is long, so windows overlap
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Minimum tick of `symbol` when it differs from the default
+    pub fn with_tick_size(mut self, symbol: &str, tick_size: f64) -> Self {
+        self.tick_sizes.insert(symbol.into(), tick_size);
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<QuoteMetrics> {
+        self.tx.subscribe()
+    }
+
+    /// Time of the next scheduled emission
+    pub fn next_tick(&self) -> Option<DateTime<Utc>> {
+        self.next_tick
+    }
+
+    /// Feed a message, returning the metrics that came due before its
+    /// timestamp
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<QuoteMetrics> {
+        match msg {
+            MarketDataMessage::Quote(quote) => self.on_quote(quote),
+            _ => msg.timestamp().map(|ts| self.poll(ts)).unwrap_or_default(),
+        }
+    }
+
+    pub fn on_quote(&mut self, quote: &Quote) -> Vec<QuoteMetrics> {
+        let due = self.poll(quote.timestamp);
+        if quote.bid_price <= 0.0 || quote.ask_price <= 0.0 {
+            return due;
+        }
+
+        let tick_size = self
+            .tick_sizes
+            .get(&quote.symbol)
+            .copied()
+            .unwrap_or(self.tick_size);
+        let spread = quote.spread();
+        let segment = Segment {
+            start: quote.timestamp,
+            spread,
+            spread_bps: spread / quote.mid_price() * 10_000.0,
+            bid_size: quote.bid_size,
+            ask_size: quote.ask_size,
+            // Tolerate float noise in decimal prices
+            at_min_tick: spread <= tick_size * (1.0 + 1e-9),
+        };
+        let segments = match self.quotes.get_mut(&quote.symbol) {
+            Some(segments) => segments,
+            None => self.quotes.entry(quote.symbol).or_default(),
+        };
+        segments.push_back(segment);
+
+        // Keep the segment prevailing at the start of the oldest window
+        let horizon = quote.timestamp - self.window;
+        while segments.len() > 1 && segments[1].start <= horizon {
+            segments.pop_front();
+        }
+        due
+    }
+
+    /// Emit metrics for the latest tick at or before `now`, if one is due
+    pub fn poll(&mut self, now: DateTime<Utc>) -> Vec<QuoteMetrics> {
+        let tick = align(now, self.interval);
+        let Some(next) = self.next_tick else {
+            self.next_tick = Some(tick + self.interval);
+            return Vec::new();
+        };
+        if now < next {
+            return Vec::new();
+        }
+
+        self.next_tick = Some(tick + self.interval);
+        let due: Vec<QuoteMetrics> = self
+            .quotes
+            .keys()
+            .filter_map(|symbol| self.metrics(symbol, tick))
+            .collect();
+        for metrics in &due {
+            let _ = self.tx.send(metrics.clone());
+        }
+        due
+    }
+
+    /// Metrics for `symbol` over the window ending at `end`
+    pub fn metrics(&self, symbol: &str, end: DateTime<Utc>) -> Option<QuoteMetrics> {
+        let segments = self.quotes.get(symbol)?;
+        let start = end - self.window;
+        let mut metrics = QuoteMetrics {
+            symbol: symbol.into(),
+            start,
+            end,
+            avg_spread: 0.0,
+            avg_spread_bps: 0.0,
+            at_min_tick: 0.0,
+            avg_bid_size: 0.0,
+            avg_ask_size: 0.0,
+            quote_count: 0,
+        };
+
+        let mut covered = 0.0;
+        for (i, segment) in segments.iter().enumerate() {
+            if segment.start > start && segment.start <= end {
+                metrics.quote_count += 1;
+            }
+            let until = segments.get(i + 1).map_or(end, |next| next.start.min(end));
+            let from = segment.start.max(start);
+            if until <= from {
+                continue;
+            }
+            let weight = (until - from).num_nanoseconds().unwrap_or(i64::MAX) as f64;
+            covered += weight;
+            metrics.avg_spread += segment.spread * weight;
+            metrics.avg_spread_bps += segment.spread_bps * weight;
+            metrics.avg_bid_size += segment.bid_size * weight;
+            metrics.avg_ask_size += segment.ask_size * weight;
+            if segment.at_min_tick {
+                metrics.at_min_tick += weight;
+            }
+        }
+        if covered == 0.0 {
+            return None;
+        }
+
+        metrics.avg_spread /= covered;
+        metrics.avg_spread_bps /= covered;
+        metrics.at_min_tick /= covered;
+        metrics.avg_bid_size /= covered;
+        metrics.avg_ask_size /= covered;
+        Some(metrics)
+    }
+}
+
+impl Stage for QuoteAnalytics {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+/// A trade paired with the quote prevailing when it printed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchedTrade {
+    pub trade: Trade,
+    pub quote: Quote,
+    /// Aggressor by the Lee-Ready quote and tick rules
+    pub inferred_side: TradeSide,
+    /// Twice the distance from the mid, the round-trip cost actually paid
+    pub effective_spread: f64,
+    /// Effective spread relative to the mid, in basis points
+    pub effective_spread_bps: f64,
+    /// How far inside the quoted price for the inferred side the trade
+    /// printed; negative when it printed outside the quote
+    pub price_improvement: f64,
+}
+
+/// Pairs trades with the prevailing quote by timestamp to compute effective
+/// spread and price improvement.
+///
+/// Feed messages in event order with [`on_message`](Self::on_message), or
+/// hand a captured session to [`replay`](Self::replay), which orders it by
+/// timestamp first.
+#[derive(Debug, Clone)]
+pub struct TradeQuoteMatcher {
+    lag: Duration,
+    history: Duration,
+    quotes: HashMap<Symbol, VecDeque<Quote>>,
+    /// Last trade price and the side inferred for it, for the tick rule
+    last_trades: HashMap<Symbol, (f64, TradeSide)>,
+}
+
+impl Default for TradeQuoteMatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TradeQuoteMatcher {
+    pub fn new() -> Self {
+        Self {
+            lag: Duration::zero(),
+            history: Duration::seconds(5),
+            quotes: HashMap::new(),
+            last_trades: HashMap::new(),
+        }
+    }
+
+    /// Match against the quote prevailing `lag` before each trade, for feeds
+    /// where trades are reported late relative to quotes
+    pub fn with_quote_lag(mut self, lag: Duration) -> Self {
+        self.lag = lag;
+        self
+    }
+
+    /// How long quotes are kept for trades that arrive out of order
+    pub fn with_history(mut self, history: Duration) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Option<MatchedTrade> {
+        match msg {
+            MarketDataMessage::Quote(quote) => {
+                self.on_quote(quote);
+                None
+            }
+            MarketDataMessage::Trade(trade) => self.on_trade(trade),
+            _ => None,
+        }
+    }
+
+    pub fn on_quote(&mut self, quote: &Quote) {
+        if quote.bid_price <= 0.0 || quote.ask_price <= 0.0 {
+            return;
+        }
+        let quotes = match self.quotes.get_mut(&quote.symbol) {
+            Some(quotes) => quotes,
+            None => self.quotes.entry(quote.symbol).or_default(),
+        };
+        quotes.push_back(quote.clone());
+        let horizon = quote.timestamp - self.history - self.lag;
+        while quotes.len() > 1 && quotes[1].timestamp <= horizon {
+            quotes.pop_front();
+        }
+    }
+
+    /// Match a trade; `None` until a quote has been seen for its symbol
+    pub fn on_trade(&mut self, trade: &Trade) -> Option<MatchedTrade> {
+        let cutoff = trade.timestamp - self.lag;
+        let quote = self
+            .quotes
+            .get(&trade.symbol)
+            .and_then(|quotes| quotes.iter().rev().find(|q| q.timestamp <= cutoff))
+            .cloned();
+
+        let mid = quote.as_ref().map(Quote::mid_price);
+        let last = self.last_trades.get(&trade.symbol).copied();
+        let side = match AggressorRule::LeeReady.classify(trade.price, mid, last) {
+            TradeSide::Unknown => trade.side,
+            side => side,
+        };
+        self.last_trades.insert(trade.symbol, (trade.price, side));
+
+        let quote = quote?;
+        let mid = quote.mid_price();
+        let effective_spread = 2.0 * (trade.price - mid).abs();
+        Some(MatchedTrade {
+            trade: trade.clone(),
+            inferred_side: side,
+            effective_spread,
+            effective_spread_bps: effective_spread / mid * 10_000.0,
+            price_improvement: match side {
+                TradeSide::Buy => quote.ask_price - trade.price,
+                TradeSide::Sell => trade.price - quote.bid_price,
+                TradeSide::Unknown => 0.0,
+            },
+            quote,
+        })
+    }
+
+    /// Match every trade of a captured session, ordering messages by
+    /// timestamp while keeping arrival order for ties
+    pub fn replay<'a>(
+        &mut self,
+        messages: impl IntoIterator<Item = &'a MarketDataMessage>,
+    ) -> Vec<MatchedTrade> {
+        let mut messages: Vec<_> = messages
+            .into_iter()
+            .filter(|msg| msg.timestamp().is_some())
+            .collect();
+        messages.sort_by_key(|msg| msg.timestamp());
+        messages
+            .into_iter()
+            .filter_map(|msg| self.on_message(msg))
+            .collect()
+    }
+}
+
+/// Top-of-book change-only stream: passes a quote only when the best bid
+/// or ask price (and optionally size) moved by more than `epsilon`.
+///
+/// Book snapshots are reduced to their top of book. As a pipeline stage,
+/// unchanged quotes are dropped, books are passed through followed by a
+/// quote when their top changed, and other messages are untouched.
+#[derive(Debug, Clone)]
+pub struct BboChangeFilter {
+    epsilon: f64,
+    sizes: bool,
+    last: HashMap<Symbol, Quote>,
+}
+
+impl BboChangeFilter {
+    pub fn new(epsilon: f64) -> Self {
+        Self {
+            epsilon,
+            sizes: false,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Also emit when only the size at the best bid or ask changed
+    pub fn with_sizes(mut self) -> Self {
+        self.sizes = true;
+        self
+    }
+
+    /// Top of book of `msg` if it differs from the last one emitted
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Option<Quote> {
+        let quote = match msg {
+            MarketDataMessage::Quote(quote) => quote.clone(),
+            MarketDataMessage::OrderBook(book) => {
+                let (bid, ask) = (book.best_bid()?, book.best_ask()?);
+                Quote {
+                    symbol: book.symbol,
+                    bid_price: bid.price,
+                    bid_size: bid.size,
+                    ask_price: ask.price,
+                    ask_size: ask.size,
+                    timestamp: book.timestamp,
+                    instrument_id: book.instrument_id,
+                    contract: book.contract,
+                    received: book.received,
+                }
+            }
+            _ => return None,
+        };
+        if let Some(last) = self.last.get(&quote.symbol) {
+            let moved = |a: f64, b: f64| (a - b).abs() > self.epsilon;
+            let changed = moved(last.bid_price, quote.bid_price)
+                || moved(last.ask_price, quote.ask_price)
+                || (self.sizes
+                    && (moved(last.bid_size, quote.bid_size)
+                        || moved(last.ask_size, quote.ask_size)));
+            if !changed {
+                return None;
+            }
+        }
+        self.last.insert(quote.symbol, quote.clone());
+        Some(quote)
+    }
+}
+
+impl Stage for BboChangeFilter {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        let changed = self.on_message(&msg);
+        match msg {
+            MarketDataMessage::Quote(_) => out.extend(changed.map(MarketDataMessage::Quote)),
+            msg => {
+                out.push(msg);
+                out.extend(changed.map(MarketDataMessage::Quote));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn quote(ts: DateTime<Utc>, bid: f64, ask: f64, bid_size: f64) -> Quote {
+        Quote {
+            bid_size,
+            ask_size: 100.0,
+            timestamp: ts,
+            ..Quote::test("AAPL", bid, ask)
+        }
+    }
+
+    #[test]
+    fn test_time_weighted_window_metrics() {
+        let t0 = Utc.with_ymd_and_hms(2024, 3, 1, 14, 30, 0).unwrap();
+        let mut analytics = QuoteAnalytics::new(Duration::seconds(10), 0.01);
+        let mut rx = analytics.subscribe();
+
+        assert!(analytics
+            .on_quote(&quote(t0, 100.00, 100.01, 200.0))
+            .is_empty());
+        // At the minimum tick for 6s, then 3 ticks wide for 4s
+        analytics.on_quote(&quote(t0 + Duration::seconds(6), 99.99, 100.02, 400.0));
+        let due = analytics.on_quote(&quote(t0 + Duration::seconds(11), 100.0, 100.01, 100.0));
+
+        assert_eq!(due.len(), 1);
+        let metrics = &due[0];
+        assert_eq!(metrics.end, t0 + Duration::seconds(10));
+        assert_eq!(metrics.quote_count, 1);
+        assert!((metrics.avg_spread - 0.018).abs() < 1e-9);
+        assert!((metrics.at_min_tick - 0.6).abs() < 1e-9);
+        assert!((metrics.avg_bid_size - 280.0).abs() < 1e-9);
+        assert_eq!(metrics.avg_ask_size, 100.0);
+        assert_eq!(rx.try_recv().unwrap(), *metrics);
+
+        // As a stage, later messages of any kind emit the next window
+        let mut out = Vec::new();
+        analytics.process(MarketDataMessage::Heartbeat, &mut out);
+        let trade = Trade {
+            timestamp: t0 + Duration::seconds(21),
+            ..Trade::test("AAPL", 100.0)
+        };
+        analytics.process(MarketDataMessage::Trade(trade), &mut out);
+        assert_eq!(rx.try_recv().unwrap().end, t0 + Duration::seconds(20));
+        assert_eq!(out.len(), 2);
+    }
+
+    #[test]
+    fn test_bbo_filter_drops_unchanged_quotes() {
+        let t0 = Utc.with_ymd_and_hms(2024, 3, 1, 14, 30, 0).unwrap();
+        let mut filter = BboChangeFilter::new(1e-9);
+        let mut out = Vec::new();
+        for (bid, bid_size) in [(100.0, 200.0), (100.0, 300.0), (100.01, 300.0)] {
+            let msg = MarketDataMessage::Quote(quote(t0, bid, 100.02, bid_size));
+            filter.process(msg, &mut out);
+        }
+        assert_eq!(out.len(), 2);
+
+        let mut filter = BboChangeFilter::new(1e-9).with_sizes();
+        let sized = |bid_size| MarketDataMessage::Quote(quote(t0, 100.0, 100.01, bid_size));
+        assert!(filter.on_message(&sized(200.0)).is_some());
+        assert!(filter.on_message(&sized(300.0)).is_some());
+        assert!(filter.on_message(&sized(300.0)).is_none());
+    }
+
+    #[test]
+    fn test_replay_matches_prevailing_quote() {
+        let t0 = Utc.with_ymd_and_hms(2024, 3, 1, 14, 30, 0).unwrap();
+        let trade = |ms: i64, price: f64| {
+            MarketDataMessage::Trade(Trade {
+                quantity: 100.0,
+                timestamp: t0 + Duration::milliseconds(ms),
+                trade_id: ms.to_string(),
+                ..Trade::test("AAPL", price)
+            })
+        };
+        // Captured out of order: the second trade arrived before the quote
+        // that was prevailing when it printed
+        let session = [
+            MarketDataMessage::Quote(quote(t0, 100.00, 100.04, 100.0)),
+            trade(10, 100.01),
+            trade(30, 100.03),
+            MarketDataMessage::Quote(quote(
+                t0 + Duration::milliseconds(20),
+                100.02,
+                100.04,
+                100.0,
+            )),
+            trade(40, 100.03),
+        ];
+
+        let matched = TradeQuoteMatcher::new().replay(&session);
+
+        assert_eq!(matched.len(), 3);
+        assert_eq!(matched[0].inferred_side, TradeSide::Sell);
+        assert!((matched[0].effective_spread - 0.02).abs() < 1e-9);
+        assert!((matched[0].price_improvement - 0.01).abs() < 1e-9);
+        assert_eq!(matched[1].quote.bid_price, 100.02);
+        // Both print at the mid: an uptick, then a zero tick
+        assert_eq!(matched[1].inferred_side, TradeSide::Buy);
+        assert_eq!(matched[2].inferred_side, TradeSide::Buy);
+        assert!((matched[2].price_improvement - 0.01).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,267 @@
+use super::{
+    io_error, JsonLinesReader, RecordingKey, RecordingReader, RecordingWriter,
+    DEFAULT_BLOCK_RECORDS, MAGIC,
+};
+use crate::client::{ClientError, Result};
+use crate::clock::align;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Quote};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use tracing::info;
+
+/// What to keep when compacting a capture
+#[derive(Debug, Clone)]
+pub struct CompactOptions {
+    /// Drop heartbeat messages
+    pub drop_heartbeats: bool,
+    /// Keep at most one quote per symbol per interval (the latest one)
+    pub quote_interval: Option<Duration>,
+    /// Keep only these symbols (all symbols when `None`)
+    pub symbols: Option<HashSet<Symbol>>,
+    /// Messages per compressed block in the output
+    pub block_records: u32,
+    /// Key for reading an encrypted input and encrypting the output
+    pub key: Option<RecordingKey>,
+}
+
+impl Default for CompactOptions {
+    fn default() -> Self {
+        Self {
+            drop_heartbeats: true,
+            quote_interval: None,
+            symbols: None,
+            block_records: DEFAULT_BLOCK_RECORDS,
+            key: None,
+        }
+    }
+}
+
+/// Counts produced by [`compact`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompactionReport {
+    pub read: u64,
+    pub written: u64,
+    pub heartbeats_dropped: u64,
+    pub quotes_conflated: u64,
+    pub symbols_filtered: u64,
+}
+
+/// Rewrite a JSON-lines or binary capture into a compacted binary recording.
+///
+/// Conflated quotes are emitted once their interval closes, so they may trail
+/// other messages from the same interval by up to `quote_interval`. The
+/// output must be a different file from the input, which creating it would
+/// truncate.
+pub fn compact(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    options: &CompactOptions,
+) -> Result<CompactionReport> {
+    let input = input.as_ref();
+    if let (Ok(from), Ok(to)) = (input.canonicalize(), output.as_ref().canonicalize()) {
+        if from == to {
+            return Err(ClientError::Io(format!(
+                "cannot compact {} onto itself",
+                input.display()
+            )));
+        }
+    }
+    let mut writer = RecordingWriter::create(output.as_ref(), options.block_records)?;
+    if let Some(key) = &options.key {
+        writer = writer.with_encryption(key)?;
+    }
+    let mut report = CompactionReport::default();
+    let mut conflator = options.quote_interval.map(QuoteConflator::new);
+
+    let mut process = |msg: MarketDataMessage| -> Result<()> {
+        report.read += 1;
+
+        if let Some(symbols) = &options.symbols {
+            if msg
+                .symbol()
+                .is_some_and(|symbol| !symbols.contains(&symbol))
+            {
+                report.symbols_filtered += 1;
+                return Ok(());
+            }
+        }
+
+        if let Some(conflator) = conflator.as_mut() {
+            if let Some(ts) = msg.timestamp() {
+                for quote in conflator.drain_closed(ts) {
+                    writer.write(&MarketDataMessage::Quote(quote))?;
+                    report.written += 1;
+                }
+            }
+            if let MarketDataMessage::Quote(quote) = msg {
+                if conflator.push(quote) {
+                    report.quotes_conflated += 1;
+                }
+                return Ok(());
+            }
+        }
+
+        if options.drop_heartbeats && matches!(msg, MarketDataMessage::Heartbeat) {
+            report.heartbeats_dropped += 1;
+            return Ok(());
+        }
+
+        writer.write(&msg)?;
+        report.written += 1;
+        Ok(())
+    };
+
+    if is_binary(input)? {
+        let mut reader = RecordingReader::open(input)?;
+        if let Some(key) = &options.key {
+            reader = reader.with_key(key)?;
+        }
+        for entry in reader.messages() {
+            process(entry?.1)?;
+        }
+    } else {
+        for msg in JsonLinesReader::open(input)? {
+            process(msg?)?;
+        }
+    }
+
+    if let Some(conflator) = conflator.as_mut() {
+        for quote in conflator.drain_all() {
+            writer.write(&MarketDataMessage::Quote(quote))?;
+            report.written += 1;
+        }
+    }
+    writer.finish()?;
+
+    info!(
+        "Compacted {}: {} messages read, {} written",
+        input.display(),
+        report.read,
+        report.written
+    );
+    Ok(report)
+}
+
+pub(super) fn is_binary(path: &Path) -> Result<bool> {
+    let mut magic = [0u8; 8];
+    let mut file = File::open(path).map_err(io_error)?;
+    Ok(file.read_exact(&mut magic).is_ok() && &magic == MAGIC)
+}
+
+/// Keeps the latest quote per symbol until its interval closes
+struct QuoteConflator {
+    interval: Duration,
+    pending: HashMap<Symbol, (DateTime<Utc>, Quote)>,
+}
+
+impl QuoteConflator {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Buffer a quote, returning true if it replaced one in the same interval
+    fn push(&mut self, quote: Quote) -> bool {
+        let bucket_end = self.bucket_end(quote.timestamp);
+        match self.pending.get_mut(&quote.symbol) {
+            Some(entry) if entry.0 == bucket_end => {
+                entry.1 = quote;
+                true
+            }
+            _ => {
+                self.pending.insert(quote.symbol, (bucket_end, quote));
+                false
+            }
+        }
+    }
+
+    fn drain_closed(&mut self, now: DateTime<Utc>) -> Vec<Quote> {
+        let closed: Vec<Symbol> = self
+            .pending
+            .iter()
+            .filter(|(_, (bucket_end, _))| *bucket_end <= now)
+            .map(|(symbol, _)| *symbol)
+            .collect();
+
+        let mut quotes: Vec<Quote> = closed
+            .iter()
+            .filter_map(|symbol| self.pending.remove(symbol))
+            .map(|(_, quote)| quote)
+            .collect();
+        quotes.sort_by_key(|quote| quote.timestamp);
+        quotes
+    }
+
+    fn drain_all(&mut self) -> Vec<Quote> {
+        let mut quotes: Vec<Quote> = self.pending.drain().map(|(_, (_, quote))| quote).collect();
+        quotes.sort_by_key(|quote| quote.timestamp);
+        quotes
+    }
+
+    fn bucket_end(&self, ts: DateTime<Utc>) -> DateTime<Utc> {
+        align(ts, self.interval) + self.interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::io::Write;
+
+    #[test]
+    fn test_compact_jsonl_capture() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("capture.jsonl");
+        let output = dir.path().join("capture.mds");
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+
+        let mut file = File::create(&input).unwrap();
+        for i in 0..10 {
+            for symbol in ["BTCUSD", "ETHUSD"] {
+                let quote = MarketDataMessage::Quote(Quote {
+                    timestamp: t0 + Duration::milliseconds(i * 300),
+                    ..Quote::test(symbol, 100.0 + i as f64, 101.0 + i as f64)
+                });
+                writeln!(file, "{}", serde_json::to_string(&quote).unwrap()).unwrap();
+            }
+            writeln!(file, "{{\"type\":\"Heartbeat\"}}").unwrap();
+        }
+        drop(file);
+
+        let options = CompactOptions {
+            quote_interval: Some(Duration::seconds(1)),
+            symbols: Some(HashSet::from(["BTCUSD".into()])),
+            ..Default::default()
+        };
+        let report = compact(&input, &output, &options).unwrap();
+
+        assert_eq!(report.read, 30);
+        assert_eq!(report.heartbeats_dropped, 10);
+        assert_eq!(report.symbols_filtered, 10);
+        assert_eq!(report.written, 3);
+
+        // The last quote of each one-second interval survives
+        let mut reader = RecordingReader::open(&output).unwrap();
+        let bids: Vec<f64> = reader
+            .messages()
+            .map(|entry| match entry.unwrap().1 {
+                MarketDataMessage::Quote(quote) => quote.bid_price,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(bids, vec![103.0, 106.0, 109.0]);
+
+        // Compacting a file onto itself, by any path, leaves it intact
+        let size = std::fs::metadata(&output).unwrap().len();
+        let alias = dir.path().join(".").join("capture.mds");
+        assert!(compact(&output, &alias, &CompactOptions::default()).is_err());
+        assert_eq!(std::fs::metadata(&output).unwrap().len(), size);
+    }
+}
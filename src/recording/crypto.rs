@@ -0,0 +1,179 @@
+use super::corrupt;
+use crate::client::{ClientError, Result};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use std::fmt;
+
+const NONCE_LEN: usize = 12;
+
+/// Bytes an encrypted block adds to its compressed payload
+pub(crate) const OVERHEAD: usize = NONCE_LEN + 16;
+
+/// AES-256 key protecting recording blocks
+#[derive(Clone)]
+pub struct RecordingKey([u8; 32]);
+
+impl RecordingKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Parse 64 hex digits
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim();
+        let invalid = || ClientError::Parse("recording key must be 64 hex digits".to_string());
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut key = [0u8; 32];
+        for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(key))
+    }
+
+    /// Read a hex key from the environment variable `var`
+    pub fn from_env(var: &str) -> Result<Self> {
+        let hex =
+            std::env::var(var).map_err(|_| ClientError::Parse(format!("{} is not set", var)))?;
+        Self::from_hex(&hex)
+    }
+
+    pub(crate) fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.0.into())
+    }
+}
+
+impl fmt::Debug for RecordingKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RecordingKey(..)")
+    }
+}
+
+/// Source of recording keys, such as a KMS client unwrapping a data key
+pub trait KeyProvider {
+    fn recording_key(&self) -> Result<RecordingKey>;
+}
+
+impl KeyProvider for RecordingKey {
+    fn recording_key(&self) -> Result<RecordingKey> {
+        Ok(self.clone())
+    }
+}
+
+impl<F: Fn() -> Result<RecordingKey>> KeyProvider for F {
+    fn recording_key(&self) -> Result<RecordingKey> {
+        self()
+    }
+}
+
+/// Hex key held in an environment variable
+#[derive(Debug, Clone)]
+pub struct EnvKey(pub String);
+
+impl KeyProvider for EnvKey {
+    fn recording_key(&self) -> Result<RecordingKey> {
+        RecordingKey::from_env(&self.0)
+    }
+}
+
+/// Associated data of a block: its position in the file and its header,
+/// so blocks cannot be reordered or swapped between positions undetected
+fn aad(block: u64, header: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(8 + header.len());
+    aad.extend_from_slice(&block.to_le_bytes());
+    aad.extend_from_slice(header);
+    aad
+}
+
+/// `[nonce][ciphertext + tag]` of the `block`th block, authenticating its
+/// position and header as well
+pub(crate) fn seal(
+    cipher: &Aes256Gcm,
+    block: u64,
+    header: &[u8],
+    payload: &[u8],
+) -> Result<Vec<u8>> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let sealed = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: payload,
+                aad: &aad(block, header),
+            },
+        )
+        .map_err(|_| ClientError::Io("block encryption failed".to_string()))?;
+    let mut out = Vec::with_capacity(NONCE_LEN + sealed.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+pub(crate) fn open(
+    cipher: &Aes256Gcm,
+    block: u64,
+    header: &[u8],
+    sealed: &[u8],
+) -> Result<Vec<u8>> {
+    if sealed.len() < OVERHEAD {
+        return Err(corrupt("truncated encrypted block"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &aad(block, header),
+            },
+        )
+        .map_err(|_| corrupt("block failed authentication (wrong key?)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: &str) -> RecordingKey {
+        RecordingKey::from_hex(&byte.repeat(32)).unwrap()
+    }
+
+    #[test]
+    fn test_key_parsing() {
+        let parsed = RecordingKey::from_hex(&format!(" {} \n", "aB".repeat(32))).unwrap();
+        assert_eq!(parsed.0, [0xab; 32]);
+        for bad in [
+            "2a".repeat(31),
+            "2a".repeat(33),
+            "zz".repeat(32),
+            "é".repeat(32),
+        ] {
+            assert!(RecordingKey::from_hex(&bad).is_err(), "accepted {:?}", bad);
+        }
+        // Keys never show up in logs
+        assert_eq!(format!("{:?}", parsed), "RecordingKey(..)");
+    }
+
+    #[test]
+    fn test_sealed_blocks_only_open_unchanged() {
+        let cipher = key("2a").cipher();
+        let sealed = seal(&cipher, 3, b"header", b"payload").unwrap();
+        assert_eq!(sealed.len(), b"payload".len() + OVERHEAD);
+        assert_eq!(open(&cipher, 3, b"header", &sealed).unwrap(), b"payload");
+        // Fresh nonces, so equal blocks do not seal alike
+        assert_ne!(seal(&cipher, 3, b"header", b"payload").unwrap(), sealed);
+
+        assert!(open(&key("2b").cipher(), 3, b"header", &sealed).is_err());
+        assert!(open(&cipher, 4, b"header", &sealed).is_err());
+        assert!(open(&cipher, 3, b"headex", &sealed).is_err());
+        for i in [0, NONCE_LEN, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert!(open(&cipher, 3, b"header", &tampered).is_err());
+        }
+        assert!(open(&cipher, 3, b"header", &sealed[..sealed.len() - 1]).is_err());
+        assert!(open(&cipher, 3, b"header", &sealed[..OVERHEAD - 1]).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use super::corrupt;
+use crate::book::{BookSide, OrderBook};
+use crate::client::Result;
+use crate::delta::diff;
+use crate::symbology::{InstrumentId, Symbol};
+use crate::types::{ContractSpec, OrderBookSnapshot, PriceLevel};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Levels of a book that changed since the previous update of its symbol,
+/// or all of its levels for a keyframe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BookUpdate {
+    pub symbol: Symbol,
+    /// Per-symbol sequence number, increasing through the recording
+    pub seq: u64,
+    /// Sequence number of the update the levels apply to; `None` for a
+    /// keyframe
+    pub base: Option<u64>,
+    /// Changed levels, with size 0 for removed ones
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+    pub timestamp: DateTime<Utc>,
+    pub instrument_id: Option<InstrumentId>,
+    pub contract: Option<ContractSpec>,
+    pub received: Option<DateTime<Utc>>,
+}
+
+/// Writer side: turns snapshots into updates against the previous one
+#[derive(Debug, Default)]
+pub(crate) struct DeltaEncoder {
+    seqs: HashMap<Symbol, u64>,
+    /// Last snapshot per symbol in the current block
+    last: HashMap<Symbol, OrderBookSnapshot>,
+}
+
+impl DeltaEncoder {
+    /// The update reproducing `snapshot`, or `None` if the snapshot is not
+    /// in the canonical form a rebuilt book has and must be kept whole
+    pub fn encode(&mut self, snapshot: &OrderBookSnapshot) -> Option<BookUpdate> {
+        if !is_canonical(snapshot) {
+            self.last.remove(&snapshot.symbol);
+            return None;
+        }
+        let seq = self.seqs.entry(snapshot.symbol).or_default();
+        *seq += 1;
+        let (base, bids, asks) = match self.last.get(&snapshot.symbol) {
+            Some(last) => (
+                Some(*seq - 1),
+                diff(&last.bids, &snapshot.bids),
+                diff(&last.asks, &snapshot.asks),
+            ),
+            None => (None, snapshot.bids.clone(), snapshot.asks.clone()),
+        };
+        let update = BookUpdate {
+            symbol: snapshot.symbol,
+            seq: *seq,
+            base,
+            bids,
+            asks,
+            timestamp: snapshot.timestamp,
+            instrument_id: snapshot.instrument_id,
+            contract: snapshot.contract,
+            received: snapshot.received,
+        };
+        self.last.insert(snapshot.symbol, snapshot.clone());
+        Some(update)
+    }
+
+    /// Start each block with keyframes, so blocks decode on their own
+    pub fn reset(&mut self) {
+        self.last.clear();
+    }
+}
+
+/// Reader side: rebuilds the snapshots an encoder was given
+#[derive(Debug, Default)]
+pub(crate) struct DeltaDecoder {
+    books: HashMap<Symbol, (u64, OrderBook)>,
+}
+
+impl DeltaDecoder {
+    pub fn apply(&mut self, update: BookUpdate) -> Result<OrderBookSnapshot> {
+        let mut book = match update.base {
+            None => OrderBook::new(update.symbol),
+            Some(base) => match self.books.remove(&update.symbol) {
+                Some((seq, book)) if seq == base => book,
+                _ => {
+                    return Err(corrupt(format!(
+                        "book update {} of {} without its base {}",
+                        update.seq, update.symbol, base
+                    )))
+                }
+            },
+        };
+        for (side, levels) in [(BookSide::Bid, update.bids), (BookSide::Ask, update.asks)] {
+            for level in levels {
+                book.update_level(side, level, update.timestamp);
+            }
+        }
+        let mut snapshot = book.snapshot(None);
+        snapshot.timestamp = update.timestamp;
+        snapshot.instrument_id = update.instrument_id;
+        snapshot.contract = update.contract;
+        snapshot.received = update.received;
+        self.books.insert(update.symbol, (update.seq, book));
+        Ok(snapshot)
+    }
+}
+
+/// Whether levels are sorted best first with distinct prices and positive
+/// sizes, as [`OrderBook::snapshot`] produces them
+fn is_canonical(snapshot: &OrderBookSnapshot) -> bool {
+    let positive = |levels: &[PriceLevel]| levels.iter().all(|level| level.size > 0.0);
+    positive(&snapshot.bids)
+        && positive(&snapshot.asks)
+        && snapshot.bids.windows(2).all(|w| w[0].price > w[1].price)
+        && snapshot.asks.windows(2).all(|w| w[0].price < w[1].price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{BookReconstructor, RecordingReader, RecordingWriter};
+    use crate::types::{MarketDataMessage, OrderBookSnapshot, PriceLevel};
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn level(price: f64, size: f64) -> PriceLevel {
+        PriceLevel {
+            price,
+            size,
+            num_orders: 1,
+        }
+    }
+
+    #[test]
+    fn test_deltas_replay_books_exactly() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deltas.mds");
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let books: Vec<_> = (0..10)
+            .map(|i| OrderBookSnapshot {
+                symbol: "BTCUSD".into(),
+                bids: vec![level(100.0, 1.0 + i as f64), level(99.0, 2.0)],
+                asks: match i {
+                    // Out of order, so kept whole
+                    4 => vec![level(102.0, 1.0), level(101.0, 1.0)],
+                    _ => vec![level(101.0 + (i % 3) as f64, 1.0)],
+                },
+                timestamp: t0 + Duration::seconds(i),
+                instrument_id: None,
+                contract: None,
+                received: Some(t0 + Duration::seconds(i) + Duration::milliseconds(3)),
+            })
+            .collect();
+
+        let mut writer = RecordingWriter::create(&path, 4)
+            .unwrap()
+            .with_book_deltas();
+        for book in &books {
+            writer
+                .write(&MarketDataMessage::OrderBook(book.clone()))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut reader = RecordingReader::open(&path).unwrap();
+        let replayed: Vec<_> = reader
+            .range(t0 + Duration::seconds(6)..)
+            .map(|entry| match entry.unwrap().1 {
+                MarketDataMessage::OrderBook(book) => book,
+                other => panic!("expected book, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(replayed.len(), 4);
+        for (replayed, original) in replayed.iter().zip(&books[6..]) {
+            assert_eq!(
+                (&replayed.bids, &replayed.asks, replayed.timestamp),
+                (&original.bids, &original.asks, original.timestamp)
+            );
+            assert_eq!(replayed.received, original.received);
+        }
+        let all: Vec<_> = reader.messages().map(|entry| entry.unwrap()).collect();
+        assert!(
+            matches!(&all[4].1, MarketDataMessage::OrderBook(book) if book.asks[0].price == 102.0)
+        );
+
+        let book = BookReconstructor::open(&path)
+            .unwrap()
+            .book_at("BTCUSD", t0 + Duration::seconds(7))
+            .unwrap()
+            .unwrap();
+        assert_eq!(book.best_bid().unwrap().size, 8.0);
+        assert_eq!(book.best_ask().unwrap().price, 102.0);
+    }
+}
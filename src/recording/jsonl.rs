@@ -0,0 +1,68 @@
+use super::io_error;
+use crate::client::{ClientError, Result};
+use crate::types::MarketDataMessage;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::Path;
+
+/// Reads JSON-lines captures, one serialized message per line
+pub struct JsonLinesReader {
+    lines: Lines<BufReader<File>>,
+}
+
+impl JsonLinesReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path).map_err(io_error)?;
+        Ok(Self {
+            lines: BufReader::new(file).lines(),
+        })
+    }
+}
+
+impl Iterator for JsonLinesReader {
+    type Item = Result<MarketDataMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(io_error(e))),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(
+                serde_json::from_str(&line).map_err(|e| ClientError::Parse(e.to_string())),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Trade;
+    use std::io::Write;
+
+    #[test]
+    fn test_reads_lines_and_reports_bad_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.jsonl");
+        let trade = MarketDataMessage::Trade(Trade::test("BTCUSD", 100.0));
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "{}", serde_json::to_string(&trade).unwrap()).unwrap();
+        writeln!(file, "  ").unwrap();
+        writeln!(file, "{{\"type\":\"Trade\"").unwrap();
+        write!(file, "{{\"type\":\"Heartbeat\"}}").unwrap();
+        drop(file);
+
+        let read: Vec<Result<MarketDataMessage>> = JsonLinesReader::open(&path).unwrap().collect();
+        assert_eq!(read.len(), 3);
+        assert!(matches!(&read[0], Ok(MarketDataMessage::Trade(t)) if t.price == 100.0));
+        // A bad line is reported without ending the capture
+        assert!(matches!(read[1], Err(ClientError::Parse(_))));
+        assert!(matches!(read[2], Ok(MarketDataMessage::Heartbeat)));
+
+        assert!(JsonLinesReader::open(dir.path().join("missing.jsonl")).is_err());
+    }
+}
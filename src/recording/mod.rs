@@ -0,0 +1,260 @@
+//! Compact binary recording format.
+//!
+//! Recordings are a sequence of zstd-compressed blocks followed by a time
+//! index, so a [`RecordingReader`] can seek straight to the blocks covering a
+//! time range instead of scanning the whole capture.
+//!
+//! ```text
+//! header  MAGIC
+//! block   [compressed_len: u32][records: u32][flags: u32][min_ts: i64][max_ts: i64][crc32: u32][zstd payload]
+//! ...
+//! footer  [postcard Vec<BlockIndex>][index_len: u32][INDEX_MAGIC]
+//! ```
+//!
+//! A block payload is a run of `[len: u32][ts: i64][postcard record]` entries.
+//! All integers are little endian and timestamps are nanoseconds since the
+//! Unix epoch. Blocks flagged with [`BLOCK_CHECKPOINT`] start with a full book
+//! checkpoint for every symbol, which lets a
+//! [`BookReconstructor`] begin replay there. Files without a footer (e.g. after a crash) are still readable;
+//! the index is rebuilt by scanning block headers.
+//!
+//! Each block carries a CRC-32 of its stored payload, and finished recordings
+//! get a JSON [`Manifest`] next to them (`<file>.manifest.json`) with the
+//! file's length, SHA-256 and counts; [`verify`] checks all of these before
+//! a capture is trusted for replay. A [`RetentionManager`] uses the manifests
+//! to expire, compact or delete old recordings under a [`RetentionPolicy`],
+//! and an [`Uploader`] ships finished ones to object storage (S3-compatible
+//! with the `s3` feature) through a local spool directory. With the
+//! `tickstore` feature, a `TickStore` loads recordings and Parquet/Arrow
+//! captures into an embedded DuckDB database for ad hoc SQL queries per
+//! symbol and time range.
+//!
+//! Writers built with [`RecordingWriter::with_book_deltas`] journal order
+//! books as the levels that changed since the previous book of the symbol,
+//! numbered per symbol, with a full keyframe at the start of every block.
+//! Readers check each delta against the sequence it was based on and
+//! rebuild the written snapshots exactly, also when seeking into the middle
+//! of a file.
+//!
+//! Writers send their output to a [`StorageBackend`]: a [`FileStorage`] by
+//! default, a [`MemoryStorage`], or any custom destination.
+//!
+//! Writers given a [`RecordingKey`], or a [`KeyProvider`] such as an
+//! [`EnvKey`] or a KMS hook, seal each compressed payload with AES-256-GCM
+//! as `[nonce: 12][ciphertext][tag: 16]` and flag the block with
+//! [`BLOCK_ENCRYPTED`]. Block headers and the index stay readable, so time
+//! seeks work without the key, but the header (except its checksum) and the
+//! block's position in the file are authenticated with the payload.
+
+mod compact;
+mod crypto;
+mod deltas;
+mod jsonl;
+#[cfg(feature = "tickstore")]
+mod query;
+mod reader;
+mod reconstruct;
+mod retention;
+mod storage;
+mod upload;
+mod verify;
+mod writer;
+
+pub use compact::{compact, CompactOptions, CompactionReport};
+pub use crypto::{EnvKey, KeyProvider, RecordingKey};
+pub use jsonl::JsonLinesReader;
+#[cfg(feature = "tickstore")]
+pub use query::{QueryResult, TickStore};
+pub use reader::{RecordingIter, RecordingReader};
+pub use reconstruct::BookReconstructor;
+pub use retention::{RetentionManager, RetentionPolicy, RetentionReport};
+pub use storage::{FileStorage, MemoryStorage, StorageBackend};
+#[cfg(feature = "s3")]
+pub use upload::S3Store;
+pub use upload::{LocalStore, ObjectStore, UploadReport, Uploader};
+pub use verify::{manifest_path, verify, Manifest, VerifyReport};
+pub use writer::RecordingWriter;
+
+use crate::client::{ClientError, Result};
+use crate::symbology::{InstrumentId, Symbol};
+use crate::types::{
+    ContractSpec, MarketDataMessage, OrderBookSnapshot, PriceLevel, Quote, Trade, TradeBust,
+    TradeConditions, TradeCorrection, TradeSide, TradeTerms,
+};
+use chrono::{DateTime, Utc};
+use deltas::{BookUpdate, DeltaDecoder};
+use serde::{Deserialize, Serialize};
+
+/// Bumped whenever the record encoding changes, so older recordings are
+/// rejected instead of misread
+pub(crate) const MAGIC: &[u8; 8] = b"MDSREC07";
+pub(crate) const INDEX_MAGIC: &[u8; 8] = b"MDSIDX01";
+pub(crate) const BLOCK_HEADER_LEN: usize = 32;
+
+/// Block flag: the block begins with book checkpoints
+pub const BLOCK_CHECKPOINT: u32 = 1;
+
+/// Block flag: the payload is encrypted
+pub const BLOCK_ENCRYPTED: u32 = 2;
+
+/// Default number of messages per compressed block
+pub const DEFAULT_BLOCK_RECORDS: u32 = 4096;
+
+/// Location and time span of one block in a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockIndex {
+    pub offset: u64,
+    pub records: u32,
+    pub flags: u32,
+    pub min_ts: i64,
+    pub max_ts: i64,
+}
+
+// `MarketDataMessage` is internally tagged, which non self-describing formats
+// cannot decode, so records use an externally tagged mirror.
+#[derive(Serialize)]
+enum RecordRef<'a> {
+    Trade(#[serde(with = "TradeDef")] &'a Trade),
+    Quote(#[serde(with = "QuoteDef")] &'a Quote),
+    OrderBook(#[serde(with = "OrderBookDef")] &'a OrderBookSnapshot),
+    Heartbeat,
+    Checkpoint(#[serde(with = "OrderBookDef")] &'a OrderBookSnapshot),
+    TradeCorrection(#[serde(with = "TradeCorrectionDef")] &'a TradeCorrection),
+    TradeBust(#[serde(with = "TradeBustDef")] &'a TradeBust),
+    BookDelta(&'a BookUpdate),
+}
+
+#[derive(Deserialize)]
+pub(crate) enum Record {
+    Trade(#[serde(with = "TradeDef")] Trade),
+    Quote(#[serde(with = "QuoteDef")] Quote),
+    OrderBook(#[serde(with = "OrderBookDef")] OrderBookSnapshot),
+    Heartbeat,
+    /// Book state written by the recorder, not part of the original feed
+    Checkpoint(#[serde(with = "OrderBookDef")] OrderBookSnapshot),
+    TradeCorrection(#[serde(with = "TradeCorrectionDef")] TradeCorrection),
+    TradeBust(#[serde(with = "TradeBustDef")] TradeBust),
+    /// Book stored as changed levels, see [`RecordingWriter::with_book_deltas`]
+    BookDelta(BookUpdate),
+}
+
+// Messages skip unset optional fields in JSON, which positional formats
+// cannot tell apart from the next field, so records keep every field.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Trade")]
+struct TradeDef {
+    symbol: Symbol,
+    price: f64,
+    quantity: f64,
+    side: TradeSide,
+    timestamp: DateTime<Utc>,
+    trade_id: String,
+    conditions: TradeConditions,
+    instrument_id: Option<InstrumentId>,
+    contract: Option<ContractSpec>,
+    received: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Quote")]
+struct QuoteDef {
+    symbol: Symbol,
+    bid_price: f64,
+    bid_size: f64,
+    ask_price: f64,
+    ask_size: f64,
+    timestamp: DateTime<Utc>,
+    instrument_id: Option<InstrumentId>,
+    contract: Option<ContractSpec>,
+    received: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "OrderBookSnapshot")]
+struct OrderBookDef {
+    symbol: Symbol,
+    bids: Vec<PriceLevel>,
+    asks: Vec<PriceLevel>,
+    timestamp: DateTime<Utc>,
+    instrument_id: Option<InstrumentId>,
+    contract: Option<ContractSpec>,
+    received: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "TradeCorrection")]
+struct TradeCorrectionDef {
+    symbol: Symbol,
+    trade_id: String,
+    timestamp: DateTime<Utc>,
+    original: Option<TradeTerms>,
+    corrected: TradeTerms,
+    instrument_id: Option<InstrumentId>,
+    contract: Option<ContractSpec>,
+    received: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "TradeBust")]
+struct TradeBustDef {
+    symbol: Symbol,
+    trade_id: String,
+    timestamp: DateTime<Utc>,
+    original: Option<TradeTerms>,
+    instrument_id: Option<InstrumentId>,
+    contract: Option<ContractSpec>,
+    received: Option<DateTime<Utc>>,
+}
+
+impl<'a> From<&'a MarketDataMessage> for RecordRef<'a> {
+    fn from(msg: &'a MarketDataMessage) -> Self {
+        match msg {
+            MarketDataMessage::Trade(trade) => RecordRef::Trade(trade),
+            MarketDataMessage::Quote(quote) => RecordRef::Quote(quote),
+            MarketDataMessage::OrderBook(book) => RecordRef::OrderBook(book),
+            MarketDataMessage::Heartbeat => RecordRef::Heartbeat,
+            MarketDataMessage::TradeCorrection(correction) => {
+                RecordRef::TradeCorrection(correction)
+            }
+            MarketDataMessage::TradeBust(bust) => RecordRef::TradeBust(bust),
+        }
+    }
+}
+
+impl Record {
+    /// The recorded feed message, or `None` for checkpoints. Book deltas are
+    /// rebuilt against the books `deltas` has seen so far.
+    pub(crate) fn into_message(
+        self,
+        deltas: &mut DeltaDecoder,
+    ) -> Result<Option<MarketDataMessage>> {
+        Ok(match self {
+            Record::Trade(trade) => Some(MarketDataMessage::Trade(trade)),
+            Record::Quote(quote) => Some(MarketDataMessage::Quote(quote)),
+            Record::OrderBook(book) => Some(MarketDataMessage::OrderBook(book)),
+            Record::Heartbeat => Some(MarketDataMessage::Heartbeat),
+            Record::Checkpoint(_) => None,
+            Record::TradeCorrection(correction) => {
+                Some(MarketDataMessage::TradeCorrection(correction))
+            }
+            Record::TradeBust(bust) => Some(MarketDataMessage::TradeBust(bust)),
+            Record::BookDelta(update) => Some(MarketDataMessage::OrderBook(deltas.apply(update)?)),
+        })
+    }
+}
+
+pub(crate) fn to_nanos(ts: DateTime<Utc>) -> i64 {
+    ts.timestamp_nanos_opt().unwrap_or(i64::MAX)
+}
+
+pub(crate) fn from_nanos(nanos: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_nanos(nanos)
+}
+
+pub(crate) fn io_error(e: std::io::Error) -> ClientError {
+    ClientError::Io(e.to_string())
+}
+
+pub(crate) fn corrupt(reason: impl Into<String>) -> ClientError {
+    ClientError::Parse(format!("corrupt recording: {}", reason.into()))
+}
@@ -0,0 +1,715 @@
+use super::{to_nanos, RecordingReader};
+use crate::client::{ClientError, Result};
+use crate::types::MarketDataMessage;
+use arrow_ipc::reader::FileReader;
+use chrono::{DateTime, Utc};
+use duckdb::arrow::array::{Array, RecordBatch};
+use duckdb::arrow::compute::cast;
+use duckdb::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use duckdb::types::{TimeUnit as DbTimeUnit, ValueRef};
+use duckdb::vtab::arrow::ArrowVTab;
+use duckdb::vtab::arrow_recordbatch_to_query_params;
+use duckdb::{params, Connection};
+use serde::Serialize;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::sync::Arc;
+
+const SCHEMA: &str = "
+CREATE SCHEMA IF NOT EXISTS capture;
+CREATE TABLE IF NOT EXISTS capture.captures (path VARCHAR PRIMARY KEY, messages BIGINT NOT NULL);
+CREATE TABLE IF NOT EXISTS capture.trades (
+    symbol VARCHAR NOT NULL, ts BIGINT NOT NULL, price DOUBLE NOT NULL, quantity DOUBLE NOT NULL,
+    side VARCHAR NOT NULL, trade_id VARCHAR NOT NULL, conditions INTEGER NOT NULL,
+    instrument_id VARCHAR
+);
+CREATE TABLE IF NOT EXISTS capture.quotes (
+    symbol VARCHAR NOT NULL, ts BIGINT NOT NULL, bid_price DOUBLE NOT NULL,
+    bid_size DOUBLE NOT NULL, ask_price DOUBLE NOT NULL, ask_size DOUBLE NOT NULL,
+    instrument_id VARCHAR
+);
+CREATE TABLE IF NOT EXISTS capture.books (
+    symbol VARCHAR NOT NULL, ts BIGINT NOT NULL, side VARCHAR NOT NULL, level INTEGER NOT NULL,
+    price DOUBLE NOT NULL, size DOUBLE NOT NULL, num_orders INTEGER NOT NULL,
+    instrument_id VARCHAR
+);
+CREATE TABLE IF NOT EXISTS capture.pending_amends (
+    seq BIGINT NOT NULL, symbol VARCHAR NOT NULL, trade_id VARCHAR NOT NULL, price DOUBLE,
+    quantity DOUBLE, bust BOOLEAN NOT NULL
+);
+";
+
+/// Apply the latest held amendment of every trade loaded so far
+const SETTLE_AMENDS: &str = "
+CREATE OR REPLACE TEMP TABLE settling AS
+    SELECT * FROM capture.pending_amends a
+    WHERE EXISTS (
+        SELECT 1 FROM capture.trades t WHERE t.symbol = a.symbol AND t.trade_id = a.trade_id
+    )
+    QUALIFY row_number() OVER (PARTITION BY symbol, trade_id ORDER BY seq DESC) = 1;
+UPDATE capture.trades
+    SET price = coalesce(s.price, trades.price), quantity = coalesce(s.quantity, trades.quantity)
+    FROM settling s
+    WHERE NOT s.bust AND trades.symbol = s.symbol AND trades.trade_id = s.trade_id;
+DELETE FROM capture.trades USING settling s
+    WHERE s.bust AND trades.symbol = s.symbol AND trades.trade_id = s.trade_id;
+DELETE FROM capture.pending_amends USING settling s
+    WHERE pending_amends.symbol = s.symbol AND pending_amends.trade_id = s.trade_id;
+DROP TABLE settling;
+";
+
+/// Tables scoped per query, named like the [`crate::flight::BatchKind`]
+/// layouts Parquet and Arrow captures are written in
+const TABLES: [&str; 3] = ["trades", "quotes", "books"];
+
+/// Columns and rows returned by [`TickStore::query`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+}
+
+/// Captures loaded into an embedded DuckDB database (the `tickstore`
+/// feature), queryable per symbol and time range.
+///
+/// Registered `.mds` recordings and `.parquet` or Arrow IPC (`.arrow`)
+/// captures fill the `trades`, `quotes` and `books` tables (one row per book
+/// level) of the `capture` schema, with `ts` in nanoseconds since the Unix
+/// epoch. Parquet and Arrow captures use the Flight batch layouts, one kind
+/// per file, told apart by their columns; Parquet timestamps keep the
+/// precision DuckDB reads them at, microseconds for UTC-adjusted ones.
+/// Queries are DuckDB SQL.
+///
+/// Trade corrections and busts update or delete the trades they amend. Ones
+/// seen before their trade, also in an earlier capture, are held back and
+/// applied when the trade is loaded.
+pub struct TickStore {
+    db: Connection,
+}
+
+impl TickStore {
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory().map_err(db_error)?)
+    }
+
+    /// Keep the store in a database file, so captures stay registered
+    /// across restarts
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::init(Connection::open(path).map_err(db_error)?)
+    }
+
+    fn init(db: Connection) -> Result<Self> {
+        db.execute_batch(SCHEMA).map_err(db_error)?;
+        db.register_table_function::<ArrowVTab>("arrow")
+            .map_err(db_error)?;
+        Ok(Self { db })
+    }
+
+    /// Load a `.mds` recording, or a `.parquet` or `.arrow` capture; returns
+    /// the messages or rows loaded, 0 if it was already registered
+    pub fn register(&mut self, capture: impl AsRef<Path>) -> Result<u64> {
+        let path = capture.as_ref();
+        let key = std::fs::canonicalize(path)
+            .map_err(|e| ClientError::Io(format!("{}: {}", path.display(), e)))?
+            .to_string_lossy()
+            .into_owned();
+        let known: i64 = self
+            .db
+            .query_row(
+                "SELECT count(*) FROM capture.captures WHERE path = ?",
+                [&key],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        if known > 0 {
+            return Ok(0);
+        }
+
+        let tx = self.db.transaction().map_err(db_error)?;
+        let loaded = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("parquet") => load_parquet(&tx, &key)?,
+            Some("arrow") => load_arrow(&tx, path)?,
+            _ => load_recording(&tx, path)?,
+        };
+        tx.execute_batch(SETTLE_AMENDS).map_err(db_error)?;
+        tx.execute(
+            "INSERT INTO capture.captures VALUES (?, ?)",
+            params![key, loaded as i64],
+        )
+        .map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+        Ok(loaded)
+    }
+
+    /// Register every `.mds`, `.parquet` and `.arrow` capture in `dir`
+    pub fn register_dir(&mut self, dir: impl AsRef<Path>) -> Result<u64> {
+        let dir = dir.as_ref();
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| ClientError::Io(format!("{}: {}", dir.display(), e)))?;
+        let mut paths: Vec<_> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "mds" || ext == "parquet" || ext == "arrow")
+            })
+            .collect();
+        paths.sort();
+        let mut loaded = 0;
+        for path in paths {
+            loaded += self.register(path)?;
+        }
+        Ok(loaded)
+    }
+
+    /// Run a single read-only query `sql` over `trades`, `quotes` and
+    /// `books` restricted to `symbol` within `range`, e.g.
+    /// `SELECT sum(price * quantity) / sum(quantity) AS vwap FROM trades`.
+    ///
+    /// The restriction is a convenience, not access control: the tables
+    /// stay reachable as `capture.trades` and so on, and DuckDB functions
+    /// may read files, so don't run SQL from untrusted callers.
+    pub fn query(
+        &self,
+        symbol: &str,
+        range: impl RangeBounds<DateTime<Utc>>,
+        sql: &str,
+    ) -> Result<QueryResult> {
+        let start = match range.start_bound() {
+            Bound::Included(t) => to_nanos(*t),
+            Bound::Excluded(t) => to_nanos(*t).saturating_add(1),
+            Bound::Unbounded => i64::MIN,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(t) => to_nanos(*t),
+            Bound::Excluded(t) => to_nanos(*t).saturating_sub(1),
+            Bound::Unbounded => i64::MAX,
+        };
+        let views: String = TABLES
+            .iter()
+            .map(|table| {
+                format!(
+                    "CREATE OR REPLACE TEMP VIEW {table} AS SELECT * FROM capture.{table} \
+                     WHERE symbol = {} AND ts BETWEEN {start} AND {end};",
+                    literal(symbol)
+                )
+            })
+            .collect();
+        self.db.execute_batch(&views).map_err(db_error)?;
+
+        // A subquery admits exactly one query and no other statement
+        let parse = |e: duckdb::Error| ClientError::Parse(format!("query: {}", e));
+        let sql = sql.trim().trim_end_matches(';');
+        let mut stmt = self
+            .db
+            .prepare(&format!("SELECT * FROM (\n{}\n)", sql))
+            .map_err(parse)?;
+        let mut rows = stmt.query([]).map_err(parse)?;
+        let mut result = QueryResult {
+            columns: rows
+                .as_ref()
+                .map(|stmt| stmt.column_names())
+                .unwrap_or_default(),
+            rows: Vec::new(),
+        };
+        while let Some(row) = rows.next().map_err(parse)? {
+            let values = (0..result.columns.len())
+                .map(|i| row.get_ref(i).map(json_value))
+                .collect::<std::result::Result<_, _>>()
+                .map_err(parse)?;
+            result.rows.push(values);
+        }
+        Ok(result)
+    }
+}
+
+fn load_recording(db: &Connection, path: &Path) -> Result<u64> {
+    let mut reader = RecordingReader::open(path)?;
+    let mut seq: i64 = db
+        .query_row(
+            "SELECT coalesce(max(seq), 0) FROM capture.pending_amends",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(db_error)?;
+    let mut trades = db.appender_to_db("trades", "capture").map_err(db_error)?;
+    let mut quotes = db.appender_to_db("quotes", "capture").map_err(db_error)?;
+    let mut books = db.appender_to_db("books", "capture").map_err(db_error)?;
+    let mut amends = db
+        .appender_to_db("pending_amends", "capture")
+        .map_err(db_error)?;
+    let mut loaded = 0u64;
+    for item in reader.messages() {
+        let (ts, msg) = item?;
+        let ts = to_nanos(ts);
+        let appended = match &msg {
+            MarketDataMessage::Trade(t) => trades.append_row(params![
+                t.symbol.as_str(),
+                ts,
+                t.price,
+                t.quantity,
+                t.side.as_str(),
+                t.trade_id,
+                t.conditions.bits(),
+                t.instrument_id.as_deref()
+            ]),
+            MarketDataMessage::Quote(q) => quotes.append_row(params![
+                q.symbol.as_str(),
+                ts,
+                q.bid_price,
+                q.bid_size,
+                q.ask_price,
+                q.ask_size,
+                q.instrument_id.as_deref()
+            ]),
+            MarketDataMessage::OrderBook(b) => {
+                let mut appended = Ok(());
+                for (side, levels) in [("bid", &b.bids), ("ask", &b.asks)] {
+                    for (i, level) in levels.iter().enumerate() {
+                        appended = appended.and_then(|_| {
+                            books.append_row(params![
+                                b.symbol.as_str(),
+                                ts,
+                                side,
+                                i as u32,
+                                level.price,
+                                level.size,
+                                level.num_orders,
+                                b.instrument_id.as_deref()
+                            ])
+                        });
+                    }
+                }
+                appended
+            }
+            // Held until the capture is loaded, then applied to the trades
+            // they amend
+            MarketDataMessage::TradeCorrection(c) => {
+                seq += 1;
+                amends.append_row(params![
+                    seq,
+                    c.symbol.as_str(),
+                    c.trade_id,
+                    c.corrected.price,
+                    c.corrected.quantity,
+                    false
+                ])
+            }
+            MarketDataMessage::TradeBust(b) => {
+                seq += 1;
+                amends.append_row(params![
+                    seq,
+                    b.symbol.as_str(),
+                    b.trade_id,
+                    None::<f64>,
+                    None::<f64>,
+                    true
+                ])
+            }
+            _ => continue,
+        };
+        appended.map_err(db_error)?;
+        loaded += 1;
+    }
+    for appender in [&mut trades, &mut quotes, &mut books, &mut amends] {
+        appender.flush().map_err(db_error)?;
+    }
+    Ok(loaded)
+}
+
+fn load_parquet(db: &Connection, path: &str) -> Result<u64> {
+    let columns = {
+        let mut stmt = db
+            .prepare(&format!("SELECT name FROM parquet_schema({})", literal(path)))
+            .map_err(db_error)?;
+        let names = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(db_error)?;
+        names
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(db_error)?
+    };
+    let table = table_for(columns.iter().map(String::as_str))?;
+    let inserted = db
+        .execute(
+            &format!(
+                "INSERT INTO capture.{table} BY NAME \
+                 SELECT * EXCLUDE (\"timestamp\"), epoch_ns(\"timestamp\") AS ts FROM read_parquet({})",
+                literal(path)
+            ),
+            [],
+        )
+        .map_err(db_error)?;
+    Ok(inserted as u64)
+}
+
+fn load_arrow(db: &Connection, path: &Path) -> Result<u64> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| ClientError::Io(format!("{}: {}", path.display(), e)))?;
+    let arrow_error = |e: duckdb::arrow::error::ArrowError| {
+        ClientError::Parse(format!("{}: {}", path.display(), e))
+    };
+    let reader = FileReader::try_new(file, None).map_err(arrow_error)?;
+    let schema = reader.schema();
+    let table = table_for(schema.fields().iter().map(|field| field.name().as_str()))?;
+    let timestamp = schema
+        .index_of("timestamp")
+        .map_err(arrow_error)?;
+    let mut loaded = 0;
+    for batch in reader {
+        let batch = with_nanos(batch.map_err(arrow_error)?, timestamp).map_err(arrow_error)?;
+        loaded += batch.num_rows() as u64;
+        db.execute(
+            &format!("INSERT INTO capture.{table} BY NAME SELECT * FROM arrow(?, ?)"),
+            arrow_recordbatch_to_query_params(batch),
+        )
+        .map_err(db_error)?;
+    }
+    Ok(loaded)
+}
+
+/// `batch` with its `timestamp` column as a `ts` column of nanoseconds
+fn with_nanos(
+    batch: RecordBatch,
+    timestamp: usize,
+) -> std::result::Result<RecordBatch, duckdb::arrow::error::ArrowError> {
+    let nanos = cast(
+        batch.column(timestamp),
+        &DataType::Timestamp(TimeUnit::Nanosecond, None),
+    )?;
+    let ts = cast(&nanos, &DataType::Int64)?;
+    let mut fields: Vec<Field> = Vec::new();
+    let mut columns = Vec::new();
+    for (i, field) in batch.schema().fields().iter().enumerate() {
+        if i == timestamp {
+            fields.push(Field::new("ts", DataType::Int64, ts.is_nullable()));
+            columns.push(Arc::clone(&ts));
+        } else {
+            fields.push(field.as_ref().clone());
+            columns.push(Arc::clone(batch.column(i)));
+        }
+    }
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+}
+
+/// Table a Parquet or Arrow capture loads into, by its columns
+fn table_for<'a>(mut columns: impl Iterator<Item = &'a str> + Clone) -> Result<&'static str> {
+    let has = |name| columns.clone().any(|column| column == name);
+    let table = if has("trade_id") {
+        "trades"
+    } else if has("bid_price") {
+        "quotes"
+    } else if has("level") {
+        "books"
+    } else {
+        return Err(ClientError::Parse(
+            "capture has no trade, quote or book columns".to_string(),
+        ));
+    };
+    if !columns.any(|column| column == "timestamp") {
+        return Err(ClientError::Parse(
+            "capture has no timestamp column".to_string(),
+        ));
+    }
+    Ok(table)
+}
+
+/// `value` as a SQL string literal
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn json_value(value: ValueRef) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Boolean(b) => b.into(),
+        ValueRef::TinyInt(i) => i.into(),
+        ValueRef::SmallInt(i) => i.into(),
+        ValueRef::Int(i) => i.into(),
+        ValueRef::BigInt(i) => i.into(),
+        ValueRef::HugeInt(i) => match i64::try_from(i) {
+            Ok(i) => i.into(),
+            Err(_) => (i as f64).into(),
+        },
+        ValueRef::UTinyInt(i) => i.into(),
+        ValueRef::USmallInt(i) => i.into(),
+        ValueRef::UInt(i) => i.into(),
+        ValueRef::UBigInt(i) => i.into(),
+        ValueRef::Float(f) => f.into(),
+        ValueRef::Double(f) => f.into(),
+        ValueRef::Decimal(d) => d.to_string().parse::<f64>().map_or(serde_json::Value::Null, Into::into),
+        ValueRef::Timestamp(unit, value) => {
+            let nanos = match unit {
+                DbTimeUnit::Second => value.saturating_mul(1_000_000_000),
+                DbTimeUnit::Millisecond => value.saturating_mul(1_000_000),
+                DbTimeUnit::Microsecond => value.saturating_mul(1_000),
+                DbTimeUnit::Nanosecond => value,
+            };
+            DateTime::from_timestamp_nanos(nanos).to_rfc3339().into()
+        }
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into(),
+        ValueRef::Blob(blob) => blob
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+            .into(),
+        other => format!("{:?}", other.to_owned()).into(),
+    }
+}
+
+fn db_error(e: duckdb::Error) -> ClientError {
+    ClientError::Io(format!("tick store: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::RecordingWriter;
+    use super::*;
+    use crate::types::{Quote, Trade, TradeBust, TradeConditions, TradeCorrection, TradeTerms};
+    use chrono::TimeZone;
+
+    fn trade(symbol: &str, price: f64, quantity: f64, ts: DateTime<Utc>) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            quantity,
+            timestamp: ts,
+            ..Trade::test(symbol, price)
+        })
+    }
+
+    #[test]
+    fn test_query_symbol_and_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("day.mds");
+        let t = |m| Utc.with_ymd_and_hms(2024, 3, 5, 14, m, 0).unwrap();
+        let mut writer = RecordingWriter::create(&path, 16).unwrap();
+        writer.write(&trade("BTCUSD", 100.0, 1.0, t(0))).unwrap();
+        writer.write(&trade("BTCUSD", 110.0, 3.0, t(1))).unwrap();
+        writer.write(&trade("ETHUSD", 10.0, 5.0, t(1))).unwrap();
+        writer
+            .write(&MarketDataMessage::Quote(Quote {
+                ask_size: 2.0,
+                timestamp: t(2),
+                ..Quote::test("BTCUSD", 109.0, 111.0)
+            }))
+            .unwrap();
+        writer.write(&trade("BTCUSD", 200.0, 1.0, t(5))).unwrap();
+        writer.finish().unwrap();
+
+        let mut store = TickStore::open_in_memory().unwrap();
+        assert_eq!(store.register_dir(dir.path()).unwrap(), 5);
+        assert_eq!(store.register(&path).unwrap(), 0);
+
+        let result = store
+            .query(
+                "BTCUSD",
+                t(0)..t(5),
+                "SELECT count(*) AS n, sum(price * quantity) / sum(quantity) AS vwap FROM trades",
+            )
+            .unwrap();
+        assert_eq!(result.columns, ["n", "vwap"]);
+        assert_eq!(
+            result.rows,
+            [[serde_json::json!(2), serde_json::json!(107.5)]]
+        );
+
+        // Queries may bring their own common table expressions
+        let result = store
+            .query(
+                "BTCUSD",
+                ..,
+                "WITH spreads AS (SELECT ask_price - bid_price AS s FROM quotes) SELECT s FROM spreads",
+            )
+            .unwrap();
+        assert_eq!(result.rows, [[serde_json::json!(2.0)]]);
+        let result = store
+            .query(
+                "BTCUSD",
+                ..,
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 3)\n\
+                 SELECT count(*) FROM n, trades",
+            )
+            .unwrap();
+        assert_eq!(result.rows, [[serde_json::json!(9)]]);
+        let result = store
+            .query("ETHUSD", .., "WITH\nv AS (SELECT quantity FROM trades) SELECT * FROM v;")
+            .unwrap();
+        assert_eq!(result.rows, [[serde_json::json!(5.0)]]);
+        assert!(store
+            .query("BTCUSD", .., "SELECT nope FROM trades")
+            .is_err());
+
+        // Queries cannot change the store
+        assert!(store.query("BTCUSD", .., "DELETE FROM trades").is_err());
+        assert!(store
+            .query("BTCUSD", .., "SELECT 1; DROP TABLE trades")
+            .is_err());
+        let result = store
+            .query("BTCUSD", .., "SELECT count(*) FROM capture.trades")
+            .unwrap();
+        assert_eq!(result.rows, [[serde_json::json!(4)]]);
+    }
+
+    #[test]
+    fn test_amendments_before_their_trade() {
+        let dir = tempfile::tempdir().unwrap();
+        let t = |m| Utc.with_ymd_and_hms(2024, 3, 5, 14, m, 0).unwrap();
+        let traded = |id: &str, price| {
+            MarketDataMessage::Trade(Trade {
+                trade_id: id.to_string(),
+                timestamp: t(1),
+                ..Trade::test("BTCUSD", price)
+            })
+        };
+        let corrected = |id: &str, price| {
+            MarketDataMessage::TradeCorrection(TradeCorrection {
+                symbol: "BTCUSD".into(),
+                trade_id: id.to_string(),
+                timestamp: t(1),
+                original: None,
+                corrected: TradeTerms {
+                    price,
+                    quantity: 2.0,
+                    conditions: TradeConditions::empty(),
+                },
+                instrument_id: None,
+                contract: None,
+                received: None,
+            })
+        };
+        let busted = MarketDataMessage::TradeBust(TradeBust {
+            symbol: "BTCUSD".into(),
+            trade_id: "b".to_string(),
+            timestamp: t(1),
+            original: None,
+            instrument_id: None,
+            contract: None,
+            received: None,
+        });
+
+        // The amendments of a and b reach an earlier capture than the trades
+        let early = dir.path().join("1.mds");
+        let mut writer = RecordingWriter::create(&early, 16).unwrap();
+        writer.write(&corrected("a", 101.0)).unwrap();
+        writer.write(&busted).unwrap();
+        writer.finish().unwrap();
+        let late = dir.path().join("2.mds");
+        let mut writer = RecordingWriter::create(&late, 16).unwrap();
+        writer.write(&traded("a", 100.0)).unwrap();
+        writer.write(&traded("b", 100.0)).unwrap();
+        writer.write(&corrected("c", 103.0)).unwrap();
+        writer.write(&traded("c", 100.0)).unwrap();
+        writer.finish().unwrap();
+
+        let mut store = TickStore::open_in_memory().unwrap();
+        store.register(&early).unwrap();
+        store.register(&late).unwrap();
+        let result = store
+            .query(
+                "BTCUSD",
+                ..,
+                "SELECT trade_id, price, quantity FROM trades ORDER BY trade_id",
+            )
+            .unwrap();
+        let row = |id: &str, price: f64| {
+            vec![
+                serde_json::json!(id),
+                serde_json::json!(price),
+                serde_json::json!(2.0),
+            ]
+        };
+        assert_eq!(result.rows, [row("a", 101.0), row("c", 103.0)]);
+        let pending: i64 = store
+            .db
+            .query_row("SELECT count(*) FROM capture.pending_amends", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(pending, 0);
+    }
+
+    #[test]
+    fn test_register_parquet_and_arrow() {
+        use arrow_ipc::writer::FileWriter;
+        use duckdb::arrow::array::{Float64Array, StringArray, TimestampNanosecondArray};
+
+        let dir = tempfile::tempdir().unwrap();
+        let t = |m| Utc.with_ymd_and_hms(2024, 3, 5, 14, m, 0).unwrap();
+        let quotes = dir.path().join("quotes.parquet");
+        let writer = Connection::open_in_memory().unwrap();
+        writer
+            .execute_batch(&format!(
+                "COPY (SELECT 'BTCUSD' AS symbol, TIMESTAMPTZ '2024-03-05 14:01:00+00' AS timestamp, \
+                 99.0 AS bid_price, 1.0 AS bid_size, 101.0 AS ask_price, 2.0 AS ask_size, \
+                 NULL::VARCHAR AS instrument_id) TO {} (FORMAT parquet)",
+                literal(&quotes.to_string_lossy())
+            ))
+            .unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("symbol", DataType::Utf8, false),
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+                false,
+            ),
+            Field::new("price", DataType::Float64, false),
+            Field::new("quantity", DataType::Float64, false),
+            Field::new("side", DataType::Utf8, false),
+            Field::new("trade_id", DataType::Utf8, false),
+            Field::new("conditions", DataType::UInt8, false),
+            Field::new("instrument_id", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(StringArray::from(vec!["BTCUSD", "ETHUSD"])),
+                Arc::new(
+                    TimestampNanosecondArray::from(vec![
+                        to_nanos(t(1)) + 7,
+                        to_nanos(t(1)),
+                    ])
+                    .with_timezone("UTC"),
+                ),
+                Arc::new(Float64Array::from(vec![100.0, 10.0])),
+                Arc::new(Float64Array::from(vec![2.0, 1.0])),
+                Arc::new(StringArray::from(vec!["buy", "sell"])),
+                Arc::new(StringArray::from(vec!["1", "2"])),
+                Arc::new(duckdb::arrow::array::UInt8Array::from(vec![0, 0])),
+                Arc::new(StringArray::from(vec![None::<&str>, None])),
+            ],
+        )
+        .unwrap();
+        let trades = dir.path().join("trades.arrow");
+        let mut writer = FileWriter::try_new(std::fs::File::create(&trades).unwrap(), &schema)
+            .unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+
+        let mut store = TickStore::open_in_memory().unwrap();
+        assert_eq!(store.register_dir(dir.path()).unwrap(), 3);
+        let result = store
+            .query(
+                "BTCUSD",
+                t(1)..t(2),
+                "SELECT trades.ts, price, ask_price - bid_price FROM trades, quotes",
+            )
+            .unwrap();
+        assert_eq!(
+            result.rows,
+            [[
+                serde_json::json!(to_nanos(t(1)) + 7),
+                serde_json::json!(100.0),
+                serde_json::json!(2.0)
+            ]]
+        );
+
+        // Files of another layout are refused
+        let other = dir.path().join("other.parquet");
+        Connection::open_in_memory()
+            .unwrap()
+            .execute_batch(&format!(
+                "COPY (SELECT 1 AS x) TO {} (FORMAT parquet)",
+                literal(&other.to_string_lossy())
+            ))
+            .unwrap();
+        assert!(store.register(&other).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use super::crypto::{self, KeyProvider};
+use super::deltas::DeltaDecoder;
+use super::{
+    corrupt, from_nanos, io_error, to_nanos, BlockIndex, Record, BLOCK_ENCRYPTED, BLOCK_HEADER_LEN,
+    INDEX_MAGIC, MAGIC,
+};
+use crate::client::{ClientError, Result};
+use crate::types::MarketDataMessage;
+use aes_gcm::Aes256Gcm;
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+/// Largest decompressed block accepted, guarding against corrupt headers
+/// and decompression bombs
+const MAX_BLOCK_BYTES: u64 = 256 << 20;
+
+/// Reads recordings written by [`RecordingWriter`](super::RecordingWriter)
+pub struct RecordingReader {
+    file: BufReader<File>,
+    len: u64,
+    index: Vec<BlockIndex>,
+    cipher: Option<Aes256Gcm>,
+}
+
+impl RecordingReader {
+    /// Open a recording and load (or rebuild) its time index
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path).map_err(io_error)?);
+
+        let mut magic = [0u8; 8];
+        file.read_exact(&mut magic).map_err(io_error)?;
+        if &magic != MAGIC {
+            return Err(corrupt("bad magic"));
+        }
+
+        let len = file.seek(SeekFrom::End(0)).map_err(io_error)?;
+        let index = match Self::read_footer(&mut file)? {
+            Some((index, _)) => index,
+            None => Self::scan_blocks(&mut file, len)?.0,
+        };
+
+        Ok(Self {
+            file,
+            len,
+            index,
+            cipher: None,
+        })
+    }
+
+    /// Decrypt encrypted blocks with the key from `keys`
+    pub fn with_key(mut self, keys: &impl KeyProvider) -> Result<Self> {
+        self.cipher = Some(keys.recording_key()?.cipher());
+        Ok(self)
+    }
+
+    /// Whether any block is encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.index
+            .iter()
+            .any(|block| block.flags & BLOCK_ENCRYPTED != 0)
+    }
+
+    /// Time index of all blocks in the file
+    pub fn index(&self) -> &[BlockIndex] {
+        &self.index
+    }
+
+    /// Time span covered by the recording
+    pub fn time_span(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let min = self.index.iter().map(|block| block.min_ts).min()?;
+        let max = self.index.iter().map(|block| block.max_ts).max()?;
+        Some((from_nanos(min), from_nanos(max)))
+    }
+
+    /// Iterate over every message in the recording
+    pub fn messages(&mut self) -> RecordingIter<'_> {
+        self.range(..)
+    }
+
+    /// Iterate over messages whose recording timestamp falls in `range`,
+    /// decoding only the blocks that overlap it
+    pub fn range(&mut self, range: impl RangeBounds<DateTime<Utc>>) -> RecordingIter<'_> {
+        let start = match range.start_bound() {
+            Bound::Included(ts) => to_nanos(*ts),
+            Bound::Excluded(ts) => to_nanos(*ts).saturating_add(1),
+            Bound::Unbounded => i64::MIN,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(ts) => to_nanos(*ts),
+            Bound::Excluded(ts) => to_nanos(*ts).saturating_sub(1),
+            Bound::Unbounded => i64::MAX,
+        };
+
+        let blocks = self
+            .index
+            .iter()
+            .filter(|block| block.max_ts >= start && block.min_ts <= end)
+            .cloned()
+            .collect();
+
+        RecordingIter {
+            reader: self,
+            blocks,
+            current: VecDeque::new(),
+            deltas: DeltaDecoder::default(),
+            start,
+            end,
+        }
+    }
+
+    /// Header and stored payload of a block, checked against its checksum
+    pub(crate) fn read_raw_block(
+        &mut self,
+        block: &BlockIndex,
+    ) -> Result<([u8; BLOCK_HEADER_LEN], Vec<u8>)> {
+        self.file
+            .seek(SeekFrom::Start(block.offset))
+            .map_err(io_error)?;
+        let mut header = [0u8; BLOCK_HEADER_LEN];
+        self.file.read_exact(&mut header).map_err(io_error)?;
+        let compressed_len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as u64;
+        if block.offset + BLOCK_HEADER_LEN as u64 + compressed_len > self.len {
+            return Err(corrupt("block extends past end of file"));
+        }
+
+        let mut stored = vec![0u8; compressed_len as usize];
+        self.file.read_exact(&mut stored).map_err(io_error)?;
+        let crc = u32::from_le_bytes(header[28..32].try_into().unwrap());
+        if crc32fast::hash(&stored) != crc {
+            return Err(corrupt(format!(
+                "checksum mismatch in block at offset {}",
+                block.offset
+            )));
+        }
+        Ok((header, stored))
+    }
+
+    pub(crate) fn read_block(&mut self, block: &BlockIndex) -> Result<Vec<(i64, Record)>> {
+        let (header, mut compressed) = self.read_raw_block(block)?;
+        let flags = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if flags & BLOCK_ENCRYPTED != 0 {
+            let cipher = self.cipher.as_ref().ok_or_else(|| {
+                ClientError::Parse("recording is encrypted and no key was given".to_string())
+            })?;
+            let position = self
+                .index
+                .binary_search_by_key(&block.offset, |indexed| indexed.offset)
+                .map_err(|_| corrupt(format!("block at offset {} is not indexed", block.offset)))?;
+            compressed = crypto::open(cipher, position as u64, &header[..28], &compressed)?;
+        }
+        let mut payload = Vec::new();
+        zstd::stream::read::Decoder::new(compressed.as_slice())
+            .map_err(io_error)?
+            .take(MAX_BLOCK_BYTES + 1)
+            .read_to_end(&mut payload)
+            .map_err(io_error)?;
+        if payload.len() as u64 > MAX_BLOCK_BYTES {
+            return Err(corrupt("block too large"));
+        }
+
+        let mut records = Vec::with_capacity((block.records as usize).min(payload.len() / 12));
+        let mut offset = 0;
+        while offset < payload.len() {
+            if payload.len() - offset < 12 {
+                return Err(corrupt("truncated record header"));
+            }
+            let len = u32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap()) as usize;
+            let ts = i64::from_le_bytes(payload[offset + 4..offset + 12].try_into().unwrap());
+            let start = offset + 12;
+            let body = payload
+                .get(start..start.saturating_add(len))
+                .ok_or_else(|| corrupt("truncated record"))?;
+            let record: Record = postcard::from_bytes(body).map_err(|e| corrupt(e.to_string()))?;
+            records.push((ts, record));
+            offset = start + len;
+        }
+        Ok(records)
+    }
+
+    /// The footer's index and the offset it starts at
+    pub(crate) fn read_footer(
+        file: &mut BufReader<File>,
+    ) -> Result<Option<(Vec<BlockIndex>, u64)>> {
+        let len = file.seek(SeekFrom::End(0)).map_err(io_error)?;
+        if len < (MAGIC.len() + 12) as u64 {
+            return Ok(None);
+        }
+
+        let mut trailer = [0u8; 12];
+        file.seek(SeekFrom::End(-12)).map_err(io_error)?;
+        file.read_exact(&mut trailer).map_err(io_error)?;
+        if &trailer[4..] != INDEX_MAGIC {
+            return Ok(None);
+        }
+
+        let index_len = u32::from_le_bytes(trailer[0..4].try_into().unwrap()) as i64;
+        if index_len as u64 + 12 + MAGIC.len() as u64 > len {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::End(-12 - index_len))
+            .map_err(io_error)?;
+        let mut index = vec![0u8; index_len as usize];
+        file.read_exact(&mut index).map_err(io_error)?;
+
+        let start = len - 12 - index_len as u64;
+        postcard::from_bytes(&index)
+            .map(|index| Some((index, start)))
+            .map_err(|e| corrupt(e.to_string()))
+    }
+
+    /// Index complete blocks before `len` from their headers, returning the
+    /// offset just past the last one
+    pub(crate) fn scan_blocks(
+        file: &mut BufReader<File>,
+        len: u64,
+    ) -> Result<(Vec<BlockIndex>, u64)> {
+        let mut offset = MAGIC.len() as u64;
+        let mut index = Vec::new();
+
+        while offset + BLOCK_HEADER_LEN as u64 <= len {
+            file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+            let mut header = [0u8; BLOCK_HEADER_LEN];
+            file.read_exact(&mut header).map_err(io_error)?;
+
+            let compressed_len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as u64;
+            let next = offset + BLOCK_HEADER_LEN as u64 + compressed_len;
+            if next > len {
+                break;
+            }
+            index.push(BlockIndex {
+                offset,
+                records: u32::from_le_bytes(header[4..8].try_into().unwrap()),
+                flags: u32::from_le_bytes(header[8..12].try_into().unwrap()),
+                min_ts: i64::from_le_bytes(header[12..20].try_into().unwrap()),
+                max_ts: i64::from_le_bytes(header[20..28].try_into().unwrap()),
+            });
+            offset = next;
+        }
+        Ok((index, offset))
+    }
+}
+
+/// Iterator over recorded `(timestamp, message)` pairs
+pub struct RecordingIter<'a> {
+    reader: &'a mut RecordingReader,
+    blocks: VecDeque<BlockIndex>,
+    current: VecDeque<(i64, Record)>,
+    deltas: DeltaDecoder,
+    start: i64,
+    end: i64,
+}
+
+impl Iterator for RecordingIter<'_> {
+    type Item = Result<(DateTime<Utc>, MarketDataMessage)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while let Some((ts, record)) = self.current.pop_front() {
+                let in_range = ts >= self.start && ts <= self.end;
+                if !in_range && !matches!(record, Record::BookDelta(_)) {
+                    continue;
+                }
+                // Deltas before the range still build up the books
+                match record.into_message(&mut self.deltas) {
+                    Ok(Some(msg)) if in_range => return Some(Ok((from_nanos(ts), msg))),
+                    Ok(_) => {}
+                    Err(e) => {
+                        self.blocks.clear();
+                        self.current.clear();
+                        return Some(Err(e));
+                    }
+                }
+            }
+
+            let block = self.blocks.pop_front()?;
+            match self.reader.read_block(&block) {
+                Ok(records) => self.current = records.into(),
+                Err(e) => {
+                    self.blocks.clear();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{RecordingKey, RecordingWriter};
+    use super::*;
+    use crate::types::Trade;
+    use chrono::{Duration, TimeZone};
+
+    fn trade(ts: DateTime<Utc>, price: f64) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            timestamp: ts,
+            trade_id: price.to_string(),
+            ..Trade::test("BTCUSD", price)
+        })
+    }
+
+    #[test]
+    fn test_time_range_seek() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.mds");
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+
+        let mut writer = RecordingWriter::create(&path, 10).unwrap();
+        for i in 0..100 {
+            writer
+                .write(&trade(t0 + Duration::seconds(i), i as f64))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut reader = RecordingReader::open(&path).unwrap();
+        assert_eq!(reader.index().len(), 10);
+        assert_eq!(reader.messages().count(), 100);
+
+        let prices: Vec<f64> = reader
+            .range(t0 + Duration::seconds(42)..t0 + Duration::seconds(45))
+            .map(|entry| match entry.unwrap().1 {
+                MarketDataMessage::Trade(trade) => trade.price,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(prices, vec![42.0, 43.0, 44.0]);
+    }
+
+    #[test]
+    fn test_reads_recording_without_footer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.mds");
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+
+        let mut writer = RecordingWriter::create(&path, 4).unwrap();
+        for i in 0..8 {
+            writer
+                .write(&trade(t0 + Duration::seconds(i), i as f64))
+                .unwrap();
+        }
+        writer.flush_block().unwrap();
+        std::mem::forget(writer);
+
+        let mut reader = RecordingReader::open(&path).unwrap();
+        assert_eq!(reader.index().len(), 2);
+        assert_eq!(reader.messages().count(), 8);
+    }
+
+    #[test]
+    fn test_encrypted_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.mds");
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let key = RecordingKey::from_hex(&"2a".repeat(32)).unwrap();
+
+        let mut writer = RecordingWriter::create(&path, 4)
+            .unwrap()
+            .with_encryption(&key)
+            .unwrap();
+        for i in 0..6 {
+            writer
+                .write(&trade(t0 + Duration::seconds(i), i as f64))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        let raw = std::fs::read(&path).unwrap();
+        assert!(!raw.windows(6).any(|w| w == b"BTCUSD"));
+
+        let mut reader = RecordingReader::open(&path).unwrap();
+        assert!(reader.is_encrypted());
+        assert!(reader.messages().next().unwrap().is_err());
+
+        let wrong = RecordingKey::from_hex(&"2b".repeat(32)).unwrap();
+        let mut reader = RecordingReader::open(&path)
+            .unwrap()
+            .with_key(&wrong)
+            .unwrap();
+        assert!(reader.messages().next().unwrap().is_err());
+        let missing = || -> Result<RecordingKey> { Err(ClientError::Parse("no key".to_string())) };
+        assert!(RecordingReader::open(&path)
+            .unwrap()
+            .with_key(&missing)
+            .is_err());
+
+        // A block only opens at the position it was sealed for
+        let cipher = key.cipher();
+        let sealed = crypto::seal(&cipher, 0, b"header", b"payload").unwrap();
+        assert!(crypto::open(&cipher, 1, b"header", &sealed).is_err());
+        assert!(crypto::open(&cipher, 0, b"header", &sealed).is_ok());
+
+        let provider = || RecordingKey::from_hex(&"2a".repeat(32));
+        let mut reader = RecordingReader::open(&path)
+            .unwrap()
+            .with_key(&provider)
+            .unwrap();
+        let seek = reader.range(t0 + Duration::seconds(5)..);
+        assert_eq!(
+            seek.map(|entry| entry.unwrap().0).collect::<Vec<_>>(),
+            [t0 + Duration::seconds(5)]
+        );
+        assert_eq!(reader.messages().count(), 6);
+    }
+}
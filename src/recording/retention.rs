@@ -0,0 +1,322 @@
+use super::compact::is_binary;
+use super::{compact, io_error, manifest_path, CompactOptions, Manifest};
+use crate::client::{ClientError, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Limits on the recordings kept in a directory
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Delete recordings whose last message is older than this
+    pub max_age: Option<Duration>,
+    /// Delete the oldest recordings while the directory holds more bytes
+    pub max_total_bytes: Option<u64>,
+    /// Per symbol, delete the oldest recordings containing it while they
+    /// hold more bytes
+    pub symbol_quotas: HashMap<String, u64>,
+    /// Compact recordings older than this in place, once
+    pub compact_after: Option<Duration>,
+    pub compact_options: CompactOptions,
+}
+
+/// What one pass of [`RetentionManager::enforce`] did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionReport {
+    pub deleted: Vec<PathBuf>,
+    /// Compacted outputs
+    pub compacted: Vec<PathBuf>,
+    /// Bytes freed by deletion and compaction
+    pub reclaimed_bytes: u64,
+    /// Files skipped, each with the reason
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Capture {
+    path: PathBuf,
+    bytes: u64,
+    manifest: Manifest,
+}
+
+impl Capture {
+    fn end(&self) -> DateTime<Utc> {
+        self.manifest.end.unwrap_or(DateTime::<Utc>::MIN_UTC)
+    }
+
+    fn is_compacted(&self) -> bool {
+        self.path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .is_some_and(|stem| stem.ends_with(".compact"))
+    }
+}
+
+/// Applies a [`RetentionPolicy`] to the recordings in one directory.
+///
+/// Only finished recordings (those with a manifest) are considered, so a
+/// file still being written is never touched.
+pub struct RetentionManager {
+    dir: PathBuf,
+    policy: RetentionPolicy,
+    tx: broadcast::Sender<RetentionReport>,
+}
+
+impl RetentionManager {
+    pub fn new(dir: impl AsRef<Path>, policy: RetentionPolicy) -> Self {
+        let (tx, _) = broadcast::channel(16);
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            policy,
+            tx,
+        }
+    }
+
+    /// Reports of passes run by [`spawn`](Self::spawn)
+    pub fn subscribe(&self) -> broadcast::Receiver<RetentionReport> {
+        self.tx.subscribe()
+    }
+
+    /// Enforce the policy every `period`, which must not be zero, on a
+    /// background task
+    pub fn spawn(self, period: std::time::Duration) -> Result<JoinHandle<()>> {
+        if period.is_zero() {
+            return Err(ClientError::Parse(
+                "retention period must not be zero".to_string(),
+            ));
+        }
+        Ok(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            let manager = std::sync::Arc::new(self);
+            loop {
+                ticker.tick().await;
+                let pass = manager.clone();
+                match tokio::task::spawn_blocking(move || pass.enforce(Utc::now())).await {
+                    Ok(Ok(report)) => {
+                        if report.reclaimed_bytes > 0 {
+                            info!(
+                                "Retention in {}: deleted {}, compacted {}, reclaimed {} bytes",
+                                manager.dir.display(),
+                                report.deleted.len(),
+                                report.compacted.len(),
+                                report.reclaimed_bytes
+                            );
+                        }
+                        let _ = manager.tx.send(report);
+                    }
+                    Ok(Err(e)) => warn!("Retention in {} failed: {}", manager.dir.display(), e),
+                    Err(e) => warn!("Retention task panicked: {}", e),
+                }
+            }
+        }))
+    }
+
+    /// Run one pass with ages measured against `now`. Files that cannot be
+    /// read, compacted or deleted are skipped and listed in the report;
+    /// only an unreadable directory fails the pass.
+    pub fn enforce(&self, now: DateTime<Utc>) -> Result<RetentionReport> {
+        let mut report = RetentionReport::default();
+        let mut captures = self.captures(&mut report)?;
+        captures.sort_by_key(Capture::end);
+
+        if let Some(max_age) = self.policy.max_age {
+            let (expired, kept) = captures.into_iter().partition(|c| now - c.end() > max_age);
+            captures = kept;
+            for capture in expired {
+                delete(capture, &mut report);
+            }
+        }
+
+        if let Some(after) = self.policy.compact_after {
+            for capture in &mut captures {
+                if capture.is_compacted() || now - capture.end() <= after {
+                    continue;
+                }
+                let compacted = match self.compact_capture(capture) {
+                    Ok(compacted) => compacted,
+                    Err(e) => {
+                        skip(&mut report, &capture.path, e);
+                        continue;
+                    }
+                };
+                let original = std::mem::replace(capture, compacted);
+                report.compacted.push(capture.path.clone());
+                match remove(&original.path) {
+                    Ok(()) => {
+                        report.reclaimed_bytes += original.bytes.saturating_sub(capture.bytes)
+                    }
+                    Err(e) => skip(&mut report, &original.path, e),
+                }
+            }
+        }
+
+        if let Some(max) = self.policy.max_total_bytes {
+            let mut total: u64 = captures.iter().map(|c| c.bytes).sum();
+            while total > max && !captures.is_empty() {
+                let capture = captures.remove(0);
+                total -= capture.bytes;
+                delete(capture, &mut report);
+            }
+        }
+
+        let mut quotas: Vec<_> = self.policy.symbol_quotas.iter().collect();
+        quotas.sort();
+        for (symbol, &quota) in quotas {
+            let holds = |c: &Capture| c.manifest.symbols.contains(symbol);
+            let mut used: u64 = captures.iter().filter(|c| holds(c)).map(|c| c.bytes).sum();
+            while used > quota {
+                let Some(i) = captures.iter().position(holds) else {
+                    break;
+                };
+                let capture = captures.remove(i);
+                used -= capture.bytes;
+                delete(capture, &mut report);
+            }
+        }
+        Ok(report)
+    }
+
+    fn captures(&self, report: &mut RetentionReport) -> Result<Vec<Capture>> {
+        let mut captures = Vec::new();
+        for entry in std::fs::read_dir(&self.dir).map_err(io_error)? {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    skip(report, &self.dir, io_error(e));
+                    continue;
+                }
+            };
+            match capture(&path) {
+                Ok(Some(capture)) => captures.push(capture),
+                Ok(None) => {}
+                Err(e) => skip(report, &path, e),
+            }
+        }
+        Ok(captures)
+    }
+
+    /// Compact `capture` next to it, returning the compacted capture
+    fn compact_capture(&self, capture: &Capture) -> Result<Capture> {
+        let output = compacted_path(&capture.path);
+        compact(&capture.path, &output, &self.policy.compact_options)?;
+        Ok(Capture {
+            bytes: std::fs::metadata(&output).map_err(io_error)?.len(),
+            manifest: Manifest::load(manifest_path(&output))?,
+            path: output,
+        })
+    }
+}
+
+/// The finished recording at `path`, if it is one
+fn capture(path: &Path) -> Result<Option<Capture>> {
+    let manifest = manifest_path(path);
+    if !path.is_file() || !manifest.exists() || !is_binary(path)? {
+        return Ok(None);
+    }
+    Ok(Some(Capture {
+        bytes: std::fs::metadata(path).map_err(io_error)?.len(),
+        manifest: Manifest::load(&manifest)?,
+        path: path.to_path_buf(),
+    }))
+}
+
+/// `capture.mds` becomes `capture.compact.mds`
+fn compacted_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}.compact.{}", stem, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}.compact", stem)),
+    }
+}
+
+fn delete(capture: Capture, report: &mut RetentionReport) {
+    match remove(&capture.path) {
+        Ok(()) => {
+            report.reclaimed_bytes += capture.bytes;
+            report.deleted.push(capture.path);
+        }
+        Err(e) => skip(report, &capture.path, e),
+    }
+}
+
+/// Log and report a file a pass could not handle
+fn skip(report: &mut RetentionReport, path: &Path, e: ClientError) {
+    warn!("Retention skipped {}: {}", path.display(), e);
+    report.errors.push(format!("{}: {}", path.display(), e));
+}
+
+fn remove(path: &Path) -> Result<()> {
+    std::fs::remove_file(path).map_err(io_error)?;
+    let _ = std::fs::remove_file(manifest_path(path));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::RecordingWriter;
+    use super::*;
+    use crate::types::{MarketDataMessage, Trade};
+    use chrono::TimeZone;
+
+    fn record(path: &Path, symbol: &str, end: DateTime<Utc>, count: i64) {
+        let mut writer = RecordingWriter::create(path, 64).unwrap();
+        for i in 0..count {
+            let trade = Trade {
+                timestamp: end - Duration::seconds(count - 1 - i),
+                trade_id: i.to_string(),
+                ..Trade::test(symbol, 100.0)
+            };
+            writer.write(&MarketDataMessage::Trade(trade)).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_age_total_size_and_symbol_quotas() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap();
+        let day = Duration::days(1);
+        let path = |name: &str| dir.path().join(name);
+        record(&path("btc-1.mds"), "BTCUSD", now - day * 8, 50);
+        record(&path("btc-2.mds"), "BTCUSD", now - day * 3, 50);
+        record(&path("btc-3.mds"), "BTCUSD", now - day * 2, 50);
+        record(&path("eth-1.mds"), "ETHUSD", now - day * 4, 50);
+        record(&path("eth-2.mds"), "ETHUSD", now - day, 50);
+        // Unfinished: no footer or manifest yet
+        let writer = RecordingWriter::create(path("live.mds"), 64).unwrap();
+        std::mem::forget(writer);
+        // A corrupt manifest is reported without stopping the pass
+        record(&path("bad.mds"), "ETHUSD", now - day * 9, 50);
+        std::fs::write(manifest_path(path("bad.mds")), "not json").unwrap();
+
+        let len = |name: &str| std::fs::metadata(path(name)).unwrap().len();
+        let size = len("btc-2.mds");
+        let reclaimed = len("btc-1.mds") + len("eth-1.mds") + len("btc-2.mds");
+        // Sizes vary by a few bytes, so limits sit between file counts
+        let policy = RetentionPolicy {
+            max_age: Some(day * 7),
+            max_total_bytes: Some(size * 7 / 2),
+            symbol_quotas: HashMap::from([("BTCUSD".to_string(), size * 3 / 2)]),
+            ..RetentionPolicy::default()
+        };
+        let report = RetentionManager::new(dir.path(), policy)
+            .enforce(now)
+            .unwrap();
+
+        let names: Vec<_> = report
+            .deleted
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect();
+        // Expired, then the oldest for total size, then BTC over its quota
+        assert_eq!(names, ["btc-1.mds", "eth-1.mds", "btc-2.mds"]);
+        assert_eq!(report.reclaimed_bytes, reclaimed);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].contains("bad.mds"));
+        assert!(path("live.mds").exists());
+        assert!(!manifest_path(path("btc-1.mds")).exists());
+    }
+}
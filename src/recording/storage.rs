@@ -0,0 +1,139 @@
+use super::{io_error, manifest_path, Manifest};
+use crate::client::Result;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Destination of a [`RecordingWriter`](super::RecordingWriter)'s output.
+///
+/// Recordings are written strictly sequentially, so a backend only needs to
+/// append bytes, make them durable when a block is complete, and keep the
+/// manifest once the recording is finished. Implement it to record straight
+/// to a custom destination; finished files can also be shipped to object
+/// storage with an [`Uploader`](super::Uploader).
+pub trait StorageBackend: Send {
+    /// Append bytes to the recording
+    fn append(&mut self, bytes: &[u8]) -> Result<()>;
+
+    /// Persist everything appended so far; called after every block
+    fn flush(&mut self) -> Result<()>;
+
+    /// Store the manifest of the finished recording
+    fn finish(&mut self, manifest: &Manifest) -> Result<()>;
+}
+
+/// Recording to a local file, with its manifest saved next to it
+#[derive(Debug)]
+pub struct FileStorage {
+    file: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl FileStorage {
+    /// Create (or truncate) the file at `path`
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        Ok(Self {
+            file: BufWriter::new(File::create(&path).map_err(io_error)?),
+            path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl StorageBackend for FileStorage {
+    fn append(&mut self, bytes: &[u8]) -> Result<()> {
+        self.file.write_all(bytes).map_err(io_error)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush().map_err(io_error)
+    }
+
+    fn finish(&mut self, manifest: &Manifest) -> Result<()> {
+        manifest.save(&manifest_path(&self.path))
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryRecording {
+    bytes: Vec<u8>,
+    manifest: Option<Manifest>,
+}
+
+/// Recording held in memory, e.g. for tests or to hand to another writer.
+///
+/// Clones share the same buffer, so one can be given to the writer and
+/// another kept to read the result.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    recording: Arc<Mutex<MemoryRecording>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes written so far
+    pub fn bytes(&self) -> Vec<u8> {
+        self.recording.lock().unwrap().bytes.clone()
+    }
+
+    /// Manifest of the recording once finished
+    pub fn manifest(&self) -> Option<Manifest> {
+        self.recording.lock().unwrap().manifest.clone()
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn append(&mut self, bytes: &[u8]) -> Result<()> {
+        self.recording
+            .lock()
+            .unwrap()
+            .bytes
+            .extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, manifest: &Manifest) -> Result<()> {
+        self.recording.lock().unwrap().manifest = Some(manifest.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::{verify, RecordingReader, RecordingWriter};
+    use crate::types::MarketDataMessage;
+
+    #[test]
+    fn test_memory_recording_matches_file_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("day.mds");
+        let memory = MemoryStorage::new();
+        let mut to_memory = RecordingWriter::new(memory.clone(), 2).unwrap();
+        let mut to_file = RecordingWriter::create(&path, 2).unwrap();
+        for _ in 0..5 {
+            to_memory.write(&MarketDataMessage::Heartbeat).unwrap();
+            to_file.write(&MarketDataMessage::Heartbeat).unwrap();
+        }
+        to_memory.finish().unwrap();
+        to_file.finish().unwrap();
+
+        assert_eq!(memory.bytes(), std::fs::read(&path).unwrap());
+        assert_eq!(memory.manifest().unwrap().records, 5);
+        assert!(verify(&path, None).unwrap().is_ok());
+        let mut reader = RecordingReader::open(&path).unwrap();
+        assert_eq!(reader.messages().count(), 5);
+    }
+}
@@ -0,0 +1,747 @@
+use super::{io_error, manifest_path, Manifest};
+use crate::client::{ClientError, Result};
+use futures_util::future::BoxFuture;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Destination for uploaded recordings
+pub trait ObjectStore: Send + Sync {
+    /// Store the contents of the file at `source` under `key`, replacing
+    /// any existing object
+    fn put<'a>(&'a self, key: &'a str, source: &'a Path) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Store writing objects below a local directory, e.g. a mounted bucket
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl ObjectStore for LocalStore {
+    fn put<'a>(&'a self, key: &'a str, source: &'a Path) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let relative = Path::new(key);
+            let contained = relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            if !contained || key.is_empty() {
+                return Err(ClientError::Io(format!(
+                    "object key {} is not a path below the store root",
+                    key
+                )));
+            }
+            let path = self.root.join(relative);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await.map_err(io_error)?;
+            }
+            tokio::fs::copy(source, &path).await.map_err(io_error)?;
+            Ok(())
+        })
+    }
+}
+
+/// Result of one pass over the spool
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UploadReport {
+    /// Object keys of uploaded recordings
+    pub uploaded: Vec<String>,
+    /// Recordings left in the spool after exhausting their retries
+    pub failed: Vec<PathBuf>,
+    pub bytes: u64,
+}
+
+/// Ships finished recordings from a local spool directory to an
+/// [`ObjectStore`].
+///
+/// Recordings are moved into the spool with [`enqueue`](Self::enqueue) and
+/// deleted from it once both the recording and its manifest are stored, the
+/// manifest last so its presence marks a complete upload. Failed uploads stay
+/// in the spool for the next pass.
+pub struct Uploader {
+    store: Arc<dyn ObjectStore>,
+    spool: PathBuf,
+    prefix: String,
+    venue: String,
+    max_attempts: u32,
+    backoff: Duration,
+}
+
+impl Uploader {
+    pub fn new(store: impl ObjectStore + 'static, spool: impl AsRef<Path>) -> Result<Self> {
+        let spool = spool.as_ref().to_path_buf();
+        std::fs::create_dir_all(&spool).map_err(io_error)?;
+        Ok(Self {
+            store: Arc::new(store),
+            spool,
+            prefix: "{venue}/{symbol}/{date}".to_string(),
+            venue: "default".to_string(),
+            max_attempts: 3,
+            backoff: Duration::from_secs(1),
+        })
+    }
+
+    /// Key prefix template with `{venue}`, `{symbol}` and `{date}`
+    /// placeholders; the file name is appended. Venues and symbols are
+    /// percent-encoded so each stays a single key segment.
+    pub fn with_prefix(mut self, template: &str) -> Self {
+        self.prefix = template.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_venue(mut self, venue: &str) -> Self {
+        self.venue = venue.to_string();
+        self
+    }
+
+    /// Attempts per object, doubling `backoff` between them
+    pub fn with_retry(mut self, max_attempts: u32, backoff: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.backoff = backoff;
+        self
+    }
+
+    /// Object key of a recording; `{symbol}` is `multi` when it holds several
+    pub fn object_key(&self, manifest: &Manifest, file_name: &str) -> String {
+        let symbol = match manifest.symbols.as_slice() {
+            [symbol] => symbol.as_str(),
+            _ => "multi",
+        };
+        let date = manifest
+            .start
+            .map(|start| start.format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| "undated".to_string());
+        let prefix = self
+            .prefix
+            .replace("{venue}", &key_segment(&self.venue))
+            .replace("{symbol}", &key_segment(symbol))
+            .replace("{date}", &date);
+        if prefix.is_empty() {
+            file_name.to_string()
+        } else {
+            format!("{}/{}", prefix, file_name)
+        }
+    }
+
+    /// Move a finished recording and its manifest into the spool; fails
+    /// without moving anything while a recording of the same file name is
+    /// spooled, as both would upload to the same key
+    pub fn enqueue(&self, recording: impl AsRef<Path>) -> Result<PathBuf> {
+        let recording = recording.as_ref();
+        let manifest = manifest_path(recording);
+        if !manifest.exists() {
+            return Err(ClientError::Io(format!(
+                "{} has no manifest; finish the recording first",
+                recording.display()
+            )));
+        }
+        let name = recording.file_name().unwrap_or_default();
+        let target = self.spool.join(name);
+        let target_manifest = manifest_path(&target);
+        if target_manifest.exists() {
+            return Err(spooled(&target));
+        }
+        move_file(recording, &target)?;
+        if let Err(e) = move_file(&manifest, &target_manifest) {
+            // Put the recording back rather than leave it unmanifested
+            move_file(&target, recording)?;
+            return Err(e);
+        }
+        Ok(target)
+    }
+
+    /// Upload every spooled recording
+    pub async fn upload_spooled(&self) -> Result<UploadReport> {
+        let mut report = UploadReport::default();
+        let mut entries = tokio::fs::read_dir(&self.spool).await.map_err(io_error)?;
+        let mut recordings = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let path = entry.path();
+            if manifest_path(&path).exists() {
+                recordings.push(path);
+            }
+        }
+        recordings.sort();
+
+        for path in recordings {
+            match self.upload(&path).await {
+                Ok((key, bytes)) => {
+                    report.uploaded.push(key);
+                    report.bytes += bytes;
+                }
+                Err(e) => {
+                    warn!("Upload of {} failed: {}", path.display(), e);
+                    report.failed.push(path);
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    async fn upload(&self, path: &Path) -> Result<(String, u64)> {
+        let manifest_file = manifest_path(path);
+        let manifest = Manifest::load(&manifest_file)?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let key = self.object_key(&manifest, &name);
+
+        let bytes = tokio::fs::metadata(path).await.map_err(io_error)?.len();
+        self.put_with_retry(&key, path).await?;
+        self.put_with_retry(&format!("{}.manifest.json", key), &manifest_file)
+            .await?;
+
+        tokio::fs::remove_file(path).await.map_err(io_error)?;
+        tokio::fs::remove_file(&manifest_file)
+            .await
+            .map_err(io_error)?;
+        Ok((key, bytes))
+    }
+
+    async fn put_with_retry(&self, key: &str, source: &Path) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            match self.store.put(key, source).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.max_attempts => return Err(e),
+                Err(e) => {
+                    let delay = self.backoff * 2u32.saturating_pow(attempt - 1);
+                    warn!("Upload of {} failed ({}), retrying in {:?}", key, e, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Upload the spool every `period`, which must not be zero, on a
+    /// background task
+    pub fn spawn(self, period: Duration) -> Result<JoinHandle<()>> {
+        if period.is_zero() {
+            return Err(ClientError::Parse(
+                "upload period must not be zero".to_string(),
+            ));
+        }
+        Ok(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            loop {
+                ticker.tick().await;
+                match self.upload_spooled().await {
+                    Ok(report) if !report.uploaded.is_empty() => info!(
+                        "Uploaded {} recordings ({} bytes), {} pending retry",
+                        report.uploaded.len(),
+                        report.bytes,
+                        report.failed.len()
+                    ),
+                    Ok(_) => {}
+                    Err(e) => warn!("Reading spool {} failed: {}", self.spool.display(), e),
+                }
+            }
+        }))
+    }
+}
+
+/// `value` as one object key segment: percent-encoded like a URI path
+/// segment, with dot-only names encoded too so they cannot step out of the
+/// prefix
+fn key_segment(value: &str) -> String {
+    if value.bytes().all(|byte| byte == b'.') {
+        return value.bytes().map(|_| "%2E").collect();
+    }
+    uri_encode(value)
+}
+
+fn spooled(path: &Path) -> ClientError {
+    ClientError::Io(format!("{} is already spooled", path.display()))
+}
+
+/// Move `from` to `to`, failing if `to` exists
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    match std::fs::hard_link(from, to) {
+        Ok(()) => return std::fs::remove_file(from).map_err(io_error),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Err(spooled(to)),
+        // Across filesystems, or without hard links
+        Err(_) => {}
+    }
+    let mut source = std::fs::File::open(from).map_err(io_error)?;
+    let mut target = match std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(to)
+    {
+        Ok(target) => target,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Err(spooled(to)),
+        Err(e) => return Err(io_error(e)),
+    };
+    if let Err(e) = std::io::copy(&mut source, &mut target) {
+        let _ = std::fs::remove_file(to);
+        return Err(io_error(e));
+    }
+    std::fs::remove_file(from).map_err(io_error)
+}
+
+/// S3-compatible store (AWS S3, GCS interoperability, MinIO) signing path
+/// style requests with AWS Signature Version 4. Objects over 5 GiB, the
+/// single `PUT` limit, are sent as multipart uploads.
+#[cfg(feature = "s3")]
+#[derive(Debug, Clone)]
+pub struct S3Store {
+    client: reqwest::Client,
+    endpoint: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+#[cfg(feature = "s3")]
+impl S3Store {
+    /// `endpoint` such as `https://s3.us-east-1.amazonaws.com` or
+    /// `https://storage.googleapis.com`
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            bucket: bucket.to_string(),
+            region: region.to_string(),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+        }
+    }
+
+    /// Credentials from `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+    pub fn from_env(endpoint: &str, bucket: &str, region: &str) -> Result<Self> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| ClientError::Connection(format!("{} is not set", name)))
+        };
+        Ok(Self::new(
+            endpoint,
+            bucket,
+            region,
+            &var("AWS_ACCESS_KEY_ID")?,
+            &var("AWS_SECRET_ACCESS_KEY")?,
+        ))
+    }
+
+    /// URL and `Host` header value of the object at `key`
+    fn object_url(&self, key: &str) -> Result<(reqwest::Url, String)> {
+        let uri = std::iter::once(self.bucket.as_str())
+            .chain(key.split('/'))
+            .map(uri_encode)
+            .fold(String::new(), |uri, segment| uri + "/" + &segment);
+        let url = reqwest::Url::parse(&format!("{}{}", self.endpoint, uri))
+            .map_err(|e| ClientError::Connection(e.to_string()))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(ClientError::Connection("endpoint has no host".to_string())),
+        };
+        Ok((url, host))
+    }
+
+    fn authorization(
+        &self,
+        method: &str,
+        host: &str,
+        uri: &str,
+        query: &str,
+        amz_date: &str,
+        payload_hash: &str,
+    ) -> String {
+        use super::verify::hex;
+        use hmac::{Hmac, Mac};
+        use sha2::{Digest, Sha256};
+
+        let hmac = |key: &[u8], data: &str| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+            mac.update(data.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        };
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, uri, query, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical.as_bytes()))
+        );
+        let key = [date, &self.region, "s3", "aws4_request"].iter().fold(
+            format!("AWS4{}", self.secret_key).into_bytes(),
+            |key, part| hmac(&key, part),
+        );
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key,
+            scope,
+            signed_headers,
+            hex(&hmac(&key, &to_sign))
+        )
+    }
+
+    /// Send a signed request for the object at `key` with `query`
+    /// parameters, returning the successful response
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        query: &[(&str, &str)],
+        body: reqwest::Body,
+        length: u64,
+        payload_hash: String,
+    ) -> Result<reqwest::Response> {
+        let (mut url, host) = self.object_url(key)?;
+        let mut params: Vec<_> = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", uri_encode(name), uri_encode(value)))
+            .collect();
+        params.sort();
+        let query = params.join("&");
+        if !query.is_empty() {
+            url.set_query(Some(&query));
+        }
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let authorization = self.authorization(
+            method.as_str(),
+            &host,
+            url.path(),
+            &query,
+            &amz_date,
+            &payload_hash,
+        );
+        self.client
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", authorization)
+            .header(reqwest::header::CONTENT_LENGTH, length)
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ClientError::Connection(e.to_string()))
+    }
+
+    /// Send an in-memory body
+    async fn send_bytes(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response> {
+        use sha2::{Digest, Sha256};
+
+        let payload_hash = super::verify::hex(&Sha256::digest(&body));
+        let length = body.len() as u64;
+        self.send(method, key, query, body.into(), length, payload_hash)
+            .await
+    }
+
+    /// Upload `source` in one `PUT`
+    async fn put_single(&self, key: &str, source: &Path) -> Result<()> {
+        use sha2::{Digest, Sha256};
+        use tokio::io::AsyncReadExt;
+
+        // Hash, then send, the file in chunks rather than loading it
+        let mut file = tokio::fs::File::open(source).await.map_err(io_error)?;
+        let mut hasher = Sha256::new();
+        let mut chunk = vec![0u8; 64 * 1024];
+        let mut length = 0u64;
+        loop {
+            let n = file.read(&mut chunk).await.map_err(io_error)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&chunk[..n]);
+            length += n as u64;
+        }
+        let payload_hash = super::verify::hex(&hasher.finalize());
+        let file = tokio::fs::File::open(source).await.map_err(io_error)?;
+        let body = reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(file));
+        self.send(reqwest::Method::PUT, key, &[], body, length, payload_hash)
+            .await?;
+        Ok(())
+    }
+
+    /// Upload `source` of `length` bytes as a multipart upload, aborting
+    /// it on failure so no parts are left billed
+    async fn put_multipart(&self, key: &str, source: &Path, length: u64) -> Result<()> {
+        let response = self
+            .send_bytes(reqwest::Method::POST, key, &[("uploads", "")], Vec::new())
+            .await?;
+        let body = response
+            .text()
+            .await
+            .map_err(|e| ClientError::Connection(e.to_string()))?;
+        let upload_id = xml_element(&body, "UploadId")
+            .ok_or_else(|| ClientError::Connection(format!("no UploadId in {}", body)))?;
+
+        let result = self.upload_parts(key, source, length, &upload_id).await;
+        if result.is_err() {
+            let abort = self
+                .send_bytes(
+                    reqwest::Method::DELETE,
+                    key,
+                    &[("uploadId", &upload_id)],
+                    Vec::new(),
+                )
+                .await;
+            if let Err(e) = abort {
+                warn!("Aborting multipart upload of {} failed: {}", key, e);
+            }
+        }
+        result
+    }
+
+    async fn upload_parts(
+        &self,
+        key: &str,
+        source: &Path,
+        length: u64,
+        upload_id: &str,
+    ) -> Result<()> {
+        use tokio::io::AsyncReadExt;
+
+        let part_size = part_size(length);
+        let mut file = tokio::fs::File::open(source).await.map_err(io_error)?;
+        let mut complete = String::from("<CompleteMultipartUpload>");
+        let mut offset = 0;
+        let mut number = 1;
+        while offset < length {
+            let size = part_size.min(length - offset);
+            let mut part = vec![0u8; size as usize];
+            file.read_exact(&mut part).await.map_err(io_error)?;
+            let response = self
+                .send_bytes(
+                    reqwest::Method::PUT,
+                    key,
+                    &[("partNumber", &number.to_string()), ("uploadId", upload_id)],
+                    part,
+                )
+                .await?;
+            let etag = response
+                .headers()
+                .get(reqwest::header::ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .ok_or_else(|| ClientError::Connection(format!("part {} has no ETag", number)))?;
+            complete.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                number, etag
+            ));
+            offset += size;
+            number += 1;
+        }
+        complete.push_str("</CompleteMultipartUpload>");
+
+        let response = self
+            .send_bytes(
+                reqwest::Method::POST,
+                key,
+                &[("uploadId", upload_id)],
+                complete.into_bytes(),
+            )
+            .await?;
+        // Completion can fail after the 200 status has been sent
+        let body = response
+            .text()
+            .await
+            .map_err(|e| ClientError::Connection(e.to_string()))?;
+        match xml_element(&body, "Message") {
+            Some(message) if body.contains("<Error>") => Err(ClientError::Connection(message)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Largest object S3 accepts in a single `PUT`; larger ones go multipart
+#[cfg(feature = "s3")]
+const MULTIPART_THRESHOLD: u64 = 5 << 30;
+
+/// Smallest multipart part size used, grown for objects that would need
+/// more than S3's 10,000 parts
+#[cfg(feature = "s3")]
+const PART_SIZE: u64 = 64 << 20;
+
+#[cfg(feature = "s3")]
+fn part_size(length: u64) -> u64 {
+    PART_SIZE.max(length.div_ceil(10_000))
+}
+
+/// Text of the first `<name>` element in an S3 XML response
+#[cfg(feature = "s3")]
+fn xml_element(body: &str, name: &str) -> Option<String> {
+    let open = format!("<{}>", name);
+    let start = body.find(&open)? + open.len();
+    let end = start + body[start..].find(&format!("</{}>", name))?;
+    Some(body[start..end].to_string())
+}
+
+#[cfg(feature = "s3")]
+impl ObjectStore for S3Store {
+    fn put<'a>(&'a self, key: &'a str, source: &'a Path) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let length = tokio::fs::metadata(source).await.map_err(io_error)?.len();
+            if length > MULTIPART_THRESHOLD {
+                self.put_multipart(key, source, length).await
+            } else {
+                self.put_single(key, source).await
+            }
+        })
+    }
+}
+
+/// Percent-encode a path segment as SigV4 expects
+fn uri_encode(segment: &str) -> String {
+    let mut out = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::RecordingWriter;
+    use super::*;
+    use crate::types::{MarketDataMessage, Trade};
+    use chrono::{TimeZone, Utc};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails the first `failures` puts
+    struct Flaky {
+        inner: LocalStore,
+        failures: AtomicU32,
+    }
+
+    impl ObjectStore for Flaky {
+        fn put<'a>(&'a self, key: &'a str, source: &'a Path) -> BoxFuture<'a, Result<()>> {
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Box::pin(async { Err(ClientError::Connection("503".to_string())) });
+            }
+            self.inner.put(key, source)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spool_upload_with_retry() {
+        let dir = tempfile::tempdir().unwrap();
+        let recording = dir.path().join("btc-0001.mds");
+        let mut writer = RecordingWriter::create(&recording, 16).unwrap();
+        let trade = Trade {
+            timestamp: Utc.with_ymd_and_hms(2024, 3, 5, 12, 0, 0).unwrap(),
+            trade_id: "1".to_string(),
+            ..Trade::test("BTCUSD", 100.0)
+        };
+        writer.write(&MarketDataMessage::Trade(trade)).unwrap();
+        writer.finish().unwrap();
+
+        let bucket = dir.path().join("bucket");
+        let store = Flaky {
+            inner: LocalStore::new(&bucket),
+            failures: AtomicU32::new(2),
+        };
+        let uploader = Uploader::new(store, dir.path().join("spool"))
+            .unwrap()
+            .with_venue("coinbase")
+            .with_retry(3, Duration::from_millis(1));
+        let spooled = uploader.enqueue(&recording).unwrap();
+        assert!(!recording.exists());
+
+        let report = uploader.upload_spooled().await.unwrap();
+        assert_eq!(report.uploaded, ["coinbase/BTCUSD/2024-03-05/btc-0001.mds"]);
+        assert!(report.failed.is_empty());
+        assert!(bucket
+            .join("coinbase/BTCUSD/2024-03-05/btc-0001.mds.manifest.json")
+            .exists());
+        assert!(!spooled.exists());
+        assert!(uploader.spawn(Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_enqueue_rejects_spooled_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let uploader =
+            Uploader::new(LocalStore::new(dir.path()), dir.path().join("spool")).unwrap();
+        let mut recordings = Vec::new();
+        for (session, price) in [("a", 100.0), ("b", 101.0)] {
+            std::fs::create_dir(dir.path().join(session)).unwrap();
+            let recording = dir.path().join(session).join("btc.mds");
+            let mut writer = RecordingWriter::create(&recording, 16).unwrap();
+            writer
+                .write(&MarketDataMessage::Trade(Trade::test("BTCUSD", price)))
+                .unwrap();
+            writer.finish().unwrap();
+            recordings.push(recording);
+        }
+
+        let spooled = uploader.enqueue(&recordings[0]).unwrap();
+        let contents = std::fs::read(&spooled).unwrap();
+        assert!(uploader.enqueue(&recordings[1]).is_err());
+        assert!(recordings[1].exists() && manifest_path(&recordings[1]).exists());
+        assert_eq!(std::fs::read(&spooled).unwrap(), contents);
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn test_multipart_parts() {
+        assert_eq!(part_size(MULTIPART_THRESHOLD + 1), PART_SIZE);
+        assert_eq!(part_size(1 << 40), (1u64 << 40).div_ceil(10_000));
+        let body = "<InitiateMultipartUploadResult><UploadId>x~1</UploadId></InitiateMultipartUploadResult>";
+        assert_eq!(xml_element(body, "UploadId").as_deref(), Some("x~1"));
+        assert_eq!(xml_element(body, "Key"), None);
+    }
+
+    #[tokio::test]
+    async fn test_keys_stay_below_the_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalStore::new(dir.path().join("bucket"));
+        let uploader = Uploader::new(store.clone(), dir.path().join("spool"))
+            .unwrap()
+            .with_venue("..");
+        let manifest = Manifest {
+            bytes: 0,
+            sha256: String::new(),
+            blocks: 0,
+            records: 0,
+            start: None,
+            end: None,
+            symbols: vec!["BTC/USD".to_string()],
+        };
+        assert_eq!(
+            uploader.object_key(&manifest, "a.mds"),
+            "%2E%2E/BTC%2FUSD/undated/a.mds"
+        );
+
+        let source = dir.path().join("a.mds");
+        std::fs::write(&source, b"data").unwrap();
+        assert!(store.put("../escaped.mds", &source).await.is_err());
+        assert!(store.put("/abs.mds", &source).await.is_err());
+        store.put("venue/a.mds", &source).await.unwrap();
+        assert!(dir.path().join("bucket/venue/a.mds").exists());
+    }
+}
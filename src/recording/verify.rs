@@ -0,0 +1,226 @@
+use super::{io_error, RecordingKey, RecordingReader, BLOCK_ENCRYPTED};
+use crate::client::{ClientError, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Summary written next to a finished recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// File length in bytes
+    pub bytes: u64,
+    /// Hex SHA-256 of the whole file
+    pub sha256: String,
+    pub blocks: usize,
+    pub records: u64,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    /// Symbols of the recorded messages
+    #[serde(default)]
+    pub symbols: Vec<String>,
+}
+
+impl Manifest {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| ClientError::Io(format!("{}: {}", path.display(), e)))?;
+        serde_json::from_str(&json).map_err(|e| ClientError::Parse(e.to_string()))
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| ClientError::Parse(e.to_string()))?;
+        std::fs::write(path, json).map_err(io_error)
+    }
+}
+
+/// `<recording>.manifest.json`
+pub fn manifest_path(recording: impl AsRef<Path>) -> PathBuf {
+    let mut path = recording.as_ref().as_os_str().to_owned();
+    path.push(".manifest.json");
+    PathBuf::from(path)
+}
+
+/// Outcome of [`verify`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerifyReport {
+    pub blocks: usize,
+    /// Records decoded; encrypted blocks are only checksummed without a key
+    pub records: u64,
+    /// The index footer is present
+    pub finished: bool,
+    /// A manifest was found and compared
+    pub manifest: bool,
+    pub problems: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Check a recording's structure, block checksums and contents, and its
+/// manifest if there is one. Problems are reported rather than returned as
+/// errors; errors mean the file could not be read at all.
+pub fn verify(path: impl AsRef<Path>, key: Option<&RecordingKey>) -> Result<VerifyReport> {
+    let path = path.as_ref();
+    let mut report = VerifyReport::default();
+    let mut reader = match RecordingReader::open(path) {
+        Ok(reader) => reader,
+        Err(ClientError::Io(e)) => {
+            return Err(ClientError::Io(format!("{}: {}", path.display(), e)))
+        }
+        Err(e) => {
+            report.problems.push(e.to_string());
+            return Ok(report);
+        }
+    };
+    if let Some(key) = key {
+        reader = reader.with_key(key)?;
+    }
+
+    let mut file = BufReader::new(File::open(path).map_err(io_error)?);
+    let len = file.seek(SeekFrom::End(0)).map_err(io_error)?;
+    let footer = RecordingReader::read_footer(&mut file)?;
+    report.finished = footer.is_some();
+    let end = footer.as_ref().map_or(len, |(_, start)| *start);
+    let (blocks, blocks_end) = RecordingReader::scan_blocks(&mut file, end)?;
+    report.blocks = blocks.len();
+
+    match &footer {
+        Some((index, _)) if *index != blocks => report
+            .problems
+            .push("index footer disagrees with block headers".to_string()),
+        Some(_) => {}
+        None => report
+            .problems
+            .push("no index footer (unfinished or truncated recording)".to_string()),
+    }
+    if blocks_end < end {
+        report.problems.push(format!(
+            "{} bytes after the last complete block at offset {}",
+            end - blocks_end,
+            blocks_end
+        ));
+    }
+
+    for block in &blocks {
+        let decoded = if block.flags & BLOCK_ENCRYPTED != 0 && key.is_none() {
+            reader.read_raw_block(block).map(|_| None)
+        } else {
+            reader.read_block(block).map(|records| Some(records.len()))
+        };
+        match decoded {
+            Ok(Some(records)) if records != block.records as usize => {
+                report.problems.push(format!(
+                    "block at offset {} holds {} records, header says {}",
+                    block.offset, records, block.records
+                ))
+            }
+            Ok(records) => report.records += records.unwrap_or_default() as u64,
+            Err(e) => report.problems.push(e.to_string()),
+        }
+    }
+
+    let manifest = manifest_path(path);
+    if manifest.exists() {
+        report.manifest = true;
+        match Manifest::load(&manifest) {
+            Ok(manifest) => {
+                let records: u64 = blocks.iter().map(|block| block.records as u64).sum();
+                if manifest.bytes != len {
+                    report.problems.push(format!(
+                        "file is {} bytes, manifest says {}",
+                        len, manifest.bytes
+                    ));
+                } else if manifest.sha256 != sha256_file(path)? {
+                    report
+                        .problems
+                        .push("SHA-256 does not match the manifest".to_string());
+                }
+                if (manifest.blocks, manifest.records) != (blocks.len(), records) {
+                    report.problems.push(format!(
+                        "{} blocks with {} records, manifest says {} with {}",
+                        blocks.len(),
+                        records,
+                        manifest.blocks,
+                        manifest.records
+                    ));
+                }
+            }
+            Err(e) => report.problems.push(format!("unreadable manifest: {}", e)),
+        }
+    }
+    Ok(report)
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut file = File::open(path).map_err(io_error)?;
+    std::io::copy(&mut file, &mut hasher).map_err(io_error)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{RecordingWriter, BLOCK_HEADER_LEN, MAGIC};
+    use super::*;
+    use crate::types::{MarketDataMessage, Trade};
+    use chrono::{Duration, TimeZone};
+    use std::io::Write as _;
+
+    #[test]
+    fn test_verify_detects_corruption_and_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.mds");
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut writer = RecordingWriter::create(&path, 4).unwrap();
+        for i in 0..10 {
+            let trade = Trade {
+                timestamp: t0 + Duration::seconds(i),
+                trade_id: i.to_string(),
+                ..Trade::test("BTCUSD", 100.0 + i as f64)
+            };
+            writer.write(&MarketDataMessage::Trade(trade)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let report = verify(&path, None).unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!((report.blocks, report.records), (3, 10));
+        assert!(report.finished && report.manifest);
+        let manifest = Manifest::load(manifest_path(&path)).unwrap();
+        assert_eq!(manifest.end, Some(t0 + Duration::seconds(9)));
+
+        // Flip a payload byte in the first block
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[MAGIC.len() + BLOCK_HEADER_LEN + 3] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+        let report = verify(&path, None).unwrap();
+        assert!(report.problems.iter().any(|p| p.contains("checksum")));
+        assert!(report.problems.iter().any(|p| p.contains("SHA-256")));
+
+        // Cut the file in the middle of the last block
+        let cut = RecordingReader::open(&path).unwrap().index()[2].offset + 10;
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&bytes[..cut as usize]).unwrap();
+        let report = verify(&path, None).unwrap();
+        assert!(!report.finished);
+        assert_eq!(report.blocks, 2);
+        assert!(report.problems.iter().any(|p| p.contains("10 bytes after")));
+    }
+}
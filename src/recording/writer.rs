@@ -0,0 +1,392 @@
+use super::crypto::{self, KeyProvider};
+use super::deltas::DeltaEncoder;
+use super::storage::{FileStorage, StorageBackend};
+use super::verify::{hex, Manifest};
+use super::{
+    from_nanos, io_error, to_nanos, BlockIndex, RecordRef, BLOCK_CHECKPOINT, BLOCK_ENCRYPTED,
+    BLOCK_HEADER_LEN, INDEX_MAGIC, MAGIC,
+};
+use crate::book::OrderBook;
+use crate::client::{ClientError, Result};
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use aes_gcm::Aes256Gcm;
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Writes messages into the compact block format
+pub struct RecordingWriter {
+    storage: Box<dyn StorageBackend>,
+    hasher: Sha256,
+    records: u64,
+    symbols: BTreeSet<Symbol>,
+    offset: u64,
+    block: Vec<u8>,
+    block_records: u32,
+    block_flags: u32,
+    max_block_records: u32,
+    min_ts: i64,
+    max_ts: i64,
+    last_ts: i64,
+    index: Vec<BlockIndex>,
+    checkpoint_interval: Option<i64>,
+    last_checkpoint: Option<i64>,
+    books: BTreeMap<Symbol, OrderBook>,
+    cipher: Option<Aes256Gcm>,
+    deltas: Option<DeltaEncoder>,
+    finished: bool,
+}
+
+impl RecordingWriter {
+    /// Create a new recording file, compressing every `max_block_records`
+    /// messages
+    pub fn create(path: impl AsRef<Path>, max_block_records: u32) -> Result<Self> {
+        Self::new(FileStorage::create(path)?, max_block_records)
+    }
+
+    /// Start a new recording in `storage`, compressing every
+    /// `max_block_records` messages
+    pub fn new(storage: impl StorageBackend + 'static, max_block_records: u32) -> Result<Self> {
+        let mut storage: Box<dyn StorageBackend> = Box::new(storage);
+        storage.append(MAGIC)?;
+
+        Ok(Self {
+            storage,
+            hasher: Sha256::new_with_prefix(MAGIC),
+            records: 0,
+            symbols: BTreeSet::new(),
+            offset: MAGIC.len() as u64,
+            block: Vec::new(),
+            block_records: 0,
+            block_flags: 0,
+            max_block_records: max_block_records.max(1),
+            min_ts: 0,
+            max_ts: 0,
+            last_ts: 0,
+            index: Vec::new(),
+            checkpoint_interval: None,
+            last_checkpoint: None,
+            books: BTreeMap::new(),
+            cipher: None,
+            deltas: None,
+            finished: false,
+        })
+    }
+
+    /// Start a block with a full checkpoint of every order book at most once
+    /// per `interval`, so readers can reconstruct books without replaying
+    /// from the start of the file
+    pub fn with_book_checkpoints(mut self, interval: Duration) -> Self {
+        self.checkpoint_interval = Some(interval.num_nanoseconds().unwrap_or(i64::MAX));
+        self
+    }
+
+    /// Store each book as the levels that changed since the previous one of
+    /// its symbol, with a full keyframe at the start of every block. Readers
+    /// rebuild the exact snapshots that were written.
+    pub fn with_book_deltas(mut self) -> Self {
+        self.deltas = Some(DeltaEncoder::default());
+        self
+    }
+
+    /// Encrypt every block written from now on with the key from `keys`
+    pub fn with_encryption(mut self, keys: &impl KeyProvider) -> Result<Self> {
+        self.cipher = Some(keys.recording_key()?.cipher());
+        Ok(self)
+    }
+
+    /// Append a message stamped with its own timestamp.
+    ///
+    /// Messages without a timestamp (heartbeats) reuse the previous one.
+    pub fn write(&mut self, msg: &MarketDataMessage) -> Result<()> {
+        let ts = msg.timestamp().map(to_nanos).unwrap_or(self.last_ts);
+        self.write_nanos(ts, msg)
+    }
+
+    /// Append a message with an explicit recording timestamp
+    pub fn write_at(&mut self, ts: DateTime<Utc>, msg: &MarketDataMessage) -> Result<()> {
+        self.write_nanos(to_nanos(ts), msg)
+    }
+
+    fn write_nanos(&mut self, ts: i64, msg: &MarketDataMessage) -> Result<()> {
+        if self.block_records == 0 && self.checkpoint_due(ts) {
+            self.write_checkpoints()?;
+        }
+
+        let update = match (msg, self.deltas.as_mut()) {
+            (MarketDataMessage::OrderBook(snapshot), Some(deltas)) => deltas.encode(snapshot),
+            _ => None,
+        };
+        match &update {
+            Some(update) => self.push_record(ts, &RecordRef::BookDelta(update))?,
+            None => self.push_record(ts, &RecordRef::from(msg))?,
+        }
+        if let Some(symbol) = msg.symbol() {
+            self.symbols.insert(symbol);
+        }
+
+        if self.checkpoint_interval.is_some() {
+            if let MarketDataMessage::OrderBook(snapshot) = msg {
+                self.books
+                    .entry(snapshot.symbol)
+                    .or_insert_with(|| OrderBook::new(snapshot.symbol))
+                    .apply_snapshot(snapshot);
+            }
+        }
+
+        if self.block_records >= self.max_block_records {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    fn checkpoint_due(&self, ts: i64) -> bool {
+        match (self.checkpoint_interval, self.last_checkpoint) {
+            (None, _) => false,
+            (Some(_), _) if self.books.is_empty() => false,
+            (Some(_), None) => true,
+            (Some(interval), Some(last)) => ts.saturating_sub(last) >= interval,
+        }
+    }
+
+    fn write_checkpoints(&mut self) -> Result<()> {
+        let ts = self.last_ts;
+        let snapshots: Vec<_> = self
+            .books
+            .values()
+            .map(|book| book.snapshot(None))
+            .collect();
+        for snapshot in &snapshots {
+            self.push_record(ts, &RecordRef::Checkpoint(snapshot))?;
+        }
+        self.block_flags |= BLOCK_CHECKPOINT;
+        self.last_checkpoint = Some(ts);
+        Ok(())
+    }
+
+    fn push_record(&mut self, ts: i64, record: &RecordRef<'_>) -> Result<()> {
+        let payload =
+            postcard::to_allocvec(record).map_err(|e| ClientError::Parse(e.to_string()))?;
+
+        if self.block_records == 0 {
+            self.min_ts = ts;
+            self.max_ts = ts;
+        }
+        self.min_ts = self.min_ts.min(ts);
+        self.max_ts = self.max_ts.max(ts);
+        self.block
+            .extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.block.extend_from_slice(&ts.to_le_bytes());
+        self.block.extend_from_slice(&payload);
+        self.block_records += 1;
+        self.last_ts = ts;
+        Ok(())
+    }
+
+    /// Compress and write the pending block, if any
+    pub fn flush_block(&mut self) -> Result<()> {
+        if self.block_records == 0 {
+            return Ok(());
+        }
+
+        let compressed = zstd::encode_all(self.block.as_slice(), 3).map_err(io_error)?;
+        let mut payload_len = compressed.len();
+        if self.cipher.is_some() {
+            self.block_flags |= BLOCK_ENCRYPTED;
+            payload_len += crypto::OVERHEAD;
+        }
+        let mut header = Vec::with_capacity(BLOCK_HEADER_LEN);
+        header.extend_from_slice(&(payload_len as u32).to_le_bytes());
+        header.extend_from_slice(&self.block_records.to_le_bytes());
+        header.extend_from_slice(&self.block_flags.to_le_bytes());
+        header.extend_from_slice(&self.min_ts.to_le_bytes());
+        header.extend_from_slice(&self.max_ts.to_le_bytes());
+        let compressed = match &self.cipher {
+            // The checksum is not known yet, so it is left out of the AAD
+            Some(cipher) => crypto::seal(cipher, self.index.len() as u64, &header, &compressed)?,
+            None => compressed,
+        };
+        header.extend_from_slice(&crc32fast::hash(&compressed).to_le_bytes());
+
+        self.put(&header)?;
+        self.put(&compressed)?;
+        self.storage.flush()?;
+
+        self.records += self.block_records as u64;
+        self.index.push(BlockIndex {
+            offset: self.offset,
+            records: self.block_records,
+            flags: self.block_flags,
+            min_ts: self.min_ts,
+            max_ts: self.max_ts,
+        });
+        self.offset += (header.len() + compressed.len()) as u64;
+        self.block.clear();
+        self.block_records = 0;
+        self.block_flags = 0;
+        if let Some(deltas) = self.deltas.as_mut() {
+            deltas.reset();
+        }
+        Ok(())
+    }
+
+    /// Index entries for the blocks written so far
+    pub fn index(&self) -> &[BlockIndex] {
+        &self.index
+    }
+
+    /// Flush the last block and write the time index footer
+    pub fn finish(mut self) -> Result<()> {
+        self.write_footer()
+    }
+
+    fn write_footer(&mut self) -> Result<()> {
+        self.flush_block()?;
+        let index =
+            postcard::to_allocvec(&self.index).map_err(|e| ClientError::Parse(e.to_string()))?;
+
+        self.put(&index)?;
+        self.put(&(index.len() as u32).to_le_bytes())?;
+        self.put(INDEX_MAGIC)?;
+        self.storage.flush()?;
+        self.finished = true;
+
+        let manifest = Manifest {
+            bytes: self.offset + index.len() as u64 + 4 + INDEX_MAGIC.len() as u64,
+            sha256: hex(&self.hasher.clone().finalize()),
+            blocks: self.index.len(),
+            records: self.records,
+            start: self.index.iter().map(|b| b.min_ts).min().map(from_nanos),
+            end: self.index.iter().map(|b| b.max_ts).max().map(from_nanos),
+            symbols: self.symbols.iter().map(Symbol::to_string).collect(),
+        };
+        self.storage.finish(&manifest)
+    }
+
+    /// Write through to storage, feeding the whole-file checksum
+    fn put(&mut self, bytes: &[u8]) -> Result<()> {
+        self.hasher.update(bytes);
+        self.storage.append(bytes)
+    }
+}
+
+impl Drop for RecordingWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.write_footer();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::storage::MemoryStorage;
+    use super::super::{RecordingKey, RecordingReader};
+    use super::*;
+    use crate::types::Trade;
+    use chrono::TimeZone;
+
+    fn trade(symbol: &str, ts: DateTime<Utc>) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            timestamp: ts,
+            ..Trade::test(symbol, 100.0)
+        })
+    }
+
+    /// Write `count` trades one second apart in blocks of two
+    fn record(path: &Path, count: i64, key: Option<&RecordingKey>) {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut writer = RecordingWriter::create(path, 2).unwrap();
+        if let Some(key) = key {
+            writer = writer.with_encryption(key).unwrap();
+        }
+        for i in 0..count {
+            writer
+                .write(&trade("BTCUSD", t0 + Duration::seconds(i)))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_blocks_index_and_manifest() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let storage = MemoryStorage::new();
+        let mut writer = RecordingWriter::new(storage.clone(), 2).unwrap();
+        writer.write(&trade("ETHUSD", t0)).unwrap();
+        // Heartbeats have no timestamp of their own
+        writer.write(&MarketDataMessage::Heartbeat).unwrap();
+        assert_eq!(writer.index().len(), 1);
+        assert_eq!(writer.index()[0].records, 2);
+        assert_eq!(writer.index()[0].max_ts, to_nanos(t0));
+        writer
+            .write_at(t0 + Duration::seconds(5), &trade("BTCUSD", t0))
+            .unwrap();
+        writer.finish().unwrap();
+
+        let bytes = storage.bytes();
+        assert!(bytes.starts_with(MAGIC) && bytes.ends_with(INDEX_MAGIC));
+        let manifest = storage.manifest().unwrap();
+        assert_eq!(manifest.bytes, bytes.len() as u64);
+        assert_eq!(manifest.sha256, hex(&Sha256::digest(&bytes)));
+        assert_eq!((manifest.blocks, manifest.records), (2, 3));
+        assert_eq!(manifest.start, Some(t0));
+        assert_eq!(manifest.end, Some(t0 + Duration::seconds(5)));
+        assert_eq!(manifest.symbols, ["BTCUSD", "ETHUSD"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.mds");
+        std::fs::write(&path, &bytes).unwrap();
+        let stamps: Vec<_> = RecordingReader::open(&path)
+            .unwrap()
+            .messages()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+        assert_eq!(stamps, [t0, t0, t0 + Duration::seconds(5)]);
+    }
+
+    #[test]
+    fn test_tampered_and_truncated_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.mds");
+        record(&path, 4, None);
+        let bytes = std::fs::read(&path).unwrap();
+        let second = RecordingReader::open(&path).unwrap().index()[1].offset as usize;
+
+        // A flipped payload byte fails the block checksum
+        let mut tampered = bytes.clone();
+        tampered[second + BLOCK_HEADER_LEN + 1] ^= 0x40;
+        std::fs::write(&path, &tampered).unwrap();
+        let read: Vec<_> = RecordingReader::open(&path).unwrap().messages().collect();
+        assert_eq!(read.len(), 3);
+        assert!(read[..2].iter().all(Result::is_ok));
+        assert!(read[2].is_err());
+
+        // A recording cut off inside a block keeps the blocks before it
+        std::fs::write(&path, &bytes[..second + BLOCK_HEADER_LEN + 4]).unwrap();
+        assert_eq!(RecordingReader::open(&path).unwrap().messages().count(), 2);
+        std::fs::write(&path, &bytes[..4]).unwrap();
+        assert!(RecordingReader::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_block_headers_are_authenticated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.mds");
+        let key = RecordingKey::from_bytes([7; 32]);
+        record(&path, 2, Some(&key));
+        let mut bytes = std::fs::read(&path).unwrap();
+
+        // Moving the block in time changes no checksummed byte
+        let min_ts = MAGIC.len() + 12;
+        bytes[min_ts] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let mut reader = RecordingReader::open(&path)
+            .unwrap()
+            .with_key(&key)
+            .unwrap();
+        assert!(reader.messages().next().unwrap().is_err());
+    }
+}
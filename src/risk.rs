@@ -0,0 +1,350 @@
+//! Risk limits evaluated against the stream.
+//!
+//! A [`RiskMonitor`] holds per-symbol [`RiskLimit`]s and checks them on
+//! every price, taken from trades and the quote or book mid: the adverse
+//! move of a position from its entry, the volatility of recent price
+//! changes, and the age of the last price. A limit raises one
+//! [`RiskBreach`] when it is crossed and re-arms once the condition clears;
+//! while any limit is breached [`RiskMonitor::is_halted`] reports the
+//! symbol halted, for execution systems to stop trading it. Staleness is
+//! measured in event time, against the `now` passed to
+//! [`check`](RiskMonitor::check).
+
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A limit on one symbol
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RiskLimit {
+    /// The price moved against a position of signed `quantity` entered at
+    /// `entry_price` by `max_bps` basis points or more
+    AdverseMove {
+        quantity: f64,
+        entry_price: f64,
+        max_bps: f64,
+    },
+    /// The standard deviation of the last `window` price returns reached
+    /// `max_bps` basis points
+    Volatility { window: usize, max_bps: f64 },
+    /// No price for `max_age` or longer; symbols not yet priced are halted
+    StalePrice { max_age: Duration },
+}
+
+/// Which limit was breached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreachKind {
+    AdverseMove,
+    Volatility,
+    StalePrice,
+}
+
+/// A limit crossed by the market
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RiskBreach {
+    pub symbol: Symbol,
+    pub kind: BreachKind,
+    /// Measured value: basis points, or seconds for stale prices
+    pub value: f64,
+    /// The limit it crossed, in the same unit
+    pub limit: f64,
+    /// Last price of the symbol, if any
+    pub price: Option<f64>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct LimitState {
+    limit: RiskLimit,
+    breached: bool,
+    /// Recent returns for volatility limits
+    returns: VecDeque<f64>,
+}
+
+impl LimitState {
+    /// The measured value and limit, if the limit applies yet
+    fn measure(&mut self, price: f64, previous: Option<f64>) -> Option<(f64, f64)> {
+        match self.limit {
+            RiskLimit::AdverseMove {
+                quantity,
+                entry_price,
+                max_bps,
+            } => {
+                if quantity == 0.0 || entry_price <= 0.0 {
+                    return None;
+                }
+                let moved = (price - entry_price) / entry_price * 10_000.0;
+                Some((-moved * quantity.signum(), max_bps))
+            }
+            RiskLimit::Volatility { window, max_bps } => {
+                let previous = previous.filter(|previous| *previous > 0.0)?;
+                self.returns.push_back((price / previous).ln());
+                if self.returns.len() > window {
+                    self.returns.pop_front();
+                }
+                if self.returns.len() < window.max(2) {
+                    return None;
+                }
+                let n = self.returns.len() as f64;
+                let mean = self.returns.iter().sum::<f64>() / n;
+                let variance =
+                    self.returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+                Some((variance.sqrt() * 10_000.0, max_bps))
+            }
+            RiskLimit::StalePrice { .. } => None,
+        }
+    }
+
+    fn kind(&self) -> BreachKind {
+        match self.limit {
+            RiskLimit::AdverseMove { .. } => BreachKind::AdverseMove,
+            RiskLimit::Volatility { .. } => BreachKind::Volatility,
+            RiskLimit::StalePrice { .. } => BreachKind::StalePrice,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct SymbolRisk {
+    limits: Vec<LimitState>,
+    price: Option<f64>,
+    priced_at: Option<DateTime<Utc>>,
+}
+
+/// Evaluates [`RiskLimit`]s on the stream and publishes [`RiskBreach`]es.
+///
+/// Breaches are only reported, to receivers from
+/// [`subscribe`](Self::subscribe); the monitor never drops or holds back
+/// messages.
+pub struct RiskMonitor {
+    symbols: HashMap<Symbol, SymbolRisk>,
+    tx: broadcast::Sender<RiskBreach>,
+}
+
+impl Default for RiskMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RiskMonitor {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            symbols: HashMap::new(),
+            tx,
+        }
+    }
+
+    pub fn with_limit(mut self, symbol: &str, limit: RiskLimit) -> Self {
+        self.add_limit(symbol, limit);
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RiskBreach> {
+        self.tx.subscribe()
+    }
+
+    pub fn add_limit(&mut self, symbol: &str, limit: RiskLimit) {
+        self.symbols
+            .entry(symbol.into())
+            .or_default()
+            .limits
+            .push(LimitState {
+                limit,
+                breached: false,
+                returns: VecDeque::new(),
+            });
+    }
+
+    /// Drop every limit on `symbol`, lifting its halt
+    pub fn clear_limits(&mut self, symbol: &str) {
+        self.symbols.remove(symbol);
+    }
+
+    /// Limits of `symbol` currently breached
+    pub fn breaches(&self, symbol: &str) -> Vec<BreachKind> {
+        self.symbols.get(symbol).map_or_else(Vec::new, |risk| {
+            risk.limits
+                .iter()
+                .filter(|state| state.breached)
+                .map(LimitState::kind)
+                .collect()
+        })
+    }
+
+    /// Whether `symbol` has a breached limit or a stale-price limit and no
+    /// price yet
+    pub fn is_halted(&self, symbol: &str) -> bool {
+        self.symbols.get(symbol).is_some_and(|risk| {
+            risk.limits.iter().any(|state| {
+                state.breached
+                    || (matches!(state.limit, RiskLimit::StalePrice { .. })
+                        && risk.priced_at.is_none())
+            })
+        })
+    }
+
+    /// Feed a message, returning the breaches its price raises
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<RiskBreach> {
+        let (symbol, price) = match msg {
+            MarketDataMessage::Trade(trade) => (trade.symbol, Some(trade.price)),
+            MarketDataMessage::Quote(quote) => (
+                quote.symbol,
+                (quote.bid_price > 0.0 && quote.ask_price > 0.0).then(|| quote.mid_price()),
+            ),
+            MarketDataMessage::OrderBook(book) => (book.symbol, book.mid_price()),
+            _ => return Vec::new(),
+        };
+        let (Some(price), Some(risk)) = (price, self.symbols.get_mut(&symbol)) else {
+            return Vec::new();
+        };
+        let timestamp = msg.timestamp().unwrap_or_else(Utc::now);
+        let previous = risk.price.replace(price);
+        risk.priced_at = Some(timestamp);
+
+        let mut breaches = Vec::new();
+        for state in &mut risk.limits {
+            let measured = match state.limit {
+                // A fresh price clears staleness
+                RiskLimit::StalePrice { .. } => None,
+                _ => match state.measure(price, previous) {
+                    Some(measured) => Some(measured),
+                    None => continue,
+                },
+            };
+            let crossed = measured.filter(|(value, limit)| value >= limit);
+            if let (Some((value, limit)), false) = (crossed, state.breached) {
+                breaches.push(RiskBreach {
+                    symbol,
+                    kind: state.kind(),
+                    value,
+                    limit,
+                    price: Some(price),
+                    timestamp,
+                });
+            }
+            state.breached = crossed.is_some();
+        }
+        self.publish(&breaches);
+        breaches
+    }
+
+    /// Check stale-price limits at `now`, returning new breaches
+    pub fn check(&mut self, now: DateTime<Utc>) -> Vec<RiskBreach> {
+        let mut breaches = Vec::new();
+        for (symbol, risk) in &mut self.symbols {
+            let Some(priced_at) = risk.priced_at else {
+                continue;
+            };
+            let age = now - priced_at;
+            for state in &mut risk.limits {
+                let RiskLimit::StalePrice { max_age } = state.limit else {
+                    continue;
+                };
+                if age >= max_age && !state.breached {
+                    breaches.push(RiskBreach {
+                        symbol: *symbol,
+                        kind: BreachKind::StalePrice,
+                        value: age.num_milliseconds() as f64 / 1000.0,
+                        limit: max_age.num_milliseconds() as f64 / 1000.0,
+                        price: risk.price,
+                        timestamp: now,
+                    });
+                }
+                state.breached = age >= max_age;
+            }
+        }
+        self.publish(&breaches);
+        breaches
+    }
+
+    fn publish(&self, breaches: &[RiskBreach]) {
+        for breach in breaches {
+            let _ = self.tx.send(breach.clone());
+        }
+    }
+}
+
+impl Stage for RiskMonitor {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Trade;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_limits_breach_once_and_halt() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let trade = |secs: i64, price: f64| {
+            MarketDataMessage::Trade(Trade {
+                timestamp: t0 + Duration::seconds(secs),
+                ..Trade::test("BTCUSD", price)
+            })
+        };
+        let mut monitor = RiskMonitor::new()
+            .with_limit(
+                "BTCUSD",
+                RiskLimit::AdverseMove {
+                    quantity: -2.0,
+                    entry_price: 100.0,
+                    max_bps: 100.0,
+                },
+            )
+            .with_limit(
+                "BTCUSD",
+                RiskLimit::Volatility {
+                    window: 3,
+                    max_bps: 300.0,
+                },
+            )
+            .with_limit(
+                "BTCUSD",
+                RiskLimit::StalePrice {
+                    max_age: Duration::seconds(10),
+                },
+            );
+        let mut rx = monitor.subscribe();
+        assert!(monitor.is_halted("BTCUSD"));
+        assert!(!monitor.is_halted("ETHUSD"));
+
+        assert!(monitor.on_message(&trade(0, 100.0)).is_empty());
+        assert!(!monitor.is_halted("BTCUSD"));
+        // Up 1% against the short position
+        let breaches = monitor.on_message(&trade(1, 101.0));
+        assert_eq!(breaches.len(), 1);
+        assert_eq!(breaches[0].kind, BreachKind::AdverseMove);
+        assert!(monitor.on_message(&trade(2, 101.5)).is_empty());
+        assert!(monitor.on_message(&trade(3, 99.0)).is_empty());
+        assert_eq!(monitor.breaches("BTCUSD"), vec![]);
+
+        // Swings of several percent trip the volatility breaker
+        let breaches = monitor.on_message(&trade(4, 93.0));
+        assert_eq!(breaches[0].kind, BreachKind::Volatility);
+        assert!(monitor.is_halted("BTCUSD"));
+        assert_eq!(rx.try_recv().unwrap().kind, BreachKind::AdverseMove);
+
+        assert!(monitor.check(t0 + Duration::seconds(13)).is_empty());
+        let stale = monitor.check(t0 + Duration::seconds(14));
+        assert_eq!(
+            (stale[0].kind, stale[0].value),
+            (BreachKind::StalePrice, 10.0)
+        );
+        assert!(monitor.check(t0 + Duration::seconds(20)).is_empty());
+        monitor.on_message(&trade(21, 93.0));
+        assert!(!monitor.breaches("BTCUSD").contains(&BreachKind::StalePrice));
+    }
+}
@@ -0,0 +1,267 @@
+//! Rolling trade statistics over the last N trades per symbol.
+//!
+//! [`RollingWindow`] keeps prices, sizes and log returns in separate
+//! fixed-size ring buffers. Sums, minima and maxima do not depend on the
+//! order of the samples, so the kernels run over the whole buffers in
+//! chunks of [`LANES`] independent accumulators, which the compiler turns
+//! into SIMD instructions on stable Rust. [`RollingStatsTracker`] keeps one
+//! window per symbol for stats across many symbols at high tick rates.
+
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Trade};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Accumulators per kernel, enough for 512-bit vectors of `f64`
+pub const LANES: usize = 8;
+
+/// Statistics of the trades in a window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RollingStats {
+    pub trades: usize,
+    pub volume: f64,
+    pub vwap: f64,
+    /// Sample standard deviation of trade-to-trade log returns
+    pub volatility: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Prices, sizes and returns of the last `capacity` trades
+#[derive(Debug, Clone)]
+pub struct RollingWindow {
+    prices: Vec<f64>,
+    sizes: Vec<f64>,
+    returns: Vec<f64>,
+    capacity: usize,
+    /// Next slot of `prices` and `sizes` to overwrite once full
+    head: usize,
+    return_head: usize,
+    last_price: Option<f64>,
+}
+
+impl RollingWindow {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            prices: Vec::with_capacity(capacity),
+            sizes: Vec::with_capacity(capacity),
+            returns: Vec::with_capacity(capacity),
+            capacity,
+            head: 0,
+            return_head: 0,
+            last_price: None,
+        }
+    }
+
+    pub fn push(&mut self, price: f64, size: f64) {
+        if let Some(last) = self.last_price.filter(|last| *last > 0.0 && price > 0.0) {
+            ring_push(
+                &mut self.returns,
+                &mut self.return_head,
+                self.capacity,
+                (price / last).ln(),
+            );
+        }
+        self.last_price = Some(price);
+        if self.prices.len() < self.capacity {
+            self.prices.push(price);
+            self.sizes.push(size);
+        } else {
+            self.prices[self.head] = price;
+            self.sizes[self.head] = size;
+            self.head = (self.head + 1) % self.capacity;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.prices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prices.is_empty()
+    }
+
+    pub fn stats(&self) -> RollingStats {
+        if self.prices.is_empty() {
+            return RollingStats::default();
+        }
+        let volume = sum(&self.sizes);
+        let (min, max) = min_max(&self.prices);
+        RollingStats {
+            trades: self.prices.len(),
+            volume,
+            vwap: if volume > 0.0 {
+                dot(&self.prices, &self.sizes) / volume
+            } else {
+                0.0
+            },
+            volatility: std_dev(&self.returns),
+            min,
+            max,
+        }
+    }
+}
+
+fn ring_push(values: &mut Vec<f64>, head: &mut usize, capacity: usize, value: f64) {
+    if values.len() < capacity {
+        values.push(value);
+    } else {
+        values[*head] = value;
+        *head = (*head + 1) % capacity;
+    }
+}
+
+fn sum(values: &[f64]) -> f64 {
+    let mut acc = [0.0; LANES];
+    let chunks = values.chunks_exact(LANES);
+    let tail: f64 = chunks.remainder().iter().sum();
+    for chunk in chunks {
+        for lane in 0..LANES {
+            acc[lane] += chunk[lane];
+        }
+    }
+    acc.iter().sum::<f64>() + tail
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    let mut acc = [0.0; LANES];
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail: f64 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for lane in 0..LANES {
+            acc[lane] += x[lane] * y[lane];
+        }
+    }
+    acc.iter().sum::<f64>() + tail
+}
+
+fn min_max(values: &[f64]) -> (f64, f64) {
+    let mut lo = [f64::INFINITY; LANES];
+    let mut hi = [f64::NEG_INFINITY; LANES];
+    let chunks = values.chunks_exact(LANES);
+    let (mut min, mut max) = chunks
+        .remainder()
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| {
+            (lo.min(*x), hi.max(*x))
+        });
+    for chunk in chunks {
+        for lane in 0..LANES {
+            lo[lane] = lo[lane].min(chunk[lane]);
+            hi[lane] = hi[lane].max(chunk[lane]);
+        }
+    }
+    for lane in 0..LANES {
+        min = min.min(lo[lane]);
+        max = max.max(hi[lane]);
+    }
+    (min, max)
+}
+
+/// Sample standard deviation, in two passes for numerical stability
+fn std_dev(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let n = values.len() as f64;
+    let mean = sum(values) / n;
+    let mut acc = [0.0; LANES];
+    let chunks = values.chunks_exact(LANES);
+    let tail: f64 = chunks.remainder().iter().map(|x| (x - mean).powi(2)).sum();
+    for chunk in chunks {
+        for lane in 0..LANES {
+            let d = chunk[lane] - mean;
+            acc[lane] += d * d;
+        }
+    }
+    ((acc.iter().sum::<f64>() + tail) / (n - 1.0)).sqrt()
+}
+
+/// One [`RollingWindow`] per symbol
+#[derive(Debug, Clone)]
+pub struct RollingStatsTracker {
+    capacity: usize,
+    windows: HashMap<Symbol, RollingWindow>,
+}
+
+impl RollingStatsTracker {
+    /// Stats over the last `capacity` trades of each symbol
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            windows: HashMap::new(),
+        }
+    }
+
+    pub fn on_message(&mut self, msg: &MarketDataMessage) {
+        if let MarketDataMessage::Trade(trade) = msg {
+            self.on_trade(trade);
+        }
+    }
+
+    pub fn on_trade(&mut self, trade: &Trade) {
+        self.windows
+            .entry(trade.symbol)
+            .or_insert_with(|| RollingWindow::new(self.capacity))
+            .push(trade.price, trade.quantity);
+    }
+
+    pub fn stats(&self, symbol: &str) -> Option<RollingStats> {
+        self.windows.get(symbol).map(RollingWindow::stats)
+    }
+
+    /// Stats of every symbol seen
+    pub fn all(&self) -> HashMap<Symbol, RollingStats> {
+        self.windows
+            .iter()
+            .map(|(symbol, window)| (*symbol, window.stats()))
+            .collect()
+    }
+}
+
+impl Stage for RollingStatsTracker {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_matches_scalar_stats() {
+        let mut window = RollingWindow::new(20);
+        let prices: Vec<f64> = (0..35).map(|i| 100.0 + ((i * 7) % 11) as f64).collect();
+        for (i, price) in prices.iter().enumerate() {
+            window.push(*price, 1.0 + (i % 3) as f64);
+        }
+
+        // Only the last 20 trades and the 20 returns between the last 21
+        let last = &prices[15..];
+        let sizes: Vec<f64> = (15..35).map(|i| 1.0 + (i % 3) as f64).collect();
+        let vwap =
+            last.iter().zip(&sizes).map(|(p, q)| p * q).sum::<f64>() / sizes.iter().sum::<f64>();
+        let returns: Vec<f64> = prices[14..]
+            .windows(2)
+            .map(|w| (w[1] / w[0]).ln())
+            .collect();
+        let mean = returns.iter().sum::<f64>() / 20.0;
+        let volatility = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / 19.0).sqrt();
+
+        let stats = window.stats();
+        assert_eq!((stats.trades, stats.volume), (20, sizes.iter().sum()));
+        assert!((stats.vwap - vwap).abs() < 1e-9);
+        assert!((stats.volatility - volatility).abs() < 1e-12);
+        assert_eq!((stats.min, stats.max), (100.0, 110.0));
+    }
+}
@@ -0,0 +1,209 @@
+//! Evenly spaced mid-price series with forward-fill and staleness flags.
+
+use crate::client::{ClientError, Result};
+use crate::clock::align;
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Mid price of one symbol at a grid time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub symbol: Symbol,
+    pub timestamp: DateTime<Utc>,
+    pub mid: f64,
+    /// Time of the quote the mid came from
+    pub updated: DateTime<Utc>,
+    /// The quote is older than the maximum age, so the mid was carried
+    /// forward (or interpolated by [`interpolate_stale`])
+    pub stale: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Series {
+    mid: f64,
+    updated: DateTime<Utc>,
+    samples: VecDeque<Sample>,
+}
+
+/// Samples mid prices from quotes and books onto a fixed event-time grid.
+///
+/// Each grid time crossed by the stream yields one sample per symbol seen so
+/// far, forward-filling gaps; a sample is stale when no quote arrived within
+/// the maximum age (one interval by default).
+#[derive(Debug, Clone)]
+pub struct MidSampler {
+    interval: Duration,
+    max_age: Duration,
+    history: usize,
+    series: BTreeMap<Symbol, Series>,
+    next_tick: Option<DateTime<Utc>>,
+}
+
+impl MidSampler {
+    /// Sample every `interval`, which must be positive
+    pub fn new(interval: Duration) -> Result<Self> {
+        if interval <= Duration::zero() {
+            return Err(ClientError::Parse(format!(
+                "sampling interval must be positive, got {}",
+                interval
+            )));
+        }
+        Ok(Self {
+            interval,
+            max_age: interval,
+            history: 3600,
+            series: BTreeMap::new(),
+            next_tick: None,
+        })
+    }
+
+    /// Quotes older than `max_age` at a grid time are flagged stale
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Samples kept per symbol for [`series`](Self::series)
+    pub fn with_history(mut self, samples: usize) -> Self {
+        self.history = samples.max(1);
+        self
+    }
+
+    /// Feed a message, returning the samples of every grid time up to its
+    /// timestamp, oldest first
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<Sample> {
+        let Some(ts) = msg.timestamp() else {
+            return Vec::new();
+        };
+        let samples = self.advance(ts);
+        let (symbol, mid) = match msg {
+            MarketDataMessage::Quote(quote) if quote.bid_price > 0.0 && quote.ask_price > 0.0 => {
+                (&quote.symbol, quote.mid_price())
+            }
+            MarketDataMessage::OrderBook(book) => match book.mid_price() {
+                Some(mid) => (&book.symbol, mid),
+                None => return samples,
+            },
+            _ => return samples,
+        };
+        match self.series.get_mut(symbol) {
+            Some(series) => {
+                series.mid = mid;
+                series.updated = ts;
+            }
+            None => {
+                let series = Series {
+                    mid,
+                    updated: ts,
+                    samples: VecDeque::new(),
+                };
+                self.series.insert(*symbol, series);
+            }
+        }
+        samples
+    }
+
+    /// Sample every grid time at or before `now`
+    pub fn advance(&mut self, now: DateTime<Utc>) -> Vec<Sample> {
+        let Some(mut tick) = self.next_tick else {
+            self.next_tick = Some(align(now, self.interval) + self.interval);
+            return Vec::new();
+        };
+        // Beyond the history, earlier fills would be evicted right away
+        let width = self.interval.num_nanoseconds().unwrap_or(i64::MAX).max(1);
+        let behind = (now - tick).num_nanoseconds().unwrap_or(i64::MAX) / width;
+        if behind >= self.history as i64 {
+            tick = align(now, self.interval) - self.interval * (self.history as i32 - 1);
+        }
+
+        let mut out = Vec::new();
+        while tick <= now {
+            for (symbol, series) in &mut self.series {
+                let sample = Sample {
+                    symbol: *symbol,
+                    timestamp: tick,
+                    mid: series.mid,
+                    updated: series.updated,
+                    stale: tick - series.updated > self.max_age,
+                };
+                if series.samples.len() == self.history {
+                    series.samples.pop_front();
+                }
+                series.samples.push_back(sample.clone());
+                out.push(sample);
+            }
+            tick += self.interval;
+        }
+        self.next_tick = Some(tick);
+        out
+    }
+
+    /// Recent samples of `symbol`, oldest first
+    pub fn series(&self, symbol: &str) -> Vec<Sample> {
+        self.series
+            .get(symbol)
+            .map(|series| series.samples.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Replace runs of stale samples between two fresh ones with a linear
+/// interpolation of the fresh mids; stale flags are kept
+pub fn interpolate_stale(series: &mut [Sample]) {
+    let mut last_fresh: Option<usize> = None;
+    for i in 0..series.len() {
+        if series[i].stale {
+            continue;
+        }
+        if let Some(start) = last_fresh.filter(|&start| i > start + 1) {
+            let (from, to) = (series[start].mid, series[i].mid);
+            let steps = (i - start) as f64;
+            for (k, sample) in series[start + 1..i].iter_mut().enumerate() {
+                sample.mid = from + (to - from) * (k + 1) as f64 / steps;
+            }
+        }
+        last_fresh = Some(i);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Quote;
+    use chrono::TimeZone;
+
+    fn quote(ts: DateTime<Utc>, mid: f64) -> MarketDataMessage {
+        MarketDataMessage::Quote(Quote {
+            timestamp: ts,
+            ..Quote::test("BTCUSD", mid - 0.5, mid + 0.5)
+        })
+    }
+
+    #[test]
+    fn test_forward_fill_flags_and_interpolation() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        assert!(MidSampler::new(Duration::zero()).is_err());
+        let mut sampler = MidSampler::new(Duration::seconds(1)).unwrap();
+        let ms = Duration::milliseconds;
+
+        sampler.on_message(&quote(t0 + ms(200), 100.0));
+        // Quiet for three seconds
+        let samples = sampler.on_message(&quote(t0 + ms(4_100), 104.0));
+        let flags: Vec<(f64, bool)> = samples.iter().map(|s| (s.mid, s.stale)).collect();
+        assert_eq!(
+            flags,
+            [(100.0, false), (100.0, true), (100.0, true), (100.0, true)]
+        );
+        sampler.advance(t0 + ms(5_000));
+
+        let mut series = sampler.series("BTCUSD");
+        assert_eq!(series.len(), 5);
+        assert_eq!(series[4].timestamp, t0 + Duration::seconds(5));
+        interpolate_stale(&mut series);
+        let mids: Vec<f64> = series.iter().map(|s| s.mid).collect();
+        assert_eq!(mids, [100.0, 101.0, 102.0, 103.0, 104.0]);
+    }
+}
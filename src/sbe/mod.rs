@@ -0,0 +1,390 @@
+//! Schema-driven Simple Binary Encoding (SBE) decoding.
+//!
+//! An [`SbeSchema`] is loaded at runtime from the venue's XML message schema
+//! (e.g. CME MDP 3.0 `templates_FixBinary.xml`) and decodes any message it
+//! defines into named [`Value`]s, including repeating groups and variable
+//! length data, so binary feeds can be normalized by
+//! [`SbeAdapter`](crate::adapters::SbeAdapter) handlers without hand-written
+//! byte parsing. `mantissa`/`exponent` composites decode as decimals and
+//! null values as [`Value::Null`].
+
+mod schema;
+
+pub use schema::{Block, Field, Group, Member, MessageDef, Primitive, SbeSchema, SbeType};
+
+use crate::client::{ClientError, Result};
+
+pub(crate) fn invalid(reason: impl Into<String>) -> ClientError {
+    ClientError::Parse(format!("invalid sbe: {}", reason.into()))
+}
+
+/// Decoded field value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Decimal(f64),
+    Str(String),
+    Enum(String),
+    Set(Vec<String>),
+    Bytes(Vec<u8>),
+    Composite(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Numeric value of integer, float and decimal fields
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Int(v) => Some(v as f64),
+            Value::UInt(v) => Some(v as f64),
+            Value::Float(v) | Value::Decimal(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Int(v) => Some(v),
+            Value::UInt(v) => i64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    /// Text of string and enum fields
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(v) | Value::Enum(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Fields and groups of a message body or of one group entry
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SbeEntry {
+    pub fields: Vec<(String, Value)>,
+    pub groups: Vec<(String, Vec<SbeEntry>)>,
+}
+
+impl SbeEntry {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Entries of repeating group `name` (empty when absent)
+    pub fn group(&self, name: &str) -> &[SbeEntry] {
+        self.groups
+            .iter()
+            .find(|(group, _)| group == name)
+            .map_or(&[], |(_, entries)| entries.as_slice())
+    }
+}
+
+/// A decoded message
+#[derive(Debug, Clone, PartialEq)]
+pub struct SbeMessage {
+    pub template_id: u16,
+    pub name: String,
+    pub body: SbeEntry,
+}
+
+/// Raw integer or float read from the buffer
+enum Raw {
+    Int(i128),
+    Float(f64),
+}
+
+impl SbeSchema {
+    fn raw(&self, buf: &[u8], at: usize, primitive: Primitive) -> Result<Raw> {
+        let size = primitive.size();
+        let bytes = buf
+            .get(at..at + size)
+            .ok_or_else(|| invalid("truncated message"))?;
+        let mut word = [0u8; 8];
+        if self.big_endian {
+            word[8 - size..].copy_from_slice(bytes);
+            word.reverse();
+        } else {
+            word[..size].copy_from_slice(bytes);
+        }
+        let bits = u64::from_le_bytes(word);
+        Ok(match primitive {
+            Primitive::Float => Raw::Float(f32::from_bits(bits as u32) as f64),
+            Primitive::Double => Raw::Float(f64::from_bits(bits)),
+            signed if signed.is_signed() => {
+                let shift = 64 - size * 8;
+                Raw::Int((((bits << shift) as i64) >> shift) as i128)
+            }
+            _ => Raw::Int(bits as i128),
+        })
+    }
+
+    fn value(&self, buf: &[u8], at: usize, ty: &SbeType) -> Result<Value> {
+        Ok(match ty {
+            SbeType::Primitive {
+                constant: Some(constant),
+                ..
+            } => match constant.parse() {
+                Ok(v) => Value::Int(v),
+                Err(_) => Value::Str(constant.clone()),
+            },
+            SbeType::Primitive {
+                primitive: Primitive::Char,
+                length,
+                ..
+            } if *length != 1 => {
+                let bytes = buf
+                    .get(at..at + length)
+                    .ok_or_else(|| invalid("truncated message"))?;
+                let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                Value::Str(
+                    String::from_utf8_lossy(&bytes[..end])
+                        .trim_end()
+                        .to_string(),
+                )
+            }
+            SbeType::Primitive {
+                primitive, null, ..
+            } => match self.raw(buf, at, *primitive)? {
+                Raw::Float(v) if v.is_nan() => Value::Null,
+                Raw::Float(v) => Value::Float(v),
+                Raw::Int(v) if v == *null => Value::Null,
+                Raw::Int(v) if *primitive == Primitive::Char => {
+                    Value::Str(char::from(v as u8).to_string())
+                }
+                Raw::Int(v) if primitive.is_signed() => Value::Int(v as i64),
+                Raw::Int(v) => Value::UInt(v as u64),
+            },
+            SbeType::Composite(members) => {
+                let mut values = Vec::with_capacity(members.len());
+                for member in members {
+                    values.push((
+                        member.name.clone(),
+                        self.value(buf, at + member.offset, &member.ty)?,
+                    ));
+                }
+                decimal(&values).unwrap_or(Value::Composite(values))
+            }
+            SbeType::Enum { encoding, values } => match self.raw(buf, at, *encoding)? {
+                Raw::Int(raw) => values
+                    .iter()
+                    .find(|(value, _)| *value == raw)
+                    .map(|(_, name)| Value::Enum(name.clone()))
+                    .unwrap_or(Value::Null),
+                Raw::Float(_) => return Err(invalid("float enum encoding")),
+            },
+            SbeType::Set { encoding, choices } => match self.raw(buf, at, *encoding)? {
+                Raw::Int(raw) => Value::Set(
+                    choices
+                        .iter()
+                        .filter(|(bit, _)| *bit < 64 && raw >> bit & 1 == 1)
+                        .map(|(_, name)| name.clone())
+                        .collect(),
+                ),
+                Raw::Float(_) => return Err(invalid("float set encoding")),
+            },
+        })
+    }
+
+    /// Integer member of a composite such as the header or a group dimension
+    fn member_int(&self, buf: &[u8], at: usize, ty: &SbeType, name: &str) -> Result<usize> {
+        let member = ty
+            .member(name)
+            .ok_or_else(|| invalid(format!("composite without {}", name)))?;
+        match self.value(buf, at + member.offset, &member.ty)? {
+            Value::UInt(v) => Ok(v as usize),
+            Value::Int(v) if v >= 0 => Ok(v as usize),
+            _ => Err(invalid(format!("bad {}", name))),
+        }
+    }
+
+    /// Decode a block whose fixed part is `block_length` bytes at `at`,
+    /// returning the entry and the offset just past its groups and data
+    fn entry(
+        &self,
+        buf: &[u8],
+        at: usize,
+        block_length: usize,
+        block: &Block,
+    ) -> Result<(SbeEntry, usize)> {
+        let mut entry = SbeEntry::default();
+        for field in &block.fields {
+            // Fields added in later schema versions lie beyond older blocks
+            let value = if field.offset + field.ty.size() > block_length {
+                Value::Null
+            } else {
+                self.value(buf, at + field.offset, &field.ty)?
+            };
+            entry.fields.push((field.name.clone(), value));
+        }
+
+        // The block length comes from the wire, not the schema
+        let mut pos = at
+            .checked_add(block_length)
+            .filter(|&end| end <= buf.len())
+            .ok_or_else(|| invalid("block length past end of message"))?;
+        for group in &block.groups {
+            let entry_length = self.member_int(buf, pos, &group.dimension, "blockLength")?;
+            let count = self.member_int(buf, pos, &group.dimension, "numInGroup")?;
+            pos += group.dimension.size();
+            let mut entries = Vec::with_capacity(count.min(buf.len()));
+            for _ in 0..count {
+                let (item, end) = self.entry(buf, pos, entry_length, &group.block)?;
+                entries.push(item);
+                pos = end;
+            }
+            entry.groups.push((group.name.clone(), entries));
+        }
+
+        for (name, ty) in &block.data {
+            let length = self.member_int(buf, pos, ty, "length")?;
+            pos += ty.member("length").map_or(0, |m| m.offset + m.ty.size());
+            let bytes = buf
+                .get(pos..pos.saturating_add(length))
+                .ok_or_else(|| invalid("truncated data"))?;
+            entry
+                .fields
+                .push((name.clone(), Value::Bytes(bytes.to_vec())));
+            pos += length;
+        }
+        Ok((entry, pos))
+    }
+
+    /// Template id of the message at the start of `buf`, without decoding
+    /// its body
+    pub fn peek_template(&self, buf: &[u8]) -> Result<u16> {
+        Ok(self.member_int(buf, 0, &self.header, "templateId")? as u16)
+    }
+
+    /// Decode the message at the start of `buf`, returning it with the
+    /// number of bytes it occupies
+    pub fn decode(&self, buf: &[u8]) -> Result<(SbeMessage, usize)> {
+        let block_length = self.member_int(buf, 0, &self.header, "blockLength")?;
+        let template_id = self.peek_template(buf)?;
+        let message = self
+            .message(template_id)
+            .ok_or_else(|| invalid(format!("unknown template {}", template_id)))?;
+        let (body, end) = self.entry(buf, self.header.size(), block_length, &message.block)?;
+        Ok((
+            SbeMessage {
+                template_id,
+                name: message.name.clone(),
+                body,
+            },
+            end,
+        ))
+    }
+}
+
+/// `mantissa * 10^exponent` for decimal composites
+fn decimal(members: &[(String, Value)]) -> Option<Value> {
+    let [(m, mantissa), (e, exponent)] = members else {
+        return None;
+    };
+    if !m.eq_ignore_ascii_case("mantissa") || !e.eq_ignore_ascii_case("exponent") {
+        return None;
+    }
+    Some(match (mantissa.as_i64(), exponent.as_i64()) {
+        (Some(mantissa), Some(exponent)) => {
+            Value::Decimal(mantissa as f64 * 10f64.powi(exponent as i32))
+        }
+        _ => Value::Null,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A trade summary message in the style of CME MDP 3.0
+    pub(crate) const SCHEMA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<sbe:messageSchema xmlns:sbe="http://fixprotocol.io/2016/sbe" package="test" id="1" version="9" byteOrder="littleEndian">
+  <types>
+    <composite name="messageHeader">
+      <type name="blockLength" primitiveType="uint16"/>
+      <type name="templateId" primitiveType="uint16"/>
+      <type name="schemaId" primitiveType="uint16"/>
+      <type name="version" primitiveType="uint16"/>
+    </composite>
+    <composite name="groupSize">
+      <type name="blockLength" primitiveType="uint16"/>
+      <type name="numInGroup" primitiveType="uint8"/>
+    </composite>
+    <composite name="PRICE9">
+      <type name="mantissa" primitiveType="int64"/>
+      <type name="exponent" primitiveType="int8" presence="constant">-9</type>
+    </composite>
+    <type name="uInt8" primitiveType="uint8"/>
+    <type name="Symbol" primitiveType="char" length="8"/>
+    <enum name="AggressorSide" encodingType="uInt8">
+      <validValue name="NoAggressor">0</validValue>
+      <validValue name="Buy">1</validValue>
+      <validValue name="Sell">2</validValue>
+    </enum>
+  </types>
+  <sbe:message name="TradeSummary" id="48" blockLength="16">
+    <field name="TransactTime" id="60" type="uint64"/>
+    <field name="Symbol" id="55" type="Symbol"/>
+    <group name="NoMDEntries" id="268" dimensionType="groupSize" blockLength="20">
+      <field name="MDEntryPx" id="270" type="PRICE9"/>
+      <field name="MDEntrySize" id="271" type="int32"/>
+      <field name="RptSeq" id="83" type="uint32"/>
+      <field name="AggressorSide" id="5797" type="AggressorSide"/>
+    </group>
+  </sbe:message>
+</sbe:messageSchema>"#;
+
+    /// Encoded `TradeSummary` with one entry per `(price, size, aggressor)`
+    pub(crate) fn trade_summary(ts: u64, entries: &[(f64, i32, u8)]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for field in [16u16, 48, 1, 9] {
+            buf.extend(field.to_le_bytes());
+        }
+        buf.extend(ts.to_le_bytes());
+        buf.extend(b"ESH4\0\0\0\0");
+        buf.extend(20u16.to_le_bytes());
+        buf.push(entries.len() as u8);
+        for (seq, &(price, size, side)) in entries.iter().enumerate() {
+            buf.extend(((price * 1e9).round() as i64).to_le_bytes());
+            buf.extend(size.to_le_bytes());
+            buf.extend((seq as u32).to_le_bytes());
+            buf.push(side);
+            buf.extend([0u8; 3]);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_decodes_fields_groups_and_decimals() {
+        let schema = SbeSchema::parse(SCHEMA).unwrap();
+        let buf = trade_summary(
+            1_709_301_600_000_000_000,
+            &[(5125.25, 3, 1), (5125.0, 1, 2)],
+        );
+
+        let (msg, len) = schema.decode(&buf).unwrap();
+        assert_eq!(len, buf.len());
+        assert_eq!(msg.name, "TradeSummary");
+        assert_eq!(
+            msg.body.get("Symbol"),
+            Some(&Value::Str("ESH4".to_string()))
+        );
+        let entries = msg.body.group("NoMDEntries");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].get("MDEntryPx"), Some(&Value::Decimal(5125.25)));
+        assert_eq!(
+            entries[1].get("AggressorSide").unwrap().as_str(),
+            Some("Sell")
+        );
+
+        // A block length beyond the buffer is an error, not a panic
+        let mut buf = trade_summary(0, &[]);
+        buf[..2].copy_from_slice(&60_000u16.to_le_bytes());
+        assert!(schema.decode(&buf).is_err());
+    }
+}
@@ -0,0 +1,575 @@
+//! Loading SBE message schemas from their XML definition.
+
+use super::invalid;
+use crate::client::{ClientError, Result};
+use roxmltree::{Document, Node};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// SBE primitive encodings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Primitive {
+    Char,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float,
+    Double,
+}
+
+impl Primitive {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" => Primitive::Char,
+            "int8" => Primitive::Int8,
+            "int16" => Primitive::Int16,
+            "int32" => Primitive::Int32,
+            "int64" => Primitive::Int64,
+            "uint8" => Primitive::UInt8,
+            "uint16" => Primitive::UInt16,
+            "uint32" => Primitive::UInt32,
+            "uint64" => Primitive::UInt64,
+            "float" => Primitive::Float,
+            "double" => Primitive::Double,
+            _ => return None,
+        })
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            Primitive::Char | Primitive::Int8 | Primitive::UInt8 => 1,
+            Primitive::Int16 | Primitive::UInt16 => 2,
+            Primitive::Int32 | Primitive::UInt32 | Primitive::Float => 4,
+            Primitive::Int64 | Primitive::UInt64 | Primitive::Double => 8,
+        }
+    }
+
+    pub(crate) fn is_signed(self) -> bool {
+        matches!(
+            self,
+            Primitive::Int8 | Primitive::Int16 | Primitive::Int32 | Primitive::Int64
+        )
+    }
+
+    /// Null value used when a schema does not declare one
+    fn default_null(self) -> i128 {
+        match self {
+            Primitive::Char => 0,
+            Primitive::Float | Primitive::Double => i128::MIN,
+            signed if signed.is_signed() => -(1i128 << (signed.size() * 8 - 1)),
+            unsigned => (1i128 << (unsigned.size() * 8)) - 1,
+        }
+    }
+}
+
+/// Resolved encoding of a field or composite member
+#[derive(Debug, Clone)]
+pub enum SbeType {
+    Primitive {
+        primitive: Primitive,
+        length: usize,
+        /// Integer value meaning "absent"
+        null: i128,
+        /// Value of `presence="constant"` types, which take no space
+        constant: Option<String>,
+    },
+    Composite(Vec<Member>),
+    Enum {
+        encoding: Primitive,
+        /// `(encoded value, name)`; char enums are encoded as their byte
+        values: Vec<(i128, String)>,
+    },
+    Set {
+        encoding: Primitive,
+        /// `(bit, name)`
+        choices: Vec<(u32, String)>,
+    },
+}
+
+impl SbeType {
+    /// Encoded size in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            SbeType::Primitive {
+                constant: Some(_), ..
+            } => 0,
+            SbeType::Primitive {
+                primitive, length, ..
+            } => primitive.size() * length,
+            SbeType::Composite(members) => members
+                .iter()
+                .map(|member| member.offset + member.ty.size())
+                .max()
+                .unwrap_or(0),
+            SbeType::Enum { encoding, .. } | SbeType::Set { encoding, .. } => encoding.size(),
+        }
+    }
+
+    pub(crate) fn member(&self, name: &str) -> Option<&Member> {
+        match self {
+            SbeType::Composite(members) => members.iter().find(|m| m.name == name),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub offset: usize,
+    pub ty: SbeType,
+}
+
+/// Field of a message or repeating group
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub id: u16,
+    pub offset: usize,
+    pub ty: SbeType,
+}
+
+/// Fields, repeating groups and variable length data of a message or group
+#[derive(Debug, Clone, Default)]
+pub struct Block {
+    pub fields: Vec<Field>,
+    pub groups: Vec<Group>,
+    /// `(name, length-prefixed composite)`
+    pub data: Vec<(String, SbeType)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub id: u16,
+    /// Composite carrying `blockLength` and `numInGroup`
+    pub dimension: SbeType,
+    pub block: Block,
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageDef {
+    pub name: String,
+    pub id: u16,
+    pub block: Block,
+}
+
+/// A parsed SBE message schema
+#[derive(Debug, Clone)]
+pub struct SbeSchema {
+    pub id: u16,
+    pub version: u16,
+    pub(crate) big_endian: bool,
+    pub(crate) header: SbeType,
+    pub(crate) messages: HashMap<u16, MessageDef>,
+}
+
+fn attr<T: std::str::FromStr>(node: Node, name: &str) -> Result<Option<T>> {
+    node.attribute(name)
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| invalid(format!("bad {} attribute \"{}\"", name, value)))
+        })
+        .transpose()
+}
+
+fn name_of(node: Node) -> Result<String> {
+    node.attribute("name")
+        .map(str::to_string)
+        .ok_or_else(|| invalid(format!("<{}> without name", node.tag_name().name())))
+}
+
+/// Named types of the schema, resolved on first use
+struct Types<'a, 'input> {
+    nodes: HashMap<&'a str, Node<'a, 'input>>,
+    resolved: HashMap<String, SbeType>,
+    /// Types being resolved, to refuse ones that contain themselves
+    pending: HashSet<String>,
+}
+
+impl<'a, 'input> Types<'a, 'input> {
+    fn resolve(&mut self, name: &str) -> Result<SbeType> {
+        if let Some(primitive) = Primitive::parse(name) {
+            return Ok(SbeType::Primitive {
+                primitive,
+                length: 1,
+                null: primitive.default_null(),
+                constant: None,
+            });
+        }
+        if let Some(ty) = self.resolved.get(name) {
+            return Ok(ty.clone());
+        }
+        let node = *self
+            .nodes
+            .get(name)
+            .ok_or_else(|| invalid(format!("unknown type {}", name)))?;
+        if !self.pending.insert(name.to_string()) {
+            return Err(invalid(format!("type {} contains itself", name)));
+        }
+        let ty = self.build(node);
+        self.pending.remove(name);
+        let ty = ty?;
+        self.resolved.insert(name.to_string(), ty.clone());
+        Ok(ty)
+    }
+
+    /// Primitive behind an `encodingType`, which may name another type
+    fn encoding(&mut self, name: &str) -> Result<Primitive> {
+        match self.resolve(name)? {
+            SbeType::Primitive { primitive, .. } => Ok(primitive),
+            _ => Err(invalid(format!("{} is not a primitive encoding", name))),
+        }
+    }
+
+    fn build(&mut self, node: Node) -> Result<SbeType> {
+        match node.tag_name().name() {
+            "type" => {
+                let name = name_of(node)?;
+                let primitive = node
+                    .attribute("primitiveType")
+                    .and_then(Primitive::parse)
+                    .ok_or_else(|| invalid(format!("type {} without primitive", name)))?;
+                let constant = (node.attribute("presence") == Some("constant"))
+                    .then(|| node.text().unwrap_or_default().trim().to_string());
+                let null = match node.attribute("nullValue") {
+                    Some(value) if primitive == Primitive::Char => {
+                        value.bytes().next().unwrap_or(0) as i128
+                    }
+                    Some(_) => attr(node, "nullValue")?.unwrap(),
+                    None => primitive.default_null(),
+                };
+                Ok(SbeType::Primitive {
+                    primitive,
+                    length: attr(node, "length")?.unwrap_or(1),
+                    null,
+                    constant,
+                })
+            }
+            "composite" => {
+                let mut members = Vec::new();
+                let mut offset = 0;
+                for child in node.children().filter(Node::is_element) {
+                    let ty = match child.tag_name().name() {
+                        "ref" => {
+                            let name = child
+                                .attribute("type")
+                                .ok_or_else(|| invalid("<ref> without type"))?;
+                            self.resolve(name)?
+                        }
+                        _ => self.build(child)?,
+                    };
+                    offset = attr(child, "offset")?.unwrap_or(offset);
+                    let size = ty.size();
+                    members.push(Member {
+                        name: name_of(child)?,
+                        offset,
+                        ty,
+                    });
+                    offset += size;
+                }
+                Ok(SbeType::Composite(members))
+            }
+            "enum" => {
+                let encoding = self.encoding(node.attribute("encodingType").unwrap_or("uint8"))?;
+                let mut values = Vec::new();
+                for value in node.children().filter(|c| c.has_tag_name("validValue")) {
+                    let text = value.text().unwrap_or_default().trim();
+                    let raw = match encoding {
+                        Primitive::Char => text.bytes().next().unwrap_or(0) as i128,
+                        _ => text
+                            .parse()
+                            .map_err(|_| invalid(format!("bad enum value \"{}\"", text)))?,
+                    };
+                    values.push((raw, name_of(value)?));
+                }
+                Ok(SbeType::Enum { encoding, values })
+            }
+            "set" => {
+                let encoding = self.encoding(node.attribute("encodingType").unwrap_or("uint8"))?;
+                let mut choices = Vec::new();
+                for choice in node.children().filter(|c| c.has_tag_name("choice")) {
+                    let text = choice.text().unwrap_or_default().trim();
+                    let bit = text
+                        .parse()
+                        .map_err(|_| invalid(format!("bad choice bit \"{}\"", text)))?;
+                    choices.push((bit, name_of(choice)?));
+                }
+                Ok(SbeType::Set { encoding, choices })
+            }
+            other => Err(invalid(format!("unsupported type element <{}>", other))),
+        }
+    }
+
+    fn block(&mut self, node: Node) -> Result<Block> {
+        let mut block = Block::default();
+        let mut offset = 0;
+        for child in node.children().filter(Node::is_element) {
+            let name = name_of(child)?;
+            let id = attr(child, "id")?.unwrap_or(0);
+            match child.tag_name().name() {
+                "field" => {
+                    let ty = if child.attribute("presence") == Some("constant") {
+                        // Field level constants carry their value in `valueRef`
+                        SbeType::Primitive {
+                            primitive: Primitive::Char,
+                            length: 0,
+                            null: 0,
+                            constant: Some(
+                                child
+                                    .attribute("valueRef")
+                                    .map(str::to_string)
+                                    .unwrap_or_else(|| {
+                                        child.text().unwrap_or_default().trim().to_string()
+                                    }),
+                            ),
+                        }
+                    } else {
+                        let ty = child
+                            .attribute("type")
+                            .ok_or_else(|| invalid(format!("field {} without type", name)))?;
+                        self.resolve(ty)?
+                    };
+                    offset = attr(child, "offset")?.unwrap_or(offset);
+                    let size = ty.size();
+                    block.fields.push(Field {
+                        name,
+                        id,
+                        offset,
+                        ty,
+                    });
+                    offset += size;
+                }
+                "group" => {
+                    let dimension = self.resolve(
+                        child
+                            .attribute("dimensionType")
+                            .unwrap_or("groupSizeEncoding"),
+                    )?;
+                    block.groups.push(Group {
+                        name,
+                        id,
+                        dimension,
+                        block: self.block(child)?,
+                    });
+                }
+                "data" => {
+                    let ty = child
+                        .attribute("type")
+                        .ok_or_else(|| invalid(format!("data {} without type", name)))?;
+                    block.data.push((name, self.resolve(ty)?));
+                }
+                _ => {}
+            }
+        }
+        Ok(block)
+    }
+}
+
+impl SbeSchema {
+    /// Parse a schema from its XML text
+    pub fn parse(xml: &str) -> Result<Self> {
+        let doc = Document::parse(xml).map_err(|e| invalid(e.to_string()))?;
+        let root = doc.root_element();
+        if root.tag_name().name() != "messageSchema" {
+            return Err(invalid("root element is not <messageSchema>"));
+        }
+
+        let mut types = Types {
+            nodes: HashMap::new(),
+            resolved: HashMap::new(),
+            pending: HashSet::new(),
+        };
+        for node in root
+            .children()
+            .filter(|c| c.has_tag_name("types"))
+            .flat_map(|types| types.children().filter(Node::is_element))
+        {
+            types.nodes.insert(
+                node.attribute("name")
+                    .ok_or_else(|| invalid("type without name"))?,
+                node,
+            );
+        }
+
+        let header = types.resolve(root.attribute("headerType").unwrap_or("messageHeader"))?;
+        for member in ["blockLength", "templateId"] {
+            if header.member(member).is_none() {
+                return Err(invalid(format!("message header without {}", member)));
+            }
+        }
+
+        let mut messages = HashMap::new();
+        for node in root.children().filter(|c| c.has_tag_name("message")) {
+            let id = attr(node, "id")?.ok_or_else(|| invalid("message without id"))?;
+            let message = MessageDef {
+                name: name_of(node)?,
+                id,
+                block: types.block(node)?,
+            };
+            messages.insert(id, message);
+        }
+
+        Ok(Self {
+            id: attr(root, "id")?.unwrap_or(0),
+            version: attr(root, "version")?.unwrap_or(0),
+            big_endian: root.attribute("byteOrder") == Some("bigEndian"),
+            header,
+            messages,
+        })
+    }
+
+    /// Load a schema XML file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let xml = std::fs::read_to_string(path).map_err(|e| ClientError::Io(e.to_string()))?;
+        Self::parse(&xml)
+    }
+
+    pub fn message(&self, template_id: u16) -> Option<&MessageDef> {
+        self.messages.get(&template_id)
+    }
+
+    /// Template id of the message called `name`
+    pub fn template_id(&self, name: &str) -> Option<u16> {
+        self.messages
+            .values()
+            .find(|message| message.name == name)
+            .map(|message| message.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::SCHEMA;
+    use super::*;
+
+    /// `SCHEMA` with its `<types>` and `<message>` bodies replaced
+    fn schema(types: &str, message: &str) -> String {
+        format!(
+            r#"<messageSchema id="1" version="1">
+  <types>
+    <composite name="messageHeader">
+      <type name="blockLength" primitiveType="uint16"/>
+      <type name="templateId" primitiveType="uint16"/>
+    </composite>
+    {}
+  </types>
+  <message name="Test" id="7">{}</message>
+</messageSchema>"#,
+            types, message
+        )
+    }
+
+    #[test]
+    fn test_resolves_offsets_sizes_and_nulls() {
+        let schema = SbeSchema::parse(SCHEMA).unwrap();
+        assert_eq!((schema.id, schema.version), (1, 9));
+        assert!(!schema.big_endian);
+        assert_eq!(schema.header.size(), 8);
+        assert_eq!(schema.template_id("TradeSummary"), Some(48));
+        assert!(schema.template_id("Missing").is_none());
+
+        let message = schema.message(48).unwrap();
+        let offsets: Vec<(&str, usize)> = message
+            .block
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.offset))
+            .collect();
+        assert_eq!(offsets, [("TransactTime", 0), ("Symbol", 8)]);
+        let group = &message.block.groups[0];
+        assert_eq!(group.dimension.size(), 3);
+        // The constant exponent takes no space in the price composite
+        let fields = &group.block.fields;
+        assert_eq!(fields[0].ty.size(), 8);
+        assert_eq!((fields[1].offset, fields[3].offset), (8, 16));
+        assert!(matches!(
+            fields[2].ty,
+            SbeType::Primitive { primitive: Primitive::UInt32, null, .. } if null == u32::MAX as i128
+        ));
+        assert!(matches!(
+            &fields[3].ty,
+            SbeType::Enum { encoding: Primitive::UInt8, values } if values[2] == (2, "Sell".to_string())
+        ));
+    }
+
+    #[test]
+    fn test_explicit_offsets_sets_and_char_nulls() {
+        let xml = schema(
+            r#"<set name="Flags" encodingType="uint8">
+      <choice name="Last">0</choice>
+      <choice name="Recovery">7</choice>
+    </set>
+    <type name="Side" primitiveType="char" nullValue="0"/>"#,
+            r#"<field name="Flags" id="1" type="Flags"/>
+    <field name="Side" id="2" type="Side" offset="4"/>
+    <field name="Venue" id="3" presence="constant" valueRef="Venue.XCME"/>
+    <data name="Text" id="4" type="messageHeader"/>"#,
+        );
+        let schema = SbeSchema::parse(&xml).unwrap();
+        let block = &schema.message(7).unwrap().block;
+        assert!(matches!(
+            &block.fields[0].ty,
+            SbeType::Set { choices, .. } if choices[1] == (7, "Recovery".to_string())
+        ));
+        assert_eq!(block.fields[1].offset, 4);
+        assert!(
+            matches!(block.fields[1].ty, SbeType::Primitive { null, .. } if null == b'0' as i128)
+        );
+        assert_eq!(block.fields[2].ty.size(), 0);
+        assert_eq!(block.data[0].0, "Text");
+    }
+
+    #[test]
+    fn test_rejects_malformed_schemas() {
+        let field = |ty: &str| format!(r#"<field name="F" id="1" type="{}"/>"#, ty);
+        let cases = [
+            "<messageSchema".to_string(),
+            "<schema/>".to_string(),
+            // Header without a template id
+            r#"<messageSchema><types><composite name="messageHeader">
+              <type name="blockLength" primitiveType="uint16"/>
+            </composite></types></messageSchema>"#
+                .to_string(),
+            schema("", &field("Unknown")),
+            schema("", r#"<field name="F" id="1"/>"#),
+            schema("", r#"<field name="F" id="x" type="uint8"/>"#),
+            schema(r#"<type name="T"/>"#, &field("T")),
+            schema(r#"<type name="T" primitiveType="int128"/>"#, &field("T")),
+            schema(
+                r#"<type name="T" primitiveType="uint8" length="-1"/>"#,
+                &field("T"),
+            ),
+            schema(
+                r#"<enum name="E" encodingType="Header"><validValue name="A">x</validValue></enum>
+                <composite name="Header"><type name="a" primitiveType="uint8"/></composite>"#,
+                &field("E"),
+            ),
+            schema(
+                r#"<set name="S"><choice name="A">bit</choice></set>"#,
+                &field("S"),
+            ),
+            schema(r#"<blob name="B"/>"#, &field("B")),
+            // Composites that contain themselves, directly or not
+            schema(
+                r#"<composite name="A"><ref name="b" type="B"/></composite>
+                <composite name="B"><ref name="a" type="A"/></composite>"#,
+                &field("A"),
+            ),
+        ];
+        for xml in &cases {
+            assert!(SbeSchema::parse(xml).is_err(), "accepted {}", xml);
+        }
+
+        let xml = schema("", "").replace(r#" id="7""#, "");
+        assert!(SbeSchema::parse(&xml).is_err());
+        assert!(SbeSchema::load("/nonexistent/schema.xml").is_err());
+    }
+}
@@ -0,0 +1,978 @@
+//! Multi-tenant fan-out server.
+//!
+//! Re-publishes a normalized stream over TCP as JSON lines. Each [`Tenant`]
+//! has an API key, an optional symbol allowlist and limits on its
+//! connections; within those, every connection picks its own symbols,
+//! [`Filter`], message rate and quote conflation. Optional [`Entitlements`]
+//! further restrict tenants by name. Traffic is accounted per tenant in
+//! [`TenantUsage`].
+//!
+//! Given a [`SyncHandle`], each connection's first `subscribe` is answered
+//! with the cached book, quote and last trade of its symbols before the live
+//! stream, with no message missed or repeated in between.
+//!
+//! The protocol is line based. Commands are answered with `ok` or
+//! `error: <reason>`; the first must authenticate:
+//!
+//! ```text
+//! auth <api-key>
+//! subscribe BTCUSD,ETHUSD   (or * for every entitled symbol; names the process has not seen are rejected)
+//! filter price > 50000      (empty to clear)
+//! rate 100                  (messages per second)
+//! conflate 250              (milliseconds, 0 to disable)
+//! deltas 100                (book deltas with a keyframe every 100 updates, 0 to disable)
+//! ack BTCUSD 42             (book state 42 received; later deltas build on it)
+//! replay 1200 1250          (re-send retained messages 1200 to 1250, with gap-fill)
+//! ```
+//!
+//! With deltas enabled, books are sent as [`BookDelta`](crate::delta::BookDelta)
+//! lines holding only the levels changed since the last acknowledged state.
+//!
+//! With [gap-fill](FanOutServer::with_gap_fill) every message is numbered
+//! and sent as a [`Sequenced`] envelope, and the latest are retained in a
+//! [`HistoryBuffer`] so a consumer that missed some, on the same or a new
+//! connection, can ask for them again with `replay`. Replayed messages pass the connection's
+//! subscription, entitlements and filter but not its rate limit or
+//! conflation, and books are replayed in full. Snapshot messages are not
+//! numbered; the live stream picks up with the first message after them.
+//! Messages the relay itself lags behind on keep their numbers, so the gap
+//! shows, but cannot be replayed.
+//!
+//! Output is JSON lines unless the server is given a binary [`WireFormat`];
+//! then messages and command replies (as strings) are sent as
+//! `[len: u32 BE][payload]` frames. Commands are always text lines.
+//! Timestamps keep their full nanosecond precision unless the server is
+//! given a coarser [`TimestampPrecision`].
+
+use crate::burst::RateLimiter;
+use crate::client::{ClientError, Result};
+use crate::clock::TimestampPrecision;
+use crate::codec::WireFormat;
+use crate::delta::BookDeltaEncoder;
+use crate::entitlements::Entitlements;
+use crate::filter::Filter;
+use crate::history::HistoryBuffer;
+use crate::lvc::{LastValueCache, SyncHandle, SyncSnapshot};
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio_util::codec::{FramedRead, LinesCodec, LinesCodecError};
+use tracing::{debug, info, warn};
+
+/// A team or application allowed to connect
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tenant {
+    pub name: String,
+    pub api_key: String,
+    /// Symbols the tenant may subscribe to, every symbol when empty
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    /// Highest message rate of any one connection
+    #[serde(default)]
+    pub max_rate: Option<f64>,
+    #[serde(default)]
+    pub max_connections: Option<usize>,
+}
+
+impl Tenant {
+    pub fn new(name: &str, api_key: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            api_key: api_key.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_symbols(mut self, symbols: &[&str]) -> Self {
+        self.symbols = symbols.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_max_rate(mut self, per_second: f64) -> Self {
+        self.max_rate = Some(per_second);
+        self
+    }
+
+    pub fn with_max_connections(mut self, connections: usize) -> Self {
+        self.max_connections = Some(connections);
+        self
+    }
+
+    pub fn entitled(&self, symbol: &str) -> bool {
+        self.symbols.is_empty() || self.symbols.iter().any(|s| s == symbol)
+    }
+}
+
+/// Traffic served to one tenant
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TenantUsage {
+    /// Currently open connections
+    pub connections: usize,
+    pub messages: u64,
+    pub bytes: u64,
+    /// Messages lost to rate limits or slow readers
+    pub dropped: u64,
+}
+
+type UsageMap = Arc<Mutex<HashMap<String, TenantUsage>>>;
+
+/// Numbered messages queued for connections before they lag
+const RELAY_CAPACITY: usize = 4096;
+
+/// Longest command line accepted; longer lines close the connection
+const MAX_COMMAND_LEN: usize = 8192;
+
+/// Time a new connection has to authenticate
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A message with its sequence number, as sent with gap-fill enabled
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sequenced<T> {
+    /// Absent for snapshot messages sent ahead of the live stream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    pub message: T,
+}
+
+/// Numbered messages retained for replay, and with sync the state they
+/// add up to
+struct Retention {
+    history: HistoryBuffer,
+    cache: Option<LastValueCache>,
+}
+
+type SharedRetention = Arc<Mutex<Retention>>;
+type RelaySender = broadcast::Sender<(u64, MarketDataMessage)>;
+
+/// Messages as a connection receives them: as published, or numbered by
+/// the gap-fill relay
+enum Feed {
+    Plain(broadcast::Receiver<MarketDataMessage>),
+    Sequenced(broadcast::Receiver<(u64, MarketDataMessage)>),
+}
+
+impl Feed {
+    async fn recv(
+        &mut self,
+    ) -> std::result::Result<(Option<u64>, MarketDataMessage), broadcast::error::RecvError> {
+        match self {
+            Feed::Plain(rx) => rx.recv().await.map(|msg| (None, msg)),
+            Feed::Sequenced(rx) => rx.recv().await.map(|(seq, msg)| (Some(seq), msg)),
+        }
+    }
+}
+
+/// Where a synced connection takes its snapshot and feed from on its first
+/// subscribe
+enum Primer {
+    Sync(SyncHandle),
+    /// The gap-fill relay, numbering what it takes from sync
+    Relay(SharedRetention, RelaySender),
+}
+
+impl Primer {
+    /// Current state and a feed of everything after it
+    fn subscribe(&self) -> (SyncSnapshot, Feed) {
+        match self {
+            Primer::Sync(sync) => {
+                let (snapshot, rx) = sync.subscribe();
+                (snapshot, Feed::Plain(rx))
+            }
+            Primer::Relay(retention, tx) => {
+                let retention = retention.lock().unwrap();
+                let snapshot = retention
+                    .cache
+                    .as_ref()
+                    .map(LastValueCache::snapshot)
+                    .unwrap_or_default();
+                (snapshot, Feed::Sequenced(tx.subscribe()))
+            }
+        }
+    }
+}
+
+/// Serves one source stream to authenticated tenants
+pub struct FanOutServer {
+    source: broadcast::Receiver<MarketDataMessage>,
+    /// Keyed by API key
+    tenants: HashMap<String, Arc<Tenant>>,
+    entitlements: Option<Arc<Entitlements>>,
+    sync: Option<SyncHandle>,
+    format: WireFormat,
+    precision: TimestampPrecision,
+    usage: UsageMap,
+    /// Messages retained for gap-fill, when enabled
+    gap_fill: Option<HistoryBuffer>,
+}
+
+impl FanOutServer {
+    /// Serve the messages of `source`, e.g. from
+    /// [`MarketDataClient::subscribe`](crate::MarketDataClient::subscribe)
+    pub fn new(source: broadcast::Receiver<MarketDataMessage>) -> Self {
+        Self {
+            source,
+            tenants: HashMap::new(),
+            entitlements: None,
+            sync: None,
+            format: WireFormat::Json,
+            precision: TimestampPrecision::Nanos,
+            usage: Arc::default(),
+            gap_fill: None,
+        }
+    }
+
+    pub fn with_tenant(mut self, tenant: Tenant) -> Self {
+        self.tenants
+            .insert(tenant.api_key.clone(), Arc::new(tenant));
+        self
+    }
+
+    /// Also require tenants to be entitled by name to what they receive
+    pub fn with_entitlements(mut self, entitlements: Arc<Entitlements>) -> Self {
+        self.entitlements = Some(entitlements);
+        self
+    }
+
+    /// Serve from `sync` instead of the source, priming late joiners with
+    /// its cached state
+    pub fn with_sync(mut self, sync: SyncHandle) -> Self {
+        self.sync = Some(sync);
+        self
+    }
+
+    /// Send messages as MessagePack or CBOR frames instead of JSON lines
+    pub fn with_format(mut self, format: WireFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Truncate sent timestamps to `precision`, for consumers that parse
+    /// no finer than milliseconds or microseconds
+    pub fn with_timestamp_precision(mut self, precision: TimestampPrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Number every message and keep those `history` retains for `replay`
+    /// requests
+    pub fn with_gap_fill(mut self, history: HistoryBuffer) -> Self {
+        self.gap_fill = Some(history);
+        self
+    }
+
+    /// Add the tenants of a JSON array file
+    pub fn with_tenants_file(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ClientError::Io(format!("{}: {}", path.display(), e)))?;
+        let tenants: Vec<Tenant> =
+            serde_json::from_str(&text).map_err(|e| ClientError::Parse(e.to_string()))?;
+        for tenant in tenants {
+            self = self.with_tenant(tenant);
+        }
+        Ok(self)
+    }
+
+    /// Usage so far, keyed by tenant name
+    pub fn usage(&self) -> HashMap<String, TenantUsage> {
+        self.usage.lock().unwrap().clone()
+    }
+
+    /// Accept connections until the listener fails
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        if let Ok(addr) = listener.local_addr() {
+            info!("Fan-out server listening on {}", addr);
+        }
+        let tenants = Arc::new(self.tenants.clone());
+        let relay = self.gap_fill.clone().map(|history| {
+            let (cache, source) = match &self.sync {
+                Some(sync) => {
+                    let (snapshot, rx) = sync.subscribe();
+                    (Some(LastValueCache::from(snapshot)), rx)
+                }
+                None => (None, self.source.resubscribe()),
+            };
+            let retention = Arc::new(Mutex::new(Retention { history, cache }));
+            let (tx, _) = broadcast::channel(RELAY_CAPACITY);
+            tokio::spawn(relay(source, retention.clone(), tx.clone()));
+            (retention, tx)
+        });
+        loop {
+            let (stream, peer) = listener
+                .accept()
+                .await
+                .map_err(|e| ClientError::Connection(e.to_string()))?;
+            // Synced connections take their feed with the snapshot on the
+            // first subscribe instead
+            let (rx, primer) = match (&relay, &self.sync) {
+                (Some((retention, tx)), Some(_)) => {
+                    (None, Some(Primer::Relay(retention.clone(), tx.clone())))
+                }
+                (Some((_, tx)), None) => (Some(Feed::Sequenced(tx.subscribe())), None),
+                (None, Some(sync)) => (None, Some(Primer::Sync(sync.clone()))),
+                (None, None) => (Some(Feed::Plain(self.source.resubscribe())), None),
+            };
+            let retention = relay.as_ref().map(|(retention, _)| retention.clone());
+            let connection = Connection {
+                tenants: tenants.clone(),
+                entitlements: self.entitlements.clone(),
+                usage: self.usage.clone(),
+                rx,
+                primer,
+                format: self.format,
+                precision: self.precision,
+                retention,
+            };
+            tokio::spawn(async move {
+                if let Err(e) = connection.run(stream).await {
+                    debug!("Fan-out client {} disconnected: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+/// Number the source's messages into the retention buffer and pass them on
+async fn relay(
+    mut source: broadcast::Receiver<MarketDataMessage>,
+    retention: SharedRetention,
+    tx: RelaySender,
+) {
+    loop {
+        match source.recv().await {
+            Ok(msg) => {
+                // Under one lock with primed subscribes, so each message is
+                // in a connection's snapshot or on its feed
+                let mut retention = retention.lock().unwrap();
+                if let Some(cache) = &mut retention.cache {
+                    cache.update(&msg);
+                }
+                let seq = retention.history.push(msg.clone());
+                let _ = tx.send((seq, msg));
+            }
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Gap-fill relay lagged, {} messages lost", missed);
+                retention.lock().unwrap().history.skip(missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+struct Connection {
+    tenants: Arc<HashMap<String, Arc<Tenant>>>,
+    entitlements: Option<Arc<Entitlements>>,
+    usage: UsageMap,
+    /// Absent with sync until the first subscribe
+    rx: Option<Feed>,
+    /// Taken on the first subscribe for the snapshot and, atomically with
+    /// it, `rx`
+    primer: Option<Primer>,
+    format: WireFormat,
+    precision: TimestampPrecision,
+    retention: Option<SharedRetention>,
+}
+
+impl Connection {
+    async fn run(mut self, stream: TcpStream) -> Result<()> {
+        let io = |e: std::io::Error| ClientError::Io(e.to_string());
+        let (read, mut write) = stream.into_split();
+        let mut lines = FramedRead::new(read, LinesCodec::new_with_max_length(MAX_COMMAND_LEN));
+
+        let line = tokio::time::timeout(AUTH_TIMEOUT, lines.next())
+            .await
+            .map_err(|_| ClientError::Control("authentication timed out".to_string()))
+            .and_then(|line| line.transpose().map_err(line_error));
+        let tenant = match line.and_then(|line| self.authenticate(&line.unwrap_or_default())) {
+            Ok(tenant) => tenant,
+            Err(e) => {
+                let reply = self.reply(&format!("error: {}", e))?;
+                write.write_all(&reply).await.map_err(io)?;
+                return Err(e);
+            }
+        };
+        let _guard = ConnectionGuard {
+            usage: self.usage.clone(),
+            tenant: tenant.name.clone(),
+        };
+        write.write_all(&self.reply("ok")?).await.map_err(io)?;
+
+        let mut session = Session::new(tenant.clone(), self.entitlements.clone());
+        session.sequenced = self.retention.is_some();
+        let mut ticker: Option<tokio::time::Interval> = None;
+        loop {
+            let mut outgoing = Vec::new();
+            tokio::select! {
+                line = lines.next() => {
+                    let line = match line.transpose().map_err(line_error) {
+                        Ok(Some(line)) => line,
+                        Ok(None) => return Ok(()),
+                        Err(e) => {
+                            let reply = self.reply(&format!("error: {}", e))?;
+                            write.write_all(&reply).await.map_err(io)?;
+                            return Err(e);
+                        }
+                    };
+                    if let Some(range) = line.trim().strip_prefix("replay") {
+                        let replayed = match self.replay(&session, range) {
+                            Ok(replayed) => replayed,
+                            Err(e) => {
+                                let reply = self.reply(&format!("error: {}", e))?;
+                                write.write_all(&reply).await.map_err(io)?;
+                                continue;
+                            }
+                        };
+                        write.write_all(&self.reply("ok")?).await.map_err(io)?;
+                        for (seq, mut msg) in replayed {
+                            msg.truncate_timestamps(self.precision);
+                            let frame = session.frame(&msg, Some(seq), self.format)?;
+                            self.account(&tenant.name, |usage| {
+                                usage.messages += 1;
+                                usage.bytes += frame.len() as u64;
+                            });
+                            write.write_all(&frame).await.map_err(io)?;
+                        }
+                        continue;
+                    }
+                    let reply = match session.command(&line) {
+                        Ok(()) => "ok".to_string(),
+                        Err(e) => format!("error: {}", e),
+                    };
+                    write.write_all(&self.reply(&reply)?).await.map_err(io)?;
+                    if reply == "ok" && line.trim_start().starts_with("subscribe") {
+                        if let Some(primer) = self.primer.take() {
+                            let (snapshot, rx) = primer.subscribe();
+                            self.rx = Some(rx);
+                            outgoing.extend(
+                                snapshot
+                                    .messages()
+                                    .into_iter()
+                                    .filter_map(|msg| session.offer(None, msg)),
+                            );
+                        }
+                    }
+                    if ticker.as_ref().map(|ticker| ticker.period()) != session.conflate {
+                        ticker = session.conflate.map(tokio::time::interval);
+                    }
+                }
+                msg = async { self.rx.as_mut().unwrap().recv().await }, if self.rx.is_some() => match msg {
+                    Ok((seq, msg)) => outgoing.extend(session.offer(seq, msg)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        self.account(&tenant.name, |usage| usage.dropped += missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                _ = async { ticker.as_mut().unwrap().tick().await }, if ticker.is_some() => {
+                    outgoing = session.flush();
+                }
+            }
+
+            for (seq, mut msg) in outgoing {
+                if !session.permit(Instant::now()) {
+                    self.account(&tenant.name, |usage| usage.dropped += 1);
+                    continue;
+                }
+                msg.truncate_timestamps(self.precision);
+                let frame = session.encode(&msg, seq, self.format)?;
+                self.account(&tenant.name, |usage| {
+                    usage.messages += 1;
+                    usage.bytes += frame.len() as u64;
+                });
+                write.write_all(&frame).await.map_err(io)?;
+            }
+        }
+    }
+
+    /// A command reply as a text line, or a string frame in binary formats
+    fn reply(&self, text: &str) -> Result<Vec<u8>> {
+        match self.format {
+            WireFormat::Json => Ok(format!("{}\n", text).into_bytes()),
+            format => format.encode_frame(text),
+        }
+    }
+
+    /// Retained messages of a `replay <from> <to>` request that the
+    /// session admits
+    fn replay(&self, session: &Session, range: &str) -> Result<Vec<(u64, MarketDataMessage)>> {
+        let retention = self
+            .retention
+            .as_ref()
+            .ok_or_else(|| ClientError::Control("gap-fill is not enabled".to_string()))?;
+        let (from, to) = range
+            .trim()
+            .split_once(' ')
+            .and_then(|(from, to)| Some((from.parse::<u64>().ok()?, to.trim().parse().ok()?)))
+            .filter(|(from, to)| from <= to)
+            .ok_or_else(|| ClientError::Control("replay takes a sequence range".to_string()))?;
+        let messages = retention.lock().unwrap().history.sequence_range(from, to)?;
+        Ok(messages
+            .into_iter()
+            .filter(|(_, msg)| session.admits(msg))
+            .collect())
+    }
+
+    fn authenticate(&self, line: &str) -> Result<Arc<Tenant>> {
+        let key = match line.split_once(' ') {
+            Some(("auth", key)) => key.trim(),
+            _ => return Err(ClientError::Control("authenticate first".to_string())),
+        };
+        let Some(tenant) = self.tenants.get(key) else {
+            warn!(target: "audit", "Fan-out connection with invalid api key");
+            return Err(ClientError::Control("invalid api key".to_string()));
+        };
+
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(tenant.name.clone()).or_default();
+        if tenant
+            .max_connections
+            .is_some_and(|max| usage.connections >= max)
+        {
+            return Err(ClientError::Control("connection limit reached".to_string()));
+        }
+        usage.connections += 1;
+        Ok(tenant.clone())
+    }
+
+    fn account(&self, tenant: &str, update: impl FnOnce(&mut TenantUsage)) {
+        update(
+            self.usage
+                .lock()
+                .unwrap()
+                .entry(tenant.to_string())
+                .or_default(),
+        );
+    }
+}
+
+fn line_error(e: LinesCodecError) -> ClientError {
+    match e {
+        LinesCodecError::MaxLineLengthExceeded => {
+            ClientError::Control(format!("command longer than {} bytes", MAX_COMMAND_LEN))
+        }
+        LinesCodecError::Io(e) => ClientError::Io(e.to_string()),
+    }
+}
+
+/// Releases a connection slot when the connection ends
+struct ConnectionGuard {
+    usage: UsageMap,
+    tenant: String,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(usage) = self.usage.lock().unwrap().get_mut(&self.tenant) {
+            usage.connections = usage.connections.saturating_sub(1);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Subscription {
+    None,
+    All,
+    Symbols(HashSet<Symbol>),
+}
+
+/// Per-connection settings and conflation state
+struct Session {
+    tenant: Arc<Tenant>,
+    entitlements: Option<Arc<Entitlements>>,
+    subscription: Subscription,
+    filter: Option<Filter>,
+    limiter: Option<RateLimiter>,
+    conflate: Option<Duration>,
+    /// Latest quote or book per symbol awaiting the next conflation tick,
+    /// with its sequence number
+    pending: BTreeMap<(Symbol, bool), (Option<u64>, MarketDataMessage)>,
+    deltas: Option<BookDeltaEncoder>,
+    /// Whether messages are sent in [`Sequenced`] envelopes
+    sequenced: bool,
+}
+
+impl Session {
+    fn new(tenant: Arc<Tenant>, entitlements: Option<Arc<Entitlements>>) -> Self {
+        let limiter = tenant
+            .max_rate
+            .map(|rate| RateLimiter::new(rate, Instant::now()));
+        Self {
+            tenant,
+            entitlements,
+            subscription: Subscription::None,
+            filter: None,
+            limiter,
+            conflate: None,
+            pending: BTreeMap::new(),
+            deltas: None,
+            sequenced: false,
+        }
+    }
+
+    fn command(&mut self, line: &str) -> Result<()> {
+        let invalid = |what: &str| ClientError::Control(what.to_string());
+        let line = line.trim();
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match command {
+            "subscribe" if arg == "*" => self.subscription = Subscription::All,
+            "subscribe" => {
+                let mut symbols = HashSet::new();
+                for name in arg.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                    // Never intern client input: interned names are not freed
+                    let symbol = Symbol::lookup(name)?
+                        .ok_or_else(|| ClientError::Control(format!("unknown symbol {}", name)))?;
+                    self.authorize(&symbol)?;
+                    symbols.insert(symbol);
+                }
+                self.subscription = Subscription::Symbols(symbols);
+            }
+            "filter" if arg.is_empty() => self.filter = None,
+            "filter" => self.filter = Some(Filter::parse(arg)?),
+            "rate" => {
+                let rate: f64 = arg
+                    .parse()
+                    .ok()
+                    .filter(|rate| *rate > 0.0)
+                    .ok_or_else(|| invalid("rate must be a positive number"))?;
+                if self.tenant.max_rate.is_some_and(|max| rate > max) {
+                    return Err(invalid("rate above tenant limit"));
+                }
+                self.limiter = Some(RateLimiter::new(rate, Instant::now()));
+            }
+            "conflate" => {
+                let ms: u64 = arg
+                    .parse()
+                    .map_err(|_| invalid("conflate takes milliseconds"))?;
+                self.conflate = (ms > 0).then(|| Duration::from_millis(ms));
+                if self.conflate.is_none() {
+                    self.pending.clear();
+                }
+            }
+            "deltas" => {
+                let keyframe_every: u64 = arg
+                    .parse()
+                    .map_err(|_| invalid("deltas takes a keyframe interval"))?;
+                self.deltas = (keyframe_every > 0).then(|| BookDeltaEncoder::new(keyframe_every));
+            }
+            "ack" => {
+                let (symbol, seq) = arg
+                    .split_once(' ')
+                    .and_then(|(symbol, seq)| Some((symbol, seq.trim().parse().ok()?)))
+                    .ok_or_else(|| invalid("ack takes a symbol and sequence"))?;
+                let encoder = self
+                    .deltas
+                    .as_mut()
+                    .ok_or_else(|| invalid("deltas are not enabled"))?;
+                if !encoder.ack(symbol, seq) {
+                    return Err(invalid("unknown book state"));
+                }
+            }
+            _ => return Err(ClientError::Control(format!("unknown command: {}", line))),
+        }
+        Ok(())
+    }
+
+    fn authorize(&self, symbol: &str) -> Result<()> {
+        if !self.tenant.entitled(symbol) {
+            warn!(target: "audit", consumer = %self.tenant.name, request = symbol, "Entitlement denied");
+            return Err(ClientError::Control(format!("not entitled to {}", symbol)));
+        }
+        match &self.entitlements {
+            Some(entitlements) => entitlements.authorize(&self.tenant.name, symbol, None),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the connection's subscription, entitlements and filter
+    /// let `msg` through
+    fn admits(&self, msg: &MarketDataMessage) -> bool {
+        let subscribed = match (&self.subscription, msg.symbol()) {
+            (Subscription::None, _) => false,
+            (_, None) => true,
+            (Subscription::All, Some(symbol)) => self.tenant.entitled(&symbol),
+            (Subscription::Symbols(symbols), Some(symbol)) => symbols.contains(&symbol),
+        };
+        let entitled = self
+            .entitlements
+            .as_ref()
+            .is_none_or(|e| e.allows(&self.tenant.name, msg));
+        subscribed && entitled && self.filter.as_ref().is_none_or(|f| f.matches(msg))
+    }
+
+    /// The message if it should be sent now; conflated quotes and books are
+    /// held for [`flush`](Self::flush)
+    fn offer(
+        &mut self,
+        seq: Option<u64>,
+        msg: MarketDataMessage,
+    ) -> Option<(Option<u64>, MarketDataMessage)> {
+        if !self.admits(&msg) {
+            return None;
+        }
+        let conflatable = match &msg {
+            MarketDataMessage::Quote(quote) => Some((&quote.symbol, false)),
+            MarketDataMessage::OrderBook(book) => Some((&book.symbol, true)),
+            _ => None,
+        };
+        match conflatable.filter(|_| self.conflate.is_some()) {
+            Some((symbol, is_book)) => {
+                self.pending.insert((*symbol, is_book), (seq, msg));
+                None
+            }
+            None => Some((seq, msg)),
+        }
+    }
+
+    /// Framed wire form of an outgoing message
+    fn encode(
+        &mut self,
+        msg: &MarketDataMessage,
+        seq: Option<u64>,
+        format: WireFormat,
+    ) -> Result<Vec<u8>> {
+        match (msg, &mut self.deltas) {
+            (MarketDataMessage::OrderBook(book), Some(encoder)) => {
+                let delta = encoder.encode(book);
+                self.frame(&delta, seq, format)
+            }
+            _ => self.frame(msg, seq, format),
+        }
+    }
+
+    /// `item` framed as is, or in a [`Sequenced`] envelope with gap-fill
+    fn frame<T: Serialize>(
+        &self,
+        item: &T,
+        seq: Option<u64>,
+        format: WireFormat,
+    ) -> Result<Vec<u8>> {
+        if self.sequenced {
+            format.encode_frame(&Sequenced { seq, message: item })
+        } else {
+            format.encode_frame(item)
+        }
+    }
+
+    fn flush(&mut self) -> Vec<(Option<u64>, MarketDataMessage)> {
+        std::mem::take(&mut self.pending).into_values().collect()
+    }
+
+    fn permit(&mut self, now: Instant) -> bool {
+        self.limiter
+            .as_mut()
+            .is_none_or(|limiter| limiter.try_acquire(now).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entitlements::Grant;
+    use crate::types::{Quote, Trade};
+
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    fn trade(symbol: &str) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            trade_id: "1".to_string(),
+            ..Trade::test(symbol, 100.0)
+        })
+    }
+
+    fn quote(symbol: &str, bid: f64) -> MarketDataMessage {
+        MarketDataMessage::Quote(Quote::test(symbol, bid, bid + 1.0))
+    }
+
+    #[test]
+    fn test_session_entitlements_and_conflation() {
+        let tenant = Tenant::new("research", "k1")
+            .with_symbols(&["BTCUSD", "ETHUSD"])
+            .with_max_rate(50.0);
+        let entitlements = Entitlements::new().with_grant(
+            "research",
+            Grant::new(&["*"]).with_channels(&["trade", "quote"]),
+        );
+        let mut session = Session::new(Arc::new(tenant), Some(Arc::new(entitlements)));
+
+        assert!(session.offer(None, trade("BTCUSD")).is_none());
+        assert!(session.command("subscribe BTCUSD,SOLUSD").is_err());
+        assert!(session.command("subscribe BTCUSD,NOSUCHSYM").is_err());
+        assert!(Symbol::lookup("NOSUCHSYM").unwrap().is_none());
+        assert!(session.command("rate 100").is_err());
+        session.command("subscribe *").unwrap();
+        assert!(session.offer(None, trade("BTCUSD")).is_some());
+        assert!(session.offer(None, trade("SOLUSD")).is_none());
+        // Heartbeats are not among the granted channels
+        assert!(session.offer(None, MarketDataMessage::Heartbeat).is_none());
+
+        session.command("filter type == 'quote'").unwrap();
+        session.command("conflate 100").unwrap();
+        assert!(session.offer(None, trade("ETHUSD")).is_none());
+        assert!(session.offer(None, quote("ETHUSD", 10.0)).is_none());
+        assert!(session.offer(None, quote("ETHUSD", 11.0)).is_none());
+        match session.flush().as_slice() {
+            [(None, MarketDataMessage::Quote(quote))] => assert_eq!(quote.bid_price, 11.0),
+            other => panic!("expected one conflated quote, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serves_authenticated_tenants() {
+        let (tx, rx) = broadcast::channel(16);
+        let server = Arc::new(
+            FanOutServer::new(rx)
+                .with_tenant(Tenant::new("research", "k1").with_symbols(&["BTCUSD"])),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.serve(listener).await }
+        });
+
+        let connect = |line: &'static str| async move {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let (read, mut write) = stream.into_split();
+            write.write_all(line.as_bytes()).await.unwrap();
+            (BufReader::new(read).lines(), write)
+        };
+        let (mut lines, _write) = connect("auth wrong\n").await;
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "error: Control error: invalid api key"
+        );
+
+        // Over-long commands are refused and close the connection
+        let (mut lines, mut write) = connect("auth k1\n").await;
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        let long = format!("filter {}\n", "x".repeat(MAX_COMMAND_LEN));
+        write.write_all(long.as_bytes()).await.unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.contains("command longer than"), "{}", line);
+        assert!(lines.next_line().await.unwrap().is_none());
+
+        let (mut lines, _write) = connect("auth k1\nsubscribe BTCUSD\n").await;
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        tx.send(trade("ETHUSD")).unwrap();
+        tx.send(trade("BTCUSD")).unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.contains("BTCUSD"), "{}", line);
+
+        let usage = server.usage()["research"];
+        assert_eq!((usage.connections, usage.messages), (1, 1));
+        assert_eq!(usage.bytes, line.len() as u64 + 1);
+    }
+
+    #[tokio::test]
+    async fn test_primes_first_subscribe_from_sync() {
+        let (tx, rx) = broadcast::channel(16);
+        let sync = SyncHandle::new(tx.clone());
+        sync.publish(trade("BTCUSD")).unwrap();
+        let server = FanOutServer::new(rx)
+            .with_tenant(Tenant::new("research", "k1"))
+            .with_sync(sync.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server.serve(listener).await });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write.write_all(b"auth k1\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        // Nothing is subscribed, or cloned, before the first subscribe
+        assert_eq!(tx.receiver_count(), 1);
+
+        write.write_all(b"subscribe BTCUSD\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        let mut next = async || {
+            let line = lines.next_line().await.unwrap().unwrap();
+            serde_json::from_str::<MarketDataMessage>(&line).unwrap()
+        };
+        assert!(matches!(next().await, MarketDataMessage::Trade(_)));
+        assert_eq!(tx.receiver_count(), 2);
+        sync.publish(quote("BTCUSD", 10.0)).unwrap();
+        assert!(matches!(next().await, MarketDataMessage::Quote(_)));
+
+        // With gap-fill, numbering carries on from the snapshot's boundary
+        let server = FanOutServer::new(tx.subscribe())
+            .with_tenant(Tenant::new("research", "k1"))
+            .with_sync(sync.clone())
+            .with_gap_fill(HistoryBuffer::new());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server.serve(listener).await });
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write.write_all(b"auth k1\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        sync.publish(trade("BTCUSD")).unwrap();
+        write.write_all(b"subscribe BTCUSD\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        sync.publish(quote("BTCUSD", 11.0)).unwrap();
+        let mut seqs = Vec::new();
+        loop {
+            let line = lines.next_line().await.unwrap().unwrap();
+            let sequenced: Sequenced<MarketDataMessage> = serde_json::from_str(&line).unwrap();
+            seqs.push(sequenced.seq);
+            if matches!(sequenced.message, MarketDataMessage::Quote(q) if q.bid_price == 11.0) {
+                break;
+            }
+        }
+        // The trade is in the snapshot or numbered 1, never both
+        assert!(seqs == [None, None, Some(2)] || seqs == [None, None, Some(1), Some(2)]);
+    }
+
+    #[tokio::test]
+    async fn test_replays_retained_sequence_range() {
+        let (tx, rx) = broadcast::channel(16);
+        let server = FanOutServer::new(rx)
+            .with_tenant(Tenant::new("research", "k1").with_symbols(&["BTCUSD"]))
+            .with_gap_fill(HistoryBuffer::new().with_max_messages(2));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server.serve(listener).await });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write
+            .write_all(b"auth k1\nsubscribe BTCUSD\n")
+            .await
+            .unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        for symbol in ["BTCUSD", "ETHUSD", "BTCUSD", "BTCUSD"] {
+            tx.send(trade(symbol)).unwrap();
+        }
+        let mut next = async || {
+            let line = lines.next_line().await.unwrap().unwrap();
+            serde_json::from_str::<Sequenced<serde_json::Value>>(&line)
+                .map(|sequenced| sequenced.seq)
+                .unwrap_or_else(|_| panic!("{}", line))
+        };
+        assert_eq!(next().await, Some(1));
+        assert_eq!(next().await, Some(3));
+        assert_eq!(next().await, Some(4));
+
+        // BTCUSD's seq 1 was evicted, and ETHUSD's 2 is not subscribed
+        write.write_all(b"replay 1 4\nreplay 2 3\n").await.unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("error:"), "{}", line);
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        let line = lines.next_line().await.unwrap().unwrap();
+        let replayed: Sequenced<MarketDataMessage> = serde_json::from_str(&line).unwrap();
+        assert_eq!(replayed.seq, Some(3));
+        assert!(matches!(replayed.message, MarketDataMessage::Trade(t) if t.symbol == "BTCUSD"));
+    }
+}
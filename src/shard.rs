@@ -0,0 +1,502 @@
+//! Splitting a symbol universe across streamer processes.
+//!
+//! Symbols hash to a fixed number of shards with a hash that is stable
+//! across processes and builds. A [`ShardCoordinator`] decides which shards
+//! this process streams: either a static slice of the universe, or shards
+//! held under expiring leases in a shared [`LeaseStore`], so that when a
+//! peer dies its leases lapse and a surviving process takes its symbols
+//! over. Each process subscribes only to its own symbols and publishes to
+//! the shared sink as usual.
+//!
+//! Leased coordinators also keep a membership heartbeat in the store and
+//! split the shards nobody prefers round-robin over the live members, so
+//! every process streams a fair share. When a peer joins, the others hand
+//! over the shards that are now its share; when it dies, its share returns
+//! to the survivors once its leases lapse.
+//!
+//! [`FileLeaseStore`] keeps leases on a shared filesystem, updating each
+//! under an exclusively created lock file. A Redis (`SET NX PX`) or etcd
+//! lease implementation of [`LeaseStore`] works across hosts without a
+//! shared filesystem.
+
+use crate::client::{ClientError, Result};
+use crate::control::ControlHandle;
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Shard of `symbol` among `shards`, the same in every process
+pub fn shard_of(symbol: &str, shards: usize) -> usize {
+    // FNV-1a, unlike `DefaultHasher`, is specified and will not change
+    let hash = symbol.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    (hash % shards.max(1) as u64) as usize
+}
+
+/// Shared record of which process owns each shard
+pub trait LeaseStore: Send + Sync {
+    /// Take or renew the lease on `shard` for `ttl`. False if another owner
+    /// holds an unexpired lease.
+    fn acquire(&self, shard: usize, owner: &str, ttl: Duration) -> Result<bool>;
+
+    /// Give up the lease on `shard` if `owner` holds it
+    fn release(&self, shard: usize, owner: &str) -> Result<()>;
+
+    /// Mark `owner` live for `ttl`
+    fn heartbeat(&self, owner: &str, ttl: Duration) -> Result<()>;
+
+    /// Owners with an unexpired heartbeat
+    fn members(&self) -> Result<Vec<String>>;
+
+    /// Stop counting `owner` as live
+    fn leave(&self, owner: &str) -> Result<()>;
+}
+
+/// Age from which a lock file is taken to be left by a dead process
+const STALE_LOCK: Duration = Duration::from_secs(5);
+
+/// Leases as files in a directory shared by all processes
+#[derive(Debug, Clone)]
+pub struct FileLeaseStore {
+    dir: PathBuf,
+}
+
+impl FileLeaseStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| ClientError::Io(e.to_string()))?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, shard: usize) -> PathBuf {
+        self.dir.join(format!("shard-{}.lease", shard))
+    }
+
+    /// Membership file of `owner`, named by its hex encoding so that any
+    /// owner string is a safe file name
+    fn member_path(&self, owner: &str) -> PathBuf {
+        let hex: String = owner.bytes().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(format!("member-{}.lease", hex))
+    }
+
+    /// Run `update` holding the shard's lock file; `None` while a peer
+    /// holds it
+    fn locked<T>(&self, shard: usize, update: impl FnOnce() -> Result<T>) -> Result<Option<T>> {
+        let lock = self.dir.join(format!("shard-{}.lock", shard));
+        match OpenOptions::new().write(true).create_new(true).open(&lock) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let stale = fs::metadata(&lock)
+                    .and_then(|meta| meta.modified())
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok())
+                    .is_some_and(|age| age > STALE_LOCK);
+                if stale {
+                    warn!("Removing stale lease lock {}", lock.display());
+                    let _ = fs::remove_file(&lock);
+                }
+                return Ok(None);
+            }
+            Err(e) => return Err(ClientError::Io(e.to_string())),
+        }
+        let result = update();
+        let _ = fs::remove_file(&lock);
+        result.map(Some)
+    }
+}
+
+/// Owner and expiry (unix millis) of a lease or membership file
+fn read_lease(path: &Path) -> Option<(String, u128)> {
+    let contents = fs::read_to_string(path).ok()?;
+    let (owner, expires) = contents.trim().rsplit_once(' ')?;
+    Some((owner.to_string(), expires.parse().ok()?))
+}
+
+/// Replace `path` with `owner`'s lease until `expires`, via `tmp` so that
+/// readers never see a partial lease
+fn write_lease(path: &Path, tmp: &Path, owner: &str, expires: u128) -> Result<()> {
+    fs::write(tmp, format!("{} {}", owner, expires))
+        .and_then(|()| fs::rename(tmp, path))
+        .map_err(|e| ClientError::Io(e.to_string()))
+}
+
+impl LeaseStore for FileLeaseStore {
+    fn acquire(&self, shard: usize, owner: &str, ttl: Duration) -> Result<bool> {
+        let path = self.path(shard);
+        let acquired = self.locked(shard, || {
+            let now = unix_millis();
+            if let Some((holder, expires)) = read_lease(&path) {
+                if holder != owner && expires > now {
+                    return Ok(false);
+                }
+            }
+            let tmp = self.dir.join(format!("shard-{}.tmp", shard));
+            write_lease(&path, &tmp, owner, now + ttl.as_millis())?;
+            Ok(true)
+        })?;
+        // While a peer updates the shard, an unexpired lease stays ours
+        Ok(acquired.unwrap_or_else(|| {
+            read_lease(&path)
+                .is_some_and(|(holder, expires)| holder == owner && expires > unix_millis())
+        }))
+    }
+
+    fn release(&self, shard: usize, owner: &str) -> Result<()> {
+        let path = self.path(shard);
+        self.locked(shard, || {
+            if read_lease(&path).is_some_and(|(holder, _)| holder == owner) {
+                fs::remove_file(&path).map_err(|e| ClientError::Io(e.to_string()))?;
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    fn heartbeat(&self, owner: &str, ttl: Duration) -> Result<()> {
+        let path = self.member_path(owner);
+        write_lease(
+            &path,
+            &path.with_extension("tmp"),
+            owner,
+            unix_millis() + ttl.as_millis(),
+        )
+    }
+
+    fn members(&self) -> Result<Vec<String>> {
+        let now = unix_millis();
+        let entries = fs::read_dir(&self.dir).map_err(|e| ClientError::Io(e.to_string()))?;
+        let mut members: Vec<String> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension().is_some_and(|ext| ext == "lease")
+                    && path
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy().starts_with("member-"))
+            })
+            .filter_map(|path| read_lease(&path))
+            .filter(|(_, expires)| *expires > now)
+            .map(|(owner, _)| owner)
+            .collect();
+        members.sort();
+        Ok(members)
+    }
+
+    fn leave(&self, owner: &str) -> Result<()> {
+        match fs::remove_file(self.member_path(owner)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(ClientError::Io(e.to_string())),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn unix_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+/// Symbols to subscribe to and drop after a change of shards
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShardChange {
+    pub subscribe: Vec<String>,
+    pub unsubscribe: Vec<String>,
+}
+
+impl ShardChange {
+    pub fn is_empty(&self) -> bool {
+        self.subscribe.is_empty() && self.unsubscribe.is_empty()
+    }
+}
+
+/// Decides which shards of the universe this process streams
+pub struct ShardCoordinator {
+    owner: String,
+    universe: Vec<String>,
+    shards: usize,
+    store: Option<Arc<dyn LeaseStore>>,
+    /// Shards claimed from the start and kept; others only once the grace
+    /// period has let their preferred owners claim them
+    preferred: BTreeSet<usize>,
+    ttl: Duration,
+    grace: Duration,
+    started: Instant,
+    owned: BTreeSet<usize>,
+}
+
+impl ShardCoordinator {
+    /// Always stream shard `index` of `shards`
+    pub fn fixed(universe: Vec<String>, index: usize, shards: usize) -> Self {
+        let mut coordinator = Self::new(format!("shard-{}", index), universe, shards, None);
+        coordinator.preferred.insert(index);
+        coordinator
+    }
+
+    /// Stream the shards `owner` holds leases on in `store`, taking over
+    /// any shard whose lease lapses
+    pub fn leased(
+        owner: impl Into<String>,
+        universe: Vec<String>,
+        shards: usize,
+        store: impl LeaseStore + 'static,
+    ) -> Self {
+        Self::new(owner.into(), universe, shards, Some(Arc::new(store)))
+    }
+
+    fn new(
+        owner: String,
+        universe: Vec<String>,
+        shards: usize,
+        store: Option<Arc<dyn LeaseStore>>,
+    ) -> Self {
+        let ttl = Duration::from_secs(10);
+        Self {
+            owner,
+            universe,
+            shards: shards.max(1),
+            store,
+            preferred: BTreeSet::new(),
+            ttl,
+            grace: ttl * 2,
+            started: Instant::now(),
+            owned: BTreeSet::new(),
+        }
+    }
+
+    /// Shards to claim as soon as the coordinator starts
+    pub fn with_preferred(mut self, shards: impl IntoIterator<Item = usize>) -> Self {
+        self.preferred = shards.into_iter().collect();
+        self
+    }
+
+    /// Lease lifetime (default 10s, at least 3ms); leases are renewed
+    /// every third of it
+    pub fn with_ttl(mut self, ttl: Duration) -> Result<Self> {
+        if ttl < Duration::from_millis(3) {
+            return Err(ClientError::Parse(format!(
+                "lease ttl must be at least 3ms, got {:?}",
+                ttl
+            )));
+        }
+        self.ttl = ttl;
+        Ok(self)
+    }
+
+    /// Wait before taking shards that are not preferred (default twice
+    /// the ttl)
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    pub fn owned_shards(&self) -> &BTreeSet<usize> {
+        &self.owned
+    }
+
+    pub fn owns(&self, symbol: &str) -> bool {
+        self.owned.contains(&shard_of(symbol, self.shards))
+    }
+
+    /// Symbols of the universe this process currently streams
+    pub fn symbols(&self) -> Vec<String> {
+        self.symbols_in(&self.owned)
+    }
+
+    fn symbols_in(&self, shards: &BTreeSet<usize>) -> Vec<String> {
+        self.universe
+            .iter()
+            .filter(|symbol| shards.contains(&shard_of(symbol, self.shards)))
+            .cloned()
+            .collect()
+    }
+
+    /// Renew held leases, hand over shards that are now a joined peer's
+    /// share and claim free shards of this process's share.
+    ///
+    /// Shards are dealt round-robin over the live members in name order, so
+    /// each process owns at most `shards / members` (rounded up) besides
+    /// its preferred shards.
+    pub fn tick(&mut self, now: Instant) -> Result<ShardChange> {
+        let owned = match &self.store {
+            None => self.preferred.clone(),
+            Some(store) => {
+                store.heartbeat(&self.owner, self.ttl)?;
+                let mut members = store.members()?;
+                if !members.contains(&self.owner) {
+                    members.push(self.owner.clone());
+                    members.sort();
+                }
+                let me = members.iter().position(|m| *m == self.owner).unwrap_or(0);
+                let past_grace = now.saturating_duration_since(self.started) >= self.grace;
+
+                let mut owned = BTreeSet::new();
+                for shard in 0..self.shards {
+                    let preferred = self.preferred.contains(&shard);
+                    let share = shard % members.len() == me;
+                    let wanted =
+                        preferred || (share && (past_grace || self.owned.contains(&shard)));
+                    if wanted {
+                        if store.acquire(shard, &self.owner, self.ttl)? {
+                            owned.insert(shard);
+                        }
+                    } else if self.owned.contains(&shard) {
+                        store.release(shard, &self.owner)?;
+                    }
+                }
+                owned
+            }
+        };
+
+        let change = ShardChange {
+            subscribe: self.symbols_in(&owned.difference(&self.owned).copied().collect()),
+            unsubscribe: self.symbols_in(&self.owned.difference(&owned).copied().collect()),
+        };
+        for shard in owned.difference(&self.owned) {
+            info!("{} acquired shard {}", self.owner, shard);
+        }
+        for shard in self.owned.difference(&owned) {
+            warn!("{} lost shard {}", self.owner, shard);
+        }
+        self.owned = owned;
+        Ok(change)
+    }
+
+    /// Give up every held lease, e.g. on shutdown so peers take over at once
+    pub fn release(&mut self) -> Result<ShardChange> {
+        if let Some(store) = &self.store {
+            for shard in &self.owned {
+                store.release(*shard, &self.owner)?;
+            }
+            store.leave(&self.owner)?;
+        }
+        let change = ShardChange {
+            subscribe: Vec::new(),
+            unsubscribe: self.symbols(),
+        };
+        self.owned.clear();
+        Ok(change)
+    }
+
+    /// Keep a running client subscribed to this process's symbols until
+    /// its control channel closes
+    pub async fn run(mut self, control: ControlHandle) -> Result<()> {
+        let mut interval = tokio::time::interval(self.ttl / 3);
+        loop {
+            interval.tick().await;
+            let change = match self.tick(Instant::now()) {
+                Ok(change) => change,
+                Err(e) => {
+                    warn!("Shard lease renewal failed: {}", e);
+                    continue;
+                }
+            };
+            if change.is_empty() {
+                continue;
+            }
+            let add: Vec<&str> = change.subscribe.iter().map(String::as_str).collect();
+            let remove: Vec<&str> = change.unsubscribe.iter().map(String::as_str).collect();
+            if !remove.is_empty() {
+                control.unsubscribe_symbols(&remove).await?;
+            }
+            if !add.is_empty() {
+                control.subscribe_symbols(&add).await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_takeover_after_lease_lapses() {
+        let dir = tempfile::tempdir().unwrap();
+        let universe: Vec<String> = (0..20).map(|i| format!("SYM{}", i)).collect();
+        let ttl = Duration::from_millis(50);
+        let coordinator = |owner: &str, shard| {
+            ShardCoordinator::leased(
+                owner,
+                universe.clone(),
+                2,
+                FileLeaseStore::new(dir.path()).unwrap(),
+            )
+            .with_preferred([shard])
+            .with_ttl(ttl)
+            .unwrap()
+            .with_grace(ttl)
+        };
+        assert!(ShardCoordinator::fixed(universe.clone(), 0, 2)
+            .with_ttl(Duration::ZERO)
+            .is_err());
+        let mut a = coordinator("a", 0);
+        let mut b = coordinator("b", 1);
+        let now = Instant::now();
+
+        // Each claims its own shard first, then cannot take the other's
+        let claimed = a.tick(now).unwrap();
+        assert!(claimed.subscribe.iter().all(|s| shard_of(s, 2) == 0));
+        assert_eq!(a.owned_shards(), &BTreeSet::from([0]));
+        b.tick(now).unwrap();
+        assert_eq!(a.tick(now).unwrap(), ShardChange::default());
+        assert_eq!(b.owned_shards(), &BTreeSet::from([1]));
+        assert_eq!(a.symbols().len() + b.symbols().len(), universe.len());
+
+        // b stops renewing, so past the grace period a picks up its symbols
+        // once the lease lapses
+        std::thread::sleep(ttl * 2);
+        let change = a.tick(Instant::now()).unwrap();
+        assert_eq!(change.subscribe, b.symbols());
+        assert_eq!(a.symbols(), universe);
+    }
+
+    #[test]
+    fn test_live_owners_share_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        let universe: Vec<String> = (0..20).map(|i| format!("SYM{}", i)).collect();
+        let coordinator = |owner: &str| {
+            ShardCoordinator::leased(
+                owner,
+                universe.clone(),
+                4,
+                FileLeaseStore::new(dir.path()).unwrap(),
+            )
+            .with_grace(Duration::ZERO)
+        };
+        let mut a = coordinator("a");
+        let now = Instant::now();
+
+        // Alone, a owns every shard
+        a.tick(now).unwrap();
+        assert_eq!(a.owned_shards().len(), 4);
+
+        // Once b joins, a hands over b's share, which b claims next tick
+        let mut b = coordinator("b");
+        b.tick(now).unwrap();
+        assert!(b.owned_shards().is_empty());
+        let change = a.tick(now).unwrap();
+        assert_eq!(a.owned_shards(), &BTreeSet::from([0, 2]));
+        b.tick(now).unwrap();
+        assert_eq!(b.owned_shards(), &BTreeSet::from([1, 3]));
+        assert_eq!(change.unsubscribe, b.symbols());
+
+        // A lease held under the lock stays with its owner
+        let store = FileLeaseStore::new(dir.path()).unwrap();
+        assert!(!store.acquire(1, "a", Duration::from_secs(1)).unwrap());
+        fs::write(dir.path().join("shard-1.lock"), "").unwrap();
+        assert!(store.acquire(1, "b", Duration::from_secs(1)).unwrap());
+        assert!(!store.acquire(1, "c", Duration::from_secs(1)).unwrap());
+
+        // Leaving returns the share to the remaining owner
+        fs::remove_file(dir.path().join("shard-1.lock")).unwrap();
+        b.release().unwrap();
+        a.tick(now).unwrap();
+        assert_eq!(a.owned_shards().len(), 4);
+    }
+}
@@ -0,0 +1,532 @@
+//! Paper-trading fill simulation against live or replayed data.
+//!
+//! Orders submitted to a [`FillSimulator`] become active after the configured
+//! latency and are matched against the order book and trade stream fed
+//! through [`FillSimulator::on_message`]. Market and marketable limit orders
+//! take liquidity from the visible book, which stays consumed until the
+//! venue updates the level; resting limit orders fill according to the
+//! [`QueueModel`], sharing each trade or crossing level between them. Whatever a market order cannot take from the book
+//! is cancelled and reported as [`Fill::cancelled`]. Fill notionals apply
+//! the contract multiplier of tagged messages, so futures fills are valued
+//! in currency.
+
+use crate::book::{BookSide, OrderBook};
+use crate::client::{ClientError, Result};
+use crate::symbology::Symbol;
+use crate::types::{
+    ContractSpec, MarketDataMessage, OrderBookSnapshot, PriceLevel, Quote, Trade, TradeSide,
+};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use tracing::debug;
+
+/// How resting limit orders are assumed to sit in the queue
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueueModel {
+    /// Fill as soon as a trade prints at the order's price
+    Optimistic,
+    /// Join the back of the queue: volume displayed at the price when the
+    /// order rests must trade first
+    QueuePosition,
+    /// Fill only when the market trades through the order's price
+    Conservative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderType {
+    Market,
+    Limit(f64),
+}
+
+/// Whether a fill added or removed liquidity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+/// Simulated execution
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub order_id: u64,
+    pub symbol: Symbol,
+    pub side: TradeSide,
+    pub price: f64,
+    pub quantity: f64,
+    /// Price times quantity times the contract multiplier
+    pub notional: f64,
+    pub liquidity: Liquidity,
+    pub timestamp: DateTime<Utc>,
+    /// Quantity of the order cancelled after this fill: the remainder of a
+    /// market order that exhausted the book. An order the book could not
+    /// fill at all reports a fill of zero quantity.
+    pub cancelled: f64,
+}
+
+#[derive(Debug, Clone)]
+struct SimOrder {
+    id: u64,
+    symbol: Symbol,
+    side: TradeSide,
+    order_type: OrderType,
+    remaining: f64,
+    active_at: DateTime<Utc>,
+    queue_ahead: f64,
+    resting: bool,
+}
+
+/// Matches hypothetical orders against market data
+#[derive(Debug)]
+pub struct FillSimulator {
+    latency: Duration,
+    queue_model: QueueModel,
+    books: HashMap<Symbol, OrderBook>,
+    contracts: HashMap<Symbol, ContractSpec>,
+    orders: Vec<SimOrder>,
+    next_id: u64,
+}
+
+impl FillSimulator {
+    pub fn new(latency: Duration, queue_model: QueueModel) -> Self {
+        Self {
+            latency,
+            queue_model,
+            books: HashMap::new(),
+            contracts: HashMap::new(),
+            orders: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Value fills of `symbol` with `contract`, for messages that are not
+    /// tagged with their contract terms
+    pub fn with_contract(mut self, symbol: &str, contract: ContractSpec) -> Self {
+        self.contracts.insert(symbol.into(), contract);
+        self
+    }
+
+    /// Submit an order at time `now`; it reaches the simulated venue after
+    /// the configured latency. Returns the order id; orders need a buy or
+    /// sell side.
+    pub fn submit(
+        &mut self,
+        symbol: &str,
+        side: TradeSide,
+        quantity: f64,
+        order_type: OrderType,
+        now: DateTime<Utc>,
+    ) -> Result<u64> {
+        if side == TradeSide::Unknown {
+            return Err(ClientError::Parse(format!(
+                "order for {} needs a buy or sell side",
+                symbol
+            )));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.orders.push(SimOrder {
+            id,
+            symbol: symbol.into(),
+            side,
+            order_type,
+            remaining: quantity,
+            active_at: now + self.latency,
+            queue_ahead: 0.0,
+            resting: false,
+        });
+        Ok(id)
+    }
+
+    /// Cancel an open order, returning false if it was already done
+    pub fn cancel(&mut self, order_id: u64) -> bool {
+        let before = self.orders.len();
+        self.orders.retain(|order| order.id != order_id);
+        self.orders.len() != before
+    }
+
+    /// Remaining quantity of an open order
+    pub fn open_quantity(&self, order_id: u64) -> Option<f64> {
+        self.orders
+            .iter()
+            .find(|order| order.id == order_id)
+            .map(|order| order.remaining)
+    }
+
+    /// Feed a market data message, returning any fills it caused
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<Fill> {
+        let Some(ts) = msg.timestamp() else {
+            return Vec::new();
+        };
+
+        if let (Some(symbol), Some(contract)) = (msg.symbol(), msg.contract()) {
+            self.contracts.insert(symbol, contract);
+        }
+
+        // Orders that reached the venue before this update see the prior book
+        let mut fills = self.activate(ts);
+
+        match msg {
+            MarketDataMessage::OrderBook(snapshot) => {
+                self.book_mut(&snapshot.symbol).apply_snapshot(snapshot);
+                fills.extend(self.match_resting_against_book(&snapshot.symbol, ts));
+            }
+            MarketDataMessage::Quote(quote) => {
+                self.book_mut(&quote.symbol)
+                    .apply_snapshot(&top_of_book(quote));
+                fills.extend(self.match_resting_against_book(&quote.symbol, ts));
+            }
+            MarketDataMessage::Trade(trade) => {
+                fills.extend(self.match_resting_against_trade(trade))
+            }
+            _ => {}
+        }
+
+        self.orders.retain(|order| order.remaining > 0.0);
+        for fill in fills.iter_mut() {
+            if let Some(contract) = self.contracts.get(&fill.symbol) {
+                fill.notional = contract.notional(fill.price, fill.quantity);
+            }
+        }
+        fills
+    }
+
+    fn book_mut(&mut self, symbol: &str) -> &mut OrderBook {
+        self.books
+            .entry(symbol.into())
+            .or_insert_with(|| OrderBook::new(symbol.to_string()))
+    }
+
+    fn activate(&mut self, now: DateTime<Utc>) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        for order in self.orders.iter_mut() {
+            if order.resting || order.active_at > now {
+                continue;
+            }
+            let Some(book) = self.books.get_mut(&order.symbol) else {
+                debug!("No book for {} yet, order {} waits", order.symbol, order.id);
+                continue;
+            };
+
+            let mut taken = take_liquidity(order, book, order.active_at);
+            match order.order_type {
+                OrderType::Market if order.remaining > 0.0 => {
+                    debug!(
+                        "Market order {} left {} of {} unfilled",
+                        order.id, order.remaining, order.symbol
+                    );
+                    let cancelled = order.remaining;
+                    if taken.is_empty() {
+                        taken.push(fill(order, 0.0, 0.0, Liquidity::Taker, order.active_at));
+                    }
+                    if let Some(last) = taken.last_mut() {
+                        last.cancelled = cancelled;
+                    }
+                    order.remaining = 0.0;
+                }
+                OrderType::Market => {}
+                OrderType::Limit(price) if order.remaining > 0.0 => {
+                    order.resting = true;
+                    order.queue_ahead = match self.queue_model {
+                        QueueModel::QueuePosition => displayed_size(book, order.side, price),
+                        _ => 0.0,
+                    };
+                }
+                OrderType::Limit(_) => {}
+            }
+            fills.append(&mut taken);
+        }
+        fills
+    }
+
+    fn match_resting_against_book(&mut self, symbol: &str, now: DateTime<Utc>) -> Vec<Fill> {
+        let Some(book) = self.books.get(symbol) else {
+            return Vec::new();
+        };
+        let mut fills = Vec::new();
+        // Size of each crossing level already given to an earlier order
+        let mut consumed: HashMap<u64, f64> = HashMap::new();
+
+        for order in self.orders.iter_mut() {
+            if !order.resting || order.symbol != symbol {
+                continue;
+            }
+            let OrderType::Limit(price) = order.order_type else {
+                continue;
+            };
+            // Opposite levels that moved through our price hit us
+            let crossing: Vec<&PriceLevel> = match order.side {
+                TradeSide::Buy => book.asks().take_while(|ask| ask.price < price).collect(),
+                TradeSide::Sell => book.bids().take_while(|bid| bid.price > price).collect(),
+                TradeSide::Unknown => Vec::new(),
+            };
+            let mut quantity = 0.0;
+            for level in crossing {
+                let used = consumed.entry(level.price.to_bits()).or_default();
+                let take = (level.size - *used).min(order.remaining - quantity);
+                if take > 0.0 {
+                    *used += take;
+                    quantity += take;
+                }
+            }
+            if quantity > 0.0 {
+                fills.push(fill(order, price, quantity, Liquidity::Maker, now));
+            }
+        }
+        fills
+    }
+
+    fn match_resting_against_trade(&mut self, trade: &Trade) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        // Trade quantity not yet given to an earlier order
+        let mut left = trade.quantity;
+
+        for order in self.orders.iter_mut() {
+            if !order.resting || order.symbol != trade.symbol {
+                continue;
+            }
+            let OrderType::Limit(price) = order.order_type else {
+                continue;
+            };
+
+            let through = match order.side {
+                TradeSide::Buy => trade.price < price,
+                TradeSide::Sell => trade.price > price,
+                TradeSide::Unknown => false,
+            };
+            let available = if through {
+                trade.quantity
+            } else if trade.price == price && self.queue_model != QueueModel::Conservative {
+                let consumed = order.queue_ahead.min(trade.quantity);
+                order.queue_ahead -= consumed;
+                trade.quantity - consumed
+            } else {
+                0.0
+            };
+
+            let quantity = available.min(left).min(order.remaining);
+            if quantity > 0.0 {
+                left -= quantity;
+                fills.push(fill(
+                    order,
+                    price,
+                    quantity,
+                    Liquidity::Maker,
+                    trade.timestamp,
+                ));
+            }
+        }
+        fills
+    }
+}
+
+/// Fill as much of `order` as the book allows within its limit price,
+/// removing the size taken from the book
+fn take_liquidity(order: &mut SimOrder, book: &mut OrderBook, now: DateTime<Utc>) -> Vec<Fill> {
+    let (side, levels): (BookSide, Vec<PriceLevel>) = match order.side {
+        TradeSide::Buy => (BookSide::Ask, book.asks().cloned().collect()),
+        TradeSide::Sell => (BookSide::Bid, book.bids().cloned().collect()),
+        TradeSide::Unknown => return Vec::new(),
+    };
+
+    let mut fills = Vec::new();
+    for level in levels {
+        if order.remaining <= 0.0 {
+            break;
+        }
+        if let OrderType::Limit(limit) = order.order_type {
+            let marketable = match order.side {
+                TradeSide::Buy => level.price <= limit,
+                TradeSide::Sell => level.price >= limit,
+                TradeSide::Unknown => false,
+            };
+            if !marketable {
+                break;
+            }
+        }
+        let quantity = level.size.min(order.remaining);
+        fills.push(fill(order, level.price, quantity, Liquidity::Taker, now));
+        let left = PriceLevel {
+            size: level.size - quantity,
+            ..level
+        };
+        book.update_level(side, left, now);
+    }
+    fills
+}
+
+fn fill(
+    order: &mut SimOrder,
+    price: f64,
+    quantity: f64,
+    liquidity: Liquidity,
+    timestamp: DateTime<Utc>,
+) -> Fill {
+    order.remaining -= quantity;
+    Fill {
+        order_id: order.id,
+        symbol: order.symbol,
+        side: order.side,
+        price,
+        quantity,
+        notional: price * quantity,
+        liquidity,
+        timestamp,
+        cancelled: 0.0,
+    }
+}
+
+fn displayed_size(book: &OrderBook, side: TradeSide, price: f64) -> f64 {
+    let mut levels: Box<dyn Iterator<Item = &PriceLevel>> = match side {
+        TradeSide::Buy => Box::new(book.bids()),
+        TradeSide::Sell => Box::new(book.asks()),
+        TradeSide::Unknown => return 0.0,
+    };
+    levels
+        .find(|level| level.price == price)
+        .map(|level| level.size)
+        .unwrap_or(0.0)
+}
+
+fn top_of_book(quote: &Quote) -> OrderBookSnapshot {
+    let level = |price, size| PriceLevel {
+        price,
+        size,
+        num_orders: 1,
+    };
+    OrderBookSnapshot {
+        symbol: quote.symbol,
+        bids: vec![level(quote.bid_price, quote.bid_size)],
+        asks: vec![level(quote.ask_price, quote.ask_size)],
+        timestamp: quote.timestamp,
+        instrument_id: None,
+        contract: None,
+        received: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn quote(ts: DateTime<Utc>, bid: f64, ask: f64) -> MarketDataMessage {
+        MarketDataMessage::Quote(Quote {
+            bid_size: 5.0,
+            timestamp: ts,
+            ..Quote::test("BTCUSD", bid, ask)
+        })
+    }
+
+    fn trade(ts: DateTime<Utc>, price: f64, quantity: f64) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            quantity,
+            side: TradeSide::Sell,
+            timestamp: ts,
+            trade_id: "1".to_string(),
+            ..Trade::test("BTCUSD", price)
+        })
+    }
+
+    #[test]
+    fn test_market_order_respects_latency() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut sim = FillSimulator::new(Duration::milliseconds(50), QueueModel::Optimistic);
+        sim.on_message(&quote(t0, 99.0, 100.0));
+
+        let id = sim
+            .submit("BTCUSD", TradeSide::Buy, 0.5, OrderType::Market, t0)
+            .unwrap();
+        assert!(sim
+            .on_message(&quote(t0 + Duration::milliseconds(10), 99.0, 100.0))
+            .is_empty());
+
+        // Book moved before the order arrived; it executes at the new ask
+        sim.on_message(&quote(t0 + Duration::milliseconds(40), 100.0, 101.0));
+        let fills = sim.on_message(&quote(t0 + Duration::milliseconds(60), 100.0, 101.0));
+        assert_eq!(fills.len(), 1);
+        assert_eq!((fills[0].order_id, fills[0].price), (id, 101.0));
+        assert_eq!(fills[0].liquidity, Liquidity::Taker);
+        assert_eq!((fills[0].notional, fills[0].cancelled), (50.5, 0.0));
+        assert!(sim.open_quantity(id).is_none());
+
+        // Tagged futures quotes value fills with the contract multiplier
+        let mut future = quote(t0 + Duration::milliseconds(70), 100.0, 101.0);
+        if let MarketDataMessage::Quote(quote) = &mut future {
+            quote.contract = Some(ContractSpec::future(50.0, t0 + Duration::days(30)));
+        }
+        sim.on_message(&future);
+        sim.submit("BTCUSD", TradeSide::Buy, 0.5, OrderType::Market, t0)
+            .unwrap();
+        let fills = sim.on_message(&quote(t0 + Duration::milliseconds(80), 100.0, 101.0));
+        assert_eq!(fills[0].notional, 2525.0);
+
+        // What the book cannot absorb is cancelled, and says so
+        let id = sim
+            .submit("BTCUSD", TradeSide::Sell, 8.0, OrderType::Market, t0)
+            .unwrap();
+        let fills = sim.on_message(&quote(t0 + Duration::milliseconds(90), 100.0, 101.0));
+        assert_eq!(fills.len(), 1);
+        assert_eq!((fills[0].quantity, fills[0].cancelled), (5.0, 3.0));
+        assert!(sim.open_quantity(id).is_none());
+        assert!(sim
+            .submit("BTCUSD", TradeSide::Unknown, 1.0, OrderType::Market, t0)
+            .is_err());
+
+        // Orders arriving together share the displayed size
+        let ts = t0 + Duration::milliseconds(100);
+        sim.submit("BTCUSD", TradeSide::Sell, 3.0, OrderType::Market, t0)
+            .unwrap();
+        sim.submit("BTCUSD", TradeSide::Sell, 3.0, OrderType::Market, t0)
+            .unwrap();
+        let fills = sim.on_message(&quote(ts, 100.0, 101.0));
+        let filled: Vec<_> = fills.iter().map(|f| (f.quantity, f.cancelled)).collect();
+        assert_eq!(filled, [(3.0, 0.0), (2.0, 1.0)]);
+    }
+
+    #[test]
+    fn test_queue_position_model() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut sim = FillSimulator::new(Duration::zero(), QueueModel::QueuePosition);
+        sim.on_message(&quote(t0, 99.0, 100.0));
+
+        let id = sim
+            .submit("BTCUSD", TradeSide::Buy, 2.0, OrderType::Limit(99.0), t0)
+            .unwrap();
+        assert!(sim.on_message(&trade(t0, 99.0, 3.0)).is_empty());
+        assert_eq!(sim.open_quantity(id), Some(2.0));
+
+        // 5 lots were ahead of us; 3 traded, so 2 more clear the queue
+        let fills = sim.on_message(&trade(t0, 99.0, 3.0));
+        assert_eq!(fills[0].quantity, 1.0);
+        assert_eq!(sim.open_quantity(id), Some(1.0));
+    }
+
+    #[test]
+    fn test_resting_orders_share_liquidity() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut sim = FillSimulator::new(Duration::zero(), QueueModel::Optimistic);
+        sim.on_message(&quote(t0, 99.0, 100.0));
+        let first = sim
+            .submit("BTCUSD", TradeSide::Buy, 2.0, OrderType::Limit(99.5), t0)
+            .unwrap();
+        let second = sim
+            .submit("BTCUSD", TradeSide::Buy, 2.0, OrderType::Limit(99.5), t0)
+            .unwrap();
+        sim.on_message(&quote(t0, 99.0, 100.0));
+
+        // A trade through both orders fills them from the same 3 lots
+        let fills = sim.on_message(&trade(t0, 99.0, 3.0));
+        let filled: Vec<_> = fills.iter().map(|f| (f.order_id, f.quantity)).collect();
+        assert_eq!(filled, [(first, 2.0), (second, 1.0)]);
+
+        // As does an ask level moving through them
+        let mut crossed = quote(t0, 98.0, 99.0);
+        if let MarketDataMessage::Quote(quote) = &mut crossed {
+            quote.ask_size = 0.5;
+        }
+        let fills = sim.on_message(&crossed);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(sim.open_quantity(second), Some(0.5));
+    }
+}
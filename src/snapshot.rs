@@ -0,0 +1,152 @@
+//! Periodic full order book snapshots from incrementally maintained books.
+
+use crate::book::{BookSide, OrderBook};
+use crate::clock::align;
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, OrderBookSnapshot, PriceLevel};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+
+/// Materializes [`OrderBookSnapshot`]s of every tracked book on a fixed
+/// cadence, so consumers and recorders get full state without applying
+/// deltas themselves.
+///
+/// Ticks are aligned to multiples of the interval and driven by event time:
+/// the first update at or after a tick emits one snapshot per non-empty book,
+/// stamped with the tick time.
+#[derive(Debug, Clone)]
+pub struct SnapshotScheduler {
+    interval: Duration,
+    depth: Option<usize>,
+    books: BTreeMap<Symbol, OrderBook>,
+    next_tick: Option<DateTime<Utc>>,
+}
+
+impl SnapshotScheduler {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            depth: None,
+            books: BTreeMap::new(),
+            next_tick: None,
+        }
+    }
+
+    /// Limit snapshots to the top `depth` levels per side
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Time of the next scheduled snapshot
+    pub fn next_tick(&self) -> Option<DateTime<Utc>> {
+        self.next_tick
+    }
+
+    /// Apply an incremental level update (size 0 removes the level)
+    pub fn update_level(
+        &mut self,
+        symbol: impl Into<Symbol>,
+        side: BookSide,
+        level: PriceLevel,
+        timestamp: DateTime<Utc>,
+    ) -> Vec<OrderBookSnapshot> {
+        let due = self.poll(timestamp);
+        self.book_mut(symbol.into())
+            .update_level(side, level, timestamp);
+        due
+    }
+
+    /// Feed a message; full books replace the tracked state. Returns the
+    /// snapshots that came due before the message's timestamp.
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<OrderBookSnapshot> {
+        let Some(ts) = msg.timestamp() else {
+            return Vec::new();
+        };
+        let due = self.poll(ts);
+        if let MarketDataMessage::OrderBook(snapshot) = msg {
+            self.book_mut(snapshot.symbol).apply_snapshot(snapshot);
+        }
+        due
+    }
+
+    /// Emit the snapshot for the latest tick at or before `now`, if one is due
+    pub fn poll(&mut self, now: DateTime<Utc>) -> Vec<OrderBookSnapshot> {
+        let tick = align(now, self.interval);
+        let Some(next) = self.next_tick else {
+            self.next_tick = Some(tick + self.interval);
+            return Vec::new();
+        };
+        if now < next {
+            return Vec::new();
+        }
+
+        // Skipped ticks are not replayed: the book state is the same for all
+        self.next_tick = Some(tick + self.interval);
+        self.books
+            .values()
+            .filter(|book| !book.is_empty())
+            .map(|book| {
+                let mut snapshot = book.snapshot(self.depth);
+                snapshot.timestamp = tick;
+                snapshot
+            })
+            .collect()
+    }
+
+    fn book_mut(&mut self, symbol: Symbol) -> &mut OrderBook {
+        self.books
+            .entry(symbol)
+            .or_insert_with(|| OrderBook::new(symbol))
+    }
+}
+
+impl Stage for SnapshotScheduler {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        out.extend(
+            self.on_message(&msg)
+                .into_iter()
+                .map(MarketDataMessage::OrderBook),
+        );
+        out.push(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn level(price: f64, size: f64) -> PriceLevel {
+        PriceLevel {
+            price,
+            size,
+            num_orders: 1,
+        }
+    }
+
+    #[test]
+    fn test_snapshots_on_cadence() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut scheduler = SnapshotScheduler::new(Duration::seconds(1)).with_depth(1);
+
+        let ms = Duration::milliseconds;
+        assert!(scheduler
+            .update_level("BTCUSD", BookSide::Bid, level(99.0, 1.0), t0 + ms(100))
+            .is_empty());
+        scheduler.update_level("BTCUSD", BookSide::Bid, level(98.0, 2.0), t0 + ms(200));
+        scheduler.update_level("BTCUSD", BookSide::Ask, level(100.0, 1.0), t0 + ms(900));
+
+        let due = scheduler.update_level("BTCUSD", BookSide::Bid, level(99.0, 0.0), t0 + ms(1500));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].timestamp, t0 + ms(1000));
+        // Taken before the update that triggered it, limited to depth 1
+        assert_eq!(due[0].bids, vec![level(99.0, 1.0)]);
+        assert_eq!(scheduler.next_tick(), Some(t0 + ms(2000)));
+
+        let due = scheduler.poll(t0 + ms(4200));
+        assert_eq!(due[0].timestamp, t0 + ms(4000));
+        assert_eq!(due[0].bids, vec![level(98.0, 2.0)]);
+    }
+}
@@ -0,0 +1,221 @@
+//! Running market statistics with lock-free reads.
+//!
+//! [`StatsEngine`] is owned by the single ingest task and is the only
+//! writer. Every symbol has a cell holding an immutable [`MarketStats`]
+//! that is swapped atomically after each trade, and cells are spread over
+//! shards so adding a symbol only copies one shard's directory. Any number
+//! of [`StatsReader`]s poll stats concurrently without taking a lock or
+//! delaying the writer.
+
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, MarketStats, Trade};
+use arc_swap::ArcSwap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+const DEFAULT_SHARDS: usize = 16;
+
+type Cell = Arc<ArcSwap<MarketStats>>;
+
+/// Cells of the symbols hashed to one shard, replaced on new symbols
+type Shard = ArcSwap<HashMap<Symbol, Cell>>;
+
+/// Single-writer stats per symbol, published to [`StatsReader`]s
+pub struct StatsEngine {
+    shards: Arc<[Shard]>,
+    /// The writer's working copy of each symbol and its published cell
+    symbols: HashMap<Symbol, (MarketStats, Cell)>,
+    regular_only: bool,
+}
+
+impl Default for StatsEngine {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARDS)
+    }
+}
+
+impl StatsEngine {
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Shard::default()).collect(),
+            symbols: HashMap::new(),
+            regular_only: false,
+        }
+    }
+
+    /// Ignore trades with conditions set, as in
+    /// [`MarketStats::with_regular_only`]
+    pub fn with_regular_only(mut self) -> Self {
+        self.regular_only = true;
+        self
+    }
+
+    pub fn reader(&self) -> StatsReader {
+        StatsReader {
+            shards: Arc::clone(&self.shards),
+        }
+    }
+
+    pub fn on_message(&mut self, msg: &MarketDataMessage) {
+        match msg {
+            MarketDataMessage::Trade(trade) => self.on_trade(trade),
+            MarketDataMessage::TradeCorrection(_) | MarketDataMessage::TradeBust(_) => {
+                self.on_revision(msg)
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply a bust or correction to a symbol that has traded
+    fn on_revision(&mut self, msg: &MarketDataMessage) {
+        let Some((stats, cell)) = msg
+            .symbol()
+            .and_then(|symbol| self.symbols.get_mut(&symbol))
+        else {
+            return;
+        };
+        stats.update(msg);
+        cell.store(Arc::new(stats.clone()));
+    }
+
+    pub fn on_trade(&mut self, trade: &Trade) {
+        if !self.symbols.contains_key(&trade.symbol) {
+            let mut stats = MarketStats::new(trade.symbol);
+            if self.regular_only {
+                stats = stats.with_regular_only();
+            }
+            let cell = Arc::new(ArcSwap::from_pointee(stats.clone()));
+            let shard = &self.shards[shard_of(&trade.symbol, self.shards.len())];
+            let mut directory = HashMap::clone(&shard.load());
+            directory.insert(trade.symbol, Arc::clone(&cell));
+            shard.store(Arc::new(directory));
+            self.symbols.insert(trade.symbol, (stats, cell));
+        }
+        let (stats, cell) = self.symbols.get_mut(&trade.symbol).unwrap();
+        stats.update_with_trade(trade);
+        cell.store(Arc::new(stats.clone()));
+    }
+}
+
+impl Stage for StatsEngine {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.on_message(&msg);
+        out.push(msg);
+    }
+}
+
+/// Lock-free view of a [`StatsEngine`], cheap to clone across tasks
+#[derive(Clone)]
+pub struct StatsReader {
+    shards: Arc<[Shard]>,
+}
+
+impl StatsReader {
+    /// Latest stats of `symbol`, consistent as of one trade
+    pub fn get(&self, symbol: &str) -> Option<Arc<MarketStats>> {
+        let shard = &self.shards[shard_of(symbol, self.shards.len())];
+        shard.load().get(symbol).map(|cell| cell.load_full())
+    }
+
+    /// Stats of every symbol, in no particular order
+    pub fn all(&self) -> Vec<Arc<MarketStats>> {
+        self.shards
+            .iter()
+            .flat_map(|shard| {
+                shard
+                    .load()
+                    .values()
+                    .map(|cell| cell.load_full())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The `n` most traded symbols by notional volume, most traded first.
+    /// Ranking by quantity would favour cheap instruments.
+    pub fn top_by_notional(&self, n: usize) -> Vec<Arc<MarketStats>> {
+        let mut stats = self.all();
+        stats.sort_by(|a, b| {
+            b.notional_volume
+                .total_cmp(&a.notional_volume)
+                .then_with(|| a.symbol.cmp(&b.symbol))
+        });
+        stats.truncate(n);
+        stats
+    }
+}
+
+fn shard_of(symbol: &str, shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    symbol.hash(&mut hasher);
+    (hasher.finish() % shards as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ContractSpec;
+    use chrono::Utc;
+    use std::thread;
+
+    fn trade(symbol: &str, price: f64) -> Trade {
+        Trade::test(symbol, price)
+    }
+
+    #[test]
+    fn test_readers_see_published_stats() {
+        let mut engine = StatsEngine::new(4);
+        let reader = engine.reader();
+        assert!(reader.get("BTCUSD").is_none());
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let reader = reader.clone();
+                thread::spawn(move || {
+                    // Every snapshot is internally consistent
+                    for _ in 0..1000 {
+                        if let Some(stats) = reader.get("BTCUSD") {
+                            assert_eq!(stats.total_volume, stats.trade_count as f64);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for i in 0..1000 {
+            engine.on_trade(&trade("BTCUSD", 100.0 + (i % 10) as f64));
+        }
+        engine.on_trade(&trade("ETHUSD", 2000.0));
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        let stats = reader.get("BTCUSD").unwrap();
+        assert_eq!(
+            (stats.trade_count, stats.high, stats.low),
+            (1000, 109.0, 100.0)
+        );
+        assert_eq!(reader.all().len(), 2);
+    }
+
+    #[test]
+    fn test_top_by_notional_ranks_across_price_scales() {
+        let mut engine = StatsEngine::default();
+        for _ in 0..100 {
+            engine.on_trade(&trade("DOGEUSD", 0.1));
+        }
+        engine.on_trade(&trade("BTCUSD", 60_000.0));
+        let mut future = trade("ESH4", 5000.0);
+        future.contract = Some(ContractSpec::future(50.0, Utc::now()));
+        engine.on_trade(&future);
+
+        let top = engine.reader().top_by_notional(2);
+        let ranked: Vec<_> = top
+            .iter()
+            .map(|s| (s.symbol.as_str(), s.notional_volume))
+            .collect();
+        assert_eq!(ranked, [("ESH4", 250_000.0), ("BTCUSD", 60_000.0)]);
+    }
+}
@@ -0,0 +1,231 @@
+//! Trading strategies driven by live or replayed sources.
+//!
+//! A [`Strategy`] receives typed callbacks for trades, quotes, books, bars
+//! and timers between `on_start` and `on_stop`. A [`StrategyRunner`] drives
+//! it from any graph [`Source`]: a client subscription when live, or an
+//! [`IterSource`](crate::graph::IterSource) over a recording to backtest,
+//! so the same strategy runs unchanged in both. Scheduling, bars and event
+//! ordering are those of a [`Backtest`]: timers and bar closes fire before
+//! the first message at or past their due time. On event time, the default,
+//! the clock follows message times and timers wait for the next message; on
+//! the wall clock, messages are stamped on arrival and timers fire on time
+//! while the feed is quiet.
+
+use crate::backtest::{Backtest, BacktestContext, BacktestHandler, BacktestReport, TimerId};
+use crate::graph::Source;
+use crate::types::{Candle, MarketDataMessage, OrderBookSnapshot, Quote, Trade};
+use chrono::{Duration, Utc};
+
+/// Trading logic called by a [`StrategyRunner`]
+pub trait Strategy: Send {
+    fn on_start(&mut self, _ctx: &mut BacktestContext) {}
+
+    fn on_trade(&mut self, _ctx: &mut BacktestContext, _trade: &Trade) {}
+
+    fn on_quote(&mut self, _ctx: &mut BacktestContext, _quote: &Quote) {}
+
+    fn on_book(&mut self, _ctx: &mut BacktestContext, _book: &OrderBookSnapshot) {}
+
+    /// A bar closed (requires [`StrategyRunner::with_bars`])
+    fn on_bar(&mut self, _ctx: &mut BacktestContext, _bar: &Candle) {}
+
+    /// A timer scheduled through the context fired
+    fn on_timer(&mut self, _ctx: &mut BacktestContext, _timer: TimerId) {}
+
+    fn on_stop(&mut self, _ctx: &mut BacktestContext) {}
+}
+
+/// Routes backtest callbacks to the typed strategy hooks
+struct Dispatch<'a, S>(&'a mut S);
+
+impl<S: Strategy> BacktestHandler for Dispatch<'_, S> {
+    fn on_start(&mut self, ctx: &mut BacktestContext) {
+        self.0.on_start(ctx);
+    }
+
+    fn on_message(&mut self, ctx: &mut BacktestContext, msg: &MarketDataMessage) {
+        match msg {
+            MarketDataMessage::Trade(trade) => self.0.on_trade(ctx, trade),
+            MarketDataMessage::Quote(quote) => self.0.on_quote(ctx, quote),
+            MarketDataMessage::OrderBook(book) => self.0.on_book(ctx, book),
+            _ => {}
+        }
+    }
+
+    fn on_bar(&mut self, ctx: &mut BacktestContext, bar: &Candle) {
+        self.0.on_bar(ctx, bar);
+    }
+
+    fn on_interval(&mut self, ctx: &mut BacktestContext, timer: TimerId) {
+        self.0.on_timer(ctx, timer);
+    }
+
+    fn on_stop(&mut self, ctx: &mut BacktestContext) {
+        self.0.on_stop(ctx);
+    }
+}
+
+/// Drives a [`Strategy`] from a source until it is exhausted or the
+/// strategy stops
+pub struct StrategyRunner<S: Source> {
+    source: S,
+    backtest: Backtest,
+    wall_clock: bool,
+}
+
+impl<S: Source> StrategyRunner<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            backtest: Backtest::new(),
+            wall_clock: false,
+        }
+    }
+
+    /// Aggregate trades into bars of `interval`, delivered via `on_bar`
+    pub fn with_bars(mut self, interval: Duration) -> Self {
+        self.backtest = self.backtest.with_bars(interval);
+        self
+    }
+
+    /// Seed of [`BacktestContext::rng`] (default 0)
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.backtest = self.backtest.with_seed(seed);
+        self
+    }
+
+    /// Run on the system clock instead of message times, for live sources
+    pub fn with_wall_clock(mut self) -> Self {
+        self.wall_clock = true;
+        self
+    }
+
+    /// Run `strategy` over the source
+    pub async fn run<T: Strategy>(&mut self, strategy: &mut T) -> BacktestReport {
+        let mut handler = Dispatch(strategy);
+        let (mut ctx, mut report) = if self.wall_clock {
+            self.backtest.begin(Utc::now(), &mut handler)
+        } else {
+            let Some(msg) = self.source.next_message().await else {
+                return BacktestReport::default();
+            };
+            let ts = msg.timestamp().unwrap_or_else(Utc::now);
+            let (mut ctx, mut report) = self.backtest.begin(ts, &mut handler);
+            self.backtest
+                .step(&mut ctx, &mut handler, ts, &msg, &mut report);
+            (ctx, report)
+        };
+
+        while !ctx.is_stopped() {
+            let msg = match self.backtest.next_due(&ctx).filter(|_| self.wall_clock) {
+                Some(due) => {
+                    let wait = (due - Utc::now()).to_std().unwrap_or_default();
+                    tokio::select! {
+                        msg = self.source.next_message() => msg,
+                        _ = tokio::time::sleep(wait) => {
+                            self.backtest.advance(&mut ctx, &mut handler, Utc::now(), &mut report);
+                            continue;
+                        }
+                    }
+                }
+                None => self.source.next_message().await,
+            };
+            let Some(msg) = msg else {
+                break;
+            };
+            let ts = match msg.timestamp() {
+                Some(ts) if !self.wall_clock => ts,
+                _ => Utc::now().max(ctx.now()),
+            };
+            self.backtest
+                .step(&mut ctx, &mut handler, ts, &msg, &mut report);
+        }
+        self.backtest.finish(ctx, &mut handler, report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::IterSource;
+
+    use chrono::{DateTime, TimeZone};
+
+    #[derive(Default)]
+    struct Momentum {
+        log: Vec<String>,
+        last: Option<f64>,
+    }
+
+    impl Strategy for Momentum {
+        fn on_start(&mut self, ctx: &mut BacktestContext) {
+            ctx.schedule_interval(Duration::seconds(30)).unwrap();
+        }
+
+        fn on_trade(&mut self, ctx: &mut BacktestContext, trade: &Trade) {
+            if self.last.is_some_and(|last| trade.price > last) {
+                self.log.push(format!("buy@{}", ctx.now().timestamp()));
+            }
+            self.last = Some(trade.price);
+        }
+
+        fn on_quote(&mut self, ctx: &mut BacktestContext, _quote: &Quote) {
+            self.log.push(format!("quote@{}", ctx.now().timestamp()));
+        }
+
+        fn on_bar(&mut self, _ctx: &mut BacktestContext, bar: &Candle) {
+            self.log.push(format!("bar@{}", bar.end.timestamp()));
+        }
+
+        fn on_timer(&mut self, ctx: &mut BacktestContext, _timer: TimerId) {
+            self.log.push(format!("timer@{}", ctx.now().timestamp()));
+        }
+
+        fn on_stop(&mut self, _ctx: &mut BacktestContext) {
+            self.log.push("stop".to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_runner_drives_strategy_hooks() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let at = |secs: i64| t0 + Duration::seconds(secs);
+        let trade = |ts: DateTime<Utc>, price: f64| {
+            MarketDataMessage::Trade(Trade {
+                timestamp: ts,
+                ..Trade::test("BTCUSD", price)
+            })
+        };
+        let quote = MarketDataMessage::Quote(Quote {
+            timestamp: at(50),
+            ..Quote::test("BTCUSD", 100.0, 101.0)
+        });
+        let messages = vec![
+            trade(at(0), 100.0),
+            trade(at(40), 101.0),
+            quote,
+            trade(at(70), 99.0),
+        ];
+
+        let mut strategy = Momentum::default();
+        let report = StrategyRunner::new(IterSource(messages.into_iter()))
+            .with_bars(Duration::minutes(1))
+            .run(&mut strategy)
+            .await;
+
+        let base = t0.timestamp();
+        let expected: Vec<String> = [
+            ("timer", 30),
+            ("buy", 40),
+            ("quote", 50),
+            ("bar", 60),
+            ("timer", 60),
+        ]
+        .iter()
+        .map(|(kind, secs)| format!("{}@{}", kind, base + secs))
+        .chain(["stop".to_string()])
+        .collect();
+        assert_eq!(strategy.log, expected);
+        assert_eq!((report.messages, report.bars), (4, 1));
+    }
+}
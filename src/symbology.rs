@@ -0,0 +1,344 @@
+//! Interned identifiers for symbols, venues, instruments and currencies.
+//!
+//! [`Symbol`], [`Venue`], [`InstrumentId`] and [`Currency`] wrap an
+//! interned string: each distinct name is stored once for the life of the
+//! process, so the types are `Copy`, compare by pointer and dereference to
+//! `str` for free. They serialize as plain strings, so JSON, recordings and
+//! other wire formats are unchanged, and hash and order by their text, so
+//! maps keyed by them can be queried with a `&str`.
+//!
+//! Interned names are never freed. [`Symbol::new`] and its siblings are for
+//! names from feeds, files and other input: they check the name (non-empty,
+//! at most [`MAX_LEN`] bytes, no control characters and no surrounding
+//! whitespace) and refuse new names once [`max_interned`] are held, so a
+//! hostile or very broad feed cannot grow memory without bound.
+//! Deserialization goes through them too. The `From` conversions intern
+//! without either check, for configured instruments and literals only. For
+//! names that should never be added, such as client requests,
+//! [`Symbol::lookup`] finds a name already interned.
+
+use crate::client::{ClientError, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{LazyLock, RwLock};
+
+/// Longest accepted name in bytes
+pub const MAX_LEN: usize = 64;
+
+/// Distinct names held before checked interning refuses new ones
+pub const DEFAULT_MAX_INTERNED: usize = 100_000;
+
+static NAMES: LazyLock<RwLock<HashSet<&'static str>>> = LazyLock::new(Default::default);
+
+static MAX_INTERNED: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_INTERNED);
+
+/// Distinct names held before [`Symbol::new`] and its siblings refuse new
+/// ones
+pub fn max_interned() -> usize {
+    MAX_INTERNED.load(AtomicOrdering::Relaxed)
+}
+
+/// Raise or lower the limit on distinct interned names, e.g. for feeds with
+/// whole option chains; names already held stay
+pub fn set_max_interned(limit: usize) {
+    MAX_INTERNED.store(limit, AtomicOrdering::Relaxed);
+}
+
+fn intern(name: &str) -> &'static str {
+    try_intern(name, usize::MAX).expect("unlimited interning")
+}
+
+/// Intern `name` unless that would hold more than `limit` names
+fn try_intern(name: &str, limit: usize) -> Option<&'static str> {
+    if let Some(interned) = NAMES.read().unwrap().get(name) {
+        return Some(interned);
+    }
+    let mut names = NAMES.write().unwrap();
+    match names.get(name) {
+        Some(interned) => Some(interned),
+        None if names.len() >= limit => None,
+        None => {
+            let interned: &'static str = Box::leak(name.into());
+            names.insert(interned);
+            Some(interned)
+        }
+    }
+}
+
+fn check(kind: &str, name: &str) -> Result<()> {
+    let problem = if name.is_empty() {
+        "is empty"
+    } else if name.len() > MAX_LEN {
+        "is too long"
+    } else if name.chars().any(char::is_control) {
+        "contains control characters"
+    } else if name.trim() != name {
+        "has surrounding whitespace"
+    } else {
+        return Ok(());
+    };
+    Err(ClientError::Parse(format!(
+        "{} {:?} {}",
+        kind, name, problem
+    )))
+}
+
+macro_rules! interned {
+    ($(#[$doc:meta])* $name:ident, $kind:literal) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy)]
+        pub struct $name(&'static str);
+
+        impl $name {
+            /// Intern `name` after checking it, unless it is new and
+            /// [`max_interned`] names are already held
+            pub fn new(name: &str) -> Result<Self> {
+                check($kind, name)?;
+                try_intern(name, max_interned()).map(Self).ok_or_else(|| {
+                    ClientError::Parse(format!(
+                        "{} {:?} refused, {} names already interned",
+                        $kind,
+                        name,
+                        max_interned()
+                    ))
+                })
+            }
+
+            /// Check `name` and find it if already interned, without
+            /// interning it
+            pub fn lookup(name: &str) -> Result<Option<Self>> {
+                check($kind, name)?;
+                Ok(NAMES.read().unwrap().get(name).map(|interned| Self(interned)))
+            }
+
+            pub fn as_str(&self) -> &'static str {
+                self.0
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self(intern(""))
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                self.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                self.0
+            }
+        }
+
+        impl Borrow<str> for $name {
+            fn borrow(&self) -> &str {
+                self.0
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                std::ptr::eq(self.0, other.0)
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl PartialEq<String> for $name {
+            fn eq(&self, other: &String) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<$name> for str {
+            fn eq(&self, other: &$name) -> bool {
+                self == other.0
+            }
+        }
+
+        impl PartialEq<$name> for &str {
+            fn eq(&self, other: &$name) -> bool {
+                *self == other.0
+            }
+        }
+
+        impl PartialEq<$name> for String {
+            fn eq(&self, other: &$name) -> bool {
+                self == other.0
+            }
+        }
+
+        impl Hash for $name {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.0.hash(state)
+            }
+        }
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.cmp(other.0)
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(self.0, f)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.0)
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = ClientError;
+
+            fn from_str(name: &str) -> Result<Self> {
+                Self::new(name)
+            }
+        }
+
+        /// Interns without checking or limit, for configured names only
+        impl From<&str> for $name {
+            fn from(name: &str) -> Self {
+                Self(intern(name))
+            }
+        }
+
+        /// Interns without checking or limit, for configured names only
+        impl From<&String> for $name {
+            fn from(name: &String) -> Self {
+                Self(intern(name))
+            }
+        }
+
+        /// Interns without checking or limit, for configured names only
+        impl From<String> for $name {
+            fn from(name: String) -> Self {
+                Self(intern(&name))
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(name: $name) -> Self {
+                name.0.to_string()
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                serializer.serialize_str(self.0)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                let name = std::borrow::Cow::<'de, str>::deserialize(deserializer)?;
+                Self::new(&name).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+interned!(
+    /// Instrument name as a venue or the native feed spells it, e.g. `BTCUSD`
+    Symbol,
+    "symbol"
+);
+
+interned!(
+    /// Name of a venue or feed, e.g. `binance`
+    Venue,
+    "venue"
+);
+
+interned!(
+    /// Canonical instrument identifier shared across venues
+    InstrumentId,
+    "instrument id"
+);
+
+interned!(
+    /// Currency or asset code, e.g. `USD` or `USDT`
+    Currency,
+    "currency"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_interned_symbols_compare_and_serialize_as_strings() {
+        let a = Symbol::new("BTCUSD").unwrap();
+        let b = Symbol::from(String::from("BTCUSD"));
+        assert_eq!(a, b);
+        assert!(std::ptr::eq(a.as_str(), b.as_str()));
+        assert_eq!(a, "BTCUSD");
+        assert!(Symbol::new("ETHUSD").unwrap() > a);
+
+        let prices = HashMap::from([(a, 100.0)]);
+        assert_eq!(prices.get("BTCUSD"), Some(&100.0));
+
+        assert_eq!(serde_json::to_string(&a).unwrap(), "\"BTCUSD\"");
+        let parsed: Symbol = serde_json::from_str("\"BTCUSD\"").unwrap();
+        assert_eq!(parsed, a);
+        assert!(serde_json::from_str::<Symbol>("\"\"").is_err());
+        assert!(Symbol::new(" BTCUSD").is_err());
+        assert!(Venue::new("binance\n").is_err());
+
+        assert_eq!(Symbol::lookup("BTCUSD").unwrap(), Some(a));
+        assert_eq!(Symbol::lookup("NEVER-SEEN-1").unwrap(), None);
+        assert!(!NAMES.read().unwrap().contains("NEVER-SEEN-1"));
+        assert!(Symbol::lookup("").is_err());
+    }
+
+    #[test]
+    fn test_checked_interning_is_limited() {
+        let known = Symbol::new("LIMITED-KNOWN").unwrap();
+        // Names already held are found past the limit, new ones refused
+        assert_eq!(try_intern("LIMITED-KNOWN", 0), Some(known.as_str()));
+        assert_eq!(try_intern("LIMITED-NEW", 0), None);
+        assert!(!NAMES.read().unwrap().contains("LIMITED-NEW"));
+        assert_eq!(max_interned(), DEFAULT_MAX_INTERNED);
+    }
+}
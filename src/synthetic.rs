@@ -0,0 +1,523 @@
+//! Derived instruments computed from two or more underlying symbols.
+//!
+//! A [`SyntheticEngine`] watches the legs of each registered
+//! [`SyntheticInstrument`] and emits a synthetic [`Quote`] whenever a leg's
+//! top of book changes, and a synthetic [`Trade`] whenever a leg trades, once
+//! every leg has data. A [`BasketCalculator`] prices a weighted index of
+//! many constituents and tolerates missing or stale ones.
+
+use crate::client::{ClientError, Result};
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, Quote, Trade, TradeConditions, TradeSide};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How leg prices combine into the synthetic price
+#[derive(Debug, Clone, PartialEq)]
+pub enum Combination {
+    /// Sum of `weight * price` over the legs
+    Linear(Vec<f64>),
+    /// First leg divided by the second
+    Ratio,
+}
+
+/// A derived instrument such as a cross-venue spread or a price ratio
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticInstrument {
+    symbol: Symbol,
+    legs: Vec<Symbol>,
+    combination: Combination,
+}
+
+impl SyntheticInstrument {
+    /// Combine `legs`, which must be exactly two for a ratio and match the
+    /// weights of a linear combination
+    pub fn new(symbol: &str, legs: Vec<Symbol>, combination: Combination) -> Result<Self> {
+        let expected = match &combination {
+            Combination::Linear(weights) => weights.len().max(1),
+            Combination::Ratio => 2,
+        };
+        if legs.len() != expected {
+            return Err(ClientError::Parse(format!(
+                "synthetic {} needs {} legs, got {}",
+                symbol,
+                expected,
+                legs.len()
+            )));
+        }
+        Ok(Self {
+            symbol: symbol.into(),
+            legs,
+            combination,
+        })
+    }
+
+    /// `a - b`, e.g. the same pair on two venues
+    pub fn spread(symbol: &str, a: &str, b: &str) -> Self {
+        Self::linear(symbol, vec![(a.into(), 1.0), (b.into(), -1.0)])
+    }
+
+    /// `a / b`, e.g. ETHUSD / BTCUSD for an ETH/BTC cross
+    pub fn ratio(symbol: &str, a: &str, b: &str) -> Self {
+        Self {
+            symbol: symbol.into(),
+            legs: vec![a.into(), b.into()],
+            combination: Combination::Ratio,
+        }
+    }
+
+    /// Weighted sum of leg prices
+    pub fn linear(symbol: &str, legs: Vec<(Symbol, f64)>) -> Self {
+        let (legs, weights) = legs.into_iter().unzip();
+        Self {
+            symbol: symbol.into(),
+            legs,
+            combination: Combination::Linear(weights),
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn legs(&self) -> &[Symbol] {
+        &self.legs
+    }
+
+    pub fn combination(&self) -> &Combination {
+        &self.combination
+    }
+
+    /// Executable bid/ask and sizes from leg quotes (in leg order).
+    ///
+    /// Buying the synthetic means buying positively weighted legs at their
+    /// ask and selling negatively weighted legs at their bid. Sizes are in
+    /// units of the synthetic and limited by the thinnest leg.
+    pub fn quote(&self, legs: &[&Quote], timestamp: DateTime<Utc>) -> Quote {
+        let (bid_price, ask_price, bid_size, ask_size) = match &self.combination {
+            Combination::Linear(weights) => {
+                let mut bid = 0.0;
+                let mut ask = 0.0;
+                let mut bid_size = f64::INFINITY;
+                let mut ask_size = f64::INFINITY;
+                for (quote, weight) in legs.iter().zip(weights) {
+                    if *weight >= 0.0 {
+                        bid += weight * quote.bid_price;
+                        ask += weight * quote.ask_price;
+                        bid_size = bid_size.min(quote.bid_size / weight.abs());
+                        ask_size = ask_size.min(quote.ask_size / weight.abs());
+                    } else {
+                        bid += weight * quote.ask_price;
+                        ask += weight * quote.bid_price;
+                        bid_size = bid_size.min(quote.ask_size / weight.abs());
+                        ask_size = ask_size.min(quote.bid_size / weight.abs());
+                    }
+                }
+                (bid, ask, bid_size, ask_size)
+            }
+            Combination::Ratio => {
+                let (a, b) = (legs[0], legs[1]);
+                (
+                    a.bid_price / b.ask_price,
+                    a.ask_price / b.bid_price,
+                    a.bid_size,
+                    a.ask_size,
+                )
+            }
+        };
+
+        Quote {
+            symbol: self.symbol,
+            bid_price,
+            bid_size,
+            ask_price,
+            ask_size,
+            timestamp,
+            instrument_id: None,
+            contract: None,
+            received: None,
+        }
+    }
+
+    /// Synthetic price from leg prices (in leg order)
+    pub fn price(&self, legs: &[f64]) -> f64 {
+        match &self.combination {
+            Combination::Linear(weights) => legs.iter().zip(weights).map(|(p, w)| p * w).sum(),
+            Combination::Ratio => legs[0] / legs[1],
+        }
+    }
+
+    /// Whether a trade on `leg` moves the synthetic in the same direction
+    fn leg_is_long(&self, leg: usize) -> bool {
+        match &self.combination {
+            Combination::Linear(weights) => weights[leg] >= 0.0,
+            Combination::Ratio => leg == 0,
+        }
+    }
+}
+
+/// Computes synthetic instrument streams from leg updates
+#[derive(Debug, Default)]
+pub struct SyntheticEngine {
+    instruments: Vec<SyntheticInstrument>,
+    quotes: HashMap<Symbol, Quote>,
+    last_prices: HashMap<Symbol, f64>,
+}
+
+impl SyntheticEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, instrument: SyntheticInstrument) {
+        self.instruments.push(instrument);
+    }
+
+    /// Feed an underlying message, returning synthetic messages it produced
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<MarketDataMessage> {
+        match msg {
+            MarketDataMessage::Quote(quote) => {
+                self.quotes.insert(quote.symbol, quote.clone());
+                self.synthetic_quotes(&quote.symbol, quote.timestamp)
+            }
+            MarketDataMessage::OrderBook(book) => {
+                let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask()) else {
+                    return Vec::new();
+                };
+                let quote = Quote {
+                    symbol: book.symbol,
+                    bid_price: bid.price,
+                    bid_size: bid.size,
+                    ask_price: ask.price,
+                    ask_size: ask.size,
+                    timestamp: book.timestamp,
+                    instrument_id: None,
+                    contract: None,
+                    received: None,
+                };
+                self.quotes.insert(book.symbol, quote);
+                self.synthetic_quotes(&book.symbol, book.timestamp)
+            }
+            MarketDataMessage::Trade(trade) => {
+                self.last_prices.insert(trade.symbol, trade.price);
+                self.synthetic_trades(trade)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn synthetic_quotes(&self, leg: &str, timestamp: DateTime<Utc>) -> Vec<MarketDataMessage> {
+        self.instruments
+            .iter()
+            .filter(|instrument| instrument.legs.iter().any(|l| l == leg))
+            .filter_map(|instrument| {
+                let legs: Option<Vec<&Quote>> = instrument
+                    .legs
+                    .iter()
+                    .map(|leg| self.quotes.get(leg))
+                    .collect();
+                legs.map(|legs| MarketDataMessage::Quote(instrument.quote(&legs, timestamp)))
+            })
+            .collect()
+    }
+
+    fn synthetic_trades(&self, trade: &Trade) -> Vec<MarketDataMessage> {
+        self.instruments
+            .iter()
+            .filter_map(|instrument| {
+                let leg = instrument.legs.iter().position(|l| *l == trade.symbol)?;
+                let prices: Option<Vec<f64>> = instrument
+                    .legs
+                    .iter()
+                    .map(|leg| self.last_prices.get(leg).copied())
+                    .collect();
+                let side = if instrument.leg_is_long(leg) {
+                    trade.side
+                } else {
+                    trade.side.opposite()
+                };
+
+                Some(MarketDataMessage::Trade(Trade {
+                    symbol: instrument.symbol,
+                    price: instrument.price(&prices?),
+                    quantity: trade.quantity,
+                    side,
+                    timestamp: trade.timestamp,
+                    trade_id: format!("{}:{}", trade.symbol, trade.trade_id),
+                    conditions: TradeConditions::empty(),
+                    instrument_id: None,
+                    contract: None,
+                    received: None,
+                }))
+            })
+            .collect()
+    }
+}
+
+/// One index constituent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Constituent {
+    pub symbol: Symbol,
+    /// Units of the constituent in the basket
+    pub weight: f64,
+}
+
+/// What a basket does while constituents have stopped updating
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StalePolicy {
+    /// Keep pricing with the last known values
+    #[default]
+    Carry,
+    /// Emit nothing until every constituent is fresh again
+    Suppress,
+}
+
+/// Basket definition, e.g. loaded from JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BasketConfig {
+    pub symbol: Symbol,
+    pub constituents: Vec<Constituent>,
+    /// Index level is the weighted sum divided by this
+    #[serde(default = "default_divisor")]
+    pub divisor: f64,
+    /// Constituents without an update for this long are stale
+    #[serde(default)]
+    pub stale_after_ms: Option<u64>,
+    #[serde(default)]
+    pub stale_policy: StalePolicy,
+}
+
+fn default_divisor() -> f64 {
+    1.0
+}
+
+/// Prices a weighted index from its constituents' quotes and trades.
+///
+/// Quotes are executable like a [`SyntheticInstrument::linear`] combination
+/// scaled by the divisor; index prints use each constituent's last trade,
+/// or its mid before it has traded, and carry no quantity. Nothing is
+/// emitted until every constituent has been priced.
+#[derive(Debug, Clone)]
+pub struct BasketCalculator {
+    config: BasketConfig,
+    instrument: SyntheticInstrument,
+    quotes: HashMap<Symbol, Quote>,
+    last_prices: HashMap<Symbol, f64>,
+    updated: HashMap<Symbol, DateTime<Utc>>,
+    prints: u64,
+}
+
+impl BasketCalculator {
+    pub fn new(config: BasketConfig) -> Self {
+        let legs = config
+            .constituents
+            .iter()
+            .map(|c| (c.symbol, c.weight))
+            .collect();
+        Self {
+            instrument: SyntheticInstrument::linear(&config.symbol, legs),
+            config,
+            quotes: HashMap::new(),
+            last_prices: HashMap::new(),
+            updated: HashMap::new(),
+            prints: 0,
+        }
+    }
+
+    pub fn config(&self) -> &BasketConfig {
+        &self.config
+    }
+
+    /// Constituents that are unpriced, or stale as of `now`
+    pub fn stale(&self, now: DateTime<Utc>) -> Vec<&str> {
+        let max_age = self
+            .config
+            .stale_after_ms
+            .map(|ms| Duration::milliseconds(ms as i64));
+        self.config
+            .constituents
+            .iter()
+            .filter(|c| match (self.updated.get(&c.symbol), max_age) {
+                (None, _) => true,
+                (Some(&updated), Some(max_age)) => now - updated > max_age,
+                (Some(_), None) => false,
+            })
+            .map(|c| c.symbol.as_str())
+            .collect()
+    }
+
+    /// Feed a constituent message, returning the index messages it produced
+    pub fn on_message(&mut self, msg: &MarketDataMessage) -> Vec<MarketDataMessage> {
+        let (symbol, timestamp) = match msg {
+            MarketDataMessage::Quote(quote) => (&quote.symbol, quote.timestamp),
+            MarketDataMessage::Trade(trade) => (&trade.symbol, trade.timestamp),
+            _ => return Vec::new(),
+        };
+        if !self.instrument.legs.contains(symbol) {
+            return Vec::new();
+        }
+        self.updated.insert(*symbol, timestamp);
+        match msg {
+            MarketDataMessage::Quote(quote) => {
+                self.quotes.insert(quote.symbol, quote.clone());
+            }
+            MarketDataMessage::Trade(trade) => {
+                self.last_prices.insert(trade.symbol, trade.price);
+            }
+            _ => {}
+        }
+
+        let stale = self.stale(timestamp);
+        let unpriced = self
+            .instrument
+            .legs
+            .iter()
+            .any(|leg| !self.updated.contains_key(leg));
+        if unpriced || (self.config.stale_policy == StalePolicy::Suppress && !stale.is_empty()) {
+            return Vec::new();
+        }
+
+        match msg {
+            MarketDataMessage::Quote(_) => self.quote(timestamp).into_iter().collect(),
+            _ => self.print(timestamp).into_iter().collect(),
+        }
+    }
+
+    fn quote(&self, timestamp: DateTime<Utc>) -> Option<MarketDataMessage> {
+        let legs: Option<Vec<&Quote>> = self
+            .instrument
+            .legs
+            .iter()
+            .map(|leg| self.quotes.get(leg))
+            .collect();
+        let mut quote = self.instrument.quote(&legs?, timestamp);
+        quote.bid_price /= self.config.divisor;
+        quote.ask_price /= self.config.divisor;
+        Some(MarketDataMessage::Quote(quote))
+    }
+
+    fn print(&mut self, timestamp: DateTime<Utc>) -> Option<MarketDataMessage> {
+        let prices: Option<Vec<f64>> = self
+            .instrument
+            .legs
+            .iter()
+            .map(|leg| {
+                self.last_prices
+                    .get(leg)
+                    .copied()
+                    .or_else(|| self.quotes.get(leg).map(Quote::mid_price))
+            })
+            .collect();
+        let price = self.instrument.price(&prices?) / self.config.divisor;
+        self.prints += 1;
+        Some(MarketDataMessage::Trade(Trade {
+            symbol: self.config.symbol,
+            price,
+            quantity: 0.0,
+            side: TradeSide::Buy,
+            timestamp,
+            trade_id: self.prints.to_string(),
+            conditions: TradeConditions::empty(),
+            instrument_id: None,
+            contract: None,
+            received: None,
+        }))
+    }
+}
+
+impl Stage for BasketCalculator {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        let derived = self.on_message(&msg);
+        out.push(msg);
+        out.extend(derived);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(symbol: &str, bid: f64, ask: f64) -> MarketDataMessage {
+        MarketDataMessage::Quote(Quote {
+            bid_size: 2.0,
+            ..Quote::test(symbol, bid, ask)
+        })
+    }
+
+    #[test]
+    fn test_spread_and_ratio_quotes() {
+        let mut engine = SyntheticEngine::new();
+        engine.add(SyntheticInstrument::spread(
+            "BTC:A-B", "BTCUSD@a", "BTCUSD@b",
+        ));
+        engine.add(SyntheticInstrument::ratio("ETHBTC", "ETHUSD@a", "BTCUSD@a"));
+
+        assert!(engine
+            .on_message(&quote("BTCUSD@a", 100.0, 101.0))
+            .is_empty());
+        let out = engine.on_message(&quote("BTCUSD@b", 98.0, 99.0));
+        let MarketDataMessage::Quote(spread) = &out[0] else {
+            panic!("expected quote");
+        };
+        // Sell A at its bid and buy B at its ask
+        assert_eq!((spread.bid_price, spread.ask_price), (1.0, 3.0));
+        assert_eq!((spread.bid_size, spread.ask_size), (1.0, 1.0));
+
+        let out = engine.on_message(&quote("ETHUSD@a", 5.0, 5.05));
+        let MarketDataMessage::Quote(ratio) = &out[0] else {
+            panic!("expected quote");
+        };
+        assert_eq!(ratio.symbol, "ETHBTC");
+        assert!((ratio.bid_price - 5.0 / 101.0).abs() < 1e-12);
+
+        // A ratio of one leg is rejected rather than panicking on updates
+        assert!(SyntheticInstrument::new("X", vec!["A".into()], Combination::Ratio).is_err());
+        assert!(SyntheticInstrument::new(
+            "X",
+            vec!["A".into()],
+            Combination::Linear(vec![1.0, -1.0])
+        )
+        .is_err());
+        let ratio = SyntheticInstrument::new(
+            "ETHBTC",
+            vec!["ETHUSD@a".into(), "BTCUSD@a".into()],
+            Combination::Ratio,
+        )
+        .unwrap();
+        assert_eq!(ratio.price(&[5.0, 100.0]), 0.05);
+    }
+
+    #[test]
+    fn test_basket_waits_for_constituents_and_suppresses_stale() {
+        let config: BasketConfig = serde_json::from_str(
+            r#"{"symbol":"IDX","constituents":[{"symbol":"A","weight":2},{"symbol":"B","weight":1}],
+                "divisor":10,"stale_after_ms":1000,"stale_policy":"suppress"}"#,
+        )
+        .unwrap();
+        let mut basket = BasketCalculator::new(config);
+        let t0 = Utc::now();
+        let trade = |symbol: &str, price: f64, ms: i64| {
+            MarketDataMessage::Trade(Trade {
+                side: TradeSide::Sell,
+                timestamp: t0 + Duration::milliseconds(ms),
+                trade_id: "1".to_string(),
+                ..Trade::test(symbol, price)
+            })
+        };
+
+        assert!(basket.on_message(&trade("A", 100.0, 0)).is_empty());
+        assert_eq!(basket.stale(t0), ["B"]);
+        let out = basket.on_message(&trade("B", 50.0, 500));
+        let [MarketDataMessage::Trade(index)] = out.as_slice() else {
+            panic!("expected one index print");
+        };
+        assert_eq!((index.symbol.as_str(), index.price), ("IDX", 25.0));
+
+        // A last updated 1.5s ago
+        assert!(basket.on_message(&trade("B", 52.0, 1500)).is_empty());
+        assert_eq!(basket.on_message(&trade("A", 101.0, 1600)).len(), 1);
+    }
+}
@@ -0,0 +1,350 @@
+//! Per-sink, per-symbol rate caps on outbound messages.
+//!
+//! [`SinkThrottles`] configure, centrally, how many messages per second
+//! each named sink receives per symbol and channel, so one pipeline can
+//! feed consumers of very different fidelity: a Kafka sink every trade
+//! but ten quotes a second, a UI two updates a second. Channels are those
+//! of [`entitlements::channel`](crate::entitlements::channel); channels
+//! without a cap pass unthrottled, as do trade corrections, busts and
+//! heartbeats. Over the cap, trades are dropped while quotes and books are
+//! conflated: the latest per symbol is held back and sent once due, so the
+//! sink still converges on the current market even if the symbol goes
+//! quiet.
+//!
+//! Config is a JSON object keyed by sink, mapping channels to messages
+//! per second per symbol:
+//!
+//! ```json
+//! {
+//!   "kafka": { "quote": 10 },
+//!   "ui": { "trade": 2, "quote": 2, "book": 2 }
+//! }
+//! ```
+//!
+//! [`StreamGraph::with_throttles`](crate::StreamGraph::with_throttles)
+//! applies them to the graph's sinks by name, releasing held-back messages
+//! on a timer; a [`Throttle`] is also a pipeline [`Stage`] for use in front
+//! of other consumers, where they go out with the next message unless the
+//! owner calls [`release`](Throttle::release) at
+//! [`next_release`](Throttle::next_release).
+
+use crate::client::{ClientError, Result};
+use crate::entitlements::channel;
+use crate::graph::MessageSink;
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::MarketDataMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+const CHANNELS: [&str; 3] = ["trade", "quote", "book"];
+
+/// Rate caps keyed by sink name, then channel
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SinkThrottles {
+    sinks: HashMap<String, HashMap<String, f64>>,
+}
+
+impl SinkThrottles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cap `sink` at `per_second` messages of `channel` per symbol
+    pub fn with_rate(mut self, sink: &str, channel: &str, per_second: f64) -> Self {
+        self.sinks
+            .entry(sink.to_string())
+            .or_default()
+            .insert(channel.to_string(), per_second);
+        self
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let throttles: Self =
+            serde_json::from_str(json).map_err(|e| ClientError::Parse(e.to_string()))?;
+        throttles.validate()?;
+        Ok(throttles)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| ClientError::Io(format!("{}: {}", path.display(), e)))?;
+        Self::from_json(&json)
+    }
+
+    /// Check that every channel is known and every rate positive
+    pub fn validate(&self) -> Result<()> {
+        for (sink, rates) in &self.sinks {
+            for (channel, rate) in rates {
+                if !CHANNELS.contains(&channel.as_str()) {
+                    return Err(ClientError::Parse(format!(
+                        "throttle for {} has unknown channel {}",
+                        sink, channel
+                    )));
+                }
+                if !(*rate > 0.0 && rate.is_finite()) {
+                    return Err(ClientError::Parse(format!(
+                        "throttle for {} {} needs a positive rate, got {}",
+                        sink, channel, rate
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of the throttled sinks
+    pub fn sinks(&self) -> impl Iterator<Item = &str> {
+        self.sinks.keys().map(String::as_str)
+    }
+
+    /// A fresh throttle for `sink`, if it has caps
+    pub fn throttle(&self, sink: &str) -> Option<Throttle> {
+        let mut throttle = Throttle::new();
+        for (channel, rate) in self.sinks.get(sink)? {
+            throttle = throttle.with_rate(channel, *rate);
+        }
+        Some(throttle)
+    }
+}
+
+/// Rate caps for one consumer
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    intervals: HashMap<&'static str, Duration>,
+    sent: HashMap<(Symbol, &'static str), Instant>,
+    /// Latest quote or book per symbol held back until due
+    pending: HashMap<(Symbol, &'static str), MarketDataMessage>,
+    dropped: u64,
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pass at most `per_second` messages of `channel` per symbol; unknown
+    /// channels and rates that are not positive are ignored
+    pub fn with_rate(mut self, channel: &str, per_second: f64) -> Self {
+        if let Some(channel) = CHANNELS.iter().find(|known| **known == channel) {
+            if per_second > 0.0 && per_second.is_finite() {
+                self.intervals
+                    .insert(channel, Duration::from_secs_f64(1.0 / per_second));
+            }
+        }
+        self
+    }
+
+    /// Trades dropped and quotes or books superseded while held back
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Throttle `msg` at `now`, pushing it and any held-back messages now
+    /// due onto `out`
+    pub fn process_at(
+        &mut self,
+        msg: MarketDataMessage,
+        now: Instant,
+        out: &mut Vec<MarketDataMessage>,
+    ) {
+        self.release(now, out);
+        let channel = channel(&msg);
+        let throttled = matches!(
+            msg,
+            MarketDataMessage::Trade(_)
+                | MarketDataMessage::Quote(_)
+                | MarketDataMessage::OrderBook(_)
+        );
+        let (Some(symbol), Some(interval), true) =
+            (msg.symbol(), self.intervals.get(channel), throttled)
+        else {
+            out.push(msg);
+            return;
+        };
+        let key = (symbol, channel);
+        let due = self
+            .sent
+            .get(&key)
+            .is_none_or(|at| now.saturating_duration_since(*at) >= *interval);
+        if due {
+            self.sent.insert(key, now);
+            self.dropped += u64::from(self.pending.remove(&key).is_some());
+            out.push(msg);
+        } else if channel == "trade" || self.pending.insert(key, msg).is_some() {
+            self.dropped += 1;
+        }
+    }
+
+    /// Push every held-back message, regardless of rate
+    pub fn flush(&mut self, out: &mut Vec<MarketDataMessage>) {
+        out.extend(self.pending.drain().map(|(_, msg)| msg));
+    }
+
+    /// When the earliest held-back message is due
+    pub fn next_release(&self) -> Option<Instant> {
+        self.pending
+            .keys()
+            .map(|key| match self.sent.get(key) {
+                Some(at) => *at + self.intervals[key.1],
+                None => Instant::now(),
+            })
+            .min()
+    }
+
+    /// Push the held-back messages due at `now`
+    pub fn release(&mut self, now: Instant, out: &mut Vec<MarketDataMessage>) {
+        let intervals = &self.intervals;
+        let sent = &mut self.sent;
+        self.pending.retain(|key, msg| {
+            let due = sent
+                .get(key)
+                .is_none_or(|at| now.saturating_duration_since(*at) >= intervals[key.1]);
+            if due {
+                sent.insert(*key, now);
+                out.push(msg.clone());
+            }
+            !due
+        });
+    }
+}
+
+impl Stage for Throttle {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        self.process_at(msg, Instant::now(), out);
+    }
+}
+
+/// A graph sink behind a throttle
+pub(crate) struct ThrottledSink {
+    pub(crate) sink: Box<dyn MessageSink>,
+    pub(crate) throttle: Throttle,
+}
+
+impl ThrottledSink {
+    /// Consume `rx`, releasing held-back messages as they fall due
+    pub(crate) async fn run(mut self, mut rx: mpsc::Receiver<MarketDataMessage>) {
+        loop {
+            let due = self.throttle.next_release();
+            let wake = tokio::time::Instant::from_std(due.unwrap_or_else(Instant::now));
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(msg) => self.consume(msg),
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(wake), if due.is_some() => {
+                    let mut out = Vec::new();
+                    self.throttle.release(Instant::now(), &mut out);
+                    for msg in out {
+                        self.sink.consume(msg);
+                    }
+                }
+            }
+        }
+        self.close();
+    }
+}
+
+impl MessageSink for ThrottledSink {
+    fn consume(&mut self, msg: MarketDataMessage) {
+        let mut out = Vec::new();
+        self.throttle.process(msg, &mut out);
+        for msg in out {
+            self.sink.consume(msg);
+        }
+    }
+
+    fn close(&mut self) {
+        let mut out = Vec::new();
+        self.throttle.flush(&mut out);
+        for msg in out {
+            self.sink.consume(msg);
+        }
+        self.sink.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Quote, Trade};
+
+    #[test]
+    fn test_caps_per_sink_and_symbol() {
+        let throttles =
+            SinkThrottles::from_json(r#"{"kafka": {"quote": 10}, "ui": {"trade": 2}}"#).unwrap();
+        assert!(SinkThrottles::from_json(r#"{"ui": {"bars": 2}}"#).is_err());
+        assert!(SinkThrottles::from_json(r#"{"ui": {"trade": 0}}"#).is_err());
+        assert!(throttles.throttle("audit").is_none());
+
+        let quote =
+            |symbol: &str, bid: f64| MarketDataMessage::Quote(Quote::test(symbol, bid, bid + 1.0));
+        let trade = MarketDataMessage::Trade(Trade::test("BTCUSD", 100.0));
+        let bids = |out: &[MarketDataMessage]| -> Vec<f64> {
+            out.iter()
+                .filter_map(|msg| match msg {
+                    MarketDataMessage::Quote(quote) => Some(quote.bid_price),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let mut kafka = throttles.throttle("kafka").unwrap();
+        let t0 = Instant::now();
+        let at = |millis: u64| t0 + Duration::from_millis(millis);
+        let mut out = Vec::new();
+        for (millis, bid) in [(0, 1.0), (20, 2.0), (40, 3.0)] {
+            kafka.process_at(quote("BTCUSD", bid), at(millis), &mut out);
+            kafka.process_at(trade.clone(), at(millis), &mut out);
+        }
+        // Other symbols have their own budget
+        kafka.process_at(quote("ETHUSD", 9.0), at(50), &mut out);
+        assert_eq!(bids(&out), vec![1.0, 9.0]);
+        assert_eq!(out.len(), 5);
+        assert_eq!(kafka.dropped(), 1);
+
+        // The latest held-back quote goes out once due
+        out.clear();
+        kafka.process_at(MarketDataMessage::Heartbeat, at(100), &mut out);
+        assert_eq!(bids(&out), vec![3.0]);
+
+        let mut ui = throttles.throttle("ui").unwrap();
+        out.clear();
+        for millis in [0, 100, 600] {
+            ui.process_at(trade.clone(), at(millis), &mut out);
+        }
+        ui.flush(&mut out);
+        assert_eq!((out.len(), ui.dropped()), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_sink_releases_held_back_on_timer() {
+        let received = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = {
+            let received = received.clone();
+            move |msg: MarketDataMessage| received.lock().unwrap().push(msg)
+        };
+        let throttle = Throttle::new().with_rate("quote", 20.0);
+        let (tx, rx) = mpsc::channel(8);
+        let task = tokio::spawn(
+            ThrottledSink {
+                sink: Box::new(sink),
+                throttle,
+            }
+            .run(rx),
+        );
+        let quote = MarketDataMessage::Quote(Quote::test("BTCUSD", 1.0, 2.0));
+        tx.send(quote.clone()).await.unwrap();
+        tx.send(quote).await.unwrap();
+        // The symbol goes quiet, yet the held-back quote still goes out
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(received.lock().unwrap().len(), 2);
+        drop(tx);
+        task.await.unwrap();
+    }
+}
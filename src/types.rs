@@ -1,42 +1,303 @@
+use crate::clock::TimestampPrecision;
+use crate::symbology::{Currency, InstrumentId, Symbol};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 /// Market data message types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
+#[non_exhaustive]
 pub enum MarketDataMessage {
     Trade(Trade),
     Quote(Quote),
     OrderBook(OrderBookSnapshot),
     Heartbeat,
+    TradeCorrection(TradeCorrection),
+    TradeBust(TradeBust),
+}
+
+/// Which kind of [`MarketDataMessage`] a message is, without its payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum MessageKind {
+    Trade,
+    Quote,
+    OrderBook,
+    Heartbeat,
+    TradeCorrection,
+    TradeBust,
+}
+
+impl MarketDataMessage {
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            MarketDataMessage::Trade(_) => MessageKind::Trade,
+            MarketDataMessage::Quote(_) => MessageKind::Quote,
+            MarketDataMessage::OrderBook(_) => MessageKind::OrderBook,
+            MarketDataMessage::Heartbeat => MessageKind::Heartbeat,
+            MarketDataMessage::TradeCorrection(_) => MessageKind::TradeCorrection,
+            MarketDataMessage::TradeBust(_) => MessageKind::TradeBust,
+        }
+    }
+
+    /// Symbol the message is about, `None` for heartbeats
+    pub fn symbol(&self) -> Option<Symbol> {
+        match self {
+            MarketDataMessage::Trade(trade) => Some(trade.symbol),
+            MarketDataMessage::Quote(quote) => Some(quote.symbol),
+            MarketDataMessage::OrderBook(book) => Some(book.symbol),
+            MarketDataMessage::Heartbeat => None,
+            MarketDataMessage::TradeCorrection(correction) => Some(correction.symbol),
+            MarketDataMessage::TradeBust(bust) => Some(bust.symbol),
+        }
+    }
+
+    /// Contract terms of the instrument, when tagged with them
+    pub fn contract(&self) -> Option<ContractSpec> {
+        match self {
+            MarketDataMessage::Trade(trade) => trade.contract,
+            MarketDataMessage::Quote(quote) => quote.contract,
+            MarketDataMessage::OrderBook(book) => book.contract,
+            MarketDataMessage::Heartbeat => None,
+            MarketDataMessage::TradeCorrection(correction) => correction.contract,
+            MarketDataMessage::TradeBust(bust) => bust.contract,
+        }
+    }
+
+    /// Exchange timestamp carried by the message, if any
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            MarketDataMessage::Trade(trade) => Some(trade.timestamp),
+            MarketDataMessage::Quote(quote) => Some(quote.timestamp),
+            MarketDataMessage::OrderBook(book) => Some(book.timestamp),
+            MarketDataMessage::Heartbeat => None,
+            MarketDataMessage::TradeCorrection(correction) => Some(correction.timestamp),
+            MarketDataMessage::TradeBust(bust) => Some(bust.timestamp),
+        }
+    }
+
+    /// When the client received the message, if it was stamped
+    pub fn received(&self) -> Option<DateTime<Utc>> {
+        match self {
+            MarketDataMessage::Trade(trade) => trade.received,
+            MarketDataMessage::Quote(quote) => quote.received,
+            MarketDataMessage::OrderBook(book) => book.received,
+            MarketDataMessage::Heartbeat => None,
+            MarketDataMessage::TradeCorrection(correction) => correction.received,
+            MarketDataMessage::TradeBust(bust) => bust.received,
+        }
+    }
+
+    pub fn set_received(&mut self, at: DateTime<Utc>) {
+        match self {
+            MarketDataMessage::Trade(trade) => trade.received = Some(at),
+            MarketDataMessage::Quote(quote) => quote.received = Some(at),
+            MarketDataMessage::OrderBook(book) => book.received = Some(at),
+            MarketDataMessage::Heartbeat => {}
+            MarketDataMessage::TradeCorrection(correction) => correction.received = Some(at),
+            MarketDataMessage::TradeBust(bust) => bust.received = Some(at),
+        }
+    }
+
+    /// Drop timestamp digits finer than `precision`
+    pub fn truncate_timestamps(&mut self, precision: TimestampPrecision) {
+        let (timestamp, received) = match self {
+            MarketDataMessage::Trade(trade) => (&mut trade.timestamp, &mut trade.received),
+            MarketDataMessage::Quote(quote) => (&mut quote.timestamp, &mut quote.received),
+            MarketDataMessage::OrderBook(book) => (&mut book.timestamp, &mut book.received),
+            MarketDataMessage::Heartbeat => return,
+            MarketDataMessage::TradeCorrection(correction) => {
+                (&mut correction.timestamp, &mut correction.received)
+            }
+            MarketDataMessage::TradeBust(bust) => (&mut bust.timestamp, &mut bust.received),
+        };
+        *timestamp = precision.truncate(*timestamp);
+        if let Some(received) = received {
+            *received = precision.truncate(*received);
+        }
+    }
 }
 
 /// Trade tick
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trade {
-    pub symbol: String,
+    pub symbol: Symbol,
     pub price: f64,
     pub quantity: f64,
     pub side: TradeSide,
     pub timestamp: DateTime<Utc>,
     pub trade_id: String,
+    /// Venue conditions normalized to a bitset, empty for regular trades
+    #[serde(default)]
+    pub conditions: TradeConditions,
+    /// Canonical instrument, set by an [`InstrumentTagger`](crate::instruments::InstrumentTagger)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instrument_id: Option<InstrumentId>,
+    /// Contract terms of derivatives, set by an [`InstrumentTagger`](crate::instruments::InstrumentTagger)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<ContractSpec>,
+    /// When the client received it, by its [`ClockSource`](crate::clock::ClockSource)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received: Option<DateTime<Utc>>,
+}
+
+/// Price, quantity and conditions of a trade
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TradeTerms {
+    pub price: f64,
+    pub quantity: f64,
+    /// Conditions as the venue reports them, empty when regular or not
+    /// repeated
+    #[serde(default)]
+    pub conditions: TradeConditions,
+}
+
+/// A venue's amendment of an earlier trade's price or quantity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeCorrection {
+    pub symbol: Symbol,
+    /// Id of the corrected trade
+    pub trade_id: String,
+    /// Time of the corrected trade, or of the correction when the venue
+    /// does not repeat it
+    pub timestamp: DateTime<Utc>,
+    /// Terms as first reported, when the venue repeats them
+    pub original: Option<TradeTerms>,
+    pub corrected: TradeTerms,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instrument_id: Option<InstrumentId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<ContractSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received: Option<DateTime<Utc>>,
+}
+
+/// A venue's cancellation of an earlier trade, which should be treated as
+/// never having happened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeBust {
+    pub symbol: Symbol,
+    /// Id of the busted trade
+    pub trade_id: String,
+    /// Time of the busted trade, or of the bust when the venue does not
+    /// repeat it
+    pub timestamp: DateTime<Utc>,
+    /// Terms as first reported, when the venue repeats them
+    pub original: Option<TradeTerms>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instrument_id: Option<InstrumentId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<ContractSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum TradeSide {
     Buy,
     Sell,
+    /// The venue did not report the aggressor
+    Unknown,
+}
+
+impl TradeSide {
+    /// Lower-case name: `buy`, `sell` or `unknown`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TradeSide::Buy => "buy",
+            TradeSide::Sell => "sell",
+            TradeSide::Unknown => "unknown",
+        }
+    }
+
+    /// The counterparty's side; an unknown side stays unknown
+    pub fn opposite(self) -> Self {
+        match self {
+            TradeSide::Buy => TradeSide::Sell,
+            TradeSide::Sell => TradeSide::Buy,
+            TradeSide::Unknown => TradeSide::Unknown,
+        }
+    }
+}
+
+/// Normalized trade conditions as a bitset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TradeConditions(u8);
+
+impl TradeConditions {
+    /// Negotiated block trade
+    pub const BLOCK: Self = Self(1);
+    /// Opening, closing or other auction print
+    pub const AUCTION: Self = Self(1 << 1);
+    /// Reported away from the order book, e.g. average or derivatively priced
+    pub const OFF_BOOK: Self = Self(1 << 2);
+    /// Forced liquidation of a position
+    pub const LIQUIDATION: Self = Self(1 << 3);
+    /// Failed validation but passed on by a lenient
+    /// [`Validator`](crate::validation::Validator)
+    pub const SUSPECT: Self = Self(1 << 4);
+    /// `side` was inferred, not reported by the venue; informational only
+    pub const INFERRED_SIDE: Self = Self(1 << 5);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// No conditions set; only regular trades set last price, VWAP and OHLC
+    /// when non-regular ones are excluded
+    pub const fn is_regular(self) -> bool {
+        self.0 & !Self::INFERRED_SIDE.0 == 0
+    }
+}
+
+impl std::ops::BitOr for TradeConditions {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
 }
 
 /// Quote (BBO - Best Bid/Offer)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Quote {
-    pub symbol: String,
+    pub symbol: Symbol,
     pub bid_price: f64,
     pub bid_size: f64,
     pub ask_price: f64,
     pub ask_size: f64,
     pub timestamp: DateTime<Utc>,
+    /// Canonical instrument, set by an [`InstrumentTagger`](crate::instruments::InstrumentTagger)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instrument_id: Option<InstrumentId>,
+    /// Contract terms of derivatives, set by an [`InstrumentTagger`](crate::instruments::InstrumentTagger)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<ContractSpec>,
+    /// When the client received it, by its [`ClockSource`](crate::clock::ClockSource)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received: Option<DateTime<Utc>>,
+}
+
+impl Trade {
+    /// Value traded in the quote or settlement currency: price times
+    /// quantity times the contract multiplier
+    pub fn notional(&self) -> f64 {
+        self.price * self.quantity * self.contract.map_or(1.0, |c| c.multiplier)
+    }
 }
 
 impl Quote {
@@ -49,8 +310,108 @@ impl Quote {
     }
 }
 
+#[cfg(test)]
+impl Trade {
+    /// A regular buy of one unit of `symbol` at `price`, now, as the base
+    /// of test trades
+    pub(crate) fn test(symbol: impl Into<Symbol>, price: f64) -> Self {
+        Self {
+            symbol: symbol.into(),
+            price,
+            quantity: 1.0,
+            side: TradeSide::Buy,
+            timestamp: Utc::now(),
+            trade_id: String::new(),
+            conditions: TradeConditions::empty(),
+            instrument_id: None,
+            contract: None,
+            received: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Quote {
+    /// A quote of one unit a side for `symbol`, now, as the base of test
+    /// quotes
+    pub(crate) fn test(symbol: impl Into<Symbol>, bid_price: f64, ask_price: f64) -> Self {
+        Self {
+            symbol: symbol.into(),
+            bid_price,
+            bid_size: 1.0,
+            ask_price,
+            ask_size: 1.0,
+            timestamp: Utc::now(),
+            instrument_id: None,
+            contract: None,
+            received: None,
+        }
+    }
+}
+
+/// Terms of a futures, perpetual swap or other derivatives contract
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ContractSpec {
+    /// Units of the underlying per contract; quantities count contracts
+    pub multiplier: f64,
+    /// Last trading day of a dated contract
+    #[serde(default)]
+    pub expiry: Option<DateTime<Utc>>,
+    /// Currency profits and losses settle in, if not the quote currency
+    #[serde(default)]
+    pub settlement_currency: Option<Currency>,
+    #[serde(default)]
+    pub perpetual: bool,
+}
+
+impl Default for ContractSpec {
+    fn default() -> Self {
+        Self {
+            multiplier: 1.0,
+            expiry: None,
+            settlement_currency: None,
+            perpetual: false,
+        }
+    }
+}
+
+impl ContractSpec {
+    /// Dated future with `multiplier` units per contract
+    pub fn future(multiplier: f64, expiry: DateTime<Utc>) -> Self {
+        Self {
+            multiplier,
+            expiry: Some(expiry),
+            ..Self::default()
+        }
+    }
+
+    /// Perpetual swap with `multiplier` units per contract
+    pub fn perpetual(multiplier: f64) -> Self {
+        Self {
+            multiplier,
+            perpetual: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_settlement_currency(mut self, currency: &str) -> Self {
+        self.settlement_currency = Some(currency.into());
+        self
+    }
+
+    /// Value of `quantity` contracts at `price`
+    pub fn notional(&self, price: f64, quantity: f64) -> f64 {
+        price * quantity * self.multiplier
+    }
+
+    /// Whether a dated contract has expired by `at`
+    pub fn is_expired(&self, at: DateTime<Utc>) -> bool {
+        self.expiry.is_some_and(|expiry| expiry <= at)
+    }
+}
+
 /// Order book level
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PriceLevel {
     pub price: f64,
     pub size: f64,
@@ -58,12 +419,21 @@ pub struct PriceLevel {
 }
 
 /// Full order book snapshot
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct OrderBookSnapshot {
-    pub symbol: String,
+    pub symbol: Symbol,
     pub bids: Vec<PriceLevel>,
     pub asks: Vec<PriceLevel>,
     pub timestamp: DateTime<Utc>,
+    /// Canonical instrument, set by an [`InstrumentTagger`](crate::instruments::InstrumentTagger)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instrument_id: Option<InstrumentId>,
+    /// Contract terms of derivatives, set by an [`InstrumentTagger`](crate::instruments::InstrumentTagger)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<ContractSpec>,
+    /// When the client received it, by its [`ClockSource`](crate::clock::ClockSource)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received: Option<DateTime<Utc>>,
 }
 
 impl OrderBookSnapshot {
@@ -98,36 +468,121 @@ impl OrderBookSnapshot {
     }
 }
 
+/// OHLCV bar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    pub symbol: Symbol,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    /// Traded value in the quote currency: price times quantity times the
+    /// contract multiplier
+    #[serde(default)]
+    pub notional: f64,
+    pub trade_count: u64,
+    /// What closes the bar
+    #[serde(default)]
+    pub bar_kind: BarKind,
+    /// A bust or correction amended a trade in the bar; the high and low
+    /// may still reflect the original
+    #[serde(default)]
+    pub revised: bool,
+}
+
+/// Bar construction methods
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BarKind {
+    /// Fixed time interval
+    #[default]
+    Time,
+    /// Number of trades
+    Tick,
+    /// Traded quantity
+    Volume,
+    /// Traded notional
+    Dollar,
+    /// Fixed-size price bricks
+    Renko,
+    /// Fixed high-low range
+    Range,
+}
+
+/// Aggressor volume traded at one price within a footprint bar
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FootprintLevel {
+    pub price: f64,
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+}
+
+/// OHLCV bar with its volume broken down by price and aggressor side
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FootprintCandle {
+    pub candle: Candle,
+    /// Levels in ascending price order
+    pub levels: Vec<FootprintLevel>,
+    /// Buy minus sell aggressor volume in this bar
+    pub delta: f64,
+    /// Running delta of the symbol including this bar
+    pub cumulative_delta: f64,
+}
+
 /// Market statistics
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MarketStats {
-    pub symbol: String,
+    pub symbol: Symbol,
     pub trade_count: u64,
     pub total_volume: f64,
+    /// Traded value in the quote currency, comparable across symbols
+    #[serde(default)]
+    pub notional_volume: f64,
     pub vwap: f64,
     pub high: f64,
     pub low: f64,
     pub last_price: f64,
     pub last_update: Option<DateTime<Utc>>,
+    /// Busts and corrections applied
+    #[serde(default)]
+    pub revisions: u64,
+    #[serde(skip)]
+    regular_only: bool,
 }
 
 impl MarketStats {
-    pub fn new(symbol: String) -> Self {
+    pub fn new(symbol: impl Into<Symbol>) -> Self {
         Self {
-            symbol,
+            symbol: symbol.into(),
             trade_count: 0,
             total_volume: 0.0,
+            notional_volume: 0.0,
             vwap: 0.0,
             high: f64::MIN,
             low: f64::MAX,
             last_price: 0.0,
             last_update: None,
+            revisions: 0,
+            regular_only: false,
         }
     }
 
+    /// Ignore trades with [`TradeConditions`] set, such as block or
+    /// auction prints
+    pub fn with_regular_only(mut self) -> Self {
+        self.regular_only = true;
+        self
+    }
+
     pub fn update_with_trade(&mut self, trade: &Trade) {
+        if self.regular_only && !trade.conditions.is_regular() {
+            return;
+        }
         self.trade_count += 1;
         self.total_volume += trade.quantity;
+        self.notional_volume += trade.notional();
         
         // Update VWAP
         let prev_total = self.vwap * (self.total_volume - trade.quantity);
@@ -145,4 +600,184 @@ impl MarketStats {
         self.last_price = trade.price;
         self.last_update = Some(trade.timestamp);
     }
+
+    /// Update from a trade, bust or correction; other messages are ignored
+    pub fn update(&mut self, msg: &MarketDataMessage) {
+        match msg {
+            MarketDataMessage::Trade(trade) => self.update_with_trade(trade),
+            MarketDataMessage::TradeCorrection(correction) => self.apply_correction(correction),
+            MarketDataMessage::TradeBust(bust) => self.apply_bust(bust),
+            _ => {}
+        }
+    }
+
+    /// Take a busted trade back out of the totals. Busts without the
+    /// original terms are only counted, and the high and low are never
+    /// recomputed.
+    pub fn apply_bust(&mut self, bust: &TradeBust) {
+        self.revise(bust.original, None, bust.contract);
+    }
+
+    /// Replace a trade's original terms with the corrected ones, as far as
+    /// known; see [`apply_bust`](Self::apply_bust)
+    pub fn apply_correction(&mut self, correction: &TradeCorrection) {
+        self.revise(
+            correction.original,
+            Some(correction.corrected),
+            correction.contract,
+        );
+    }
+
+    /// Whether trades on `terms` count towards the totals
+    fn counts(&self, terms: &TradeTerms) -> bool {
+        !self.regular_only || terms.conditions.is_regular()
+    }
+
+    fn revise(
+        &mut self,
+        original: Option<TradeTerms>,
+        corrected: Option<TradeTerms>,
+        contract: Option<ContractSpec>,
+    ) {
+        self.revisions += 1;
+        let Some(original) = original else {
+            return;
+        };
+        // Trades the totals never counted have nothing to take back, and
+        // corrections to excluded conditions only take back
+        let original = Some(original).filter(|terms| self.counts(terms));
+        let corrected = corrected.filter(|terms| self.counts(terms));
+        let multiplier = contract.map_or(1.0, |contract| contract.multiplier);
+        let mut value = self.vwap * self.total_volume;
+        if let Some(original) = original {
+            value -= original.price * original.quantity;
+            self.trade_count = self.trade_count.saturating_sub(1);
+            self.total_volume -= original.quantity;
+            self.notional_volume -= original.price * original.quantity * multiplier;
+        }
+        if let Some(corrected) = corrected {
+            value += corrected.price * corrected.quantity;
+            self.trade_count += 1;
+            self.total_volume += corrected.quantity;
+            self.notional_volume += corrected.price * corrected.quantity * multiplier;
+            self.high = self.high.max(corrected.price);
+            self.low = self.low.min(corrected.price);
+        }
+        self.vwap = if self.total_volume > 0.0 {
+            value / self.total_volume
+        } else {
+            0.0
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_apply_busts_and_corrections() {
+        let trade = |id: &str, price: f64| Trade {
+            quantity: 10.0,
+            trade_id: id.to_string(),
+            ..Trade::test("AAPL", price)
+        };
+        let mut stats = MarketStats::new("AAPL");
+        stats.update_with_trade(&trade("1", 100.0));
+        stats.update_with_trade(&trade("2", 110.0));
+
+        stats.update(&MarketDataMessage::TradeBust(TradeBust {
+            symbol: "AAPL".into(),
+            trade_id: "2".to_string(),
+            timestamp: Utc::now(),
+            original: Some(TradeTerms {
+                price: 110.0,
+                quantity: 10.0,
+                conditions: TradeConditions::empty(),
+            }),
+            instrument_id: None,
+            contract: None,
+            received: None,
+        }));
+        assert_eq!((stats.trade_count, stats.total_volume), (1, 10.0));
+        assert!((stats.vwap - 100.0).abs() < 1e-9);
+
+        stats.update(&MarketDataMessage::TradeCorrection(TradeCorrection {
+            symbol: "AAPL".into(),
+            trade_id: "1".to_string(),
+            timestamp: Utc::now(),
+            original: Some(TradeTerms {
+                price: 100.0,
+                quantity: 10.0,
+                conditions: TradeConditions::empty(),
+            }),
+            corrected: TradeTerms {
+                price: 102.0,
+                quantity: 20.0,
+                conditions: TradeConditions::empty(),
+            },
+            instrument_id: None,
+            contract: None,
+            received: None,
+        }));
+        assert_eq!((stats.trade_count, stats.total_volume), (1, 20.0));
+        assert!((stats.vwap - 102.0).abs() < 1e-9);
+        assert_eq!((stats.notional_volume, stats.revisions), (2040.0, 2));
+    }
+
+    #[test]
+    fn test_regular_only_stats_skip_revisions_of_excluded_trades() {
+        let terms = |price, conditions| TradeTerms {
+            price,
+            quantity: 10.0,
+            conditions,
+        };
+        let mut stats = MarketStats::new("AAPL").with_regular_only();
+        stats.update_with_trade(&Trade {
+            quantity: 10.0,
+            ..Trade::test("AAPL", 100.0)
+        });
+        stats.update_with_trade(&Trade {
+            quantity: 10.0,
+            conditions: TradeConditions::OFF_BOOK,
+            ..Trade::test("AAPL", 150.0)
+        });
+        assert_eq!((stats.trade_count, stats.total_volume), (1, 10.0));
+
+        // The off-book print was never counted, so its bust changes nothing
+        stats.apply_bust(&TradeBust {
+            symbol: "AAPL".into(),
+            trade_id: "2".to_string(),
+            timestamp: Utc::now(),
+            original: Some(terms(150.0, TradeConditions::OFF_BOOK)),
+            instrument_id: None,
+            contract: None,
+            received: None,
+        });
+        assert_eq!((stats.trade_count, stats.total_volume), (1, 10.0));
+        assert!((stats.vwap - 100.0).abs() < 1e-9);
+
+        // Corrected into an excluded condition, the regular trade drops out
+        stats.apply_correction(&TradeCorrection {
+            symbol: "AAPL".into(),
+            trade_id: "1".to_string(),
+            timestamp: Utc::now(),
+            original: Some(terms(100.0, TradeConditions::empty())),
+            corrected: terms(101.0, TradeConditions::OFF_BOOK),
+            instrument_id: None,
+            contract: None,
+            received: None,
+        });
+        assert_eq!((stats.trade_count, stats.total_volume), (0, 0.0));
+        assert_eq!((stats.vwap, stats.revisions), (0.0, 2));
+    }
+
+    #[test]
+    fn test_kind_and_symbol_accessors() {
+        let quote = MarketDataMessage::Quote(Quote::test("BTCUSD", 99.0, 101.0));
+        assert_eq!(quote.kind(), MessageKind::Quote);
+        assert_eq!(quote.symbol(), Some(Symbol::from("BTCUSD")));
+        assert_eq!(MarketDataMessage::Heartbeat.kind(), MessageKind::Heartbeat);
+        assert_eq!(MarketDataMessage::Heartbeat.symbol(), None);
+    }
 }
@@ -0,0 +1,269 @@
+//! Invariant checks on normalized messages.
+//!
+//! A [`Validator`] checks every message for an empty symbol, non-positive
+//! prices or sizes, a crossed quote or book (bid above ask), and a timestamp
+//! earlier than the previous message of the same symbol and kind. In
+//! [`ValidationMode::Strict`] failing messages are dropped; in
+//! [`ValidationMode::Lenient`] they are passed on, with trades marked
+//! [`TradeConditions::SUSPECT`]. Either way the client reports them as
+//! [`ClientEvent::ValidationFailed`](crate::client::ClientEvent::ValidationFailed).
+//! Each client connects to one venue, so validation is configured per venue
+//! with [`MarketDataClient::with_validation`](crate::client::MarketDataClient::with_validation);
+//! checks a venue legitimately breaks, such as crossed books during an
+//! auction, can be turned off with [`Validator::without`].
+
+use crate::pipeline::Stage;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, MessageKind, PriceLevel, TradeConditions};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// What happens to messages failing validation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValidationMode {
+    /// Drop them
+    #[default]
+    Strict,
+    /// Pass them on, marking trades as suspect
+    Lenient,
+}
+
+/// A broken invariant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Violation {
+    EmptySymbol,
+    NonPositivePrice,
+    NonPositiveSize,
+    /// Bid above ask
+    Crossed,
+    /// Earlier than the previous message of the same symbol and kind
+    TimestampRegression,
+}
+
+/// A message that failed validation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationFailure {
+    pub symbol: Symbol,
+    pub violations: Vec<Violation>,
+    /// Whether the message was dropped
+    pub rejected: bool,
+}
+
+/// Checks messages against invariants, dropping or marking failures
+#[derive(Debug, Clone, Default)]
+pub struct Validator {
+    mode: ValidationMode,
+    disabled: Vec<Violation>,
+    /// Latest timestamp per symbol and message kind
+    latest: HashMap<(Symbol, MessageKind), DateTime<Utc>>,
+    rejected: u64,
+    flagged: u64,
+}
+
+impl Validator {
+    pub fn new(mode: ValidationMode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    pub fn strict() -> Self {
+        Self::new(ValidationMode::Strict)
+    }
+
+    pub fn lenient() -> Self {
+        Self::new(ValidationMode::Lenient)
+    }
+
+    /// Skip the check for `violation`
+    pub fn without(mut self, violation: Violation) -> Self {
+        self.disabled.push(violation);
+        self
+    }
+
+    /// Messages dropped in strict mode
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Messages passed on despite failing, in lenient mode
+    pub fn flagged(&self) -> u64 {
+        self.flagged
+    }
+
+    /// Invariants `msg` breaks, remembering its timestamp for later checks
+    pub fn check(&mut self, msg: &MarketDataMessage) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let (kind, symbol, timestamp) = match msg {
+            MarketDataMessage::Trade(trade) => {
+                if !positive(trade.price) {
+                    violations.push(Violation::NonPositivePrice);
+                }
+                if !positive(trade.quantity) {
+                    violations.push(Violation::NonPositiveSize);
+                }
+                (MessageKind::Trade, &trade.symbol, Some(trade.timestamp))
+            }
+            MarketDataMessage::Quote(quote) => {
+                // A side with neither price nor size is empty, not invalid
+                let sides = [
+                    (quote.bid_price, quote.bid_size),
+                    (quote.ask_price, quote.ask_size),
+                ];
+                for (price, size) in sides {
+                    if !non_negative(price) || (price == 0.0 && size > 0.0) {
+                        violations.push(Violation::NonPositivePrice);
+                    }
+                    if !non_negative(size) || (size == 0.0 && price > 0.0) {
+                        violations.push(Violation::NonPositiveSize);
+                    }
+                }
+                if quote.bid_size > 0.0 && quote.ask_size > 0.0 && quote.bid_price > quote.ask_price
+                {
+                    violations.push(Violation::Crossed);
+                }
+                (MessageKind::Quote, &quote.symbol, Some(quote.timestamp))
+            }
+            MarketDataMessage::OrderBook(book) => {
+                let levels = || book.bids.iter().chain(&book.asks);
+                if levels().any(|level: &PriceLevel| !positive(level.price)) {
+                    violations.push(Violation::NonPositivePrice);
+                }
+                if levels().any(|level| !positive(level.size)) {
+                    violations.push(Violation::NonPositiveSize);
+                }
+                if let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask()) {
+                    if bid.price > ask.price {
+                        violations.push(Violation::Crossed);
+                    }
+                }
+                (MessageKind::OrderBook, &book.symbol, Some(book.timestamp))
+            }
+            // Amendments refer back to earlier trades, so are not ordered
+            MarketDataMessage::TradeCorrection(correction) => {
+                if !positive(correction.corrected.price) {
+                    violations.push(Violation::NonPositivePrice);
+                }
+                if !positive(correction.corrected.quantity) {
+                    violations.push(Violation::NonPositiveSize);
+                }
+                (MessageKind::TradeCorrection, &correction.symbol, None)
+            }
+            MarketDataMessage::TradeBust(bust) => (MessageKind::TradeBust, &bust.symbol, None),
+            MarketDataMessage::Heartbeat => return violations,
+        };
+        if symbol.trim().is_empty() {
+            violations.push(Violation::EmptySymbol);
+        }
+        if let Some(timestamp) = timestamp {
+            let latest = self.latest.entry((*symbol, kind)).or_insert(timestamp);
+            if timestamp < *latest {
+                violations.push(Violation::TimestampRegression);
+            } else {
+                *latest = timestamp;
+            }
+        }
+
+        violations.dedup();
+        violations.retain(|violation| !self.disabled.contains(violation));
+        violations
+    }
+
+    /// Check `msgs`, dropping or marking failures according to the mode
+    pub fn validate(&mut self, msgs: &mut Vec<MarketDataMessage>) -> Vec<ValidationFailure> {
+        let mut failures = Vec::new();
+        msgs.retain_mut(|msg| {
+            let violations = self.check(msg);
+            if violations.is_empty() {
+                return true;
+            }
+            let rejected = self.mode == ValidationMode::Strict;
+            if rejected {
+                self.rejected += 1;
+            } else {
+                self.flagged += 1;
+                if let MarketDataMessage::Trade(trade) = msg {
+                    trade.conditions.insert(TradeConditions::SUSPECT);
+                }
+            }
+            failures.push(ValidationFailure {
+                symbol: msg.symbol().unwrap_or_default(),
+                violations,
+                rejected,
+            });
+            !rejected
+        });
+        failures
+    }
+}
+
+/// False for NaN too
+fn positive(x: f64) -> bool {
+    x > 0.0
+}
+
+fn non_negative(x: f64) -> bool {
+    x >= 0.0
+}
+
+impl Stage for Validator {
+    fn process(&mut self, msg: MarketDataMessage, out: &mut Vec<MarketDataMessage>) {
+        let mut msgs = vec![msg];
+        self.validate(&mut msgs);
+        out.append(&mut msgs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Quote, Trade};
+    use chrono::{Duration, TimeZone};
+
+    fn trade(price: f64, second: i64) -> MarketDataMessage {
+        MarketDataMessage::Trade(Trade {
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 0).unwrap()
+                + Duration::seconds(second),
+            ..Trade::test("BTCUSD", price)
+        })
+    }
+
+    #[test]
+    fn test_strict_rejects_and_lenient_marks() {
+        let crossed = MarketDataMessage::Quote(Quote {
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 0).unwrap(),
+            ..Quote::test("BTCUSD", 101.0, 100.0)
+        });
+        let batch = || {
+            vec![
+                trade(100.0, 5),
+                trade(-1.0, 6),
+                trade(100.0, 2),
+                crossed.clone(),
+            ]
+        };
+
+        let mut strict = Validator::strict();
+        let mut msgs = batch();
+        let failures = strict.validate(&mut msgs);
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(strict.rejected(), 3);
+        assert_eq!(failures[0].violations, [Violation::NonPositivePrice]);
+        assert_eq!(failures[1].violations, [Violation::TimestampRegression]);
+        assert_eq!(failures[2].violations, [Violation::Crossed]);
+
+        let mut lenient = Validator::lenient().without(Violation::Crossed);
+        let mut msgs = batch();
+        let failures = lenient.validate(&mut msgs);
+        assert_eq!(msgs.len(), 4);
+        assert_eq!((failures.len(), lenient.flagged()), (2, 2));
+        assert!(!failures[0].rejected);
+        assert!(matches!(
+            &msgs[1],
+            MarketDataMessage::Trade(trade) if trade.conditions.contains(TradeConditions::SUSPECT)
+        ));
+    }
+}
@@ -0,0 +1,332 @@
+//! Named symbol watchlists with change notifications.
+//!
+//! [`Watchlists`] holds named sets of symbols that change at runtime. Given
+//! the client's [`ControlHandle`], the first watchlist to add a symbol
+//! subscribes to it and the last one to drop it unsubscribes, on venues that
+//! subscribe by symbol. Each watchlist has its own stream of
+//! [`WatchlistUpdate`]s carrying membership changes and, as messages arrive,
+//! the latest quote and running stats of its members, ready for a UI pane.
+
+use crate::client::Result;
+use crate::control::ControlHandle;
+use crate::symbology::Symbol;
+use crate::types::{MarketDataMessage, MarketStats, Quote};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// Latest state of one watched symbol
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchlistRow {
+    pub symbol: Symbol,
+    /// Latest quote
+    pub bbo: Option<Quote>,
+    pub stats: MarketStats,
+}
+
+impl WatchlistRow {
+    fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.into(),
+            bbo: None,
+            stats: MarketStats::new(symbol.to_string()),
+        }
+    }
+}
+
+/// A change to one watchlist
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum WatchlistUpdate {
+    /// The watchlist's symbols after an add or remove
+    Members {
+        watchlist: String,
+        symbols: Vec<String>,
+    },
+    /// A member's row after a trade or quote
+    Row {
+        watchlist: String,
+        row: Box<WatchlistRow>,
+    },
+}
+
+#[derive(Default)]
+struct State {
+    lists: BTreeMap<String, BTreeSet<Symbol>>,
+    rows: HashMap<Symbol, WatchlistRow>,
+    senders: HashMap<String, broadcast::Sender<WatchlistUpdate>>,
+}
+
+impl State {
+    fn watched(&self, symbol: &str) -> bool {
+        self.lists.values().any(|list| list.contains(symbol))
+    }
+
+    fn sender(&mut self, watchlist: &str) -> &broadcast::Sender<WatchlistUpdate> {
+        self.senders
+            .entry(watchlist.to_string())
+            .or_insert_with(|| broadcast::channel(1024).0)
+    }
+
+    /// Of `symbols`, those no longer in any watchlist, with their rows
+    /// discarded
+    fn drop_unwatched<'a>(&mut self, symbols: &[&'a str]) -> Vec<&'a str> {
+        let dropped: Vec<&str> = symbols
+            .iter()
+            .copied()
+            .filter(|symbol| !self.watched(symbol))
+            .collect();
+        for symbol in &dropped {
+            self.rows.remove(*symbol);
+        }
+        dropped
+    }
+
+    fn announce_members(&mut self, watchlist: &str) {
+        let symbols = self
+            .lists
+            .get(watchlist)
+            .map(|list| list.iter().map(Symbol::to_string).collect())
+            .unwrap_or_default();
+        let _ = self.sender(watchlist).send(WatchlistUpdate::Members {
+            watchlist: watchlist.to_string(),
+            symbols,
+        });
+    }
+}
+
+/// Named sets of symbols, shared between the task following the stream and
+/// whatever edits them
+#[derive(Clone, Default)]
+pub struct Watchlists {
+    state: Arc<Mutex<State>>,
+    control: Option<ControlHandle>,
+}
+
+impl Watchlists {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to and drop symbols through a running client as they
+    /// enter and leave every watchlist
+    pub fn with_control(mut self, control: ControlHandle) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// Add symbols to a watchlist, creating it if needed
+    pub async fn add(&self, watchlist: &str, symbols: &[&str]) -> Result<()> {
+        let added: Vec<&str> = {
+            let mut state = self.state.lock().unwrap();
+            let added: Vec<&str> = symbols
+                .iter()
+                .copied()
+                .filter(|symbol| !state.watched(symbol))
+                .collect();
+            for symbol in &added {
+                state
+                    .rows
+                    .insert(Symbol::from(*symbol), WatchlistRow::new(symbol));
+            }
+            state
+                .lists
+                .entry(watchlist.to_string())
+                .or_default()
+                .extend(symbols.iter().map(|s| Symbol::from(*s)));
+            state.announce_members(watchlist);
+            added
+        };
+        match &self.control {
+            Some(control) if !added.is_empty() => control.subscribe_symbols(&added).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove symbols from a watchlist
+    pub async fn remove(&self, watchlist: &str, symbols: &[&str]) -> Result<()> {
+        let dropped: Vec<&str> = {
+            let mut state = self.state.lock().unwrap();
+            let Some(list) = state.lists.get_mut(watchlist) else {
+                return Ok(());
+            };
+            for symbol in symbols {
+                list.remove(*symbol);
+            }
+            state.announce_members(watchlist);
+            state.drop_unwatched(symbols)
+        };
+        self.unsubscribe(&dropped).await
+    }
+
+    /// Remove a watchlist; its update streams end
+    pub async fn delete(&self, watchlist: &str) -> Result<()> {
+        let dropped: Vec<String> = {
+            let mut state = self.state.lock().unwrap();
+            let Some(list) = state.lists.remove(watchlist) else {
+                return Ok(());
+            };
+            state.announce_members(watchlist);
+            state.senders.remove(watchlist);
+            let symbols: Vec<&str> = list.iter().map(|s| s.as_str()).collect();
+            state
+                .drop_unwatched(&symbols)
+                .into_iter()
+                .map(|s| s.to_string())
+                .collect()
+        };
+        let dropped: Vec<&str> = dropped.iter().map(|s| s.as_str()).collect();
+        self.unsubscribe(&dropped).await
+    }
+
+    async fn unsubscribe(&self, symbols: &[&str]) -> Result<()> {
+        match &self.control {
+            Some(control) if !symbols.is_empty() => control.unsubscribe_symbols(symbols).await,
+            _ => Ok(()),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.state.lock().unwrap().lists.keys().cloned().collect()
+    }
+
+    pub fn symbols(&self, watchlist: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .lists
+            .get(watchlist)
+            .map(|list| list.iter().map(Symbol::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    /// Current rows of a watchlist, e.g. to draw a pane before following
+    /// its updates
+    pub fn rows(&self, watchlist: &str) -> Vec<WatchlistRow> {
+        let state = self.state.lock().unwrap();
+        let Some(list) = state.lists.get(watchlist) else {
+            return Vec::new();
+        };
+        list.iter()
+            .filter_map(|symbol| state.rows.get(symbol).cloned())
+            .collect()
+    }
+
+    /// Updates of one watchlist
+    pub fn subscribe(&self, watchlist: &str) -> broadcast::Receiver<WatchlistUpdate> {
+        self.state.lock().unwrap().sender(watchlist).subscribe()
+    }
+
+    /// Apply a message to the rows of watched symbols
+    pub fn on_message(&self, msg: &MarketDataMessage) {
+        let mut state = self.state.lock().unwrap();
+        let row = match msg {
+            MarketDataMessage::Trade(trade) => state.rows.get_mut(&trade.symbol).map(|row| {
+                row.stats.update_with_trade(trade);
+                row.clone()
+            }),
+            MarketDataMessage::Quote(quote) => state.rows.get_mut(&quote.symbol).map(|row| {
+                row.bbo = Some(quote.clone());
+                row.clone()
+            }),
+            MarketDataMessage::TradeCorrection(_) | MarketDataMessage::TradeBust(_) => msg
+                .symbol()
+                .and_then(|symbol| state.rows.get_mut(&symbol))
+                .map(|row| {
+                    row.stats.update(msg);
+                    row.clone()
+                }),
+            _ => None,
+        };
+        let Some(row) = row else {
+            return;
+        };
+        for (name, list) in &state.lists {
+            if !list.contains(&row.symbol) {
+                continue;
+            }
+            if let Some(tx) = state.senders.get(name) {
+                let _ = tx.send(WatchlistUpdate::Row {
+                    watchlist: name.clone(),
+                    row: Box::new(row.clone()),
+                });
+            }
+        }
+    }
+
+    /// Follow `rx` on a background task
+    pub fn spawn(&self, mut rx: broadcast::Receiver<MarketDataMessage>) -> JoinHandle<()> {
+        let watchlists = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => watchlists.on_message(&msg),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("Watchlists lagged, skipped {} messages", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::ControlCommand;
+    use crate::types::Trade;
+
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_shared_symbols_subscribe_once() {
+        let (tx, mut commands) = mpsc::channel(8);
+        let watchlists = Watchlists::new().with_control(ControlHandle::new(tx));
+        let mut updated = |expect_add: &[&str], expect_remove: &[&str]| match commands.try_recv() {
+            Ok(ControlCommand::UpdateSymbols { add, remove }) => {
+                assert_eq!((add, remove), (str_vec(expect_add), str_vec(expect_remove)))
+            }
+            Ok(other) => panic!("unexpected command {:?}", other),
+            Err(_) => assert!(expect_add.is_empty() && expect_remove.is_empty()),
+        };
+
+        watchlists
+            .add("crypto", &["BTCUSD", "ETHUSD"])
+            .await
+            .unwrap();
+        updated(&["BTCUSD", "ETHUSD"], &[]);
+        watchlists.add("majors", &["BTCUSD"]).await.unwrap();
+        updated(&[], &[]);
+        watchlists.remove("crypto", &["BTCUSD"]).await.unwrap();
+        updated(&[], &[]);
+        assert_eq!(watchlists.symbols("crypto"), ["ETHUSD"]);
+
+        let mut rx = watchlists.subscribe("majors");
+        watchlists.on_message(&MarketDataMessage::Trade(Trade {
+            quantity: 2.0,
+            ..Trade::test("BTCUSD", 50000.0)
+        }));
+        match rx.try_recv().unwrap() {
+            WatchlistUpdate::Row { watchlist, row } => {
+                assert_eq!(watchlist, "majors");
+                assert_eq!((row.stats.trade_count, row.stats.last_price), (1, 50000.0));
+            }
+            other => panic!("expected a row update, got {:?}", other),
+        }
+
+        watchlists.delete("majors").await.unwrap();
+        updated(&[], &["BTCUSD"]);
+        assert!(matches!(
+            rx.try_recv().unwrap(),
+            WatchlistUpdate::Members { symbols, .. } if symbols.is_empty()
+        ));
+        assert_eq!(watchlists.names(), ["crypto"]);
+    }
+
+    fn str_vec(symbols: &[&str]) -> Vec<String> {
+        symbols.iter().map(|s| s.to_string()).collect()
+    }
+}
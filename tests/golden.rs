@@ -0,0 +1,54 @@
+//! Golden tests replaying captured feed samples through each adapter.
+//!
+//! Fixtures live in `tests/fixtures/<adapter>/<name>.jsonl` (record new ones
+//! with `mds capture`); the expected normalized output sits next to them in
+//! `<name>.golden.jsonl`. Run with `UPDATE_GOLDEN=1` to write the golden files
+//! of new fixtures or regenerate them after an intentional change, and review
+//! the diff.
+
+use rust_market_data_stream::{adapters, fixtures};
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn adapters_match_golden_output() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut checked = 0;
+    let mut failures = Vec::new();
+
+    for venue in fs::read_dir(&root).unwrap() {
+        let venue = venue.unwrap().path();
+        let name = venue.file_name().unwrap().to_string_lossy().to_string();
+
+        for fixture in fs::read_dir(&venue).unwrap() {
+            let fixture = fixture.unwrap().path();
+            let file_name = fixture.file_name().unwrap().to_string_lossy().to_string();
+            let Some(stem) = file_name.strip_suffix(".jsonl") else {
+                continue;
+            };
+            if stem.ends_with(".golden") {
+                continue;
+            }
+
+            let mut adapter = adapters::by_name(&name, &[])
+                .unwrap_or_else(|| panic!("no adapter named {}", name));
+            let frames = fixtures::load(&fixture).unwrap();
+            let messages = fixtures::replay(adapter.as_mut(), &frames)
+                .unwrap_or_else(|e| panic!("{}: {}", fixture.display(), e));
+
+            let golden = venue.join(format!("{}.golden.jsonl", stem));
+            if let Err(diff) = fixtures::check_golden(&golden, &messages, update).unwrap() {
+                failures.push(format!("{}: {}", fixture.display(), diff));
+            }
+            checked += 1;
+        }
+    }
+
+    assert!(checked > 0, "no fixtures found in {}", root.display());
+    assert!(
+        failures.is_empty(),
+        "{}\n(run with UPDATE_GOLDEN=1 if the change is intended)",
+        failures.join("\n")
+    );
+}